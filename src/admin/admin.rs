@@ -5,8 +5,8 @@ use ruma::events::room::message::RoomMessageEventContent;
 use crate::{
 	appservice, appservice::AppserviceCommand, check, check::CheckCommand, command::Command,
 	debug, debug::DebugCommand, federation, federation::FederationCommand, media,
-	media::MediaCommand, query, query::QueryCommand, room, room::RoomCommand, server,
-	server::ServerCommand, user, user::UserCommand,
+	media::MediaCommand, query, query::QueryCommand, report, report::ReportCommand, room,
+	room::RoomCommand, server, server::ServerCommand, user, user::UserCommand,
 };
 
 #[derive(Debug, Parser)]
@@ -36,6 +36,11 @@ pub(super) enum AdminCommand {
 	/// - Commands for managing media
 	Media(MediaCommand),
 
+	#[command(subcommand)]
+	/// - Commands for handling the event reports of users
+	#[clap(alias = "report")]
+	Reports(ReportCommand),
+
 	#[command(subcommand)]
 	/// - Commands for checking integrity
 	Check(CheckCommand),
@@ -59,6 +64,7 @@ pub(super) async fn process(
 	Ok(match command {
 		| Appservices(command) => appservice::process(command, context).await?,
 		| Media(command) => media::process(command, context).await?,
+		| Reports(command) => report::process(command, context).await?,
 		| Users(command) => user::process(command, context).await?,
 		| Rooms(command) => room::process(command, context).await?,
 		| Federation(command) => federation::process(command, context).await?,
@@ -1,3 +1,5 @@
+use std::fmt::Write;
+
 use ruma::{api::appservice::Registration, events::room::message::RoomMessageEventContent};
 
 use crate::{admin_command, Result};
@@ -82,3 +84,20 @@ pub(super) async fn list_registered(&self) -> Result<RoomMessageEventContent> {
 	let output = format!("Appservices ({}): {}", appservices.len(), appservices.join(", "));
 	Ok(RoomMessageEventContent::text_plain(output))
 }
+
+#[admin_command]
+pub(super) async fn reload_all(&self) -> Result<RoomMessageEventContent> {
+	let reload = self.services.appservice.reload_all().await?;
+	let mut output = format!(
+		"Registered: {}\nUnregistered: {}\nUnchanged: {}\n",
+		reload.registered.join(", "),
+		reload.unregistered.join(", "),
+		reload.unchanged,
+	);
+
+	for (path, e) in &reload.failed {
+		writeln!(output, "Failed to register {}: {e}", path.display())?;
+	}
+
+	Ok(RoomMessageEventContent::text_plain(output))
+}
@@ -37,4 +37,9 @@ pub(super) enum AppserviceCommand {
 	/// - List all the currently registered appservices
 	#[clap(alias("list"))]
 	ListRegistered,
+
+	/// - Register the appservices of the registration files in the configured
+	///   `appservice_registration_dir` again, and unregister those whose file
+	///   was removed
+	ReloadAll,
 }
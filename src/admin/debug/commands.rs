@@ -1,24 +1,29 @@
 use std::{
-	collections::HashMap,
+	collections::{BTreeSet, HashMap, HashSet},
 	fmt::Write,
 	iter::once,
 	time::{Instant, SystemTime},
 };
 
 use conduwuit::{
-	debug_error, err, info, trace, utils, utils::string::EMPTY, warn, Error, PduEvent, Result,
+	debug_error, err, info, trace, utils,
+	utils::{stream::TryIgnore, string::EMPTY},
+	warn, Err, Error, PduEvent, Result,
 };
 use futures::{FutureExt, StreamExt};
 use ruma::{
 	api::{client::error::ErrorKind, federation::event::get_room_state},
 	events::room::message::RoomMessageEventContent,
-	CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomOrAliasId, RoomId, RoomVersionId,
-	ServerName,
+	CanonicalJsonObject, EventId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, RoomId,
+	RoomVersionId, ServerName,
 };
 use service::rooms::state_compressor::HashSetCompressStateEvent;
 use tracing_subscriber::EnvFilter;
 
-use crate::admin_command;
+use crate::{
+	admin_command,
+	utils::{parse_local_user_id, parse_user_id},
+};
 
 #[admin_command]
 pub(super) async fn echo(&self, message: Vec<String>) -> Result<RoomMessageEventContent> {
@@ -317,6 +322,100 @@ pub(super) async fn get_room_state(
 	Ok(RoomMessageEventContent::notice_markdown(format!("```json\n{json}\n```")))
 }
 
+#[admin_command]
+pub(super) async fn state_diff(
+	&self,
+	room_id: OwnedRoomId,
+	event_id_a: Box<EventId>,
+	event_id_b: Box<EventId>,
+) -> Result<RoomMessageEventContent> {
+	let mut states = Vec::with_capacity(2);
+	for event_id in [&event_id_a, &event_id_b] {
+		let pdu = self
+			.services
+			.rooms
+			.timeline
+			.get_pdu(event_id)
+			.await
+			.map_err(|_| {
+				err!(Request(NotFound("Event {event_id} not found in our database.")))
+			})?;
+
+		if pdu.room_id != room_id {
+			return Err!("Event {event_id} is in {} instead of {room_id}.", pdu.room_id);
+		}
+
+		let shortstatehash = self
+			.services
+			.rooms
+			.state_accessor
+			.pdu_shortstatehash(event_id)
+			.await
+			.map_err(|_| err!(Database("No state snapshot is recorded for {event_id}.")))?;
+
+		let state: HashMap<_, OwnedEventId> = self
+			.services
+			.rooms
+			.state_accessor
+			.state_full_ids(shortstatehash)
+			.await?;
+
+		states.push((shortstatehash, state));
+	}
+
+	let (shortstatehash_b, state_b) = states.pop().expect("state of the second event");
+	let (shortstatehash_a, state_a) = states.pop().expect("state of the first event");
+
+	let mut added = Vec::new();
+	let mut removed = Vec::new();
+	let mut changed = Vec::new();
+	for (shortstatekey, event_id) in &state_b {
+		match state_a.get(shortstatekey) {
+			| None => added.push((*shortstatekey, format!("{event_id}"))),
+			| Some(old) if old != event_id =>
+				changed.push((*shortstatekey, format!("{old} -> {event_id}"))),
+			| Some(_) => {},
+		}
+	}
+
+	for (shortstatekey, event_id) in &state_a {
+		if !state_b.contains_key(shortstatekey) {
+			removed.push((*shortstatekey, format!("{event_id}")));
+		}
+	}
+
+	let mut out = format!(
+		"State diff from {event_id_a} (shortstatehash {shortstatehash_a}) to {event_id_b} \
+		 (shortstatehash {shortstatehash_b}): {} added, {} removed, {} changed\n",
+		added.len(),
+		removed.len(),
+		changed.len(),
+	);
+
+	for (title, entries) in [("Added", added), ("Removed", removed), ("Changed", changed)] {
+		if entries.is_empty() {
+			continue;
+		}
+
+		let mut lines = Vec::with_capacity(entries.len());
+		for (shortstatekey, events) in entries {
+			let (event_type, state_key) = self
+				.services
+				.rooms
+				.short
+				.get_statekey_from_short(shortstatekey)
+				.await?;
+
+			lines.push(format!("- `{event_type}` `{state_key}`: {events}"));
+		}
+
+		lines.sort_unstable();
+		writeln!(out, "\n{title}:\n{}", lines.join("\n"))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
 #[admin_command]
 pub(super) async fn ping(&self, server: Box<ServerName>) -> Result<RoomMessageEventContent> {
 	if server == self.services.globals.server_name() {
@@ -716,6 +815,131 @@ pub(super) async fn force_set_room_state_from_server(
 	))
 }
 
+#[admin_command]
+pub(super) async fn event_graph(
+	&self,
+	room_id: OwnedRoomOrAliasId,
+	limit: usize,
+	dot: bool,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room_id).await?;
+	let pdus: Vec<PduEvent> = self
+		.services
+		.rooms
+		.timeline
+		.pdus_rev(None, &room_id, None)
+		.ignore_err()
+		.map(|(_, pdu)| pdu)
+		.take(limit)
+		.collect()
+		.await;
+
+	if pdus.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No events found in the room."));
+	}
+
+	let extremities: HashSet<OwnedEventId> = self
+		.services
+		.rooms
+		.state
+		.get_forward_extremities(&room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let included: HashSet<&EventId> = pdus.iter().map(|pdu| &*pdu.event_id).collect();
+	let outside: BTreeSet<&EventId> = pdus
+		.iter()
+		.flat_map(|pdu| pdu.prev_events.iter().map(|prev_event| &**prev_event))
+		.filter(|event_id| !included.contains(event_id))
+		.collect();
+
+	let mut soft_failed = HashSet::new();
+	for event_id in included.iter().chain(outside.iter()) {
+		if self
+			.services
+			.rooms
+			.pdu_metadata
+			.is_event_soft_failed(event_id)
+			.await
+		{
+			soft_failed.insert(*event_id);
+		}
+	}
+
+	if dot {
+		let mut out = String::new();
+		writeln!(out, "digraph \"{room_id}\" {{")?;
+		writeln!(out, "\trankdir=BT;")?;
+		for pdu in &pdus {
+			let mut style = Vec::new();
+			if extremities.contains(&pdu.event_id) {
+				style.push("peripheries=2");
+			}
+			if soft_failed.contains(&*pdu.event_id) {
+				style.push("color=red");
+			}
+
+			let style = style
+				.iter()
+				.map(|attr| format!(", {attr}"))
+				.collect::<String>();
+			writeln!(
+				out,
+				"\t\"{}\" [label=\"{}\\n{}\\ndepth {}\"{style}];",
+				pdu.event_id, pdu.event_id, pdu.kind, pdu.depth
+			)?;
+		}
+
+		for event_id in &outside {
+			let color = if soft_failed.contains(event_id) { "red" } else { "gray" };
+			writeln!(out, "\t\"{event_id}\" [style=dashed, color={color}];")?;
+		}
+
+		for pdu in &pdus {
+			for prev_event in &pdu.prev_events {
+				writeln!(out, "\t\"{}\" -> \"{prev_event}\";", pdu.event_id)?;
+			}
+		}
+
+		writeln!(out, "}}")?;
+		return Ok(RoomMessageEventContent::notice_markdown(format!("```dot\n{out}```")));
+	}
+
+	let events: Vec<_> = pdus
+		.iter()
+		.map(|pdu| {
+			serde_json::json!({
+				"event_id": pdu.event_id,
+				"type": pdu.kind,
+				"sender": pdu.sender,
+				"depth": pdu.depth,
+				"prev_events": pdu.prev_events,
+				"soft_failed": soft_failed.contains(&*pdu.event_id),
+				"extremity": extremities.contains(&pdu.event_id),
+			})
+		})
+		.collect();
+
+	let outside: Vec<_> = outside
+		.iter()
+		.map(|event_id| {
+			serde_json::json!({
+				"event_id": event_id,
+				"soft_failed": soft_failed.contains(event_id),
+			})
+		})
+		.collect();
+
+	let json = serde_json::to_string_pretty(&serde_json::json!({
+		"room_id": room_id,
+		"events": events,
+		"outside": outside,
+	}))?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!("```json\n{json}\n```")))
+}
+
 #[admin_command]
 pub(super) async fn get_signing_keys(
 	&self,
@@ -876,6 +1100,29 @@ pub(super) async fn time(&self) -> Result<RoomMessageEventContent> {
 	Ok(RoomMessageEventContent::text_markdown(utils::time::format(now, "%+")))
 }
 
+#[admin_command]
+pub(super) async fn event_timing(&self) -> Result<RoomMessageEventContent> {
+	let timings = self.services.rooms.event_handler.stage_timings();
+	if timings.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No inbound PDUs have been handled yet."));
+	}
+
+	let mut out = String::from(
+		"| Stage | Count | Mean | p50 | p90 | p99 | Max |\n| --- | --- | --- | --- | --- | --- \
+		 | --- |\n",
+	);
+
+	for (stage, timing) in timings {
+		writeln!(
+			out,
+			"| {stage:?} | {} | {:?} | {:?} | {:?} | {:?} | {:?} |",
+			timing.count, timing.mean, timing.p50, timing.p90, timing.p99, timing.max
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
 #[admin_command]
 pub(super) async fn list_dependencies(&self, names: bool) -> Result<RoomMessageEventContent> {
 	if names {
@@ -923,3 +1170,181 @@ pub(super) async fn database_stats(
 
 	Ok(RoomMessageEventContent::notice_markdown(out))
 }
+
+#[admin_command]
+pub(super) async fn get_account_data(
+	&self,
+	user_id: String,
+	room_id: Option<OwnedRoomId>,
+	kind: String,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_user_id(self.services, &user_id)?;
+	let data = self
+		.services
+		.account_data
+		.get_raw(room_id.as_deref(), &user_id, &kind)
+		.await
+		.map_err(|_| err!("No {kind} account data for {user_id}."))?;
+
+	let data: serde_json::Value = serde_json::from_slice(&data)?;
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"```json\n{}\n```",
+		serde_json::to_string_pretty(&data)?
+	)))
+}
+
+#[admin_command]
+pub(super) async fn put_account_data(
+	&self,
+	user_id: String,
+	room_id: Option<OwnedRoomId>,
+	kind: String,
+	yes_i_want_to_do_this: bool,
+) -> Result<RoomMessageEventContent> {
+	use ruma::events::{
+		direct::DirectEventContent, fully_read::FullyReadEventContent,
+		push_rules::PushRulesEventContent, tag::TagEventContent,
+	};
+
+	if !yes_i_want_to_do_this {
+		return Ok(RoomMessageEventContent::notice_markdown(
+			"You must pass the --yes-i-want-to-do-this flag to ensure you really want to \
+			 overwrite this account data.",
+		));
+	}
+
+	if self.body.len() < 2
+		|| !self.body[0].trim().starts_with("```")
+		|| self.body.last().unwrap_or(&EMPTY).trim() != "```"
+	{
+		return Ok(RoomMessageEventContent::text_plain(
+			"Expected code block in command body. Add --help for details.",
+		));
+	}
+
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let string = self.body[1..self.body.len().saturating_sub(1)].join("\n");
+	let content: serde_json::Value = serde_json::from_str(&string)?;
+	if !content.is_object() {
+		return Err!("Account data content must be a JSON object.");
+	}
+
+	let checked = match kind.as_str() {
+		| "m.direct" => serde_json::from_value::<DirectEventContent>(content.clone()).map(drop),
+		| "m.fully_read" =>
+			serde_json::from_value::<FullyReadEventContent>(content.clone()).map(drop),
+		| "m.push_rules" =>
+			serde_json::from_value::<PushRulesEventContent>(content.clone()).map(drop),
+		| "m.tag" => serde_json::from_value::<TagEventContent>(content.clone()).map(drop),
+		| _ => Ok(()),
+	};
+
+	if let Err(e) = checked {
+		return Err!("Content is not valid {kind} account data: {e}");
+	}
+
+	self.services
+		.account_data
+		.update(
+			room_id.as_deref(),
+			&user_id,
+			kind.as_str().into(),
+			&serde_json::json!({
+				"type": kind,
+				"content": content,
+			}),
+		)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Replaced {kind} account data for {user_id}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn push(
+	&self,
+	user_id: String,
+	event_id: Box<EventId>,
+) -> Result<RoomMessageEventContent> {
+	use ruma::events::{room::power_levels::RoomPowerLevelsEventContent, StateEventType};
+
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let pdu = self
+		.services
+		.rooms
+		.timeline
+		.get_pdu(&event_id)
+		.await
+		.map_err(|_| err!("Event {event_id} not found."))?;
+
+	let power_levels: RoomPowerLevelsEventContent = self
+		.services
+		.rooms
+		.state_accessor
+		.room_state_get_content(&pdu.room_id, &StateEventType::RoomPowerLevels, "")
+		.await
+		.unwrap_or_default();
+
+	let pusher = &self.services.pusher;
+	let rules = pusher.rules(&user_id).await;
+	let sync_pdu = pdu.to_sync_room_event();
+	let matching = pusher
+		.matching_rules(&user_id, &rules, &power_levels, &sync_pdu, &pdu.room_id)
+		.await;
+
+	let mut msg = String::new();
+	if pdu.sender == user_id {
+		writeln!(msg, "{user_id} sent the event, so they are never notified of it.\n")?;
+	}
+
+	if pdu.sender != user_id
+		&& !self
+			.services
+			.rooms
+			.state_cache
+			.is_joined(&user_id, &pdu.room_id)
+			.await
+	{
+		writeln!(msg, "{user_id} is not in {}, so they are not notified.\n", pdu.room_id)?;
+	}
+
+	let Some(first) = matching.first() else {
+		writeln!(msg, "None of the push rules of {user_id} match {event_id}.")?;
+		return Ok(RoomMessageEventContent::notice_markdown(msg));
+	};
+
+	writeln!(
+		msg,
+		"The push rules of {user_id} matching {event_id}; the actions of the first one are \
+		 taken: {:?}\n\n| Kind | Rule | Actions |\n| --- | --- | --- |",
+		first.actions()
+	)?;
+
+	for rule in &matching {
+		writeln!(msg, "| {} | {} | {:?} |", rule.kind(), rule.rule_id(), rule.actions())?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn join_status(&self) -> Result<RoomMessageEventContent> {
+	let joins = self.services.rooms.joins.list();
+	if joins.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No joins are in progress."));
+	}
+
+	let mut msg = format!(
+		"{} joins in progress:\n\n| User | Room | Stage | Elapsed | Waiting retries |\n| --- | \
+		 --- | --- | --- | --- |",
+		joins.len()
+	);
+
+	for (user_id, room_id, started, stage, waiting) in joins {
+		let elapsed = utils::time::pretty(started.elapsed());
+		write!(msg, "\n| {user_id} | {room_id} | {stage} | {elapsed} | {waiting} |")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
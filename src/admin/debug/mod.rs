@@ -3,7 +3,7 @@ pub(crate) mod tester;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::{EventId, OwnedRoomOrAliasId, RoomId, ServerName};
+use ruma::{EventId, OwnedRoomId, OwnedRoomOrAliasId, RoomId, ServerName};
 
 use self::tester::TesterCommand;
 use crate::admin_command_dispatch;
@@ -76,6 +76,39 @@ pub(super) enum DebugCommand {
 		room_id: OwnedRoomOrAliasId,
 	},
 
+	/// - Compare the room state at two events of a room, printing the state
+	///   entries added, removed and changed from the first to the second.
+	///
+	/// Useful to see how a room got into a state it should not be in.
+	StateDiff {
+		/// The room ID
+		room_id: OwnedRoomId,
+
+		/// The event to compare from
+		event_id_a: Box<EventId>,
+
+		/// The event to compare to
+		event_id_b: Box<EventId>,
+	},
+
+	/// - Print the most recent events of a room as a graph of their
+	///   prev_events, with their depth and whether they were soft-failed or are
+	///   forward extremities, in JSON or DOT
+	///
+	/// Useful to see where extremities build up and how a room forks.
+	EventGraph {
+		/// Room ID or alias
+		room_id: OwnedRoomOrAliasId,
+
+		/// How many of the most recent events to include
+		#[arg(short, long, default_value("100"))]
+		limit: usize,
+
+		/// Print the graph in the DOT format of Graphviz instead of JSON
+		#[arg(long)]
+		dot: bool,
+	},
+
 	/// - Get and display signing keys from local cache or remote server.
 	GetSigningKeys {
 		server_name: Option<Box<ServerName>>,
@@ -193,6 +226,9 @@ pub(super) enum DebugCommand {
 	/// - Print the current time
 	Time,
 
+	/// - Print how long each stage of handling inbound PDUs has recently taken
+	EventTiming,
+
 	/// - List dependencies
 	ListDependencies {
 		#[arg(short, long)]
@@ -207,6 +243,52 @@ pub(super) enum DebugCommand {
 		map: Option<String>,
 	},
 
+	/// - Print a user's account data event of the type, global or in the room
+	///   given with --room-id
+	GetAccountData {
+		user_id: String,
+
+		/// Room of the account data, instead of the user's global data
+		#[arg(short, long)]
+		room_id: Option<OwnedRoomId>,
+
+		/// Account data event type
+		kind: String,
+	},
+
+	/// - Replace a local user's account data event of the type, global or in
+	///   the room given with --room-id, with the content given in a JSON code
+	///   block below the command
+	///
+	/// Well-known types (m.direct, m.fully_read, m.push_rules, m.tag) must
+	/// parse as their specified content before anything is written.
+	///
+	/// Requires the `--yes-i-want-to-do-this` flag.
+	PutAccountData {
+		user_id: String,
+
+		/// Room of the account data, instead of the user's global data
+		#[arg(short, long)]
+		room_id: Option<OwnedRoomId>,
+
+		/// Account data event type
+		kind: String,
+
+		#[arg(long)]
+		yes_i_want_to_do_this: bool,
+	},
+
+	/// - Show which of a local user's push rules match an event, and the
+	///   actions taken for it, to find out why they were or weren't notified
+	Push {
+		user_id: String,
+		event_id: Box<EventId>,
+	},
+
+	/// - Show the joins of rooms in progress, how far each got and how many
+	///   retried requests are waiting for it
+	JoinStatus,
+
 	/// - Developer test stubs
 	#[command(subcommand)]
 	#[allow(non_snake_case)]
@@ -0,0 +1,45 @@
+use std::fmt::Write;
+
+use clap::Subcommand;
+use conduwuit::{utils::time, Result};
+use ruma::{events::room::message::RoomMessageEventContent, OwnedEventId};
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+/// Events which failed to be fetched or handled and are being backed off
+pub(crate) enum BadEventsCommand {
+	/// - List the bad events, most recent failure first
+	List,
+
+	/// - Forget the failures of a bad event, or of all bad events, so they are
+	///   attempted again right away
+	Clear {
+		event_id: Option<OwnedEventId>,
+	},
+}
+
+#[admin_command]
+async fn list(&self) -> Result<RoomMessageEventContent> {
+	let events = self.services.globals.bad_events();
+	if events.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("There are no bad events."));
+	}
+
+	let mut out = String::new();
+	writeln!(out, "| Event ID | Failures | Last Failure |")?;
+	writeln!(out, "| -------- | -------- | ------------ |")?;
+	for (event_id, tries, elapsed) in events {
+		writeln!(out, "| {event_id} | {tries} | {} ago |", time::pretty(elapsed))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn clear(&self, event_id: Option<OwnedEventId>) -> Result<RoomMessageEventContent> {
+	let count = self.services.globals.clear_bad_events(event_id.as_deref());
+
+	Ok(RoomMessageEventContent::text_plain(format!("Cleared {count} bad events.")))
+}
@@ -1,10 +1,21 @@
-use std::fmt::Write;
+use std::{
+	fmt::Write,
+	iter::once,
+	time::{Duration, SystemTime},
+};
 
-use conduwuit::Result;
+use conduwuit::{
+	utils,
+	utils::{stream::IterStream, time},
+	Result,
+};
 use futures::StreamExt;
 use ruma::{
-	events::room::message::RoomMessageEventContent, OwnedRoomId, RoomId, ServerName, UserId,
+	api::federation::discovery::ServerSigningKeys,
+	events::room::message::RoomMessageEventContent, OwnedRoomId, OwnedServerName, RoomId,
+	ServerName, UserId,
 };
+use service::sending::{Destination, SendingEvent};
 
 use crate::{admin_command, get_room_info};
 
@@ -133,3 +144,333 @@ pub(super) async fn remote_user_in_rooms(
 
 	Ok(RoomMessageEventContent::text_markdown(output))
 }
+
+#[admin_command]
+pub(super) async fn dest_cache(
+	&self,
+	server_name: Option<OwnedServerName>,
+) -> Result<RoomMessageEventContent> {
+	use service::resolver::cache::{CachedDest, CachedOverride, UsageCounts};
+
+	let cache = &self.services.resolver.cache;
+	let mut out = String::new();
+	writeln!(
+		out,
+		"| Server Name | Destination | Hostname | Successes | Failures | Last Used | Expires |"
+	)?;
+	writeln!(
+		out,
+		"| ----------- | ----------- | -------- | ---------:| --------:| --------- | ------- |"
+	)?;
+
+	let mut hosts = Vec::new();
+	{
+		let destinations = cache.destinations.read().expect("locked");
+		let mut row = |(name, &CachedDest { ref dest, ref host, expire, ref usage })| {
+			let UsageCounts { successes, failures, last_used } = usage.load();
+			let last_used = time::format(last_used, "%+");
+			let expire = time::format(expire, "%+");
+			writeln!(
+				out,
+				"| {name} | {dest} | {host} | {successes} | {failures} | {last_used} | {expire} \
+				 |"
+			)
+			.expect("wrote line");
+			hosts.push(dest.hostname().into_owned());
+		};
+
+		if let Some(server_name) = server_name.as_ref() {
+			destinations.get_key_value(server_name).map(&mut row);
+		} else {
+			destinations.iter().for_each(row);
+		}
+	}
+
+	writeln!(out)?;
+	writeln!(out, "| Hostname | IP  | Port | Successes | Failures | Last Used | Expires |")?;
+	writeln!(out, "| -------- | --- | ----:| ---------:| --------:| --------- | ------- |")?;
+	let row = |(name, &CachedOverride { ref ips, port, expire, ref usage })| {
+		let UsageCounts { successes, failures, last_used } = usage.load();
+		let last_used = time::format(last_used, "%+");
+		let expire = time::format(expire, "%+");
+		writeln!(
+			out,
+			"| {name} | {ips:?} | {port} | {successes} | {failures} | {last_used} | {expire} |"
+		)
+		.expect("wrote line");
+	};
+
+	let overrides = cache.overrides.read().expect("locked");
+	if server_name.is_some() {
+		hosts
+			.iter()
+			.filter_map(|host| overrides.get_key_value(host))
+			.for_each(row);
+	} else {
+		overrides.iter().for_each(row);
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn resolve(
+	&self,
+	server_name: OwnedServerName,
+) -> Result<RoomMessageEventContent> {
+	let resolver = &self.services.resolver;
+	let Some(cached) = resolver
+		.cache
+		.destinations
+		.read()
+		.expect("locked")
+		.get(&server_name)
+		.cloned()
+	else {
+		return Ok(RoomMessageEventContent::notice_plain(format!(
+			"No destination of {server_name} is cached; it is resolved on the next request."
+		)));
+	};
+
+	let expires = |expire: SystemTime| match expire.duration_since(SystemTime::now()) {
+		| Ok(left) => format!("{} (in {})", time::format(expire, "%+"), time::pretty(left)),
+		| Err(_) => format!("{} (expired)", time::format(expire, "%+")),
+	};
+
+	let mut out = format!(
+		"{server_name} is reached at {} with the hostname {}; this expires at {}.\n",
+		cached.dest,
+		cached.host,
+		expires(cached.expire),
+	);
+
+	let hostname = cached.dest.hostname();
+	match resolver
+		.cache
+		.overrides
+		.read()
+		.expect("locked")
+		.get(hostname.as_ref())
+	{
+		| Some(over) => writeln!(
+			out,
+			"{hostname} resolved to {:?} on port {}; this expires at {}.",
+			over.ips,
+			over.port,
+			expires(over.expire),
+		)?,
+		| None => writeln!(out, "No addresses of {hostname} are cached.")?,
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn backoff_list(&self) -> Result<RoomMessageEventContent> {
+	let threshold = self.services.server.config.sender_dead_server_threshold;
+	let backoffs: Vec<_> = self.services.sending.backoffs().collect().await;
+	if backoffs.is_empty() {
+		return Ok(RoomMessageEventContent::notice_plain("No server is backed off from."));
+	}
+
+	let now = utils::millis_since_unix_epoch();
+	let mut out = String::new();
+	writeln!(out, "| Server Name | Failures | Dead | Last Failure | Retry In | Error |")?;
+	writeln!(out, "| ----------- | --------:| ---- | ------------ | -------- | ----- |")?;
+	for (server, backoff) in backoffs {
+		let dead = threshold > 0 && backoff.failures >= threshold;
+		let ago = Duration::from_millis(now.saturating_sub(backoff.last_failure));
+		let retry_in = if backoff.is_expired() {
+			"now".to_owned()
+		} else {
+			time::pretty(Duration::from_millis(backoff.retry_at.saturating_sub(now)))
+		};
+
+		writeln!(
+			out,
+			"| {server} | {} | {dead} | {} ago | {retry_in} | {} |",
+			backoff.failures,
+			time::pretty(ago),
+			backoff.error.replace('|', "\\|"),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn queue(
+	&self,
+	server_name: OwnedServerName,
+) -> Result<RoomMessageEventContent> {
+	let dest = Destination::Federation(server_name.clone());
+	let sending = &self.services.sending;
+	let active: Vec<_> = sending.db.active_requests_for(&dest).collect().await;
+	let queued: Vec<_> = sending.db.queued_requests(&dest).collect().await;
+
+	let mut oldest = None;
+	for (_, event) in active.iter().chain(&queued) {
+		if let SendingEvent::Pdu(pdu_id) = event {
+			if let Ok(pdu) = self.services.rooms.timeline.get_pdu_from_id(pdu_id).await {
+				let ts: u64 = pdu.origin_server_ts.into();
+				oldest = Some(oldest.map_or(ts, |oldest: u64| oldest.min(ts)));
+			}
+		}
+	}
+
+	let count = |events: &[(Vec<u8>, SendingEvent)]| {
+		let pdus = events
+			.iter()
+			.filter(|(_, event)| matches!(event, SendingEvent::Pdu(_)))
+			.count();
+
+		let edus = events
+			.iter()
+			.filter(|(_, event)| matches!(event, SendingEvent::Edu(_)))
+			.count();
+
+		(pdus, edus)
+	};
+
+	let (active_pdus, active_edus) = count(&active);
+	let (queued_pdus, queued_edus) = count(&queued);
+	let mut out = String::new();
+	writeln!(out, "| Events | PDUs | EDUs |")?;
+	writeln!(out, "| ------ | ----:| ----:|")?;
+	writeln!(out, "| In flight | {active_pdus} | {active_edus} |")?;
+	writeln!(out, "| Queued | {queued_pdus} | {queued_edus} |")?;
+	writeln!(out)?;
+
+	if let Some(oldest) = oldest {
+		let age = utils::millis_since_unix_epoch().saturating_sub(oldest);
+		writeln!(out, "Oldest PDU: {} old", time::pretty(Duration::from_millis(age)))?;
+	}
+
+	if let Some(breaker) = sending.breaker(&server_name) {
+		let state = if breaker.opened.is_some() { "open" } else { "closed" };
+		writeln!(out, "Circuit breaker {state} after {} failed requests", breaker.failures)?;
+	}
+
+	if let Some(backoff) = sending.backoff(&server_name).await {
+		let dead = sending.is_dead(&server_name).await;
+		writeln!(
+			out,
+			"Backed off after {} failures{}: {}",
+			backoff.failures,
+			if dead { ", considered dead" } else { "" },
+			backoff.error,
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+pub(super) async fn flush(
+	&self,
+	server_name: OwnedServerName,
+) -> Result<RoomMessageEventContent> {
+	let sending = &self.services.sending;
+	sending.reset_backoff(&server_name);
+	sending
+		.flush_servers(once(server_name.as_ref()).stream())
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Retrying the transaction to {server_name}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn drop_queue(
+	&self,
+	server_name: OwnedServerName,
+) -> Result<RoomMessageEventContent> {
+	let dest = Destination::Federation(server_name.clone());
+	let sending = &self.services.sending;
+	let active = sending.db.active_requests_for(&dest).count().await;
+	let queued = sending.db.queued_requests(&dest).count().await;
+
+	sending.drop_queue(&server_name).await;
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Dropped {active} events in flight and {queued} queued for {server_name}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn reset_breaker(
+	&self,
+	server_name: OwnedServerName,
+) -> Result<RoomMessageEventContent> {
+	if !self.services.sending.reset_breaker(&server_name) {
+		return Ok(RoomMessageEventContent::notice_plain(format!(
+			"No requests to {server_name} failed recently."
+		)));
+	}
+
+	Ok(RoomMessageEventContent::notice_plain(format!(
+		"Closed the circuit breaker of {server_name}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn fetch_keys(
+	&self,
+	server_name: OwnedServerName,
+) -> Result<RoomMessageEventContent> {
+	let keys = self.services.server_keys.refresh_keys(&server_name).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(keys_table(&keys)?))
+}
+
+#[admin_command]
+pub(super) async fn list_keys(
+	&self,
+	server_name: OwnedServerName,
+) -> Result<RoomMessageEventContent> {
+	let Ok(keys) = self
+		.services
+		.server_keys
+		.signing_keys_for(&server_name)
+		.await
+	else {
+		return Ok(RoomMessageEventContent::notice_plain(format!(
+			"No signing keys of {server_name} are known."
+		)));
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(keys_table(&keys)?))
+}
+
+fn keys_table(keys: &ServerSigningKeys) -> Result<String> {
+	let now = utils::millis_since_unix_epoch();
+	let valid_until: u64 = keys.valid_until_ts.get().into();
+	let validity = if valid_until > now {
+		format!(
+			"are valid for {}",
+			time::pretty(Duration::from_millis(valid_until.saturating_sub(now)))
+		)
+	} else {
+		format!(
+			"expired {} ago",
+			time::pretty(Duration::from_millis(now.saturating_sub(valid_until)))
+		)
+	};
+
+	let mut out = String::new();
+	writeln!(out, "Keys of {} {validity}\n", keys.server_name)?;
+	writeln!(out, "| Key ID | Public Key | Expired |")?;
+	writeln!(out, "| ------ | ---------- | ------- |")?;
+	for (key_id, key) in &keys.verify_keys {
+		writeln!(out, "| {key_id} | {} | |", key.key)?;
+	}
+
+	for (key_id, key) in &keys.old_verify_keys {
+		let expired: u64 = key.expired_ts.get().into();
+		let ago = Duration::from_millis(now.saturating_sub(expired));
+		writeln!(out, "| {key_id} | {} | {} ago |", key.key, time::pretty(ago))?;
+	}
+
+	Ok(out)
+}
@@ -1,9 +1,11 @@
+mod bad_events;
 mod commands;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::{RoomId, ServerName, UserId};
+use ruma::{OwnedServerName, RoomId, ServerName, UserId};
 
+use self::bad_events::BadEventsCommand;
 use crate::admin_command_dispatch;
 
 #[admin_command_dispatch]
@@ -39,4 +41,58 @@ pub(super) enum FederationCommand {
 	RemoteUserInRooms {
 		user_id: Box<UserId>,
 	},
+
+	/// - Show the cached federation destinations and overrides, with how many
+	///   requests through each succeeded or failed and when one was last made
+	DestCache {
+		server_name: Option<OwnedServerName>,
+	},
+
+	/// - Show the cached destination of a server and the addresses it resolved
+	///   to, and when they expire and are resolved again
+	Resolve {
+		server_name: OwnedServerName,
+	},
+
+	/// - List the servers backed off from after failing our transactions, and
+	///   whether they are considered dead
+	BackoffList,
+
+	/// - Show how many PDUs and EDUs are queued and in flight for a server, and
+	///   how old the oldest PDU is
+	Queue {
+		server_name: OwnedServerName,
+	},
+
+	/// - Forget the backoff of a server and retry its transaction now
+	Flush {
+		server_name: OwnedServerName,
+	},
+
+	/// - Drop the events queued and in flight for a server without sending them
+	DropQueue {
+		server_name: OwnedServerName,
+	},
+
+	/// - Close the circuit breaker of a server, so requests are sent to it
+	///   again right away
+	ResetBreaker {
+		server_name: OwnedServerName,
+	},
+
+	/// - Fetch the signing keys of a server again, from itself or else from the
+	///   trusted servers, and list them
+	FetchKeys {
+		server_name: OwnedServerName,
+	},
+
+	/// - List the signing keys we have for a server and until when they are
+	///   valid
+	ListKeys {
+		server_name: OwnedServerName,
+	},
+
+	/// - Inspect and reset the backoff of events which failed
+	#[command(subcommand)]
+	BadEvents(BadEventsCommand),
 }
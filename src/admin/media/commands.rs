@@ -1,9 +1,11 @@
 use std::time::Duration;
 
 use conduwuit::{
-	debug, debug_info, debug_warn, error, info, trace, utils::time::parse_timepoint_ago, Result,
+	debug, debug_info, debug_warn, error, info, trace,
+	utils::{self, time::parse_timepoint_ago},
+	Result,
 };
-use conduwuit_service::media::Dim;
+use conduwuit_service::media::{Dim, Pruned};
 use ruma::{
 	events::room::message::RoomMessageEventContent, EventId, Mxc, MxcUri, OwnedMxcUri,
 	OwnedServerName, ServerName,
@@ -262,6 +264,82 @@ pub(super) async fn delete_past_remote_media(
 	)))
 }
 
+#[admin_command]
+pub(super) async fn prune(&self, before: String) -> Result<RoomMessageEventContent> {
+	let before = parse_timepoint_ago(&before)?;
+	let Pruned { count, bytes } = self.services.media.prune(before).await?;
+	let bytes = usize::try_from(bytes).unwrap_or(usize::MAX);
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Deleted {count} remote media files, reclaiming {}.",
+		utils::bytes::pretty(bytes)
+	)))
+}
+
+#[admin_command]
+pub(super) async fn quarantine(&self, mxc: OwnedMxcUri) -> Result<RoomMessageEventContent> {
+	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
+	if self.services.media.is_quarantined(&mxc).await {
+		return Ok(RoomMessageEventContent::text_plain(format!("{mxc} is already quarantined.")));
+	}
+
+	self.services.media.quarantine(&mxc).await;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Quarantined {mxc}; it is no longer served but kept on our filesystem.",
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unquarantine(&self, mxc: OwnedMxcUri) -> Result<RoomMessageEventContent> {
+	let mxc: Mxc<'_> = mxc.as_str().try_into()?;
+	if !self.services.media.unquarantine(&mxc).await {
+		return Ok(RoomMessageEventContent::text_plain(format!("{mxc} is not quarantined.")));
+	}
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Unquarantined {mxc}; it is served again.",
+	)))
+}
+
+#[admin_command]
+pub(super) async fn list_quarantined(&self) -> Result<RoomMessageEventContent> {
+	let mxcs = self.services.media.list_quarantined().await;
+	if mxcs.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("No media is quarantined."));
+	}
+
+	let list = mxcs
+		.iter()
+		.map(ToString::to_string)
+		.collect::<Vec<_>>()
+		.join("\n");
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Quarantined media ({}):\n```\n{list}\n```",
+		mxcs.len()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn clear_url_previews(
+	&self,
+	url: Option<String>,
+) -> Result<RoomMessageEventContent> {
+	if let Some(url) = url {
+		self.services.media.remove_url_preview(&url).await?;
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"Deleted the cached URL preview of {url}."
+		)));
+	}
+
+	let count = self.services.media.clear_url_previews().await;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Deleted {count} cached URL previews."
+	)))
+}
+
 #[admin_command]
 pub(super) async fn delete_all_from_user(
 	&self,
@@ -47,8 +47,20 @@ pub(super) enum MediaCommand {
 		yes_i_want_to_delete_local_media: bool,
 	},
 
+	/// - Deletes remote media last accessed (or, if it has not been accessed
+	///   since access was first recorded, created) before \[before] ago, and
+	///   reports how much space was reclaimed. The avatars of local users are
+	///   kept when `media_retention_exempt_local_avatars` is set, and
+	///   quarantined media always is.
+	Prune {
+		/// - The relative time (e.g. 30s, 5m, 7d) before which to delete
+		#[arg(long)]
+		before: String,
+	},
+
 	/// - Deletes all the local media from a local user on our server. This will
 	///   always ignore errors by default.
+	#[clap(alias = "delete-from-user")]
 	DeleteAllFromUser {
 		username: String,
 	},
@@ -63,6 +75,29 @@ pub(super) enum MediaCommand {
 		yes_i_want_to_delete_local_media: bool,
 	},
 
+	/// - Quarantines a single media file: it is no longer served, nor fetched
+	///   if it is remote, but kept on our filesystem as evidence until deleted
+	Quarantine {
+		/// The MXC URL to quarantine
+		mxc: OwnedMxcUri,
+	},
+
+	/// - Lets a quarantined media file be served again
+	Unquarantine {
+		/// The MXC URL to unquarantine
+		mxc: OwnedMxcUri,
+	},
+
+	/// - Lists the MXC URLs of all quarantined media
+	ListQuarantined,
+
+	/// - Deletes cached URL previews, so they are requested again the next
+	///   time. Without a URL, deletes all of them.
+	ClearUrlPreviews {
+		/// The URL to delete the cached preview of
+		url: Option<String>,
+	},
+
 	GetFileInfo {
 		/// The MXC URL to lookup info for.
 		mxc: OwnedMxcUri,
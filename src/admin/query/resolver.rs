@@ -19,6 +19,17 @@ pub(crate) enum ResolverCommand {
 	OverridesCache {
 		name: Option<String>,
 	},
+
+	/// Query the well-known delegations cache
+	DelegationsCache {
+		server_name: Option<OwnedServerName>,
+	},
+
+	/// Forget the cached well-known delegation of a server and the destination
+	/// resolved from it, so both are looked up again
+	InvalidateDelegation {
+		server_name: OwnedServerName,
+	},
 }
 
 #[admin_command]
@@ -31,7 +42,7 @@ async fn destinations_cache(
 	let mut out = String::new();
 	writeln!(out, "| Server Name | Destination | Hostname | Expires |")?;
 	writeln!(out, "| ----------- | ----------- | -------- | ------- |")?;
-	let row = |(name, &CachedDest { ref dest, ref host, expire })| {
+	let row = |(name, &CachedDest { ref dest, ref host, expire, .. })| {
 		let expire = time::format(expire, "%+");
 		writeln!(out, "| {name} | {dest} | {host} | {expire} |").expect("wrote line");
 	};
@@ -60,7 +71,7 @@ async fn overrides_cache(&self, server_name: Option<String>) -> Result<RoomMessa
 	let mut out = String::new();
 	writeln!(out, "| Server Name | IP  | Port | Expires |")?;
 	writeln!(out, "| ----------- | --- | ----:| ------- |")?;
-	let row = |(name, &CachedOverride { ref ips, port, expire })| {
+	let row = |(name, &CachedOverride { ref ips, port, expire, .. })| {
 		let expire = time::format(expire, "%+");
 		writeln!(out, "| {name} | {ips:?} | {port} | {expire} |").expect("wrote line");
 	};
@@ -81,3 +92,50 @@ async fn overrides_cache(&self, server_name: Option<String>) -> Result<RoomMessa
 
 	Ok(RoomMessageEventContent::notice_markdown(out))
 }
+
+#[admin_command]
+async fn delegations_cache(
+	&self,
+	server_name: Option<OwnedServerName>,
+) -> Result<RoomMessageEventContent> {
+	use service::resolver::cache::CachedDelegation;
+
+	let mut out = String::new();
+	writeln!(out, "| Server Name | Delegated To | Expires |")?;
+	writeln!(out, "| ----------- | ------------ | ------- |")?;
+	let row = |(name, &CachedDelegation { ref server, expire })| {
+		let server = server.as_deref().unwrap_or("(none)");
+		let expire = time::format(expire, "%+");
+		writeln!(out, "| {name} | {server} | {expire} |").expect("wrote line");
+	};
+
+	let map = self
+		.services
+		.resolver
+		.cache
+		.delegations
+		.read()
+		.expect("locked");
+
+	if let Some(server_name) = server_name.as_ref() {
+		map.get_key_value(server_name.as_str()).map(row);
+	} else {
+		map.iter().for_each(row);
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn invalidate_delegation(
+	&self,
+	server_name: OwnedServerName,
+) -> Result<RoomMessageEventContent> {
+	let msg = if self.services.resolver.invalidate_delegation(&server_name) {
+		format!("Invalidated the cached delegation of {server_name}.")
+	} else {
+		format!("No delegation of {server_name} is cached.")
+	};
+
+	Ok(RoomMessageEventContent::notice_plain(msg))
+}
@@ -0,0 +1,95 @@
+use std::fmt::Write;
+
+use conduwuit::{utils::ReadyExt, Result};
+use futures::StreamExt;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{admin_command, PAGE_SIZE};
+
+#[admin_command]
+pub(super) async fn list(&self, all: bool) -> Result<RoomMessageEventContent> {
+	let reports: Vec<_> = self
+		.services
+		.moderation
+		.reports
+		.list()
+		.ready_filter(|(_, report)| all || !report.is_resolved())
+		.take(PAGE_SIZE)
+		.collect()
+		.await;
+
+	if reports.is_empty() {
+		return Ok(RoomMessageEventContent::notice_markdown("No reports to list."));
+	}
+
+	let mut msg = format!(
+		"{} report(s):\n\n| ID | Event | Room | Sender | Reporter | Score | Reason | Resolved \
+		 |\n| --- | --- | --- | --- | --- | --- | --- | --- |\n",
+		reports.len()
+	);
+
+	for (report_id, report) in reports {
+		writeln!(
+			msg,
+			"| {report_id} | {} | {} | {} | {} | {} | {} | {} |",
+			report.event_id,
+			report.room_id,
+			report.sender,
+			report.reporter,
+			report.score.unwrap_or_default(),
+			report.reason.as_deref().unwrap_or("").replace('|', "\\|"),
+			report
+				.resolved
+				.as_ref()
+				.map_or_else(|| "no".to_owned(), |(admin, _)| format!("by {admin}")),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn show(&self, report_id: u64) -> Result<RoomMessageEventContent> {
+	let report = self.services.moderation.reports.get(report_id).await?;
+	let event = self
+		.services
+		.rooms
+		.timeline
+		.get_pdu_json(&report.event_id)
+		.await
+		.ok()
+		.map(|json| serde_json::to_string_pretty(&json))
+		.transpose()?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Report #{report_id}:\n```rs\n{report:#?}\n```\nEvent reported:\n```json\n{}\n```",
+		event.as_deref().unwrap_or("(not found)"),
+	)))
+}
+
+#[admin_command]
+pub(super) async fn resolve(&self, report_id: u64) -> Result<RoomMessageEventContent> {
+	let admin = match self.reply_id {
+		| Some(reply_id) => self
+			.services
+			.rooms
+			.timeline
+			.get_pdu(reply_id)
+			.await
+			.map(|pdu| pdu.sender)
+			.unwrap_or_else(|_| self.services.globals.server_user.clone()),
+		| None => self.services.globals.server_user.clone(),
+	};
+
+	let report = self
+		.services
+		.moderation
+		.reports
+		.resolve(report_id, &admin)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Resolved report #{report_id} of event {} by {}.",
+		report.event_id, report.reporter
+	)))
+}
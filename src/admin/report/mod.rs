@@ -0,0 +1,27 @@
+mod commands;
+
+use clap::Subcommand;
+use conduwuit::Result;
+
+use crate::admin_command_dispatch;
+
+#[derive(Debug, Subcommand)]
+#[admin_command_dispatch]
+pub(super) enum ReportCommand {
+	/// - List the event reports of local users not resolved yet
+	List {
+		/// Also list the reports already resolved
+		#[arg(long)]
+		all: bool,
+	},
+
+	/// - Show a report with the event reported
+	Show {
+		report_id: u64,
+	},
+
+	/// - Mark a report resolved, once acted on
+	Resolve {
+		report_id: u64,
+	},
+}
@@ -1,8 +1,29 @@
-use conduwuit::Result;
+use std::{fmt::Write, time::Duration};
+
+use api::client::join_room_by_id_helper;
+use conduwuit::{
+	implement,
+	pdu::PduBuilder,
+	utils,
+	utils::{time, ReadyExt},
+	warn, Err, Result,
+};
 use futures::StreamExt;
-use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomId};
+use ruma::{
+	events::{
+		room::{
+			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			message::RoomMessageEventContent,
+			power_levels::{RoomPowerLevels, RoomPowerLevelsEventContent},
+		},
+		StateEventType,
+	},
+	OwnedRoomId, OwnedRoomOrAliasId, OwnedServerName, OwnedUserId, RoomId,
+};
+use serde_json::value::to_raw_value;
+use service::sending::RoomEdus;
 
-use crate::{admin_command, get_room_info, PAGE_SIZE};
+use crate::{admin_command, get_room_info, Command, PAGE_SIZE};
 
 #[admin_command]
 pub(super) async fn list_rooms(
@@ -67,3 +88,365 @@ pub(super) async fn exists(&self, room_id: OwnedRoomId) -> Result<RoomMessageEve
 
 	Ok(RoomMessageEventContent::notice_markdown(format!("{result}")))
 }
+
+#[admin_command]
+pub(super) async fn set_history_visibility(
+	&self,
+	room: OwnedRoomOrAliasId,
+	visibility: String,
+) -> Result<RoomMessageEventContent> {
+	let history_visibility = HistoryVisibility::from(visibility.as_str());
+	if !matches!(
+		history_visibility,
+		HistoryVisibility::WorldReadable
+			| HistoryVisibility::Shared
+			| HistoryVisibility::Invited
+			| HistoryVisibility::Joined
+	) {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{visibility:?} is not one of \"world_readable\", \"shared\", \"invited\" or \
+			 \"joined\"."
+		)));
+	}
+
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let power_levels = self
+		.services
+		.rooms
+		.state_accessor
+		.room_state_get_content::<RoomPowerLevelsEventContent>(
+			&room_id,
+			&StateEventType::RoomPowerLevels,
+			"",
+		)
+		.await
+		.map(RoomPowerLevels::from)
+		.ok();
+
+	let Some(power_levels) = power_levels else {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{room_id} has no power levels to find a local member allowed to change its history \
+			 visibility by."
+		)));
+	};
+
+	let server_user = &self.services.globals.server_user;
+	let mut members: Vec<OwnedUserId> = self
+		.services
+		.rooms
+		.state_cache
+		.room_members(&room_id)
+		.ready_filter(|user| self.services.globals.user_is_local(user))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	members.sort_by_key(|user| user != server_user);
+	let Some(sender) = members.into_iter().find(|user| {
+		power_levels.user_can_send_state(user, StateEventType::RoomHistoryVisibility)
+	}) else {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"No local member of {room_id} is allowed to change its history visibility."
+		)));
+	};
+
+	let state_lock = self.services.rooms.state.mutex.lock(&room_id).await;
+	let event_id = self
+		.services
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::state(
+				String::new(),
+				&RoomHistoryVisibilityEventContent::new(history_visibility),
+			),
+			&sender,
+			&room_id,
+			&state_lock,
+		)
+		.await?;
+
+	drop(state_lock);
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Set the history visibility of {room_id} to {visibility} as {sender}: {event_id}"
+	)))
+}
+
+/// Each dummy event refers to up to 20 forward extremities, so a few collapse
+/// those of any room.
+const MAX_DUMMY_EVENTS: usize = 10;
+
+#[admin_command]
+pub(super) async fn prune_extremities(
+	&self,
+	room: Option<OwnedRoomOrAliasId>,
+) -> Result<RoomMessageEventContent> {
+	let Some(room) = room else {
+		let mut rooms = self.services.rooms.state.excess_extremities();
+		if rooms.is_empty() {
+			return Ok(RoomMessageEventContent::text_plain(
+				"No rooms have more forward extremities than the threshold.",
+			));
+		}
+
+		rooms.sort_by(|a, b| b.1.cmp(&a.1));
+		let mut out =
+			format!("Rooms with too many forward extremities ({}):\n```\n", rooms.len());
+		for (room_id, count) in rooms {
+			writeln!(out, "{room_id}\t{count}")?;
+		}
+
+		out.push_str("```");
+		return Ok(RoomMessageEventContent::notice_markdown(out));
+	};
+
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let server_user = &self.services.globals.server_user;
+	let sender = if self
+		.services
+		.rooms
+		.state_cache
+		.is_joined(server_user, &room_id)
+		.await
+	{
+		Some(server_user.clone())
+	} else {
+		self.services
+			.rooms
+			.state_cache
+			.room_members(&room_id)
+			.ready_filter(|user| self.services.globals.user_is_local(user))
+			.map(ToOwned::to_owned)
+			.boxed()
+			.next()
+			.await
+	};
+
+	let Some(sender) = sender else {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"No local user is joined to {room_id} to send dummy events as."
+		)));
+	};
+
+	let before = self.extremities_count(&room_id).await;
+	let mut sent = 0_usize;
+	while sent < MAX_DUMMY_EVENTS && self.extremities_count(&room_id).await > 1 {
+		let state_lock = self.services.rooms.state.mutex.lock(&room_id).await;
+		self.services
+			.rooms
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder {
+					event_type: "org.matrix.dummy_event".into(),
+					content: to_raw_value(&serde_json::json!({}))
+						.expect("empty object is valid json"),
+					..PduBuilder::default()
+				},
+				&sender,
+				&room_id,
+				&state_lock,
+			)
+			.await?;
+
+		drop(state_lock);
+		sent = sent.saturating_add(1);
+	}
+
+	let after = self.extremities_count(&room_id).await;
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Sent {sent} dummy events to {room_id} as {sender}; it went from {before} to {after} \
+		 forward extremities."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn send_message(
+	&self,
+	room: OwnedRoomOrAliasId,
+	message: Vec<String>,
+) -> Result<RoomMessageEventContent> {
+	let message = message.join(" ");
+	if message.trim().is_empty() {
+		return Err!("The message can not be empty.");
+	}
+
+	let (room_id, servers) = self
+		.services
+		.rooms
+		.alias
+		.resolve_with_servers(&room, None)
+		.await?;
+
+	if !self.services.rooms.metadata.exists(&room_id).await {
+		return Err!("{room_id} is not known to this server.");
+	}
+
+	let server_user = &self.services.globals.server_user;
+	if !self
+		.services
+		.rooms
+		.state_cache
+		.is_joined(server_user, &room_id)
+		.await
+	{
+		join_room_by_id_helper(self.services, server_user, &room_id, None, &servers, None, &None)
+			.await?;
+	}
+
+	let state_lock = self.services.rooms.state.mutex.lock(&room_id).await;
+	let event_id = self
+		.services
+		.rooms
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::timeline(&RoomMessageEventContent::text_markdown(&message)),
+			server_user,
+			&room_id,
+			&state_lock,
+		)
+		.await?;
+
+	drop(state_lock);
+	warn!(%room_id, %event_id, "Sent a message as the server user by admin command: {message}");
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Sent {event_id} to {room_id} as {server_user}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn state_sync_status(
+	&self,
+	room: Option<OwnedRoomOrAliasId>,
+) -> Result<RoomMessageEventContent> {
+	let joins = &self.services.rooms.joins;
+	let now = utils::millis_since_unix_epoch();
+	let Some(room) = room else {
+		let rooms: Vec<_> = joins.partial_states().collect().await;
+		if rooms.is_empty() {
+			return Ok(RoomMessageEventContent::text_plain("No room is partial-state."));
+		}
+
+		let mut out = format!("Partial-state rooms ({}):\n```\n", rooms.len());
+		for (room_id, partial) in rooms {
+			let since = Duration::from_millis(now.saturating_sub(partial.since));
+			writeln!(out, "{room_id}\t{} ago\t{} failed", time::pretty(since), partial.attempts)?;
+		}
+
+		out.push_str("```");
+		return Ok(RoomMessageEventContent::notice_markdown(out));
+	};
+
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let Some(partial) = joins.partial_state(&room_id).await else {
+		return Ok(RoomMessageEventContent::text_plain(format!("{room_id} has its full state.")));
+	};
+
+	let since = Duration::from_millis(now.saturating_sub(partial.since));
+	let servers: Vec<_> = partial
+		.servers_in_room
+		.iter()
+		.map(ToString::to_string)
+		.collect();
+
+	let mut out = format!(
+		"{room_id} is partial-state since it was joined {} ago with {}.\n",
+		time::pretty(since),
+		partial.event_id,
+	);
+
+	writeln!(out, "Servers known to be in it: {}", servers.join(", "))?;
+	if let Some(error) = &partial.last_error {
+		writeln!(
+			out,
+			"Fetching its full state failed {} times, last: {error}",
+			partial.attempts
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::text_plain(out))
+}
+
+#[admin_command]
+pub(super) async fn complete_state_sync(
+	&self,
+	room: OwnedRoomOrAliasId,
+	via: Vec<OwnedServerName>,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let added = self
+		.services
+		.rooms
+		.joins
+		.complete_partial_state(&room_id, &via)
+		.await?;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Fetched the full state of {room_id}; {added} state events were missing."
+	)))
+}
+
+#[implement(Command, params = "<'_>")]
+async fn extremities_count(&self, room_id: &RoomId) -> usize {
+	self.services
+		.rooms
+		.state
+		.get_forward_extremities(room_id)
+		.count()
+		.await
+}
+
+#[admin_command]
+pub(super) async fn federation_edus(
+	&self,
+	room: OwnedRoomOrAliasId,
+	presence: Option<bool>,
+	receipts: Option<bool>,
+	typing: Option<bool>,
+	reset: bool,
+) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	if !self.services.rooms.metadata.exists(&room_id).await {
+		return Err!("{room_id} is not known to this server.");
+	}
+
+	let sending = &self.services.sending;
+	let mut edus = if reset {
+		RoomEdus::default()
+	} else {
+		sending.room_edus(&room_id).await
+	};
+
+	edus.presence = presence.or(edus.presence);
+	edus.receipts = receipts.or(edus.receipts);
+	edus.typing = typing.or(edus.typing);
+	if reset || presence.is_some() || receipts.is_some() || typing.is_some() {
+		sending.set_room_edus(&room_id, edus).await?;
+	}
+
+	let source = |value: Option<bool>| if value.is_some() { "room" } else { "config" };
+	let mut out = String::new();
+	writeln!(out, "| EDU | Federated | Set by |")?;
+	writeln!(out, "| --- | --------- | ------ |")?;
+	writeln!(
+		out,
+		"| Presence | {} | {} |",
+		sending.allow_outgoing_presence(&room_id).await,
+		source(edus.presence),
+	)?;
+	writeln!(
+		out,
+		"| Read receipts | {} | {} |",
+		sending.allow_outgoing_read_receipts(&room_id).await,
+		source(edus.receipts),
+	)?;
+	writeln!(
+		out,
+		"| Typing | {} | {} |",
+		sending.allow_outgoing_typing(&room_id).await,
+		source(edus.typing),
+	)?;
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
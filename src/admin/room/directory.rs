@@ -32,11 +32,11 @@ pub(super) async fn process(
 	let services = context.services;
 	match command {
 		| RoomDirectoryCommand::Publish { room_id } => {
-			services.rooms.directory.set_public(&room_id);
+			services.rooms.directory.set_public(&room_id).await;
 			Ok(RoomMessageEventContent::notice_plain("Room published"))
 		},
 		| RoomDirectoryCommand::Unpublish { room_id } => {
-			services.rooms.directory.set_not_public(&room_id);
+			services.rooms.directory.set_not_public(&room_id).await;
 			Ok(RoomMessageEventContent::notice_plain("Room unpublished"))
 		},
 		| RoomDirectoryCommand::List { page } => {
@@ -3,14 +3,15 @@ mod commands;
 mod directory;
 mod info;
 mod moderation;
+mod policy;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::OwnedRoomId;
+use ruma::{OwnedRoomId, OwnedRoomOrAliasId, OwnedServerName};
 
 use self::{
 	alias::RoomAliasCommand, directory::RoomDirectoryCommand, info::RoomInfoCommand,
-	moderation::RoomModerationCommand,
+	moderation::RoomModerationCommand, policy::RoomPolicyCommand,
 };
 use crate::admin_command_dispatch;
 
@@ -44,6 +45,10 @@ pub(super) enum RoomCommand {
 	/// - Manage moderation of remote or local rooms
 	Moderation(RoomModerationCommand),
 
+	#[command(subcommand)]
+	/// - Manage the moderation policy lists enforced on this server
+	Policy(RoomPolicyCommand),
+
 	#[command(subcommand)]
 	/// - Manage rooms' aliases
 	Alias(RoomAliasCommand),
@@ -56,4 +61,87 @@ pub(super) enum RoomCommand {
 	Exists {
 		room_id: OwnedRoomId,
 	},
+
+	/// - Change the history visibility of a room, as the server user or else
+	///   the first local member allowed to
+	SetHistoryVisibility {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+
+		/// One of "world_readable", "shared", "invited" or "joined"
+		visibility: String,
+	},
+
+	/// - Collapse the forward extremities of a room by sending dummy events
+	///   referring to them, as a local user joined to it
+	///
+	/// Without a room, lists the rooms with more forward extremities than
+	/// `forward_extremities_warn_threshold`.
+	PruneExtremities {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: Option<OwnedRoomOrAliasId>,
+	},
+
+	/// - Send a message to a room as the server user, e.g. an announcement
+	///
+	/// The server user joins the room first if it is not in it yet. The
+	/// message is markdown; every message sent is logged.
+	SendMessage {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+
+		message: Vec<String>,
+	},
+
+	/// - Show whether a room joined with partial state has its full state yet
+	///
+	/// Without a room, lists the rooms still partial-state.
+	StateSyncStatus {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: Option<OwnedRoomOrAliasId>,
+	},
+
+	/// - Fetch the full state of a partial-state room now instead of in the
+	///   background, reporting why each server asked refused
+	CompleteStateSync {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+
+		/// Servers to ask before those in the room
+		#[arg(long)]
+		via: Vec<OwnedServerName>,
+	},
+
+	/// - Show or override which EDUs of a room are federated
+	///
+	/// Overrides `allow_outgoing_presence`, `allow_outgoing_read_receipts` and
+	/// `allow_outgoing_typing` for the room, e.g. `--typing false` to not
+	/// federate typing in an enormous room. Without options, shows what is
+	/// federated.
+	FederationEdus {
+		/// The room in the format of `!roomid:example.com` or a room alias in
+		/// the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+
+		/// Whether presence is federated through the room
+		#[arg(long)]
+		presence: Option<bool>,
+
+		/// Whether the read receipts of the room are federated
+		#[arg(long)]
+		receipts: Option<bool>,
+
+		/// Whether typing in the room is federated
+		#[arg(long)]
+		typing: Option<bool>,
+
+		/// Remove the overrides, so the room follows the config again
+		#[arg(long, conflicts_with_all = ["presence", "receipts", "typing"])]
+		reset: bool,
+	},
 }
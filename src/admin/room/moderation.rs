@@ -1,3 +1,5 @@
+use std::fmt::Write;
+
 use api::client::leave_room;
 use clap::Subcommand;
 use conduwuit::{
@@ -74,6 +76,21 @@ pub(crate) enum RoomModerationCommand {
 		/// information
 		no_details: bool,
 	},
+
+	/// - Purges a banned room from the database: its events, state, aliases,
+	///   the room account data of users and the media its events refer to
+	///
+	/// The room stays banned, so it is not joined again. Its local users must
+	/// have left it, e.g. by banning it with --force. This cannot be undone.
+	///
+	/// Requires the `--yes-i-want-to-do-this` flag.
+	PurgeRoom {
+		#[arg(long)]
+		yes_i_want_to_do_this: bool,
+
+		/// The room ID in the format of `!roomid:example.com`
+		room_id: OwnedRoomId,
+	},
 }
 
 #[admin_command]
@@ -242,7 +259,7 @@ async fn ban_room(
 	}
 
 	// unpublish from room directory, ignore errors
-	self.services.rooms.directory.set_not_public(&room_id);
+	self.services.rooms.directory.set_not_public(&room_id).await;
 
 	if disable_federation {
 		self.services.rooms.metadata.disable_room(&room_id, true);
@@ -481,7 +498,7 @@ async fn ban_list_of_rooms(
 			.await;
 
 		// unpublish from room directory, ignore errors
-		self.services.rooms.directory.set_not_public(&room_id);
+		self.services.rooms.directory.set_not_public(&room_id).await;
 
 		if disable_federation {
 			self.services.rooms.metadata.disable_room(&room_id, true);
@@ -637,3 +654,56 @@ async fn list_banned_rooms(&self, no_details: bool) -> Result<RoomMessageEventCo
 
 	Ok(RoomMessageEventContent::notice_markdown(output_plain))
 }
+
+#[admin_command]
+async fn purge_room(
+	&self,
+	yes_i_want_to_do_this: bool,
+	room_id: OwnedRoomId,
+) -> Result<RoomMessageEventContent> {
+	if !yes_i_want_to_do_this {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Purging a room cannot be undone. Pass --yes-i-want-to-do-this to purge it.",
+		));
+	}
+
+	if self.services.admin.is_admin_room(&room_id).await {
+		return Ok(RoomMessageEventContent::text_plain("Not allowed to purge the admin room."));
+	}
+
+	if !self.services.rooms.metadata.is_banned(&room_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"Room {room_id} is not banned; ban it first."
+		)));
+	}
+
+	let joined = self
+		.services
+		.rooms
+		.state_cache
+		.room_members(&room_id)
+		.ready_filter(|user| self.services.globals.user_is_local(user))
+		.count()
+		.await;
+
+	if joined > 0 {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"{joined} local users are still joined to {room_id}; ban it with --force to evict \
+			 them first."
+		)));
+	}
+
+	let purged = self.services.rooms.metadata.purge_room(&room_id).await?;
+	let mut out = format!(
+		"Purged {room_id}: deleted {} keys and {} media files.\n```\n",
+		purged.total(),
+		purged.media
+	);
+
+	for (map, count) in &purged.keys {
+		writeln!(out, "{map}: {count}")?;
+	}
+
+	out.push_str("```");
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
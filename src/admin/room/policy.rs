@@ -0,0 +1,126 @@
+use std::fmt::Write;
+
+use api::client::{join_room_by_id_helper, leave_room};
+use clap::Subcommand;
+use conduwuit::Result;
+use ruma::{events::room::message::RoomMessageEventContent, OwnedRoomOrAliasId};
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum RoomPolicyCommand {
+	/// - Subscribe to a policy list, joining it with the server user and
+	///   enforcing its rules from now on, starting with the current ones
+	Subscribe {
+		/// The policy list room in the format of `!roomid:example.com` or a
+		/// room alias in the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - Stop enforcing the rules of a policy list and leave it. Users and
+	///   rooms banned already stay banned
+	Unsubscribe {
+		/// The policy list room in the format of `!roomid:example.com` or a
+		/// room alias in the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+	},
+
+	/// - List the policy lists subscribed to
+	List,
+
+	/// - Show what enforcing the current rules of a policy list would do,
+	///   without doing it. The server user has to be joined to the room
+	DryRun {
+		/// The policy list room in the format of `!roomid:example.com` or a
+		/// room alias in the format of `#roomalias:example.com`
+		room: OwnedRoomOrAliasId,
+	},
+}
+
+#[admin_command]
+async fn subscribe(&self, room: OwnedRoomOrAliasId) -> Result<RoomMessageEventContent> {
+	let server_user = &self.services.globals.server_user;
+	let (room_id, servers) = self
+		.services
+		.rooms
+		.alias
+		.resolve_with_servers(&room, None)
+		.await?;
+
+	if self.services.moderation.policy.is_subscribed(&room_id) {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"Already subscribed to {room_id}."
+		)));
+	}
+
+	if !self
+		.services
+		.rooms
+		.state_cache
+		.is_joined(server_user, &room_id)
+		.await
+	{
+		join_room_by_id_helper(self.services, server_user, &room_id, None, &servers, None, &None)
+			.await?;
+	}
+
+	let actions = self.services.moderation.policy.subscribe(&room_id).await?;
+	let applied = self.services.moderation.policy.apply(&actions).await;
+
+	Ok(RoomMessageEventContent::text_plain(format!(
+		"Subscribed to {room_id}. Enforcing its current rules took {applied} of {} actions.",
+		actions.len()
+	)))
+}
+
+#[admin_command]
+async fn unsubscribe(&self, room: OwnedRoomOrAliasId) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	if !self.services.moderation.policy.unsubscribe(&room_id) {
+		return Ok(RoomMessageEventContent::text_plain(format!("Not subscribed to {room_id}.")));
+	}
+
+	if !self.services.admin.is_admin_room(&room_id).await {
+		leave_room(self.services, &self.services.globals.server_user, &room_id, None).await?;
+	}
+
+	Ok(RoomMessageEventContent::text_plain(format!("Unsubscribed from {room_id}.")))
+}
+
+#[admin_command]
+async fn list(&self) -> Result<RoomMessageEventContent> {
+	let lists = self.services.moderation.policy.list();
+	if lists.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain("Not subscribed to any policy lists."));
+	}
+
+	let mut out = String::new();
+	writeln!(out, "Policy lists subscribed to ({}):", lists.len())?;
+	for (room_id, rules) in lists {
+		writeln!(out, "- {room_id}: {rules} rules")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
+
+#[admin_command]
+async fn dry_run(&self, room: OwnedRoomOrAliasId) -> Result<RoomMessageEventContent> {
+	let room_id = self.services.rooms.alias.resolve(&room).await?;
+	let actions = self.services.moderation.policy.dry_run(&room_id).await?;
+	if actions.is_empty() {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"The rules of {room_id} would not do anything."
+		)));
+	}
+
+	let mut out = String::new();
+	writeln!(out, "Enforcing the rules of {room_id} would ({}):", actions.len())?;
+	writeln!(out, "```")?;
+	for action in &actions {
+		writeln!(out, "{action}")?;
+	}
+	writeln!(out, "```")?;
+
+	Ok(RoomMessageEventContent::notice_markdown(out))
+}
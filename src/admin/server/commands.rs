@@ -1,7 +1,16 @@
-use std::{fmt::Write, sync::Arc};
+use std::{collections::BTreeSet, fmt::Write, path::PathBuf, sync::Arc};
 
-use conduwuit::{info, utils::time, warn, Err, Result};
+use conduwuit::{
+	info,
+	utils::{
+		bytes::pretty,
+		math::usize_from_u64_truncated,
+		time::{self, rfc2822_from_seconds},
+	},
+	warn, Err, Result,
+};
 use ruma::events::room::message::RoomMessageEventContent;
+use service::bus::Message;
 
 use crate::admin_command;
 
@@ -27,6 +36,57 @@ pub(super) async fn show_config(&self) -> Result<RoomMessageEventContent> {
 	)))
 }
 
+#[admin_command]
+pub(super) async fn services(&self, graph: bool) -> Result<RoomMessageEventContent> {
+	let workers = self.services.workers().await;
+	let queues = self.services.queues();
+
+	let mut res = String::new();
+	if graph {
+		writeln!(res, "```dot\ndigraph services {{")?;
+		for (name, deps) in self.services.graph() {
+			let state = workers
+				.get(name)
+				.map(|status| status.state.to_string())
+				.unwrap_or_default();
+
+			let queue = queues
+				.get(name)
+				.map(|len| format!("\\nqueue: {len}"))
+				.unwrap_or_default();
+
+			writeln!(res, "\t\"{name}\" [label=\"{name}\\n{state}{queue}\"];")?;
+			for dep in deps.iter().collect::<BTreeSet<_>>() {
+				writeln!(res, "\t\"{name}\" -> \"{dep}\";")?;
+			}
+		}
+		writeln!(res, "}}\n```")?;
+
+		return Ok(RoomMessageEventContent::notice_markdown(res));
+	}
+
+	writeln!(res, "| service | worker | since | restarts | queue | last error |")?;
+	writeln!(res, "| :--- | :--- | ---: | ---: | ---: | :--- |")?;
+	for (name, status) in &workers {
+		let since = status.since.elapsed().unwrap_or_default();
+		let queue = queues
+			.get(name)
+			.map(ToString::to_string)
+			.unwrap_or_default();
+
+		writeln!(
+			res,
+			"| {name} | {} | {} | {} | {queue} | {} |",
+			status.state,
+			time::pretty(since),
+			status.restarts,
+			status.error.as_deref().unwrap_or_default(),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(res))
+}
+
 #[admin_command]
 pub(super) async fn list_features(
 	&self,
@@ -75,6 +135,7 @@ pub(super) async fn memory_usage(&self) -> Result<RoomMessageEventContent> {
 #[admin_command]
 pub(super) async fn clear_caches(&self) -> Result<RoomMessageEventContent> {
 	self.services.clear_cache().await;
+	self.services.bus.publish(Message::ClearCaches)?;
 
 	Ok(RoomMessageEventContent::text_plain("Done."))
 }
@@ -110,6 +171,37 @@ pub(super) async fn backup_database(&self) -> Result<RoomMessageEventContent> {
 	Ok(RoomMessageEventContent::notice_markdown(result))
 }
 
+#[admin_command]
+pub(super) async fn restore(&self, to: u64) -> Result<RoomMessageEventContent> {
+	let db = Arc::clone(&self.services.db.db);
+	let path = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking(move || db.restore(to))
+		.await??;
+
+	let when = rfc2822_from_seconds(i64::try_from(to)?);
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Restored the database as of {when} to `{}`. Shut down the server, replace \
+		 `database_path` with the restored directory and restart without `rocksdb_read_only`.",
+		path.display()
+	)))
+}
+
+#[admin_command]
+pub(super) async fn export_database(&self, path: PathBuf) -> Result<RoomMessageEventContent> {
+	let db = Arc::clone(&self.services.db.db);
+	let result = self
+		.services
+		.server
+		.runtime()
+		.spawn_blocking(move || db.export(&path))
+		.await??;
+
+	Ok(RoomMessageEventContent::notice_markdown(result))
+}
+
 #[admin_command]
 pub(super) async fn list_database_files(&self) -> Result<RoomMessageEventContent> {
 	let result = self.services.globals.db.file_list()?;
@@ -117,6 +209,41 @@ pub(super) async fn list_database_files(&self) -> Result<RoomMessageEventContent
 	Ok(RoomMessageEventContent::notice_markdown(result))
 }
 
+#[admin_command]
+pub(super) async fn db_stats(&self, column: Option<String>) -> Result<RoomMessageEventContent> {
+	let mut stats: Vec<_> = self
+		.services
+		.db
+		.iter()
+		.filter(|(name, _)| column.as_deref().is_none_or(|column| **name == column))
+		.map(|(name, map)| (*name, map.stats()))
+		.collect();
+
+	if stats.is_empty() {
+		return Err!("Column {column:?} not found.");
+	}
+
+	stats.sort_by_key(|(_, stats)| std::cmp::Reverse(stats.sst_size));
+
+	let size = |bytes| pretty(usize_from_u64_truncated(bytes));
+	let mut res = String::new();
+	writeln!(res, "| column | keys | sst | memtable | block cache | pending compaction |")?;
+	writeln!(res, "| :--- | ---: | ---: | ---: | ---: | ---: |")?;
+	for (name, stats) in &stats {
+		writeln!(
+			res,
+			"| {name} | {} | {} | {} | {} | {} |",
+			stats.keys,
+			size(stats.sst_size),
+			size(stats.memtable_size),
+			size(stats.block_cache_usage),
+			size(stats.pending_compaction),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(res))
+}
+
 #[admin_command]
 pub(super) async fn admin_notice(&self, message: Vec<String>) -> Result<RoomMessageEventContent> {
 	let message = message.join(" ");
@@ -125,6 +252,35 @@ pub(super) async fn admin_notice(&self, message: Vec<String>) -> Result<RoomMess
 	Ok(RoomMessageEventContent::notice_plain("Notice was sent to #admins"))
 }
 
+#[admin_command]
+pub(super) async fn set_motd(
+	&self,
+	notice: bool,
+	message: Vec<String>,
+) -> Result<RoomMessageEventContent> {
+	let message = message.join(" ");
+	if message.trim().is_empty() {
+		return Err!("The message of the day can not be empty; use clear-motd to remove it.");
+	}
+
+	self.services.admin.set_motd(message, notice)?;
+
+	Ok(RoomMessageEventContent::notice_plain(if notice {
+		"Message of the day set; local users are sent it when they next log in."
+	} else {
+		"Message of the day set."
+	}))
+}
+
+#[admin_command]
+pub(super) async fn clear_motd(&self) -> Result<RoomMessageEventContent> {
+	Ok(RoomMessageEventContent::notice_plain(if self.services.admin.clear_motd() {
+		"Message of the day removed."
+	} else {
+		"There is no message of the day."
+	}))
+}
+
 #[admin_command]
 pub(super) async fn reload_mods(&self) -> Result<RoomMessageEventContent> {
 	self.services.server.reload()?;
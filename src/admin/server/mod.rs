@@ -1,5 +1,7 @@
 mod commands;
 
+use std::path::PathBuf;
+
 use clap::Subcommand;
 use conduwuit::Result;
 
@@ -14,6 +16,13 @@ pub(super) enum ServerCommand {
 	/// - Show configuration values
 	ShowConfig,
 
+	/// - Show the state of each service's worker and its queue depth
+	Services {
+		/// Output the dependency graph between services in Graphviz dot format
+		#[arg(long)]
+		graph: bool,
+	},
+
 	/// - List the features built into the server
 	ListFeatures {
 		#[arg(short, long)]
@@ -39,14 +48,54 @@ pub(super) enum ServerCommand {
 	/// - List database backups
 	ListBackups,
 
+	/// - Restore the database as of a point in time from the latest backup
+	///   before it and the archived write-ahead logs. The server must be
+	///   running with rocksdb_read_only; the result is written beside the
+	///   database for you to swap in
+	Restore {
+		/// Seconds since the unix epoch
+		#[arg(long)]
+		to: u64,
+	},
+
+	/// - Copy every column into a fresh database at the path, rebuilding it
+	///   without fragmentation. Running it again with the same path resumes an
+	///   interrupted export; a run with rocksdb_read_only rewrites or deletes
+	///   the keys changed since earlier runs, making the copy consistent
+	ExportDatabase {
+		/// Directory of the new database
+		path: PathBuf,
+	},
+
 	/// - List database files
 	ListDatabaseFiles,
 
+	/// - Show per-column database statistics: estimated entries, SST size,
+	///   block cache usage and pending compaction, largest columns first
+	DbStats {
+		/// Only show statistics for this column
+		column: Option<String>,
+	},
+
 	/// - Send a message to the admin room.
 	AdminNotice {
 		message: Vec<String>,
 	},
 
+	/// - Set the message of the day, shown to clients in the client well-known,
+	///   e.g. to announce maintenance
+	SetMotd {
+		/// Also send it to each local user as a server notice the next time
+		/// they log in
+		#[arg(long)]
+		notice: bool,
+
+		message: Vec<String>,
+	},
+
+	/// - Remove the message of the day
+	ClearMotd,
+
 	/// - Hot-reload the server
 	#[clap(alias = "reload")]
 	ReloadMods,
@@ -2,13 +2,14 @@ use std::{collections::BTreeMap, fmt::Write as _};
 
 use api::client::{full_user_deactivate, join_room_by_id_helper, leave_room};
 use conduwuit::{
-	debug_warn, error, info, is_equal_to,
-	utils::{self, ReadyExt},
+	debug_warn, error, implement, info, is_equal_to,
+	utils::{self, stream::TryIgnore, ReadyExt},
 	warn, PduBuilder, Result,
 };
 use conduwuit_api::client::{leave_all_rooms, update_avatar_url, update_displayname};
 use futures::StreamExt;
 use ruma::{
+	api::client::push::{set_pusher::v3::PusherAction, PusherKind},
 	events::{
 		room::{
 			message::RoomMessageEventContent,
@@ -16,18 +17,21 @@ use ruma::{
 			redaction::RoomRedactionEventContent,
 		},
 		tag::{TagEvent, TagEventContent, TagInfo},
-		RoomAccountDataEventType, StateEventType,
+		AnyRawAccountDataEvent, RoomAccountDataEventType, StateEventType, TimelineEventType,
 	},
-	EventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId, RoomId, UserId,
+	EventId, OwnedEventId, OwnedRoomId, OwnedRoomOrAliasId, OwnedUserId, RoomId, UserId,
 };
+use service::{ratelimit::Key, users::LockState};
 
 use crate::{
 	admin_command, get_room_info,
 	utils::{parse_active_local_user_id, parse_local_user_id},
+	Command,
 };
 
 const AUTO_GEN_PASSWORD_LENGTH: usize = 25;
 const BULK_JOIN_REASON: &str = "Bulk force joining this room as initiated by the server admin.";
+const ERASE_BATCH_SIZE: usize = 100;
 
 #[admin_command]
 pub(super) async fn list_users(&self) -> Result<RoomMessageEventContent> {
@@ -75,20 +79,7 @@ pub(super) async fn create_user(
 		.create(&user_id, Some(password.as_str()))?;
 
 	// Default to pretty displayname
-	let mut displayname = user_id.localpart().to_owned();
-
-	// If `new_user_displayname_suffix` is set, registration will push whatever
-	// content is set to the user's display name with a space before it
-	if !self
-		.services
-		.globals
-		.config
-		.new_user_displayname_suffix
-		.is_empty()
-	{
-		write!(displayname, " {}", self.services.globals.config.new_user_displayname_suffix)
-			.expect("should be able to write to string buffer");
-	}
+	let displayname = self.services.users.new_user_displayname(&user_id, None);
 
 	self.services
 		.users
@@ -198,6 +189,7 @@ pub(super) async fn create_user(
 pub(super) async fn deactivate(
 	&self,
 	no_leave_rooms: bool,
+	erase: bool,
 	user_id: String,
 ) -> Result<RoomMessageEventContent> {
 	// Validate user id
@@ -212,6 +204,11 @@ pub(super) async fn deactivate(
 
 	self.services.users.deactivate_account(&user_id).await?;
 
+	// redact before leaving, as the user can only redact in rooms they are in
+	if erase {
+		self.erase_user_events(&user_id).await?;
+	}
+
 	if !no_leave_rooms {
 		self.services
 			.admin
@@ -241,6 +238,61 @@ pub(super) async fn deactivate(
 	)))
 }
 
+#[admin_command]
+pub(super) async fn lock(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	self.set_user_lock_state(&user_id, Some(LockState::Locked))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn suspend(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	self.set_user_lock_state(&user_id, Some(LockState::Suspended))
+		.await
+}
+
+#[admin_command]
+pub(super) async fn unlock(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	self.set_user_lock_state(&user_id, None).await
+}
+
+#[implement(Command, params = "<'_>")]
+async fn set_user_lock_state(
+	&self,
+	user_id: &str,
+	lock_state: Option<LockState>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, user_id)?;
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to lock the server service account.",
+		));
+	}
+
+	if !self.services.users.exists(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} does not exist."
+		)));
+	}
+
+	if lock_state.is_some() && self.services.users.is_admin(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is an admin; revoke their admin privileges first."
+		)));
+	}
+
+	let previous = self.services.users.lock_state(&user_id).await;
+	self.services.users.set_lock_state(&user_id, lock_state);
+
+	let msg = match (previous, lock_state) {
+		| (None, None) => format!("User {user_id} is not locked or suspended."),
+		| (_, None) => format!("User {user_id} has been unlocked."),
+		| (_, Some(LockState::Locked)) => format!("User {user_id} has been locked."),
+		| (_, Some(LockState::Suspended)) => format!("User {user_id} has been suspended."),
+	};
+
+	Ok(RoomMessageEventContent::text_plain(msg))
+}
+
 #[admin_command]
 pub(super) async fn reset_password(
 	&self,
@@ -277,6 +329,7 @@ pub(super) async fn deactivate_all(
 	&self,
 	no_leave_rooms: bool,
 	force: bool,
+	erase: bool,
 ) -> Result<RoomMessageEventContent> {
 	if self.body.len() < 2
 		|| !self.body[0].trim().starts_with("```")
@@ -344,6 +397,18 @@ pub(super) async fn deactivate_all(
 		match self.services.users.deactivate_account(&user_id).await {
 			| Ok(()) => {
 				deactivation_count = deactivation_count.saturating_add(1);
+				if erase {
+					if let Err(e) = self.erase_user_events(&user_id).await {
+						self.services
+							.admin
+							.send_message(RoomMessageEventContent::text_plain(format!(
+								"Failed erasing the messages of {user_id}: {e}"
+							)))
+							.await
+							.ok();
+					}
+				}
+
 				if !no_leave_rooms {
 					info!("Forcing user {user_id} to leave all rooms apart of deactivate-all");
 					let all_joined_rooms: Vec<OwnedRoomId> = self
@@ -387,6 +452,93 @@ pub(super) async fn deactivate_all(
 	}
 }
 
+/// Redact all of the user's messages in the rooms they are joined to, in
+/// batches of [`ERASE_BATCH_SIZE`] with a progress notice to the admin room
+/// after each. State events are kept, as are messages already redacted.
+#[implement(Command, params = "<'_>")]
+async fn erase_user_events(&self, user_id: &UserId) -> Result<usize> {
+	let reason = format!(
+		"The administrator(s) of {} has redacted this user's message.",
+		self.services.globals.server_name()
+	);
+
+	let all_joined_rooms: Vec<OwnedRoomId> = self
+		.services
+		.rooms
+		.state_cache
+		.rooms_joined(user_id)
+		.map(Into::into)
+		.collect()
+		.await;
+
+	let mut total: usize = 0;
+	for room_id in &all_joined_rooms {
+		let event_ids: Vec<OwnedEventId> = self
+			.services
+			.rooms
+			.timeline
+			.pdus(None, room_id, None)
+			.ignore_err()
+			.ready_filter_map(|(_, pdu)| {
+				(pdu.sender == user_id
+					&& pdu.state_key.is_none()
+					&& pdu.kind != TimelineEventType::RoomRedaction
+					&& !pdu.is_redacted())
+				.then_some(pdu.event_id)
+			})
+			.collect()
+			.await;
+
+		let mut redacted: usize = 0;
+		for batch in event_ids.chunks(ERASE_BATCH_SIZE) {
+			let state_lock = self.services.rooms.state.mutex.lock(room_id).await;
+			for event_id in batch {
+				self.services
+					.rooms
+					.timeline
+					.build_and_append_pdu(
+						PduBuilder {
+							redacts: Some(event_id.clone()),
+							..PduBuilder::timeline(&RoomRedactionEventContent {
+								redacts: Some(event_id.clone()),
+								reason: Some(reason.clone()),
+							})
+						},
+						user_id,
+						room_id,
+						&state_lock,
+					)
+					.await?;
+			}
+
+			drop(state_lock);
+			redacted = redacted.saturating_add(batch.len());
+			self.services
+				.admin
+				.send_message(RoomMessageEventContent::text_plain(format!(
+					"Erasing {user_id}: redacted {redacted}/{} messages in {room_id}",
+					event_ids.len()
+				)))
+				.await
+				.ok();
+		}
+
+		total = total.saturating_add(redacted);
+	}
+
+	info!("Redacted {total} messages of {user_id} in {} rooms", all_joined_rooms.len());
+	self.services
+		.admin
+		.send_message(RoomMessageEventContent::text_plain(format!(
+			"Erased {user_id}: redacted {total} messages in {} rooms",
+			all_joined_rooms.len()
+		)))
+		.await
+		.ok();
+
+	Ok(total)
+}
+
 #[admin_command]
 pub(super) async fn list_joined_rooms(&self, user_id: String) -> Result<RoomMessageEventContent> {
 	// Validate user id
@@ -782,6 +934,227 @@ pub(super) async fn make_user_admin(&self, user_id: String) -> Result<RoomMessag
 	)))
 }
 
+#[admin_command]
+pub(super) async fn rate_limits(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let ratelimit = &self.services.ratelimit;
+	let mut msg = String::new();
+	if ratelimit.is_exempt(&user_id).await {
+		writeln!(msg, "{user_id} is exempt from the rate limits.\n")?;
+	}
+
+	let factor = ratelimit.factor(&user_id);
+	if (factor - 1.0).abs() >= f64::EPSILON {
+		writeln!(msg, "{user_id}'s rate limits are scaled by {factor}.\n")?;
+	}
+
+	let remaining = ratelimit.remaining(&Key::User(user_id.clone()));
+	if remaining.is_empty() {
+		writeln!(msg, "{user_id} has every request of their rate limits left.")?;
+		return Ok(RoomMessageEventContent::notice_markdown(msg));
+	}
+
+	writeln!(msg, "| Class | Requests left | Burst count |\n| --- | --- | --- |")?;
+	for (class, tokens, burst) in remaining {
+		writeln!(msg, "| {} | {tokens:.1} | {burst} |", class.as_str())?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn exempt_from_rate_limits(
+	&self,
+	user_id: String,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	self.services.ratelimit.set_exempt(&user_id, true);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{user_id} is now exempt from the rate limits."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unexempt_from_rate_limits(
+	&self,
+	user_id: String,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	self.services.ratelimit.set_exempt(&user_id, false);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{user_id} is subject to the rate limits again."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn list_rate_limit_exempt(&self) -> Result<RoomMessageEventContent> {
+	let users: Vec<_> = self
+		.services
+		.ratelimit
+		.list_exempt()
+		.map(ToString::to_string)
+		.collect()
+		.await;
+
+	let mut plain_msg = format!(
+		"Users exempt from the rate limits ({}):\n```\n{}\n```",
+		users.len(),
+		users.join("\n")
+	);
+
+	let factors = self.services.ratelimit.list_factors().await;
+	if !factors.is_empty() {
+		writeln!(plain_msg, "\nUsers with scaled rate limits ({}):\n```", factors.len())?;
+		for (user_id, factor) in factors {
+			writeln!(plain_msg, "{user_id}: {factor}")?;
+		}
+		writeln!(plain_msg, "```")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(plain_msg))
+}
+
+#[admin_command]
+pub(super) async fn ratelimit(
+	&self,
+	user_id: String,
+	factor: Option<f64>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	if let Some(factor) = factor {
+		self.services.ratelimit.set_factor(&user_id, factor)?;
+	}
+
+	let factor = self.services.ratelimit.factor(&user_id);
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{user_id}'s rate limits are scaled by {factor}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn email_address(
+	&self,
+	user_id: String,
+	set: Option<String>,
+	remove: bool,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	if set.is_some() || remove {
+		self.services
+			.pusher
+			.set_email_address(&user_id, set.as_deref())?;
+	}
+
+	let msg = match self.services.pusher.email_address(&user_id).await {
+		| Ok(address) => format!("{user_id} may receive email notifications at {address}."),
+		| Err(_) => format!("{user_id} has no email address for notifications."),
+	};
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn shadow_ban(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"Not allowed to shadow-ban the server service account.",
+		));
+	}
+
+	if self.services.users.is_admin(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} is an admin; revoke their admin privileges first."
+		)));
+	}
+
+	self.services.users.set_shadow_banned(&user_id, true);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{user_id} is now shadow-banned. Their events from now on are dropped without reaching \
+		 the room."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn unshadow_ban(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	self.services.users.set_shadow_banned(&user_id, false);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{user_id} is no longer shadow-banned. Their events sent while shadow-banned stay \
+		 hidden from other servers."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn list_shadow_banned(&self) -> Result<RoomMessageEventContent> {
+	let users: Vec<_> = self
+		.services
+		.users
+		.list_shadow_banned()
+		.map(ToString::to_string)
+		.collect()
+		.await;
+
+	let plain_msg =
+		format!("Shadow-banned users ({}):\n```\n{}\n```", users.len(), users.join("\n"));
+
+	Ok(RoomMessageEventContent::notice_markdown(plain_msg))
+}
+
+#[admin_command]
+pub(super) async fn service_account(
+	&self,
+	user_id: String,
+	remove: bool,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	if user_id == self.services.globals.server_user {
+		return Ok(RoomMessageEventContent::text_plain(
+			"The server service account is handled by the server itself.",
+		));
+	}
+
+	if remove {
+		self.services.users.set_service_account(&user_id, false);
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"{user_id} is no longer a service account."
+		)));
+	}
+
+	if !self.services.users.exists(&user_id).await {
+		return Ok(RoomMessageEventContent::text_plain(format!(
+			"User {user_id} does not exist."
+		)));
+	}
+
+	self.services.users.set_service_account(&user_id, true);
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"{user_id} is now a service account. It is neither rate limited nor listed in the user \
+		 directory, and its devices no longer change its presence."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn list_service_accounts(&self) -> Result<RoomMessageEventContent> {
+	let users: Vec<_> = self
+		.services
+		.users
+		.list_service_accounts()
+		.map(ToString::to_string)
+		.collect()
+		.await;
+
+	let plain_msg =
+		format!("Service accounts ({}):\n```\n{}\n```", users.len(), users.join("\n"));
+
+	Ok(RoomMessageEventContent::notice_markdown(plain_msg))
+}
+
 #[admin_command]
 pub(super) async fn put_room_tag(
 	&self,
@@ -879,6 +1252,179 @@ pub(super) async fn get_room_tags(
 	)))
 }
 
+#[admin_command]
+pub(super) async fn account_data(
+	&self,
+	user_id: String,
+	kind: Option<String>,
+	room_id: Option<OwnedRoomId>,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let room_id = room_id.as_deref();
+	let account_data = &self.services.account_data;
+
+	if let Some(kind) = kind {
+		let data = account_data.get_raw(room_id, &user_id, &kind).await?;
+		let data: serde_json::Value = serde_json::from_slice(&data)?;
+
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"```json\n{}\n```",
+			serde_json::to_string_pretty(&data)?
+		)));
+	}
+
+	let mut types: Vec<_> = account_data
+		.changes_since(room_id, &user_id, 0)
+		.map(|event| {
+			let (kind, json) = match event {
+				| AnyRawAccountDataEvent::Global(event) =>
+					(event.get_field::<String>("type"), event.json().get().len()),
+				| AnyRawAccountDataEvent::Room(event) =>
+					(event.get_field::<String>("type"), event.json().get().len()),
+			};
+
+			(kind.ok().flatten().unwrap_or_default(), json)
+		})
+		.collect()
+		.await;
+
+	types.sort_by(|a, b| b.1.cmp(&a.1));
+
+	let total = account_data.total_size(&user_id).await;
+	let mut msg = format!(
+		"{} account data types, {} of {} in total for the user:\n\n| Type | Size |\n| --- | --- \
+		 |\n",
+		types.len(),
+		utils::bytes::pretty(types.iter().map(|(_, size)| *size).sum()),
+		utils::bytes::pretty(total),
+	);
+
+	for (kind, size) in types {
+		writeln!(msg, "| {kind} | {} |", utils::bytes::pretty(size))?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn sync_status(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let devices = self.services.sync.active_syncs(&user_id);
+	if devices.is_empty() {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"No device of {user_id} is syncing right now."
+		)));
+	}
+
+	let mut msg = format!(
+		"Syncing devices of {user_id}:\n\n| Device | Requests | Since | Connected |\n| --- | \
+		 --- | --- | --- |\n"
+	);
+
+	for device in devices {
+		let Some(oldest) = device.syncs.first() else {
+			continue;
+		};
+
+		writeln!(
+			msg,
+			"| {} | {} | {} | {} |",
+			device.device_id,
+			device.syncs.len(),
+			oldest.since.as_deref().unwrap_or("initial"),
+			utils::time::pretty(oldest.connected()),
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn list_pushers(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let pushers = self.services.pusher.get_pushers(&user_id).await;
+	if pushers.is_empty() {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"{user_id} has no pushers."
+		)));
+	}
+
+	let mut msg = format!(
+		"Pushers of {user_id} ({}):\n\n| Kind | App | Push Key | Device | Destination |\n| --- \
+		 | --- | --- | --- | --- |\n",
+		pushers.len()
+	);
+
+	for pusher in pushers {
+		let (kind, destination) = match &pusher.kind {
+			| PusherKind::Http(http) => ("http", http.url.as_str()),
+			| PusherKind::Email(_) => ("email", pusher.ids.pushkey.as_str()),
+			| _ => ("unknown", ""),
+		};
+
+		writeln!(
+			msg,
+			"| {kind} | {} ({}) | {} | {} | {destination} |",
+			pusher.app_display_name,
+			pusher.ids.app_id,
+			pusher.ids.pushkey,
+			pusher.device_display_name,
+		)?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+pub(super) async fn delete_pusher(
+	&self,
+	user_id: String,
+	pushkey: String,
+) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let Ok(pusher) = self.services.pusher.get_pusher(&user_id, &pushkey).await else {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"{user_id} has no pusher with the push key {pushkey:?}."
+		)));
+	};
+
+	self.services
+		.pusher
+		.set_pusher(&user_id, &PusherAction::Delete(pusher.ids))
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Deleted the pusher {pushkey:?} of {user_id}."
+	)))
+}
+
+#[admin_command]
+pub(super) async fn test_push(&self, user_id: String) -> Result<RoomMessageEventContent> {
+	let user_id = parse_local_user_id(self.services, &user_id)?;
+	let pushers = self.services.pusher.get_pushers(&user_id).await;
+	if pushers.is_empty() {
+		return Ok(RoomMessageEventContent::notice_markdown(format!(
+			"{user_id} has no pushers."
+		)));
+	}
+
+	let mut msg = format!("Sent test notifications to the pushers of {user_id}:\n\n");
+	for pusher in pushers {
+		let result = self
+			.services
+			.pusher
+			.send_test_notice(&user_id, &pusher)
+			.await;
+
+		match result {
+			| Ok(()) => writeln!(msg, "- {}: sent", pusher.ids.pushkey)?,
+			| Err(e) => writeln!(msg, "- {}: failed: {e}", pusher.ids.pushkey)?,
+		}
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
 #[admin_command]
 pub(super) async fn redact_event(
 	&self,
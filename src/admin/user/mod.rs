@@ -1,9 +1,11 @@
 mod commands;
+mod registration_token;
 
 use clap::Subcommand;
 use conduwuit::Result;
-use ruma::{EventId, OwnedRoomOrAliasId, RoomId};
+use ruma::{EventId, OwnedRoomId, OwnedRoomOrAliasId, RoomId};
 
+use self::registration_token::RegistrationTokenCommand;
 use crate::admin_command_dispatch;
 
 #[admin_command_dispatch]
@@ -30,9 +32,31 @@ pub(super) enum UserCommand {
 	///
 	/// User will be removed from all rooms by default.
 	/// Use --no-leave-rooms to not leave all rooms by default.
+	///
+	/// Use --erase to also redact all of their messages in the rooms they are
+	/// in.
 	Deactivate {
 		#[arg(short, long)]
 		no_leave_rooms: bool,
+		#[arg(long)]
+		erase: bool,
+		user_id: String,
+	},
+
+	/// - Lock a user, refusing all their requests apart from logging out until
+	///   unlocked, while keeping their account and data
+	Lock {
+		user_id: String,
+	},
+
+	/// - Suspend a user, refusing their requests which would change anything
+	///   apart from leaving rooms and logging out until unlocked
+	Suspend {
+		user_id: String,
+	},
+
+	/// - Unlock a locked or suspended user
+	Unlock {
 		user_id: String,
 	},
 
@@ -44,6 +68,10 @@ pub(super) enum UserCommand {
 	///
 	/// Can be overridden with --no-leave-rooms.
 	///
+	/// With --erase, all of the users' messages in the rooms they are in are
+	/// redacted in batches before they leave, with progress notices posted to
+	/// the admin room. This may take a long time for users in many rooms.
+	///
 	/// Removing a mass amount of users from a room may cause a significant
 	/// amount of leave events. The time to leave rooms may depend significantly
 	/// on joined rooms and servers.
@@ -57,6 +85,9 @@ pub(super) enum UserCommand {
 		#[arg(short, long)]
 		/// Also deactivate admin accounts and will assume leave all rooms too
 		force: bool,
+		#[arg(long)]
+		/// Also redact all of the users' messages in the rooms they are in
+		erase: bool,
 	},
 
 	/// - List local users in the database
@@ -93,6 +124,89 @@ pub(super) enum UserCommand {
 		user_id: String,
 	},
 
+	/// - Show a user's rate limit buckets: the requests they have left of each
+	///   class of endpoints
+	RateLimits {
+		user_id: String,
+	},
+
+	/// - Exempt a user from the rate limits of client requests
+	ExemptFromRateLimits {
+		user_id: String,
+	},
+
+	/// - Subject a user exempted from the rate limits to them again
+	UnexemptFromRateLimits {
+		user_id: String,
+	},
+
+	/// - List the users exempted from the rate limits, and those whose rate
+	///   limits are scaled
+	ListRateLimitExempt,
+
+	/// - Show or set the factor a user's rate limits are scaled by
+	///
+	/// Bots and bridges which legitimately send many requests can be given
+	/// higher limits than regular users, e.g. `--factor 10` for ten times the
+	/// rates and burst counts. A factor of 1 removes the override.
+	Ratelimit {
+		user_id: String,
+
+		/// The factor to scale the user's rates and burst counts by
+		#[arg(long)]
+		factor: Option<f64>,
+	},
+
+	/// - Show or set the email address a user may receive notifications at
+	///
+	/// The server cannot verify email addresses itself, so email pushers are
+	/// only accepted for the address set here.
+	EmailAddress {
+		user_id: String,
+
+		/// The address to allow
+		#[arg(long)]
+		set: Option<String>,
+
+		/// Remove the address, stopping the user's email notifications
+		#[arg(long, conflicts_with = "set")]
+		remove: bool,
+	},
+
+	/// - Shadow-ban a user: their events appear to be sent, but are dropped
+	///   without reaching the room; only their own joins and leaves are kept
+	ShadowBan {
+		user_id: String,
+	},
+
+	/// - Lift the shadow-ban of a user; their events from now on reach the room
+	///   again
+	UnshadowBan {
+		user_id: String,
+	},
+
+	/// - List the shadow-banned users
+	ListShadowBanned,
+
+	/// - Make a user a service account, for bots without an appservice
+	///   registration: it is neither rate limited nor listed in the user
+	///   directory, and its devices are non-interactive, never changing its
+	///   presence
+	ServiceAccount {
+		user_id: String,
+
+		/// Make the service account a normal user again
+		#[arg(long)]
+		remove: bool,
+	},
+
+	/// - List the service accounts
+	ListServiceAccounts,
+
+	#[command(subcommand)]
+	/// - Manage the registration tokens
+	RegistrationToken(RegistrationTokenCommand),
+
 	/// - Puts a room tag for the specified user and room ID.
 	///
 	/// This is primarily useful if you'd like to set your admin room
@@ -119,6 +233,41 @@ pub(super) enum UserCommand {
 		room_id: Box<RoomId>,
 	},
 
+	/// - Show a user's account data: the types set and their sizes, or the
+	///   content of one type
+	AccountData {
+		user_id: String,
+
+		/// Account data event type to show the content of
+		kind: Option<String>,
+
+		/// Room of the account data, instead of the user's global data
+		#[arg(long)]
+		room_id: Option<OwnedRoomId>,
+	},
+
+	/// - Show the sync requests of a user's devices waiting for something to
+	///   return, with their since token and how long they have been connected
+	SyncStatus {
+		user_id: String,
+	},
+
+	/// - List a user's pushers: where their notifications are sent
+	ListPushers {
+		user_id: String,
+	},
+
+	/// - Delete a user's pusher, by the push key of its device
+	DeletePusher {
+		user_id: String,
+		pushkey: String,
+	},
+
+	/// - Send a test notification through each of a user's pushers
+	TestPush {
+		user_id: String,
+	},
+
 	/// - Attempts to forcefully redact the specified event ID from the sender
 	///   user
 	///
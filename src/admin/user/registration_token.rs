@@ -0,0 +1,106 @@
+use std::{fmt::Write, time::Duration};
+
+use clap::Subcommand;
+use conduwuit::{
+	utils::{time, time::now_millis},
+	Result,
+};
+use futures::StreamExt;
+use ruma::events::room::message::RoomMessageEventContent;
+
+use crate::{admin_command, admin_command_dispatch};
+
+#[admin_command_dispatch]
+#[derive(Debug, Subcommand)]
+pub(crate) enum RegistrationTokenCommand {
+	/// - Create a registration token
+	Create {
+		/// The token; if unspecified one is generated
+		token: Option<String>,
+
+		/// Registrations the token may be used for; unlimited if unspecified
+		#[arg(long)]
+		uses: Option<u64>,
+
+		/// When the token expires, in milliseconds since the unix epoch; never
+		/// if unspecified
+		#[arg(long)]
+		expires: Option<u64>,
+	},
+
+	/// - List the registration tokens with their uses and expiry
+	List,
+
+	/// - Revoke a registration token, so it can no longer be used
+	Revoke {
+		token: String,
+	},
+}
+
+#[admin_command]
+async fn create(
+	&self,
+	token: Option<String>,
+	uses: Option<u64>,
+	expires: Option<u64>,
+) -> Result<RoomMessageEventContent> {
+	let token = self
+		.services
+		.registration_tokens
+		.create(token, uses, expires)
+		.await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Created registration token `{token}`."
+	)))
+}
+
+#[admin_command]
+async fn list(&self) -> Result<RoomMessageEventContent> {
+	let tokens: Vec<_> = self
+		.services
+		.registration_tokens
+		.list()
+		.map(|(token, info)| (token.to_owned(), info))
+		.collect()
+		.await;
+
+	let mut msg = format!(
+		"{} registration token(s) created by the admins:\n\n| Token | Uses | Expires | Valid \
+		 |\n| --- | --- | --- | --- |\n",
+		tokens.len()
+	);
+
+	let now = now_millis();
+	for (token, info) in tokens {
+		let uses = info.uses_allowed.map_or_else(
+			|| format!("{} of unlimited", info.completed),
+			|uses_allowed| format!("{} of {uses_allowed}", info.completed),
+		);
+
+		let expires = info.expiry_time.map_or_else(
+			|| "never".to_owned(),
+			|expiry_time| {
+				let remaining = Duration::from_millis(expiry_time.saturating_sub(now));
+				format!("{expiry_time} (in {})", time::pretty(remaining))
+			},
+		);
+
+		writeln!(msg, "| `{token}` | {uses} | {expires} | {} |", info.is_valid())?;
+	}
+
+	if self.services.globals.registration_token.is_some() {
+		writeln!(msg, "\nThe static `registration_token` of the config is also valid.")?;
+	}
+
+	Ok(RoomMessageEventContent::notice_markdown(msg))
+}
+
+#[admin_command]
+async fn revoke(&self, token: String) -> Result<RoomMessageEventContent> {
+	self.services.registration_tokens.revoke(&token).await?;
+
+	Ok(RoomMessageEventContent::notice_markdown(format!(
+		"Revoked registration token `{token}`."
+	)))
+}
@@ -1,5 +1,3 @@
-use std::fmt::Write;
-
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
 use conduwuit::{
@@ -17,7 +15,7 @@ use ruma::{
 			whoami, ThirdPartyIdRemovalStatus,
 		},
 		error::ErrorKind,
-		uiaa::{AuthFlow, AuthType, UiaaInfo},
+		uiaa::{AuthData, AuthFlow, AuthType, UiaaInfo},
 	},
 	events::{
 		room::{
@@ -28,7 +26,7 @@ use ruma::{
 	},
 	push, OwnedRoomId, UserId,
 };
-use service::Services;
+use service::{hooks::Event, spamcheck::Verdict, Services};
 
 use super::{join_room_by_id_helper, DEVICE_ID_LENGTH, SESSION_ID_LENGTH, TOKEN_LENGTH};
 use crate::Ruma;
@@ -128,11 +126,11 @@ pub(crate) async fn register_route(
 	}
 
 	let is_guest = body.kind == RegistrationKind::Guest;
+	let token_required = services.registration_tokens.required().await;
 
 	if is_guest
 		&& (!services.globals.allow_guest_registration()
-			|| (services.globals.allow_registration()
-				&& services.globals.registration_token.is_some()))
+			|| (services.globals.allow_registration() && token_required))
 	{
 		info!(
 			"Guest registration disabled / registration enabled with token configured, \
@@ -231,7 +229,7 @@ pub(crate) async fn register_route(
 
 	// UIAA
 	let mut uiaainfo;
-	let skip_auth = if services.globals.registration_token.is_some() {
+	let skip_auth = if token_required {
 		// Registration token required
 		uiaainfo = UiaaInfo {
 			flows: vec![AuthFlow {
@@ -273,55 +271,51 @@ pub(crate) async fn register_route(
 		// Success!
 		} else if let Some(json) = body.json_body {
 			uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
-			services.uiaa.create(
-				&UserId::parse_with_server_name("", services.globals.server_name())
-					.expect("we know this is valid"),
-				"".into(),
-				&uiaainfo,
-				&json,
-			);
+			services
+				.uiaa
+				.create(
+					&UserId::parse_with_server_name("", services.globals.server_name())
+						.expect("we know this is valid"),
+					"".into(),
+					&mut uiaainfo,
+					&json,
+				)
+				.await;
 			return Err(Error::Uiaa(uiaainfo));
 		} else {
 			return Err(Error::BadRequest(ErrorKind::NotJson, "Not json."));
 		}
 	}
 
+	if let Verdict::Deny(reason) = services
+		.spamcheck
+		.check_registration(&user_id, client)
+		.await
+	{
+		info!("Registration of {user_id} from {client} refused by the spam checker: {reason}");
+		return Err!(Request(Forbidden("{reason}")));
+	}
+
 	let password = if is_guest { None } else { body.password.as_deref() };
 
 	// Create user
 	services.users.create(&user_id, password)?;
 
-	// Default to pretty displayname
-	let mut displayname = user_id.localpart().to_owned();
-
-	// If `new_user_displayname_suffix` is set, registration will push whatever
-	// content is set to the user's display name with a space before it
-	if !services.globals.new_user_displayname_suffix().is_empty()
-		&& body.appservice_info.is_none()
-	{
-		write!(displayname, " {}", services.globals.config.new_user_displayname_suffix)
-			.expect("should be able to write to string buffer");
+	if let Some(AuthData::RegistrationToken(auth)) = &body.auth {
+		services
+			.registration_tokens
+			.mark_used(auth.token.trim())
+			.await;
 	}
 
-	services
-		.users
-		.set_displayname(&user_id, Some(displayname.clone()));
+	// Default to pretty displayname, without the suffix or template for appservices
+	let displayname = if body.appservice_info.is_none() {
+		services.users.new_user_displayname(&user_id, None)
+	} else {
+		user_id.localpart().to_owned()
+	};
 
-	// Initial account data
-	services
-		.account_data
-		.update(
-			None,
-			&user_id,
-			GlobalAccountDataEventType::PushRules.to_string().into(),
-			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
-				content: ruma::events::push_rules::PushRulesEventContent {
-					global: push::Ruleset::server_default(&user_id),
-				},
-			})
-			.expect("to json always works"),
-		)
-		.await?;
+	set_up_new_user(&services, &user_id, displayname).await?;
 
 	// Inhibit login does not work for guests
 	if !is_guest && body.inhibit_login {
@@ -354,6 +348,12 @@ pub(crate) async fn register_route(
 		.await?;
 
 	debug_info!(%user_id, %device_id, "User account was created");
+	if body.appservice_info.is_none() {
+		services.hooks.notify(Event::Registration {
+			user_id: user_id.clone(),
+			guest: is_guest,
+		});
+	}
 
 	let device_display_name = body.initial_device_display_name.as_deref().unwrap_or("");
 
@@ -423,65 +423,13 @@ pub(crate) async fn register_route(
 	// If this is the first real user, grant them admin privileges except for guest
 	// users Note: the server user, @conduit:servername, is generated first
 	if !is_guest {
-		if let Ok(admin_room) = services.admin.get_admin_room().await {
-			if services
-				.rooms
-				.state_cache
-				.room_joined_count(&admin_room)
-				.await
-				.is_ok_and(is_equal_to!(1))
-			{
-				services.admin.make_user_admin(&user_id).await?;
-				warn!("Granting {user_id} admin privileges as the first user");
-			}
-		}
+		grant_admin_to_first_user(&services, &user_id).await?;
 	}
 
 	if body.appservice_info.is_none()
-		&& !services.globals.config.auto_join_rooms.is_empty()
 		&& (services.globals.allow_guests_auto_join_rooms() || !is_guest)
 	{
-		for room in &services.globals.config.auto_join_rooms {
-			let Ok(room_id) = services.rooms.alias.resolve(room).await else {
-				error!(
-					"Failed to resolve room alias to room ID when attempting to auto join \
-					 {room}, skipping"
-				);
-				continue;
-			};
-
-			if !services
-				.rooms
-				.state_cache
-				.server_in_room(services.globals.server_name(), &room_id)
-				.await
-			{
-				warn!(
-					"Skipping room {room} to automatically join as we have never joined before."
-				);
-				continue;
-			}
-
-			if let Some(room_server_name) = room.server_name() {
-				if let Err(e) = join_room_by_id_helper(
-					&services,
-					&user_id,
-					&room_id,
-					Some("Automatically joining this room upon registration".to_owned()),
-					&[services.globals.server_name().to_owned(), room_server_name.to_owned()],
-					None,
-					&body.appservice_info,
-				)
-				.boxed()
-				.await
-				{
-					// don't return this error so we don't fail registrations
-					error!("Failed to automatically join room {room} for user {user_id}: {e}");
-				} else {
-					info!("Automatically joined room {room} for user {user_id}");
-				};
-			}
-		}
+		auto_join_rooms(&services, &user_id).await;
 	}
 
 	Ok(register::v3::Response {
@@ -493,6 +441,93 @@ pub(crate) async fn register_route(
 	})
 }
 
+/// Give a new user their display name and the initial account data.
+pub(super) async fn set_up_new_user(
+	services: &Services,
+	user_id: &UserId,
+	displayname: String,
+) -> Result {
+	services.users.set_displayname(user_id, Some(displayname));
+
+	// Initial account data
+	services
+		.account_data
+		.update(
+			None,
+			user_id,
+			GlobalAccountDataEventType::PushRules.to_string().into(),
+			&serde_json::to_value(ruma::events::push_rules::PushRulesEvent {
+				content: ruma::events::push_rules::PushRulesEventContent {
+					global: push::Ruleset::server_default(user_id),
+				},
+			})
+			.expect("to json always works"),
+		)
+		.await
+}
+
+/// Grant admin privileges to the user if they are the first real one; the
+/// server user, @conduit:servername, is generated first.
+pub(super) async fn grant_admin_to_first_user(services: &Services, user_id: &UserId) -> Result {
+	if let Ok(admin_room) = services.admin.get_admin_room().await {
+		if services
+			.rooms
+			.state_cache
+			.room_joined_count(&admin_room)
+			.await
+			.is_ok_and(is_equal_to!(1))
+		{
+			services.admin.make_user_admin(user_id).await?;
+			warn!("Granting {user_id} admin privileges as the first user");
+		}
+	}
+
+	Ok(())
+}
+
+/// Join a new user to the `auto_join_rooms` this server is in.
+pub(super) async fn auto_join_rooms(services: &Services, user_id: &UserId) {
+	for room in &services.globals.config.auto_join_rooms {
+		let Ok(room_id) = services.rooms.alias.resolve(room).await else {
+			error!(
+				"Failed to resolve room alias to room ID when attempting to auto join {room}, \
+				 skipping"
+			);
+			continue;
+		};
+
+		if !services
+			.rooms
+			.state_cache
+			.server_in_room(services.globals.server_name(), &room_id)
+			.await
+		{
+			warn!("Skipping room {room} to automatically join as we have never joined before.");
+			continue;
+		}
+
+		if let Some(room_server_name) = room.server_name() {
+			if let Err(e) = join_room_by_id_helper(
+				services,
+				user_id,
+				&room_id,
+				Some("Automatically joining this room upon registration".to_owned()),
+				&[services.globals.server_name().to_owned(), room_server_name.to_owned()],
+				None,
+				&None,
+			)
+			.boxed()
+			.await
+			{
+				// don't return this error so we don't fail registrations
+				error!("Failed to automatically join room {room} for user {user_id}: {e}");
+			} else {
+				info!("Automatically joined room {room} for user {user_id}");
+			};
+		}
+	}
+}
+
 /// # `POST /_matrix/client/r0/account/password`
 ///
 /// Changes the password of this account.
@@ -547,7 +582,8 @@ pub(crate) async fn change_password_route(
 		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 		services
 			.uiaa
-			.create(sender_user, sender_device, &uiaainfo, &json);
+			.create(sender_user, sender_device, &mut uiaainfo, &json)
+			.await;
 
 		return Err(Error::Uiaa(uiaainfo));
 	} else {
@@ -650,7 +686,8 @@ pub(crate) async fn deactivate_route(
 		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 		services
 			.uiaa
-			.create(sender_user, sender_device, &uiaainfo, &json);
+			.create(sender_user, sender_device, &mut uiaainfo, &json)
+			.await;
 
 		return Err(Error::Uiaa(uiaainfo));
 	} else {
@@ -735,22 +772,23 @@ pub(crate) async fn request_3pid_management_token_via_msisdn_route(
 
 /// # `GET /_matrix/client/v1/register/m.login.registration_token/validity`
 ///
-/// Checks if the provided registration token is valid at the time of checking
-///
-/// Currently does not have any ratelimiting, and this isn't very practical as
-/// there is only one registration token allowed.
+/// Checks if the provided registration token is valid at the time of checking:
+/// the static token of the config, or a token created by the admins which has
+/// neither expired nor been used up.
 pub(crate) async fn check_registration_token_validity(
 	State(services): State<crate::State>,
 	body: Ruma<check_registration_token_validity::v1::Request>,
 ) -> Result<check_registration_token_validity::v1::Response> {
-	let Some(reg_token) = services.globals.registration_token.clone() else {
+	if !services.registration_tokens.required().await {
 		return Err(Error::BadRequest(
 			ErrorKind::forbidden(),
 			"Server does not allow token registration.",
 		));
-	};
+	}
 
-	Ok(check_registration_token_validity::v1::Response { valid: reg_token == body.token })
+	Ok(check_registration_token_validity::v1::Response {
+		valid: services.registration_tokens.is_valid(&body.token).await,
+	})
 }
 
 /// Runs through all the deactivation steps:
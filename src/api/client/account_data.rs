@@ -134,17 +134,19 @@ async fn set_account_data(
 	let data: serde_json::Value = serde_json::from_str(data.get())
 		.map_err(|e| err!(Request(BadJson(warn!("Invalid JSON provided: {e}")))))?;
 
+	let data = json!({
+		"type": event_type_s,
+		"content": data,
+	});
+
+	services
+		.account_data
+		.check_limits(room_id, sender_user, event_type_s, &data)
+		.await?;
+
 	services
 		.account_data
-		.update(
-			room_id,
-			sender_user,
-			event_type_s.into(),
-			&json!({
-				"type": event_type_s,
-				"content": data,
-			}),
-		)
+		.update(room_id, sender_user, event_type_s.into(), &data)
 		.await
 }
 
@@ -0,0 +1,134 @@
+//! conduwuit-specific admin API, for tools managing the server over HTTP
+//! instead of through the admin room. Every request needs the access token of
+//! a server admin.
+
+use axum::{
+	extract::{Path, State},
+	response::IntoResponse,
+	Json,
+};
+use axum_extra::{
+	headers::{authorization::Bearer, Authorization},
+	TypedHeader,
+};
+use conduwuit::{err, info, Err, Error, Result};
+use ruma::{
+	api::{appservice::Registration, client::error::ErrorKind},
+	OwnedUserId,
+};
+use service::Services;
+
+type Token = Option<TypedHeader<Authorization<Bearer>>>;
+
+/// # `GET /_conduwuit/admin/v1/appservices`
+///
+/// List the IDs of the registered appservices.
+pub(crate) async fn admin_list_appservices(
+	State(services): State<crate::State>,
+	token: Token,
+) -> Result<impl IntoResponse> {
+	admin_user(&services, token).await?;
+	let appservices = services.appservice.iter_ids().await;
+
+	Ok(Json(serde_json::json!({ "appservices": appservices })))
+}
+
+/// # `GET /_conduwuit/admin/v1/appservices/{appserviceId}`
+///
+/// Return the registration of the appservice.
+pub(crate) async fn admin_get_appservice(
+	State(services): State<crate::State>,
+	Path(appservice_id): Path<String>,
+	token: Token,
+) -> Result<impl IntoResponse> {
+	admin_user(&services, token).await?;
+	let registration = services
+		.appservice
+		.get_registration(&appservice_id)
+		.await
+		.ok_or_else(|| err!(Request(NotFound("Appservice does not exist."))))?;
+
+	Ok(Json(registration))
+}
+
+/// # `PUT /_conduwuit/admin/v1/appservices/{appserviceId}`
+///
+/// Register the appservice with the registration in the body, as YAML or
+/// JSON, replacing the one of the same ID.
+pub(crate) async fn admin_put_appservice(
+	State(services): State<crate::State>,
+	Path(appservice_id): Path<String>,
+	token: Token,
+	body: String,
+) -> Result<impl IntoResponse> {
+	let sender_user = admin_user(&services, token).await?;
+	let registration: Registration = serde_yaml::from_str(&body)
+		.map_err(|e| err!(Request(BadJson("Invalid appservice registration: {e}"))))?;
+
+	if registration.id != appservice_id {
+		return Err!(Request(InvalidParam(
+			"The ID of the registration does not match the one of the path."
+		)));
+	}
+
+	services
+		.appservice
+		.register_appservice(&registration, &body)
+		.await?;
+
+	info!("{sender_user} registered appservice {appservice_id} through the admin API");
+
+	Ok(Json(serde_json::json!({})))
+}
+
+/// # `DELETE /_conduwuit/admin/v1/appservices/{appserviceId}`
+///
+/// Unregister the appservice.
+pub(crate) async fn admin_delete_appservice(
+	State(services): State<crate::State>,
+	Path(appservice_id): Path<String>,
+	token: Token,
+) -> Result<impl IntoResponse> {
+	let sender_user = admin_user(&services, token).await?;
+	if services
+		.appservice
+		.get_registration(&appservice_id)
+		.await
+		.is_none()
+	{
+		return Err!(Request(NotFound("Appservice does not exist.")));
+	}
+
+	services
+		.appservice
+		.unregister_appservice(&appservice_id)
+		.await?;
+
+	info!("{sender_user} unregistered appservice {appservice_id} through the admin API");
+
+	Ok(Json(serde_json::json!({})))
+}
+
+/// The server admin the request is authenticated as.
+async fn admin_user(services: &Services, token: Token) -> Result<OwnedUserId> {
+	let Some(TypedHeader(Authorization(bearer))) = token else {
+		return Err!(Request(MissingToken("Missing access token.")));
+	};
+
+	let (user_id, _) = services
+		.users
+		.find_from_token(bearer.token())
+		.await
+		.map_err(|_| {
+			Error::BadRequest(
+				ErrorKind::UnknownToken { soft_logout: false },
+				"Unknown access token.",
+			)
+		})?;
+
+	if !services.users.is_admin(&user_id).await {
+		return Err!(Request(Forbidden("Only server admins may use the admin API.")));
+	}
+
+	Ok(user_id)
+}
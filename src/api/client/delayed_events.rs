@@ -0,0 +1,125 @@
+use axum::{
+	extract::State,
+	response::{IntoResponse, Response},
+	Json,
+};
+use futures::StreamExt;
+use serde_json::json;
+
+use crate::{Result, Ruma};
+
+/// # `POST /_matrix/client/unstable/org.matrix.msc4140/delayed_events/{delayId}`
+///
+/// Cancels, restarts or immediately sends one of the user's delayed events.
+///
+/// An implementation of [MSC4140](https://github.com/matrix-org/matrix-spec-proposals/pull/4140)
+pub(crate) async fn update_delayed_event_route(
+	State(services): State<crate::State>,
+	body: Ruma<update_delayed_event::unstable::Request>,
+) -> Result<update_delayed_event::unstable::Response> {
+	use update_delayed_event::unstable::UpdateAction;
+
+	let sender_user = body.sender_user();
+	let delayed = &services.rooms.delayed;
+	match body.action {
+		| UpdateAction::Cancel => delayed.cancel(sender_user, &body.delay_id).await?,
+		| UpdateAction::Restart => delayed.restart(sender_user, &body.delay_id).await?,
+		| UpdateAction::Send => delayed.send_now(sender_user, &body.delay_id).await?,
+	}
+
+	Ok(update_delayed_event::unstable::Response {})
+}
+
+/// # `GET /_matrix/client/unstable/org.matrix.msc4140/delayed_events`
+///
+/// Lists the user's pending delayed events.
+///
+/// An implementation of [MSC4140](https://github.com/matrix-org/matrix-spec-proposals/pull/4140)
+pub(crate) async fn get_delayed_events_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_delayed_events::unstable::Request>,
+) -> Result<get_delayed_events::unstable::Response> {
+	let delayed_events = services
+		.rooms
+		.delayed
+		.list(body.sender_user())
+		.collect()
+		.await;
+
+	Ok(get_delayed_events::unstable::Response { delayed_events })
+}
+
+/// Response to a send request carrying a delay: the event is not sent yet, so
+/// there is no event ID to return, only the ID to manage the delay by.
+pub(crate) fn delayed_response(delay_id: String) -> Response {
+	Json(json!({ "delay_id": delay_id })).into_response()
+}
+
+// Ruma does not define the MSC4140 endpoints (yet).
+
+pub(crate) mod update_delayed_event {
+	pub(crate) mod unstable {
+		use ruma::{
+			api::{client::error::Error, request, response, Metadata},
+			metadata,
+		};
+		use serde::{Deserialize, Serialize};
+
+		const METADATA: Metadata = metadata! {
+			method: POST,
+			rate_limited: true,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_matrix/client/unstable/org.matrix.msc4140/delayed_events/:delay_id",
+			}
+		};
+
+		#[request(error = Error)]
+		pub struct Request {
+			#[ruma_api(path)]
+			pub delay_id: String,
+
+			pub action: UpdateAction,
+		}
+
+		#[response(error = Error)]
+		#[derive(Default)]
+		pub struct Response {}
+
+		#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+		#[serde(rename_all = "lowercase")]
+		pub enum UpdateAction {
+			Cancel,
+			Restart,
+			Send,
+		}
+	}
+}
+
+pub(crate) mod get_delayed_events {
+	pub(crate) mod unstable {
+		use ruma::{
+			api::{client::error::Error, request, response, Metadata},
+			metadata,
+		};
+		use service::rooms::delayed::DelayedEvent;
+
+		const METADATA: Metadata = metadata! {
+			method: GET,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_matrix/client/unstable/org.matrix.msc4140/delayed_events",
+			}
+		};
+
+		#[request(error = Error)]
+		#[derive(Default)]
+		pub struct Request {}
+
+		#[response(error = Error)]
+		pub struct Response {
+			pub delayed_events: Vec<DelayedEvent>,
+		}
+	}
+}
@@ -121,7 +121,8 @@ pub(crate) async fn delete_device_route(
 		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 		services
 			.uiaa
-			.create(sender_user, sender_device, &uiaainfo, &json);
+			.create(sender_user, sender_device, &mut uiaainfo, &json)
+			.await;
 
 		return Err!(Uiaa(uiaainfo));
 	} else {
@@ -178,7 +179,8 @@ pub(crate) async fn delete_devices_route(
 		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 		services
 			.uiaa
-			.create(sender_user, sender_device, &uiaainfo, &json);
+			.create(sender_user, sender_device, &mut uiaainfo, &json)
+			.await;
 
 		return Err(Error::Uiaa(uiaainfo));
 	} else {
@@ -1,6 +1,13 @@
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
-use conduwuit::{info, warn, Err, Error, Result};
+use conduwuit::{
+	info,
+	utils::{
+		math::{ruma_from_usize, usize_from_ruma},
+		IterStream,
+	},
+	warn, Err, Error, Result,
+};
 use futures::{StreamExt, TryFutureExt};
 use ruma::{
 	api::{
@@ -22,9 +29,9 @@ use ruma::{
 		},
 		StateEventType,
 	},
-	uint, OwnedRoomId, RoomId, ServerName, UInt, UserId,
+	OwnedRoomId, RoomId, ServerName, UInt, UserId,
 };
-use service::Services;
+use service::{hooks::Event, Services};
 
 use crate::Ruma;
 
@@ -179,7 +186,7 @@ pub(crate) async fn set_room_visibility_route(
 				));
 			}
 
-			services.rooms.directory.set_public(&body.room_id);
+			services.rooms.directory.set_public(&body.room_id).await;
 
 			if services.globals.config.admin_room_notices {
 				services
@@ -191,8 +198,20 @@ pub(crate) async fn set_room_visibility_route(
 					.await;
 			}
 			info!("{sender_user} made {0} public to the room directory", body.room_id);
+			services.hooks.notify(Event::DirectoryPublication {
+				room_id: body.room_id.clone(),
+				user_id: sender_user.to_owned(),
+				published: true,
+			});
+		},
+		| room::Visibility::Private => {
+			services.rooms.directory.set_not_public(&body.room_id).await;
+			services.hooks.notify(Event::DirectoryPublication {
+				room_id: body.room_id.clone(),
+				user_id: sender_user.to_owned(),
+				published: false,
+			});
 		},
-		| room::Visibility::Private => services.rooms.directory.set_not_public(&body.room_id),
 		| _ => {
 			return Err(Error::BadRequest(
 				ErrorKind::InvalidParam,
@@ -261,100 +280,31 @@ pub(crate) async fn get_public_rooms_filtered_helper(
 	}
 
 	// Use limit or else 10, with maximum 100
-	let limit = limit.map_or(10, u64::from);
-	let mut num_since: u64 = 0;
-
-	if let Some(s) = &since {
-		let mut characters = s.chars();
-		let backwards = match characters.next() {
-			| Some('n') => false,
-			| Some('p') => true,
-			| _ =>
-				return Err(Error::BadRequest(ErrorKind::InvalidParam, "Invalid `since` token")),
-		};
-
-		num_since = characters
-			.collect::<String>()
-			.parse()
-			.map_err(|_| Error::BadRequest(ErrorKind::InvalidParam, "Invalid `since` token."))?;
-
-		if backwards {
-			num_since = num_since.saturating_sub(limit);
-		}
-	}
-
-	let mut all_rooms: Vec<PublicRoomsChunk> = services
+	let limit = limit.map_or(10, usize_from_ruma).min(100);
+	let page = services
 		.rooms
 		.directory
-		.public_rooms()
-		.map(ToOwned::to_owned)
-		.then(|room_id| public_rooms_chunk(services, room_id))
-		.filter_map(|chunk| async move {
-			if let Some(query) = filter.generic_search_term.as_ref().map(|q| q.to_lowercase()) {
-				if let Some(name) = &chunk.name {
-					if name.as_str().to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				if let Some(topic) = &chunk.topic {
-					if topic.to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				if let Some(canonical_alias) = &chunk.canonical_alias {
-					if canonical_alias.as_str().to_lowercase().contains(&query) {
-						return Some(chunk);
-					}
-				}
-
-				return None;
-			}
+		.public_rooms_page(
+			filter.generic_search_term.as_deref(),
+			&filter.room_types,
+			since,
+			limit,
+		)
+		.await?;
 
-			// No search term
-			Some(chunk)
-		})
-		// We need to collect all, so we can sort by member count
+	let chunk: Vec<_> = page
+		.rooms
+		.into_iter()
+		.stream()
+		.then(|room_id| public_rooms_chunk(services, room_id))
 		.collect()
 		.await;
 
-	all_rooms.sort_by(|l, r| r.num_joined_members.cmp(&l.num_joined_members));
-
-	let total_room_count_estimate = UInt::try_from(all_rooms.len()).unwrap_or_else(|_| uint!(0));
-
-	let chunk: Vec<_> = all_rooms
-		.into_iter()
-		.skip(
-			num_since
-				.try_into()
-				.expect("num_since should not be this high"),
-		)
-		.take(limit.try_into().expect("limit should not be this high"))
-		.collect();
-
-	let prev_batch = if num_since == 0 {
-		None
-	} else {
-		Some(format!("p{num_since}"))
-	};
-
-	let next_batch = if chunk.len() < limit.try_into().unwrap() {
-		None
-	} else {
-		Some(format!(
-			"n{}",
-			num_since
-				.checked_add(limit)
-				.expect("num_since and limit should not be that large")
-		))
-	};
-
 	Ok(get_public_rooms_filtered::v3::Response {
 		chunk,
-		prev_batch,
-		next_batch,
-		total_room_count_estimate: Some(total_room_count_estimate),
+		prev_batch: page.prev_batch,
+		next_batch: page.next_batch,
+		total_room_count_estimate: Some(ruma_from_usize(page.total)),
 	})
 }
 
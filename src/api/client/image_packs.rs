@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+
+use axum::extract::State;
+use conduwuit::{Err, Result};
+
+use crate::Ruma;
+
+/// # `GET /_matrix/client/unstable/im.ponies/rooms/{roomId}/image_packs`
+///
+/// Gets all of the room's emote and sticker packs at once, keyed by state
+/// key, rather than a client fetching the full room state to find them.
+///
+/// An extension of [MSC2545](https://github.com/matrix-org/matrix-spec-proposals/pull/2545)
+pub(crate) async fn get_room_image_packs_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_room_image_packs::unstable::Request>,
+) -> Result<get_room_image_packs::unstable::Response> {
+	if !services
+		.rooms
+		.state_accessor
+		.user_can_see_state_events(body.sender_user(), &body.room_id)
+		.await
+	{
+		return Err!(Request(Forbidden("You don't have permission to view the room state.")));
+	}
+
+	let packs = services.rooms.image_packs.room_packs(&body.room_id).await?;
+
+	Ok(get_room_image_packs::unstable::Response {
+		packs: packs
+			.iter()
+			.map(|(state_key, content)| (state_key.clone(), content.clone()))
+			.collect::<BTreeMap<_, _>>(),
+	})
+}
+
+// The MSC defines no endpoint for this; packs are otherwise only room state.
+
+pub(crate) mod get_room_image_packs {
+	pub(crate) mod unstable {
+		use std::collections::BTreeMap;
+
+		use ruma::{
+			api::{client::error::Error, request, response, Metadata},
+			metadata, OwnedRoomId,
+		};
+		use serde_json::value::RawValue as RawJsonValue;
+
+		const METADATA: Metadata = metadata! {
+			method: GET,
+			rate_limited: false,
+			authentication: AccessToken,
+			history: {
+				unstable => "/_matrix/client/unstable/im.ponies/rooms/:room_id/image_packs",
+			}
+		};
+
+		#[request(error = Error)]
+		pub struct Request {
+			#[ruma_api(path)]
+			pub room_id: OwnedRoomId,
+		}
+
+		#[response(error = Error)]
+		pub struct Response {
+			pub packs: BTreeMap<String, Box<RawJsonValue>>,
+		}
+	}
+}
@@ -139,7 +139,8 @@ pub(crate) async fn upload_signing_keys_route(
 		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 		services
 			.uiaa
-			.create(sender_user, sender_device, &uiaainfo, &json);
+			.create(sender_user, sender_device, &mut uiaainfo, &json)
+			.await;
 
 		return Err(Error::Uiaa(uiaainfo));
 	} else {
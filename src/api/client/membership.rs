@@ -12,8 +12,12 @@ use conduwuit::{
 	pdu::{gen_event_id_canonical_json, PduBuilder},
 	result::FlatOk,
 	trace,
-	utils::{self, shuffle, IterStream, ReadyExt},
-	warn, Err, PduEvent, Result,
+	utils::{
+		self, shuffle,
+		stream::{BroadbandExt, TryIgnore},
+		IterStream, ReadyExt,
+	},
+	warn, Err, PduCount, PduEvent, Result,
 };
 use futures::{join, FutureExt, StreamExt};
 use ruma::{
@@ -45,7 +49,8 @@ use ruma::{
 use service::{
 	appservice::RegistrationInfo,
 	pdu::gen_event_id,
-	rooms::{state::RoomMutexGuard, state_compressor::HashSetCompressStateEvent},
+	rooms::{joins::Stage, state::RoomMutexGuard, state_compressor::HashSetCompressStateEvent},
+	spamcheck::Verdict,
 	Services,
 };
 
@@ -527,6 +532,14 @@ pub(crate) async fn invite_user_route(
 			}
 		}
 
+		if let Verdict::Deny(reason) = services
+			.spamcheck
+			.user_may_invite(sender_user, user_id, &body.room_id)
+			.await
+		{
+			return Err!(Request(Forbidden("{reason}")));
+		}
+
 		if recipient_ignored_by_sender {
 			// silently drop the invite to the recipient if they've been ignored by the
 			// sender, pretend it worked
@@ -741,8 +754,8 @@ pub(crate) async fn joined_rooms_route(
 
 /// # `POST /_matrix/client/r0/rooms/{roomId}/members`
 ///
-/// Lists all joined users in a room (TODO: at a specific point in time, with a
-/// specific membership).
+/// Lists the member events of a room, optionally at a point in time given by
+/// `at` and filtered by `membership` and `not_membership`.
 ///
 /// - Only works if the user is currently joined
 pub(crate) async fn get_member_events_route(
@@ -750,26 +763,95 @@ pub(crate) async fn get_member_events_route(
 	body: Ruma<get_member_events::v3::Request>,
 ) -> Result<get_member_events::v3::Response> {
 	let sender_user = body.sender_user();
+	let room_id = &body.room_id;
 
 	if !services
 		.rooms
 		.state_accessor
-		.user_can_see_state_events(sender_user, &body.room_id)
+		.user_can_see_state_events(sender_user, room_id)
 		.await
 	{
 		return Err!(Request(Forbidden("You don't have permission to view this room.")));
 	}
 
-	Ok(get_member_events::v3::Response {
-		chunk: services
-			.rooms
-			.state_accessor
-			.room_state_full(&body.room_id)
-			.await?
+	let members = match body.at.as_deref() {
+		| None =>
+			services
+				.rooms
+				.state_accessor
+				.room_state_members(room_id)
+				.await?,
+		| Some(at) => {
+			let at: u64 = at
+				.parse()
+				.map_err(|_| err!(Request(InvalidParam("Invalid `at` token."))))?;
+
+			let (_, pdu) = services
+				.rooms
+				.timeline
+				.pdus_rev(None, room_id, Some(PduCount::Normal(at)))
+				.ignore_err()
+				.boxed()
+				.next()
+				.await
+				.ok_or_else(|| err!(Request(NotFound("No events found before `at` token."))))?;
+
+			let shortstatehash = services
+				.rooms
+				.state_accessor
+				.pdu_shortstatehash(&pdu.event_id)
+				.await?;
+
+			services
+				.rooms
+				.state_accessor
+				.state_members(shortstatehash)
+				.await?
+		},
+	};
+
+	let membership_of = |pdu: &PduEvent| {
+		pdu.get_content::<RoomMemberEventContent>()
+			.map(|content| content.membership)
+			.ok()
+	};
+
+	let chunk: Vec<_> = members
+		.iter()
+		.filter(|pdu| {
+			let Some(membership) = membership_of(pdu) else {
+				return false;
+			};
+
+			body.membership
+				.as_ref()
+				.is_none_or(|filter| filter.as_str() == membership.as_str())
+				&& body
+					.not_membership
+					.as_ref()
+					.is_none_or(|filter| filter.as_str() != membership.as_str())
+		})
+		.collect();
+
+	// The current member events returned here need not be sent again by a
+	// lazy-loading /sync or /messages for this device.
+	if let (None, Some(sender_device)) = (body.at.as_ref(), body.sender_device.as_deref()) {
+		let delivered: HashSet<_> = chunk
 			.iter()
-			.filter(|(key, _)| key.0 == StateEventType::RoomMember)
-			.map(|(_, pdu)| pdu.to_member_event())
-			.collect(),
+			.filter_map(|pdu| pdu.state_key.as_deref())
+			.filter_map(|state_key| UserId::parse(state_key).ok())
+			.collect();
+
+		services.rooms.lazy_loading.lazy_load_mark_delivered(
+			sender_user,
+			sender_device,
+			room_id,
+			&delivered,
+		);
+	}
+
+	Ok(get_member_events::v3::Response {
+		chunk: chunk.into_iter().map(PduEvent::to_member_event).collect(),
 	})
 }
 
@@ -796,17 +878,23 @@ pub(crate) async fn joined_members_route(
 
 	let joined: BTreeMap<OwnedUserId, RoomMember> = services
 		.rooms
-		.state_cache
-		.room_members(&body.room_id)
-		.map(ToOwned::to_owned)
-		.then(|user| async move {
-			(user.clone(), RoomMember {
-				display_name: services.users.displayname(&user).await.ok(),
-				avatar_url: services.users.avatar_url(&user).await.ok(),
-			})
+		.state_accessor
+		.room_state_members(&body.room_id)
+		.await?
+		.into_iter()
+		.filter_map(|pdu| {
+			let content: RoomMemberEventContent = pdu.get_content().ok()?;
+			if content.membership != MembershipState::Join {
+				return None;
+			}
+
+			let user_id = UserId::parse(pdu.state_key.as_deref()?).ok()?;
+			Some((user_id, RoomMember {
+				display_name: content.displayname,
+				avatar_url: content.avatar_url,
+			}))
 		})
-		.collect()
-		.await;
+		.collect();
 
 	Ok(joined_members::v3::Response { joined })
 }
@@ -819,6 +907,36 @@ pub async fn join_room_by_id_helper(
 	servers: &[OwnedServerName],
 	third_party_signed: Option<&ThirdPartySigned>,
 	appservice_info: &Option<RegistrationInfo>,
+) -> Result<join_room_by_id::v3::Response> {
+	// A retried join waits for the one in progress instead of starting another
+	services
+		.rooms
+		.joins
+		.join(
+			sender_user,
+			room_id,
+			join_room_by_id_helper_inner(
+				services,
+				sender_user,
+				room_id,
+				reason,
+				servers,
+				third_party_signed,
+				appservice_info,
+			),
+		)
+		.boxed()
+		.await
+}
+
+async fn join_room_by_id_helper_inner(
+	services: &Services,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	reason: Option<String>,
+	servers: &[OwnedServerName],
+	third_party_signed: Option<&ThirdPartySigned>,
+	appservice_info: &Option<RegistrationInfo>,
 ) -> Result<join_room_by_id::v3::Response> {
 	let state_lock = services.rooms.state.mutex.lock(room_id).await;
 
@@ -905,6 +1023,9 @@ async fn join_room_by_id_helper_remote(
 	_third_party_signed: Option<&ThirdPartySigned>,
 	state_lock: RoomMutexGuard,
 ) -> Result {
+	let joins = &services.rooms.joins;
+	let _permit = joins.permit(sender_user, room_id).await;
+
 	info!("Joining {room_id} over federation.");
 
 	let (make_join_response, remote_server) =
@@ -995,11 +1116,12 @@ async fn join_room_by_id_helper_remote(
 	// It has enough fields to be called a proper event now
 	let mut join_event = join_event_stub;
 
+	joins.set_stage(sender_user, room_id, Stage::SendJoin);
 	info!("Asking {remote_server} for send_join in room {room_id}");
 	let send_join_request = federation::membership::create_join_event::v2::Request {
 		room_id: room_id.to_owned(),
 		event_id: event_id.clone(),
-		omit_members: false,
+		omit_members: services.server.config.partial_state_joins,
 		pdu: services
 			.sending
 			.convert_to_outgoing_federation_event(join_event.clone())
@@ -1012,6 +1134,7 @@ async fn join_room_by_id_helper_remote(
 		.await?;
 
 	info!("send_join finished");
+	joins.set_stage(sender_user, room_id, Stage::State);
 
 	if join_authorized_via_users_server.is_some() {
 		if let Some(signed_raw) = &send_join_response.room_state.event {
@@ -1076,23 +1199,33 @@ async fn join_room_by_id_helper_remote(
 	let parsed_join_pdu = PduEvent::from_id_val(&event_id, join_event.clone())
 		.map_err(|e| err!(BadServerResponse("Invalid join event PDU: {e:?}")))?;
 
+	// With lazy validation only the current state is verified before the join;
+	// the auth chain is verified in the background, and only stored once it is.
+	let lazy = services.server.config.lazy_join_validation;
+	let concurrency = services.server.config.join_validation_concurrency.max(1);
+
 	info!("Acquiring server signing keys for response events");
 	let resp_events = &send_join_response.room_state;
 	let resp_state = &resp_events.state;
 	let resp_auth = &resp_events.auth_chain;
-	services
-		.server_keys
-		.acquire_events_pubkeys(resp_auth.iter().chain(resp_state.iter()))
-		.await;
+	if lazy {
+		services
+			.server_keys
+			.acquire_events_pubkeys(resp_state.iter())
+			.await;
+	} else {
+		services
+			.server_keys
+			.acquire_events_pubkeys(resp_auth.iter().chain(resp_state.iter()))
+			.await;
+	}
 
-	info!("Going through send_join response room_state");
+	info!("Going through send_join response room_state and auth_chain");
 	let cork = services.db.cork_and_flush();
-	let state = send_join_response
-		.room_state
-		.state
+	let state = resp_state
 		.iter()
 		.stream()
-		.then(|pdu| {
+		.broadn_then(concurrency, |pdu| {
 			services
 				.server_keys
 				.validate_and_add_event_id_no_fetch(pdu, &room_version_id)
@@ -1119,19 +1252,13 @@ async fn join_room_by_id_helper_remote(
 			}
 
 			state
-		})
-		.await;
+		});
 
-	drop(cork);
-
-	info!("Going through send_join response auth_chain");
-	let cork = services.db.cork_and_flush();
-	send_join_response
-		.room_state
-		.auth_chain
+	let auth_chain = resp_auth
 		.iter()
 		.stream()
-		.then(|pdu| {
+		.ready_filter(|_| !lazy)
+		.broadn_then(concurrency, |pdu| {
 			services
 				.server_keys
 				.validate_and_add_event_id_no_fetch(pdu, &room_version_id)
@@ -1139,11 +1266,18 @@ async fn join_room_by_id_helper_remote(
 		.ready_filter_map(Result::ok)
 		.ready_for_each(|(event_id, value)| {
 			services.rooms.outlier.add_pdu_outlier(&event_id, &value);
-		})
-		.await;
+		});
 
+	let (state, ()) = join!(state, auth_chain);
 	drop(cork);
 
+	if lazy {
+		services
+			.rooms
+			.joins
+			.defer_verification(room_id, &room_version_id, resp_auth.clone());
+	}
+
 	debug!("Running send_join auth check");
 	let fetch_state = &state;
 	let state_fetch = |k: &'static StateEventType, s: String| async move {
@@ -1228,6 +1362,27 @@ async fn join_room_by_id_helper_remote(
 		.state
 		.set_room_state(room_id, statehash_after_join, &state_lock);
 
+	if resp_events.members_omitted {
+		// The server joined through is asked for the full state first.
+		let mut servers_in_room = vec![remote_server.clone()];
+		let listed = resp_events
+			.servers_in_room
+			.iter()
+			.flatten()
+			.filter_map(|server| OwnedServerName::try_from(server.as_str()).ok());
+
+		for server in listed {
+			if !servers_in_room.contains(&server) {
+				servers_in_room.push(server);
+			}
+		}
+
+		services
+			.rooms
+			.joins
+			.mark_partial_state(room_id, event_id, servers_in_room);
+	}
+
 	Ok(())
 }
 
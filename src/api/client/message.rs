@@ -13,7 +13,10 @@ use conduwuit::{
 use futures::{FutureExt, StreamExt};
 use ruma::{
 	api::{
-		client::{filter::RoomEventFilter, message::get_message_events},
+		client::{
+			filter::{LazyLoadOptions, RoomEventFilter},
+			message::get_message_events,
+		},
 		Direction,
 	},
 	events::{AnyStateEvent, StateEventType, TimelineEventType, TimelineEventType::*},
@@ -127,13 +130,25 @@ pub(crate) async fn get_message_events_route(
 		.collect()
 		.await;
 
-	let lazy = events
-		.iter()
-		.stream()
-		.fold(LazySet::new(), |lazy, item| {
-			update_lazy(&services, room_id, sender, lazy, item, false)
-		})
-		.await;
+	// member events are only returned in the state block when lazy-loading; the
+	// client is otherwise expected to have the full membership already.
+	let lazy_load_redundant = match filter.lazy_load_options {
+		| LazyLoadOptions::Enabled { include_redundant_members } =>
+			Some(include_redundant_members),
+		| LazyLoadOptions::Disabled => None,
+	};
+
+	let lazy = match lazy_load_redundant {
+		| Some(redundant) =>
+			events
+				.iter()
+				.stream()
+				.fold(LazySet::new(), |lazy, item| {
+					update_lazy(&services, room_id, sender, lazy, item, redundant)
+				})
+				.await,
+		| None => LazySet::new(),
+	};
 
 	let state = lazy
 		.iter()
@@ -144,7 +159,7 @@ pub(crate) async fn get_message_events_route(
 
 	let next_token = events.last().map(at!(0));
 
-	if !cfg!(feature = "element_hacks") {
+	if !cfg!(feature = "element_hacks") && !lazy.is_empty() {
 		if let Some(next_token) = next_token {
 			services.rooms.lazy_loading.lazy_load_mark_sent(
 				sender_user,
@@ -199,7 +214,7 @@ pub(crate) async fn update_lazy(
 	 * https://github.com/vector-im/element-android/issues/3417
 	 * https://github.com/vector-im/element-web/issues/21034
 	 */
-	if force || cfg!(features = "element_hacks") {
+	if force || cfg!(feature = "element_hacks") {
 		lazy.insert(event.sender().into());
 		return lazy;
 	}
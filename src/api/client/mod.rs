@@ -1,13 +1,16 @@
 pub(super) mod account;
 pub(super) mod account_data;
+pub(super) mod admin;
 pub(super) mod alias;
 pub(super) mod appservice;
 pub(super) mod backup;
 pub(super) mod capabilities;
 pub(super) mod context;
+pub(super) mod delayed_events;
 pub(super) mod device;
 pub(super) mod directory;
 pub(super) mod filter;
+pub(super) mod image_packs;
 pub(super) mod keys;
 pub(super) mod media;
 pub(super) mod media_legacy;
@@ -26,6 +29,7 @@ pub(super) mod search;
 pub(super) mod send;
 pub(super) mod session;
 pub(super) mod space;
+pub(super) mod sso;
 pub(super) mod state;
 pub(super) mod sync;
 pub(super) mod tag;
@@ -42,14 +46,17 @@ pub(super) mod well_known;
 pub use account::full_user_deactivate;
 pub(super) use account::*;
 pub(super) use account_data::*;
+pub(super) use admin::*;
 pub(super) use alias::*;
 pub(super) use appservice::*;
 pub(super) use backup::*;
 pub(super) use capabilities::*;
 pub(super) use context::*;
+pub(super) use delayed_events::*;
 pub(super) use device::*;
 pub(super) use directory::*;
 pub(super) use filter::*;
+pub(super) use image_packs::*;
 pub(super) use keys::*;
 pub(super) use media::*;
 pub(super) use media_legacy::*;
@@ -70,6 +77,7 @@ pub(super) use search::*;
 pub(super) use send::*;
 pub(super) use session::*;
 pub(super) use space::*;
+pub(super) use sso::*;
 pub(super) use state::*;
 pub(super) use sync::*;
 pub(super) use tag::*;
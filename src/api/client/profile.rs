@@ -8,14 +8,11 @@ use conduwuit::{
 };
 use futures::{future::join3, StreamExt, TryStreamExt};
 use ruma::{
-	api::{
-		client::{
-			error::ErrorKind,
-			profile::{
-				get_avatar_url, get_display_name, get_profile, set_avatar_url, set_display_name,
-			},
+	api::client::{
+		error::ErrorKind,
+		profile::{
+			get_avatar_url, get_display_name, get_profile, set_avatar_url, set_display_name,
 		},
-		federation,
 	},
 	events::room::member::{MembershipState, RoomMemberEventContent},
 	presence::PresenceState,
@@ -40,6 +37,14 @@ pub(crate) async fn set_displayname_route(
 		return Err!(Request(Forbidden("You cannot update the profile of another user")));
 	}
 
+	if body
+		.displayname
+		.as_deref()
+		.is_some_and(|displayname| services.users.is_displayname_forbidden(displayname))
+	{
+		return Err!(Request(Forbidden("This display name is not allowed on this server.")));
+	}
+
 	let all_joined_rooms: Vec<OwnedRoomId> = services
 		.rooms
 		.state_cache
@@ -72,36 +77,8 @@ pub(crate) async fn get_displayname_route(
 	State(services): State<crate::State>,
 	body: Ruma<get_display_name::v3::Request>,
 ) -> Result<get_display_name::v3::Response> {
-	if !services.globals.user_is_local(&body.user_id) {
-		// Create and update our local copy of the user
-		if let Ok(response) = services
-			.sending
-			.send_federation_request(
-				body.user_id.server_name(),
-				federation::query::get_profile_information::v1::Request {
-					user_id: body.user_id.clone(),
-					field: None, // we want the full user's profile to update locally too
-				},
-			)
-			.await
-		{
-			if !services.users.exists(&body.user_id).await {
-				services.users.create(&body.user_id, None)?;
-			}
-
-			services
-				.users
-				.set_displayname(&body.user_id, response.displayname.clone());
-			services
-				.users
-				.set_avatar_url(&body.user_id, response.avatar_url.clone());
-			services
-				.users
-				.set_blurhash(&body.user_id, response.blurhash.clone());
-
-			return Ok(get_display_name::v3::Response { displayname: response.displayname });
-		}
-	}
+	// Create or update our local copy of a remote user
+	services.profiles.refresh(&body.user_id).await;
 
 	if !services.users.exists(&body.user_id).await {
 		// Return 404 if this user doesn't exist and we couldn't fetch it over
@@ -168,41 +145,8 @@ pub(crate) async fn get_avatar_url_route(
 	State(services): State<crate::State>,
 	body: Ruma<get_avatar_url::v3::Request>,
 ) -> Result<get_avatar_url::v3::Response> {
-	if !services.globals.user_is_local(&body.user_id) {
-		// Create and update our local copy of the user
-		if let Ok(response) = services
-			.sending
-			.send_federation_request(
-				body.user_id.server_name(),
-				federation::query::get_profile_information::v1::Request {
-					user_id: body.user_id.clone(),
-					field: None, // we want the full user's profile to update locally as well
-				},
-			)
-			.await
-		{
-			if !services.users.exists(&body.user_id).await {
-				services.users.create(&body.user_id, None)?;
-			}
-
-			services
-				.users
-				.set_displayname(&body.user_id, response.displayname.clone());
-
-			services
-				.users
-				.set_avatar_url(&body.user_id, response.avatar_url.clone());
-
-			services
-				.users
-				.set_blurhash(&body.user_id, response.blurhash.clone());
-
-			return Ok(get_avatar_url::v3::Response {
-				avatar_url: response.avatar_url,
-				blurhash: response.blurhash,
-			});
-		}
-	}
+	// Create or update our local copy of a remote user
+	services.profiles.refresh(&body.user_id).await;
 
 	if !services.users.exists(&body.user_id).await {
 		// Return 404 if this user doesn't exist and we couldn't fetch it over
@@ -226,56 +170,8 @@ pub(crate) async fn get_profile_route(
 	State(services): State<crate::State>,
 	body: Ruma<get_profile::v3::Request>,
 ) -> Result<get_profile::v3::Response> {
-	if !services.globals.user_is_local(&body.user_id) {
-		// Create and update our local copy of the user
-		if let Ok(response) = services
-			.sending
-			.send_federation_request(
-				body.user_id.server_name(),
-				federation::query::get_profile_information::v1::Request {
-					user_id: body.user_id.clone(),
-					field: None,
-				},
-			)
-			.await
-		{
-			if !services.users.exists(&body.user_id).await {
-				services.users.create(&body.user_id, None)?;
-			}
-
-			services
-				.users
-				.set_displayname(&body.user_id, response.displayname.clone());
-
-			services
-				.users
-				.set_avatar_url(&body.user_id, response.avatar_url.clone());
-
-			services
-				.users
-				.set_blurhash(&body.user_id, response.blurhash.clone());
-
-			services
-				.users
-				.set_timezone(&body.user_id, response.tz.clone());
-
-			for (profile_key, profile_key_value) in &response.custom_profile_fields {
-				services.users.set_profile_key(
-					&body.user_id,
-					profile_key,
-					Some(profile_key_value.clone()),
-				);
-			}
-
-			return Ok(get_profile::v3::Response {
-				displayname: response.displayname,
-				avatar_url: response.avatar_url,
-				blurhash: response.blurhash,
-				tz: response.tz,
-				custom_profile_fields: response.custom_profile_fields,
-			});
-		}
-	}
+	// Create or update our local copy of a remote user
+	services.profiles.refresh(&body.user_id).await;
 
 	if !services.users.exists(&body.user_id).await {
 		// Return 404 if this user doesn't exist and we couldn't fetch it over
@@ -46,7 +46,8 @@ pub(crate) async fn set_read_marker_route(
 		services
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id);
+			.reset_notification_counts(sender_user, &body.room_id)
+			.await;
 	}
 
 	// ping presence
@@ -109,12 +110,24 @@ pub(crate) async fn set_read_marker_route(
 /// # `POST /_matrix/client/r0/rooms/{roomId}/receipt/{receiptType}/{eventId}`
 ///
 /// Sets private read marker and public read receipt EDU.
+///
+/// - Public receipts may be for a thread, which replace only the previous
+///   receipt of the same thread
+/// - Private receipts are never federated nor shown to other users
 pub(crate) async fn create_receipt_route(
 	State(services): State<crate::State>,
 	body: Ruma<create_receipt::v3::Request>,
 ) -> Result<create_receipt::v3::Response> {
 	let sender_user = body.sender_user();
 
+	if body.receipt_type == create_receipt::v3::ReceiptType::FullyRead
+		&& body.thread != ReceiptThread::Unthreaded
+	{
+		return Err!(Request(InvalidParam("Fully read markers cannot be threaded.")));
+	}
+
+	// A receipt in a thread only marks that thread as read, and one in the main
+	// timeline all but the threads
 	if matches!(
 		&body.receipt_type,
 		create_receipt::v3::ReceiptType::Read | create_receipt::v3::ReceiptType::ReadPrivate
@@ -122,7 +135,8 @@ pub(crate) async fn create_receipt_route(
 		services
 			.rooms
 			.user
-			.reset_notification_counts(sender_user, &body.room_id);
+			.reset_receipt_notification_counts(sender_user, &body.room_id, &body.thread)
+			.await;
 	}
 
 	// ping presence
@@ -159,7 +173,7 @@ pub(crate) async fn create_receipt_route(
 						sender_user.to_owned(),
 						ruma::events::receipt::Receipt {
 							ts: Some(MilliSecondsSinceUnixEpoch::now()),
-							thread: ReceiptThread::Unthreaded,
+							thread: body.thread.clone(),
 						},
 					)]),
 				)]),
@@ -179,6 +193,12 @@ pub(crate) async fn create_receipt_route(
 				.await;
 		},
 		| create_receipt::v3::ReceiptType::ReadPrivate => {
+			// The private read marker is of the room, so one in a thread would move
+			// it back or forth through the timeline
+			if let ReceiptThread::Thread(_) = body.thread {
+				return Ok(create_receipt::v3::Response {});
+			}
+
 			let count = services
 				.rooms
 				.timeline
@@ -1,24 +1,47 @@
 use axum::extract::State;
+use conduwuit::{err, Err};
 use ruma::{
 	api::client::redact::redact_event, events::room::redaction::RoomRedactionEventContent,
 };
 
-use crate::{service::pdu::PduBuilder, Result, Ruma};
+use crate::{service::pdu::PduBuilder, utils, Result, Ruma};
 
 /// # `PUT /_matrix/client/r0/rooms/{roomId}/redact/{eventId}/{txnId}`
 ///
 /// Tries to send a redaction event into the room.
 ///
-/// - TODO: Handle txn id
+/// - Is a NOOP if the txn id was already used before and returns the same event
+///   id again
 pub(crate) async fn redact_event_route(
 	State(services): State<crate::State>,
 	body: Ruma<redact_event::v3::Request>,
 ) -> Result<redact_event::v3::Response> {
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
-	let body = body.body;
+	let sender_device = body.sender_device.as_deref();
 
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
+	// Check if this is a new transaction id
+	if let Ok(response) = services
+		.transaction_ids
+		.existing_txnid(sender_user, sender_device, &body.txn_id)
+		.await
+	{
+		// The txnid may have been used for /sendToDevice, which has no response,
+		// or for a delayed send, whose response is a delay_id
+		if !response.starts_with(b"$") {
+			return Err!(Request(InvalidParam(
+				"Tried to use txn id already used for an incompatible endpoint."
+			)));
+		}
+
+		return Ok(redact_event::v3::Response {
+			event_id: utils::string_from_bytes(&response)
+				.map(TryInto::try_into)
+				.map_err(|e| err!(Database("Invalid event_id in txnid data: {e:?}")))??,
+		});
+	}
+
 	let event_id = services
 		.rooms
 		.timeline
@@ -36,6 +59,13 @@ pub(crate) async fn redact_event_route(
 		)
 		.await?;
 
+	services.transaction_ids.add_txnid(
+		sender_user,
+		sender_device,
+		&body.txn_id,
+		event_id.as_bytes(),
+	);
+
 	drop(state_lock);
 
 	Ok(redact_event::v3::Response { event_id })
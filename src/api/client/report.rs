@@ -112,22 +112,11 @@ pub(crate) async fn report_event_route(
 	)
 	.await?;
 
-	// send admin room message that we received the report with an @room ping for
-	// urgency
 	services
-		.admin
-		.send_message(message::RoomMessageEventContent::text_markdown(format!(
-			"@room Event report received from {} -\n\nEvent ID: {}\nRoom ID: {}\nSent By: \
-			 {}\n\nReport Score: {}\nReport Reason: {}",
-			sender_user.to_owned(),
-			pdu.event_id,
-			pdu.room_id,
-			pdu.sender,
-			body.score.unwrap_or_else(|| ruma::Int::from(0)),
-			body.reason.as_deref().unwrap_or("")
-		)))
-		.await
-		.ok();
+		.moderation
+		.reports
+		.report_event(sender_user, &pdu, body.reason.clone(), body.score)
+		.await?;
 
 	Ok(report_content::v3::Response {})
 }
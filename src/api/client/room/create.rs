@@ -15,7 +15,7 @@ use ruma::{
 			canonical_alias::RoomCanonicalAliasEventContent,
 			create::RoomCreateEventContent,
 			guest_access::{GuestAccess, RoomGuestAccessEventContent},
-			history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+			history_visibility::RoomHistoryVisibilityEventContent,
 			join_rules::{JoinRule, RoomJoinRulesEventContent},
 			member::{MembershipState, RoomMemberEventContent},
 			name::RoomNameEventContent,
@@ -29,7 +29,7 @@ use ruma::{
 	CanonicalJsonObject, Int, OwnedRoomAliasId, OwnedRoomId, OwnedUserId, RoomId, RoomVersionId,
 };
 use serde_json::{json, value::to_raw_value};
-use service::{appservice::RegistrationInfo, Services};
+use service::{appservice::RegistrationInfo, hooks::Event, spamcheck::Verdict, Services};
 
 use crate::{client::invite_helper, Ruma};
 
@@ -68,6 +68,10 @@ pub(crate) async fn create_room_route(
 		));
 	}
 
+	if let Verdict::Deny(reason) = services.spamcheck.user_may_create_room(sender_user).await {
+		return Err!(Request(Forbidden("{reason}")));
+	}
+
 	let room_id: OwnedRoomId = if let Some(custom_room_id) = &body.room_id {
 		custom_room_id_check(&services, custom_room_id)?
 	} else {
@@ -318,13 +322,22 @@ pub(crate) async fn create_room_route(
 		.await?;
 
 	// 5.2 History Visibility
+	let config = &services.globals.config;
+	let history_visibility = match preset {
+		| RoomPreset::PublicChat => config.default_history_visibility_public_chat.clone(),
+		| RoomPreset::TrustedPrivateChat => config
+			.default_history_visibility_trusted_private_chat
+			.clone(),
+		| _ => config.default_history_visibility_private_chat.clone(),
+	};
+
 	services
 		.rooms
 		.timeline
 		.build_and_append_pdu(
 			PduBuilder::state(
 				String::new(),
-				&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Shared),
+				&RoomHistoryVisibilityEventContent::new(history_visibility),
 			),
 			sender_user,
 			&room_id,
@@ -448,7 +461,7 @@ pub(crate) async fn create_room_route(
 	}
 
 	if body.visibility == room::Visibility::Public {
-		services.rooms.directory.set_public(&room_id);
+		services.rooms.directory.set_public(&room_id).await;
 
 		if services.globals.config.admin_room_notices {
 			services
@@ -460,9 +473,18 @@ pub(crate) async fn create_room_route(
 				.await;
 		}
 		info!("{sender_user} made {0} public to the room directory", &room_id);
+		services.hooks.notify(Event::DirectoryPublication {
+			room_id: room_id.clone(),
+			user_id: sender_user.to_owned(),
+			published: true,
+		});
 	}
 
 	info!("{sender_user} created a room with room ID {room_id}");
+	services.hooks.notify(Event::RoomCreation {
+		room_id: room_id.clone(),
+		creator: sender_user.to_owned(),
+	});
 
 	Ok(create_room::v3::Response::new(room_id))
 }
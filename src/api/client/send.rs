@@ -1,11 +1,85 @@
 use std::collections::BTreeMap;
 
-use axum::extract::State;
+use axum::{
+	extract::State,
+	response::{IntoResponse, Response},
+};
 use conduwuit::{err, Err};
 use ruma::{api::client::message::send_message_event, events::MessageLikeEventType};
 use serde_json::from_str;
 
-use crate::{service::pdu::PduBuilder, utils, Result, Ruma};
+use super::delayed_response;
+use crate::{service::pdu::PduBuilder, utils, Result, Ruma, RumaResponse};
+
+/// # `PUT /_matrix/client/v3/rooms/{roomId}/send/{eventType}/{txnId}`
+///
+/// Send a message event into the room, or when the
+/// `org.matrix.msc4140.delay` query parameter is given schedule it to be sent
+/// after that many milliseconds and respond with its `delay_id` instead.
+pub(crate) async fn send_message_event_delayable_route(
+	State(services): State<crate::State>,
+	body: Ruma<send_message_event::v3::Request>,
+) -> Result<Response> {
+	let Some(delay) = body.delay else {
+		return send_message_event_route(State(services), body)
+			.await
+			.map(RumaResponse)
+			.map(IntoResponse::into_response);
+	};
+
+	if MessageLikeEventType::RoomEncrypted == body.event_type
+		&& !services.globals.allow_encryption()
+	{
+		return Err!(Request(Forbidden("Encryption has been disabled")));
+	}
+
+	let sender_user = body.sender_user();
+	let sender_device = body.sender_device.as_deref();
+	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
+
+	// A retried transaction gets the delay_id it was first given; delay IDs
+	// are told apart from event IDs by the latter's sigil.
+	if let Ok(response) = services
+		.transaction_ids
+		.existing_txnid(sender_user, sender_device, &body.txn_id)
+		.await
+	{
+		let delay_id = utils::string_from_bytes(&response)
+			.map_err(|e| err!(Database("Invalid delay_id in txnid data: {e:?}")))?;
+
+		if delay_id.is_empty() || delay_id.starts_with('$') {
+			return Err!(Request(InvalidParam(
+				"Tried to use txn id already used for an incompatible endpoint."
+			)));
+		}
+
+		return Ok(delayed_response(delay_id));
+	}
+
+	let delay_id = services
+		.rooms
+		.delayed
+		.schedule(
+			sender_user,
+			&body.room_id,
+			body.event_type.to_string(),
+			None,
+			body.body.body.json().to_owned(),
+			delay,
+		)
+		.await?;
+
+	services.transaction_ids.add_txnid(
+		sender_user,
+		sender_device,
+		&body.txn_id,
+		delay_id.as_bytes(),
+	);
+
+	drop(state_lock);
+
+	Ok(delayed_response(delay_id))
+}
 
 /// # `PUT /_matrix/client/v3/rooms/{roomId}/send/{eventType}/{txnId}`
 ///
@@ -45,9 +119,10 @@ pub(crate) async fn send_message_event_route(
 		.existing_txnid(sender_user, sender_device, &body.txn_id)
 		.await
 	{
-		// The client might have sent a txnid of the /sendToDevice endpoint
-		// This txnid has no response associated with it
-		if response.is_empty() {
+		// The client might have sent a txnid of the /sendToDevice endpoint,
+		// which has no response associated with it, or of a delayed send,
+		// whose response is a delay_id rather than an event ID
+		if !response.starts_with(b"$") {
 			return Err!(Request(InvalidParam(
 				"Tried to use txn id already used for an incompatible endpoint."
 			)));
@@ -11,7 +11,10 @@ use ruma::{
 			get_login_token,
 			get_login_types::{
 				self,
-				v3::{ApplicationServiceLoginType, PasswordLoginType, TokenLoginType},
+				v3::{
+					ApplicationServiceLoginType, IdentityProvider, PasswordLoginType,
+					SsoLoginType, TokenLoginType,
+				},
 			},
 			login::{
 				self,
@@ -23,7 +26,7 @@ use ruma::{
 	},
 	OwnedUserId, UserId,
 };
-use service::uiaa::SESSION_ID_LENGTH;
+use service::{uiaa::SESSION_ID_LENGTH, users::LockState};
 
 use super::{DEVICE_ID_LENGTH, TOKEN_LENGTH};
 use crate::{utils, utils::hash, Error, Result, Ruma};
@@ -38,13 +41,25 @@ pub(crate) async fn get_login_types_route(
 	InsecureClientIp(client): InsecureClientIp,
 	_body: Ruma<get_login_types::v3::Request>,
 ) -> Result<get_login_types::v3::Response> {
-	Ok(get_login_types::v3::Response::new(vec![
+	let mut flows = vec![
 		get_login_types::v3::LoginType::Password(PasswordLoginType::default()),
 		get_login_types::v3::LoginType::ApplicationService(ApplicationServiceLoginType::default()),
 		get_login_types::v3::LoginType::Token(TokenLoginType {
 			get_login_token: services.server.config.login_via_existing_session,
 		}),
-	]))
+	];
+
+	let oidc = &services.server.config.oidc;
+	if oidc.enable {
+		flows.push(get_login_types::v3::LoginType::Sso(SsoLoginType {
+			identity_providers: vec![IdentityProvider::new(
+				oidc.idp_id.clone(),
+				oidc.idp_name.clone(),
+			)],
+		}));
+	}
+
+	Ok(get_login_types::v3::Response::new(flows))
 }
 
 /// # `POST /_matrix/client/v3/login`
@@ -111,7 +126,10 @@ pub(crate) async fn login_route(
 		},
 		| login::v3::LoginInfo::Token(login::v3::Token { token }) => {
 			debug!("Got token login type");
-			if !services.server.config.login_via_existing_session {
+			// login tokens are also handed out by the SSO callback
+			if !services.server.config.login_via_existing_session
+				&& !services.server.config.oidc.enable
+			{
 				return Err!(Request(Unknown("Token login is not enabled.")));
 			}
 			services.users.find_from_login_token(token).await?
@@ -165,6 +183,10 @@ pub(crate) async fn login_route(
 		},
 	};
 
+	if services.users.lock_state(&user_id).await == Some(LockState::Locked) {
+		return Err!(Request(UserLocked("This account has been locked by the server admins.")));
+	}
+
 	// Generate new device id if the user didn't specify one
 	let device_id = body
 		.device_id
@@ -214,6 +236,10 @@ pub(crate) async fn login_route(
 
 	info!("{user_id} logged in");
 
+	if let Err(e) = services.admin.send_motd_notice(&user_id).await {
+		warn!(%user_id, "Failed to send the message of the day: {e}");
+	}
+
 	// home_server is deprecated but apparently must still be sent despite it being
 	// deprecated over 6 years ago. initially i thought this macro was unnecessary,
 	// but ruma uses this same macro for the same reason so...
@@ -274,7 +300,8 @@ pub(crate) async fn login_token_route(
 		uiaainfo.session = Some(utils::random_string(SESSION_ID_LENGTH));
 		services
 			.uiaa
-			.create(sender_user, sender_device, &uiaainfo, json);
+			.create(sender_user, sender_device, &mut uiaainfo, json)
+			.await;
 
 		return Err(Error::Uiaa(uiaainfo));
 	} else {
@@ -0,0 +1,277 @@
+//! Single sign-on (`m.login.sso`) through the OpenID Connect identity provider
+//! of `[global.oidc]`, both to log in and to confirm the identity of a user in
+//! user-interactive authentication.
+
+use axum::{
+	extract::{RawQuery, State},
+	response::{Html, IntoResponse, Redirect},
+};
+use axum_client_ip::InsecureClientIp;
+use conduwuit::{err, info, utils, Err, Result};
+use http::header;
+use ruma::{
+	api::client::{
+		session::{sso_login, sso_login_with_provider},
+		uiaa::AuthType,
+	},
+	events::room::message::RoomMessageEventContent,
+	UserId,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use service::{
+	sso::{Identity, Purpose},
+	Services,
+};
+use url::Url;
+
+use super::{auto_join_rooms, grant_admin_to_first_user, set_up_new_user, TOKEN_LENGTH};
+use crate::Ruma;
+
+/// Length of the random password of accounts registered through SSO, which
+/// they never log in with.
+const RANDOM_PASSWORD_LENGTH: usize = 32;
+
+/// Length of the nonce allowing the script of the UIAA completion page.
+const NONCE_LENGTH: usize = 24;
+
+#[derive(Deserialize)]
+struct CallbackQuery {
+	state: String,
+	code: Option<String>,
+	error: Option<String>,
+	error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct FallbackQuery {
+	session: String,
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect`
+///
+/// Redirects the user to the identity provider, which sends them back to the
+/// client at `redirectUrl` with a login token once they authenticated.
+pub(crate) async fn sso_login_route(
+	State(services): State<crate::State>,
+	body: Ruma<sso_login::v3::Request>,
+) -> Result<sso_login::v3::Response> {
+	let location = authorize_login(&services, &body.redirect_url).await?;
+
+	Ok(sso_login::v3::Response::new(location))
+}
+
+/// # `GET /_matrix/client/v3/login/sso/redirect/{idpId}`
+///
+/// Same as [`sso_login_route`] for the identity provider given, which has to be
+/// the configured one.
+pub(crate) async fn sso_login_with_provider_route(
+	State(services): State<crate::State>,
+	body: Ruma<sso_login_with_provider::v3::Request>,
+) -> Result<sso_login_with_provider::v3::Response> {
+	if body.idp_id != services.server.config.oidc.idp_id {
+		return Err!(Request(NotFound("Unknown identity provider {:?}.", body.idp_id)));
+	}
+
+	let location = authorize_login(&services, &body.redirect_url).await?;
+
+	Ok(sso_login_with_provider::v3::Response::new(location))
+}
+
+/// # `GET /_matrix/client/v3/auth/m.login.sso/fallback/web`
+///
+/// Fallback page of the `m.login.sso` stage of user-interactive
+/// authentication: redirects the user to the identity provider, which sends
+/// them back to the callback to complete the stage.
+pub(crate) async fn sso_fallback(
+	State(services): State<crate::State>,
+	RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse> {
+	let FallbackQuery { session } = parse_query(query.as_deref())?;
+	let (user_id, device_id) = services
+		.uiaa
+		.find_session(&session)
+		.await
+		.ok_or_else(|| err!(Request(NotFound("Unknown authentication session."))))?;
+
+	let url = services
+		.sso
+		.authorize(Purpose::Uiaa { user_id, device_id, session })
+		.await?;
+
+	Ok(Redirect::to(url.as_str()))
+}
+
+/// # `GET /_conduwuit/sso/callback`
+///
+/// Where the identity provider sends the user back to once they
+/// authenticated. Users logging in for the first time get an account, unless
+/// that is disabled by `oidc.allow_registration`.
+#[tracing::instrument(skip_all, fields(%client), name = "sso")]
+pub(crate) async fn sso_callback(
+	State(services): State<crate::State>,
+	InsecureClientIp(client): InsecureClientIp,
+	RawQuery(query): RawQuery,
+) -> Result<impl IntoResponse> {
+	let CallbackQuery { state, code, error, error_description } = parse_query(query.as_deref())?;
+	if let Some(error) = error {
+		return Err!(Request(Forbidden(
+			"The identity provider refused to authenticate: {error} {}",
+			error_description.unwrap_or_default()
+		)));
+	}
+
+	let code = code.ok_or_else(|| err!(Request(MissingParam("Missing code parameter."))))?;
+	let (purpose, identity) = services.sso.callback(&state, &code).await?;
+	match (purpose, identity) {
+		| (Purpose::Login { mut redirect_url }, identity) => {
+			let user_id = match identity {
+				| Identity::Linked(user_id) => {
+					if services
+						.users
+						.is_deactivated(&user_id)
+						.await
+						.unwrap_or(true)
+					{
+						return Err!(Request(UserDeactivated("The user has been deactivated")));
+					}
+
+					user_id
+				},
+				| Identity::New { subject, user_id, displayname } => {
+					register(&services, &user_id, displayname, &client.to_string()).await?;
+					services.sso.link(&subject, &user_id);
+					user_id
+				},
+			};
+
+			let token = utils::random_string(TOKEN_LENGTH);
+			services.users.create_login_token(&user_id, &token);
+			redirect_url
+				.query_pairs_mut()
+				.append_pair("loginToken", &token);
+
+			info!(%user_id, "Logged in through SSO");
+
+			let host = redirect_url.host_str().unwrap_or_default();
+			Ok(page(
+				"Continue to your client",
+				&format!(
+					"<p>You are signed in as {}.</p><p>Continuing gives the client at \
+					 <strong>{}</strong> access to your account; only continue if you trust \
+					 it.</p><p><a href=\"{}\">Continue to {}</a></p>",
+					escape_html(user_id.as_str()),
+					escape_html(host),
+					escape_html(redirect_url.as_str()),
+					escape_html(host),
+				),
+			)
+			.into_response())
+		},
+		| (Purpose::Uiaa { user_id, device_id, session }, Identity::Linked(linked)) => {
+			if linked != user_id {
+				return Err!(Request(Forbidden(
+					"Authenticated as {linked} instead of {user_id} with the identity provider."
+				)));
+			}
+
+			services
+				.uiaa
+				.complete_stage(&user_id, &device_id, &session, AuthType::Sso)
+				.await?;
+
+			// The router's content security policy forbids scripts, so the page allows
+			// only its own with a nonce.
+			let nonce = utils::random_string(NONCE_LENGTH);
+			let csp = format!(
+				"default-src 'none';script-src 'nonce-{nonce}';frame-ancestors \
+				 'none';form-action 'none';base-uri 'none'"
+			);
+
+			let body = page(
+				"Authentication complete",
+				&format!(
+					"<p>You may now close this page and return to your client.</p><script \
+					 nonce=\"{nonce}\">if (window.onAuthDone) {{ window.onAuthDone(); }} else \
+					 if (window.opener && window.opener.postMessage) {{ \
+					 window.opener.postMessage(\"authDone\", \"*\"); }}</script>",
+				),
+			);
+
+			Ok(([(header::CONTENT_SECURITY_POLICY, csp)], body).into_response())
+		},
+		| (Purpose::Uiaa { user_id, .. }, Identity::New { .. }) => Err!(Request(Forbidden(
+			"The account {user_id} is not linked to the identity provider."
+		))),
+	}
+}
+
+async fn authorize_login(services: &Services, redirect_url: &str) -> Result<String> {
+	let redirect_url = Url::parse(redirect_url)
+		.map_err(|e| err!(Request(InvalidParam("Invalid redirect URL: {e}"))))?;
+
+	if !matches!(redirect_url.scheme(), "http" | "https") {
+		return Err!(Request(InvalidParam("The redirect URL has to be an http(s) URL.")));
+	}
+
+	let url = services
+		.sso
+		.authorize(Purpose::Login { redirect_url })
+		.await?;
+
+	Ok(url.into())
+}
+
+/// Create the account of a user logging in through SSO for the first time,
+/// set up like accounts registered with a password.
+async fn register(
+	services: &Services,
+	user_id: &UserId,
+	displayname: Option<String>,
+	client: &str,
+) -> Result {
+	let password = utils::random_string(RANDOM_PASSWORD_LENGTH);
+	services.users.create(user_id, Some(&password))?;
+
+	let displayname = services
+		.users
+		.new_user_displayname(user_id, displayname.as_deref());
+
+	set_up_new_user(services, user_id, displayname).await?;
+
+	info!("New user \"{user_id}\" registered on this server through SSO.");
+	if services.globals.config.admin_room_notices {
+		services
+			.admin
+			.send_message(RoomMessageEventContent::notice_plain(format!(
+				"New user \"{user_id}\" registered on this server through SSO from IP {client}"
+			)))
+			.await
+			.ok();
+	}
+
+	grant_admin_to_first_user(services, user_id).await?;
+	auto_join_rooms(services, user_id).await;
+
+	Ok(())
+}
+
+fn parse_query<T: DeserializeOwned>(query: Option<&str>) -> Result<T> {
+	serde_html_form::from_str(query.unwrap_or_default())
+		.map_err(|e| err!(Request(InvalidParam("Invalid query parameters: {e}"))))
+}
+
+fn page(title: &str, body: &str) -> Html<String> {
+	Html(format!(
+		"<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" \
+		 content=\"width=device-width, \
+		 initial-scale=1\"><title>{title}</title></head><body><h1>{title}</h1>{body}</body></\
+		 html>"
+	))
+}
+
+fn escape_html(s: &str) -> String {
+	s.replace('&', "&amp;")
+		.replace('<', "&lt;")
+		.replace('>', "&gt;")
+		.replace('"', "&quot;")
+}
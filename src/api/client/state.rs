@@ -1,4 +1,7 @@
-use axum::extract::State;
+use axum::{
+	extract::State,
+	response::{IntoResponse, Response},
+};
 use conduwuit::{err, pdu::PduBuilder, utils::BoolExt, Err, PduEvent, Result};
 use ruma::{
 	api::client::state::{get_state_events, get_state_events_for_key, send_state_event},
@@ -14,8 +17,9 @@ use ruma::{
 	serde::Raw,
 	OwnedEventId, RoomId, UserId,
 };
-use service::Services;
+use service::{rooms::image_packs, Services};
 
+use super::delayed_response;
 use crate::{Ruma, RumaResponse};
 
 /// # `PUT /_matrix/client/*/rooms/{roomId}/state/{eventType}/{stateKey}`
@@ -51,10 +55,50 @@ pub(crate) async fn send_state_event_for_key_route(
 pub(crate) async fn send_state_event_for_empty_key_route(
 	State(services): State<crate::State>,
 	body: Ruma<send_state_event::v3::Request>,
-) -> Result<RumaResponse<send_state_event::v3::Response>> {
-	send_state_event_for_key_route(State(services), body)
-		.await
-		.map(RumaResponse)
+) -> Result<Response> {
+	send_state_event_delayable_route(State(services), body).await
+}
+
+/// # `PUT /_matrix/client/*/rooms/{roomId}/state/{eventType}/{stateKey}`
+///
+/// Sends a state event into the room, or when the `org.matrix.msc4140.delay`
+/// query parameter is given schedule it to be sent after that many
+/// milliseconds and respond with its `delay_id` instead.
+pub(crate) async fn send_state_event_delayable_route(
+	State(services): State<crate::State>,
+	body: Ruma<send_state_event::v3::Request>,
+) -> Result<Response> {
+	let Some(delay) = body.delay else {
+		return send_state_event_for_key_route(State(services), body)
+			.await
+			.map(RumaResponse)
+			.map(IntoResponse::into_response);
+	};
+
+	let json = &body.body.body;
+	allowed_to_send_state_event(
+		&services,
+		&body.room_id,
+		&body.event_type,
+		&body.state_key,
+		json,
+	)
+	.await?;
+
+	let delay_id = services
+		.rooms
+		.delayed
+		.schedule(
+			body.sender_user(),
+			&body.room_id,
+			body.event_type.to_string(),
+			Some(body.state_key.clone()),
+			json.json().to_owned(),
+			delay,
+		)
+		.await?;
+
+	Ok(delayed_response(delay_id))
 }
 
 /// # `GET /_matrix/client/v3/rooms/{roomid}/state`
@@ -182,6 +226,12 @@ async fn send_state_event_for_key_helper(
 		)
 		.await?;
 
+	services
+		.rooms
+		.delayed
+		.cancel_state(room_id, &event_type.to_string(), state_key)
+		.await;
+
 	Ok(event_id)
 }
 
@@ -193,6 +243,9 @@ async fn allowed_to_send_state_event(
 	json: &Raw<AnyStateEventContent>,
 ) -> Result {
 	match event_type {
+		| _ if *event_type == StateEventType::from(image_packs::ROOM_EMOTES) => {
+			services.rooms.image_packs.validate_pack(json.json())?;
+		},
 		| StateEventType::RoomCreate => {
 			return Err!(Request(BadJson(
 				"You cannot update m.room.create after a room has been created."
@@ -286,6 +339,16 @@ async fn allowed_to_send_state_event(
 				)));
 			};
 
+			if membership_content
+				.displayname
+				.as_deref()
+				.is_some_and(|displayname| services.users.is_displayname_forbidden(displayname))
+			{
+				return Err!(Request(Forbidden(
+					"This display name is not allowed on this server."
+				)));
+			}
+
 			if let Some(authorising_user) = membership_content.join_authorized_via_users_server {
 				if membership_content.membership != MembershipState::Join {
 					return Err!(Request(BadJson(
@@ -1,8 +1,4 @@
-use std::{
-	cmp::{self},
-	collections::{hash_map::Entry, BTreeMap, HashMap, HashSet},
-	time::Duration,
-};
+use std::collections::{hash_map::Entry, BTreeMap, HashMap, HashSet};
 
 use axum::extract::State;
 use conduwuit::{
@@ -114,13 +110,17 @@ pub(crate) async fn sync_events_route(
 	let (sender_user, sender_device) = body.sender();
 
 	// Presence update
-	if services.globals.allow_local_presence() {
+	if services.globals.allow_local_presence() && !services.globals.is_read_only() {
 		services
 			.presence
 			.ping_presence(sender_user, &body.body.set_presence)
 			.await?;
 	}
 
+	let active = services
+		.sync
+		.track_sync(sender_user, sender_device, body.body.since.as_deref());
+
 	// Setup watchers, so if there's no response, we can wait for them
 	let watcher = services.sync.watch(sender_user, sender_device);
 
@@ -137,9 +137,10 @@ pub(crate) async fn sync_events_route(
 
 	// Hang a few seconds so requests are not spammed
 	// Stop hanging if new info arrives
-	let default = Duration::from_secs(30);
-	let duration = cmp::min(body.body.timeout.unwrap_or(default), default);
-	_ = tokio::time::timeout(duration, watcher).await;
+	services
+		.sync
+		.wait(&active, watcher, body.body.timeout)
+		.await;
 
 	// Retry returning data
 	build_sync_events(&services, &body).await
@@ -175,6 +176,7 @@ pub(crate) async fn build_sync_events(
 	};
 
 	let full_state = body.body.full_state;
+	let unread_thread_notifications = filter.room.timeline.unread_thread_notifications;
 
 	let since = body
 		.body
@@ -199,6 +201,7 @@ pub(crate) async fn build_sync_events(
 				lazy_load_enabled,
 				lazy_load_send_redundant,
 				full_state,
+				unread_thread_notifications,
 			)
 			.map_ok(move |(joined_room, dlu, jeu)| (room_id, joined_room, dlu, jeu))
 			.ok()
@@ -626,6 +629,7 @@ async fn load_joined_room(
 	lazy_load_enabled: bool,
 	lazy_load_send_redundant: bool,
 	full_state: bool,
+	unread_thread_notifications: bool,
 ) -> Result<(JoinedRoom, HashSet<OwnedUserId>, HashSet<OwnedUserId>)> {
 	// Get and drop the lock to wait for remaining operations to finish
 	// This will make sure the we have all events until next_batch
@@ -641,12 +645,8 @@ async fn load_joined_room(
 		.get_room_shortstatehash(room_id)
 		.map_err(|_| err!(Database(error!("Room {room_id} has no state"))));
 
-	let since_shortstatehash = services
-		.rooms
-		.user
-		.get_token_shortstatehash(room_id, since)
-		.ok()
-		.map(Ok);
+	let since_shortstatehash =
+		since_shortstatehash(services, sender_user, sender_device, room_id, since).map(Ok);
 
 	let timeline = load_timeline(
 		services,
@@ -807,7 +807,41 @@ async fn load_joined_room(
 			.await;
 
 	let (room_events, account_data_events, receipt_events, typing_events) = events;
-	let (notification_count, highlight_count) = unread_notifications;
+	let (mut notification_count, mut highlight_count) = unread_notifications;
+
+	// Clients asking for the counts of threads separately get those of the main
+	// timeline alone for the room
+	let thread_counts = if send_notification_counts && unread_thread_notifications {
+		services
+			.rooms
+			.user
+			.thread_notification_counts(sender_user, room_id)
+			.await
+	} else {
+		BTreeMap::new()
+	};
+
+	let (thread_notifications, thread_highlights) =
+		thread_counts
+			.values()
+			.fold((0_u64, 0_u64), |(notifications, highlights), &(n, h)| {
+				(notifications.saturating_add(n), highlights.saturating_add(h))
+			});
+
+	notification_count =
+		notification_count.map(|count| count.saturating_sub(ruma_from_u64(thread_notifications)));
+	highlight_count =
+		highlight_count.map(|count| count.saturating_sub(ruma_from_u64(thread_highlights)));
+
+	let unread_thread_notifications = thread_counts
+		.into_iter()
+		.map(|(thread_id, (notifications, highlights))| {
+			(thread_id, UnreadNotificationsCount {
+				notification_count: Some(ruma_from_u64(notifications)),
+				highlight_count: Some(ruma_from_u64(highlights)),
+			})
+		})
+		.collect();
 
 	device_list_updates.extend(device_updates);
 
@@ -842,6 +876,14 @@ async fn load_joined_room(
 		.associate_token_shortstatehash(room_id, next_batch, current_shortstatehash)
 		.await;
 
+	services.rooms.user.associate_device_shortstatehash(
+		sender_user,
+		sender_device,
+		room_id,
+		next_batch,
+		current_shortstatehash,
+	);
+
 	let joined_room = JoinedRoom {
 		account_data: RoomAccountData { events: account_data_events },
 		summary: RoomSummary {
@@ -871,12 +913,82 @@ async fn load_joined_room(
 				.collect(),
 		},
 		ephemeral: Ephemeral { events: edus },
-		unread_thread_notifications: BTreeMap::new(),
+		unread_thread_notifications,
 	};
 
 	Ok((joined_room, device_list_updates, left_encrypted_users))
 }
 
+/// The room state at `since` to send the changes from: the state recorded for
+/// the token, or else the state last delivered to the device when that was at
+/// `since` or before, or else the state at the last event before `since` when
+/// the user was joined by then. Without any, the full state is sent.
+async fn since_shortstatehash(
+	services: &Services,
+	sender_user: &UserId,
+	sender_device: &DeviceId,
+	room_id: &RoomId,
+	since: u64,
+) -> Option<ShortStateHash> {
+	if since == 0 {
+		return None;
+	}
+
+	let user = &services.rooms.user;
+	if let Ok(shortstatehash) = user.get_token_shortstatehash(room_id, since).await {
+		return Some(shortstatehash);
+	}
+
+	if let Ok((token, shortstatehash)) = user
+		.get_device_shortstatehash(sender_user, sender_device, room_id)
+		.await
+	{
+		if token <= since {
+			return Some(shortstatehash);
+		}
+	}
+
+	let sincecount = PduCount::Normal(since);
+	if !joined_before(services, sender_user, room_id, sincecount).await {
+		return None;
+	}
+
+	let until = PduCount::Normal(since.saturating_add(1));
+	let (_, pdu) = services
+		.rooms
+		.timeline
+		.pdus_rev(None, room_id, Some(until))
+		.boxed()
+		.next()
+		.await?
+		.ok()?;
+
+	services
+		.rooms
+		.state_accessor
+		.pdu_shortstatehash(&pdu.event_id)
+		.await
+		.ok()
+}
+
+/// Whether the user's current membership in the room predates `since`.
+async fn joined_before(
+	services: &Services,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	since: PduCount,
+) -> bool {
+	services
+		.rooms
+		.state_accessor
+		.room_state_get_id(room_id, &StateEventType::RoomMember, sender_user.as_str())
+		.and_then(|event_id: OwnedEventId| async move {
+			services.rooms.timeline.get_pdu_count(&event_id).await
+		})
+		.await
+		.is_ok_and(|count| count <= since)
+}
+
 #[tracing::instrument(
 	name = "state",
 	level = "trace",
@@ -943,6 +1055,7 @@ async fn calculate_state_changes(
 			sender_device,
 			room_id,
 			next_batchcount,
+			lazy_load_enabled,
 			lazy_load_send_redundant,
 			full_state,
 			device_list_updates,
@@ -1060,6 +1173,7 @@ async fn calculate_state_incremental(
 	sender_device: &DeviceId,
 	room_id: &RoomId,
 	next_batchcount: PduCount,
+	lazy_load_enabled: bool,
 	lazy_load_send_redundant: bool,
 	full_state: bool,
 	device_list_updates: &mut HashSet<OwnedUserId>,
@@ -1181,6 +1295,38 @@ async fn calculate_state_incremental(
 
 	let mut state_events = delta_state_events;
 
+	// When lazy-loading, membership changes are only sent for the senders in the
+	// timeline. Any other member whose membership changed is forgotten so their
+	// new member event is sent once they become relevant again.
+	if lazy_load_enabled && !full_state {
+		state_events.retain(|pdu| {
+			if pdu.kind != RoomMember {
+				return true;
+			}
+
+			let Some(Ok(user_id)) = pdu.state_key.as_deref().map(UserId::parse) else {
+				return false;
+			};
+
+			if user_id.as_ref() == sender_user
+				|| timeline_pdus
+					.iter()
+					.any(|(_, event)| event.sender == user_id)
+			{
+				return true;
+			}
+
+			services.rooms.lazy_loading.lazy_load_forget(
+				sender_user,
+				sender_device,
+				room_id,
+				&user_id,
+			);
+
+			false
+		});
+	}
+
 	// Mark all member events we're returning as lazy-loaded
 	let mut lazy_loaded = state_events
 		.iter()
@@ -1200,7 +1346,7 @@ async fn calculate_state_incremental(
 	// Fetch contextual member state events for events from the timeline, and
 	// mark them as lazy-loaded as well.
 	for (_, event) in timeline_pdus {
-		if lazy_loaded.contains(&event.sender) {
+		if !lazy_load_enabled || lazy_loaded.contains(&event.sender) {
 			continue;
 		}
 
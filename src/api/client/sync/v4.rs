@@ -1,7 +1,6 @@
 use std::{
-	cmp::{self, Ordering},
+	cmp::Ordering,
 	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
-	time::Duration,
 };
 
 use axum::extract::State;
@@ -52,6 +51,10 @@ pub(crate) async fn sync_events_v4_route(
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let sender_device = body.sender_device.expect("user is authenticated");
 	let mut body = body.body;
+	let active = services
+		.sync
+		.track_sync(sender_user, &sender_device, body.pos.as_deref());
+
 	// Setup watchers, so if there's no response, we can wait for them
 	let watcher = services.sync.watch(sender_user, &sender_device);
 
@@ -761,9 +764,7 @@ pub(crate) async fn sync_events_v4_route(
 	}) {
 		// Hang a few seconds so requests are not spammed
 		// Stop hanging if new info arrives
-		let default = Duration::from_secs(30);
-		let duration = cmp::min(body.timeout.unwrap_or(default), default);
-		_ = tokio::time::timeout(duration, watcher).await;
+		services.sync.wait(&active, watcher, body.timeout).await;
 	}
 
 	Ok(sync_events::v4::Response {
@@ -1,7 +1,6 @@
 use std::{
-	cmp::{self, Ordering},
+	cmp::Ordering,
 	collections::{BTreeMap, BTreeSet, HashMap, HashSet},
-	time::Duration,
 };
 
 use axum::extract::State;
@@ -55,6 +54,9 @@ pub(crate) async fn sync_events_v5_route(
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let sender_device = body.sender_device.as_ref().expect("user is authenticated");
 	let mut body = body.body;
+	let active = services
+		.sync
+		.track_sync(sender_user, sender_device, body.pos.as_deref());
 
 	// Setup watchers, so if there's no response, we can wait for them
 	let watcher = services.sync.watch(sender_user, sender_device);
@@ -147,7 +149,7 @@ pub(crate) async fn sync_events_v5_route(
 			account_data: collect_account_data(services, sync_info).await,
 			e2ee: collect_e2ee(services, sync_info, &all_joined_rooms).await?,
 			to_device: collect_to_device(services, sync_info, next_batch).await,
-			receipts: collect_receipts(services).await,
+			receipts: sync_events::v5::response::Receipts::default(),
 			typing: sync_events::v5::response::Typing::default(),
 		},
 	};
@@ -181,6 +183,7 @@ pub(crate) async fn sync_events_v5_route(
 		r.timeline.is_empty()
 			&& r.required_state.is_empty()
 			&& !response.extensions.receipts.rooms.contains_key(id)
+			&& !response.extensions.typing.rooms.contains_key(id)
 	}) && response
 		.extensions
 		.to_device
@@ -189,15 +192,14 @@ pub(crate) async fn sync_events_v5_route(
 	{
 		// Hang a few seconds so requests are not spammed
 		// Stop hanging if new info arrives
-		let default = Duration::from_secs(30);
-		let duration = cmp::min(body.timeout.unwrap_or(default), default);
-		_ = tokio::time::timeout(duration, watcher).await;
+		services.sync.wait(&active, watcher, body.timeout).await;
 	}
 
 	trace!(
 		rooms=?response.rooms.len(),
 		account_data=?response.extensions.account_data.rooms.len(),
 		receipts=?response.extensions.receipts.rooms.len(),
+		typing=?response.extensions.typing.rooms.len(),
 		"responding to request with"
 	);
 	Ok(response)
@@ -397,50 +399,14 @@ async fn process_rooms(
 			);
 		}
 
-		let last_privateread_update = services
-			.rooms
-			.read_receipt
-			.last_privateread_update(sender_user, room_id)
-			.await > *roomsince;
-
-		let private_read_event = if last_privateread_update {
-			services
-				.rooms
-				.read_receipt
-				.private_read_get(room_id, sender_user)
-				.await
-				.ok()
+		let receipt_size = if body.extensions.receipts.enabled == Some(true) {
+			collect_receipts(services, sender_user, room_id, *roomsince, response).await
 		} else {
-			None
+			0
 		};
 
-		let mut receipts: Vec<Raw<AnySyncEphemeralRoomEvent>> = services
-			.rooms
-			.read_receipt
-			.readreceipts_since(room_id, *roomsince)
-			.filter_map(|(read_user, _ts, v)| async move {
-				services
-					.users
-					.user_is_ignored(read_user, sender_user)
-					.await
-					.or_some(v)
-			})
-			.collect()
-			.await;
-
-		if let Some(private_read_event) = private_read_event {
-			receipts.push(private_read_event);
-		}
-
-		let receipt_size = receipts.len();
-
-		if receipt_size > 0 {
-			response
-				.extensions
-				.receipts
-				.rooms
-				.insert(room_id.clone(), pack_receipts(Box::new(receipts.into_iter())));
-		}
+		let typing = body.extensions.typing.enabled == Some(true)
+			&& collect_typing(services, sender_user, room_id, *roomsince, response).await;
 
 		if roomsince != &0
 			&& timeline_pdus.is_empty()
@@ -451,6 +417,7 @@ async fn process_rooms(
 				.get(room_id)
 				.is_none_or(Vec::is_empty)
 			&& receipt_size == 0
+			&& !typing
 		{
 			continue;
 		}
@@ -880,7 +847,94 @@ async fn collect_to_device(
 	})
 }
 
-async fn collect_receipts(_services: crate::State) -> sync_events::v5::response::Receipts {
-	sync_events::v5::response::Receipts { rooms: BTreeMap::new() }
-	// TODO: get explicitly requested read receipts
+/// Add the room's receipts since the room was last synced to the response;
+/// returns the number of receipts added.
+async fn collect_receipts(
+	services: crate::State,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	roomsince: u64,
+	response: &mut sync_events::v5::Response,
+) -> usize {
+	let last_privateread_update = services
+		.rooms
+		.read_receipt
+		.last_privateread_update(sender_user, room_id)
+		.await > roomsince;
+
+	let private_read_event = if last_privateread_update {
+		services
+			.rooms
+			.read_receipt
+			.private_read_get(room_id, sender_user)
+			.await
+			.ok()
+	} else {
+		None
+	};
+
+	let mut receipts: Vec<Raw<AnySyncEphemeralRoomEvent>> = services
+		.rooms
+		.read_receipt
+		.readreceipts_since(room_id, roomsince)
+		.filter_map(|(read_user, _ts, v)| async move {
+			services
+				.users
+				.user_is_ignored(read_user, sender_user)
+				.await
+				.or_some(v)
+		})
+		.collect()
+		.await;
+
+	if let Some(private_read_event) = private_read_event {
+		receipts.push(private_read_event);
+	}
+
+	let receipt_size = receipts.len();
+	if receipt_size > 0 {
+		response
+			.extensions
+			.receipts
+			.rooms
+			.insert(room_id.to_owned(), pack_receipts(Box::new(receipts.into_iter())));
+	}
+
+	receipt_size
+}
+
+/// Add the room's typing users to the response if they changed since the room
+/// was last synced; returns whether they were added.
+async fn collect_typing(
+	services: crate::State,
+	sender_user: &UserId,
+	room_id: &RoomId,
+	roomsince: u64,
+	response: &mut sync_events::v5::Response,
+) -> bool {
+	let typing = &services.rooms.typing;
+	if !typing
+		.last_typing_update(room_id)
+		.await
+		.is_ok_and(|count| count > roomsince)
+	{
+		return false;
+	}
+
+	let Some(event) = typing
+		.typings_all(room_id, sender_user)
+		.await
+		.ok()
+		.and_then(|typings| Raw::new(&typings).ok())
+	else {
+		return false;
+	};
+
+	response
+		.extensions
+		.typing
+		.rooms
+		.insert(room_id.to_owned(), event);
+
+	true
 }
@@ -1,17 +1,49 @@
 use std::collections::BTreeMap;
 
-use ruma::api::client::thirdparty::get_protocols;
+use axum::extract::State;
+use conduwuit::{debug_warn, Err};
+use futures::future::join_all;
+use ruma::{
+	api::{
+		appservice::{thirdparty as appservice_thirdparty, Registration},
+		client::thirdparty::{
+			get_location_for_protocol, get_location_for_room_alias, get_protocol, get_protocols,
+			get_user_for_protocol, get_user_for_user_id,
+		},
+	},
+	thirdparty::Protocol,
+};
+use service::{appservice::RegistrationInfo, Services};
 
 use crate::{Result, Ruma, RumaResponse};
 
-/// # `GET /_matrix/client/r0/thirdparty/protocols`
+/// # `GET /_matrix/client/v3/thirdparty/protocols`
 ///
-/// TODO: Fetches all metadata about protocols supported by the homeserver.
+/// Fetches the metadata of the protocols the appservices provide, with the
+/// instances of each of the appservices providing the same protocol.
 pub(crate) async fn get_protocols_route(
+	State(services): State<crate::State>,
 	_body: Ruma<get_protocols::v3::Request>,
 ) -> Result<get_protocols::v3::Response> {
-	// TODO
-	Ok(get_protocols::v3::Response { protocols: BTreeMap::new() })
+	let appservices = services.appservice.read().await.clone();
+	let services = &services;
+	let queries = appservices.values().flat_map(|info| {
+		info.registration
+			.protocols
+			.iter()
+			.flatten()
+			.map(move |name| async move {
+				let protocol = query_protocol(services, &info.registration, name).await;
+				protocol.map(|protocol| (name, protocol))
+			})
+	});
+
+	let mut protocols = BTreeMap::new();
+	for (name, protocol) in join_all(queries).await.into_iter().flatten() {
+		merge_protocol(&mut protocols, name, protocol);
+	}
+
+	Ok(get_protocols::v3::Response { protocols })
 }
 
 /// # `GET /_matrix/client/unstable/thirdparty/protocols`
@@ -19,7 +51,228 @@ pub(crate) async fn get_protocols_route(
 /// Same as `get_protocols_route`, except for some reason Element Android legacy
 /// calls this
 pub(crate) async fn get_protocols_route_unstable(
+	State(services): State<crate::State>,
 	body: Ruma<get_protocols::v3::Request>,
 ) -> Result<RumaResponse<get_protocols::v3::Response>> {
-	get_protocols_route(body).await.map(RumaResponse)
+	get_protocols_route(State(services), body)
+		.await
+		.map(RumaResponse)
+}
+
+/// # `GET /_matrix/client/v3/thirdparty/protocol/{protocol}`
+///
+/// Fetches the metadata of the protocol from the appservices providing it.
+pub(crate) async fn get_protocol_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_protocol::v3::Request>,
+) -> Result<get_protocol::v3::Response> {
+	let name = &body.protocol;
+	let appservices = protocol_appservices(&services, name).await?;
+	let answers = join_all(
+		appservices
+			.iter()
+			.map(|info| query_protocol(&services, &info.registration, name)),
+	)
+	.await;
+
+	let mut protocols = BTreeMap::new();
+	for protocol in answers.into_iter().flatten() {
+		merge_protocol(&mut protocols, name, protocol);
+	}
+
+	let Some(protocol) = protocols.remove(name) else {
+		return Err!(Request(NotFound("No appservice answered for protocol {name:?}.")));
+	};
+
+	Ok(get_protocol::v3::Response { protocol })
+}
+
+/// # `GET /_matrix/client/v3/thirdparty/location/{protocol}`
+///
+/// Looks up the portal rooms of the protocol matching the fields with the
+/// appservices providing it.
+pub(crate) async fn get_location_for_protocol_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_location_for_protocol::v3::Request>,
+) -> Result<get_location_for_protocol::v3::Response> {
+	let appservices = protocol_appservices(&services, &body.protocol).await?;
+	let locations = join_all(appservices.iter().map(|info| {
+		let request = appservice_thirdparty::get_location_for_protocol::v1::Request {
+			protocol: body.protocol.clone(),
+			fields: body.fields.clone(),
+		};
+
+		query(&services, &info.registration, request)
+	}))
+	.await
+	.into_iter()
+	.flatten()
+	.flat_map(|response| response.locations)
+	.collect();
+
+	Ok(get_location_for_protocol::v3::Response { locations })
+}
+
+/// # `GET /_matrix/client/v3/thirdparty/location`
+///
+/// Looks up the portal rooms of the alias with the appservices whose namespace
+/// it is in.
+pub(crate) async fn get_location_for_room_alias_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_location_for_room_alias::v3::Request>,
+) -> Result<get_location_for_room_alias::v3::Response> {
+	let appservices: Vec<RegistrationInfo> = services
+		.appservice
+		.read()
+		.await
+		.values()
+		.filter(|info| info.aliases.is_match(body.alias.as_str()))
+		.cloned()
+		.collect();
+
+	let locations = join_all(appservices.iter().map(|info| {
+		let request = appservice_thirdparty::get_location_for_room_alias::v1::Request {
+			alias: body.alias.clone(),
+		};
+
+		query(&services, &info.registration, request)
+	}))
+	.await
+	.into_iter()
+	.flatten()
+	.flat_map(|response| response.locations)
+	.collect();
+
+	Ok(get_location_for_room_alias::v3::Response { locations })
+}
+
+/// # `GET /_matrix/client/v3/thirdparty/user/{protocol}`
+///
+/// Looks up the remote users of the protocol matching the fields with the
+/// appservices providing it.
+pub(crate) async fn get_user_for_protocol_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_user_for_protocol::v3::Request>,
+) -> Result<get_user_for_protocol::v3::Response> {
+	let appservices = protocol_appservices(&services, &body.protocol).await?;
+	let users = join_all(appservices.iter().map(|info| {
+		let request = appservice_thirdparty::get_user_for_protocol::v1::Request {
+			protocol: body.protocol.clone(),
+			fields: body.fields.clone(),
+		};
+
+		query(&services, &info.registration, request)
+	}))
+	.await
+	.into_iter()
+	.flatten()
+	.flat_map(|response| response.users)
+	.collect();
+
+	Ok(get_user_for_protocol::v3::Response { users })
+}
+
+/// # `GET /_matrix/client/v3/thirdparty/user`
+///
+/// Looks up the remote users behind the user ID with the appservices whose
+/// namespace it is in.
+pub(crate) async fn get_user_for_user_id_route(
+	State(services): State<crate::State>,
+	body: Ruma<get_user_for_user_id::v3::Request>,
+) -> Result<get_user_for_user_id::v3::Response> {
+	let appservices: Vec<RegistrationInfo> = services
+		.appservice
+		.read()
+		.await
+		.values()
+		.filter(|info| info.is_user_match(&body.userid))
+		.cloned()
+		.collect();
+
+	let users = join_all(appservices.iter().map(|info| {
+		let request = appservice_thirdparty::get_user_for_user_id::v1::Request {
+			userid: body.userid.clone(),
+		};
+
+		query(&services, &info.registration, request)
+	}))
+	.await
+	.into_iter()
+	.flatten()
+	.flat_map(|response| response.users)
+	.collect();
+
+	Ok(get_user_for_user_id::v3::Response { users })
+}
+
+/// The appservices whose registration lists the protocol.
+async fn protocol_appservices(
+	services: &Services,
+	protocol: &str,
+) -> Result<Vec<RegistrationInfo>> {
+	let appservices: Vec<RegistrationInfo> = services
+		.appservice
+		.read()
+		.await
+		.values()
+		.filter(|info| {
+			info.registration
+				.protocols
+				.iter()
+				.flatten()
+				.any(|name| name == protocol)
+		})
+		.cloned()
+		.collect();
+
+	if appservices.is_empty() {
+		return Err!(Request(NotFound("No appservice provides protocol {protocol:?}.")));
+	}
+
+	Ok(appservices)
+}
+
+async fn query_protocol(
+	services: &Services,
+	registration: &Registration,
+	protocol: &str,
+) -> Option<Protocol> {
+	let request =
+		appservice_thirdparty::get_protocol::v1::Request { protocol: protocol.to_owned() };
+
+	query(services, registration, request)
+		.await
+		.map(|response| response.protocol)
+}
+
+/// Send the request to the appservice; one which fails to answer is left out
+/// of the results.
+async fn query<T>(
+	services: &Services,
+	registration: &Registration,
+	request: T,
+) -> Option<T::IncomingResponse>
+where
+	T: ruma::api::OutgoingRequest + std::fmt::Debug + Send,
+{
+	services
+		.sending
+		.send_appservice_request(registration.clone(), request)
+		.await
+		.inspect_err(|e| {
+			debug_warn!(id = %registration.id, "Appservice failed a third-party lookup: {e}");
+		})
+		.ok()
+		.flatten()
+}
+
+/// Add the protocol an appservice answered with, adding its instances to those
+/// of the other appservices providing it.
+fn merge_protocol(protocols: &mut BTreeMap<String, Protocol>, name: &str, protocol: Protocol) {
+	match protocols.get_mut(name) {
+		| Some(merged) => merged.instances.extend(protocol.instances),
+		| None => {
+			protocols.insert(name.to_owned(), protocol);
+		},
+	}
 }
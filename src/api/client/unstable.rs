@@ -5,17 +5,14 @@ use axum_client_ip::InsecureClientIp;
 use conduwuit::Err;
 use futures::StreamExt;
 use ruma::{
-	api::{
-		client::{
-			error::ErrorKind,
-			membership::mutual_rooms,
-			profile::{
-				delete_profile_key, delete_timezone_key, get_profile_key, get_timezone_key,
-				set_profile_key, set_timezone_key,
-			},
-			room::get_summary,
+	api::client::{
+		error::ErrorKind,
+		membership::mutual_rooms,
+		profile::{
+			delete_profile_key, delete_timezone_key, get_profile_key, get_timezone_key,
+			set_profile_key, set_timezone_key,
 		},
-		federation,
+		room::get_summary,
 	},
 	events::room::member::MembershipState,
 	presence::PresenceState,
@@ -404,42 +401,8 @@ pub(crate) async fn get_timezone_key_route(
 	State(services): State<crate::State>,
 	body: Ruma<get_timezone_key::unstable::Request>,
 ) -> Result<get_timezone_key::unstable::Response> {
-	if !services.globals.user_is_local(&body.user_id) {
-		// Create and update our local copy of the user
-		if let Ok(response) = services
-			.sending
-			.send_federation_request(
-				body.user_id.server_name(),
-				federation::query::get_profile_information::v1::Request {
-					user_id: body.user_id.clone(),
-					field: None, // we want the full user's profile to update locally as well
-				},
-			)
-			.await
-		{
-			if !services.users.exists(&body.user_id).await {
-				services.users.create(&body.user_id, None)?;
-			}
-
-			services
-				.users
-				.set_displayname(&body.user_id, response.displayname.clone());
-
-			services
-				.users
-				.set_avatar_url(&body.user_id, response.avatar_url.clone());
-
-			services
-				.users
-				.set_blurhash(&body.user_id, response.blurhash.clone());
-
-			services
-				.users
-				.set_timezone(&body.user_id, response.tz.clone());
-
-			return Ok(get_timezone_key::unstable::Response { tz: response.tz });
-		}
-	}
+	// Create or update our local copy of a remote user
+	services.profiles.refresh(&body.user_id).await;
 
 	if !services.users.exists(&body.user_id).await {
 		// Return 404 if this user doesn't exist and we couldn't fetch it over
@@ -464,55 +427,8 @@ pub(crate) async fn get_profile_key_route(
 ) -> Result<get_profile_key::unstable::Response> {
 	let mut profile_key_value: BTreeMap<String, serde_json::Value> = BTreeMap::new();
 
-	if !services.globals.user_is_local(&body.user_id) {
-		// Create and update our local copy of the user
-		if let Ok(response) = services
-			.sending
-			.send_federation_request(
-				body.user_id.server_name(),
-				federation::query::get_profile_information::v1::Request {
-					user_id: body.user_id.clone(),
-					field: None, // we want the full user's profile to update locally as well
-				},
-			)
-			.await
-		{
-			if !services.users.exists(&body.user_id).await {
-				services.users.create(&body.user_id, None)?;
-			}
-
-			services
-				.users
-				.set_displayname(&body.user_id, response.displayname.clone());
-
-			services
-				.users
-				.set_avatar_url(&body.user_id, response.avatar_url.clone());
-
-			services
-				.users
-				.set_blurhash(&body.user_id, response.blurhash.clone());
-
-			services
-				.users
-				.set_timezone(&body.user_id, response.tz.clone());
-
-			if let Some(value) = response.custom_profile_fields.get(&body.key) {
-				profile_key_value.insert(body.key.clone(), value.clone());
-				services
-					.users
-					.set_profile_key(&body.user_id, &body.key, Some(value.clone()));
-			} else {
-				return Err!(Request(NotFound("The requested profile key does not exist.")));
-			}
-
-			if profile_key_value.is_empty() {
-				return Err!(Request(NotFound("The requested profile key does not exist.")));
-			}
-
-			return Ok(get_profile_key::unstable::Response { value: profile_key_value });
-		}
-	}
+	// Create or update our local copy of a remote user
+	services.profiles.refresh(&body.user_id).await;
 
 	if !services.users.exists(&body.user_id).await {
 		// Return 404 if this user doesn't exist and we couldn't fetch it over
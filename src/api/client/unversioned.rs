@@ -1,6 +1,7 @@
-use std::collections::BTreeMap;
+use std::{collections::BTreeMap, fmt::Write, sync::atomic::Ordering};
 
 use axum::{extract::State, response::IntoResponse, Json};
+use axum_extra::{headers::ContentType, TypedHeader};
 use futures::StreamExt;
 use ruma::api::client::discovery::get_supported_versions;
 
@@ -19,6 +20,7 @@ use crate::{Result, Ruma};
 /// Note: Unstable features are used while developing new features. Clients
 /// should avoid using unstable features in their stable releases
 pub(crate) async fn get_supported_versions_route(
+	State(services): State<crate::State>,
 	_body: Ruma<get_supported_versions::Request>,
 ) -> Result<get_supported_versions::Response> {
 	let resp = get_supported_versions::Response {
@@ -45,6 +47,7 @@ pub(crate) async fn get_supported_versions_route(
 			("org.matrix.msc2836".to_owned(), true), /* threading/threads (https://github.com/matrix-org/matrix-spec-proposals/pull/2836) */
 			("org.matrix.msc2946".to_owned(), true), /* spaces/hierarchy summaries (https://github.com/matrix-org/matrix-spec-proposals/pull/2946) */
 			("org.matrix.msc3026.busy_presence".to_owned(), true), /* busy presence status (https://github.com/matrix-org/matrix-spec-proposals/pull/3026) */
+			("org.matrix.msc3061".to_owned(), true), /* sharing room keys for past messages (https://github.com/matrix-org/matrix-spec-proposals/pull/3061) */
 			("org.matrix.msc3827".to_owned(), true), /* filtering of /publicRooms by room type (https://github.com/matrix-org/matrix-spec-proposals/pull/3827) */
 			("org.matrix.msc3952_intentional_mentions".to_owned(), true), /* intentional mentions (https://github.com/matrix-org/matrix-spec-proposals/pull/3952) */
 			("org.matrix.msc3575".to_owned(), true), /* sliding sync (https://github.com/matrix-org/matrix-spec-proposals/pull/3575/files#r1588877046) */
@@ -53,6 +56,7 @@ pub(crate) async fn get_supported_versions_route(
 			("uk.tcpip.msc4133".to_owned(), true), /* Extending User Profile API with Key:Value Pairs (https://github.com/matrix-org/matrix-spec-proposals/pull/4133) */
 			("us.cloke.msc4175".to_owned(), true), /* Profile field for user time zone (https://github.com/matrix-org/matrix-spec-proposals/pull/4175) */
 			("org.matrix.simplified_msc3575".to_owned(), true), /* Simplified Sliding sync (https://github.com/matrix-org/matrix-spec-proposals/pull/4186) */
+			("org.matrix.msc4140".to_owned(), services.server.config.max_event_delay > 0), /* delayed events (https://github.com/matrix-org/matrix-spec-proposals/pull/4140) */
 		]),
 	};
 
@@ -84,3 +88,48 @@ pub(crate) async fn conduwuit_local_user_count(
 		"count": user_count
 	})))
 }
+
+/// # `GET /_conduwuit/metrics`
+///
+/// conduwuit-specific API exposing request counters, server key fetches and
+/// per-column database statistics in the Prometheus text format. Endpoint is
+/// only served when `allow_metrics_endpoint` is enabled.
+pub(crate) async fn conduwuit_metrics(
+	State(services): State<crate::State>,
+) -> Result<impl IntoResponse> {
+	let requests = &services.server.metrics;
+	let counters = [
+		("spawn_active", &requests.requests_spawn_active),
+		("spawn_finished", &requests.requests_spawn_finished),
+		("handle_active", &requests.requests_handle_active),
+		("handle_finished", &requests.requests_handle_finished),
+		("panic", &requests.requests_panic),
+		("timeout", &requests.requests_timeout),
+	];
+
+	let mut out = String::new();
+	writeln!(out, "# TYPE conduwuit_requests untyped")?;
+	for (state, counter) in counters {
+		let count = counter.load(Ordering::Relaxed);
+		writeln!(out, "conduwuit_requests{{state=\"{state}\"}} {count}")?;
+	}
+
+	writeln!(
+		out,
+		"# HELP conduwuit_server_keys_fetched Servers whose keys were fetched, by path"
+	)?;
+	writeln!(out, "# TYPE conduwuit_server_keys_fetched counter")?;
+	for (path, count) in services.server_keys.fetch_counts().get() {
+		writeln!(out, "conduwuit_server_keys_fetched{{path=\"{path}\"}} {count}")?;
+	}
+
+	writeln!(out, "# HELP conduwuit_db_column Per-column database statistics")?;
+	writeln!(out, "# TYPE conduwuit_db_column gauge")?;
+	for (column, map) in services.db.iter() {
+		for (stat, value) in map.stats().iter() {
+			writeln!(out, "conduwuit_db_column{{column=\"{column}\",stat=\"{stat}\"}} {value}")?;
+		}
+	}
+
+	Ok((TypedHeader(ContentType::text_utf8()), out))
+}
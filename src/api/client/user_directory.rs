@@ -1,22 +1,21 @@
 use axum::extract::State;
-use conduwuit::utils::TryFutureExtExt;
-use futures::{pin_mut, StreamExt};
-use ruma::{
-	api::client::user_directory::search_users,
-	events::{
-		room::join_rules::{JoinRule, RoomJoinRulesEventContent},
-		StateEventType,
-	},
-};
+use conduwuit::utils::IterStream;
+use futures::StreamExt;
+use ruma::{api::client::user_directory::search_users, OwnedUserId};
+use service::Services;
 
 use crate::{Result, Ruma};
 
 /// # `POST /_matrix/client/r0/user_directory/search`
 ///
-/// Searches all known users for a match.
+/// Searches the user directory for users with a word of their user ID or
+/// display name beginning with each word of the search term.
 ///
-/// - Hides any local users that aren't in any public rooms (i.e. those that
-///   have the join rule set to public) and don't share a room with the sender
+/// - Hides users that aren't in any public rooms (i.e. those that anyone may
+///   join or whose history is world readable) and don't share a room with the
+///   sender
+/// - Hides service accounts and deactivated users
+/// - Hides remote users unless `user_directory_search_remote_users` is enabled
 pub(crate) async fn search_users_route(
 	State(services): State<crate::State>,
 	body: Ruma<search_users::v3::Request>,
@@ -24,75 +23,25 @@ pub(crate) async fn search_users_route(
 	let sender_user = body.sender_user.as_ref().expect("user is authenticated");
 	let limit = usize::try_from(body.limit).map_or(10, usize::from).min(100); // default limit is 10
 
-	let users = services.users.stream().filter_map(|user_id| async {
-		// Filter out buggy users (they should not exist, but you never know...)
-		let user = search_users::v3::User {
-			user_id: user_id.to_owned(),
-			display_name: services.users.displayname(user_id).await.ok(),
-			avatar_url: services.users.avatar_url(user_id).await.ok(),
-		};
+	let (user_ids, limited) = services
+		.users
+		.search_directory(sender_user, &body.search_term, limit)
+		.await;
 
-		let user_id_matches = user
-			.user_id
-			.to_string()
-			.to_lowercase()
-			.contains(&body.search_term.to_lowercase());
-
-		let user_displayname_matches = user
-			.display_name
-			.as_ref()
-			.filter(|name| {
-				name.to_lowercase()
-					.contains(&body.search_term.to_lowercase())
-			})
-			.is_some();
-
-		if !user_id_matches && !user_displayname_matches {
-			return None;
-		}
-
-		// It's a matching user, but is the sender allowed to see them?
-		let mut user_visible = false;
-
-		let user_is_in_public_rooms = services
-			.rooms
-			.state_cache
-			.rooms_joined(&user.user_id)
-			.any(|room| {
-				services
-					.rooms
-					.state_accessor
-					.room_state_get_content::<RoomJoinRulesEventContent>(
-						room,
-						&StateEventType::RoomJoinRules,
-						"",
-					)
-					.map_ok_or(false, |content| content.join_rule == JoinRule::Public)
-			})
-			.await;
-
-		if user_is_in_public_rooms {
-			user_visible = true;
-		} else {
-			let user_is_in_shared_rooms = services
-				.rooms
-				.state_cache
-				.user_sees_user(sender_user, &user.user_id)
-				.await;
-
-			if user_is_in_shared_rooms {
-				user_visible = true;
-			}
-		}
-
-		user_visible.then_some(user)
-	});
-
-	pin_mut!(users);
-
-	let limited = users.by_ref().next().await.is_some();
-
-	let results = users.take(limit).collect().await;
+	let results = user_ids
+		.into_iter()
+		.stream()
+		.then(|user_id| search_result(&services, user_id))
+		.collect()
+		.await;
 
 	Ok(search_users::v3::Response { results, limited })
 }
+
+async fn search_result(services: &Services, user_id: OwnedUserId) -> search_users::v3::User {
+	search_users::v3::User {
+		display_name: services.users.displayname(&user_id).await.ok(),
+		avatar_url: services.users.avatar_url(&user_id).await.ok(),
+		user_id,
+	}
+}
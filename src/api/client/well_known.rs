@@ -1,7 +1,7 @@
 use axum::{extract::State, response::IntoResponse, Json};
 use ruma::api::client::{
 	discovery::{
-		discover_homeserver::{self, HomeserverInfo, SlidingSyncProxyInfo},
+		discover_homeserver::{HomeserverInfo, SlidingSyncProxyInfo},
 		discover_support::{self, Contact},
 	},
 	error::ErrorKind,
@@ -11,22 +11,26 @@ use crate::{Error, Result, Ruma};
 
 /// # `GET /.well-known/matrix/client`
 ///
-/// Returns the .well-known URL if it is configured, otherwise returns 404.
+/// Returns the .well-known URL if it is configured, otherwise returns 404. The
+/// message of the day set by the admins is included as `org.conduwuit.motd`.
 pub(crate) async fn well_known_client(
 	State(services): State<crate::State>,
-	_body: Ruma<discover_homeserver::Request>,
-) -> Result<discover_homeserver::Response> {
+) -> Result<impl IntoResponse> {
 	let client_url = match services.server.config.well_known.client.as_ref() {
 		| Some(url) => url.to_string(),
 		| None => return Err(Error::BadRequest(ErrorKind::NotFound, "Not found.")),
 	};
 
-	Ok(discover_homeserver::Response {
-		homeserver: HomeserverInfo { base_url: client_url.clone() },
-		identity_server: None,
-		sliding_sync_proxy: Some(SlidingSyncProxyInfo { url: client_url }),
-		tile_server: None,
-	})
+	let mut response = serde_json::json!({
+		"m.homeserver": HomeserverInfo { base_url: client_url.clone() },
+		"org.matrix.msc3575.proxy": SlidingSyncProxyInfo { url: client_url },
+	});
+
+	if let Some(motd) = services.admin.motd() {
+		response["org.conduwuit.motd"] = serde_json::json!({ "message": motd.message });
+	}
+
+	Ok(Json(response))
 }
 
 /// # `GET /.well-known/matrix/support`
@@ -1,15 +1,18 @@
 mod args;
 mod auth;
 mod handler;
+mod lock;
+mod ratelimit;
 mod request;
 mod response;
+mod restrict;
 pub mod state;
 
 use std::str::FromStr;
 
 use axum::{
 	response::{IntoResponse, Redirect},
-	routing::{any, get, post},
+	routing::{any, get, post, put},
 	Router,
 };
 use conduwuit::{err, Server};
@@ -35,6 +38,17 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::get_login_types_route)
 		.ruma_route(&client::login_route)
 		.ruma_route(&client::login_token_route)
+		.ruma_route(&client::sso_login_route)
+		.ruma_route(&client::sso_login_with_provider_route)
+		.route("/_conduwuit/sso/callback", get(client::sso_callback))
+		.route(
+			"/_matrix/client/r0/auth/m.login.sso/fallback/web",
+			get(client::sso_fallback),
+		)
+		.route(
+			"/_matrix/client/v3/auth/m.login.sso/fallback/web",
+			get(client::sso_fallback),
+		)
 		.ruma_route(&client::whoami_route)
 		.ruma_route(&client::logout_route)
 		.ruma_route(&client::logout_all_route)
@@ -115,12 +129,36 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 		.ruma_route(&client::search_users_route)
 		.ruma_route(&client::get_member_events_route)
 		.ruma_route(&client::get_protocols_route)
+		.ruma_route(&client::get_protocol_route)
+		.ruma_route(&client::get_location_for_protocol_route)
+		.ruma_route(&client::get_location_for_room_alias_route)
+		.ruma_route(&client::get_user_for_protocol_route)
+		.ruma_route(&client::get_user_for_user_id_route)
 		.route("/_matrix/client/unstable/thirdparty/protocols",
 			get(client::get_protocols_route_unstable))
-		.ruma_route(&client::send_message_event_route)
-		.ruma_route(&client::send_state_event_for_key_route)
+		// MSC4140 delays are a query parameter of the send endpoints, which then respond
+		// with a delay_id in place of the event_id; Ruma's response types can't express that
+		.route(
+			"/_matrix/client/r0/rooms/:room_id/send/:event_type/:txn_id",
+			put(client::send_message_event_delayable_route),
+		)
+		.route(
+			"/_matrix/client/v3/rooms/:room_id/send/:event_type/:txn_id",
+			put(client::send_message_event_delayable_route),
+		)
+		.route(
+			"/_matrix/client/r0/rooms/:room_id/state/:event_type/:state_key",
+			put(client::send_state_event_delayable_route),
+		)
+		.route(
+			"/_matrix/client/v3/rooms/:room_id/state/:event_type/:state_key",
+			put(client::send_state_event_delayable_route),
+		)
+		.ruma_route(&client::update_delayed_event_route)
+		.ruma_route(&client::get_delayed_events_route)
 		.ruma_route(&client::get_state_events_route)
 		.ruma_route(&client::get_state_events_for_key_route)
+		.ruma_route(&client::get_room_image_packs_route)
 		// Ruma doesn't have support for multiple paths for a single endpoint yet, and these routes
 		// share one Ruma request / response type pair with {get,send}_state_event_for_key_route
 		.route(
@@ -184,8 +222,15 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			get(client::get_room_summary_legacy)
 		)
 		.ruma_route(&client::well_known_support)
-		.ruma_route(&client::well_known_client)
+		.route("/.well-known/matrix/client", get(client::well_known_client))
 		.route("/_conduwuit/server_version", get(client::conduwuit_server_version))
+		.route("/_conduwuit/admin/v1/appservices", get(client::admin_list_appservices))
+		.route(
+			"/_conduwuit/admin/v1/appservices/:appservice_id",
+			get(client::admin_get_appservice)
+				.put(client::admin_put_appservice)
+				.delete(client::admin_delete_appservice),
+		)
 		.ruma_route(&client::room_initial_sync_route)
 		.route("/client/server.json", get(client::syncv3_client_server_json));
 
@@ -234,6 +279,10 @@ pub fn build(router: Router<State>, server: &Server) -> Router<State> {
 			.route("/_conduwuit/local_user_count", any(federation_disabled));
 	}
 
+	if config.allow_metrics_endpoint {
+		router = router.route("/_conduwuit/metrics", get(client::conduwuit_metrics));
+	}
+
 	if config.allow_legacy_media {
 		router = router
 			.ruma_route(&client::get_media_config_legacy_route)
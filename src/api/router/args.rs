@@ -1,4 +1,4 @@
-use std::{mem, ops::Deref};
+use std::{mem, ops::Deref, time::Duration};
 
 use axum::{async_trait, body::Body, extract::FromRequest};
 use bytes::{BufMut, Bytes, BytesMut};
@@ -9,7 +9,7 @@ use ruma::{
 };
 use service::Services;
 
-use super::{auth, auth::Auth, request, request::Request};
+use super::{auth, auth::Auth, lock, ratelimit, request, request::Request, restrict};
 use crate::{service::appservice::RegistrationInfo, State};
 
 /// Extractor for Ruma request structs
@@ -36,6 +36,10 @@ pub(crate) struct Args<T> {
 	/// Parsed JSON content.
 	/// None when body is not a valid string
 	pub(crate) json_body: Option<CanonicalJsonValue>,
+
+	/// Delay requested for sending an event (MSC4140).
+	/// None when the event is to be sent immediately.
+	pub(crate) delay: Option<Duration>,
 }
 
 impl<T> Args<T>
@@ -108,18 +112,22 @@ where
 			json_body = Some(CanonicalJsonValue::Object(CanonicalJsonObject::new()));
 		}
 		let auth = auth::auth(services, &mut request, json_body.as_ref(), &T::METADATA).await?;
+		restrict::check(services, request.parts.uri.path(), auth.sender_user.as_deref()).await?;
+		lock::check(services, &request, &auth).await?;
+		ratelimit::check(services, &request, &auth).await?;
 		Ok(Self {
-			body: make_body::<T>(services, &mut request, json_body.as_mut(), &auth)?,
+			body: make_body::<T>(services, &mut request, json_body.as_mut(), &auth).await?,
 			origin: auth.origin,
 			sender_user: auth.sender_user,
 			sender_device: auth.sender_device,
 			appservice_info: auth.appservice_info,
 			json_body,
+			delay: request.query.delay.map(Duration::from_millis),
 		})
 	}
 }
 
-fn make_body<T>(
+async fn make_body<T>(
 	services: &Services,
 	request: &mut Request,
 	json_body: Option<&mut CanonicalJsonValue>,
@@ -128,7 +136,7 @@ fn make_body<T>(
 where
 	T: IncomingRequest,
 {
-	let body = take_body(services, request, json_body, auth);
+	let body = take_body(services, request, json_body, auth).await;
 	let http_request = into_http_request(request, body);
 	T::try_from_http_request(http_request, &request.path)
 		.map_err(|e| err!(Request(BadJson(debug_warn!("{e}")))))
@@ -152,7 +160,7 @@ fn into_http_request(request: &Request, body: Bytes) -> hyper::Request<Bytes> {
 }
 
 #[allow(clippy::needless_pass_by_value)]
-fn take_body(
+async fn take_body(
 	services: &Services,
 	request: &mut Request,
 	json_body: Option<&mut CanonicalJsonValue>,
@@ -167,16 +175,20 @@ fn take_body(
 		UserId::parse_with_server_name(EMPTY, server_name).expect("valid user_id")
 	});
 
-	let uiaa_request = json_body
+	let session = json_body
 		.get("auth")
 		.and_then(CanonicalJsonValue::as_object)
 		.and_then(|auth| auth.get("session"))
-		.and_then(CanonicalJsonValue::as_str)
-		.and_then(|session| {
+		.and_then(CanonicalJsonValue::as_str);
+
+	let uiaa_request = match session {
+		| Some(session) =>
 			services
 				.uiaa
 				.get_uiaa_request(&user_id, auth.sender_device.as_deref(), session)
-		});
+				.await,
+		| None => None,
+	};
 
 	if let Some(CanonicalJsonValue::Object(initial_request)) = uiaa_request {
 		for (key, value) in initial_request {
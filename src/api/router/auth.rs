@@ -322,6 +322,7 @@ fn auth_server_checks(services: &Services, x_matrix: &XMatrix) -> Result<()> {
 		.config
 		.forbidden_remote_server_names
 		.contains(origin)
+		|| services.moderation.policy.is_server_denied(origin)
 	{
 		return Err!(Request(Forbidden(debug_warn!(
 			"Federation requests from {origin} denied."
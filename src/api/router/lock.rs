@@ -0,0 +1,43 @@
+use conduwuit::{Err, Result};
+use http::Method;
+use service::{users::LockState, Services};
+
+use super::{auth::Auth, request::Request, restrict};
+
+/// Refuse client requests of locked accounts, and those of suspended accounts
+/// which would change anything, apart from logging out (and leaving rooms,
+/// for suspended accounts).
+pub(super) async fn check(services: &Services, request: &Request, auth: &Auth) -> Result {
+	let Some(sender_user) = auth.sender_user.as_deref() else {
+		return Ok(());
+	};
+
+	if auth.appservice_info.is_some() {
+		return Ok(());
+	}
+
+	let Some(lock_state) = services.users.lock_state(sender_user).await else {
+		return Ok(());
+	};
+
+	let Some(endpoint) = restrict::endpoint(request.parts.uri.path()) else {
+		return Ok(());
+	};
+
+	let segments: Vec<&str> = endpoint.trim_start_matches('/').split('/').collect();
+	if matches!(segments.as_slice(), ["logout"] | ["logout", "all"] | ["account", "whoami"]) {
+		return Ok(());
+	}
+
+	match lock_state {
+		| LockState::Locked =>
+			Err!(Request(UserLocked("This account has been locked by the server admins."))),
+		| LockState::Suspended
+			if request.parts.method != Method::GET
+				&& !matches!(segments.as_slice(), ["rooms", _, "leave"]) =>
+			Err!(Request(UserLocked(
+				"This account has been suspended by the server admins; it can only read."
+			))),
+		| LockState::Suspended => Ok(()),
+	}
+}
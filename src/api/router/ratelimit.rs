@@ -0,0 +1,75 @@
+use axum_client_ip::InsecureClientIp;
+use conduwuit::{Error, Result};
+use http::{Method, StatusCode};
+use ruma::api::client::error::{ErrorKind, RetryAfter};
+use service::{
+	ratelimit::{Class, Key},
+	Services,
+};
+
+use super::{auth::Auth, request::Request, restrict};
+
+/// Refuse client requests over the rate limit of their user (or IP address,
+/// when not authenticated and `limit_by_ip` is set) for the class of endpoint.
+pub(super) async fn check(services: &Services, request: &Request, auth: &Auth) -> Result {
+	let config = &services.server.config.ratelimit;
+	if !config.enable || auth.origin.is_some() {
+		return Ok(());
+	}
+
+	if auth
+		.appservice_info
+		.as_ref()
+		.is_some_and(|info| info.registration.rate_limited != Some(true))
+	{
+		return Ok(());
+	}
+
+	let Some(endpoint) = restrict::endpoint(request.parts.uri.path()) else {
+		return Ok(());
+	};
+
+	let key = match auth.sender_user.clone() {
+		| Some(sender_user) => Key::User(sender_user),
+		| None if !config.limit_by_ip => return Ok(()),
+		| None => match InsecureClientIp::from(&request.parts.headers, &request.parts.extensions)
+		{
+			| Ok(InsecureClientIp(ip)) => Key::Ip(ip),
+			| Err(_) => return Ok(()),
+		},
+	};
+
+	let class = class(&request.parts.method, &endpoint);
+	let Some(retry_after) = services.ratelimit.limited(key, class) else {
+		return Ok(());
+	};
+
+	// Exemptions are only looked up once limited, as they are rare.
+	if let Some(sender_user) = auth.sender_user.as_deref() {
+		if services.ratelimit.is_exempt(sender_user).await
+			|| services.users.is_service_account(sender_user).await
+		{
+			return Ok(());
+		}
+	}
+
+	Err(Error::Request(
+		ErrorKind::LimitExceeded {
+			retry_after: Some(RetryAfter::Delay(retry_after)),
+		},
+		"Too many requests.".into(),
+		StatusCode::TOO_MANY_REQUESTS,
+	))
+}
+
+fn class(method: &Method, endpoint: &str) -> Class {
+	let segments: Vec<&str> = endpoint.trim_start_matches('/').split('/').collect();
+	match (method, segments.as_slice()) {
+		| (&Method::POST, ["login"]) => Class::Login,
+		| (&Method::POST, ["register"])
+		| (&Method::GET, ["register", "m.login.registration_token", "validity"]) => Class::Registration,
+		| (&Method::PUT, ["rooms", _, "send" | "state" | "redact", ..]) => Class::Message,
+		| (&Method::POST, ["join" | "knock", _] | ["rooms", _, "join"]) => Class::Join,
+		| _ => Class::Default,
+	}
+}
@@ -11,6 +11,10 @@ use service::Services;
 pub(super) struct QueryParams {
 	pub(super) access_token: Option<String>,
 	pub(super) user_id: Option<String>,
+
+	/// MSC4140 delay in milliseconds for sending events.
+	#[serde(rename = "org.matrix.msc4140.delay")]
+	pub(super) delay: Option<u64>,
 }
 
 pub(super) struct Request {
@@ -0,0 +1,62 @@
+use conduwuit::{Err, Result};
+use ruma::UserId;
+use service::Services;
+
+/// Refuse requests to client endpoints the server is configured to disable or
+/// restrict to server admins.
+pub(super) async fn check(
+	services: &Services,
+	path: &str,
+	sender_user: Option<&UserId>,
+) -> Result {
+	let config = &services.server.config;
+	if config.disabled_client_endpoints.is_empty()
+		&& config.admin_only_client_endpoints.is_empty()
+	{
+		return Ok(());
+	}
+
+	let Some(endpoint) = endpoint(path) else {
+		return Ok(());
+	};
+
+	let matches = |paths: &[String]| paths.iter().any(|path| is_under(&endpoint, path));
+	if matches(&config.disabled_client_endpoints) {
+		return Err!(Request(Forbidden("This endpoint is disabled on this server.")));
+	}
+
+	if matches(&config.admin_only_client_endpoints) {
+		let is_admin = match sender_user {
+			| Some(sender_user) => services.users.is_admin(sender_user).await,
+			| None => false,
+		};
+
+		if !is_admin {
+			return Err!(Request(Forbidden("This endpoint is restricted to server admins.")));
+		}
+	}
+
+	Ok(())
+}
+
+/// The path of a client or media endpoint after its version, with legacy
+/// media endpoints given under `/media` like their authenticated counterparts.
+pub(super) fn endpoint(path: &str) -> Option<String> {
+	let (prefix, rest) = if let Some(rest) = path.strip_prefix("/_matrix/client/") {
+		("", rest)
+	} else if let Some(rest) = path.strip_prefix("/_matrix/media/") {
+		("/media", rest)
+	} else {
+		return None;
+	};
+
+	let (_version, rest) = rest.split_once('/')?;
+	Some(format!("{prefix}/{rest}"))
+}
+
+fn is_under(endpoint: &str, path: &str) -> bool {
+	let path = path.trim_end_matches('/');
+	endpoint
+		.strip_prefix(path)
+		.is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+}
@@ -8,7 +8,7 @@ use ruma::{
 	serde::JsonObject,
 	CanonicalJsonValue, OwnedUserId, UserId,
 };
-use service::pdu::gen_event_id;
+use service::{pdu::gen_event_id, spamcheck::Verdict};
 
 use crate::Ruma;
 
@@ -108,6 +108,14 @@ pub(crate) async fn create_invite_route(
 		return Err!(Request(Forbidden("This server does not allow room invites.")));
 	}
 
+	if let Verdict::Deny(reason) = services
+		.spamcheck
+		.user_may_invite(sender, &invited_user, &body.room_id)
+		.await
+	{
+		return Err!(Request(Forbidden("{reason}")));
+	}
+
 	let mut invite_state = body.invite_room_state.clone();
 
 	let mut event: JsonObject = serde_json::from_str(body.event.get())
@@ -78,6 +78,18 @@ pub(crate) async fn create_join_event_template_route(
 		));
 	}
 
+	let joins = &services.rooms.joins;
+	let membership = MembershipState::Join;
+	if let Some(event) = joins
+		.template(&body.room_id, &body.user_id, &membership)
+		.await
+	{
+		return Ok(prepare_join_event::v1::Response {
+			room_version: Some(room_version_id),
+			event,
+		});
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	let join_authorized_via_users_server: Option<OwnedUserId> = {
@@ -126,7 +138,7 @@ pub(crate) async fn create_join_event_template_route(
 		.create_hash_and_sign_event(
 			PduBuilder::state(body.user_id.to_string(), &RoomMemberEventContent {
 				join_authorized_via_users_server,
-				..RoomMemberEventContent::new(MembershipState::Join)
+				..RoomMemberEventContent::new(membership.clone())
 			}),
 			&body.user_id,
 			&body.room_id,
@@ -134,14 +146,19 @@ pub(crate) async fn create_join_event_template_route(
 		)
 		.await?;
 
-	drop(state_lock);
-
 	// room v3 and above removed the "event_id" field from remote PDU format
 	maybe_strip_event_id(&mut pdu_json, &room_version_id)?;
 
+	let event = to_raw_value(&pdu_json).expect("CanonicalJson can be serialized to JSON");
+	joins
+		.set_template(&body.room_id, &body.user_id, &membership, &event)
+		.await;
+
+	drop(state_lock);
+
 	Ok(prepare_join_event::v1::Response {
 		room_version: Some(room_version_id),
-		event: to_raw_value(&pdu_json).expect("CanonicalJson can be serialized to JSON"),
+		event,
 	})
 }
 
@@ -76,6 +76,15 @@ pub(crate) async fn create_knock_event_template_route(
 		));
 	}
 
+	let joins = &services.rooms.joins;
+	let knock = MembershipState::Knock;
+	if let Some(event) = joins.template(&body.room_id, &body.user_id, &knock).await {
+		return Ok(create_knock_event_template::v1::Response {
+			room_version: room_version_id,
+			event,
+		});
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	if let Ok(membership) = services
@@ -100,7 +109,7 @@ pub(crate) async fn create_knock_event_template_route(
 		.create_hash_and_sign_event(
 			PduBuilder::state(
 				body.user_id.to_string(),
-				&RoomMemberEventContent::new(MembershipState::Knock),
+				&RoomMemberEventContent::new(knock.clone()),
 			),
 			&body.user_id,
 			&body.room_id,
@@ -108,13 +117,15 @@ pub(crate) async fn create_knock_event_template_route(
 		)
 		.await?;
 
-	drop(state_lock);
-
 	// room v3 and above removed the "event_id" field from remote PDU format
 	super::maybe_strip_event_id(&mut pdu_json, &room_version_id)?;
 
-	Ok(create_knock_event_template::v1::Response {
-		room_version: room_version_id,
-		event: to_raw_value(&pdu_json).expect("CanonicalJson can be serialized to JSON"),
-	})
+	let event = to_raw_value(&pdu_json).expect("CanonicalJson can be serialized to JSON");
+	joins
+		.set_template(&body.room_id, &body.user_id, &knock, &event)
+		.await;
+
+	drop(state_lock);
+
+	Ok(create_knock_event_template::v1::Response { room_version: room_version_id, event })
 }
@@ -34,6 +34,18 @@ pub(crate) async fn create_leave_event_template_route(
 		.await?;
 
 	let room_version_id = services.rooms.state.get_room_version(&body.room_id).await?;
+	let joins = &services.rooms.joins;
+	let membership = MembershipState::Leave;
+	if let Some(event) = joins
+		.template(&body.room_id, &body.user_id, &membership)
+		.await
+	{
+		return Ok(prepare_leave_event::v1::Response {
+			room_version: Some(room_version_id),
+			event,
+		});
+	}
+
 	let state_lock = services.rooms.state.mutex.lock(&body.room_id).await;
 
 	let (_pdu, mut pdu_json) = services
@@ -42,7 +54,7 @@ pub(crate) async fn create_leave_event_template_route(
 		.create_hash_and_sign_event(
 			PduBuilder::state(
 				body.user_id.to_string(),
-				&RoomMemberEventContent::new(MembershipState::Leave),
+				&RoomMemberEventContent::new(membership.clone()),
 			),
 			&body.user_id,
 			&body.room_id,
@@ -50,13 +62,18 @@ pub(crate) async fn create_leave_event_template_route(
 		)
 		.await?;
 
-	drop(state_lock);
-
 	// room v3 and above removed the "event_id" field from remote PDU format
 	maybe_strip_event_id(&mut pdu_json, &room_version_id)?;
 
+	let event = to_raw_value(&pdu_json).expect("CanonicalJson can be serialized to JSON");
+	joins
+		.set_template(&body.room_id, &body.user_id, &membership, &event)
+		.await;
+
+	drop(state_lock);
+
 	Ok(prepare_leave_event::v1::Response {
 		room_version: Some(room_version_id),
-		event: to_raw_value(&pdu_json).expect("CanonicalJson can be serialized to JSON"),
+		event,
 	})
 }
@@ -3,8 +3,14 @@ use std::{collections::BTreeMap, net::IpAddr, time::Instant};
 use axum::extract::State;
 use axum_client_ip::InsecureClientIp;
 use conduwuit::{
-	debug, debug_warn, err, error, result::LogErr, trace, utils::ReadyExt, warn, Err, Error,
-	Result,
+	debug, debug_warn, err, error,
+	result::LogErr,
+	trace,
+	utils::{
+		stream::{BroadbandExt, IterStream},
+		ReadyExt,
+	},
+	warn, Err, Error, Result,
 };
 use futures::{FutureExt, StreamExt};
 use ruma::{
@@ -21,7 +27,7 @@ use ruma::{
 	events::receipt::{ReceiptEvent, ReceiptEventContent, ReceiptType},
 	serde::Raw,
 	to_device::DeviceIdOrAllDevices,
-	OwnedEventId, ServerName,
+	CanonicalJsonObject, OwnedEventId, OwnedRoomId, RoomId, ServerName,
 };
 use serde_json::value::RawValue as RawJsonValue;
 use service::{
@@ -63,6 +69,25 @@ pub(crate) async fn send_transaction_message_route(
 		)));
 	}
 
+	// A server retrying a transaction we handled already, e.g. because our
+	// response did not reach it, gets the same result again.
+	if let Ok(response) = services
+		.transaction_ids
+		.existing_server_txnid(body.origin(), &body.transaction_id)
+		.await
+	{
+		let pdus = serde_json::from_slice(&response)
+			.map_err(|e| err!(Database("Invalid federation transaction record: {e}")))?;
+
+		debug!(
+			id = ?body.transaction_id,
+			origin = ?body.origin(),
+			"Transaction was handled already",
+		);
+
+		return Ok(send_transaction_message::v1::Response { pdus });
+	}
+
 	let txn_start_time = Instant::now();
 	trace!(
 		pdus = ?body.pdus.len(),
@@ -78,7 +103,9 @@ pub(crate) async fn send_transaction_message_route(
 			.boxed()
 			.await?;
 
-	handle_edus(&services, &client, &body.edus, body.origin())
+	services
+		.db
+		.group_commit(handle_edus(&services, &client, &body.edus, body.origin()))
 		.boxed()
 		.await;
 
@@ -91,12 +118,18 @@ pub(crate) async fn send_transaction_message_route(
 		"Finished txn",
 	);
 
-	Ok(send_transaction_message::v1::Response {
-		pdus: resolved_map
-			.into_iter()
-			.map(|(e, r)| (e, r.map_err(error::sanitized_message)))
-			.collect(),
-	})
+	let pdus: BTreeMap<_, _> = resolved_map
+		.into_iter()
+		.map(|(e, r)| (e, r.map_err(error::sanitized_message)))
+		.collect();
+
+	if let Ok(response) = serde_json::to_vec(&pdus) {
+		services
+			.transaction_ids
+			.add_server_txnid(body.origin(), &body.transaction_id, &response);
+	}
+
+	Ok(send_transaction_message::v1::Response { pdus })
 }
 
 async fn handle_pdus(
@@ -120,26 +153,71 @@ async fn handle_pdus(
 		// and hashes checks
 	}
 
-	let mut resolved_map = BTreeMap::new();
+	// Group the PDUs by room, preserving their order within each room, so the
+	// rooms are handled concurrently; each event's local writes are committed
+	// together by the event handler.
+	let mut rooms: BTreeMap<OwnedRoomId, Vec<_>> = BTreeMap::new();
 	for (event_id, value, room_id) in parsed_pdus {
-		services.server.check_running()?;
-		let pdu_start_time = Instant::now();
-		let mutex_lock = services
-			.rooms
-			.event_handler
-			.mutex_federation
-			.lock(&room_id)
-			.await;
+		rooms.entry(room_id).or_default().push((event_id, value));
+	}
+
+	let concurrency = services.server.config.federation_incoming_room_concurrency;
+	let results: Vec<Result<ResolvedMap>> = rooms
+		.into_iter()
+		.stream()
+		.broadn_then(concurrency.max(1), |(room_id, pdus)| async move {
+			services.server.check_running()?;
+			Ok(handle_room_pdus(services, origin, &room_id, pdus, txn_start_time).await)
+		})
+		.collect()
+		.await;
 
+	let mut resolved_map = BTreeMap::new();
+	for results in results {
+		resolved_map.extend(results?);
+	}
+
+	for (id, result) in &resolved_map {
+		if let Err(e) = result {
+			if matches!(e, Error::BadRequest(ErrorKind::NotFound, _)) {
+				warn!("Incoming PDU failed {id}: {e:?}");
+			}
+		}
+	}
+
+	Ok(resolved_map)
+}
+
+async fn handle_room_pdus(
+	services: &Services,
+	origin: &ServerName,
+	room_id: &RoomId,
+	pdus: Vec<(OwnedEventId, CanonicalJsonObject)>,
+	txn_start_time: &Instant,
+) -> ResolvedMap {
+	services
+		.spamcheck
+		.precheck_events(pdus.iter().map(|(event_id, value)| (&**event_id, value)))
+		.await;
+
+	let mutex_lock = services
+		.rooms
+		.event_handler
+		.mutex_federation
+		.lock(room_id)
+		.await;
+
+	let mut resolved_map = BTreeMap::new();
+	for (event_id, value) in pdus {
+		let pdu_start_time = Instant::now();
 		let result = services
 			.rooms
 			.event_handler
-			.handle_incoming_pdu(origin, &room_id, &event_id, value, true)
+			.handle_incoming_pdu(origin, room_id, &event_id, value, true)
 			.boxed()
 			.await
 			.map(|_| ());
 
-		drop(mutex_lock);
 		debug!(
 			pdu_elapsed = ?pdu_start_time.elapsed(),
 			txn_elapsed = ?txn_start_time.elapsed(),
@@ -149,15 +227,8 @@ async fn handle_pdus(
 		resolved_map.insert(event_id, result);
 	}
 
-	for (id, result) in &resolved_map {
-		if let Err(e) = result {
-			if matches!(e, Error::BadRequest(ErrorKind::NotFound, _)) {
-				warn!("Incoming PDU failed {id}: {e:?}");
-			}
-		}
-	}
-
-	Ok(resolved_map)
+	drop(mutex_lock);
+	resolved_map
 }
 
 async fn handle_edus(
@@ -2,6 +2,7 @@ use std::env::consts::OS;
 
 use either::Either;
 use figment::Figment;
+use ruma::events::room::history_visibility::HistoryVisibility;
 
 use super::DEPRECATED_KEYS;
 use crate::{debug, debug_info, debug_warn, error, warn, Config, Err, Result};
@@ -22,6 +23,36 @@ pub fn check(config: &Config) -> Result<()> {
 		));
 	}
 
+	if !matches!(config.database_backend.as_str(), "rocksdb" | "memory") {
+		return Err!(Config(
+			"database_backend",
+			"Unknown database backend {:?}; expected \"rocksdb\" or \"memory\"",
+			config.database_backend
+		));
+	}
+
+	if config.database_backend == "memory"
+		&& (config.rocksdb_read_only
+			|| config.rocksdb_secondary
+			|| config.database_backup_path.is_some())
+	{
+		return Err!(Config(
+			"database_backend",
+			"An in-memory database cannot be opened read-only or as a secondary, nor backed up"
+		));
+	}
+
+	if config
+		.database_shadow_path
+		.as_ref()
+		.is_some_and(|path| *path == config.database_path)
+	{
+		return Err!(Config(
+			"database_shadow_path",
+			"The shadow database cannot be the same as the primary database_path"
+		));
+	}
+
 	if cfg!(all(feature = "hardened_malloc", feature = "jemalloc")) {
 		debug_warn!(
 			"hardened_malloc and jemalloc compile-time features are both enabled, this causes \
@@ -37,14 +68,94 @@ pub fn check(config: &Config) -> Result<()> {
 		));
 	}
 
-	if config.unix_socket_path.is_none() && config.get_bind_hosts().is_empty() {
+	if config.unix_socket_path.is_none()
+		&& config.listen.is_empty()
+		&& config.get_bind_hosts().is_empty()
+	{
 		return Err!(Config("address", "No TCP addresses were specified to listen on"));
 	}
 
-	if config.unix_socket_path.is_none() && config.get_bind_ports().is_empty() {
+	if config.unix_socket_path.is_none() && config.get_listeners().is_empty() {
 		return Err!(Config("port", "No ports were specified to listen on"));
 	}
 
+	if config.oidc.enable {
+		let oidc = &config.oidc;
+		if oidc.issuer.is_none() || oidc.client_id.is_none() || oidc.client_secret.is_none() {
+			return Err!(Config(
+				"oidc",
+				"OpenID Connect login requires the issuer, client_id and client_secret of the \
+				 identity provider"
+			));
+		}
+
+		if oidc.public_base_url.is_none() && config.well_known.client.is_none() {
+			return Err!(Config(
+				"oidc.public_base_url",
+				"OpenID Connect login requires the public URL of conduwuit, from \
+				 oidc.public_base_url or well_known.client"
+			));
+		}
+	}
+
+	if config.smtp.url.is_some() && config.smtp.from.is_none() {
+		return Err!(Config("smtp.from", "Email notifications require the sender address"));
+	}
+
+	if let Some(notary) = config
+		.key_servers
+		.notary_origins
+		.keys()
+		.chain(config.key_servers.notary_unknown_origins_only.iter())
+		.find(|notary| !config.trusted_servers.contains(notary))
+	{
+		warn!(
+			"{notary} has a policy in the key_servers section but is not one of the \
+			 trusted_servers; it will not be asked for keys."
+		);
+	}
+
+	if config.tls.acme.enable && (config.tls.certs.is_some() || config.tls.key.is_some()) {
+		return Err!(Config(
+			"tls.acme",
+			"The TLS certificate is obtained by ACME when it is enabled; remove 'certs' and \
+			 'key' from the tls section."
+		));
+	}
+
+	if config.tls.acme.enable && config.tls.dual_protocol {
+		return Err!(Config(
+			"tls.dual_protocol",
+			"Plain text (HTTP) connections cannot be accepted alongside ACME."
+		));
+	}
+
+	if config.read_replica_primary.is_some() && !config.rocksdb_secondary {
+		return Err!(Config(
+			"read_replica_primary",
+			"A read replica must open the primary's database with rocksdb_secondary."
+		));
+	}
+
+	for listen in &config.listen {
+		if listen.ipv6_only.is_some() && !listen.address.is_ipv6() {
+			return Err!(Config(
+				"listen",
+				"ipv6_only was set for IPv4 address {}; it only applies to IPv6 addresses.",
+				listen.address
+			));
+		}
+
+		if cfg!(not(target_os = "linux")) && listen.interface.is_some() {
+			return Err!(Config(
+				"listen",
+				"Binding to an interface is only supported on Linux. Please remove 'interface' \
+				 from the listener for {}.",
+				listen.address
+			));
+		}
+	}
+
 	if config.unix_socket_path.is_none() {
 		config.get_bind_addrs().iter().for_each(|addr| {
 			use std::path::Path;
@@ -142,6 +253,20 @@ pub fn check(config: &Config) -> Result<()> {
 		));
 	}
 
+	if config.bad_event_backoff_min > config.bad_event_backoff_max {
+		return Err!(Config(
+			"bad_event_backoff_min",
+			"bad_event_backoff_min must not be greater than bad_event_backoff_max."
+		));
+	}
+
+	if config.bad_event_fetch_backoff_min > config.bad_event_fetch_backoff_max {
+		return Err!(Config(
+			"bad_event_fetch_backoff_min",
+			"bad_event_fetch_backoff_min must not be greater than bad_event_fetch_backoff_max."
+		));
+	}
+
 	// check if user specified valid IP CIDR ranges on startup
 	for cidr in &config.ip_range_denylist {
 		if let Err(e) = ipaddress::IPAddress::parse(cidr) {
@@ -152,6 +277,22 @@ pub fn check(config: &Config) -> Result<()> {
 		}
 	}
 
+	for cidr in &config.trusted_proxies {
+		if let Err(e) = ipaddress::IPAddress::parse(cidr) {
+			return Err!(Config(
+				"trusted_proxies",
+				"Parsing specified IP CIDR range from string failed: {e}."
+			));
+		}
+	}
+
+	if !config.trusted_proxies.is_empty() && config.trusted_proxy_hops == 0 {
+		warn!(
+			"trusted_proxies is set but trusted_proxy_hops is 0, so no proxy headers are \
+			 honored."
+		);
+	}
+
 	if config.allow_registration
 		&& !config.yes_i_am_very_very_sure_i_want_an_open_registration_server_prone_to_abuse
 		&& config.registration_token.is_none()
@@ -224,6 +365,49 @@ pub fn check(config: &Config) -> Result<()> {
 		);
 	}
 
+	for (key, paths) in [
+		("disabled_client_endpoints", &config.disabled_client_endpoints),
+		("admin_only_client_endpoints", &config.admin_only_client_endpoints),
+	] {
+		if let Some(path) = paths.iter().find(|path| !path.starts_with('/')) {
+			return Err!(Config(
+				key,
+				"Endpoint {path:?} must be a path starting with '/', e.g. \"/createRoom\"."
+			));
+		}
+	}
+
+	if config.retention_min_lifetime > 0
+		&& config.retention_max_lifetime > 0
+		&& config.retention_min_lifetime > config.retention_max_lifetime
+	{
+		return Err!(Config(
+			"retention_min_lifetime",
+			"Must not be greater than retention_max_lifetime."
+		));
+	}
+
+	if !is_history_visibility(&config.default_history_visibility_public_chat) {
+		return Err!(Config(
+			"default_history_visibility_public_chat",
+			"Must be one of \"world_readable\", \"shared\", \"invited\" or \"joined\"."
+		));
+	}
+
+	if !is_history_visibility(&config.default_history_visibility_private_chat) {
+		return Err!(Config(
+			"default_history_visibility_private_chat",
+			"Must be one of \"world_readable\", \"shared\", \"invited\" or \"joined\"."
+		));
+	}
+
+	if !is_history_visibility(&config.default_history_visibility_trusted_private_chat) {
+		return Err!(Config(
+			"default_history_visibility_trusted_private_chat",
+			"Must be one of \"world_readable\", \"shared\", \"invited\" or \"joined\"."
+		));
+	}
+
 	if let Some(Either::Right(_)) = config.url_preview_bound_interface.as_ref() {
 		if !matches!(OS, "android" | "fuchsia" | "linux") {
 			return Err!(Config(
@@ -285,3 +469,8 @@ pub(super) fn is_dual_listening(raw_config: &Figment) -> Result<()> {
 
 	Ok(())
 }
+
+/// Whether the history visibility is one of the spec rather than custom.
+fn is_history_visibility(visibility: &HistoryVisibility) -> bool {
+	matches!(visibility.as_str(), "world_readable" | "shared" | "invited" | "joined")
+}
@@ -0,0 +1,82 @@
+use http::StatusCode;
+
+use super::Error;
+
+/// What an error is about. The Matrix error code and the HTTP status of the
+/// response follow from it, and whether the message is shown to whoever made
+/// the request, be it a client or a remote server.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Category {
+	/// The request can't be done as it is; mostly a 4xx the others don't
+	/// cover.
+	Request,
+
+	/// The requester is not authenticated or not allowed to do it; 401 or 403.
+	Auth,
+
+	/// The requested thing does not exist, or is not shown; 404.
+	NotFound,
+
+	/// The requester made too many requests; 429.
+	RateLimited,
+
+	/// The request could not be done now, e.g. it timed out or the server is
+	/// shutting down; a 5xx with a message meant for the requester.
+	Unavailable,
+
+	/// A request to another server failed or it sent something invalid, like
+	/// an event with bad signatures or which fails state resolution.
+	Remote,
+
+	/// Our own failure: the database, I/O, configuration, or a bug like a
+	/// panic or a poisoned lock. The message is only logged, never sent to the
+	/// requester.
+	Internal,
+}
+
+impl Error {
+	/// The category of the error; see [`Category`].
+	pub fn category(&self) -> Category {
+		match self {
+			| Self::Federation(..)
+			| Self::BadServerResponse(..)
+			| Self::InconsistentRoomState(..)
+			| Self::Redaction(..)
+			| Self::Reqwest(..)
+			| Self::Signatures(..)
+			| Self::StateRes(..) => Category::Remote,
+
+			// Untyped errors and invalid JSON are mostly about what was sent.
+			| Self::Err(..) | Self::Json(..) => Category::Request,
+
+			| Self::BadRequest(..)
+			| Self::Request(..)
+			| Self::Ruma(..)
+			| Self::Uiaa(..)
+			| Self::Conflict(..)
+			| Self::FeatureDisabled(..)
+			| Self::Mxc(..)
+			| Self::Mxid(..)
+			| Self::Path(..) => Category::from_status(self.status_code()),
+
+			| _ => Category::Internal,
+		}
+	}
+
+	/// Whether the error is our own failure rather than anything to do with
+	/// the request; see [`Category::Internal`].
+	#[inline]
+	pub fn is_internal(&self) -> bool { self.category() == Category::Internal }
+}
+
+impl Category {
+	fn from_status(status: StatusCode) -> Self {
+		match status {
+			| StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Self::Auth,
+			| StatusCode::NOT_FOUND => Self::NotFound,
+			| StatusCode::TOO_MANY_REQUESTS => Self::RateLimited,
+			| status if status.is_server_error() => Self::Unavailable,
+			| _ => Self::Request,
+		}
+	}
+}
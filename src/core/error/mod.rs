@@ -1,3 +1,4 @@
+mod category;
 mod err;
 mod log;
 mod panic;
@@ -6,7 +7,7 @@ mod serde;
 
 use std::{any::Any, borrow::Cow, convert::Infallible, fmt, sync::PoisonError};
 
-pub use self::{err::visit, log::*};
+pub use self::{category::Category, err::visit, log::*};
 
 #[derive(thiserror::Error)]
 pub enum Error {
@@ -132,11 +133,20 @@ impl Error {
 		crate::err!(Database(error!("{message}")))
 	}
 
-	/// Sanitizes public-facing errors that can leak sensitive information.
+	/// The message sent to a client or remote server. Our own failures, like
+	/// those of the database and the filesystem, and failed requests to other
+	/// servers are only described in general, lest the database, the files or
+	/// the addresses of other servers are revealed; the full message is for
+	/// the log.
 	pub fn sanitized_message(&self) -> String {
-		match self {
-			| Self::Database(..) => String::from("Database error occurred."),
-			| Self::Io(..) => String::from("I/O error occurred."),
+		match self.category() {
+			| Category::Internal => match self {
+				| Self::Database(..) => String::from("Database error occurred."),
+				| Self::Io(..) => String::from("I/O error occurred."),
+				| _ => String::from("Internal server error occurred."),
+			},
+			| Category::Remote if matches!(self, Self::Reqwest(..)) =>
+				String::from("Request to a remote server failed."),
 			| _ => self.message(),
 		}
 	}
@@ -153,13 +163,14 @@ impl Error {
 	/// Returns the Matrix error code / error kind
 	#[inline]
 	pub fn kind(&self) -> ruma::api::client::error::ErrorKind {
-		use ruma::api::client::error::ErrorKind::{FeatureDisabled, Unknown};
+		use ruma::api::client::error::ErrorKind::{FeatureDisabled, InvalidParam, Unknown};
 
 		match self {
 			| Self::Federation(_, error) | Self::Ruma(error) =>
 				response::ruma_error_kind(error).clone(),
 			| Self::BadRequest(kind, ..) | Self::Request(kind, ..) => kind.clone(),
 			| Self::FeatureDisabled(..) => FeatureDisabled,
+			| Self::Mxc(..) | Self::Mxid(..) => InvalidParam,
 			| _ => Unknown,
 		}
 	}
@@ -176,6 +187,8 @@ impl Error {
 			| Self::FeatureDisabled(..) => response::bad_request_code(&self.kind()),
 			| Self::Reqwest(error) => error.status().unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
 			| Self::Conflict(_) => StatusCode::CONFLICT,
+			| Self::Mxc(_) | Self::Mxid(_) => StatusCode::BAD_REQUEST,
+			| Self::Path(error) => error.status(),
 			| Self::Io(error) => response::io_error_code(error.kind()),
 			| _ => StatusCode::INTERNAL_SERVER_ERROR,
 		}
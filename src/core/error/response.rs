@@ -32,9 +32,13 @@ impl From<Error> for UiaaResponse {
 			return Self::AuthResponse(uiaainfo);
 		}
 
+		if error.is_internal() {
+			error!("{error}");
+		}
+
 		let body = ErrorBody::Standard {
 			kind: error.kind(),
-			message: error.message(),
+			message: error.sanitized_message(),
 		};
 
 		Self::MatrixError(ruma::api::client::error::Error {
@@ -77,7 +81,8 @@ pub(super) fn bad_request_code(kind: &ErrorKind) -> StatusCode {
 		| Forbidden { .. } => StatusCode::FORBIDDEN,
 
 		// 401
-		| UnknownToken { .. } | MissingToken | Unauthorized => StatusCode::UNAUTHORIZED,
+		| UnknownToken { .. } | MissingToken | Unauthorized | UserLocked =>
+			StatusCode::UNAUTHORIZED,
 
 		// 400
 		| _ => StatusCode::BAD_REQUEST,
@@ -24,6 +24,7 @@ pub struct Metrics {
 	pub requests_handle_active: AtomicU32,
 	pub requests_handle_finished: AtomicU32,
 	pub requests_panic: AtomicU32,
+	pub requests_timeout: AtomicU32,
 }
 
 impl Metrics {
@@ -53,6 +54,7 @@ impl Metrics {
 			requests_handle_active: AtomicU32::new(0),
 			requests_handle_finished: AtomicU32::new(0),
 			requests_panic: AtomicU32::new(0),
+			requests_timeout: AtomicU32::new(0),
 		}
 	}
 
@@ -0,0 +1,97 @@
+//! Limits of the federation format of events, checked before our own events
+//! are signed, so remote servers never see events they would reject.
+
+use ruma::{CanonicalJsonObject, CanonicalJsonValue};
+
+use crate::{Err, Result};
+
+/// Maximum size of an event as canonical JSON, signatures included.
+pub const MAX_PDU_BYTES: usize = 65_536;
+
+/// Maximum size of the `type`, `state_key`, `sender` and `room_id` of an
+/// event.
+pub const MAX_ID_BYTES: usize = 255;
+
+/// Maximum number of the `prev_events` of an event.
+pub const MAX_PREV_EVENTS: usize = 20;
+
+/// Maximum number of the `auth_events` of an event.
+pub const MAX_AUTH_EVENTS: usize = 10;
+
+/// Check an event in the federation format against the limits of the spec.
+/// Hashes and signatures added afterwards count towards its size too, so the
+/// signed event is checked again with [`check_pdu_size`].
+pub fn check_pdu_format(pdu_json: &CanonicalJsonObject) -> Result {
+	check_pdu_size(pdu_json)?;
+
+	match pdu_json.get("type") {
+		| Some(CanonicalJsonValue::String(kind)) => check_identifier("type", kind, false)?,
+		| _ => return Err!(Request(BadJson("Event type is missing or not a string."))),
+	}
+
+	match pdu_json.get("state_key") {
+		| Some(CanonicalJsonValue::String(state_key)) =>
+			check_identifier("state_key", state_key, true)?,
+		| Some(_) => return Err!(Request(BadJson("Event state_key is not a string."))),
+		| None => {},
+	}
+
+	for field in ["sender", "room_id"] {
+		if let Some(CanonicalJsonValue::String(id)) = pdu_json.get(field) {
+			check_identifier(field, id, false)?;
+		}
+	}
+
+	check_references(pdu_json, "prev_events", MAX_PREV_EVENTS)?;
+	check_references(pdu_json, "auth_events", MAX_AUTH_EVENTS)?;
+
+	Ok(())
+}
+
+/// Check the size of an event as canonical JSON, which has to include its
+/// hashes and signatures once it's signed.
+pub fn check_pdu_size(pdu_json: &CanonicalJsonObject) -> Result {
+	let size = serde_json::to_vec(pdu_json)?.len();
+	if size > MAX_PDU_BYTES {
+		return Err!(Request(TooLarge(
+			"Event is too large: {size} bytes as canonical JSON, more than {MAX_PDU_BYTES}."
+		)));
+	}
+
+	Ok(())
+}
+
+/// Identifiers must fit in 255 bytes and hold no control characters; only a
+/// state key may be empty.
+fn check_identifier(field: &str, value: &str, allow_empty: bool) -> Result {
+	if value.is_empty() && !allow_empty {
+		return Err!(Request(InvalidParam("Event {field} must not be empty.")));
+	}
+
+	if value.len() > MAX_ID_BYTES {
+		return Err!(Request(InvalidParam(
+			"Event {field} is too long: {} bytes, more than {MAX_ID_BYTES}.",
+			value.len()
+		)));
+	}
+
+	if value.chars().any(char::is_control) {
+		return Err!(Request(InvalidParam("Event {field} must not contain control characters.")));
+	}
+
+	Ok(())
+}
+
+fn check_references(pdu_json: &CanonicalJsonObject, field: &str, max: usize) -> Result {
+	let count = match pdu_json.get(field) {
+		| Some(CanonicalJsonValue::Array(references)) => references.len(),
+		| Some(_) => return Err!(Request(BadJson("Event {field} is not an array."))),
+		| None => return Err!(Request(BadJson("Event {field} is missing."))),
+	};
+
+	if count > max {
+		return Err!(Request(InvalidParam("Event refers to {count} {field}, more than {max}.")));
+	}
+
+	Ok(())
+}
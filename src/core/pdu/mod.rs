@@ -4,6 +4,7 @@ mod count;
 mod event;
 mod event_id;
 mod filter;
+mod format;
 mod id;
 mod raw_id;
 mod redact;
@@ -27,6 +28,7 @@ pub use self::{
 	count::Count,
 	event::Event,
 	event_id::*,
+	format::*,
 	id::*,
 	raw_id::*,
 	Count as PduCount, Id as PduId, Pdu as PduEvent, RawId as RawPduId,
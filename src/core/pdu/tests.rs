@@ -1,4 +1,7 @@
-use super::Count;
+use ruma::{CanonicalJsonObject, CanonicalJsonValue};
+use serde_json::json;
+
+use super::{check_pdu_format, Count, MAX_PDU_BYTES, MAX_PREV_EVENTS};
 
 #[test]
 fn backfilled_parse() {
@@ -15,3 +18,62 @@ fn normal_parse() {
 
 	assert!(!backfilled, "backfilled variant");
 }
+
+fn pdu_json(value: serde_json::Value) -> CanonicalJsonObject {
+	serde_json::from_value(value).expect("valid canonical json")
+}
+
+fn message() -> CanonicalJsonObject {
+	pdu_json(json!({
+		"type": "m.room.message",
+		"sender": "@alice:example.com",
+		"room_id": "!room:example.com",
+		"content": { "body": "hello" },
+		"prev_events": ["$prev"],
+		"auth_events": ["$create", "$member"],
+	}))
+}
+
+#[test]
+fn pdu_format_valid() {
+	check_pdu_format(&message()).expect("valid event");
+
+	let mut pdu = message();
+	pdu.insert("state_key".into(), CanonicalJsonValue::String(String::new()));
+	check_pdu_format(&pdu).expect("empty state key is valid");
+}
+
+#[test]
+fn pdu_format_too_large() {
+	let mut pdu = message();
+	let body = "a".repeat(MAX_PDU_BYTES);
+	pdu.insert("content".into(), CanonicalJsonValue::Object(pdu_json(json!({ "body": body }))));
+
+	check_pdu_format(&pdu).expect_err("oversized event is invalid");
+}
+
+#[test]
+fn pdu_format_bad_identifiers() {
+	let mut pdu = message();
+	pdu.insert("type".into(), CanonicalJsonValue::String(String::new()));
+	check_pdu_format(&pdu).expect_err("empty type is invalid");
+
+	let mut pdu = message();
+	pdu.insert("state_key".into(), CanonicalJsonValue::String("a".repeat(256)));
+	check_pdu_format(&pdu).expect_err("long state key is invalid");
+
+	let mut pdu = message();
+	pdu.insert("state_key".into(), CanonicalJsonValue::String("a\nb".into()));
+	check_pdu_format(&pdu).expect_err("control character is invalid");
+}
+
+#[test]
+fn pdu_format_too_many_prev_events() {
+	let mut pdu = message();
+	let prev_events = (0..=MAX_PREV_EVENTS)
+		.map(|i| CanonicalJsonValue::String(format!("$prev{i}")))
+		.collect();
+
+	pdu.insert("prev_events".into(), CanonicalJsonValue::Array(prev_events));
+	check_pdu_format(&pdu).expect_err("too many prev_events are invalid");
+}
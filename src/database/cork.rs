@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{cell::Cell, future::Future, sync::Arc};
 
 use crate::{Database, Engine};
 
@@ -8,6 +8,18 @@ pub struct Cork {
 	sync: bool,
 }
 
+/// State of a group commit in progress on the current task. Corks released
+/// within the group defer their flush and sync to it; the group always
+/// flushes when it completes and syncs if any of them requested it.
+#[derive(Default)]
+struct Group {
+	sync: Cell<bool>,
+}
+
+tokio::task_local! {
+	static GROUP: Group;
+}
+
 impl Database {
 	#[inline]
 	#[must_use]
@@ -20,6 +32,34 @@ impl Database {
 	#[inline]
 	#[must_use]
 	pub fn cork_and_sync(&self) -> Cork { Cork::new(&self.db, true, true) }
+
+	/// Run the future as a group commit. The database is corked for the
+	/// duration and flushed once after it completes; a sync requested by any
+	/// cork released within the future (on the same task) is coalesced into a
+	/// single sync as well. This amortizes the WAL overhead of the many small
+	/// writes made while e.g. appending an inbound event. The future should
+	/// only write locally: the cork defers every other writer's flush until it
+	/// completes.
+	pub async fn group_commit<F, T>(&self, fut: F) -> T
+	where
+		F: Future<Output = T> + Send,
+	{
+		let cork = self.cork();
+		let (output, sync) = GROUP
+			.scope(Group::default(), async move {
+				let output = fut.await;
+				GROUP.with(|group| (output, group.sync.get()))
+			})
+			.await;
+
+		drop(cork);
+		self.db.flush().ok();
+		if sync {
+			self.db.sync().ok();
+		}
+
+		output
+	}
 }
 
 impl Cork {
@@ -28,11 +68,23 @@ impl Cork {
 		db.cork();
 		Self { db: db.clone(), flush, sync }
 	}
+
+	/// Hand the flush and sync to the group commit if one is in progress.
+	fn defer(&self) -> bool {
+		GROUP
+			.try_with(|group| {
+				group.sync.set(group.sync.get() || self.sync);
+			})
+			.is_ok()
+	}
 }
 
 impl Drop for Cork {
 	fn drop(&mut self) {
 		self.db.uncork();
+		if (self.flush || self.sync) && self.defer() {
+			return;
+		}
 		if self.flush {
 			self.db.flush().ok();
 		}
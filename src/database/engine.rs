@@ -3,11 +3,14 @@ mod cf_opts;
 pub(crate) mod context;
 mod db_opts;
 pub(crate) mod descriptor;
+mod export;
 mod files;
 mod logger;
 mod memory_usage;
 mod open;
 mod repair;
+mod restore;
+mod shadow;
 
 use std::{
 	ffi::CStr,
@@ -27,6 +30,7 @@ pub struct Engine {
 	pub(super) secondary: bool,
 	corks: AtomicU32,
 	pub(crate) db: Db,
+	shadow: Option<Db>,
 	pub(crate) pool: Arc<Pool>,
 	pub(crate) ctx: Arc<Context>,
 }
@@ -50,10 +54,16 @@ impl Engine {
 	pub fn corked(&self) -> bool { self.corks.load(Ordering::Relaxed) > 0 }
 
 	#[tracing::instrument(skip(self))]
-	pub fn sync(&self) -> Result { result(DBCommon::flush_wal(&self.db, true)) }
+	pub fn sync(&self) -> Result {
+		self.shadow_flush(true);
+		result(DBCommon::flush_wal(&self.db, true))
+	}
 
 	#[tracing::instrument(skip(self), level = "debug")]
-	pub fn flush(&self) -> Result { result(DBCommon::flush_wal(&self.db, false)) }
+	pub fn flush(&self) -> Result {
+		self.shadow_flush(false);
+		result(DBCommon::flush_wal(&self.db, false))
+	}
 
 	#[tracing::instrument(skip(self), level = "debug")]
 	pub fn sort(&self) -> Result {
@@ -79,6 +89,11 @@ impl Engine {
 			.and_then(|val| val.map_or_else(|| Err!("Property {name:?} not found."), Ok))
 	}
 
+	/// Apply the writes made to the primary since the last catch-up; only for
+	/// databases opened as a secondary.
+	#[tracing::instrument(skip(self), level = "trace")]
+	pub fn catch_up(&self) -> Result { result(self.db.try_catch_up_with_primary()) }
+
 	#[inline]
 	#[must_use]
 	pub fn is_read_only(&self) -> bool { self.secondary || self.read_only }
@@ -1,15 +1,15 @@
 use conduwuit::{
 	err,
-	utils::{math::Expected, BoolExt},
+	utils::{math::Expected, millis_since_unix_epoch, BoolExt},
 	Config, Result,
 };
 use rocksdb::{
-	BlockBasedIndexType, BlockBasedOptions, BlockBasedPinningTier, Cache,
+	BlockBasedIndexType, BlockBasedOptions, BlockBasedPinningTier, Cache, CompactionDecision,
 	DBCompressionType as CompressionType, DataBlockIndexType, LruCacheOptions, Options,
 	UniversalCompactOptions, UniversalCompactionStopStyle,
 };
 
-use super::descriptor::{CacheDisp, Descriptor};
+use super::descriptor::{CacheDisp, Descriptor, Expiry};
 use crate::{util::map_err, Context};
 
 /// Adjust options for the specific column by name. Provide the result of
@@ -68,6 +68,8 @@ fn descriptor_cf_options(
 	opts.set_options_from_string("{{arena_block_size=2097152;}}")
 		.map_err(map_err)?;
 
+	set_expiry(&mut opts, &desc, config);
+
 	Ok(opts)
 }
 
@@ -107,6 +109,49 @@ fn set_compression(desc: &mut Descriptor, config: &Config) {
 		.then_some(config.rocksdb_bottommost_compression_level);
 }
 
+fn set_expiry(opts: &mut Options, desc: &Descriptor, config: &Config) {
+	let Some(expiry) = desc.expiry else {
+		return;
+	};
+
+	// Lifetimes of records timestamped at creation are given by the server
+	// config; zero disables expiry.
+	let lifetime = match desc.name {
+		| "url_previews" => config.url_preview_cache_ttl,
+		| "userdevicetxnid_response" => config.transaction_id_ttl,
+		| "servertxnid_response" => config.federation_transaction_ttl,
+		| "userdevicesessionid_uiaainfo"
+		| "userdevicesessionid_uiaarequest"
+		| "sessionid_uiaauserdevice" => config.uiaa_session_ttl,
+		| _ => 0,
+	};
+
+	if matches!(expiry, Expiry::AfterSecs) && lifetime == 0 {
+		return;
+	}
+
+	opts.set_compaction_filter("expiry", move |_level, _key, val| {
+		let Some(timestamp) = val
+			.get(..size_of::<u64>())
+			.and_then(|bytes| bytes.try_into().ok())
+			.map(u64::from_be_bytes)
+		else {
+			return CompactionDecision::Keep;
+		};
+
+		let expires_at = match expiry {
+			| Expiry::AtMillis => timestamp,
+			| Expiry::AfterSecs => timestamp.saturating_add(lifetime).saturating_mul(1000),
+		};
+
+		if expires_at < millis_since_unix_epoch() {
+			CompactionDecision::Remove
+		} else {
+			CompactionDecision::Keep
+		}
+	});
+}
+
 fn uc_options(desc: &Descriptor) -> UniversalCompactOptions {
 	let mut opts = UniversalCompactOptions::default();
 	opts.set_stop_style(UniversalCompactionStopStyle::Total);
@@ -33,7 +33,12 @@ impl Context {
 
 		let col_cache: BTreeMap<_, _> = [("Shared".to_owned(), col_cache)].into();
 
-		let mut env = Env::new().or_else(or_else)?;
+		let mut env = if config.database_backend == "memory" {
+			Env::mem_env()
+		} else {
+			Env::new()
+		}
+		.or_else(or_else)?;
 
 		if config.rocksdb_compaction_prio_idle {
 			env.lower_thread_pool_cpu_priority();
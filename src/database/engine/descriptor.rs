@@ -11,6 +11,18 @@ pub(crate) enum CacheDisp {
 	SharedWith(&'static str),
 }
 
+/// Records in the column lead with a big-endian timestamp and are dropped
+/// during compaction once it has passed.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Expiry {
+	/// The timestamp is the absolute expiry in milliseconds since the epoch.
+	AtMillis,
+
+	/// The timestamp is the creation time in seconds since the epoch; the
+	/// lifetime is given by the server config.
+	AfterSecs,
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Descriptor {
 	pub(crate) name: &'static str,
@@ -29,6 +41,7 @@ pub(crate) struct Descriptor {
 	pub(crate) level0_width: i32,
 	pub(crate) merge_width: (i32, i32),
 	pub(crate) ttl: u64,
+	pub(crate) expiry: Option<Expiry>,
 	pub(crate) compaction: CompactionStyle,
 	pub(crate) compaction_pri: CompactionPri,
 	pub(crate) compression: CompressionType,
@@ -55,6 +68,7 @@ pub(crate) static BASE: Descriptor = Descriptor {
 	level0_width: 2,
 	merge_width: (2, 16),
 	ttl: 60 * 60 * 24 * 21,
+	expiry: None,
 	compaction: CompactionStyle::Level,
 	compaction_pri: CompactionPri::MinOverlappingRatio,
 	compression: CompressionType::Zstd,
@@ -0,0 +1,144 @@
+use std::{fmt::Write, path::Path};
+
+use conduwuit::{debug, implement, info, Err, Result};
+use rocksdb::{Direction, IteratorMode, ReadOptions, WriteBatch};
+
+use super::{db_opts::db_options, Db, Engine};
+use crate::{maps::MAPS, or_else, util::map_err};
+
+/// Records in the target's default column noting how far each column has been
+/// copied; the value is the last key copied, or empty once the column is done.
+/// They are deleted once every column is, leaving only the copied data.
+const PROGRESS_PREFIX: &[u8] = b"conduwuit_export:";
+
+/// Keys written to the target per batch, each batch also recording progress.
+const BATCH_SIZE: usize = 8192;
+
+/// Keys copied between progress reports.
+const REPORT_INTERVAL: usize = 1_000_000;
+
+/// Copy every column into a new database at `to`, which is thereby rebuilt
+/// from scratch without fragmentation or dropped data. An interrupted export
+/// resumes where it stopped when run again with the same target. Columns are
+/// read from a snapshot per run, so keys may change between runs; a run on a
+/// read-only database instead brings every column of the target in line with
+/// it, rewriting the keys changed and deleting the keys removed since they
+/// were copied, which makes it the consistent final run.
+#[implement(Engine)]
+#[tracing::instrument(skip(self))]
+pub fn export(&self, to: &Path) -> Result<String> {
+	let config = &self.ctx.server.config;
+	if to == config.database_path {
+		return Err!("The export target must not be the database itself.");
+	}
+
+	let db_opts = db_options(config, &self.ctx.env.lock()?, &self.ctx.row_cache.lock()?)?;
+
+	let cfds = Self::configure_cfds(&self.ctx, &db_opts, MAPS, to)?;
+	let target = Db::open_cf_descriptors(&db_opts, to, cfds).or_else(or_else)?;
+	let snapshot = self.db.snapshot();
+	let reconcile = self.is_read_only();
+
+	let mut out = String::new();
+	let (mut columns, mut total, mut removed) = (0_usize, 0_usize, 0_usize);
+	for desc in MAPS.iter().filter(|desc| !desc.dropped) {
+		let name = desc.name;
+		let progress_key = [PROGRESS_PREFIX, name.as_bytes()].concat();
+		let resume = if reconcile {
+			None
+		} else {
+			target.get(&progress_key).map_err(map_err)?
+		};
+
+		if resume.as_ref().is_some_and(Vec::is_empty) {
+			debug!(column = %name, "Column already exported.");
+			continue;
+		}
+
+		let source_cf = self.cf(name);
+		let target_cf = target
+			.cf_handle(name)
+			.expect("column must be described prior to database open");
+
+		let mode = resume.as_deref().map_or(IteratorMode::Start, |last| {
+			info!(column = %name, "Resuming export of column...");
+			IteratorMode::From(last, Direction::Forward)
+		});
+
+		let mut batch = WriteBatch::default();
+		let (mut count, mut report) = (0_usize, REPORT_INTERVAL);
+		for item in snapshot.iterator_cf_opt(&source_cf, ReadOptions::default(), mode) {
+			let (key, val) = item.map_err(map_err)?;
+			if resume.as_deref() == Some(&*key) {
+				continue;
+			}
+
+			if reconcile
+				&& target
+					.get_pinned_cf(&target_cf, &key)
+					.map_err(map_err)?
+					.as_deref() == Some(&*val)
+			{
+				continue;
+			}
+
+			batch.put_cf(&target_cf, &key, &val);
+			count = count.saturating_add(1);
+			if batch.len() >= BATCH_SIZE {
+				batch.put(&progress_key, &key);
+				target.write(batch).map_err(map_err)?;
+				batch = WriteBatch::default();
+			}
+
+			if count >= report {
+				info!(column = %name, ?count, "Exporting column...");
+				report = report.saturating_add(REPORT_INTERVAL);
+			}
+		}
+
+		let mut stale = 0_usize;
+		if reconcile {
+			for item in target.iterator_cf(&target_cf, IteratorMode::Start) {
+				let (key, _) = item.map_err(map_err)?;
+				let exists = snapshot
+					.get_pinned_cf_opt(&source_cf, &key, &ReadOptions::default())
+					.map_err(map_err)?
+					.is_some();
+
+				if !exists {
+					batch.delete_cf(&target_cf, &key);
+					stale = stale.saturating_add(1);
+				}
+
+				if batch.len() >= BATCH_SIZE {
+					target.write(batch).map_err(map_err)?;
+					batch = WriteBatch::default();
+				}
+			}
+		}
+
+		batch.put(&progress_key, []);
+		target.write(batch).map_err(map_err)?;
+		info!(column = %name, ?count, ?stale, "Exported column.");
+
+		writeln!(out, "| {name} | {count} |")?;
+		columns = columns.saturating_add(1);
+		total = total.saturating_add(count);
+		removed = removed.saturating_add(stale);
+	}
+
+	let mut batch = WriteBatch::default();
+	for desc in MAPS.iter().filter(|desc| !desc.dropped) {
+		batch.delete([PROGRESS_PREFIX, desc.name.as_bytes()].concat());
+	}
+
+	target.write(batch).map_err(map_err)?;
+	target.flush().map_err(map_err)?;
+	info!(?columns, ?total, ?removed, "Exported database to {to:?}.");
+
+	Ok(format!(
+		"Exported {total} keys from {columns} columns to `{}`, deleting {removed} stale \
+		 keys.\n\n| Column | Keys |\n| --- | --- |\n{out}",
+		to.display()
+	))
+}
@@ -29,7 +29,7 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 		&ctx.row_cache.lock().expect("row cache locked"),
 	)?;
 
-	let cfds = Self::configure_cfds(&ctx, &db_opts, desc)?;
+	let cfds = Self::configure_cfds(&ctx, &db_opts, desc, path)?;
 	let num_cfds = cfds.len();
 	debug!("Configured {num_cfds} column descriptors...");
 
@@ -48,6 +48,13 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 	}
 	.or_else(or_else)?;
 
+	let shadow = config
+		.database_shadow_path
+		.as_deref()
+		.filter(|_| !config.rocksdb_read_only && !config.rocksdb_secondary)
+		.map(|shadow_path| Self::open_shadow(&ctx, &db_opts, desc, shadow_path))
+		.transpose()?;
+
 	info!(
 		columns = num_cfds,
 		sequence = %db.latest_sequence_number(),
@@ -61,20 +68,19 @@ pub(crate) async fn open(ctx: Arc<Context>, desc: &[Descriptor]) -> Result<Arc<S
 		corks: AtomicU32::new(0),
 		pool: ctx.pool.clone(),
 		db,
+		shadow,
 		ctx,
 	}))
 }
 
 #[implement(Engine)]
 #[tracing::instrument(name = "configure", skip_all)]
-fn configure_cfds(
+pub(super) fn configure_cfds(
 	ctx: &Arc<Context>,
 	db_opts: &Options,
 	desc: &[Descriptor],
+	path: &Path,
 ) -> Result<Vec<ColumnFamilyDescriptor>> {
-	let server = &ctx.server;
-	let config = &server.config;
-	let path = &config.database_path;
 	let existing = Self::discover_cfs(path, db_opts);
 
 	let creating = desc.iter().filter(|desc| !existing.contains(desc.name));
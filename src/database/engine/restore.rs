@@ -0,0 +1,139 @@
+use std::{
+	ffi::OsStr,
+	fs,
+	path::{Path, PathBuf},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use conduwuit::{implement, info, Err, Result};
+use rocksdb::backup::{BackupEngine, BackupEngineOptions, RestoreOptions};
+
+use super::{db_opts::db_options, Db, Engine};
+use crate::{maps::MAPS, or_else, util::map_err};
+
+/// Reconstruct the database as it was at `to` (seconds since the epoch) into
+/// a new directory beside the database and return its path. The most recent
+/// backup taken at or before that time is restored and the write-ahead logs
+/// written since, up to that time, are replayed on top. Logs are replayed
+/// whole, so the result may predate `to` by up to one log file's worth of
+/// writes.
+///
+/// The database must be open read-only so no logs are written or archived
+/// while they are being collected.
+#[implement(Engine)]
+#[tracing::instrument(skip(self))]
+pub fn restore(&self, to: u64) -> Result<PathBuf> {
+	let server = &self.ctx.server;
+	let config = &server.config;
+	if !self.is_read_only() {
+		return Err!("Restoring requires the database to be opened with rocksdb_read_only.");
+	}
+
+	let Some(backup_path) = config.database_backup_path.as_ref() else {
+		return Err!("Restoring requires a backup; configure database_backup_path.");
+	};
+
+	let to_time = UNIX_EPOCH
+		.checked_add(Duration::from_secs(to))
+		.unwrap_or(UNIX_EPOCH);
+
+	let to_secs = i64::try_from(to)?;
+	let options = BackupEngineOptions::new(backup_path).map_err(map_err)?;
+	let mut engine = BackupEngine::open(&options, &*self.ctx.env.lock()?).map_err(map_err)?;
+	let Some(backup) = engine
+		.get_backup_info()
+		.into_iter()
+		.filter(|info| info.timestamp <= to_secs)
+		.max_by_key(|info| info.timestamp)
+	else {
+		return Err!("No backup was taken at or before {to}.");
+	};
+
+	let path = &config.database_path;
+	let target = restore_path(path, to);
+	if target.exists() {
+		return Err!("Restore target {target:?} already exists.");
+	}
+
+	info!(
+		backup = backup.backup_id,
+		timestamp = backup.timestamp,
+		"Restoring database backup to {target:?}..."
+	);
+
+	engine
+		.restore_from_backup(&target, &target, &RestoreOptions::default(), backup.backup_id)
+		.map_err(map_err)?;
+
+	let logs = wal_files(path, to_time)?;
+	let mut replaying = 0_usize;
+	for log in &logs {
+		let name = log.file_name().unwrap_or_default();
+		let dest = target.join(name);
+		if !dest.exists() {
+			fs::copy(log, dest)?;
+			replaying = replaying.saturating_add(1);
+		}
+	}
+
+	info!("Replaying {replaying} write-ahead logs onto restored backup...");
+
+	let db_opts = db_options(config, &self.ctx.env.lock()?, &self.ctx.row_cache.lock()?)?;
+
+	let cfds = Self::configure_cfds(&self.ctx, &db_opts, MAPS, &target)?;
+	let db = Db::open_cf_descriptors(&db_opts, &target, cfds).or_else(or_else)?;
+	info!(
+		sequence = %db.latest_sequence_number(),
+		"Restored database to {target:?}."
+	);
+
+	Ok(target)
+}
+
+fn restore_path(path: &Path, to: u64) -> PathBuf {
+	let name = path
+		.file_name()
+		.map(|name| name.to_string_lossy().into_owned())
+		.unwrap_or_default();
+
+	path.with_file_name(format!("{name}-restore-{to}"))
+}
+
+/// Live and archived write-ahead log files, in order, which were last written
+/// no later than `to`. Collection stops at the first log written afterward
+/// since replay must not skip over any.
+fn wal_files(path: &Path, to: SystemTime) -> Result<Vec<PathBuf>> {
+	let mut logs = Vec::new();
+	for dir in [path.join("archive"), path.to_path_buf()] {
+		let Ok(entries) = fs::read_dir(&dir) else {
+			continue;
+		};
+
+		for entry in entries {
+			let entry = entry?;
+			let path = entry.path();
+			if path.extension().is_none_or(|ext| ext != "log") {
+				continue;
+			}
+
+			let Some(number) = path
+				.file_stem()
+				.and_then(OsStr::to_str)
+				.and_then(|stem| stem.parse::<u64>().ok())
+			else {
+				continue;
+			};
+
+			logs.push((number, entry.metadata()?.modified()?, path));
+		}
+	}
+
+	logs.sort_by_key(|(number, ..)| *number);
+	let logs = logs
+		.into_iter()
+		.take_while(|(_, modified, _)| *modified <= to)
+		.map(|(.., path)| path)
+		.collect();
+
+	Ok(logs)
+}
@@ -0,0 +1,68 @@
+//! Shadow database receiving a mirror of every write made to the primary.
+//!
+//! Reads are never served from the shadow; it exists so an operator can
+//! validate a migration under real traffic before cutting over to it. Errors
+//! writing to the shadow are logged and otherwise ignored so the primary is
+//! never affected.
+
+use std::{path::Path, sync::Arc};
+
+use conduwuit::{implement, info, warn, Result};
+use rocksdb::{BoundColumnFamily, DBCommon, Options};
+
+use super::{descriptor::Descriptor, Db, Engine};
+use crate::{or_else, Context};
+
+#[implement(Engine)]
+#[tracing::instrument(name = "shadow", skip(ctx, db_opts, desc))]
+pub(super) fn open_shadow(
+	ctx: &Arc<Context>,
+	db_opts: &Options,
+	desc: &[Descriptor],
+	path: &Path,
+) -> Result<Db> {
+	let cfds = Self::configure_cfds(ctx, db_opts, desc, path)?;
+	let db = Db::open_cf_descriptors(db_opts, path, cfds).or_else(or_else)?;
+
+	info!(
+		sequence = %db.latest_sequence_number(),
+		"Opened shadow database at {path:?}; writes will be mirrored."
+	);
+
+	Ok(db)
+}
+
+#[implement(Engine)]
+#[inline]
+#[must_use]
+pub fn is_shadowed(&self) -> bool { self.shadow.is_some() }
+
+/// Apply a write to the column in the shadow database, if one is open.
+#[implement(Engine)]
+#[inline]
+pub(crate) fn shadow<F>(&self, name: &str, write: F)
+where
+	F: FnOnce(&Db, &Arc<BoundColumnFamily<'_>>) -> Result<(), rocksdb::Error>,
+{
+	let Some(shadow) = self.shadow.as_ref() else {
+		return;
+	};
+
+	let Some(cf) = shadow.cf_handle(name) else {
+		warn!(column = %name, "Column missing from shadow database.");
+		return;
+	};
+
+	if let Err(e) = write(shadow, &cf) {
+		warn!(column = %name, "Shadow database write failed: {e}");
+	}
+}
+
+#[implement(Engine)]
+pub(super) fn shadow_flush(&self, sync: bool) {
+	if let Some(shadow) = self.shadow.as_ref() {
+		if let Err(e) = DBCommon::flush_wal(shadow, sync) {
+			warn!(?sync, "Shadow database flush failed: {e}");
+		}
+	}
+}
@@ -1,3 +1,4 @@
+mod compact;
 mod contains;
 mod count;
 mod get;
@@ -15,6 +16,7 @@ mod rev_keys_prefix;
 mod rev_stream;
 mod rev_stream_from;
 mod rev_stream_prefix;
+mod stats;
 mod stream;
 mod stream_from;
 mod stream_prefix;
@@ -33,8 +35,10 @@ use conduwuit::Result;
 use rocksdb::{AsColumnFamilyRef, ColumnFamily, ReadOptions, WriteOptions};
 
 pub(crate) use self::options::{
-	cache_read_options_default, iter_options_default, read_options_default, write_options_default,
+	cache_read_options_default, iter_options_default, iter_options_prefix, prefix_upper_bound,
+	read_options_default, write_options_default,
 };
+pub use self::stats::Stats;
 use crate::{watchers::Watchers, Engine};
 
 pub struct Map {
@@ -71,6 +75,24 @@ impl Map {
 		self.watchers.watch(prefix.as_ref())
 	}
 
+	/// Whether anything is waiting on a write under exactly this prefix.
+	#[inline]
+	pub fn is_watched<K>(&self, prefix: &K) -> bool
+	where
+		K: AsRef<[u8]> + ?Sized + Debug,
+	{
+		self.watchers.is_watched(prefix.as_ref())
+	}
+
+	/// Wake anything waiting on a write to the key without writing it.
+	#[inline]
+	pub fn notify<K>(&self, key: &K)
+	where
+		K: AsRef<[u8]> + ?Sized + Debug,
+	{
+		self.watchers.wake(key.as_ref());
+	}
+
 	#[inline]
 	pub fn property_integer(&self, name: &CStr) -> Result<u64> {
 		self.db.property_integer(&self.cf(), name)
@@ -0,0 +1,23 @@
+use std::fmt::Debug;
+
+use conduwuit::implement;
+
+/// Compact the keys of the map from `start` up to `end`, rewriting them
+/// without those deleted, e.g. to reclaim the space of many deleted at once.
+/// This blocks until the compaction is done.
+///
+/// - Bounds are raw; none starts at the first key or ends after the last.
+#[implement(super::Map)]
+#[tracing::instrument(skip(self), fields(%self), level = "debug")]
+pub fn compact_range<K>(&self, start: Option<&K>, end: Option<&K>)
+where
+	K: AsRef<[u8]> + ?Sized + Debug,
+{
+	if self.db.is_read_only() {
+		return;
+	}
+
+	self.db
+		.db
+		.compact_range_cf(&self.cf(), start.map(AsRef::as_ref), end.map(AsRef::as_ref));
+}
@@ -198,6 +198,7 @@ where
 	K: AsRef<[u8]> + ?Sized,
 	V: AsRef<[u8]>,
 {
+	let val = val.as_ref();
 	let write_options = &self.write_options;
 	self.db
 		.db
@@ -205,6 +206,9 @@ where
 		.or_else(or_else)
 		.expect("database insert error");
 
+	self.db
+		.shadow(self.name(), |shadow, cf| shadow.put_cf_opt(cf, key, val, write_options));
+
 	if !self.db.corked() {
 		self.db.flush().expect("database flush error");
 	}
@@ -221,8 +225,12 @@ where
 	V: AsRef<[u8]> + Sized + 'a,
 {
 	let mut batch = WriteBatchWithTransaction::<false>::default();
+	let mut shadow = Vec::new();
 	for (key, val) in iter {
 		batch.put_cf(&self.cf(), key.as_ref(), val.as_ref());
+		if self.db.is_shadowed() {
+			shadow.push((key, val));
+		}
 	}
 
 	let write_options = &self.write_options;
@@ -232,6 +240,15 @@ where
 		.or_else(or_else)
 		.expect("database insert batch error");
 
+	self.db.shadow(self.name(), |db, cf| {
+		let mut batch = WriteBatchWithTransaction::<false>::default();
+		for (key, val) in &shadow {
+			batch.put_cf(cf, key.as_ref(), val.as_ref());
+		}
+
+		db.write_opt(batch, write_options)
+	});
+
 	if !self.db.corked() {
 		self.db.flush().expect("database flush error");
 	}
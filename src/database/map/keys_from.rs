@@ -2,7 +2,7 @@ use std::{convert::AsRef, fmt::Debug, sync::Arc};
 
 use conduwuit::{implement, Result};
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
-use rocksdb::Direction;
+use rocksdb::{Direction, ReadOptions};
 use serde::{Deserialize, Serialize};
 
 use super::stream_from::is_cached;
@@ -46,14 +46,26 @@ where
 }
 
 #[implement(super::Map)]
-#[tracing::instrument(skip(self, from), fields(%self), level = "trace")]
+#[inline]
 pub fn raw_keys_from<P>(self: &Arc<Self>, from: &P) -> impl Stream<Item = Result<Key<'_>>> + Send
+where
+	P: AsRef<[u8]> + ?Sized + Debug,
+{
+	self.raw_keys_from_opts(from, super::iter_options_default())
+}
+
+#[implement(super::Map)]
+#[tracing::instrument(skip(self, from, opts), fields(%self), level = "trace")]
+pub(super) fn raw_keys_from_opts<P>(
+	self: &Arc<Self>,
+	from: &P,
+	opts: ReadOptions,
+) -> impl Stream<Item = Result<Key<'_>>> + Send
 where
 	P: AsRef<[u8]> + ?Sized + Debug,
 {
 	use crate::pool::Seek;
 
-	let opts = super::iter_options_default();
 	let state = stream::State::new(self, opts);
 	if is_cached(self, from) {
 		return stream::Keys::<'_>::from(state.init_fwd(from.as_ref().into())).boxed();
@@ -8,6 +8,31 @@ pub(crate) fn iter_options_default() -> ReadOptions {
 	read_options
 }
 
+/// Bound the iteration to keys beginning with `prefix`. The storage layer
+/// stops at the bounds rather than yielding out-of-range entries for the
+/// caller to discard.
+#[inline]
+pub(crate) fn iter_options_prefix(prefix: &[u8]) -> ReadOptions {
+	let mut read_options = iter_options_default();
+	read_options.set_iterate_lower_bound(prefix.to_vec());
+	if let Some(upper) = prefix_upper_bound(prefix) {
+		read_options.set_iterate_upper_bound(upper);
+	}
+
+	read_options
+}
+
+/// The smallest key greater than every key beginning with `prefix`; this is
+/// the exclusive upper bound of a prefix scan. None when no such key exists
+/// because the prefix is empty or consists only of 0xFF.
+pub(crate) fn prefix_upper_bound(prefix: &[u8]) -> Option<Vec<u8>> {
+	let last = prefix.iter().rposition(|&byte| byte != u8::MAX)?;
+	let mut upper = prefix[..=last].to_vec();
+	upper[last] = upper[last].saturating_add(1);
+
+	Some(upper)
+}
+
 #[inline]
 pub(crate) fn cache_read_options_default() -> ReadOptions {
 	let mut read_options = read_options_default();
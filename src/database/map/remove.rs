@@ -50,6 +50,9 @@ where
 		.or_else(or_else)
 		.expect("database remove error");
 
+	self.db
+		.shadow(self.name(), |shadow, cf| shadow.delete_cf_opt(cf, key, write_options));
+
 	if !self.db.corked() {
 		self.db.flush().expect("database flush error");
 	}
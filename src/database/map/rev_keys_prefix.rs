@@ -29,8 +29,12 @@ where
 	P: Serialize + ?Sized + Debug,
 {
 	let key = serialize_key(prefix).expect("failed to serialize query key");
-	self.rev_raw_keys_from(&key)
-		.try_take_while(move |k: &Key<'_>| future::ok(k.starts_with(&key)))
+	let opts = super::iter_options_prefix(&key);
+	match super::prefix_upper_bound(&key) {
+		| Some(upper) => self.rev_raw_keys_from_opts(&upper, opts).left_stream(),
+		| None => self.rev_raw_keys_from_opts(&key, opts).right_stream(),
+	}
+	.try_take_while(move |k: &Key<'_>| future::ok(k.starts_with(&key)))
 }
 
 #[implement(super::Map)]
@@ -54,6 +58,10 @@ pub fn rev_raw_keys_prefix<'a, P>(
 where
 	P: AsRef<[u8]> + ?Sized + Debug + Sync + 'a,
 {
-	self.rev_raw_keys_from(prefix)
-		.try_take_while(|k: &Key<'_>| future::ok(k.starts_with(prefix.as_ref())))
+	let opts = super::iter_options_prefix(prefix.as_ref());
+	match super::prefix_upper_bound(prefix.as_ref()) {
+		| Some(upper) => self.rev_raw_keys_from_opts(&upper, opts).left_stream(),
+		| None => self.rev_raw_keys_from_opts(prefix, opts).right_stream(),
+	}
+	.try_take_while(|k: &Key<'_>| future::ok(k.starts_with(prefix.as_ref())))
 }
@@ -6,7 +6,8 @@ use serde::{Deserialize, Serialize};
 
 use crate::keyval::{result_deserialize, serialize_key, KeyVal};
 
-/// Iterate key-value entries in the map where the key matches a prefix.
+/// Iterate key-value entries in the map where the key matches a prefix,
+/// starting from the last matching key.
 ///
 /// - Query is serialized
 /// - Result is deserialized
@@ -24,7 +25,8 @@ where
 		.map(result_deserialize::<K, V>)
 }
 
-/// Iterate key-value entries in the map where the key matches a prefix.
+/// Iterate key-value entries in the map where the key matches a prefix,
+/// starting from the last matching key.
 ///
 /// - Query is serialized
 /// - Result is raw
@@ -38,11 +40,16 @@ where
 	P: Serialize + ?Sized + Debug,
 {
 	let key = serialize_key(prefix).expect("failed to serialize query key");
-	self.rev_raw_stream_from(&key)
-		.try_take_while(move |(k, _): &KeyVal<'_>| future::ok(k.starts_with(&key)))
+	let opts = super::iter_options_prefix(&key);
+	match super::prefix_upper_bound(&key) {
+		| Some(upper) => self.rev_raw_stream_from_opts(&upper, opts).left_stream(),
+		| None => self.rev_raw_stream_from_opts(&key, opts).right_stream(),
+	}
+	.try_take_while(move |(k, _): &KeyVal<'_>| future::ok(k.starts_with(&key)))
 }
 
-/// Iterate key-value entries in the map where the key matches a prefix.
+/// Iterate key-value entries in the map where the key matches a prefix,
+/// starting from the last matching key.
 ///
 /// - Query is raw
 /// - Result is deserialized
@@ -60,7 +67,8 @@ where
 		.map(result_deserialize::<K, V>)
 }
 
-/// Iterate key-value entries in the map where the key matches a prefix.
+/// Iterate key-value entries in the map where the key matches a prefix,
+/// starting from the last matching key.
 ///
 /// - Query is raw
 /// - Result is raw
@@ -72,6 +80,10 @@ pub fn rev_raw_stream_prefix<'a, P>(
 where
 	P: AsRef<[u8]> + ?Sized + Debug + Sync + 'a,
 {
-	self.rev_raw_stream_from(prefix)
-		.try_take_while(|(k, _): &KeyVal<'_>| future::ok(k.starts_with(prefix.as_ref())))
+	let opts = super::iter_options_prefix(prefix.as_ref());
+	match super::prefix_upper_bound(prefix.as_ref()) {
+		| Some(upper) => self.rev_raw_stream_from_opts(&upper, opts).left_stream(),
+		| None => self.rev_raw_stream_from_opts(prefix, opts).right_stream(),
+	}
+	.try_take_while(|(k, _): &KeyVal<'_>| future::ok(k.starts_with(prefix.as_ref())))
 }
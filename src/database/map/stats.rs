@@ -0,0 +1,54 @@
+use std::ffi::CStr;
+
+use conduwuit::implement;
+
+/// Size and usage figures for a column as estimated by the engine. These are
+/// cheap to obtain and intended for operators monitoring growth; they are not
+/// exact.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Stats {
+	/// Estimated number of keys including those in memtables.
+	pub keys: u64,
+
+	/// Total size of all SST files belonging to the column.
+	pub sst_size: u64,
+
+	/// Size of the active and unflushed immutable memtables.
+	pub memtable_size: u64,
+
+	/// Size of the entries residing in the column's block cache.
+	pub block_cache_usage: u64,
+
+	/// Estimated number of bytes compaction needs to rewrite to bring the
+	/// column's levels back under their targets.
+	pub pending_compaction: u64,
+}
+
+impl Stats {
+	/// Name and value of each statistic, suitable for labelled export.
+	pub fn iter(&self) -> impl Iterator<Item = (&'static str, u64)> + Send {
+		[
+			("keys", self.keys),
+			("sst_bytes", self.sst_size),
+			("memtable_bytes", self.memtable_size),
+			("block_cache_bytes", self.block_cache_usage),
+			("pending_compaction_bytes", self.pending_compaction),
+		]
+		.into_iter()
+	}
+}
+
+/// Query the engine for the column's statistics. Properties which are not
+/// available (e.g. no block cache is configured) are reported as zero.
+#[implement(super::Map)]
+pub fn stats(&self) -> Stats {
+	let property = |name: &CStr| self.property_integer(name).unwrap_or(0);
+
+	Stats {
+		keys: property(c"rocksdb.estimate-num-keys"),
+		sst_size: property(c"rocksdb.total-sst-files-size"),
+		memtable_size: property(c"rocksdb.cur-size-all-mem-tables"),
+		block_cache_usage: property(c"rocksdb.block-cache-usage"),
+		pending_compaction: property(c"rocksdb.estimate-pending-compaction-bytes"),
+	}
+}
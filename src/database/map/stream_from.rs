@@ -2,7 +2,7 @@ use std::{convert::AsRef, fmt::Debug, sync::Arc};
 
 use conduwuit::{implement, Result};
 use futures::{FutureExt, Stream, StreamExt, TryFutureExt, TryStreamExt};
-use rocksdb::Direction;
+use rocksdb::{Direction, ReadOptions};
 use serde::{Deserialize, Serialize};
 use tokio::task;
 
@@ -67,17 +67,29 @@ where
 /// - Query is raw
 /// - Result is raw
 #[implement(super::Map)]
-#[tracing::instrument(skip(self, from), fields(%self), level = "trace")]
+#[inline]
 pub fn raw_stream_from<P>(
 	self: &Arc<Self>,
 	from: &P,
 ) -> impl Stream<Item = Result<KeyVal<'_>>> + Send
+where
+	P: AsRef<[u8]> + ?Sized + Debug,
+{
+	self.raw_stream_from_opts(from, super::read_options_default())
+}
+
+#[implement(super::Map)]
+#[tracing::instrument(skip(self, from, opts), fields(%self), level = "trace")]
+pub(super) fn raw_stream_from_opts<P>(
+	self: &Arc<Self>,
+	from: &P,
+	opts: ReadOptions,
+) -> impl Stream<Item = Result<KeyVal<'_>>> + Send
 where
 	P: AsRef<[u8]> + ?Sized + Debug,
 {
 	use crate::pool::Seek;
 
-	let opts = super::read_options_default();
 	let state = stream::State::new(self, opts);
 	if is_cached(self, from) {
 		let state = state.init_fwd(from.as_ref().into());
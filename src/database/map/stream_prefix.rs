@@ -38,7 +38,7 @@ where
 	P: Serialize + ?Sized + Debug,
 {
 	let key = serialize_key(prefix).expect("failed to serialize query key");
-	self.raw_stream_from(&key)
+	self.raw_stream_from_opts(&key, super::iter_options_prefix(&key))
 		.try_take_while(move |(k, _): &KeyVal<'_>| future::ok(k.starts_with(&key)))
 }
 
@@ -72,6 +72,6 @@ pub fn raw_stream_prefix<'a, P>(
 where
 	P: AsRef<[u8]> + ?Sized + Debug + Sync + 'a,
 {
-	self.raw_stream_from(prefix)
+	self.raw_stream_from_opts(prefix, super::iter_options_prefix(prefix.as_ref()))
 		.try_take_while(|(k, _): &KeyVal<'_>| future::ok(k.starts_with(prefix.as_ref())))
 }
@@ -3,7 +3,7 @@ use std::{collections::BTreeMap, sync::Arc};
 use conduwuit::Result;
 
 use crate::{
-	engine::descriptor::{self, CacheDisp, Descriptor},
+	engine::descriptor::{self, CacheDisp, Descriptor, Expiry},
 	Engine, Map,
 };
 
@@ -78,6 +78,16 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "global",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "hostname_override",
+		ttl: 60 * 60 * 48,
+		expiry: Some(Expiry::AtMillis),
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "id_appserviceregistrationfile",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "id_appserviceregistrations",
 		..descriptor::RANDOM_SMALL
@@ -98,6 +108,16 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "mediaid_file",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "mediaid_flags",
+		val_size_hint: Some(8),
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "mediaid_lastaccess",
+		val_size_hint: Some(8),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "mediaid_user",
 		..descriptor::RANDOM_SMALL
@@ -121,6 +141,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "publicroomids",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "publicroomjoined_roomid",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "publicroomkeyword_roomid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "readreceiptid_readreceipt",
 		..descriptor::RANDOM
@@ -129,6 +157,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "referencedevents",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "registrationtoken_info",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "reportid_report",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_invitedcount",
 		..descriptor::RANDOM_SMALL
@@ -141,10 +177,18 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "roomid_joinedcount",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "roomid_partialstate",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_pduleaves",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "roomid_policylist",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "roomid_shortroomid",
 		val_size_hint: Some(8),
@@ -212,10 +256,24 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "servercurrentevent_data",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "servername_backoff",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "servername_destination",
+		ttl: 60 * 60 * 48,
+		expiry: Some(Expiry::AtMillis),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "servername_educount",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "servername_mediacapability",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "servernameevent_data",
 		cache_disp: CacheDisp::Unique,
@@ -226,6 +284,18 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "serverroomids",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "servertxnid_response",
+		ttl: 60 * 60,
+		expiry: Some(Expiry::AfterSecs),
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "sessionid_uiaauserdevice",
+		ttl: 60 * 60 * 24,
+		expiry: Some(Expiry::AfterSecs),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "shorteventid_authchain",
 		cache_disp: CacheDisp::Unique,
@@ -262,6 +332,10 @@ pub(super) static MAPS: &[Descriptor] = &[
 		key_size_hint: Some(48),
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "ssosubject_userid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "statehash_shortstatehash",
 		val_size_hint: Some(8),
@@ -295,8 +369,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 	Descriptor { name: "tokenids", ..descriptor::RANDOM },
 	Descriptor {
 		name: "url_previews",
+		ttl: 60 * 60 * 24,
+		expiry: Some(Expiry::AfterSecs),
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "userdelayid_delayedevent",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userdeviceid_metadata",
 		..descriptor::RANDOM_SMALL
@@ -305,18 +385,42 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userdeviceid_token",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userdeviceroomid_shortstatehash",
+		val_size_hint: Some(16),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userdevicesessionid_uiaainfo",
+		ttl: 60 * 60 * 24,
+		expiry: Some(Expiry::AfterSecs),
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userdevicesessionid_uiaarequest",
+		ttl: 60 * 60 * 24,
+		expiry: Some(Expiry::AfterSecs),
 		..descriptor::RANDOM_SMALL
 	},
 	Descriptor {
 		name: "userdevicetxnid_response",
+		ttl: 60 * 60 * 24,
+		expiry: Some(Expiry::AfterSecs),
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userdirectoryword_userid",
 		..descriptor::RANDOM_SMALL
 	},
 	Descriptor {
 		name: "userfilterid_filter",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_accountdatasize",
+		val_size_hint: Some(8),
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_avatarurl",
 		..descriptor::RANDOM_SMALL
@@ -329,18 +433,38 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userid_devicelistversion",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_directorywords",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_displayname",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_emailaddress",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_lastonetimekeyupdate",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_lockstate",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_masterkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_motdsent",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_noticeroomid",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_password",
 		..descriptor::RANDOM
@@ -349,10 +473,31 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userid_presenceid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_profilefetched",
+		val_size_hint: Some(8),
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_ratelimitexempt",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_ratelimitfactor",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_selfsigningkeyid",
 		..descriptor::RANDOM_SMALL
 	},
+	Descriptor {
+		name: "userid_serviceaccount",
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userid_shadowbanned",
+		..descriptor::RANDOM_SMALL
+	},
 	Descriptor {
 		name: "userid_usersigningkeyid",
 		..descriptor::RANDOM_SMALL
@@ -363,10 +508,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 	},
 	Descriptor {
 		name: "openidtoken_expiresatuserid",
+		ttl: 60 * 60 * 24,
+		expiry: Some(Expiry::AtMillis),
 		..descriptor::RANDOM_SMALL
 	},
 	Descriptor {
 		name: "logintoken_expiresatuserid",
+		ttl: 60 * 60 * 24,
+		expiry: Some(Expiry::AtMillis),
 		..descriptor::RANDOM_SMALL
 	},
 	Descriptor {
@@ -393,4 +542,14 @@ pub(super) static MAPS: &[Descriptor] = &[
 		name: "userroomid_notificationcount",
 		..descriptor::RANDOM
 	},
+	Descriptor {
+		name: "userroomthreadid_highlightcount",
+		val_size_hint: Some(8),
+		..descriptor::RANDOM_SMALL
+	},
+	Descriptor {
+		name: "userroomthreadid_notificationcount",
+		val_size_hint: Some(8),
+		..descriptor::RANDOM_SMALL
+	},
 ];
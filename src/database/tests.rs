@@ -292,3 +292,30 @@ fn ser_array() {
 	let s = serialize_to_vec(arr).expect("failed to serialize");
 	assert_eq!(&s, &v, "serialization does not match");
 }
+
+#[test]
+fn prefix_upper_bound() {
+	use crate::map::prefix_upper_bound;
+
+	let user_id: &UserId = "@user:example.com".try_into().unwrap();
+	let prefix = serialize_to_vec((user_id, Interfix)).expect("failed to serialize prefix");
+	let upper = prefix_upper_bound(&prefix).expect("upper bound exists");
+
+	let mut expect = user_id.as_bytes().to_vec();
+	*expect.last_mut().unwrap() = b'n';
+	assert_eq!(upper, expect, "trailing separator dropped and last byte incremented");
+
+	let mut key = prefix.clone();
+	key.extend_from_slice(&[0xFF; 8]);
+	assert!(prefix < upper, "prefix is below the bound");
+	assert!(key < upper, "all keys with the prefix are below the bound");
+}
+
+#[test]
+fn prefix_upper_bound_saturated() {
+	use crate::map::prefix_upper_bound;
+
+	assert_eq!(prefix_upper_bound(&[0x01, 0xFF, 0xFF]), Some(vec![0x02]));
+	assert_eq!(prefix_upper_bound(&[0xFF, 0xFF]), None);
+	assert_eq!(prefix_upper_bound(&[]), None);
+}
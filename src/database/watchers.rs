@@ -34,6 +34,10 @@ impl Watchers {
 		})
 	}
 
+	pub(crate) fn is_watched(&self, prefix: &[u8]) -> bool {
+		self.watchers.read().unwrap().contains_key(prefix)
+	}
+
 	pub(crate) fn wake(&self, key: &[u8]) {
 		let watchers = self.watchers.read().unwrap();
 		let mut triggered = Vec::new();
@@ -0,0 +1,139 @@
+//! Client IP address of requests, honoring the `X-Forwarded-For` and
+//! `Forwarded` headers of the `trusted_proxies` only.
+//!
+//! The handlers extract the address with `InsecureClientIp`, which prefers
+//! these headers over the address of the peer. They are removed here and the
+//! address determined from them, when trusted, is put in `X-Real-IP` instead,
+//! so the handlers see either that or the peer.
+
+use std::{
+	net::{IpAddr, SocketAddr},
+	sync::Arc,
+};
+
+use axum::{
+	extract::{ConnectInfo, State},
+	response::Response,
+};
+use conduwuit::trace;
+use conduwuit_service::Services;
+use http::{header, HeaderMap, HeaderName, HeaderValue};
+
+/// The headers `InsecureClientIp` takes the client address from.
+const CLIENT_IP_HEADERS: &[&str] = &[
+	"x-forwarded-for",
+	"x-real-ip",
+	"forwarded",
+	"fly-client-ip",
+	"true-client-ip",
+	"cf-connecting-ip",
+	"cloudfront-viewer-address",
+];
+
+pub(crate) async fn handle(
+	State(services): State<Arc<Services>>,
+	mut req: http::Request<axum::body::Body>,
+	next: axum::middleware::Next,
+) -> Response {
+	let peer = req
+		.extensions()
+		.get::<ConnectInfo<SocketAddr>>()
+		.map(|ConnectInfo(addr)| addr.ip());
+
+	let globals = &services.globals;
+	let client = peer.map(|peer| {
+		resolve(peer, &forwarded_chain(req.headers()), globals.config.trusted_proxy_hops, |ip| {
+			globals.is_trusted_proxy(ip)
+		})
+	});
+
+	let headers = req.headers_mut();
+	for name in CLIENT_IP_HEADERS {
+		headers.remove(*name);
+	}
+
+	if let (Some(peer), Some(client)) = (peer, client) {
+		if client != peer {
+			trace!(%peer, %client, "Client address forwarded by trusted proxy");
+			if let Ok(value) = HeaderValue::from_str(&client.to_string()) {
+				headers.insert(HeaderName::from_static("x-real-ip"), value);
+			}
+		}
+	}
+
+	next.run(req).await
+}
+
+/// The client address of a request from the peer with the forwarded chain:
+/// walking the chain back from the right for up to `hops` addresses, as long
+/// as the address reached so far is a trusted proxy.
+pub(crate) fn resolve<F>(peer: IpAddr, chain: &[IpAddr], hops: usize, trusted: F) -> IpAddr
+where
+	F: Fn(&IpAddr) -> bool,
+{
+	let mut client = peer;
+	for addr in chain.iter().rev().take(hops) {
+		if !trusted(&client) {
+			break;
+		}
+
+		client = *addr;
+	}
+
+	client
+}
+
+/// The addresses of `X-Forwarded-For`, or the `for` parameters of `Forwarded`
+/// without it, from the client to the last proxy. The chain ends before the
+/// first address which does not parse, as anything before it is unreliable.
+pub(crate) fn forwarded_chain(headers: &HeaderMap) -> Vec<IpAddr> {
+	let x_forwarded_for: Vec<&str> = headers
+		.get_all("x-forwarded-for")
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.flat_map(|value| value.split(','))
+		.collect();
+
+	let entries: Vec<&str> = if x_forwarded_for.is_empty() {
+		headers
+			.get_all(header::FORWARDED)
+			.iter()
+			.filter_map(|value| value.to_str().ok())
+			.flat_map(|value| value.split(','))
+			.filter_map(|element| {
+				element.split(';').find_map(|pair| {
+					let (name, value) = pair.split_once('=')?;
+					name.trim().eq_ignore_ascii_case("for").then_some(value)
+				})
+			})
+			.collect()
+	} else {
+		x_forwarded_for
+	};
+
+	let parsed: Vec<Option<IpAddr>> = entries.into_iter().map(parse_addr).collect();
+	let valid = parsed
+		.iter()
+		.rev()
+		.take_while(|addr| addr.is_some())
+		.count();
+
+	let skip = parsed.len().saturating_sub(valid);
+	parsed.into_iter().skip(skip).flatten().collect()
+}
+
+/// An address of a forwarded header: `1.2.3.4`, `1.2.3.4:80`, `::1` or
+/// `"[::1]:80"`.
+fn parse_addr(entry: &str) -> Option<IpAddr> {
+	let entry = entry.trim().trim_matches('"');
+	entry
+		.parse::<IpAddr>()
+		.or_else(|_| entry.parse::<SocketAddr>().map(|addr| addr.ip()))
+		.ok()
+		.or_else(|| {
+			entry
+				.strip_prefix('[')
+				.and_then(|entry| entry.strip_suffix(']'))
+				.and_then(|entry| entry.parse().ok())
+		})
+}
@@ -22,7 +22,7 @@ use tower_http::{
 };
 use tracing::Level;
 
-use crate::{request, router};
+use crate::{client_ip, replica, request, router};
 
 const CONDUWUIT_CSP: &[&str; 5] = &[
 	"default-src 'none'",
@@ -59,6 +59,10 @@ pub(crate) fn build(services: &Arc<Services>) -> Result<(Router, Guard)> {
 				.on_response(DefaultOnResponse::new().level(Level::DEBUG)),
 		)
 		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), request::handle))
+		// the client address headers are sanitized before a replica forwards the
+		// request, so none forged by the client reach the primary
+		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), client_ip::handle))
+		.layer(axum::middleware::from_fn_with_state(Arc::clone(services), replica::forward))
 		.layer(SecureClientIpSource::ConnectInfo.into_extension())
 		.layer(SetResponseHeaderLayer::if_not_present(
 			HeaderName::from_static("origin-agent-cluster"), // https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Origin-Agent-Cluster
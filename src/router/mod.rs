@@ -1,8 +1,12 @@
+mod client_ip;
 mod layers;
+mod replica;
 mod request;
 mod router;
 mod run;
 mod serve;
+mod test;
+mod tests;
 
 extern crate conduwuit_core as conduwuit;
 
@@ -11,6 +15,7 @@ use std::{panic::AssertUnwindSafe, pin::Pin, sync::Arc};
 use conduwuit::{Error, Result, Server};
 use conduwuit_service::Services;
 use futures::{Future, FutureExt, TryFutureExt};
+pub use test::{test_server, test_server_with, TestServer};
 
 conduwuit::mod_ctor! {}
 conduwuit::mod_dtor! {}
@@ -0,0 +1,134 @@
+//! Read replica mode: the client reads a replica can answer from its secondary
+//! database are served locally and every other request is forwarded to the
+//! primary (`read_replica_primary`), along with the address of the client.
+
+use std::{
+	net::{IpAddr, SocketAddr},
+	sync::Arc,
+};
+
+use axum::{
+	body::{to_bytes, Body},
+	extract::{ConnectInfo, State},
+	response::Response,
+};
+use conduwuit::debug_warn;
+use conduwuit_service::Services;
+use http::{header, HeaderName, HeaderValue, Method, StatusCode, Uri};
+use ruma::ServerName;
+
+pub(crate) async fn forward(
+	State(services): State<Arc<Services>>,
+	req: http::Request<Body>,
+	next: axum::middleware::Next,
+) -> Result<Response, StatusCode> {
+	let config = &services.server.config;
+	let Some(primary) = config.read_replica_primary.as_ref() else {
+		return Ok(next.run(req).await);
+	};
+
+	if served_locally(&services, req.method(), req.uri()) {
+		return Ok(next.run(req).await);
+	}
+
+	// client_ip::handle left X-Real-IP only when a trusted proxy forwarded it
+	let client = req
+		.headers()
+		.get("x-real-ip")
+		.and_then(|value| value.to_str().ok()?.parse::<IpAddr>().ok())
+		.or_else(|| {
+			req.extensions()
+				.get::<ConnectInfo<SocketAddr>>()
+				.map(|ConnectInfo(addr)| addr.ip())
+		});
+
+	let (parts, body) = req.into_parts();
+	let body = to_bytes(body, config.max_request_size)
+		.await
+		.map_err(|_| StatusCode::PAYLOAD_TOO_LARGE)?;
+
+	let path = parts.uri.path_and_query().map_or("/", |path| path.as_str());
+
+	let mut headers = parts.headers;
+	headers.remove(header::HOST);
+	headers.remove(HeaderName::from_static("x-real-ip"));
+	if let Some(value) = client.and_then(|client| HeaderValue::from_str(&client.to_string()).ok())
+	{
+		headers.insert(HeaderName::from_static("x-forwarded-for"), value);
+	}
+
+	let url = format!("{}{path}", primary.as_str().trim_end_matches('/'));
+	let response = services
+		.client
+		.default
+		.request(parts.method, url)
+		.headers(headers)
+		.body(body)
+		.send()
+		.await
+		.map_err(|e| {
+			debug_warn!("Failed to forward request to the primary: {e}");
+			StatusCode::BAD_GATEWAY
+		})?;
+
+	let status = response.status();
+	let headers = response.headers().clone();
+	let body = response.bytes().await.map_err(|e| {
+		debug_warn!("Failed to receive response from the primary: {e}");
+		StatusCode::BAD_GATEWAY
+	})?;
+
+	let mut response = Response::new(Body::from(body));
+	*response.status_mut() = status;
+	*response.headers_mut() = headers;
+
+	Ok(response)
+}
+
+/// Whether the request only reads what the replica's database has, without
+/// writing to it (or with the writes skipped on a read-only database). A sync
+/// long-polling for new events is forwarded, as catching up with the primary
+/// does not wake it.
+fn served_locally(services: &Services, method: &Method, uri: &Uri) -> bool {
+	if method != Method::GET {
+		return false;
+	}
+
+	let segments: Vec<&str> = uri.path().trim_start_matches('/').split('/').collect();
+	match segments.as_slice() {
+		| ["_matrix", "client", "r0" | "v3", "sync"] => !long_polls(uri.query()),
+		| ["_matrix", "client", "r0" | "v3", endpoint @ ..] => matches!(
+			endpoint,
+			["rooms", _, "messages" | "state" | "members" | "joined_members"]
+				| ["rooms", _, "context" | "event", _]
+		),
+		// Remote media is fetched and cached on first download, which the
+		// primary has to do.
+		| ["_matrix", "media", "r0" | "v3", "download", server, ..]
+		| ["_matrix", "client", "v1", "media", "download", server, ..] =>
+			<&ServerName>::try_from(*server)
+				.is_ok_and(|server| services.globals.server_is_ours(server)),
+		| _ => false,
+	}
+}
+
+/// Whether a sync with the query would wait for new events: an incremental
+/// one, unless its timeout is 0 or it asks for the full state.
+fn long_polls(query: Option<&str>) -> bool {
+	let params: Vec<(&str, &str)> = query
+		.unwrap_or_default()
+		.split('&')
+		.filter_map(|param| param.split_once('='))
+		.collect();
+
+	let param = |name: &str| {
+		params
+			.iter()
+			.find(|(key, _)| *key == name)
+			.map(|(_, value)| *value)
+	};
+
+	param("since").is_some()
+		&& param("timeout") != Some("0")
+		&& param("full_state") != Some("true")
+}
@@ -1,12 +1,16 @@
-use std::sync::{atomic::Ordering, Arc};
+use std::{
+	sync::{atomic::Ordering, Arc},
+	time::Duration,
+};
 
 use axum::{
-	extract::State,
+	extract::{MatchedPath, State},
 	response::{IntoResponse, Response},
 };
-use conduwuit::{debug, debug_error, debug_warn, err, error, trace, Result};
+use conduwuit::{debug, debug_error, debug_warn, err, error, trace, warn, Error, Result};
 use conduwuit_service::Services;
 use http::{Method, StatusCode, Uri};
+use ruma::api::client::error::ErrorKind;
 
 #[tracing::instrument(
 	parent = None,
@@ -93,10 +97,44 @@ pub(crate) async fn handle(
 
 	let uri = req.uri().clone();
 	let method = req.method().clone();
-	let result = next.run(req).await;
+	let path = req.extensions().get::<MatchedPath>().cloned();
+	let result = match server.config.request_handler_timeout {
+		| 0 => next.run(req).await,
+		| budget => {
+			let budget = Duration::from_secs(budget);
+			match tokio::time::timeout(budget, next.run(req)).await {
+				| Ok(result) => result,
+				| Err(_) => handle_timeout(&services, &method, &uri, path.as_ref()),
+			}
+		},
+	};
+
 	handle_result(&method, &uri, result)
 }
 
+fn handle_timeout(
+	services: &Services,
+	method: &Method,
+	uri: &Uri,
+	path: Option<&MatchedPath>,
+) -> Response {
+	services
+		.server
+		.metrics
+		.requests_timeout
+		.fetch_add(1, Ordering::Relaxed);
+
+	let path = path.map(MatchedPath::as_str);
+	warn!(?method, ?uri, ?path, "request handler exceeded time budget");
+
+	Error::Request(
+		ErrorKind::Unknown,
+		"Request took too long to process.".into(),
+		StatusCode::GATEWAY_TIMEOUT,
+	)
+	.into_response()
+}
+
 fn handle_result(method: &Method, uri: &Uri, result: Response) -> Result<Response, StatusCode> {
 	let status = result.status();
 	let reason = status.canonical_reason().unwrap_or("Unknown Reason");
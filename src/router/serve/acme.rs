@@ -0,0 +1,110 @@
+//! Certificates obtained and renewed automatically from an ACME certificate
+//! authority. Its TLS-ALPN-01 challenges are answered on the listeners
+//! themselves.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use axum::Router;
+use axum_server::{from_tcp, Handle as ServerHandle};
+use conduwuit::{config::Listener, Result, Server};
+use futures::StreamExt;
+use rustls_acme::{caches::DirCache, AcmeConfig};
+use tokio::task::JoinSet;
+use tracing::{debug, error, info};
+
+use super::{keepalive, tcp};
+
+pub(super) async fn serve(
+	server: &Arc<Server>,
+	app: Router,
+	handle: ServerHandle,
+	listeners: Vec<Listener>,
+) -> Result {
+	let config = &server.config;
+	let acme = &config.tls.acme;
+	let domains = domains(server);
+	let cache_dir = acme
+		.cache_dir
+		.clone()
+		.unwrap_or_else(|| config.database_path.join("acme"));
+
+	info!(
+		"Obtaining TLS certificates for {domains:?} from {} by ACME, cached in {cache_dir:?}",
+		if acme.staging {
+			"Let's Encrypt (staging)"
+		} else {
+			"Let's Encrypt"
+		}
+	);
+
+	let mut state = AcmeConfig::new(domains)
+		.contact(
+			acme.contact
+				.iter()
+				.map(|contact| format!("mailto:{contact}")),
+		)
+		.cache(DirCache::new(cache_dir))
+		.directory_lets_encrypt(!acme.staging)
+		.state();
+
+	let acceptor = state.axum_acceptor(state.default_rustls_config());
+
+	// Orders and renews the certificates for as long as the server runs.
+	let ordering = async move {
+		while let Some(event) = state.next().await {
+			match event {
+				| Ok(event) => debug!(?event, "ACME"),
+				| Err(e) => error!("Failed to obtain TLS certificate by ACME: {e}"),
+			}
+		}
+	};
+
+	let orderer = server.runtime().spawn(ordering);
+
+	let addrs: Vec<SocketAddr> = listeners.iter().map(|listener| listener.addr).collect();
+	let mut join_set = JoinSet::new();
+	let app = app.into_make_service_with_connect_info::<SocketAddr>();
+	for listener in &listeners {
+		let mut listener = from_tcp(tcp::bind(listener)?).acceptor(acceptor.clone());
+		keepalive(listener.http_builder(), &server.config);
+		join_set.spawn_on(listener.handle(handle.clone()).serve(app.clone()), server.runtime());
+	}
+
+	info!("Listening on {addrs:?} with TLS certificates obtained by ACME");
+
+	while join_set.join_next().await.is_some() {}
+	orderer.abort();
+
+	Ok(())
+}
+
+/// The configured domains, or else the server_name and the hosts clients and
+/// servers are delegated to.
+fn domains(server: &Server) -> Vec<String> {
+	let config = &server.config;
+	if !config.tls.acme.domains.is_empty() {
+		return config.tls.acme.domains.clone();
+	}
+
+	let mut domains = vec![config.server_name.host().to_owned()];
+	let client = config
+		.well_known
+		.client
+		.as_ref()
+		.and_then(|client| client.host_str())
+		.map(ToOwned::to_owned);
+
+	let server = config
+		.well_known
+		.server
+		.as_ref()
+		.map(|server| server.host().to_owned());
+
+	for domain in [client, server].into_iter().flatten() {
+		if !domains.contains(&domain) {
+			domains.push(domain);
+		}
+	}
+
+	domains
+}
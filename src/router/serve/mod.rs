@@ -1,13 +1,20 @@
+#[cfg(feature = "direct_tls")]
+mod acme;
 mod plain;
+mod tcp;
 #[cfg(feature = "direct_tls")]
 mod tls;
 mod unix;
 
-use std::sync::Arc;
+use std::{sync::Arc, time::Duration};
 
 use axum_server::Handle as ServerHandle;
-use conduwuit::Result;
+use conduwuit::{Config, Result};
 use conduwuit_service::Services;
+use hyper_util::{
+	rt::{TokioExecutor, TokioTimer},
+	server::conn::auto::Builder,
+};
 use tokio::sync::broadcast;
 
 use super::layers;
@@ -20,14 +27,14 @@ pub(super) async fn serve(
 ) -> Result<()> {
 	let server = &services.server;
 	let config = &server.config;
-	let addrs = config.get_bind_addrs();
+	let listeners = config.get_listeners();
 	let (app, _guard) = layers::build(&services)?;
 
 	if cfg!(unix) && config.unix_socket_path.is_some() {
 		unix::serve(server, app, shutdown).await
-	} else if config.tls.certs.is_some() {
+	} else if config.tls.certs.is_some() || config.tls.acme.enable {
 		#[cfg(feature = "direct_tls")]
-		return tls::serve(server, app, handle, addrs).await;
+		return tls::serve(server, app, handle, listeners).await;
 
 		#[cfg(not(feature = "direct_tls"))]
 		return conduwuit::Err!(Config(
@@ -35,6 +42,23 @@ pub(super) async fn serve(
 			"conduwuit was not built with direct TLS support (\"direct_tls\")"
 		));
 	} else {
-		plain::serve(server, app, handle, addrs).await
+		plain::serve(server, app, handle, listeners).await
+	}
+}
+
+/// Apply the keepalive options of the config to the connections of clients.
+fn keepalive(builder: &mut Builder<TokioExecutor>, config: &Config) {
+	builder.http1().keep_alive(config.client_keepalive);
+	if config.client_keepalive_timeout > 0 {
+		let timeout = Duration::from_secs(config.client_keepalive_timeout);
+		builder
+			.http1()
+			.timer(TokioTimer::new())
+			.header_read_timeout(timeout);
+		builder
+			.http2()
+			.timer(TokioTimer::new())
+			.keep_alive_interval(timeout)
+			.keep_alive_timeout(timeout);
 	}
 }
@@ -4,21 +4,25 @@ use std::{
 };
 
 use axum::Router;
-use axum_server::{bind, Handle as ServerHandle};
-use conduwuit::{debug_info, info, Result, Server};
+use axum_server::{from_tcp, Handle as ServerHandle};
+use conduwuit::{config::Listener, debug_info, info, Result, Server};
 use tokio::task::JoinSet;
 
+use super::{keepalive, tcp};
+
 pub(super) async fn serve(
 	server: &Arc<Server>,
 	app: Router,
 	handle: ServerHandle,
-	addrs: Vec<SocketAddr>,
+	listeners: Vec<Listener>,
 ) -> Result<()> {
+	let addrs: Vec<SocketAddr> = listeners.iter().map(|listener| listener.addr).collect();
 	let app = app.into_make_service_with_connect_info::<SocketAddr>();
 	let mut join_set = JoinSet::new();
-	for addr in &addrs {
-		join_set
-			.spawn_on(bind(*addr).handle(handle.clone()).serve(app.clone()), server.runtime());
+	for listener in &listeners {
+		let mut listener = from_tcp(tcp::bind(listener)?);
+		keepalive(listener.http_builder(), &server.config);
+		join_set.spawn_on(listener.handle(handle.clone()).serve(app.clone()), server.runtime());
 	}
 
 	info!("Listening on {addrs:?}");
@@ -0,0 +1,48 @@
+use std::net::TcpListener;
+
+use conduwuit::{config::Listener, debug, err, Result};
+use socket2::{Domain, Protocol, Socket, Type};
+
+const BACKLOG: i32 = 1024;
+
+/// Bind a listening socket with the options configured for it. The socket is
+/// built by hand rather than by the server so options which must precede the
+/// bind (IPV6_V6ONLY, SO_BINDTODEVICE) can be applied.
+pub(super) fn bind(listener: &Listener) -> Result<TcpListener> {
+	let Listener { addr, ipv6_only, interface } = listener;
+	let socket = Socket::new(Domain::for_address(*addr), Type::STREAM, Some(Protocol::TCP))?;
+
+	socket.set_reuse_address(true)?;
+	socket.set_nonblocking(true)?;
+	if let Some(ipv6_only) = ipv6_only {
+		socket.set_only_v6(*ipv6_only)?;
+	}
+
+	if let Some(interface) = interface {
+		bind_device(&socket, interface)?;
+	}
+
+	socket
+		.bind(&(*addr).into())
+		.map_err(|e| err!("Failed to bind {addr}: {e}"))?;
+
+	socket.listen(BACKLOG)?;
+	debug!(?addr, ?ipv6_only, ?interface, "Bound listener");
+
+	Ok(socket.into())
+}
+
+#[cfg(target_os = "linux")]
+fn bind_device(socket: &Socket, interface: &str) -> Result {
+	socket
+		.bind_device(Some(interface.as_bytes()))
+		.map_err(|e| err!("Failed to bind to interface {interface:?}: {e}"))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn bind_device(_socket: &Socket, interface: &str) -> Result {
+	conduwuit::Err!(Config(
+		"listen",
+		"Binding to interface {interface:?} is only supported on Linux."
+	))
+}
@@ -1,22 +1,39 @@
-use std::{net::SocketAddr, sync::Arc};
+use std::{
+	net::SocketAddr,
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
 
 use axum::Router;
 use axum_server::Handle as ServerHandle;
 use axum_server_dual_protocol::{
-	axum_server::{bind_rustls, tls_rustls::RustlsConfig},
+	axum_server::{from_tcp_rustls, tls_rustls::RustlsConfig},
 	ServerExt,
 };
-use conduwuit::{err, Result, Server};
-use tokio::task::JoinSet;
-use tracing::{debug, info, warn};
+use conduwuit::{config::Listener, err, Result, Server};
+use tokio::{fs, sync::broadcast::error::RecvError, task::JoinSet, time::sleep};
+use tracing::{debug, error, info, warn};
+
+use super::{acme, keepalive, tcp};
 
 pub(super) async fn serve(
 	server: &Arc<Server>,
 	app: Router,
 	handle: ServerHandle,
-	addrs: Vec<SocketAddr>,
+	listeners: Vec<Listener>,
 ) -> Result {
 	let tls = &server.config.tls;
+
+	// we use ring for ruma and hashing state, but aws-lc-rs is the new default.
+	// without this, TLS mode will panic.
+	rustls::crypto::aws_lc_rs::default_provider()
+		.install_default()
+		.expect("failed to initialise aws-lc-rs rustls crypto provider");
+
+	if tls.acme.enable {
+		return acme::serve(server, app, handle, listeners).await;
+	}
+
 	let certs = tls
 		.certs
 		.as_ref()
@@ -26,12 +43,6 @@ pub(super) async fn serve(
 		.as_ref()
 		.ok_or(err!(Config("tls.key", "Missing required value in tls config section")))?;
 
-	// we use ring for ruma and hashing state, but aws-lc-rs is the new default.
-	// without this, TLS mode will panic.
-	rustls::crypto::aws_lc_rs::default_provider()
-		.install_default()
-		.expect("failed to initialise aws-lc-rs rustls crypto provider");
-
 	debug!("Using direct TLS. Certificate path {certs} and certificate private key path {key}",);
 	info!(
 		"Note: It is strongly recommended that you use a reverse proxy instead of running \
@@ -39,26 +50,25 @@ pub(super) async fn serve(
 	);
 	let conf = RustlsConfig::from_pem_file(certs, key).await?;
 
+	let addrs: Vec<SocketAddr> = listeners.iter().map(|listener| listener.addr).collect();
 	let mut join_set = JoinSet::new();
 	let app = app.into_make_service_with_connect_info::<SocketAddr>();
 	if tls.dual_protocol {
-		for addr in &addrs {
-			join_set.spawn_on(
-				axum_server_dual_protocol::bind_dual_protocol(*addr, conf.clone())
-					.set_upgrade(false)
-					.handle(handle.clone())
-					.serve(app.clone()),
-				server.runtime(),
-			);
+		for listener in &listeners {
+			let listener = tcp::bind(listener)?;
+			let mut listener =
+				axum_server_dual_protocol::from_tcp_dual_protocol(listener, conf.clone())
+					.set_upgrade(false);
+			keepalive(listener.http_builder(), &server.config);
+			join_set
+				.spawn_on(listener.handle(handle.clone()).serve(app.clone()), server.runtime());
 		}
 	} else {
-		for addr in &addrs {
-			join_set.spawn_on(
-				bind_rustls(*addr, conf.clone())
-					.handle(handle.clone())
-					.serve(app.clone()),
-				server.runtime(),
-			);
+		for listener in &listeners {
+			let mut listener = from_tcp_rustls(tcp::bind(listener)?, conf.clone());
+			keepalive(listener.http_builder(), &server.config);
+			join_set
+				.spawn_on(listener.handle(handle.clone()).serve(app.clone()), server.runtime());
 		}
 	}
 
@@ -71,7 +81,57 @@ pub(super) async fn serve(
 		info!("Listening on {addrs:?} with TLS certificate {certs}");
 	}
 
+	let reloading = reload(server.clone(), conf, certs.clone(), key.clone());
+	let reloader = server.runtime().spawn(reloading);
+
 	while join_set.join_next().await.is_some() {}
+	reloader.abort();
 
 	Ok(())
 }
+
+/// Reload the certificate on SIGHUP, or when the certificate or private key
+/// files have changed since it was last loaded. A failed reload (e.g. while a
+/// renewal is still writing the files) keeps the current certificate and is
+/// retried at the next check.
+async fn reload(server: Arc<Server>, conf: RustlsConfig, certs: String, key: String) {
+	let interval = Duration::from_secs(server.config.tls.reload_interval);
+	let mut signals = server.signal.subscribe();
+	let mut loaded = modified(&certs, &key).await;
+	while server.running() {
+		tokio::select! {
+			sig = signals.recv() => match sig {
+				Ok("SIGHUP") => info!("Received SIGHUP, reloading TLS certificate {certs}"),
+				Ok(_) | Err(RecvError::Lagged(_)) => continue,
+				Err(RecvError::Closed) => break,
+			},
+			() = sleep(interval), if !interval.is_zero() => {
+				if modified(&certs, &key).await == loaded {
+					continue;
+				}
+
+				info!("TLS certificate {certs} or its private key changed, reloading");
+			},
+		}
+
+		let current = modified(&certs, &key).await;
+		match conf.reload_from_pem_file(&certs, &key).await {
+			| Ok(()) => {
+				loaded = current;
+				info!("Reloaded TLS certificate {certs}");
+			},
+			| Err(e) => error!("Failed to reload TLS certificate {certs}: {e}"),
+		}
+	}
+}
+
+async fn modified(certs: &str, key: &str) -> [Option<SystemTime>; 2] {
+	[mtime(certs).await, mtime(key).await]
+}
+
+async fn mtime(path: &str) -> Option<SystemTime> {
+	fs::metadata(path)
+		.await
+		.and_then(|metadata| metadata.modified())
+		.ok()
+}
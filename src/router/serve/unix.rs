@@ -42,7 +42,8 @@ pub(super) async fn serve(
 	let mut tasks = JoinSet::<()>::new();
 	let executor = TokioExecutor::new();
 	let app = app.into_make_service_with_connect_info::<net::SocketAddr>();
-	let builder = server::conn::auto::Builder::new(executor);
+	let mut builder = server::conn::auto::Builder::new(executor);
+	super::keepalive(&mut builder, &server.config);
 	let listener = init(server).await?;
 	while server.running() {
 		let app = app.clone();
@@ -0,0 +1,125 @@
+//! A complete server for integration tests. It listens on an ephemeral port of
+//! the loopback interface and keeps its database in memory, so any number of
+//! them can run side by side.
+
+use std::{
+	net::{Ipv4Addr, SocketAddr, TcpListener},
+	path::PathBuf,
+	sync::Arc,
+	time::Duration,
+};
+
+use conduwuit::{
+	config::{Config, Figment},
+	err,
+	log::{capture, Log, LogLevelReloadHandles},
+	utils, Err, Result, Server,
+};
+use conduwuit_service::Services;
+use tokio::{net::TcpStream, runtime, task::JoinHandle, time::sleep};
+
+use crate::run;
+
+/// A running test server; stop it with [`TestServer::shutdown`].
+pub struct TestServer {
+	pub server: Arc<Server>,
+	pub services: Arc<Services>,
+	pub addr: SocketAddr,
+	dir: PathBuf,
+	task: JoinHandle<Result>,
+}
+
+/// How long the server may take to start listening.
+const LISTEN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start a server with the test configuration.
+pub async fn test_server() -> Result<TestServer> { test_server_with(Figment::new()).await }
+
+/// Start a server with the options given overriding the test configuration,
+/// e.g. `Figment::new().merge(("allow_registration", true))`.
+pub async fn test_server_with(options: Figment) -> Result<TestServer> {
+	let addr = ephemeral_addr()?;
+	let dir = std::env::temp_dir().join(format!("conduwuit-test-{}", utils::random_string(16)));
+	let raw_config = Figment::new()
+		.merge(("server_name", "localhost"))
+		.merge(("database_path", &dir))
+		.merge(("database_backend", "memory"))
+		.merge(("address", addr.ip()))
+		.merge(("port", addr.port()))
+		.merge(("startup_netburst", false))
+		.merge(("ratelimit.enable", false))
+		.merge(options);
+
+	let config = Config::new(&raw_config)?;
+	config.check()?;
+
+	let log = Log {
+		reload: LogLevelReloadHandles::default(),
+		capture: Arc::new(capture::State::new()),
+	};
+
+	let server = Arc::new(Server::new(config, Some(runtime::Handle::current()), log));
+	let services = run::start(server.clone()).await?;
+	let task = server.runtime().spawn(run::run(services.clone()));
+	let test_server = TestServer { server, services, addr, dir, task };
+	if let Err(e) = test_server.listening().await {
+		test_server.shutdown().await.ok();
+		return Err(e);
+	}
+
+	Ok(test_server)
+}
+
+impl TestServer {
+	/// The URL of a path on the server, e.g. `/_matrix/client/versions`.
+	#[must_use]
+	pub fn url(&self, path: &str) -> String { format!("http://{}{path}", self.addr) }
+
+	/// Execute an admin command (without the `!admin` prefix) and return its
+	/// output.
+	pub async fn admin(&self, command: &str) -> Result<String> {
+		match self
+			.services
+			.admin
+			.command_in_place(command.to_owned(), None)
+			.await
+		{
+			| Ok(output) => Ok(output
+				.map(|output| output.body().to_owned())
+				.unwrap_or_default()),
+			| Err(output) => Err!("Admin command failed: {}", output.body()),
+		}
+	}
+
+	/// Stop the server and remove its directory.
+	pub async fn shutdown(self) -> Result {
+		let Self { server, services, dir, task, .. } = self;
+		server.shutdown()?;
+		task.await
+			.map_err(|e| err!("Test server panicked: {e}"))??;
+		run::stop(services).await?;
+		if dir.exists() {
+			std::fs::remove_dir_all(&dir)?;
+		}
+
+		Ok(())
+	}
+
+	async fn listening(&self) -> Result {
+		let wait = async {
+			while TcpStream::connect(self.addr).await.is_err() {
+				sleep(Duration::from_millis(10)).await;
+			}
+		};
+
+		tokio::time::timeout(LISTEN_TIMEOUT, wait)
+			.await
+			.map_err(|_| err!("Test server did not start listening on {}", self.addr))
+	}
+}
+
+/// A free port of the loopback interface. It may be taken again before the
+/// server binds it, which is unlikely enough for tests.
+fn ephemeral_addr() -> Result<SocketAddr> {
+	Ok(TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?.local_addr()?)
+}
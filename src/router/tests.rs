@@ -0,0 +1,88 @@
+#![cfg(test)]
+
+use std::net::IpAddr;
+
+use http::{HeaderMap, HeaderValue};
+
+use crate::{
+	client_ip::{forwarded_chain, resolve},
+	test_server,
+};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn serves_client_versions() {
+	let server = test_server().await.expect("test server started");
+	let response = server
+		.services
+		.client
+		.default
+		.get(server.url("/_matrix/client/versions"))
+		.send()
+		.await
+		.expect("request sent");
+
+	assert!(response.status().is_success(), "versions request failed");
+
+	server.shutdown().await.expect("test server stopped");
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn executes_admin_commands() {
+	let server = test_server().await.expect("test server started");
+	let output = server
+		.admin("server uptime")
+		.await
+		.expect("admin command succeeded");
+
+	assert!(output.ends_with('.'), "unexpected uptime output: {output:?}");
+
+	server.shutdown().await.expect("test server stopped");
+}
+
+fn ip(addr: &str) -> IpAddr { addr.parse().expect("valid IP address") }
+
+fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+	let mut headers = HeaderMap::new();
+	for (name, value) in pairs {
+		headers.append(*name, HeaderValue::from_static(value));
+	}
+
+	headers
+}
+
+#[test]
+fn client_ip_untrusted_peer() {
+	let chain = [ip("1.2.3.4")];
+	let client = resolve(ip("5.6.7.8"), &chain, 1, |_| false);
+
+	assert_eq!(client, ip("5.6.7.8"));
+}
+
+#[test]
+fn client_ip_trusted_hops() {
+	let trusted = |addr: &IpAddr| addr.is_loopback() || *addr == ip("10.0.0.2");
+	let chain = [ip("6.6.6.6"), ip("1.2.3.4"), ip("10.0.0.2")];
+
+	assert_eq!(resolve(ip("127.0.0.1"), &chain, 1, trusted), ip("10.0.0.2"));
+	assert_eq!(resolve(ip("127.0.0.1"), &chain, 2, trusted), ip("1.2.3.4"));
+	assert_eq!(resolve(ip("127.0.0.1"), &chain, 3, trusted), ip("1.2.3.4"));
+	assert_eq!(resolve(ip("127.0.0.1"), &chain, 0, trusted), ip("127.0.0.1"));
+}
+
+#[test]
+fn client_ip_forwarded_chain() {
+	let chain = forwarded_chain(&headers(&[
+		("x-forwarded-for", "1.2.3.4, 10.0.0.1"),
+		("x-forwarded-for", "10.0.0.2:8080"),
+	]));
+	assert_eq!(chain, [ip("1.2.3.4"), ip("10.0.0.1"), ip("10.0.0.2")]);
+
+	let chain = forwarded_chain(&headers(&[(
+		"forwarded",
+		"for=1.2.3.4;proto=https, for=\"[2001:db8::1]:4711\"",
+	)]));
+	assert_eq!(chain, [ip("1.2.3.4"), ip("2001:db8::1")]);
+
+	let chain = forwarded_chain(&headers(&[("x-forwarded-for", "1.2.3.4, unknown, 10.0.0.1")]));
+	assert_eq!(chain, [ip("10.0.0.1")]);
+}
@@ -2,8 +2,8 @@ use std::sync::Arc;
 
 use conduwuit::{
 	err, implement,
-	utils::{result::LogErr, stream::TryIgnore, ReadyExt},
-	Err, Result,
+	utils::{result::LogErr, stream::TryIgnore, MutexMap, ReadyExt},
+	Err, Result, Server,
 };
 use database::{Deserialized, Handle, Interfix, Json, Map};
 use futures::{Stream, StreamExt, TryFutureExt};
@@ -13,36 +13,51 @@ use ruma::{
 		GlobalAccountDataEventType, RoomAccountDataEventType,
 	},
 	serde::Raw,
-	RoomId, UserId,
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 use serde::Deserialize;
 
-use crate::{globals, Dep};
+use crate::{bus, globals, pusher, sending, sending::ROOM_EDUS_EVENT_TYPE, Dep};
 
 pub struct Service {
 	services: Services,
 	db: Data,
+	size_mutex: MutexMap<OwnedUserId, ()>,
 }
 
 struct Data {
 	roomuserdataid_accountdata: Arc<Map>,
 	roomusertype_roomuserdataid: Arc<Map>,
+	userid_accountdatasize: Arc<Map>,
 }
 
 struct Services {
+	server: Arc<Server>,
+	bus: Dep<bus::Service>,
 	globals: Dep<globals::Service>,
+	pusher: Dep<pusher::Service>,
+	sending: Dep<sending::Service>,
 }
 
+/// Longest account data event type accepted from clients.
+const MAX_TYPE_LENGTH: usize = 255;
+
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			services: Services {
+				server: args.server.clone(),
+				bus: args.depend::<bus::Service>("bus"),
 				globals: args.depend::<globals::Service>("globals"),
+				pusher: args.depend::<pusher::Service>("pusher"),
+				sending: args.depend::<sending::Service>("sending"),
 			},
 			db: Data {
 				roomuserdataid_accountdata: args.db["roomuserdataid_accountdata"].clone(),
 				roomusertype_roomuserdataid: args.db["roomusertype_roomuserdataid"].clone(),
+				userid_accountdatasize: args.db["userid_accountdatasize"].clone(),
 			},
+			size_mutex: MutexMap::new(),
 		}))
 	}
 
@@ -64,6 +79,8 @@ pub async fn update(
 		return Err!(Request(InvalidParam("Account data doesn't have all required fields.")));
 	}
 
+	// The user's total is read and written back below
+	let size_lock = self.size_mutex.lock(user_id).await;
 	let count = self.services.globals.next_count().unwrap();
 	let roomuserdataid = (room_id, user_id, count, &event_type);
 	self.db
@@ -75,13 +92,110 @@ pub async fn update(
 	self.db.roomusertype_roomuserdataid.put(key, roomuserdataid);
 
 	// Remove old entry
+	let mut total = self.total_size(user_id).await;
 	if let Ok(prev) = prev {
+		if let Ok(prev_data) = self.db.roomuserdataid_accountdata.get(&prev).await {
+			total = total.saturating_sub(prev_data.len());
+		}
+
 		self.db.roomuserdataid_accountdata.remove(&prev);
 	}
 
+	self.set_total_size(user_id, total.saturating_add(stored_size(data)?));
+	drop(size_lock);
+
+	if room_id.is_none()
+		&& event_type.to_cow_str() == GlobalAccountDataEventType::PushRules.to_cow_str()
+	{
+		self.services.pusher.invalidate_rules(user_id);
+	}
+
+	if let Some(room_id) = room_id {
+		if *user_id == *self.services.globals.server_user
+			&& event_type.to_cow_str() == ROOM_EDUS_EVENT_TYPE
+		{
+			self.services
+				.sending
+				.set_cached_room_edus(room_id, &data["content"]);
+		}
+	}
+
+	self.services.bus.wake_syncs(user_id);
+
+	Ok(())
+}
+
+/// Check account data a client is setting has a well-formed type and is
+/// within the configured size limits. Sizes are of the event as stored, the
+/// same bytes the user's total is kept in.
+#[implement(Service)]
+pub async fn check_limits(
+	&self,
+	room_id: Option<&RoomId>,
+	user_id: &UserId,
+	event_type: &str,
+	data: &serde_json::Value,
+) -> Result {
+	let valid_type = !event_type.is_empty()
+		&& event_type.len() <= MAX_TYPE_LENGTH
+		&& event_type.chars().all(|c| c.is_ascii_graphic());
+
+	if !valid_type {
+		return Err!(Request(InvalidParam("Invalid account data type {event_type:?}.")));
+	}
+
+	let config = &self.services.server.config;
+	let size = stored_size(data)?;
+	if config.max_account_data_size > 0 && size > config.max_account_data_size {
+		return Err!(Request(TooLarge(
+			"Account data exceeds the maximum size of {} bytes.",
+			config.max_account_data_size
+		)));
+	}
+
+	if config.max_account_data_total > 0 {
+		let replaced = self
+			.get_raw(room_id, user_id, event_type)
+			.await
+			.map_or(0, |prev| prev.len());
+
+		let total = self
+			.total_size(user_id)
+			.await
+			.saturating_sub(replaced)
+			.saturating_add(size);
+
+		if total > config.max_account_data_total {
+			return Err!(Request(TooLarge(
+				"Account data would exceed the maximum total of {} bytes for this user.",
+				config.max_account_data_total
+			)));
+		}
+	}
+
 	Ok(())
 }
 
+/// Bytes of account data stored for the user, global and in all rooms.
+#[implement(Service)]
+pub async fn total_size(&self, user_id: &UserId) -> usize {
+	self.db
+		.userid_accountdatasize
+		.get(user_id)
+		.await
+		.deserialized::<u64>()
+		.map_or(0, |size| size.try_into().unwrap_or(usize::MAX))
+}
+
+/// Bytes the account data event takes as stored.
+fn stored_size(data: &serde_json::Value) -> Result<usize> { Ok(serde_json::to_vec(data)?.len()) }
+
+#[implement(Service)]
+pub(crate) fn set_total_size(&self, user_id: &UserId, size: usize) {
+	let size: u64 = size.try_into().unwrap_or(u64::MAX);
+	self.db.userid_accountdatasize.raw_put(user_id, size);
+}
+
 /// Searches the room account data for a specific kind.
 #[implement(Service)]
 pub async fn get_global<T>(&self, user_id: &UserId, kind: GlobalAccountDataEventType) -> Result<T>
@@ -124,6 +238,26 @@ pub async fn get_raw(
 		.await
 }
 
+/// The rooms in which the user has room account data of the kind.
+#[implement(Service)]
+pub fn rooms_with<'a>(
+	&'a self,
+	user_id: &'a UserId,
+	kind: &'a str,
+) -> impl Stream<Item = OwnedRoomId> + Send + 'a {
+	type Key<'a> = (&'a str, &'a UserId, &'a str);
+
+	self.db
+		.roomusertype_roomuserdataid
+		.keys()
+		.ignore_err()
+		.ready_filter_map(move |(room_id, user, key_kind): Key<'_>| {
+			(user == user_id && key_kind == kind)
+				.then(|| RoomId::parse(room_id).ok())
+				.flatten()
+		})
+}
+
 /// Returns all changes to the account data that happened after `since`.
 #[implement(Service)]
 pub fn changes_since<'a>(
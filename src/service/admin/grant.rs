@@ -102,7 +102,12 @@ pub async fn make_user_admin(&self, user_id: &UserId) -> Result<()> {
 }
 
 #[implement(super::Service)]
-async fn set_room_tag(&self, room_id: &RoomId, user_id: &UserId, tag: &str) -> Result<()> {
+pub(super) async fn set_room_tag(
+	&self,
+	room_id: &RoomId,
+	user_id: &UserId,
+	tag: &str,
+) -> Result<()> {
 	let mut event = self
 		.services
 		.account_data
@@ -1,6 +1,8 @@
 pub mod console;
 mod create;
 mod grant;
+mod motd;
+mod notice;
 mod startup;
 
 use std::{
@@ -14,8 +16,10 @@ use conduwuit::{
 	debug, err, error, error::default_log, pdu::PduBuilder, Error, PduEvent, Result, Server,
 };
 pub use create::create_admin_room;
+use database::Map;
 use futures::{FutureExt, TryFutureExt};
 use loole::{Receiver, Sender};
+pub use motd::Motd;
 use ruma::{
 	events::room::message::{Relation, RoomMessageEventContent},
 	OwnedEventId, OwnedRoomId, RoomId, UserId,
@@ -25,18 +29,27 @@ use tokio::sync::RwLock;
 use crate::{account_data, globals, rooms, rooms::state::RoomMutexGuard, Dep};
 
 pub struct Service {
+	db: Data,
 	services: Services,
 	channel: (Sender<CommandInput>, Receiver<CommandInput>),
 	pub handle: RwLock<Option<Processor>>,
 	pub complete: StdRwLock<Option<Completer>>,
+	motd: StdRwLock<Option<Motd>>,
 	#[cfg(feature = "console")]
 	pub console: Arc<console::Console>,
 }
 
+struct Data {
+	global: Arc<Map>,
+	userid_motdsent: Arc<Map>,
+	userid_noticeroomid: Arc<Map>,
+}
+
 struct Services {
 	server: Arc<Server>,
 	globals: Dep<globals::Service>,
 	alias: Dep<rooms::alias::Service>,
+	short: Dep<rooms::short::Service>,
 	timeline: Dep<rooms::timeline::Service>,
 	state: Dep<rooms::state::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
@@ -78,10 +91,16 @@ const COMMAND_QUEUE_LIMIT: usize = 512;
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			db: Data {
+				global: args.db["global"].clone(),
+				userid_motdsent: args.db["userid_motdsent"].clone(),
+				userid_noticeroomid: args.db["userid_noticeroomid"].clone(),
+			},
 			services: Services {
 				server: args.server.clone(),
 				globals: args.depend::<globals::Service>("globals"),
 				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
@@ -91,6 +110,7 @@ impl crate::Service for Service {
 			channel: loole::bounded(COMMAND_QUEUE_LIMIT),
 			handle: RwLock::new(None),
 			complete: StdRwLock::new(None),
+			motd: StdRwLock::new(motd::load_motd(&args.db["global"])),
 			#[cfg(feature = "console")]
 			console: console::Console::new(&args),
 		}))
@@ -131,6 +151,8 @@ impl crate::Service for Service {
 		}
 	}
 
+	fn queue_len(&self) -> Option<usize> { Some(self.channel.0.len()) }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -0,0 +1,76 @@
+//! The message of the day: an announcement set by the admins at runtime, e.g.
+//! of maintenance, shown in the client well-known and, when asked, sent to
+//! each local user as a server notice the next time they log in.
+
+use conduwuit::{implement, Result};
+use database::{Deserialized, Json, Map};
+use ruma::{events::room::message::RoomMessageEventContent, UserId};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Motd {
+	pub message: String,
+
+	/// Whether users are sent it as a server notice on their next login.
+	pub notice: bool,
+
+	/// The global count when it was set, telling apart the messages of the day
+	/// a user was sent.
+	pub count: u64,
+}
+
+const MOTD: &[u8] = b"motd";
+
+pub(super) fn load_motd(global: &Map) -> Option<Motd> {
+	global.get_blocking(MOTD).deserialized().ok()
+}
+
+#[implement(super::Service)]
+#[must_use]
+pub fn motd(&self) -> Option<Motd> { self.motd.read().expect("locked").clone() }
+
+#[implement(super::Service)]
+pub fn set_motd(&self, message: String, notice: bool) -> Result {
+	let motd = Motd {
+		message,
+		notice,
+		count: self.services.globals.next_count()?,
+	};
+
+	self.db.global.raw_put(MOTD, Json(&motd));
+	*self.motd.write().expect("locked") = Some(motd);
+
+	Ok(())
+}
+
+/// Remove the message of the day. Returns whether there was one.
+#[implement(super::Service)]
+pub fn clear_motd(&self) -> bool {
+	self.db.global.remove(MOTD);
+	self.motd.write().expect("locked").take().is_some()
+}
+
+/// Send the message of the day to the user as a server notice, unless it is
+/// not to be or already was.
+#[implement(super::Service)]
+pub async fn send_motd_notice(&self, user_id: &UserId) -> Result {
+	let Some(motd) = self.motd().filter(|motd| motd.notice) else {
+		return Ok(());
+	};
+
+	let sent: Option<u64> = self
+		.db
+		.userid_motdsent
+		.get(user_id)
+		.await
+		.deserialized()
+		.ok();
+
+	if sent.is_some_and(|sent| sent >= motd.count) {
+		return Ok(());
+	}
+
+	self.db.userid_motdsent.raw_put(user_id, motd.count);
+	self.send_server_notice(user_id, RoomMessageEventContent::notice_markdown(motd.message))
+		.await
+}
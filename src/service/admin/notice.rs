@@ -0,0 +1,141 @@
+use std::collections::BTreeMap;
+
+use conduwuit::{debug_info, implement, pdu::PduBuilder, Result};
+use database::Deserialized;
+use ruma::{
+	events::room::{
+		create::RoomCreateEventContent,
+		guest_access::{GuestAccess, RoomGuestAccessEventContent},
+		history_visibility::{HistoryVisibility, RoomHistoryVisibilityEventContent},
+		join_rules::{JoinRule, RoomJoinRulesEventContent},
+		member::{MembershipState, RoomMemberEventContent},
+		message::RoomMessageEventContent,
+		name::RoomNameEventContent,
+		power_levels::RoomPowerLevelsEventContent,
+	},
+	OwnedRoomId, RoomId, RoomVersionId, UserId,
+};
+
+/// Tag of the server notices room of a user, shown apart by clients.
+const SERVER_NOTICE_TAG: &str = "m.server_notice";
+
+/// Send a notice to a local user from the server user, in the server notices
+/// room of the user. The room is created, and the user invited to it, the
+/// first time or when the user left it.
+#[implement(super::Service)]
+pub async fn send_server_notice(
+	&self,
+	user_id: &UserId,
+	content: RoomMessageEventContent,
+) -> Result {
+	let room_id = match self.server_notice_room(user_id).await {
+		| Some(room_id) => room_id,
+		| None => self.create_server_notice_room(user_id).await?,
+	};
+
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+	self.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder::timeline(&content),
+			&self.services.globals.server_user,
+			&room_id,
+			&state_lock,
+		)
+		.await?;
+
+	Ok(())
+}
+
+/// The server notices room of the user, unless it left it.
+#[implement(super::Service)]
+async fn server_notice_room(&self, user_id: &UserId) -> Option<OwnedRoomId> {
+	let room_id: OwnedRoomId = self
+		.db
+		.userid_noticeroomid
+		.get(user_id)
+		.await
+		.deserialized()
+		.ok()?;
+
+	let state_cache = &self.services.state_cache;
+	(state_cache.is_joined(user_id, &room_id).await
+		|| state_cache.is_invited(user_id, &room_id).await)
+		.then_some(room_id)
+}
+
+#[implement(super::Service)]
+async fn create_server_notice_room(&self, user_id: &UserId) -> Result<OwnedRoomId> {
+	let server_user = &self.services.globals.server_user;
+	let room_id = RoomId::new(self.services.globals.server_name());
+	let room_version = &self.services.server.config.default_room_version;
+
+	let _short_id = self
+		.services
+		.short
+		.get_or_create_shortroomid(&room_id)
+		.await;
+
+	let state_lock = self.services.state.mutex.lock(&room_id).await;
+
+	let create_content = {
+		use RoomVersionId::*;
+		match room_version {
+			| V1 | V2 | V3 | V4 | V5 | V6 | V7 | V8 | V9 | V10 =>
+				RoomCreateEventContent::new_v1(server_user.clone()),
+			| _ => RoomCreateEventContent::new_v11(),
+		}
+	};
+
+	// Only the server user may send anything.
+	let power_levels = RoomPowerLevelsEventContent {
+		users: BTreeMap::from_iter([(server_user.clone(), 100.into())]),
+		events_default: 100.into(),
+		..Default::default()
+	};
+
+	let invite = RoomMemberEventContent {
+		is_direct: Some(true),
+		..RoomMemberEventContent::new(MembershipState::Invite)
+	};
+
+	let pdus = [
+		PduBuilder::state(String::new(), &RoomCreateEventContent {
+			federate: false,
+			room_version: room_version.clone(),
+			..create_content
+		}),
+		PduBuilder::state(
+			server_user.to_string(),
+			&RoomMemberEventContent::new(MembershipState::Join),
+		),
+		PduBuilder::state(String::new(), &power_levels),
+		PduBuilder::state(String::new(), &RoomJoinRulesEventContent::new(JoinRule::Invite)),
+		PduBuilder::state(
+			String::new(),
+			&RoomHistoryVisibilityEventContent::new(HistoryVisibility::Joined),
+		),
+		PduBuilder::state(
+			String::new(),
+			&RoomGuestAccessEventContent::new(GuestAccess::Forbidden),
+		),
+		PduBuilder::state(String::new(), &RoomNameEventContent::new("Server Notices".to_owned())),
+		PduBuilder::state(user_id.to_string(), &invite),
+	];
+
+	for pdu in pdus {
+		self.services
+			.timeline
+			.build_and_append_pdu(pdu, server_user, &room_id, &state_lock)
+			.await?;
+	}
+
+	drop(state_lock);
+	self.set_room_tag(&room_id, user_id, SERVER_NOTICE_TAG)
+		.await?;
+
+	self.db.userid_noticeroomid.insert(user_id, &room_id);
+	debug_info!(%user_id, %room_id, "Created server notices room");
+
+	Ok(room_id)
+}
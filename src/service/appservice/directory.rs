@@ -0,0 +1,175 @@
+//! Appservice registrations read from the YAML files of
+//! `appservice_registration_dir`. Each file is registered like one given to
+//! `appservice register`; when a file changes it is registered again, and when
+//! it is removed its appservice is unregistered.
+
+use std::{
+	collections::BTreeMap,
+	path::{Path, PathBuf},
+	time::SystemTime,
+};
+
+use conduwuit::{debug_warn, implement, utils::stream::TryIgnore, Err, Result};
+use futures::StreamExt;
+use ruma::api::appservice::Registration;
+
+/// What reloading the registration files changed.
+#[derive(Debug, Default)]
+pub struct Reload {
+	/// Appservices whose file is new or changed.
+	pub registered: Vec<String>,
+
+	/// Appservices whose file was removed.
+	pub unregistered: Vec<String>,
+
+	/// Number of files unchanged since they were last registered.
+	pub unchanged: usize,
+
+	/// Files which could not be registered, with why.
+	pub failed: Vec<(PathBuf, String)>,
+}
+
+/// The name, modification time and length of each registration file, to tell
+/// when the directory changed.
+pub(super) type Fingerprint = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+/// Register the appservices of the files in the directory, and unregister
+/// those whose file was removed.
+#[implement(super::Service)]
+pub async fn reload_all(&self) -> Result<Reload> {
+	let Some(dir) = self
+		.services
+		.server
+		.config
+		.appservice_registration_dir
+		.as_deref()
+	else {
+		return Err!(Config(
+			"appservice_registration_dir",
+			"No directory of appservice registrations is configured."
+		));
+	};
+
+	let mut reload = Reload::default();
+	let mut found: BTreeMap<String, PathBuf> = BTreeMap::new();
+	for path in registration_files(dir).await? {
+		let (registration, body) = match read_registration(&path).await {
+			| Ok(registration) => registration,
+			| Err(e) => {
+				reload.failed.push((path, e.to_string()));
+				continue;
+			},
+		};
+
+		let id = registration.id.clone();
+		if let Some(other) = found.get(&id) {
+			let e = format!("Appservice ID {id:?} is already registered by {other:?}");
+			reload.failed.push((path, e));
+			continue;
+		}
+
+		let unchanged = self
+			.db
+			.id_appserviceregistrations
+			.get(&id)
+			.await
+			.is_ok_and(|stored| *stored == *body.as_bytes());
+
+		if unchanged && self.registration_file(&id).await.as_deref() == Some(path.as_path()) {
+			reload.unchanged = reload.unchanged.saturating_add(1);
+		} else if let Err(e) = self.register_appservice(&registration, &body).await {
+			reload.failed.push((path, e.to_string()));
+			continue;
+		} else {
+			self.db
+				.id_appserviceregistrationfile
+				.insert(&id, path.to_string_lossy().as_bytes());
+
+			reload.registered.push(id.clone());
+		}
+
+		found.insert(id, path);
+	}
+
+	let loaded: Vec<String> = self
+		.db
+		.id_appserviceregistrationfile
+		.keys()
+		.ignore_err()
+		.collect()
+		.await;
+
+	for id in loaded {
+		if found.contains_key(&id) {
+			continue;
+		}
+
+		// An appservice whose file can't be read or parsed right now, e.g. while
+		// it is being edited, stays registered as it was.
+		let file = self.registration_file(&id).await;
+		if reload
+			.failed
+			.iter()
+			.any(|(path, _)| file.as_ref() == Some(path))
+		{
+			continue;
+		}
+
+		self.db.id_appserviceregistrationfile.remove(&id);
+		if let Err(e) = self.unregister_appservice(&id).await {
+			debug_warn!(%id, "Appservice of removed registration file: {e}");
+		}
+
+		reload.unregistered.push(id);
+	}
+
+	Ok(reload)
+}
+
+/// The registration file the appservice was registered from, if any.
+#[implement(super::Service)]
+pub async fn registration_file(&self, id: &str) -> Option<PathBuf> {
+	self.db
+		.id_appserviceregistrationfile
+		.get(id)
+		.await
+		.ok()
+		.map(|path| PathBuf::from(String::from_utf8_lossy(&path).into_owned()))
+}
+
+pub(super) async fn fingerprint(dir: &Path) -> Result<Fingerprint> {
+	let mut fingerprint = Fingerprint::new();
+	for path in registration_files(dir).await? {
+		let metadata = tokio::fs::metadata(&path).await?;
+		fingerprint.push((path, metadata.modified().ok(), metadata.len()));
+	}
+
+	Ok(fingerprint)
+}
+
+/// The YAML files of the directory, by name.
+async fn registration_files(dir: &Path) -> Result<Vec<PathBuf>> {
+	let mut files = Vec::new();
+	let mut entries = tokio::fs::read_dir(dir).await?;
+	while let Some(entry) = entries.next_entry().await? {
+		let path = entry.path();
+		let is_yaml = path
+			.extension()
+			.is_some_and(|extension| extension == "yaml" || extension == "yml");
+
+		if is_yaml && entry.file_type().await?.is_file() {
+			files.push(path);
+		}
+	}
+
+	files.sort();
+
+	Ok(files)
+}
+
+async fn read_registration(path: &Path) -> Result<(Registration, String)> {
+	let body = tokio::fs::read_to_string(path).await?;
+	let registration = serde_yaml::from_str(&body)?;
+
+	Ok((registration, body))
+}
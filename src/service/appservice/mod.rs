@@ -1,29 +1,38 @@
+mod directory;
 mod namespace_regex;
 mod registration_info;
 
-use std::{collections::BTreeMap, sync::Arc};
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use conduwuit::{err, utils::stream::TryIgnore, Result};
+use conduwuit::{debug, err, info, utils::stream::TryIgnore, warn, Result, Server};
 use database::Map;
 use futures::{Future, StreamExt, TryStreamExt};
 use ruma::{api::appservice::Registration, RoomAliasId, RoomId, UserId};
-use tokio::sync::RwLock;
+use tokio::{
+	sync::{Notify, RwLock},
+	time::interval,
+};
 
-pub use self::{namespace_regex::NamespaceRegex, registration_info::RegistrationInfo};
+pub use self::{
+	directory::Reload, namespace_regex::NamespaceRegex, registration_info::RegistrationInfo,
+};
 use crate::{sending, Dep};
 
 pub struct Service {
 	registration_info: RwLock<BTreeMap<String, RegistrationInfo>>,
 	services: Services,
 	db: Data,
+	interrupt: Notify,
 }
 
 struct Services {
+	server: Arc<Server>,
 	sending: Dep<sending::Service>,
 }
 
 struct Data {
+	id_appserviceregistrationfile: Arc<Map>,
 	id_appserviceregistrations: Arc<Map>,
 }
 
@@ -33,11 +42,14 @@ impl crate::Service for Service {
 		Ok(Arc::new(Self {
 			registration_info: RwLock::new(BTreeMap::new()),
 			services: Services {
+				server: args.server.clone(),
 				sending: args.depend::<sending::Service>("sending"),
 			},
 			db: Data {
+				id_appserviceregistrationfile: args.db["id_appserviceregistrationfile"].clone(),
 				id_appserviceregistrations: args.db["id_appserviceregistrations"].clone(),
 			},
+			interrupt: Notify::new(),
 		}))
 	}
 
@@ -53,13 +65,62 @@ impl crate::Service for Service {
 			);
 		}
 
+		let config = &self.services.server.config;
+		let Some(dir) = config.appservice_registration_dir.clone() else {
+			return Ok(());
+		};
+
+		self.reload_logged().await;
+		if config.appservice_registration_watch_interval == 0 {
+			return Ok(());
+		}
+
+		let mut last = directory::fingerprint(&dir).await.ok();
+		let mut interval =
+			interval(Duration::from_secs(config.appservice_registration_watch_interval));
+
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = interval.tick() => {
+					let fingerprint = directory::fingerprint(&dir).await.ok();
+					if fingerprint != last {
+						debug!(?dir, "Appservice registration directory changed");
+						self.reload_logged().await;
+						last = fingerprint;
+					}
+				},
+			}
+		}
+
 		Ok(())
 	}
 
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
 impl Service {
+	async fn reload_logged(&self) {
+		match self.reload_all().await {
+			| Err(e) => warn!("Failed to read appservice registration directory: {e}"),
+			| Ok(reload) => {
+				for (path, e) in &reload.failed {
+					warn!(?path, "Failed to register appservice: {e}");
+				}
+
+				if !reload.registered.is_empty() || !reload.unregistered.is_empty() {
+					info!(
+						registered = ?reload.registered,
+						unregistered = ?reload.unregistered,
+						"Reloaded appservice registration directory"
+					);
+				}
+			},
+		}
+	}
+
 	/// Registers an appservice and returns the ID to the caller
 	pub async fn register_appservice(
 		&self,
@@ -67,14 +128,17 @@ impl Service {
 		appservice_config_body: &str,
 	) -> Result {
 		//TODO: Check for collisions between exclusive appservice namespaces
+		let mut registration = registration.clone();
+		registration.receive_ephemeral |= push_ephemeral(appservice_config_body.as_bytes());
+		let id = registration.id.clone();
 		self.registration_info
 			.write()
 			.await
-			.insert(registration.id.clone(), registration.clone().try_into()?);
+			.insert(id.clone(), registration.try_into()?);
 
 		self.db
 			.id_appserviceregistrations
-			.insert(&registration.id, appservice_config_body);
+			.insert(&id, appservice_config_body);
 
 		Ok(())
 	}
@@ -171,7 +235,11 @@ impl Service {
 			.id_appserviceregistrations
 			.get(id)
 			.await
-			.and_then(|ref bytes| serde_yaml::from_slice(bytes).map_err(Into::into))
+			.and_then(|ref bytes| {
+				let mut registration: Registration = serde_yaml::from_slice(bytes)?;
+				registration.receive_ephemeral |= push_ephemeral(bytes);
+				Ok(registration)
+			})
 			.map_err(|e| err!(Database("Invalid appservice {id:?} registration: {e:?}")))
 	}
 
@@ -188,3 +256,16 @@ impl Service {
 			.await
 	}
 }
+
+/// Whether the registration asks for ephemeral events by the unstable name of
+/// `receive_ephemeral` from MSC2409, which some bridges still use.
+fn push_ephemeral(body: &[u8]) -> bool {
+	serde_yaml::from_slice::<serde_yaml::Value>(body)
+		.ok()
+		.and_then(|registration| {
+			registration
+				.get("de.sorunome.msc2409.push_ephemeral")
+				.and_then(serde_yaml::Value::as_bool)
+		})
+		.unwrap_or(false)
+}
@@ -0,0 +1,33 @@
+use conduwuit::Result;
+use tokio::sync::broadcast;
+
+use super::{Envelope, Transport};
+
+/// Envelopes which may be queued for a subscriber before it misses some.
+const CHANNEL_CAPACITY: usize = 1024;
+
+/// Connects the services of a single process.
+pub(super) struct Local {
+	sender: broadcast::Sender<Envelope>,
+}
+
+impl Local {
+	pub(super) fn new() -> Self {
+		Self {
+			sender: broadcast::channel(CHANNEL_CAPACITY).0,
+		}
+	}
+}
+
+impl Transport for Local {
+	fn publish(&self, envelope: Envelope) -> Result {
+		// Only fails when nothing is subscribed, so nothing missed the envelope.
+		self.sender.send(envelope).ok();
+
+		Ok(())
+	}
+
+	fn subscribe(&self) -> broadcast::Receiver<Envelope> { self.sender.subscribe() }
+
+	fn shared(&self) -> bool { false }
+}
@@ -0,0 +1,151 @@
+//! Notifications between the instances of a deployment sharing one database,
+//! so that e.g. caches are invalidated and syncs woken on every instance and
+//! not only the one which made the change. Instances are connected by a
+//! [`Transport`]; a single process uses an in-process channel.
+
+mod local;
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use conduwuit::{debug, debug_warn, implement, utils, Result, Server};
+use ruma::{OwnedUserId, UserId};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{
+	broadcast::{self, error::RecvError},
+	Notify,
+};
+
+use crate::service::Map;
+
+pub struct Service {
+	instance: String,
+	transport: Box<dyn Transport>,
+	interrupt: Notify,
+	server: Arc<Server>,
+	service: Arc<Map>,
+}
+
+/// A notification for the other instances.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub enum Message {
+	/// Clear every service's caches.
+	ClearCaches,
+
+	/// Wake the user's syncs, as something they wait on was written.
+	SyncWakeup(OwnedUserId),
+}
+
+/// A message with the instance which published it.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Envelope {
+	pub origin: String,
+	pub message: Message,
+}
+
+/// Carries envelopes between instances. Every subscriber receives every
+/// envelope published, including those of its own instance.
+pub trait Transport: Send + Sync {
+	fn publish(&self, envelope: Envelope) -> Result;
+
+	fn subscribe(&self) -> broadcast::Receiver<Envelope>;
+
+	/// Whether other instances receive the envelopes published.
+	fn shared(&self) -> bool;
+}
+
+/// Instance ID length; these only need to be unique within a deployment.
+const INSTANCE_ID_LENGTH: usize = 16;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			instance: utils::random_string(INSTANCE_ID_LENGTH),
+			transport: Box::new(local::Local::new()),
+			interrupt: Notify::new(),
+			server: args.server.clone(),
+			service: args.service.clone(),
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "bus", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let mut receiver = self.transport.subscribe();
+		while self.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				envelope = receiver.recv() => match envelope {
+					Ok(envelope) => self.handle(envelope),
+					Err(RecvError::Lagged(count)) => {
+						debug_warn!(?count, "Missed messages from other instances");
+					},
+					Err(RecvError::Closed) => break,
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn replica_worker(&self) -> bool { true }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Notify the other instances.
+#[implement(Service)]
+pub fn publish(&self, message: Message) -> Result {
+	self.transport
+		.publish(Envelope { origin: self.instance.clone(), message })
+}
+
+/// Wake the user's syncs on the other instances after writing something they
+/// wait on. Nothing is published unless other instances share the database.
+#[implement(Service)]
+pub fn wake_syncs(&self, user_id: &UserId) {
+	if !self.transport.shared() {
+		return;
+	}
+
+	if let Err(e) = self.publish(Message::SyncWakeup(user_id.to_owned())) {
+		debug_warn!(%user_id, "Failed to wake syncs on other instances: {e}");
+	}
+}
+
+/// Resolves once another instance wakes the user's syncs.
+#[implement(Service)]
+pub async fn wait_for_wakeup(&self, user_id: &UserId) {
+	let mut receiver = self.transport.subscribe();
+	loop {
+		match receiver.recv().await {
+			| Ok(Envelope {
+				origin,
+				message: Message::SyncWakeup(woken),
+			}) if origin != self.instance && woken == user_id => return,
+			| Ok(_) | Err(RecvError::Lagged(_)) => continue,
+			| Err(RecvError::Closed) => return std::future::pending().await,
+		}
+	}
+}
+
+#[implement(Service)]
+fn handle(&self, Envelope { origin, message }: Envelope) {
+	if origin == self.instance {
+		return;
+	}
+
+	debug!(%origin, ?message, "Received message from another instance");
+	match message {
+		| Message::ClearCaches => {
+			for (service, ..) in self.service.read().expect("locked for reading").values() {
+				if let Some(service) = service.upgrade() {
+					service.clear_cache();
+				}
+			}
+		},
+		| Message::SyncWakeup(_) => (),
+	}
+}
@@ -0,0 +1,106 @@
+use std::{
+	collections::hash_map,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{debug, implement, utils::continue_exponential_backoff_secs};
+use ruma::{EventId, OwnedEventId};
+
+/// How attempts at an event are backed off after failing.
+#[derive(Clone, Copy, Debug)]
+pub enum Backoff {
+	/// Fetching the event from a remote server.
+	Fetch,
+
+	/// Handling the event once it has been fetched.
+	Handle,
+}
+
+/// Record a failed attempt to fetch or handle the event, so further attempts
+/// are backed off.
+#[implement(super::Service)]
+pub fn back_off_event(&self, event_id: OwnedEventId) {
+	let now = Instant::now();
+	match self
+		.bad_event_ratelimiter
+		.write()
+		.expect("locked for writing")
+		.entry(event_id)
+	{
+		| hash_map::Entry::Vacant(e) => {
+			e.insert((now, 1));
+		},
+		| hash_map::Entry::Occupied(mut e) => {
+			*e.get_mut() = (now, e.get().1.saturating_add(1));
+		},
+	}
+}
+
+/// If attempts at the event are still being backed off, the number of failed
+/// attempts and the time since the last one.
+#[implement(super::Service)]
+pub fn event_backed_off(&self, event_id: &EventId, backoff: Backoff) -> Option<(u32, Duration)> {
+	let (min, max) = match backoff {
+		| Backoff::Fetch =>
+			(self.config.bad_event_fetch_backoff_min, self.config.bad_event_fetch_backoff_max),
+		| Backoff::Handle =>
+			(self.config.bad_event_backoff_min, self.config.bad_event_backoff_max),
+	};
+
+	let (time, tries) = *self
+		.bad_event_ratelimiter
+		.read()
+		.expect("locked for reading")
+		.get(event_id)?;
+
+	let elapsed = time.elapsed();
+	continue_exponential_backoff_secs(min, max, elapsed, tries).then_some((tries, elapsed))
+}
+
+/// Events which have failed, with the number of failed attempts and the time
+/// since the last one, most recent first.
+#[implement(super::Service)]
+pub fn bad_events(&self) -> Vec<(OwnedEventId, u32, Duration)> {
+	let mut events: Vec<_> = self
+		.bad_event_ratelimiter
+		.read()
+		.expect("locked for reading")
+		.iter()
+		.map(|(event_id, (time, tries))| (event_id.clone(), *tries, time.elapsed()))
+		.collect();
+
+	events.sort_unstable_by_key(|&(_, _, elapsed)| elapsed);
+	events
+}
+
+/// Forget the failures of the event, or of all events, so they are attempted
+/// again right away. Returns the number of events forgotten.
+#[implement(super::Service)]
+pub fn clear_bad_events(&self, event_id: Option<&EventId>) -> usize {
+	let mut events = self
+		.bad_event_ratelimiter
+		.write()
+		.expect("locked for writing");
+
+	if let Some(event_id) = event_id {
+		return events.remove(event_id).map_or(0, |_| 1);
+	}
+
+	let count = events.len();
+	events.clear();
+	count
+}
+
+/// Forget failures older than the configured expiry.
+#[implement(super::Service)]
+pub(super) fn expire_bad_events(&self) {
+	let expiry = Duration::from_secs(self.config.bad_event_expiry);
+	let mut events = self
+		.bad_event_ratelimiter
+		.write()
+		.expect("locked for writing");
+
+	let count = events.len();
+	events.retain(|_, (time, _)| time.elapsed() < expiry);
+	debug!(expired = count.saturating_sub(events.len()), "Expired bad events");
+}
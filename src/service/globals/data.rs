@@ -82,4 +82,7 @@ impl Data {
 
 	#[inline]
 	pub fn file_list(&self) -> Result<String> { self.db.db.file_list() }
+
+	#[inline]
+	pub fn catch_up(&self) -> Result { self.db.db.catch_up() }
 }
@@ -1,21 +1,28 @@
+mod bad_event;
 mod data;
 
 use std::{
 	collections::HashMap,
 	fmt::Write,
+	net::IpAddr,
 	sync::{Arc, RwLock},
-	time::Instant,
+	time::{Duration, Instant},
 };
 
-use conduwuit::{error, utils::bytes::pretty, Config, Result};
+use async_trait::async_trait;
+use conduwuit::{err, error, utils::bytes::pretty, Config, Result};
 use data::Data;
+use ipaddress::IPAddress;
 use regex::RegexSet;
 use ruma::{OwnedEventId, OwnedRoomAliasId, OwnedServerName, OwnedUserId, ServerName, UserId};
+use tokio::{sync::Notify, time::interval};
 
+pub use self::bad_event::Backoff;
 use crate::service;
 
 pub struct Service {
 	pub db: Data,
+	interrupt: Notify,
 
 	pub config: Config,
 	pub bad_event_ratelimiter: Arc<RwLock<HashMap<OwnedEventId, RateLimitState>>>,
@@ -23,10 +30,15 @@ pub struct Service {
 	pub admin_alias: OwnedRoomAliasId,
 	pub turn_secret: String,
 	pub registration_token: Option<String>,
+	pub trusted_proxies: Vec<IPAddress>,
 }
 
 type RateLimitState = (Instant, u32); // Time if last failed try, number of failed tries
 
+/// Interval between sweeps for expired bad events.
+const BAD_EVENT_SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 10);
+
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		let db = Data::new(&args);
@@ -59,6 +71,7 @@ impl crate::Service for Service {
 
 		let mut s = Self {
 			db,
+			interrupt: Notify::new(),
 			config: config.clone(),
 			bad_event_ratelimiter: Arc::new(RwLock::new(HashMap::new())),
 			admin_alias: OwnedRoomAliasId::try_from(format!("#admins:{}", &config.server_name))
@@ -70,6 +83,12 @@ impl crate::Service for Service {
 			.expect("@conduit:server_name is valid"),
 			turn_secret,
 			registration_token,
+			trusted_proxies: config
+				.trusted_proxies
+				.iter()
+				.map(IPAddress::parse)
+				.collect::<Result<_, String>>()
+				.map_err(|e| err!(Config("trusted_proxies", e)))?,
 		};
 
 		if !args
@@ -83,6 +102,38 @@ impl crate::Service for Service {
 		Ok(Arc::new(s))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		let expire = self.config.bad_event_expiry > 0;
+		let secondary = self.db.db.is_secondary();
+		if !expire && !secondary {
+			return Ok(());
+		}
+
+		let mut sweep = interval(BAD_EVENT_SWEEP_INTERVAL);
+		let mut catch_up = interval(Duration::from_millis(
+			self.config.rocksdb_secondary_catchup_interval.max(1),
+		));
+
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = sweep.tick(), if expire => self.expire_bad_events(),
+				_ = catch_up.tick(), if secondary => {
+					if let Err(e) = self.db.catch_up() {
+						error!("Failed to catch up with the primary database: {e}");
+					}
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	// Catching up with the primary is what keeps a replica current.
+	fn replica_worker(&self) -> bool { true }
+
 	fn memory_usage(&self, out: &mut dyn Write) -> Result {
 		let (ber_count, ber_bytes) = self.bad_event_ratelimiter.read()?.iter().fold(
 			(0_usize, 0_usize),
@@ -216,6 +267,13 @@ impl Service {
 		server_name == self.config.server_name
 	}
 
+	/// Whether the headers of requests from the address are honored to
+	/// determine the IP address of the client.
+	pub fn is_trusted_proxy(&self, ip: &IpAddr) -> bool {
+		IPAddress::parse(ip.to_string())
+			.is_ok_and(|ip| self.trusted_proxies.iter().any(|cidr| cidr.includes(&ip)))
+	}
+
 	#[inline]
 	pub fn is_read_only(&self) -> bool { self.db.db.is_read_only() }
 }
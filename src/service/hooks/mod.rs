@@ -0,0 +1,183 @@
+//! Webhooks of `[global.webhooks]` notified of what happens on the server, so
+//! external moderation tools needn't poll for it. Events are queued and sent
+//! in the background, in the order they happened; nothing waits for them.
+
+use std::{fmt::Write, sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{debug, utils, warn, Result, Server};
+use hmac::{Hmac, Mac};
+use loole::{Receiver, Sender};
+use ruma::{OwnedRoomId, OwnedUserId};
+use serde::Serialize;
+use sha2::Sha256;
+use url::Url;
+
+use crate::{client, Dep};
+
+pub struct Service {
+	channel: (Sender<Notification>, Receiver<Notification>),
+	server: Arc<Server>,
+	services: Services,
+}
+
+struct Services {
+	client: Dep<client::Service>,
+}
+
+/// What the webhooks are notified of.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+	/// A room was published to or removed from the room directory.
+	DirectoryPublication {
+		room_id: OwnedRoomId,
+		user_id: OwnedUserId,
+		published: bool,
+	},
+
+	/// A local account was registered.
+	Registration {
+		user_id: OwnedUserId,
+		guest: bool,
+	},
+
+	/// A local user created a room.
+	RoomCreation {
+		room_id: OwnedRoomId,
+		creator: OwnedUserId,
+	},
+}
+
+#[derive(Debug, Serialize)]
+struct Notification {
+	#[serde(flatten)]
+	event: Event,
+	origin_server_ts: u64,
+}
+
+/// The header the signature of the body is sent in.
+const SIGNATURE_HEADER: &str = "X-Conduwuit-Signature";
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			channel: loole::unbounded(),
+			server: args.server.clone(),
+			services: Services {
+				client: args.depend::<client::Service>("client"),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "hooks", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result {
+		let receiver = self.channel.1.clone();
+		while let Ok(notification) = receiver.recv_async().await {
+			self.deliver(&notification).await;
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) {
+		let (sender, _) = &self.channel;
+		if !sender.is_closed() {
+			sender.close();
+		}
+	}
+
+	fn queue_len(&self) -> Option<usize> { Some(self.channel.0.len()) }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Notify the webhooks of the event, unless none is configured for its
+	/// type.
+	pub fn notify(&self, event: Event) {
+		let config = &self.server.config.webhooks;
+		if config.urls.is_empty() {
+			return;
+		}
+
+		if !config.events.is_empty() && !config.events.iter().any(|kind| kind == event.kind()) {
+			return;
+		}
+
+		let notification = Notification {
+			event,
+			origin_server_ts: utils::millis_since_unix_epoch(),
+		};
+
+		if self.channel.0.send(notification).is_err() {
+			debug!("Not notifying the webhooks while shutting down");
+		}
+	}
+
+	async fn deliver(&self, notification: &Notification) {
+		let config = &self.server.config.webhooks;
+		let body = match serde_json::to_vec(notification) {
+			| Ok(body) => body,
+			| Err(e) => {
+				warn!("Failed to serialize the {} event: {e}", notification.event.kind());
+				return;
+			},
+		};
+
+		let signature = config.secret.as_deref().map(|secret| sign(secret, &body));
+		for url in &config.urls {
+			if let Err(e) = self.post(url, &body, signature.as_deref()).await {
+				warn!(%url, "Failed to notify the webhook of {}: {e}", notification.event.kind());
+			}
+		}
+	}
+
+	async fn post(&self, url: &Url, body: &[u8], signature: Option<&str>) -> Result {
+		let timeout = Duration::from_secs(self.server.config.webhooks.timeout);
+		let mut request = self
+			.services
+			.client
+			.default
+			.post(url.clone())
+			.timeout(timeout)
+			.header(http::header::CONTENT_TYPE, "application/json")
+			.body(body.to_vec());
+
+		if let Some(signature) = signature {
+			request = request.header(SIGNATURE_HEADER, format!("sha256={signature}"));
+		}
+
+		request.send().await?.error_for_status()?;
+
+		Ok(())
+	}
+}
+
+impl Event {
+	/// The name of the type of the event, as sent and configured.
+	#[must_use]
+	pub fn kind(&self) -> &'static str {
+		match self {
+			| Self::DirectoryPublication { .. } => "directory_publication",
+			| Self::Registration { .. } => "registration",
+			| Self::RoomCreation { .. } => "room_creation",
+		}
+	}
+}
+
+/// The hex-encoded HMAC-SHA256 of the body.
+fn sign(secret: &str, body: &[u8]) -> String {
+	let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+		.expect("HMAC can take a key of any size");
+	mac.update(body);
+
+	mac.finalize()
+		.into_bytes()
+		.iter()
+		.fold(String::new(), |mut hex, byte| {
+			write!(hex, "{byte:02x}").expect("writing to a String");
+			hex
+		})
+}
@@ -1,4 +1,10 @@
-use std::{panic::AssertUnwindSafe, sync::Arc, time::Duration};
+use std::{
+	collections::BTreeMap,
+	fmt,
+	panic::AssertUnwindSafe,
+	sync::Arc,
+	time::{Duration, SystemTime},
+};
 
 use conduwuit::{debug, debug_warn, error, trace, utils::time, warn, Err, Error, Result, Server};
 use futures::FutureExt;
@@ -8,26 +14,56 @@ use tokio::{
 	time::sleep,
 };
 
-use crate::{service, service::Service, Services};
+use crate::{admin, service, service::Service, Services};
 
 pub(crate) struct Manager {
 	manager: Mutex<Option<JoinHandle<Result<()>>>>,
 	workers: Mutex<Workers>,
+	status: std::sync::Mutex<BTreeMap<String, WorkerStatus>>,
 	server: Arc<Server>,
 	service: Arc<service::Map>,
 }
 
+/// Supervision state of a service's worker as tracked by the manager.
+#[derive(Clone, Debug)]
+pub struct WorkerStatus {
+	pub state: WorkerState,
+
+	/// Consecutive restarts after panics; reset once the worker has stayed up.
+	pub restarts: u32,
+
+	/// The error which last terminated the worker, if any.
+	pub error: Option<String>,
+
+	/// When the worker entered its current state.
+	pub since: SystemTime,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum WorkerState {
+	Running,
+	Finished,
+	Restarting,
+	Failed,
+}
+
 type Workers = JoinSet<WorkerResult>;
 type WorkerResult = (Arc<dyn Service>, Result<()>);
 type WorkersLocked<'a> = MutexGuard<'a, Workers>;
 
 const RESTART_DELAY_MS: u64 = 2500;
+const RESTART_DELAY_MAX_MS: u64 = 300_000;
+
+/// A worker which panics after running at least this long is restarted
+/// without the backoff accumulated by earlier panics.
+const RESTART_RESET_SECS: u64 = 600;
 
 impl Manager {
 	pub(super) fn new(services: &Services) -> Arc<Self> {
 		Arc::new(Self {
 			manager: Mutex::new(None),
 			workers: Mutex::new(JoinSet::new()),
+			status: std::sync::Mutex::new(BTreeMap::new()),
 			server: services.server.clone(),
 			service: services.service.clone(),
 		})
@@ -66,6 +102,11 @@ impl Manager {
 
 		debug!("Starting service workers...");
 		for service in services {
+			if self.server.config.is_replica() && !service.replica_worker() {
+				debug!("Service {:?} worker not started on a read replica.", service.name());
+				continue;
+			}
+
 			self.start_worker(&mut workers, &service).await?;
 		}
 
@@ -81,6 +122,10 @@ impl Manager {
 		}
 	}
 
+	pub(super) fn status(&self) -> BTreeMap<String, WorkerStatus> {
+		self.status.lock().expect("locked").clone()
+	}
+
 	async fn worker(&self) -> Result<()> {
 		loop {
 			let mut workers = self.workers.lock().await;
@@ -120,6 +165,8 @@ impl Manager {
 		service: &Arc<dyn Service>,
 	) -> Result<()> {
 		debug!("service {:?} worker finished", service.name());
+		self.set_state(service.name(), WorkerState::Finished, None);
+
 		Ok(())
 	}
 
@@ -134,20 +181,72 @@ impl Manager {
 
 		if !self.server.running() {
 			debug_warn!("service {name:?} error ignored on shutdown.");
+			self.set_state(name, WorkerState::Failed, Some(&error));
 			return Ok(());
 		}
 
 		if !error.is_panic() {
+			self.set_state(name, WorkerState::Failed, Some(&error));
 			return Err(error);
 		}
 
-		let delay = Duration::from_millis(RESTART_DELAY_MS);
+		let restarts = self.set_state(name, WorkerState::Restarting, Some(&error));
+		let delay = restart_delay(restarts);
 		warn!("service {name:?} worker restarting after {} delay", time::pretty(delay));
+		self.notify(format!(
+			"Service `{name}` worker panicked and will restart in {} (restart #{restarts}): \
+			 {error}",
+			time::pretty(delay),
+		));
+
 		sleep(delay).await;
 
 		self.start_worker(workers, service).await
 	}
 
+	/// Record the worker's transition to a new state. Returns the number of
+	/// consecutive restarts, which is incremented when entering Restarting.
+	fn set_state(&self, name: &str, state: WorkerState, error: Option<&Error>) -> u32 {
+		let now = SystemTime::now();
+		let mut status = self.status.lock().expect("locked");
+		let status = status
+			.entry(name.to_owned())
+			.or_insert_with(|| WorkerStatus {
+				state,
+				restarts: 0,
+				error: None,
+				since: now,
+			});
+
+		if state == WorkerState::Restarting {
+			let uptime = now.duration_since(status.since).unwrap_or_default();
+			if uptime >= Duration::from_secs(RESTART_RESET_SECS) {
+				status.restarts = 0;
+			}
+
+			status.restarts = status.restarts.saturating_add(1);
+		}
+
+		status.state = state;
+		status.since = now;
+		if let Some(error) = error {
+			status.error = Some(error.to_string());
+		}
+
+		status.restarts
+	}
+
+	/// Report to the admin room without holding up the manager.
+	fn notify(&self, message: String) {
+		let Some(admin) = service::get::<admin::Service>(&self.service, "admin") else {
+			return;
+		};
+
+		self.server.runtime().spawn(async move {
+			admin.send_text(&message).await;
+		});
+	}
+
 	/// Start the worker in a task for the service.
 	async fn start_worker(
 		&self,
@@ -162,12 +261,34 @@ impl Manager {
 		}
 
 		debug!("Service {:?} worker starting...", service.name());
+		self.set_state(service.name(), WorkerState::Running, None);
 		workers.spawn_on(worker(service.clone()), self.server.runtime());
 
 		Ok(())
 	}
 }
 
+impl fmt::Display for WorkerState {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let state = match self {
+			| Self::Running => "running",
+			| Self::Finished => "finished",
+			| Self::Restarting => "restarting",
+			| Self::Failed => "failed",
+		};
+
+		f.write_str(state)
+	}
+}
+
+/// Exponential backoff for successive restarts of a panicking worker.
+fn restart_delay(restarts: u32) -> Duration {
+	let factor = 2_u64.saturating_pow(restarts.saturating_sub(1));
+	let delay = RESTART_DELAY_MS.saturating_mul(factor);
+
+	Duration::from_millis(delay.min(RESTART_DELAY_MAX_MS))
+}
+
 /// Base frame for service worker. This runs in a tokio::task. All errors and
 /// panics from the worker are caught and returned cleanly. The JoinHandle
 /// should never error with a panic, and if so it should propagate, but it may
@@ -5,18 +5,36 @@ use conduwuit::{
 	utils::{str_from_bytes, stream::TryIgnore, string_from_bytes, ReadyExt},
 	Err, Result,
 };
-use database::{Database, Interfix, Map};
+use database::{Database, Deserialized, Interfix, Json, Map};
 use futures::StreamExt;
-use ruma::{http_headers::ContentDisposition, Mxc, OwnedMxcUri, UserId};
+use ruma::{http_headers::ContentDisposition, Mxc, OwnedMxcUri, ServerName, UserId};
+use serde::{Deserialize, Serialize};
 
 use super::{preview::UrlPreviewData, thumbnail::Dim};
 
 pub(crate) struct Data {
 	mediaid_file: Arc<Map>,
+	mediaid_flags: Arc<Map>,
+	mediaid_lastaccess: Arc<Map>,
 	mediaid_user: Arc<Map>,
+	servername_mediacapability: Arc<Map>,
 	url_previews: Arc<Map>,
 }
 
+/// Flag of media kept from being served, though not deleted.
+pub(super) const QUARANTINED: u64 = 1;
+
+/// Which media endpoints of a remote server served its media the last time it
+/// was fetched.
+#[derive(Debug, Deserialize, Serialize)]
+pub(super) struct MediaCapability {
+	/// The authenticated endpoints (MSC3916) rather than the legacy ones.
+	pub(super) authenticated: bool,
+
+	/// When it was recorded, in milliseconds since the epoch.
+	pub(super) checked: u64,
+}
+
 #[derive(Debug)]
 pub(super) struct Metadata {
 	pub(super) content_disposition: Option<ContentDisposition>,
@@ -28,7 +46,10 @@ impl Data {
 	pub(super) fn new(db: &Arc<Database>) -> Self {
 		Self {
 			mediaid_file: db["mediaid_file"].clone(),
+			mediaid_flags: db["mediaid_flags"].clone(),
+			mediaid_lastaccess: db["mediaid_lastaccess"].clone(),
 			mediaid_user: db["mediaid_user"].clone(),
+			servername_mediacapability: db["servername_mediacapability"].clone(),
 			url_previews: db["url_previews"].clone(),
 		}
 	}
@@ -63,6 +84,9 @@ impl Data {
 			.ready_for_each(|key| self.mediaid_file.remove(key))
 			.await;
 
+		self.mediaid_lastaccess.del(mxc);
+		self.mediaid_flags.del(mxc);
+
 		self.mediaid_user
 			.stream_prefix_raw(&prefix)
 			.ignore_err()
@@ -80,6 +104,60 @@ impl Data {
 			.await;
 	}
 
+	/// Records when the media was last downloaded or thumbnailed, in
+	/// milliseconds since the epoch.
+	#[inline]
+	pub(super) fn set_last_access(&self, mxc: &Mxc<'_>, millis: u64) {
+		self.mediaid_lastaccess.put(mxc, millis);
+	}
+
+	/// When the media was last downloaded or thumbnailed, if it has been since
+	/// access was first recorded.
+	pub(super) async fn last_access(&self, mxc: &Mxc<'_>) -> Option<u64> {
+		self.mediaid_lastaccess.qry(mxc).await.deserialized().ok()
+	}
+
+	pub(super) async fn media_capability(&self, server: &ServerName) -> Option<MediaCapability> {
+		self.servername_mediacapability
+			.get(server)
+			.await
+			.deserialized()
+			.ok()
+	}
+
+	pub(super) fn set_media_capability(&self, server: &ServerName, capability: &MediaCapability) {
+		self.servername_mediacapability
+			.raw_put(server, Json(capability));
+	}
+
+	/// The flags of the media, none by default.
+	pub(super) async fn flags(&self, mxc: &Mxc<'_>) -> u64 {
+		self.mediaid_flags
+			.qry(mxc)
+			.await
+			.deserialized()
+			.unwrap_or(0)
+	}
+
+	/// Sets the flags of the media, which need not be stored locally yet.
+	pub(super) fn set_flags(&self, mxc: &Mxc<'_>, flags: u64) {
+		if flags == 0 {
+			self.mediaid_flags.del(mxc);
+		} else {
+			self.mediaid_flags.put(mxc, flags);
+		}
+	}
+
+	/// All the media with any of the flags set.
+	pub(super) async fn flagged(&self, flags: u64) -> Vec<OwnedMxcUri> {
+		self.mediaid_flags
+			.stream()
+			.ignore_err()
+			.ready_filter_map(|(mxc, set): (&str, u64)| (set & flags != 0).then(|| mxc.into()))
+			.collect()
+			.await
+	}
+
 	/// Searches for all files with the given MXC
 	pub(super) async fn search_mxc_metadata_prefix(&self, mxc: &Mxc<'_>) -> Result<Vec<Vec<u8>>> {
 		debug!("MXC URI: {mxc}");
@@ -171,6 +249,18 @@ impl Data {
 		Ok(())
 	}
 
+	/// Removes all the cached URL previews, returning how many there were.
+	pub(super) async fn clear_url_previews(&self) -> usize {
+		self.url_previews
+			.raw_keys()
+			.ignore_err()
+			.ready_fold(0_usize, |count, key| {
+				self.url_previews.remove(key);
+				count.saturating_add(1)
+			})
+			.await
+	}
+
 	pub(super) fn set_url_preview(
 		&self,
 		url: &str,
@@ -206,23 +296,30 @@ impl Data {
 		value.extend_from_slice(&data.image_width.unwrap_or(0).to_be_bytes());
 		value.push(0xFF);
 		value.extend_from_slice(&data.image_height.unwrap_or(0).to_be_bytes());
+		value.push(0xFF);
+		value.extend_from_slice(
+			data.site_name
+				.as_ref()
+				.map(String::as_bytes)
+				.unwrap_or_default(),
+		);
 
 		self.url_previews.insert(url.as_bytes(), &value);
 
 		Ok(())
 	}
 
-	pub(super) async fn get_url_preview(&self, url: &str) -> Result<UrlPreviewData> {
+	/// Gets the cached URL preview with when it was cached, in seconds since
+	/// the epoch.
+	pub(super) async fn get_url_preview(&self, url: &str) -> Result<(u64, UrlPreviewData)> {
 		let values = self.url_previews.get(url).await?;
 
 		let mut values = values.split(|&b| b == 0xFF);
 
-		let _ts = values.next();
-		/* if we ever decide to use timestamp, this is here.
-		match values.next().map(|b| u64::from_be_bytes(b.try_into().expect("valid BE array"))) {
-			Some(0) => None,
-			x => x,
-		};*/
+		let timestamp = values
+			.next()
+			.map(|b| u64::from_be_bytes(b.try_into().unwrap_or_default()))
+			.unwrap_or_default();
 
 		let title = match values
 			.next()
@@ -267,13 +364,22 @@ impl Data {
 			| x => x,
 		};
 
-		Ok(UrlPreviewData {
+		let site_name = match values
+			.next()
+			.and_then(|b| String::from_utf8(b.to_vec()).ok())
+		{
+			| Some(s) if s.is_empty() => None,
+			| x => x,
+		};
+
+		Ok((timestamp, UrlPreviewData {
 			title,
 			description,
 			image,
 			image_size,
 			image_width,
 			image_height,
-		})
+			site_name,
+		}))
 	}
 }
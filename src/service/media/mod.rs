@@ -2,10 +2,16 @@ mod data;
 pub(super) mod migrations;
 mod preview;
 mod remote;
+mod retention;
 mod tests;
 mod thumbnail;
 
-use std::{path::PathBuf, sync::Arc, time::SystemTime};
+use std::{
+	collections::HashMap,
+	path::PathBuf,
+	sync::{Arc, Mutex},
+	time::SystemTime,
+};
 
 use async_trait::async_trait;
 use base64::{engine::general_purpose, Engine as _};
@@ -18,11 +24,12 @@ use ruma::{http_headers::ContentDisposition, Mxc, OwnedMxcUri, UserId};
 use tokio::{
 	fs,
 	io::{AsyncReadExt, AsyncWriteExt, BufReader},
+	sync::{Notify, Semaphore},
 };
 
-use self::data::{Data, Metadata};
-pub use self::thumbnail::Dim;
-use crate::{client, globals, sending, Dep};
+use self::data::{Data, Metadata, QUARANTINED};
+pub use self::{retention::Pruned, thumbnail::Dim};
+use crate::{client, globals, sending, users, Dep};
 
 #[derive(Debug)]
 pub struct FileMeta {
@@ -32,7 +39,9 @@ pub struct FileMeta {
 }
 
 pub struct Service {
+	interrupt: Notify,
 	url_preview_mutex: MutexMap<String, ()>,
+	url_preview_domains: Mutex<HashMap<String, Arc<Semaphore>>>,
 	pub(super) db: Data,
 	services: Services,
 }
@@ -42,6 +51,7 @@ struct Services {
 	client: Dep<client::Service>,
 	globals: Dep<globals::Service>,
 	sending: Dep<sending::Service>,
+	users: Dep<users::Service>,
 }
 
 /// generated MXC ID (`media-id`) length
@@ -57,23 +67,27 @@ pub const CORP_CROSS_ORIGIN: &str = "cross-origin";
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
 			url_preview_mutex: MutexMap::new(),
+			url_preview_domains: Mutex::new(HashMap::new()),
 			db: Data::new(args.db),
 			services: Services {
 				server: args.server.clone(),
 				client: args.depend::<client::Service>("client"),
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
+				users: args.depend::<users::Service>("users"),
 			},
 		}))
 	}
 
 	async fn worker(self: Arc<Self>) -> Result<()> {
 		self.create_media_dir().await?;
-
-		Ok(())
+		self.retention_worker().await
 	}
 
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -152,11 +166,42 @@ impl Service {
 		Ok(deletion_count)
 	}
 
+	/// Keeps the media from being served or fetched, while its files are kept,
+	/// e.g. as evidence of abuse.
+	pub async fn quarantine(&self, mxc: &Mxc<'_>) {
+		let flags = self.db.flags(mxc).await;
+		self.db.set_flags(mxc, flags | QUARANTINED);
+	}
+
+	/// Lets quarantined media be served again, returning whether it was
+	/// quarantined.
+	pub async fn unquarantine(&self, mxc: &Mxc<'_>) -> bool {
+		let flags = self.db.flags(mxc).await;
+		self.db.set_flags(mxc, flags & !QUARANTINED);
+		flags & QUARANTINED != 0
+	}
+
+	#[inline]
+	pub async fn is_quarantined(&self, mxc: &Mxc<'_>) -> bool {
+		self.db.flags(mxc).await & QUARANTINED != 0
+	}
+
+	/// Gets all the quarantined MXC URIs, including those of remote media never
+	/// fetched.
+	pub async fn list_quarantined(&self) -> Vec<OwnedMxcUri> {
+		self.db.flagged(QUARANTINED).await
+	}
+
 	/// Downloads a file.
 	pub async fn get(&self, mxc: &Mxc<'_>) -> Result<Option<FileMeta>> {
+		if self.is_quarantined(mxc).await {
+			return Err!(Request(NotFound("Media not found.")));
+		}
+
 		if let Ok(Metadata { content_disposition, content_type, key }) =
 			self.db.search_file_metadata(mxc, &Dim::default()).await
 		{
+			self.record_access(mxc);
 			let mut content = Vec::new();
 			let path = self.get_media_file(&key);
 			BufReader::new(fs::File::open(path).await?)
@@ -373,8 +418,11 @@ impl Service {
 		Ok(file)
 	}
 
-	#[inline]
 	pub async fn get_metadata(&self, mxc: &Mxc<'_>) -> Option<FileMeta> {
+		if self.is_quarantined(mxc).await {
+			return None;
+		}
+
 		self.db
 			.search_file_metadata(mxc, &Dim::default())
 			.await
@@ -5,12 +5,13 @@
 //! of dependencies and nulls out results through the existing interface when
 //! not featured.
 
-use std::time::SystemTime;
+use std::{sync::Arc, time::SystemTime};
 
-use conduwuit::{debug, Err, Result};
+use conduwuit::{debug, err, Err, Result};
 use conduwuit_core::implement;
 use ipaddress::IPAddress;
 use serde::Serialize;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
 use url::Url;
 
 use super::Service;
@@ -29,6 +30,21 @@ pub struct UrlPreviewData {
 	pub image_width: Option<u32>,
 	#[serde(skip_serializing_if = "Option::is_none", rename(serialize = "og:image:height"))]
 	pub image_height: Option<u32>,
+	#[serde(skip_serializing_if = "Option::is_none", rename(serialize = "og:site_name"))]
+	pub site_name: Option<String>,
+}
+
+/// The fields of an oEmbed response a preview is made of.
+#[cfg(feature = "url_preview")]
+#[derive(serde::Deserialize)]
+struct OEmbed {
+	#[serde(rename = "type")]
+	kind: Option<String>,
+	title: Option<String>,
+	author_name: Option<String>,
+	provider_name: Option<String>,
+	thumbnail_url: Option<String>,
+	url: Option<String>,
 }
 
 #[implement(Service)]
@@ -37,6 +53,10 @@ pub async fn remove_url_preview(&self, url: &str) -> Result<()> {
 	self.db.remove_url_preview(url)
 }
 
+/// Removes all the cached URL previews, returning how many there were.
+#[implement(Service)]
+pub async fn clear_url_previews(&self) -> usize { self.db.clear_url_previews().await }
+
 #[implement(Service)]
 pub async fn set_url_preview(&self, url: &str, data: &UrlPreviewData) -> Result<()> {
 	let now = SystemTime::now()
@@ -47,37 +67,43 @@ pub async fn set_url_preview(&self, url: &str, data: &UrlPreviewData) -> Result<
 
 #[implement(Service)]
 pub async fn get_url_preview(&self, url: &Url) -> Result<UrlPreviewData> {
-	if let Ok(preview) = self.db.get_url_preview(url.as_str()).await {
+	if let Some(preview) = self.cached_url_preview(url).await {
 		return Ok(preview);
 	}
 
 	// ensure that only one request is made per URL
 	let _request_lock = self.url_preview_mutex.lock(url.as_str()).await;
 
-	match self.db.get_url_preview(url.as_str()).await {
-		| Ok(preview) => Ok(preview),
-		| Err(_) => self.request_url_preview(url).await,
+	match self.cached_url_preview(url).await {
+		| Some(preview) => Ok(preview),
+		| None => self.request_url_preview(url).await,
 	}
 }
 
+/// The cached preview of the URL, unless it has expired.
+#[implement(Service)]
+async fn cached_url_preview(&self, url: &Url) -> Option<UrlPreviewData> {
+	let (timestamp, preview) = self.db.get_url_preview(url.as_str()).await.ok()?;
+	let ttl = self.services.server.config.url_preview_cache_ttl;
+	let now = SystemTime::now()
+		.duration_since(SystemTime::UNIX_EPOCH)
+		.expect("valid system time")
+		.as_secs();
+
+	(ttl == 0 || now.saturating_sub(timestamp) < ttl).then_some(preview)
+}
+
 #[implement(Service)]
 async fn request_url_preview(&self, url: &Url) -> Result<UrlPreviewData> {
-	if let Ok(ip) = IPAddress::parse(url.host_str().expect("URL previously validated")) {
-		if !self.services.client.valid_cidr_range(&ip) {
-			return Err!(BadServerResponse("Requesting from this address is forbidden"));
-		}
-	}
+	let host = url.host_str().expect("URL previously validated");
+	self.check_preview_address(host)?;
+
+	// the page, its oEmbed data and its image all count towards the domain
+	let _permit = self.domain_permit(host).await?;
 
 	let client = &self.services.client.url_preview;
 	let response = client.head(url.as_str()).send().await?;
-
-	if let Some(remote_addr) = response.remote_addr() {
-		if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
-			if !self.services.client.valid_cidr_range(&ip) {
-				return Err!(BadServerResponse("Requesting from this address is forbidden"));
-			}
-		}
-	}
+	self.check_preview_response(&response)?;
 
 	let Some(content_type) = response
 		.headers()
@@ -97,6 +123,56 @@ async fn request_url_preview(&self, url: &Url) -> Result<UrlPreviewData> {
 	Ok(data)
 }
 
+/// Waits until fewer previews than `url_preview_domain_concurrency` are being
+/// requested from the domain. The permit is held until the preview is done.
+#[implement(Service)]
+async fn domain_permit(&self, host: &str) -> Result<OwnedSemaphorePermit> {
+	let semaphore = {
+		let limit = self
+			.services
+			.server
+			.config
+			.url_preview_domain_concurrency
+			.max(1);
+
+		let mut domains = self.url_preview_domains.lock().expect("locked");
+		domains.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+		domains
+			.entry(host.to_owned())
+			.or_insert_with(|| Arc::new(Semaphore::new(limit)))
+			.clone()
+	};
+
+	semaphore
+		.acquire_owned()
+		.await
+		.map_err(|e| err!("URL preview domain semaphore closed: {e}"))
+}
+
+#[implement(Service)]
+fn check_preview_address(&self, host: &str) -> Result {
+	if let Ok(ip) = IPAddress::parse(host) {
+		if !self.services.client.valid_cidr_range(&ip) {
+			return Err!(BadServerResponse("Requesting from this address is forbidden"));
+		}
+	}
+
+	Ok(())
+}
+
+#[implement(Service)]
+fn check_preview_response(&self, response: &reqwest::Response) -> Result {
+	if let Some(remote_addr) = response.remote_addr() {
+		if let Ok(ip) = IPAddress::parse(remote_addr.ip().to_string()) {
+			if !self.services.client.valid_cidr_range(&ip) {
+				return Err!(BadServerResponse("Requesting from this address is forbidden"));
+			}
+		}
+	}
+
+	Ok(())
+}
+
 #[cfg(feature = "url_preview")]
 #[implement(Service)]
 pub async fn download_image(&self, url: &str) -> Result<UrlPreviewData> {
@@ -105,6 +181,7 @@ pub async fn download_image(&self, url: &str) -> Result<UrlPreviewData> {
 	use ruma::Mxc;
 
 	let image = self.services.client.url_preview.get(url).send().await?;
+	self.check_preview_response(&image)?;
 	let image = image.bytes().await?;
 	let mxc = Mxc {
 		server_name: self.services.globals.server_name(),
@@ -160,20 +237,61 @@ async fn download_html(&self, url: &str) -> Result<UrlPreviewData> {
 		}
 	}
 	let body = String::from_utf8_lossy(&bytes);
+	let oembed = match find_oembed_url(&body, url) {
+		| Some(oembed_url) => self
+			.download_oembed(&oembed_url)
+			.await
+			.inspect_err(|e| debug!(%url, %oembed_url, "Failed to get oEmbed data: {e}"))
+			.ok(),
+		| None => None,
+	};
+
 	let Ok(html) = HTML::from_string(body.to_string(), Some(url.to_owned())) else {
 		return Err!(Request(Unknown("Failed to parse HTML")));
 	};
 
-	let mut data = match html.opengraph.images.first() {
+	let props = html.opengraph.properties;
+	let twitter = |name: &str| html.meta.get(&format!("twitter:{name}")).cloned();
+
+	/* prefer oEmbed for an image, then OpenGraph, then the Twitter card */
+	let image = oembed
+		.as_ref()
+		.and_then(|oembed| match oembed.kind.as_deref() {
+			| Some("photo") => oembed.url.clone(),
+			| _ => oembed.thumbnail_url.clone(),
+		})
+		.or_else(|| html.opengraph.images.first().map(|obj| obj.url.clone()))
+		.or_else(|| twitter("image"));
+
+	let mut data = match image {
 		| None => UrlPreviewData::default(),
-		| Some(obj) => self.download_image(&obj.url).await?,
+		| Some(image) => self.download_image(&image).await?,
 	};
 
-	let props = html.opengraph.properties;
-
-	/* use OpenGraph title/description, but fall back to HTML if not available */
-	data.title = props.get("title").cloned().or(html.title);
-	data.description = props.get("description").cloned().or(html.description);
+	/* use oEmbed, OpenGraph or Twitter card titles and descriptions, but fall
+	 * back to HTML if none are available */
+	data.title = oembed
+		.as_ref()
+		.and_then(|oembed| oembed.title.clone())
+		.or_else(|| props.get("title").cloned())
+		.or_else(|| twitter("title"))
+		.or(html.title);
+
+	data.description = props
+		.get("description")
+		.cloned()
+		.or_else(|| twitter("description"))
+		.or_else(|| {
+			oembed
+				.as_ref()
+				.and_then(|oembed| oembed.author_name.clone())
+		})
+		.or(html.description);
+
+	data.site_name = oembed
+		.and_then(|oembed| oembed.provider_name)
+		.or_else(|| props.get("site_name").cloned())
+		.or_else(|| twitter("site"));
 
 	Ok(data)
 }
@@ -184,6 +302,67 @@ async fn download_html(&self, _url: &str) -> Result<UrlPreviewData> {
 	Err!(FeatureDisabled("url_preview"))
 }
 
+#[cfg(feature = "url_preview")]
+#[implement(Service)]
+async fn download_oembed(&self, url: &Url) -> Result<OEmbed> {
+	if !matches!(url.scheme(), "http" | "https") {
+		return Err!(Request(Unknown("Unsupported oEmbed URL scheme")));
+	}
+
+	let host = url.host_str().unwrap_or_default();
+	self.check_preview_address(host)?;
+
+	let response = self
+		.services
+		.client
+		.url_preview
+		.get(url.as_str())
+		.send()
+		.await?;
+
+	self.check_preview_response(&response)?;
+	let bytes = response.error_for_status()?.bytes().await?;
+	if bytes.len() > self.services.globals.url_preview_max_spider_size() {
+		return Err!(Request(TooLarge("oEmbed response is too large")));
+	}
+
+	Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// The URL of the JSON oEmbed data a page links to, resolved against the page.
+#[cfg(feature = "url_preview")]
+pub(super) fn find_oembed_url(html: &str, base: &str) -> Option<Url> {
+	use std::sync::LazyLock;
+
+	use regex::Regex;
+
+	static LINK: LazyLock<Regex> =
+		LazyLock::new(|| Regex::new(r"(?is)<link\s[^>]*>").expect("valid regex"));
+	static ATTR: LazyLock<Regex> = LazyLock::new(|| {
+		Regex::new(r#"(?is)([a-z-]+)\s*=\s*(?:"([^"]*)"|'([^']*)')"#).expect("valid regex")
+	});
+
+	LINK.find_iter(html).find_map(|link| {
+		let mut oembed = false;
+		let mut href = None;
+		for attr in ATTR.captures_iter(link.as_str()) {
+			let Some(value) = attr.get(2).or_else(|| attr.get(3)) else {
+				continue;
+			};
+
+			let value = value.as_str();
+			match attr[1].to_ascii_lowercase().as_str() {
+				| "type" => oembed = value.eq_ignore_ascii_case("application/json+oembed"),
+				| "href" => href = Some(value.replace("&amp;", "&")),
+				| _ => {},
+			}
+		}
+
+		let href = href.filter(|_| oembed)?;
+		Url::parse(base).ok()?.join(&href).ok()
+	})
+}
+
 #[implement(Service)]
 pub fn url_preview_allowed(&self, url: &Url) -> bool {
 	if ["http", "https"]
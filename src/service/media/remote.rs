@@ -1,8 +1,9 @@
-use std::{fmt::Debug, time::Duration};
+use std::{fmt::Debug, future::Future, time::Duration};
 
 use conduwuit::{
-	debug_warn, err, implement, utils::content_disposition::make_content_disposition, Err, Error,
-	Result,
+	debug_info, debug_warn, err, implement,
+	utils::{content_disposition::make_content_disposition, time::now_millis},
+	Err, Error, Result,
 };
 use http::header::{HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE};
 use ruma::{
@@ -18,7 +19,11 @@ use ruma::{
 	Mxc, ServerName, UserId,
 };
 
-use super::{Dim, FileMeta};
+use super::{data::MediaCapability, Dim, FileMeta};
+
+/// How long servers found to serve only legacy media are not asked for
+/// authenticated media.
+const LEGACY_RECHECK_MILLIS: u64 = 24 * 60 * 60 * 1000;
 
 #[implement(super::Service)]
 pub async fn fetch_remote_thumbnail(
@@ -31,17 +36,10 @@ pub async fn fetch_remote_thumbnail(
 ) -> Result<FileMeta> {
 	self.check_fetch_authorized(mxc)?;
 
-	let result = self
-		.fetch_thumbnail_unauthenticated(mxc, user, server, timeout_ms, dim)
-		.await;
-
-	if let Err(Error::Request(NotFound, ..)) = &result {
-		return self
-			.fetch_thumbnail_authenticated(mxc, user, server, timeout_ms, dim)
-			.await;
-	}
-
-	result
+	let authenticated = self.fetch_thumbnail_authenticated(mxc, user, server, timeout_ms, dim);
+	let legacy = self.fetch_thumbnail_unauthenticated(mxc, user, server, timeout_ms, dim);
+	self.negotiate(server.unwrap_or(mxc.server_name), authenticated, legacy)
+		.await
 }
 
 #[implement(super::Service)]
@@ -54,14 +52,59 @@ pub async fn fetch_remote_content(
 ) -> Result<FileMeta> {
 	self.check_fetch_authorized(mxc)?;
 
-	let result = self
-		.fetch_content_unauthenticated(mxc, user, server, timeout_ms)
-		.await;
+	let authenticated = self.fetch_content_authenticated(mxc, user, server, timeout_ms);
+	let legacy = self.fetch_content_unauthenticated(mxc, user, server, timeout_ms);
+	self.negotiate(server.unwrap_or(mxc.server_name), authenticated, legacy)
+		.await
+}
+
+/// Fetch remote media from the endpoints which served the media of the origin
+/// last, the authenticated ones (MSC3916) unless only the legacy ones did,
+/// falling back to the others when the media is not found there or the
+/// request is refused, e.g. for its authentication. Servers only serving legacy
+/// media are tried with the authenticated endpoints again after a day, in case
+/// they were upgraded meanwhile.
+#[implement(super::Service)]
+async fn negotiate<A, L>(
+	&self,
+	origin: &ServerName,
+	authenticated: A,
+	legacy: L,
+) -> Result<FileMeta>
+where
+	A: Future<Output = Result<FileMeta>> + Send,
+	L: Future<Output = Result<FileMeta>> + Send,
+{
+	let prefer_authenticated = self
+		.db
+		.media_capability(origin)
+		.await
+		.is_none_or(|capability| {
+			capability.authenticated
+				|| now_millis().saturating_sub(capability.checked) >= LEGACY_RECHECK_MILLIS
+		});
+
+	let (result, used_authenticated) = if prefer_authenticated {
+		match authenticated.await {
+			| Err(Error::Request(NotFound, ..)) => (legacy.await, false),
+			| result => (result, true),
+		}
+	} else {
+		match legacy.await {
+			| Err(Error::Request(NotFound, ..)) => (authenticated.await, true),
+			| result => (result, false),
+		}
+	};
+
+	if result.is_ok() {
+		if used_authenticated != prefer_authenticated {
+			debug_info!(%origin, ?used_authenticated, "Recording the media endpoints of the server");
+		}
 
-	if let Err(Error::Request(NotFound, ..)) = &result {
-		return self
-			.fetch_content_authenticated(mxc, user, server, timeout_ms)
-			.await;
+		self.db.set_media_capability(origin, &MediaCapability {
+			authenticated: used_authenticated,
+			checked: now_millis(),
+		});
 	}
 
 	result
@@ -0,0 +1,241 @@
+//! Retention policies for the media directory: remote media not accessed for a
+//! while is deleted, and the least recently accessed media is deleted once the
+//! directory grows past its configured size.
+
+use std::{
+	collections::{BTreeMap, HashSet},
+	time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use conduwuit::{
+	debug, debug_info, implement,
+	utils::{self, ReadyExt},
+	warn, Result,
+};
+use futures::StreamExt;
+use ruma::{Mxc, MxcUri, OwnedMxcUri};
+use tokio::{fs, time::sleep};
+
+/// Media deleted to enforce retention.
+#[derive(Debug, Default)]
+pub struct Pruned {
+	pub count: usize,
+	pub bytes: u64,
+}
+
+/// A media file with all of its thumbnails.
+struct Entry {
+	mxc: OwnedMxcUri,
+	local: bool,
+	size: u64,
+	last_access: u64,
+}
+
+#[implement(super::Service)]
+pub(super) async fn retention_worker(&self) -> Result {
+	let config = &self.services.server.config;
+	if config.media_retention_remote_max_age == 0 && config.media_retention_max_total_size == 0 {
+		return Ok(());
+	}
+
+	let interval = Duration::from_secs(config.media_retention_interval.max(1));
+	while self.services.server.running() {
+		tokio::select! {
+			() = self.interrupt.notified() => break,
+			() = sleep(interval) => (),
+		}
+
+		match self.enforce_retention().await {
+			| Ok(Pruned { count: 0, .. }) => debug!("No media to prune"),
+			| Ok(Pruned { count, bytes }) => {
+				let bytes = usize::try_from(bytes).unwrap_or(usize::MAX);
+				debug_info!(%count, bytes = %utils::bytes::pretty(bytes), "Pruned media");
+			},
+			| Err(e) => warn!("Failed to enforce media retention: {e}"),
+		}
+	}
+
+	Ok(())
+}
+
+/// Record an access to the media, which keeps it from being pruned as least
+/// recently used for a while.
+#[implement(super::Service)]
+pub(super) fn record_access(&self, mxc: &Mxc<'_>) {
+	let config = &self.services.server.config;
+	let retained =
+		config.media_retention_remote_max_age > 0 || config.media_retention_max_total_size > 0;
+
+	if retained && !self.services.globals.is_read_only() {
+		self.db
+			.set_last_access(mxc, utils::millis_since_unix_epoch());
+	}
+}
+
+/// Delete the media the retention policies in the config no longer allow to be
+/// kept.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn enforce_retention(&self) -> Result<Pruned> {
+	let config = &self.services.server.config;
+	let mut pruned = Pruned::default();
+	if config.media_retention_remote_max_age > 0 {
+		let max_age = Duration::from_secs(config.media_retention_remote_max_age);
+		pruned = self.prune(utils::time::timepoint_ago(max_age)?).await?;
+	}
+
+	let max_total_size = config.media_retention_max_total_size;
+	if max_total_size == 0 {
+		return Ok(pruned);
+	}
+
+	let mut entries = self.entries().await?;
+	let mut total = entries
+		.iter()
+		.map(|entry| entry.size)
+		.fold(0, u64::saturating_add);
+	if total <= max_total_size {
+		return Ok(pruned);
+	}
+
+	let exempt = self.exempt().await;
+	entries.sort_unstable_by_key(|entry| entry.last_access);
+	for entry in entries {
+		if total <= max_total_size {
+			break;
+		}
+
+		if exempt.contains(&entry.mxc) {
+			continue;
+		}
+
+		if self.delete_entry(&entry, &mut pruned).await {
+			total = total.saturating_sub(entry.size);
+		}
+	}
+
+	Ok(pruned)
+}
+
+/// Delete remote media last accessed before the time, or created before it
+/// when its access has not been recorded.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn prune(&self, before: SystemTime) -> Result<Pruned> {
+	let before = millis(before);
+	let exempt = self.exempt().await;
+	let mut pruned = Pruned::default();
+	for entry in self.entries().await? {
+		if entry.local || entry.last_access >= before || exempt.contains(&entry.mxc) {
+			continue;
+		}
+
+		self.delete_entry(&entry, &mut pruned).await;
+	}
+
+	Ok(pruned)
+}
+
+#[implement(super::Service)]
+async fn delete_entry(&self, entry: &Entry, pruned: &mut Pruned) -> bool {
+	let Ok(mxc) = entry.mxc.as_str().try_into() else {
+		return false;
+	};
+
+	debug!(%mxc, size = %entry.size, "Pruning media");
+	match self.delete(&mxc).await {
+		| Ok(()) => {
+			pruned.count = pruned.count.saturating_add(1);
+			pruned.bytes = pruned.bytes.saturating_add(entry.size);
+			true
+		},
+		| Err(e) => {
+			warn!(%mxc, "Failed to prune media: {e}");
+			false
+		},
+	}
+}
+
+/// All media with the total size of its files, and when it was last accessed
+/// or, failing that, created.
+#[implement(super::Service)]
+async fn entries(&self) -> Result<Vec<Entry>> {
+	let mut entries: BTreeMap<OwnedMxcUri, Entry> = BTreeMap::new();
+	for key in self.db.get_all_media_keys().await {
+		let Some(mxc) = key
+			.split(|&b| b == 0xFF)
+			.next()
+			.and_then(|bytes| utils::str_from_bytes(bytes).ok())
+		else {
+			continue;
+		};
+
+		let Ok(parsed): Result<Mxc<'_>, _> = mxc.try_into() else {
+			continue;
+		};
+
+		let path = self.get_media_file(&key);
+		let Ok(metadata) = fs::metadata(&path).await else {
+			debug!(%mxc, ?path, "Skipping media missing from the filesystem");
+			continue;
+		};
+
+		let created = metadata
+			.created()
+			.or_else(|_| metadata.modified())
+			.map(millis)
+			.unwrap_or_default();
+
+		if let Some(entry) = entries.get_mut(<&MxcUri>::from(mxc)) {
+			entry.size = entry.size.saturating_add(metadata.len());
+			entry.last_access = entry.last_access.max(created);
+			continue;
+		}
+
+		let last_access = self.db.last_access(&parsed).await.unwrap_or(created);
+		let entry = Entry {
+			mxc: mxc.into(),
+			local: self.services.globals.server_is_ours(parsed.server_name),
+			size: metadata.len(),
+			last_access: last_access.max(created),
+		};
+
+		entries.insert(entry.mxc.clone(), entry);
+	}
+
+	Ok(entries.into_values().collect())
+}
+
+/// Media which is never deleted to enforce retention: quarantined media, kept
+/// as evidence, and the avatars of local users if configured.
+#[implement(super::Service)]
+async fn exempt(&self) -> HashSet<OwnedMxcUri> {
+	let mut exempt: HashSet<_> = self.list_quarantined().await.into_iter().collect();
+	if !self
+		.services
+		.server
+		.config
+		.media_retention_exempt_local_avatars
+	{
+		return exempt;
+	}
+
+	let avatars: Vec<OwnedMxcUri> = self
+		.services
+		.users
+		.list_local_users()
+		.then(|user_id| self.services.users.avatar_url(user_id))
+		.ready_filter_map(Result::ok)
+		.collect()
+		.await;
+
+	exempt.extend(avatars);
+	exempt
+}
+
+fn millis(time: SystemTime) -> u64 {
+	time.duration_since(UNIX_EPOCH)
+		.ok()
+		.and_then(|since| since.as_millis().try_into().ok())
+		.unwrap_or_default()
+}
@@ -103,3 +103,24 @@ async fn long_file_names_works() {
 		r.to_str().unwrap().len()
 	);
 }
+
+#[test]
+#[cfg(feature = "url_preview")]
+fn oembed_link_discovery() {
+	use super::preview::find_oembed_url;
+
+	let html = r#"<html><head>
+		<link rel="stylesheet" href="/style.css">
+		<link rel="alternate" type="application/json+oembed"
+			href="/oembed?url=https%3A%2F%2Fexample.com%2Fvideo&amp;format=json" title="Video">
+		</head></html>"#;
+
+	let url = find_oembed_url(html, "https://example.com/video").expect("oEmbed link found");
+	assert_eq!(
+		url.as_str(),
+		"https://example.com/oembed?url=https%3A%2F%2Fexample.com%2Fvideo&format=json"
+	);
+
+	let html = r#"<link rel="alternate" type="text/xml+oembed" href="/oembed.xml">"#;
+	assert!(find_oembed_url(html, "https://example.com/").is_none(), "XML oEmbed used");
+}
@@ -7,7 +7,7 @@
 
 use std::{cmp, num::Saturating as Sat};
 
-use conduwuit::{checked, err, implement, Result};
+use conduwuit::{checked, err, implement, Err, Result};
 use ruma::{http_headers::ContentDisposition, media::Method, Mxc, UInt, UserId};
 use tokio::{
 	fs,
@@ -62,12 +62,18 @@ impl super::Service {
 	/// which crops the image afterwards.
 	#[tracing::instrument(skip(self), name = "thumbnail", level = "debug")]
 	pub async fn get_thumbnail(&self, mxc: &Mxc<'_>, dim: &Dim) -> Result<Option<FileMeta>> {
+		if self.is_quarantined(mxc).await {
+			return Err!(Request(NotFound("Media not found.")));
+		}
+
 		// 0, 0 because that's the original file
 		let dim = dim.normalized();
 
 		if let Ok(metadata) = self.db.search_file_metadata(mxc, &dim).await {
+			self.record_access(mxc);
 			self.get_thumbnail_saved(metadata).await
 		} else if let Ok(metadata) = self.db.search_file_metadata(mxc, &Dim::default()).await {
+			self.record_access(mxc);
 			self.get_thumbnail_generate(mxc, &dim, metadata).await
 		} else {
 			Ok(None)
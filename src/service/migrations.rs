@@ -1,9 +1,10 @@
-use std::cmp;
+use std::{cmp, collections::BTreeMap};
 
 use conduwuit::{
 	debug, debug_info, debug_warn, error, info,
 	result::NotFound,
 	utils::{
+		self,
 		stream::{TryExpect, TryIgnore},
 		IterStream, ReadyExt,
 	},
@@ -16,7 +17,7 @@ use ruma::{
 		push_rules::PushRulesEvent, room::member::MembershipState, GlobalAccountDataEventType,
 	},
 	push::Ruleset,
-	OwnedUserId, RoomId, UserId,
+	OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 
 use crate::{media, Services};
@@ -73,6 +74,11 @@ async fn fresh(services: &Services) -> Result<()> {
 	db["global"].insert(b"retroactively_fix_bad_data_from_roomuserid_joined", []);
 	db["global"].insert(b"fix_referencedevents_missing_sep", []);
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
+	db["global"].insert(b"fix_userdevicetxnid_response_timestamps", []);
+	db["global"].insert(b"populate_userid_accountdatasize", []);
+	db["global"].insert(b"index_public_rooms", []);
+	db["global"].insert(b"fix_userdevicesessionid_uiaainfo_timestamps", []);
+	db["global"].insert(b"index_user_directory", []);
 
 	// Create the admin room and server user on first run
 	crate::admin::create_admin_room(services).boxed().await?;
@@ -142,6 +148,42 @@ async fn migrate(services: &Services) -> Result<()> {
 		fix_readreceiptid_readreceipt_duplicates(services).await?;
 	}
 
+	if db["global"]
+		.get(b"fix_userdevicetxnid_response_timestamps")
+		.await
+		.is_not_found()
+	{
+		fix_userdevicetxnid_response_timestamps(services).await?;
+	}
+
+	if db["global"]
+		.get(b"populate_userid_accountdatasize")
+		.await
+		.is_not_found()
+	{
+		populate_userid_accountdatasize(services).await?;
+	}
+
+	if db["global"].get(b"index_public_rooms").await.is_not_found() {
+		index_public_rooms(services).await?;
+	}
+
+	if db["global"]
+		.get(b"fix_userdevicesessionid_uiaainfo_timestamps")
+		.await
+		.is_not_found()
+	{
+		fix_userdevicesessionid_uiaainfo_timestamps(services).await?;
+	}
+
+	if db["global"]
+		.get(b"index_user_directory")
+		.await
+		.is_not_found()
+	{
+		index_user_directory(services).await?;
+	}
+
 	let version_match = services.globals.db.database_version().await == DATABASE_VERSION
 		|| services.globals.db.database_version().await == CONDUIT_DATABASE_VERSION;
 
@@ -563,3 +605,161 @@ async fn fix_readreceiptid_readreceipt_duplicates(services: &Services) -> Result
 	db["global"].insert(b"fix_readreceiptid_readreceipt_duplicates", []);
 	db.db.sort()
 }
+
+/// Transaction records are now timestamped so they can expire; stamp those
+/// written before with the current time. Unstamped records are either empty
+/// or an event ID, while stamped records lead with a zero byte.
+async fn fix_userdevicetxnid_response_timestamps(services: &Services) -> Result {
+	warn!("Adding timestamps to entries in userdevicetxnid_response...");
+
+	let db = &services.db;
+	let cork = db.cork_and_sync();
+	let userdevicetxnid_response = db["userdevicetxnid_response"].clone();
+
+	let now = utils::time::now_secs().to_be_bytes();
+	let (mut total, mut fixed): (usize, usize) = (0, 0);
+	userdevicetxnid_response
+		.raw_stream()
+		.expect_ok()
+		.ready_for_each(|(key, val)| {
+			let stamped = val.first().is_some_and(|&b| b == 0);
+			if !stamped {
+				userdevicetxnid_response.insert(key, [now.as_slice(), val].concat());
+			}
+
+			fixed = fixed.saturating_add((!stamped).into());
+			total = total.saturating_add(1);
+		})
+		.await;
+
+	drop(cork);
+	info!(?total, ?fixed, "Added timestamps to entries in userdevicetxnid_response.");
+
+	db["global"].insert(b"fix_userdevicetxnid_response_timestamps", []);
+	db.db.sort()
+}
+
+/// Total the account data stored for each user so the per-user limit can be
+/// enforced without scanning it on every write.
+async fn populate_userid_accountdatasize(services: &Services) -> Result {
+	warn!("Totalling account data size for each user...");
+
+	let db = &services.db;
+	let cork = db.cork_and_sync();
+
+	let mut totals: BTreeMap<OwnedUserId, usize> = BTreeMap::new();
+	db["roomuserdataid_accountdata"]
+		.raw_stream()
+		.expect_ok()
+		.ready_for_each(|(key, val)| {
+			// The key is room_id (or empty for global data), user_id, count and type.
+			let Some(user_id) = key
+				.split(|&b| b == database::SEP)
+				.nth(1)
+				.and_then(|user_id| std::str::from_utf8(user_id).ok())
+				.and_then(|user_id| UserId::parse(user_id).ok())
+			else {
+				return;
+			};
+
+			let total = totals.entry(user_id).or_default();
+			*total = total.saturating_add(val.len());
+		})
+		.await;
+
+	for (user_id, total) in &totals {
+		services.account_data.set_total_size(user_id, *total);
+	}
+
+	drop(cork);
+	info!(users = ?totals.len(), "Totalled account data size for each user.");
+
+	db["global"].insert(b"populate_userid_accountdatasize", []);
+	db.db.sort()
+}
+
+/// Index the published rooms by joined member count, keywords and room type
+/// so the room directory can be paged and filtered without loading every room.
+async fn index_public_rooms(services: &Services) -> Result {
+	warn!("Indexing the public room directory...");
+
+	let db = &services.db;
+	let cork = db.cork_and_sync();
+
+	let room_ids: Vec<OwnedRoomId> = services
+		.rooms
+		.directory
+		.public_rooms()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for room_id in &room_ids {
+		services.rooms.directory.reindex(room_id).await;
+	}
+
+	drop(cork);
+	info!(rooms = ?room_ids.len(), "Indexed the public room directory.");
+
+	db["global"].insert(b"index_public_rooms", []);
+	db.db.sort()
+}
+
+async fn fix_userdevicesessionid_uiaainfo_timestamps(services: &Services) -> Result {
+	warn!("Adding timestamps to entries in userdevicesessionid_uiaainfo...");
+
+	let db = &services.db;
+	let cork = db.cork_and_sync();
+	let userdevicesessionid_uiaainfo = db["userdevicesessionid_uiaainfo"].clone();
+
+	let now = utils::time::now_secs().to_be_bytes();
+	let (mut total, mut fixed): (usize, usize) = (0, 0);
+	userdevicesessionid_uiaainfo
+		.raw_stream()
+		.expect_ok()
+		.ready_for_each(|(key, val)| {
+			let stamped = val.first().is_some_and(|&b| b == 0);
+			if !stamped {
+				userdevicesessionid_uiaainfo.insert(key, [now.as_slice(), val].concat());
+			}
+
+			fixed = fixed.saturating_add((!stamped).into());
+			total = total.saturating_add(1);
+		})
+		.await;
+
+	drop(cork);
+	info!(?total, ?fixed, "Added timestamps to entries in userdevicesessionid_uiaainfo.");
+
+	db["global"].insert(b"fix_userdevicesessionid_uiaainfo_timestamps", []);
+	db.db.sort()
+}
+
+/// Index every known user by the words of its user ID and display name for
+/// the user directory search.
+async fn index_user_directory(services: &Services) -> Result {
+	warn!("Indexing the user directory...");
+
+	let db = &services.db;
+	let cork = db.cork_and_sync();
+
+	let user_ids: Vec<OwnedUserId> = services
+		.users
+		.stream()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for user_id in &user_ids {
+		let displayname = services.users.displayname(user_id).await.ok();
+		services
+			.users
+			.reindex_directory(user_id, displayname.as_deref());
+	}
+
+	drop(cork);
+	info!(users = ?user_ids.len(), "Indexed the user directory.");
+
+	db["global"].insert(b"index_user_directory", []);
+	db.db.sort()
+}
@@ -8,17 +8,25 @@ pub mod services;
 pub mod account_data;
 pub mod admin;
 pub mod appservice;
+pub mod bus;
 pub mod client;
 pub mod emergency;
 pub mod globals;
+pub mod hooks;
 pub mod key_backups;
 pub mod media;
+pub mod moderation;
 pub mod presence;
+pub mod profiles;
 pub mod pusher;
+pub mod ratelimit;
+pub mod registration_tokens;
 pub mod resolver;
 pub mod rooms;
 pub mod sending;
 pub mod server_keys;
+pub mod spamcheck;
+pub mod sso;
 pub mod sync;
 pub mod transaction_ids;
 pub mod uiaa;
@@ -31,7 +39,10 @@ extern crate conduwuit_database as database;
 pub use conduwuit::{pdu, PduBuilder, PduCount, PduEvent};
 pub(crate) use service::{Args, Dep, Service};
 
-pub use crate::services::Services;
+pub use crate::{
+	manager::{WorkerState, WorkerStatus},
+	services::Services,
+};
 
 conduwuit::mod_ctor! {}
 conduwuit::mod_dtor! {}
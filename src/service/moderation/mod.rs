@@ -0,0 +1,9 @@
+pub mod policy;
+pub mod reports;
+
+use std::sync::Arc;
+
+pub struct Service {
+	pub policy: Arc<policy::Service>,
+	pub reports: Arc<reports::Service>,
+}
@@ -0,0 +1,629 @@
+//! Enforcement of moderation policy lists: rooms of `m.policy.rule.*` state
+//! events (MSC2313), like the code of conduct ban list of matrix.org.
+//!
+//! The server user joins the lists subscribed to, whose rules are kept here
+//! and updated as new policy events arrive. Rules with a recommendation of
+//! `policy_list_recommendations` are enforced according to the config: users
+//! are banned from the rooms the server user is joined to, as are those
+//! joining them later, federation with servers is denied and rooms are banned
+//! from local users joining.
+
+mod tests;
+
+use std::{
+	collections::{BTreeMap, HashMap, HashSet},
+	fmt,
+	fmt::Write,
+	sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, err, implement, info,
+	utils::{stream::TryIgnore, IterStream, ReadyExt},
+	warn, Err, PduBuilder, PduEvent, Result, Server,
+};
+use database::Map;
+use futures::{FutureExt, StreamExt};
+use loole::{Receiver, Sender};
+use regex::RegexSet;
+use ruma::{
+	events::{
+		room::member::{MembershipState, RoomMemberEventContent},
+		TimelineEventType,
+	},
+	OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, ServerName, UserId,
+};
+use serde::Deserialize;
+
+use crate::{globals, rooms, Dep};
+
+pub struct Service {
+	db: Data,
+	services: Services,
+	rules: RwLock<BTreeMap<OwnedRoomId, Rules>>,
+	denied: RwLock<DeniedServers>,
+	channel: (Sender<OwnedEventId>, Receiver<OwnedEventId>),
+}
+
+struct Data {
+	roomid_policylist: Arc<Map>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	metadata: Dep<rooms::metadata::Service>,
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+/// The rules of a policy list by kind and state key.
+type Rules = HashMap<(Kind, String), Rule>;
+
+/// The entities of the server rules enforced, compiled once the rules change
+/// rather than matched one by one on every federation request.
+#[derive(Default)]
+struct DeniedServers {
+	literals: HashSet<String>,
+	globs: Option<RegexSet>,
+}
+
+/// What a rule applies to.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Kind {
+	User,
+	Server,
+	Room,
+}
+
+/// The content of a policy rule event; its entity is a glob.
+#[derive(Clone, Debug, Deserialize)]
+pub struct Rule {
+	pub entity: String,
+	pub recommendation: String,
+	#[serde(default)]
+	pub reason: Option<String>,
+}
+
+/// What enforcing a rule does.
+#[derive(Clone, Debug)]
+pub enum Action {
+	BanUser {
+		room_id: OwnedRoomId,
+		user_id: OwnedUserId,
+		reason: Option<String>,
+	},
+	DenyServer {
+		entity: String,
+	},
+	BanRoom {
+		room_id: OwnedRoomId,
+	},
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				roomid_policylist: args.db["roomid_policylist"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+			rules: RwLock::default(),
+			denied: RwLock::default(),
+			channel: loole::unbounded(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let room_ids: Vec<OwnedRoomId> = self
+			.db
+			.roomid_policylist
+			.keys()
+			.ignore_err()
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for room_id in room_ids {
+			let rules = self.load(&room_id).await;
+			debug!(%room_id, "Loaded {} policy rules", rules.len());
+			self.rules.write().expect("locked").insert(room_id, rules);
+		}
+
+		self.compile_denied();
+		let receiver = self.channel.1.clone();
+		while let Ok(event_id) = receiver.recv_async().await {
+			if let Err(e) = self.handle(&event_id).await {
+				warn!(%event_id, "Failed to enforce policy rule: {e}");
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) {
+		let (sender, _) = &self.channel;
+		if !sender.is_closed() {
+			sender.close();
+		}
+	}
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let rules = self.rules.read()?;
+		let count: usize = rules.values().map(HashMap::len).sum();
+
+		writeln!(out, "policy_lists: {}", rules.len())?;
+		writeln!(out, "policy_rules: {count}")?;
+
+		Ok(())
+	}
+
+	fn queue_len(&self) -> Option<usize> { Some(self.channel.0.len()) }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Kind {
+	/// The kind of the rules of an event type, including the legacy types
+	/// still sent by some moderation bots.
+	#[must_use]
+	pub fn from_event_type(kind: &str) -> Option<Self> {
+		match kind {
+			| "m.policy.rule.user" | "m.room.rule.user" | "org.matrix.mjolnir.rule.user" =>
+				Some(Self::User),
+			| "m.policy.rule.server"
+			| "m.room.rule.server"
+			| "org.matrix.mjolnir.rule.server" => Some(Self::Server),
+			| "m.policy.rule.room" | "m.room.rule.room" | "org.matrix.mjolnir.rule.room" =>
+				Some(Self::Room),
+			| _ => None,
+		}
+	}
+}
+
+impl fmt::Display for Action {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		match self {
+			| Self::BanUser { room_id, user_id, reason } => {
+				write!(f, "ban {user_id} from {room_id}")?;
+				if let Some(reason) = reason {
+					write!(f, " ({reason})")?;
+				}
+
+				Ok(())
+			},
+			| Self::DenyServer { entity } => write!(f, "deny federation with {entity}"),
+			| Self::BanRoom { room_id } => write!(f, "ban {room_id}"),
+		}
+	}
+}
+
+/// Subscribe to a policy list the server user is joined to, returning the
+/// actions enforcing its current rules, which are not taken yet.
+#[implement(Service)]
+pub async fn subscribe(&self, room_id: &RoomId) -> Result<Vec<Action>> {
+	let actions = self.dry_run(room_id).await?;
+	let rules = self.load(room_id).await;
+
+	self.db.roomid_policylist.insert(room_id, []);
+	self.rules
+		.write()
+		.expect("locked")
+		.insert(room_id.to_owned(), rules);
+
+	self.compile_denied();
+
+	Ok(actions)
+}
+
+/// Stop enforcing the rules of a policy list. Users and rooms banned already
+/// stay banned. Returns whether it was subscribed to.
+#[implement(Service)]
+pub fn unsubscribe(&self, room_id: &RoomId) -> bool {
+	self.db.roomid_policylist.remove(room_id);
+	let subscribed = self
+		.rules
+		.write()
+		.expect("locked")
+		.remove(room_id)
+		.is_some();
+
+	self.compile_denied();
+
+	subscribed
+}
+
+#[implement(Service)]
+#[must_use]
+pub fn is_subscribed(&self, room_id: &RoomId) -> bool {
+	self.rules.read().expect("locked").contains_key(room_id)
+}
+
+/// The policy lists subscribed to with their number of rules.
+#[implement(Service)]
+#[must_use]
+pub fn list(&self) -> Vec<(OwnedRoomId, usize)> {
+	self.rules
+		.read()
+		.expect("locked")
+		.iter()
+		.map(|(room_id, rules)| (room_id.clone(), rules.len()))
+		.collect()
+}
+
+/// The actions the current rules of a policy list would take, whether it is
+/// subscribed to or not.
+#[implement(Service)]
+pub async fn dry_run(&self, room_id: &RoomId) -> Result<Vec<Action>> {
+	let server_user = &self.services.globals.server_user;
+	if !self
+		.services
+		.state_cache
+		.is_joined(server_user, room_id)
+		.await
+	{
+		return Err!(Request(Forbidden("{server_user} is not joined to {room_id}.")));
+	}
+
+	let rules = self.load(room_id).await;
+	let mut actions = Vec::new();
+	for ((kind, _), rule) in &rules {
+		actions.extend(self.actions(*kind, rule).await);
+	}
+
+	Ok(actions)
+}
+
+/// Take the actions enforcing rules, returning how many succeeded.
+#[implement(Service)]
+pub async fn apply(&self, actions: &[Action]) -> usize {
+	let mut applied: usize = 0;
+	for action in actions {
+		match self.apply_action(action).await {
+			| Ok(()) => {
+				info!("Policy list enforcement: {action}");
+				applied = applied.saturating_add(1);
+			},
+			| Err(e) => warn!("Policy list enforcement failed to {action}: {e}"),
+		}
+	}
+
+	applied
+}
+
+/// Queue a new event for enforcement if it is a policy rule of a list
+/// subscribed to, or a join to be checked against the user rules.
+#[implement(Service)]
+pub fn notify(&self, pdu: &PduEvent) {
+	if pdu.state_key.is_none() {
+		return;
+	}
+
+	let queued = if pdu.kind == TimelineEventType::RoomMember {
+		self.services.server.config.policy_list_ban_users
+			&& !self.is_subscribed(&pdu.room_id)
+			&& !self.rules.read().expect("locked").is_empty()
+			&& pdu
+				.get_content::<RoomMemberEventContent>()
+				.is_ok_and(|content| content.membership == MembershipState::Join)
+	} else {
+		Kind::from_event_type(&pdu.kind.to_string()).is_some() && self.is_subscribed(&pdu.room_id)
+	};
+
+	if queued {
+		self.channel.0.send(pdu.event_id.clone()).ok();
+	}
+}
+
+/// Whether federation with the server is denied by the server rules of the
+/// subscribed policy lists.
+#[implement(Service)]
+#[must_use]
+pub fn is_server_denied(&self, server_name: &ServerName) -> bool {
+	if !self.services.server.config.policy_list_deny_servers
+		|| self.services.globals.server_is_ours(server_name)
+	{
+		return false;
+	}
+
+	let denied = self.denied.read().expect("locked");
+	denied.literals.contains(server_name.as_str())
+		|| denied
+			.globs
+			.as_ref()
+			.is_some_and(|globs| globs.is_match(server_name.as_str()))
+}
+
+/// Compile the entities of the server rules enforced after the rules changed.
+#[implement(Service)]
+fn compile_denied(&self) {
+	let mut literals = HashSet::new();
+	let mut globs = Vec::new();
+	for ((kind, _), rule) in self.rules.read().expect("locked").values().flatten() {
+		if *kind != Kind::Server || !self.enforced(rule) {
+			continue;
+		}
+
+		if rule.entity.contains(['*', '?']) {
+			globs.push(glob_regex(&rule.entity));
+		} else {
+			literals.insert(rule.entity.clone());
+		}
+	}
+
+	let globs = (!globs.is_empty())
+		.then(|| RegexSet::new(&globs))
+		.transpose()
+		.inspect_err(|e| warn!("Failed to compile the server rules of policy lists: {e}"))
+		.ok()
+		.flatten();
+
+	*self.denied.write().expect("locked") = DeniedServers { literals, globs };
+}
+
+#[implement(Service)]
+async fn handle(&self, event_id: &OwnedEventId) -> Result {
+	let pdu = self.services.timeline.get_pdu(event_id).await?;
+	if pdu.kind == TimelineEventType::RoomMember {
+		let user_id = UserId::parse(pdu.state_key.as_deref().unwrap_or_default())?;
+		let actions = self.join_actions(&pdu.room_id, &user_id).await;
+		self.apply(&actions).boxed().await;
+
+		return Ok(());
+	}
+
+	let kind = Kind::from_event_type(&pdu.kind.to_string())
+		.ok_or_else(|| err!("{event_id} is not a policy rule"))?;
+
+	let rules = self.load(&pdu.room_id).await;
+	self.rules
+		.write()
+		.expect("locked")
+		.insert(pdu.room_id.clone(), rules);
+
+	self.compile_denied();
+
+	// an empty or invalid rule removes the one it replaces
+	let Ok(rule) = pdu.get_content::<Rule>() else {
+		return Ok(());
+	};
+
+	debug!(%event_id, ?kind, ?rule, "New policy rule");
+	let actions = self.actions(kind, &rule).await;
+	self.apply(&actions).boxed().await;
+
+	Ok(())
+}
+
+/// The rules in the current state of a policy list.
+#[implement(Service)]
+async fn load(&self, room_id: &RoomId) -> Rules {
+	let Ok(state) = self.services.state_accessor.room_state_full(room_id).await else {
+		return Rules::new();
+	};
+
+	state
+		.into_iter()
+		.filter_map(|((event_type, state_key), pdu)| {
+			let kind = Kind::from_event_type(&event_type.to_string())?;
+			let rule = pdu.get_content::<Rule>().ok()?;
+			Some(((kind, state_key), rule))
+		})
+		.collect()
+}
+
+#[implement(Service)]
+fn enforced(&self, rule: &Rule) -> bool {
+	self.services
+		.server
+		.config
+		.policy_list_recommendations
+		.contains(&rule.recommendation)
+}
+
+/// The actions enforcing a rule; none if its recommendation is not enforced.
+#[implement(Service)]
+async fn actions(&self, kind: Kind, rule: &Rule) -> Vec<Action> {
+	let config = &self.services.server.config;
+	if !self.enforced(rule) {
+		return Vec::new();
+	}
+
+	match kind {
+		| Kind::User if config.policy_list_ban_users => self.user_actions(rule).await,
+		| Kind::Server if config.policy_list_deny_servers =>
+			vec![Action::DenyServer { entity: rule.entity.clone() }],
+		| Kind::Room if config.policy_list_ban_rooms => {
+			let Ok(room_id) = OwnedRoomId::try_from(rule.entity.as_str()) else {
+				return Vec::new();
+			};
+
+			if self.services.metadata.is_banned(&room_id).await {
+				return Vec::new();
+			}
+
+			vec![Action::BanRoom { room_id }]
+		},
+		| _ => Vec::new(),
+	}
+}
+
+/// Bans of the members matching a user rule from the rooms the server user is
+/// joined to, apart from policy lists.
+#[implement(Service)]
+async fn user_actions(&self, rule: &Rule) -> Vec<Action> {
+	let server_user = &self.services.globals.server_user;
+	let room_ids: Vec<OwnedRoomId> = self
+		.services
+		.state_cache
+		.rooms_joined(server_user)
+		.ready_filter(|room_id| !self.is_subscribed(room_id))
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	room_ids
+		.into_iter()
+		.stream()
+		.then(|room_id| async move {
+			self.services
+				.state_cache
+				.room_members(&room_id)
+				.ready_filter(|user_id| {
+					*user_id != &**server_user && glob_match(&rule.entity, user_id.as_str())
+				})
+				.map(|user_id| Action::BanUser {
+					room_id: room_id.clone(),
+					user_id: user_id.to_owned(),
+					reason: rule.reason.clone(),
+				})
+				.collect::<Vec<_>>()
+				.await
+		})
+		.collect::<Vec<_>>()
+		.await
+		.into_iter()
+		.flatten()
+		.collect()
+}
+
+/// The ban of a user who joined a room the server user is joined to, if an
+/// enforced user rule matches them.
+#[implement(Service)]
+async fn join_actions(&self, room_id: &RoomId, user_id: &UserId) -> Vec<Action> {
+	let server_user = &self.services.globals.server_user;
+	if user_id == server_user
+		|| !self
+			.services
+			.state_cache
+			.is_joined(server_user, room_id)
+			.await
+	{
+		return Vec::new();
+	}
+
+	let reason = self
+		.rules
+		.read()
+		.expect("locked")
+		.values()
+		.flatten()
+		.find(|((kind, _), rule)| {
+			*kind == Kind::User
+				&& self.enforced(rule)
+				&& glob_match(&rule.entity, user_id.as_str())
+		})
+		.map(|(_, rule)| rule.reason.clone());
+
+	reason
+		.map(|reason| Action::BanUser {
+			room_id: room_id.to_owned(),
+			user_id: user_id.to_owned(),
+			reason,
+		})
+		.into_iter()
+		.collect()
+}
+
+#[implement(Service)]
+async fn apply_action(&self, action: &Action) -> Result {
+	match action {
+		| Action::BanUser { room_id, user_id, reason } => {
+			let state_lock = self.services.state.mutex.lock(room_id).await;
+			let current_member_content = self
+				.services
+				.state_accessor
+				.get_member(room_id, user_id)
+				.await
+				.unwrap_or_else(|_| RoomMemberEventContent::new(MembershipState::Ban));
+
+			self.services
+				.timeline
+				.build_and_append_pdu(
+					PduBuilder::state(user_id.to_string(), &RoomMemberEventContent {
+						membership: MembershipState::Ban,
+						reason: reason.clone(),
+						displayname: None,
+						avatar_url: None,
+						is_direct: None,
+						join_authorized_via_users_server: None,
+						third_party_invite: None,
+						..current_member_content
+					}),
+					&self.services.globals.server_user,
+					room_id,
+					&state_lock,
+				)
+				.await?;
+		},
+		// enforced by is_server_denied as long as the rule stands
+		| Action::DenyServer { .. } => {},
+		| Action::BanRoom { room_id } => {
+			self.services.metadata.ban_room(room_id, true);
+			self.services.metadata.disable_room(room_id, true);
+		},
+	}
+
+	Ok(())
+}
+
+/// The anchored regular expression of a policy entity glob.
+fn glob_regex(pattern: &str) -> String {
+	let mut regex = String::from("^");
+	for c in pattern.chars() {
+		match c {
+			| '*' => regex.push_str(".*"),
+			| '?' => regex.push('.'),
+			| c => regex.push_str(&regex::escape(c.encode_utf8(&mut [0; 4]))),
+		}
+	}
+
+	regex.push('$');
+	regex
+}
+
+/// Match a policy entity glob, where `*` matches any number of characters and
+/// `?` any single one.
+fn glob_match(pattern: &str, value: &str) -> bool {
+	let pattern: Vec<char> = pattern.chars().collect();
+	let value: Vec<char> = value.chars().collect();
+	let (mut p, mut v) = (0_usize, 0_usize);
+	let mut backtrack: Option<(usize, usize)> = None;
+
+	while v < value.len() {
+		match pattern.get(p) {
+			| Some('*') => {
+				backtrack = Some((p, v));
+				p = p.saturating_add(1);
+			},
+			| Some(&c) if c == '?' || c == value[v] => {
+				p = p.saturating_add(1);
+				v = v.saturating_add(1);
+			},
+			| _ => {
+				let Some((star, matched)) = backtrack else {
+					return false;
+				};
+
+				backtrack = Some((star, matched.saturating_add(1)));
+				p = star.saturating_add(1);
+				v = matched.saturating_add(1);
+			},
+		}
+	}
+
+	pattern[p..].iter().all(|&c| c == '*')
+}
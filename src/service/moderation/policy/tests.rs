@@ -0,0 +1,36 @@
+#![cfg(test)]
+
+use regex::Regex;
+
+use super::{glob_match, glob_regex};
+
+#[test]
+fn glob_literal() {
+	assert!(glob_match("@spam:example.com", "@spam:example.com"));
+	assert!(!glob_match("@spam:example.com", "@spam:example.org"));
+	assert!(!glob_match("@spam:example.com", "@spam:example.com.evil"));
+}
+
+#[test]
+fn glob_wildcards() {
+	assert!(glob_match("*", "evil.example"));
+	assert!(glob_match("*.evil.example", "matrix.evil.example"));
+	assert!(!glob_match("*.evil.example", "evil.example"));
+	assert!(glob_match("@*:evil.example", "@spam:evil.example"));
+	assert!(glob_match("@spam?:example.com", "@spam1:example.com"));
+	assert!(!glob_match("@spam?:example.com", "@spam:example.com"));
+	assert!(glob_match("a*b*c", "aXbYbZc"));
+	assert!(!glob_match("a*b*c", "aXbYbZ"));
+}
+
+#[test]
+fn glob_compiled() {
+	let regex = Regex::new(&glob_regex("*.evil.example")).expect("valid regex");
+	assert!(regex.is_match("matrix.evil.example"));
+	assert!(!regex.is_match("evil.example"));
+	assert!(!regex.is_match("matrix.evil.example.org"));
+
+	let regex = Regex::new(&glob_regex("evil?.example")).expect("valid regex");
+	assert!(regex.is_match("evil1.example"));
+	assert!(!regex.is_match("evil1Xexample"));
+}
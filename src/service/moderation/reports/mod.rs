@@ -0,0 +1,178 @@
+//! Reports of events by local users, kept until the server admins resolve
+//! them. Each report is announced in the admin room with the context needed to
+//! act on it.
+
+use std::sync::Arc;
+
+use conduwuit::{
+	err, implement,
+	utils::{self, stream::TryIgnore},
+	warn, Err, PduEvent, Result,
+};
+use database::{Deserialized, Json, Map};
+use futures::Stream;
+use ruma::{
+	events::room::message::RoomMessageEventContent, Int, OwnedEventId, OwnedRoomId, OwnedUserId,
+	UserId,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{admin, globals, Dep};
+
+pub struct Service {
+	db: Data,
+	services: Services,
+}
+
+struct Data {
+	reportid_report: Arc<Map>,
+}
+
+struct Services {
+	admin: Dep<admin::Service>,
+	globals: Dep<globals::Service>,
+}
+
+/// A report of an event.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Report {
+	pub event_id: OwnedEventId,
+
+	pub room_id: OwnedRoomId,
+
+	/// Sender of the reported event.
+	pub sender: OwnedUserId,
+
+	pub reporter: OwnedUserId,
+
+	#[serde(default)]
+	pub reason: Option<String>,
+
+	/// From -100, most offensive, to 0.
+	#[serde(default)]
+	pub score: Option<Int>,
+
+	/// When the event was reported, in milliseconds since the unix epoch.
+	pub ts: u64,
+
+	/// The admin who resolved the report, and when.
+	#[serde(default)]
+	pub resolved: Option<(OwnedUserId, u64)>,
+}
+
+#[derive(Deserialize)]
+struct ExtractBody {
+	body: Option<String>,
+}
+
+/// Length of the event body quoted in the admin room.
+const BODY_EXCERPT_CHARS: usize = 500;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				reportid_report: args.db["reportid_report"].clone(),
+			},
+			services: Services {
+				admin: args.depend::<admin::Service>("admin"),
+				globals: args.depend::<globals::Service>("globals"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Report {
+	#[inline]
+	#[must_use]
+	pub fn is_resolved(&self) -> bool { self.resolved.is_some() }
+}
+
+/// Store a report of the event and announce it in the admin room, returning
+/// the ID of the report.
+#[implement(Service)]
+pub async fn report_event(
+	&self,
+	reporter: &UserId,
+	pdu: &PduEvent,
+	reason: Option<String>,
+	score: Option<Int>,
+) -> Result<u64> {
+	let report_id = self.services.globals.next_count()?;
+	let report = Report {
+		event_id: pdu.event_id.clone(),
+		room_id: pdu.room_id.clone(),
+		sender: pdu.sender.clone(),
+		reporter: reporter.to_owned(),
+		reason,
+		score,
+		ts: utils::millis_since_unix_epoch(),
+		resolved: None,
+	};
+
+	self.db.reportid_report.put(report_id, Json(&report));
+
+	let body = pdu
+		.get_content::<ExtractBody>()
+		.ok()
+		.and_then(|content| content.body)
+		.map(|body| body.chars().take(BODY_EXCERPT_CHARS).collect::<String>());
+
+	// an @room ping for urgency; plain text, as the reason and body are the
+	// reporter's and the sender's to format
+	let notice = format!(
+		"@room Event report #{report_id} received from {} -\n\nEvent ID: {}\nRoom ID: {}\nSent \
+		 By: {}\nEvent Type: {}\n\nReport Score: {}\nReport Reason: {}\n\nEvent Body:\n{}",
+		report.reporter,
+		report.event_id,
+		report.room_id,
+		report.sender,
+		pdu.kind,
+		report.score.unwrap_or_default(),
+		report.reason.as_deref().unwrap_or(""),
+		body.as_deref().unwrap_or("(none)"),
+	);
+
+	if let Err(e) = self
+		.services
+		.admin
+		.send_message(RoomMessageEventContent::text_plain(notice))
+		.await
+	{
+		warn!(%report_id, "Failed to announce event report in the admin room: {e}");
+	}
+
+	Ok(report_id)
+}
+
+/// Mark the report resolved by the admin.
+#[implement(Service)]
+pub async fn resolve(&self, report_id: u64, admin: &UserId) -> Result<Report> {
+	let mut report = self.get(report_id).await?;
+	if report.is_resolved() {
+		return Err!(Request(InvalidParam("Report #{report_id} is already resolved.")));
+	}
+
+	report.resolved = Some((admin.to_owned(), utils::millis_since_unix_epoch()));
+	self.db.reportid_report.put(report_id, Json(&report));
+
+	Ok(report)
+}
+
+#[implement(Service)]
+pub async fn get(&self, report_id: u64) -> Result<Report> {
+	self.db
+		.reportid_report
+		.qry(&report_id)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(NotFound("No report #{report_id}."))))
+}
+
+/// The reports, the oldest first.
+#[implement(Service)]
+pub fn list(&self) -> impl Stream<Item = (u64, Report)> + Send + '_ {
+	self.db.reportid_report.stream().ignore_err()
+}
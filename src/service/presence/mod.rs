@@ -14,7 +14,7 @@ use ruma::{events::presence::PresenceEvent, presence::PresenceState, OwnedUserId
 use tokio::time::sleep;
 
 use self::{data::Data, presence::Presence};
-use crate::{globals, users, Dep};
+use crate::{globals, sending, users, Dep};
 
 pub struct Service {
 	timer_channel: (Sender<TimerType>, Receiver<TimerType>),
@@ -29,6 +29,7 @@ struct Services {
 	server: Arc<Server>,
 	db: Arc<Database>,
 	globals: Dep<globals::Service>,
+	sending: Dep<sending::Service>,
 	users: Dep<users::Service>,
 }
 
@@ -50,6 +51,7 @@ impl crate::Service for Service {
 				server: args.server.clone(),
 				db: args.db.clone(),
 				globals: args.depend::<globals::Service>("globals"),
+				sending: args.depend::<sending::Service>("sending"),
 				users: args.depend::<users::Service>("users"),
 			},
 		}))
@@ -84,6 +86,8 @@ impl crate::Service for Service {
 		}
 	}
 
+	fn queue_len(&self) -> Option<usize> { Some(self.timer_channel.0.len()) }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -102,6 +106,11 @@ impl Service {
 	pub async fn ping_presence(&self, user_id: &UserId, new_state: &PresenceState) -> Result<()> {
 		const REFRESH_TIMEOUT: u64 = 60 * 1000;
 
+		// the devices of service accounts are non-interactive
+		if self.services.users.is_service_account(user_id).await {
+			return Ok(());
+		}
+
 		let last_presence = self.db.get_presence(user_id).await;
 		let state_changed = match last_presence {
 			| Err(_) => true,
@@ -147,8 +156,16 @@ impl Service {
 			.set_presence(user_id, presence_state, currently_active, last_active_ago, status_msg)
 			.await?;
 
+		if let Ok(event) = self.get_presence(user_id).await {
+			self.services
+				.sending
+				.send_ephemeral_presence(user_id, &event)
+				.await?;
+		}
+
 		if (self.timeout_remote_users || self.services.globals.user_is_local(user_id))
 			&& user_id != self.services.globals.server_user
+			&& !self.services.users.is_service_account(user_id).await
 		{
 			let timeout = match presence_state {
 				| PresenceState::Online => self.services.server.config.presence_idle_timeout_s,
@@ -0,0 +1,160 @@
+//! Profiles of remote users, fetched over federation into the local copy of
+//! the user. A copy is used for `remote_profile_cache_ttl` seconds; a lookup of
+//! an older one returns it at once while it is refreshed in the background, so
+//! only the first lookup of a user waits for its server.
+
+use std::{
+	collections::HashSet,
+	sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use conduwuit::{debug_warn, utils::time::now_millis, Result, Server};
+use database::{Deserialized, Map};
+use loole::{Receiver, Sender};
+use ruma::{api::federation::query::get_profile_information, OwnedUserId, UserId};
+
+use crate::{globals, sending, users, Dep};
+
+pub struct Service {
+	db: Data,
+	services: Services,
+	channel: (Sender<OwnedUserId>, Receiver<OwnedUserId>),
+	refreshing: Mutex<HashSet<OwnedUserId>>,
+}
+
+struct Data {
+	userid_profilefetched: Arc<Map>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	sending: Dep<sending::Service>,
+	users: Dep<users::Service>,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				userid_profilefetched: args.db["userid_profilefetched"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				sending: args.depend::<sending::Service>("sending"),
+				users: args.depend::<users::Service>("users"),
+			},
+			channel: loole::unbounded(),
+			refreshing: Mutex::default(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let receiver = self.channel.1.clone();
+		while let Ok(user_id) = receiver.recv_async().await {
+			if let Err(e) = self.fetch(&user_id).await {
+				debug_warn!(%user_id, "Failed to refresh remote profile: {e}");
+			}
+
+			self.refreshing.lock().expect("locked").remove(&user_id);
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) {
+		let (sender, _) = &self.channel;
+		if !sender.is_closed() {
+			sender.close();
+		}
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+
+	fn queue_len(&self) -> Option<usize> { Some(self.channel.0.len()) }
+}
+
+impl Service {
+	/// Make sure the local copy of a remote user's profile may be returned:
+	/// fetch it if it never was or there is no cache, or else refresh it in
+	/// the background once it is stale. Local users are ignored.
+	pub async fn refresh(&self, user_id: &UserId) {
+		if self.services.globals.user_is_local(user_id) {
+			return;
+		}
+
+		let ttl = self
+			.services
+			.server
+			.config
+			.remote_profile_cache_ttl
+			.saturating_mul(1000);
+
+		match self.fetched(user_id).await {
+			| Some(fetched) if now_millis().saturating_sub(fetched) < ttl => (),
+			| Some(_) if ttl > 0 => self.queue(user_id),
+			| _ =>
+				if let Err(e) = self.fetch(user_id).await {
+					debug_warn!(%user_id, "Failed to fetch remote profile: {e}");
+				},
+		}
+	}
+
+	fn queue(&self, user_id: &UserId) {
+		if self
+			.refreshing
+			.lock()
+			.expect("locked")
+			.insert(user_id.to_owned())
+		{
+			self.channel.0.send(user_id.to_owned()).ok();
+		}
+	}
+
+	/// Fetch the whole profile of the remote user into its local copy.
+	async fn fetch(&self, user_id: &UserId) -> Result {
+		let response = self
+			.services
+			.sending
+			.send_federation_request(
+				user_id.server_name(),
+				get_profile_information::v1::Request { user_id: user_id.to_owned(), field: None },
+			)
+			.await?;
+
+		let users = &self.services.users;
+		if !users.exists(user_id).await {
+			users.create(user_id, None)?;
+		}
+
+		let displayname = response
+			.displayname
+			.filter(|displayname| !users.is_displayname_forbidden(displayname));
+
+		users.set_displayname(user_id, displayname);
+		users.set_avatar_url(user_id, response.avatar_url);
+		users.set_blurhash(user_id, response.blurhash);
+		users.set_timezone(user_id, response.tz);
+		for (profile_key, profile_key_value) in response.custom_profile_fields {
+			users.set_profile_key(user_id, &profile_key, Some(profile_key_value));
+		}
+
+		self.db.userid_profilefetched.raw_put(user_id, now_millis());
+
+		Ok(())
+	}
+
+	/// When the profile of the user was last fetched, in milliseconds since
+	/// the unix epoch.
+	async fn fetched(&self, user_id: &UserId) -> Option<u64> {
+		self.db
+			.userid_profilefetched
+			.get(user_id)
+			.await
+			.deserialized()
+			.ok()
+	}
+}
@@ -0,0 +1,338 @@
+//! Email notifications for `m.email` pushers. The messages a user missed are
+//! collected into one digest per user, sent through the SMTP server of the
+//! config once the first of them is `smtp.digest_delay` old. Rooms the user
+//! read in the meantime are left out.
+
+use std::{
+	collections::{BTreeMap, BTreeSet},
+	fmt::Write,
+	time::{Duration, Instant},
+};
+
+use conduwuit::{
+	config::SmtpConfig, err, implement, utils::HtmlEscape, warn, Err, PduEvent, Result,
+};
+use database::Deserialized;
+use lettre::{
+	message::{Mailbox, MultiPart},
+	Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+};
+use ruma::{events::TimelineEventType, OwnedEventId, OwnedRoomId, OwnedUserId, RoomId, UserId};
+use serde::Deserialize;
+
+pub(super) type Digests = BTreeMap<OwnedUserId, Digest>;
+
+pub(super) struct Mailer {
+	transport: AsyncSmtpTransport<Tokio1Executor>,
+	from: Mailbox,
+}
+
+/// The messages a user missed since the last digest.
+pub(super) struct Digest {
+	queued: Instant,
+
+	/// The addresses of the email pushers of the user.
+	addresses: BTreeSet<String>,
+
+	/// Every missed event, counted in the digest.
+	events: BTreeSet<(OwnedRoomId, OwnedEventId)>,
+
+	/// The missed events listed in the digest, up to
+	/// `smtp.digest_max_messages`.
+	messages: Vec<Missed>,
+}
+
+struct Missed {
+	room_id: OwnedRoomId,
+	room_name: String,
+	sender: String,
+	summary: String,
+}
+
+#[derive(Deserialize)]
+struct ExtractBody {
+	body: Option<String>,
+}
+
+/// Length of the message bodies quoted in a digest.
+const SUMMARY_CHARS: usize = 200;
+
+pub(super) fn mailer(config: &SmtpConfig) -> Result<Option<Mailer>> {
+	let Some(url) = config.url.as_deref() else {
+		return Ok(None);
+	};
+
+	let transport = AsyncSmtpTransport::<Tokio1Executor>::from_url(url)
+		.map_err(|e| err!(Config("smtp.url", "Invalid SMTP server URL: {e}")))?
+		.build();
+
+	let from = config
+		.from
+		.as_deref()
+		.unwrap_or_default()
+		.parse()
+		.map_err(|e| err!(Config("smtp.from", "Invalid sender address: {e}")))?;
+
+	Ok(Some(Mailer { transport, from }))
+}
+
+/// Add the event to the digest of the user, to be sent to the address.
+#[implement(super::Service)]
+pub(super) async fn queue_email(&self, user_id: &UserId, address: &str, event: &PduEvent) {
+	let missed = Missed {
+		room_id: event.room_id.clone(),
+		room_name: self
+			.services
+			.state_accessor
+			.get_name(&event.room_id)
+			.await
+			.unwrap_or_else(|_| event.room_id.to_string()),
+		sender: self
+			.services
+			.users
+			.displayname(&event.sender)
+			.await
+			.unwrap_or_else(|_| event.sender.to_string()),
+		summary: summary(user_id, event),
+	};
+
+	let max = self.services.server.config.smtp.digest_max_messages;
+	let mut digests = self.digests.lock().expect("locked");
+	let digest = digests.entry(user_id.to_owned()).or_insert_with(|| Digest {
+		queued: Instant::now(),
+		addresses: BTreeSet::new(),
+		events: BTreeSet::new(),
+		messages: Vec::new(),
+	});
+
+	digest.addresses.insert(address.to_owned());
+
+	// The event is queued again for every other email pusher of the user.
+	let key = (event.room_id.clone(), event.event_id.clone());
+	if digest.events.insert(key) && digest.messages.len() < max {
+		digest.messages.push(missed);
+	}
+}
+
+/// Set the address an admin allowed the user to receive email notifications
+/// at, or remove it. The server cannot verify addresses itself, so only this
+/// one is accepted for the email pushers of the user.
+#[implement(super::Service)]
+pub fn set_email_address(&self, user_id: &UserId, address: Option<&str>) -> Result {
+	match address {
+		| Some(address) => {
+			let address: Address = address
+				.parse()
+				.map_err(|e| err!(Request(InvalidParam("Invalid email address: {e}"))))?;
+
+			self.db
+				.userid_emailaddress
+				.insert(user_id, address.to_string());
+		},
+		| None => self.db.userid_emailaddress.remove(user_id),
+	}
+
+	Ok(())
+}
+
+/// The address an admin allowed the user to receive email notifications at.
+#[implement(super::Service)]
+pub async fn email_address(&self, user_id: &UserId) -> Result<String> {
+	self.db
+		.userid_emailaddress
+		.get(user_id)
+		.await
+		.deserialized()
+}
+
+#[implement(super::Service)]
+pub(super) async fn email_address_allowed(&self, user_id: &UserId, address: &str) -> bool {
+	self.email_address(user_id)
+		.await
+		.is_ok_and(|allowed| allowed.eq_ignore_ascii_case(address))
+}
+
+/// Send the digests due, or all of them at shutdown.
+#[implement(super::Service)]
+pub(super) async fn send_digests(&self, all: bool) {
+	let delay = Duration::from_secs(self.services.server.config.smtp.digest_delay);
+	let due: Vec<(OwnedUserId, Digest)> = {
+		let mut digests = self.digests.lock().expect("locked");
+		let user_ids: Vec<OwnedUserId> = digests
+			.iter()
+			.filter(|(_, digest)| all || digest.queued.elapsed() >= delay)
+			.map(|(user_id, _)| user_id.clone())
+			.collect();
+
+		user_ids
+			.iter()
+			.filter_map(|user_id| digests.remove_entry(user_id))
+			.collect()
+	};
+
+	for (user_id, digest) in due {
+		if let Err(e) = self.send_digest(&user_id, digest).await {
+			warn!(%user_id, "Failed to send email notification: {e}");
+		}
+	}
+}
+
+#[implement(super::Service)]
+async fn send_digest(&self, user_id: &UserId, mut digest: Digest) -> Result {
+	let Some(mailer) = self.mailer.as_ref() else {
+		return Ok(());
+	};
+
+	let mut read = BTreeSet::new();
+	for (room_id, _) in &digest.events {
+		if self
+			.services
+			.user
+			.notification_count(user_id, room_id)
+			.await == 0
+		{
+			read.insert(room_id.clone());
+		}
+	}
+
+	digest.events.retain(|(room_id, _)| !read.contains(room_id));
+	digest
+		.messages
+		.retain(|missed| !read.contains(&missed.room_id));
+
+	let count = digest.events.len();
+	if count == 0 {
+		return Ok(());
+	}
+
+	let server_name = self.services.globals.server_name();
+	let subject = if count == 1 {
+		format!("1 unread message on {server_name}")
+	} else {
+		format!("{count} unread messages on {server_name}")
+	};
+
+	let (plain, html) = render(&subject, count, &digest.messages)?;
+	for address in &digest.addresses {
+		let sent = async {
+			let to: Mailbox = address.parse().map_err(|e| {
+				err!(Request(InvalidParam("Invalid email address {address:?}: {e}")))
+			})?;
+
+			let message = Message::builder()
+				.from(mailer.from.clone())
+				.to(to)
+				.subject(&subject)
+				.multipart(MultiPart::alternative_plain_html(plain.clone(), html.clone()))
+				.map_err(|e| err!("Failed to build email notification: {e}"))?;
+
+			mailer
+				.transport
+				.send(message)
+				.await
+				.map_err(|e| err!("Failed to send email notification to {address:?}: {e}"))
+		};
+
+		// The other addresses of the digest are still sent to
+		if let Err(e) = sent.await {
+			warn!(%user_id, "{e}");
+		}
+	}
+
+	Ok(())
+}
+
+/// Send an email to the address of a pusher of the user at once, to check
+/// the notifications reach it.
+#[implement(super::Service)]
+pub(super) async fn send_test_email(&self, user_id: &UserId, address: &str) -> Result {
+	let Some(mailer) = self.mailer.as_ref() else {
+		return Err!("Email notifications are not enabled on this server.");
+	};
+
+	let to: Mailbox = address
+		.parse()
+		.map_err(|e| err!(Request(InvalidParam("Invalid email address {address:?}: {e}"))))?;
+
+	let server_name = self.services.globals.server_name();
+	let message = Message::builder()
+		.from(mailer.from.clone())
+		.to(to)
+		.subject(format!("Test notification from {server_name}"))
+		.body(format!(
+			"This is a test of the email notifications of {user_id} on {server_name}.\n"
+		))
+		.map_err(|e| err!("Failed to build email notification: {e}"))?;
+
+	mailer
+		.transport
+		.send(message)
+		.await
+		.map_err(|e| err!("Failed to send email notification to {address:?}: {e}"))?;
+
+	Ok(())
+}
+
+/// The plain text and HTML bodies of a digest, listing the messages by room.
+fn render(subject: &str, count: usize, messages: &[Missed]) -> Result<(String, String)> {
+	let mut rooms: BTreeMap<&RoomId, Vec<&Missed>> = BTreeMap::new();
+	for missed in messages {
+		rooms.entry(&missed.room_id).or_default().push(missed);
+	}
+
+	let mut plain = format!("{subject}\n");
+	let mut html = format!(
+		"<!DOCTYPE html><html><head><meta \
+		 charset=\"utf-8\"><title>{}</title></head><body><h1>{}</h1>",
+		HtmlEscape(subject),
+		HtmlEscape(subject),
+	);
+
+	for room in rooms.values() {
+		let room_name = room
+			.first()
+			.map(|missed| missed.room_name.as_str())
+			.unwrap_or_default();
+
+		writeln!(plain, "\n{room_name}")?;
+		write!(html, "<h2>{}</h2><ul>", HtmlEscape(room_name))?;
+		for missed in room {
+			writeln!(plain, "- {}: {}", missed.sender, missed.summary)?;
+			write!(
+				html,
+				"<li><b>{}</b>: {}</li>",
+				HtmlEscape(&missed.sender),
+				HtmlEscape(&missed.summary)
+			)?;
+		}
+
+		html.push_str("</ul>");
+	}
+
+	let unlisted = count.saturating_sub(messages.len());
+	if unlisted > 0 {
+		writeln!(plain, "\n...and {unlisted} more.")?;
+		write!(html, "<p>...and {unlisted} more.</p>")?;
+	}
+
+	html.push_str("</body></html>");
+
+	Ok((plain, html))
+}
+
+/// What the event says, as listed in a digest.
+fn summary(user_id: &UserId, event: &PduEvent) -> String {
+	match event.kind {
+		| TimelineEventType::RoomEncrypted => "Sent an encrypted message".to_owned(),
+		| TimelineEventType::RoomMember if event.state_key.as_deref() == Some(user_id.as_str()) =>
+			"Invited you to the room".to_owned(),
+		| _ => event
+			.get_content::<ExtractBody>()
+			.ok()
+			.and_then(|content| content.body)
+			.map_or_else(
+				|| format!("Sent a {} event", event.kind),
+				|body| body.chars().take(SUMMARY_CHARS).collect(),
+			),
+	}
+}
@@ -1,14 +1,24 @@
-use std::{fmt::Debug, mem, sync::Arc};
+mod email;
+mod rules;
+
+use std::{
+	fmt::Debug,
+	mem,
+	sync::{Arc, Mutex},
+	time::Duration,
+};
 
+use async_trait::async_trait;
 use bytes::BytesMut;
 use conduwuit::{
 	debug_warn, err, trace,
-	utils::{stream::TryIgnore, string_from_bytes},
-	warn, Err, PduEvent, Result,
+	utils::{math::usize_from_f64, stream::TryIgnore, string_from_bytes},
+	warn, Err, PduEvent, Result, Server,
 };
 use database::{Deserialized, Ignore, Interfix, Json, Map};
 use futures::{Stream, StreamExt};
 use ipaddress::IPAddress;
+use lettre::Address;
 use ruma::{
 	api::{
 		client::push::{set_pusher, Pusher, PusherKind},
@@ -23,50 +33,100 @@ use ruma::{
 		TimelineEventType,
 	},
 	push::{
-		Action, PushConditionPowerLevelsCtx, PushConditionRoomCtx, PushFormat, Ruleset, Tweak,
+		Action, AnyPushRuleRef, FlattenedJson, PushConditionPowerLevelsCtx, PushConditionRoomCtx,
+		PushFormat, Ruleset, Tweak,
 	},
 	serde::Raw,
 	uint, RoomId, UInt, UserId,
 };
+use tokio::{sync::Notify, time::interval};
+
+use self::email::{Digests, Mailer};
+use crate::{account_data, client, globals, rooms, sending, users, Dep};
 
-use crate::{client, globals, rooms, sending, users, Dep};
+/// How often the digests of email notifications due are sent.
+const DIGEST_INTERVAL: Duration = Duration::from_secs(30);
 
 pub struct Service {
 	db: Data,
 	services: Services,
+	mailer: Option<Mailer>,
+	digests: Mutex<Digests>,
+	rules: Mutex<rules::Cache>,
+	interrupt: Notify,
 }
 
 struct Services {
+	server: Arc<Server>,
 	globals: Dep<globals::Service>,
+	account_data: Dep<account_data::Service>,
 	client: Dep<client::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
+	user: Dep<rooms::user::Service>,
 	users: Dep<users::Service>,
 	sending: Dep<sending::Service>,
 }
 
 struct Data {
 	senderkey_pusher: Arc<Map>,
+	userid_emailaddress: Arc<Map>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		let pushrules_cache_capacity =
+			f64::from(config.pushrules_cache_capacity) * config.cache_capacity_modifier;
+
 		Ok(Arc::new(Self {
 			db: Data {
 				senderkey_pusher: args.db["senderkey_pusher"].clone(),
+				userid_emailaddress: args.db["userid_emailaddress"].clone(),
 			},
 			services: Services {
+				server: args.server.clone(),
 				globals: args.depend::<globals::Service>("globals"),
+				account_data: args.depend::<account_data::Service>("account_data"),
 				client: args.depend::<client::Service>("client"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				user: args.depend::<rooms::user::Service>("rooms::user"),
 				users: args.depend::<users::Service>("users"),
 				sending: args.depend::<sending::Service>("sending"),
 			},
+			mailer: email::mailer(&args.server.config.smtp)?,
+			digests: Mutex::default(),
+			rules: Mutex::new(rules::Cache::new(usize_from_f64(pushrules_cache_capacity)?)),
+			interrupt: Notify::new(),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		if self.mailer.is_none() {
+			return Ok(());
+		}
+
+		let mut interval = interval(DIGEST_INTERVAL);
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = interval.tick() => self.send_digests(false).await,
+			}
+		}
+
+		// The messages missed shortly before shutdown are still sent.
+		self.send_digests(true).await;
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn clear_cache(&self) { self.rules.lock().expect("locked").clear(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -122,6 +182,29 @@ impl Service {
 					}
 				}
 
+				if let PusherKind::Email(_) = pusher_kind {
+					if self.mailer.is_none() {
+						return Err!(Request(InvalidParam(
+							"Email notifications are not enabled on this server."
+						)));
+					}
+
+					if pushkey.parse::<Address>().is_err() {
+						return Err!(Request(InvalidParam(
+							"Push key of an email pusher must be an email address."
+						)));
+					}
+
+					if !self.email_address_allowed(sender, pushkey).await {
+						return Err!(Request(
+							Forbidden(
+								"Notifications may only be sent to the email address an admin \
+								 set 							 for your account."
+							)
+						));
+					}
+				}
+
 				let key = (sender, data.pusher.ids.pushkey.as_str());
 				self.db.senderkey_pusher.put(key, Json(pusher));
 			},
@@ -257,13 +340,13 @@ impl Service {
 		}
 	}
 
-	#[tracing::instrument(skip(self, user, unread, pusher, ruleset, pdu))]
+	#[tracing::instrument(skip(self, user, unread, pusher, rules, pdu))]
 	pub async fn send_push_notice(
 		&self,
 		user: &UserId,
 		unread: UInt,
 		pusher: &Pusher,
-		ruleset: Ruleset,
+		rules: &Ruleset,
 		pdu: &PduEvent,
 	) -> Result<()> {
 		let mut notify = None;
@@ -282,7 +365,7 @@ impl Service {
 			.unwrap_or_default();
 
 		for action in self
-			.get_actions(user, &ruleset, &power_levels, &pdu.to_sync_room_event(), &pdu.room_id)
+			.get_actions(user, rules, &power_levels, &pdu.to_sync_room_event(), &pdu.room_id)
 			.await
 		{
 			let n = match action {
@@ -304,22 +387,50 @@ impl Service {
 		}
 
 		if notify == Some(true) {
-			self.send_notice(unread, pusher, tweaks, pdu).await?;
+			self.send_notice(user, unread, pusher, tweaks, pdu).await?;
 		}
 		// Else the event triggered no actions
 
 		Ok(())
 	}
 
-	#[tracing::instrument(skip(self, user, ruleset, pdu), level = "debug")]
+	#[tracing::instrument(skip(self, user, rules, pdu), level = "debug")]
 	pub async fn get_actions<'a>(
 		&self,
 		user: &UserId,
-		ruleset: &'a Ruleset,
+		rules: &'a Ruleset,
 		power_levels: &RoomPowerLevelsEventContent,
 		pdu: &Raw<AnySyncTimelineEvent>,
 		room_id: &RoomId,
 	) -> &'a [Action] {
+		let ctx = self.push_context(user, power_levels, room_id).await;
+		rules.get_actions(pdu, &ctx)
+	}
+
+	/// The rules of the user matching the event, in the order they are
+	/// evaluated; the actions of the first one are those taken.
+	pub async fn matching_rules<'a>(
+		&self,
+		user: &UserId,
+		rules: &'a Ruleset,
+		power_levels: &RoomPowerLevelsEventContent,
+		pdu: &Raw<AnySyncTimelineEvent>,
+		room_id: &RoomId,
+	) -> Vec<AnyPushRuleRef<'a>> {
+		let ctx = self.push_context(user, power_levels, room_id).await;
+		let event = FlattenedJson::from_raw(pdu);
+		rules
+			.iter()
+			.filter(|rule| rule.applies(&event, &ctx))
+			.collect()
+	}
+
+	async fn push_context(
+		&self,
+		user: &UserId,
+		power_levels: &RoomPowerLevelsEventContent,
+		room_id: &RoomId,
+	) -> PushConditionRoomCtx {
 		let power_levels = PushConditionPowerLevelsCtx {
 			users: power_levels.users.clone(),
 			users_default: power_levels.users_default,
@@ -342,26 +453,49 @@ impl Service {
 			.await
 			.unwrap_or_else(|_| user.localpart().to_owned());
 
-		let ctx = PushConditionRoomCtx {
+		PushConditionRoomCtx {
 			room_id: room_id.to_owned(),
 			member_count: room_joined_count,
 			user_id: user.to_owned(),
 			user_display_name,
 			power_levels: Some(power_levels),
-		};
+		}
+	}
+
+	/// Send a notification which is not about any event through the pusher,
+	/// to check it works: a high priority one with a badge count of 1 for an
+	/// HTTP pusher, an email at once for an email one.
+	pub async fn send_test_notice(&self, user: &UserId, pusher: &Pusher) -> Result {
+		match &pusher.kind {
+			| PusherKind::Http(http) => {
+				let mut device =
+					Device::new(pusher.ids.app_id.clone(), pusher.ids.pushkey.clone());
+				device.data.data.clone_from(&http.data);
+				device.data.format.clone_from(&http.format);
 
-		ruleset.get_actions(pdu, &ctx)
+				let mut notifi = Notification::new(vec![device]);
+				notifi.prio = NotificationPriority::High;
+				notifi.counts = NotificationCounts::new(uint!(1), uint!(0));
+
+				self.send_request(&http.url, send_event_notification::v1::Request::new(notifi))
+					.await?;
+
+				Ok(())
+			},
+			| PusherKind::Email(_) => self.send_test_email(user, &pusher.ids.pushkey).await,
+			| _ => Err!("Pushers of this kind can not be tested."),
+		}
 	}
 
-	#[tracing::instrument(skip(self, unread, pusher, tweaks, event))]
+	#[tracing::instrument(skip(self, user, unread, pusher, tweaks, event))]
 	async fn send_notice(
 		&self,
+		user: &UserId,
 		unread: UInt,
 		pusher: &Pusher,
 		tweaks: Vec<Tweak>,
 		event: &PduEvent,
 	) -> Result {
-		// TODO: email
 		match &pusher.kind {
 			| PusherKind::Http(http) => {
 				let url = &http.url;
@@ -471,8 +605,13 @@ impl Service {
 
 				Ok(())
 			},
-			// TODO: Handle email
-			//PusherKind::Email(_) => Ok(()),
+			| PusherKind::Email(_) => {
+				if self.email_address_allowed(user, &pusher.ids.pushkey).await {
+					self.queue_email(user, &pusher.ids.pushkey, event).await;
+				}
+
+				Ok(())
+			},
 			| _ => Ok(()),
 		}
 	}
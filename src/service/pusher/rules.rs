@@ -0,0 +1,80 @@
+//! The push rules of local users, compiled from their account data once and
+//! cached until it changes. Events with `m.mentions` are evaluated per
+//! intentional mentions: the ruleset itself skips the legacy rules matching
+//! the display name, user name or `@room` in the body for them.
+
+use std::sync::Arc;
+
+use conduwuit::implement;
+use lru_cache::LruCache;
+use ruma::{
+	events::{push_rules::PushRulesEvent, GlobalAccountDataEventType},
+	push::Ruleset,
+	OwnedUserId, UserId,
+};
+
+/// The compiled push rules of the users evaluated lately.
+pub(super) struct Cache {
+	rules: LruCache<OwnedUserId, Arc<Ruleset>>,
+
+	/// Bumped whenever rules are invalidated, so that rules compiled from
+	/// account data read before are not cached after it.
+	generation: u64,
+}
+
+impl Cache {
+	pub(super) fn new(capacity: usize) -> Self {
+		Self {
+			rules: LruCache::new(capacity),
+			generation: 0,
+		}
+	}
+
+	pub(super) fn clear(&mut self) {
+		self.rules.clear();
+		self.generation = self.generation.wrapping_add(1);
+	}
+}
+
+/// The push rules of the user, compiled from its account data unless cached.
+#[implement(super::Service)]
+pub async fn rules(&self, user_id: &UserId) -> Arc<Ruleset> {
+	let generation = {
+		let mut cache = self.rules.lock().expect("locked");
+		if let Some(rules) = cache.rules.get_mut(user_id) {
+			return rules.clone();
+		}
+
+		cache.generation
+	};
+
+	let mut ruleset = self
+		.services
+		.account_data
+		.get_global(user_id, GlobalAccountDataEventType::PushRules)
+		.await
+		.map_or_else(
+			|_| Ruleset::server_default(user_id),
+			|ev: PushRulesEvent| ev.content.global,
+		);
+
+	// Rules added to the server default since the user's were stored, like the
+	// intentional mention ones, apply to them as well.
+	ruleset.update_with_server_default(Ruleset::server_default(user_id));
+
+	let rules = Arc::new(ruleset);
+	let mut cache = self.rules.lock().expect("locked");
+	if cache.generation == generation {
+		cache.rules.insert(user_id.to_owned(), rules.clone());
+	}
+
+	rules
+}
+
+/// Forget the compiled push rules of the user after its account data changed.
+#[implement(super::Service)]
+pub fn invalidate_rules(&self, user_id: &UserId) {
+	let mut cache = self.rules.lock().expect("locked");
+	cache.rules.remove(user_id);
+	cache.generation = cache.generation.wrapping_add(1);
+}
@@ -0,0 +1,303 @@
+//! Rate limiting of client requests by token buckets, one per class of
+//! endpoint and user (or IP address, when the request is not authenticated).
+
+use std::{
+	collections::HashMap,
+	fmt::Write,
+	net::IpAddr,
+	sync::{Arc, Mutex, RwLock},
+	time::{Duration, Instant},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	config::RateLimitConfig,
+	implement,
+	utils::{bytes::pretty, stream::TryIgnore},
+	Err, Result, Server,
+};
+use database::Map;
+use futures::{Stream, StreamExt};
+use ruma::{OwnedUserId, UserId};
+use tokio::{sync::Notify, time::interval};
+
+pub struct Service {
+	db: Data,
+	buckets: Mutex<HashMap<(Key, Class), Bucket>>,
+	factors: RwLock<HashMap<OwnedUserId, f64>>,
+	interrupt: Notify,
+	server: Arc<Server>,
+}
+
+struct Data {
+	userid_ratelimitexempt: Arc<Map>,
+	userid_ratelimitfactor: Arc<Map>,
+}
+
+/// Who a bucket limits.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Key {
+	User(OwnedUserId),
+	Ip(IpAddr),
+}
+
+/// The class of endpoints a bucket limits, each with its own rate.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Class {
+	Login,
+	Registration,
+	Message,
+	Join,
+	Default,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Bucket {
+	tokens: f64,
+	updated: Instant,
+}
+
+/// The refill rate per second and burst count of a class.
+type Rate = (f64, f64);
+
+/// Interval between sweeps for buckets which have filled up again.
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				userid_ratelimitexempt: args.db["userid_ratelimitexempt"].clone(),
+				userid_ratelimitfactor: args.db["userid_ratelimitfactor"].clone(),
+			},
+			buckets: Mutex::default(),
+			factors: RwLock::default(),
+			interrupt: Notify::new(),
+			server: args.server.clone(),
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		let factors: HashMap<OwnedUserId, f64> = self
+			.db
+			.userid_ratelimitfactor
+			.stream()
+			.ignore_err()
+			.filter_map(|(user_id, factor): (&UserId, String)| async move {
+				Some((user_id.to_owned(), factor.parse().ok()?))
+			})
+			.collect()
+			.await;
+
+		*self.factors.write()? = factors;
+
+		if !self.server.config.ratelimit.enable {
+			return Ok(());
+		}
+
+		let mut sweep = interval(SWEEP_INTERVAL);
+		loop {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = sweep.tick() => self.sweep(),
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let count = self.buckets.lock()?.len();
+		let bytes = count.saturating_mul(size_of::<((Key, Class), Bucket)>());
+
+		writeln!(out, "buckets: {count} ({})", pretty(bytes))?;
+		writeln!(out, "factors: {}", self.factors.read()?.len())?;
+
+		Ok(())
+	}
+
+	fn clear_cache(&self) { self.buckets.lock().expect("locked").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Class {
+	#[must_use]
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			| Self::Login => "login",
+			| Self::Registration => "registration",
+			| Self::Message => "message",
+			| Self::Join => "join",
+			| Self::Default => "default",
+		}
+	}
+
+	fn rate(self, config: &RateLimitConfig, factor: f64) -> Rate {
+		let (per_second, burst_count) = match self {
+			| Self::Login => (config.login_per_second, config.login_burst_count),
+			| Self::Registration =>
+				(config.registration_per_second, config.registration_burst_count),
+			| Self::Message => (config.message_per_second, config.message_burst_count),
+			| Self::Join => (config.join_per_second, config.join_burst_count),
+			| Self::Default => (config.default_per_second, config.default_burst_count),
+		};
+
+		(per_second * factor, (f64::from(burst_count) * factor).max(1.0))
+	}
+}
+
+impl Bucket {
+	fn refill(&mut self, (per_second, burst): Rate, now: Instant) {
+		let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+		self.tokens = elapsed.mul_add(per_second, self.tokens).min(burst);
+		self.updated = now;
+	}
+}
+
+/// Take a request from the bucket of the key and class. When the bucket is
+/// empty, the time until it holds a request again is returned instead.
+#[implement(Service)]
+pub fn limited(&self, key: Key, class: Class) -> Option<Duration> {
+	let config = &self.server.config.ratelimit;
+	let rate @ (per_second, burst) = class.rate(config, self.factor_of(&key));
+	if !config.enable || per_second <= 0.0 {
+		return None;
+	}
+
+	let now = Instant::now();
+	let mut buckets = self.buckets.lock().expect("locked");
+	let bucket = buckets
+		.entry((key, class))
+		.or_insert(Bucket { tokens: burst, updated: now });
+
+	bucket.refill(rate, now);
+	if bucket.tokens >= 1.0 {
+		bucket.tokens -= 1.0;
+		return None;
+	}
+
+	// rounded up to the millisecond, as clients are told in those
+	let retry_after = ((1.0 - bucket.tokens) / per_second * 1000.0).ceil() / 1000.0;
+	Some(Duration::try_from_secs_f64(retry_after).unwrap_or(Duration::MAX))
+}
+
+/// The requests left in each of the key's buckets, with their burst counts.
+/// Classes without a bucket (i.e. a full one) are omitted.
+#[implement(Service)]
+pub fn remaining(&self, key: &Key) -> Vec<(Class, f64, f64)> {
+	let config = &self.server.config.ratelimit;
+	let factor = self.factor_of(key);
+	let now = Instant::now();
+	self.buckets
+		.lock()
+		.expect("locked")
+		.iter_mut()
+		.filter(|((bucket_key, _), _)| bucket_key == key)
+		.map(|((_, class), bucket)| {
+			let rate @ (_, burst) = class.rate(config, factor);
+			bucket.refill(rate, now);
+			(*class, bucket.tokens, burst)
+		})
+		.collect()
+}
+
+/// Drop the buckets which have filled up again; they are recreated full.
+#[implement(Service)]
+fn sweep(&self) {
+	let config = &self.server.config.ratelimit;
+	let now = Instant::now();
+	self.buckets
+		.lock()
+		.expect("locked")
+		.retain(|(key, class), bucket| {
+			let rate @ (_, burst) = class.rate(config, self.factor_of(key));
+			bucket.refill(rate, now);
+			bucket.tokens < burst
+		});
+}
+
+#[implement(Service)]
+#[inline]
+pub fn set_exempt(&self, user_id: &UserId, exempt: bool) {
+	if exempt {
+		self.db.userid_ratelimitexempt.insert(user_id, []);
+	} else {
+		self.db.userid_ratelimitexempt.remove(user_id);
+	}
+}
+
+#[implement(Service)]
+#[inline]
+pub async fn is_exempt(&self, user_id: &UserId) -> bool {
+	self.db.userid_ratelimitexempt.get(user_id).await.is_ok()
+}
+
+#[implement(Service)]
+pub fn list_exempt(&self) -> impl Stream<Item = &UserId> + Send + '_ {
+	self.db.userid_ratelimitexempt.keys().ignore_err()
+}
+
+/// Scale the rates and burst counts of the user's buckets by the factor, for
+/// bots and bridges which need higher limits (or lower, below 1). A factor of
+/// 1 removes the override.
+#[implement(Service)]
+pub fn set_factor(&self, user_id: &UserId, factor: f64) -> Result {
+	if !factor.is_finite() || factor <= 0.0 {
+		return Err!(Request(InvalidParam("The factor must be a positive number.")));
+	}
+
+	if (factor - 1.0).abs() < f64::EPSILON {
+		self.db.userid_ratelimitfactor.remove(user_id);
+		self.factors.write()?.remove(user_id);
+	} else {
+		self.db
+			.userid_ratelimitfactor
+			.insert(user_id, factor.to_string());
+		self.factors.write()?.insert(user_id.to_owned(), factor);
+	}
+
+	// the buckets are recreated with the new burst count; the factors must not
+	// be locked here, as sweep() reads them while holding the buckets
+	self.buckets
+		.lock()?
+		.retain(|(key, _), _| *key != Key::User(user_id.to_owned()));
+
+	Ok(())
+}
+
+/// The factor of the user's rate limits; 1 unless overridden.
+#[implement(Service)]
+#[must_use]
+pub fn factor(&self, user_id: &UserId) -> f64 {
+	self.factors
+		.read()
+		.expect("locked")
+		.get(user_id)
+		.copied()
+		.unwrap_or(1.0)
+}
+
+#[implement(Service)]
+pub async fn list_factors(&self) -> Vec<(String, f64)> {
+	self.db
+		.userid_ratelimitfactor
+		.stream()
+		.ignore_err()
+		.filter_map(|(user_id, factor): (&UserId, String)| async move {
+			Some((user_id.to_string(), factor.parse().ok()?))
+		})
+		.collect()
+		.await
+}
+
+#[implement(Service)]
+fn factor_of(&self, key: &Key) -> f64 {
+	match key {
+		| Key::User(user_id) => self.factor(user_id),
+		| Key::Ip(_) => 1.0,
+	}
+}
@@ -0,0 +1,171 @@
+//! Registration tokens (MSC3231) managed by the server admins, in addition to
+//! the static `registration_token` of the config.
+
+use std::sync::Arc;
+
+use conduwuit::{
+	implement,
+	utils::{self, stream::TryIgnore, time::now_millis},
+	Err, Result,
+};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use crate::{globals, Dep};
+
+pub struct Service {
+	db: Data,
+	services: Services,
+}
+
+struct Data {
+	registrationtoken_info: Arc<Map>,
+}
+
+struct Services {
+	globals: Dep<globals::Service>,
+}
+
+/// The limits of a token and how often it was used.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct TokenInfo {
+	/// Registrations the token may be used for; unlimited when unset.
+	pub uses_allowed: Option<u64>,
+
+	/// Registrations completed with the token.
+	pub completed: u64,
+
+	/// When the token expires, in milliseconds since the unix epoch; never
+	/// when unset.
+	pub expiry_time: Option<u64>,
+}
+
+/// Length of the tokens generated when none is given.
+const TOKEN_LENGTH: usize = 16;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				registrationtoken_info: args.db["registrationtoken_info"].clone(),
+			},
+			services: Services {
+				globals: args.depend::<globals::Service>("globals"),
+			},
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl TokenInfo {
+	/// Whether the token can still be used to register.
+	#[must_use]
+	pub fn is_valid(&self) -> bool {
+		self.uses_allowed
+			.is_none_or(|uses_allowed| self.completed < uses_allowed)
+			&& self
+				.expiry_time
+				.is_none_or(|expiry_time| now_millis() < expiry_time)
+	}
+}
+
+/// Add a token, generating one when none is given, and return it.
+#[implement(Service)]
+pub async fn create(
+	&self,
+	token: Option<String>,
+	uses_allowed: Option<u64>,
+	expiry_time: Option<u64>,
+) -> Result<String> {
+	let token = token.unwrap_or_else(|| utils::random_string(TOKEN_LENGTH));
+	if token.is_empty()
+		|| token.len() > 64
+		|| !token
+			.chars()
+			.all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '~' | '-'))
+	{
+		return Err!(Request(InvalidParam(
+			"Registration tokens are 1 to 64 characters of A-Z, a-z, 0-9, '.', '_', '~' and '-'."
+		)));
+	}
+
+	if self.db.registrationtoken_info.get(&token).await.is_ok() {
+		return Err!(Request(InvalidParam("Registration token {token:?} already exists.")));
+	}
+
+	let info = TokenInfo { uses_allowed, completed: 0, expiry_time };
+	self.db.registrationtoken_info.raw_put(&token, Json(info));
+
+	Ok(token)
+}
+
+/// Remove a token, so it can no longer be used to register.
+#[implement(Service)]
+pub async fn revoke(&self, token: &str) -> Result {
+	if self.db.registrationtoken_info.get(token).await.is_err() {
+		return Err!(Request(NotFound("Registration token {token:?} does not exist.")));
+	}
+
+	self.db.registrationtoken_info.remove(token);
+
+	Ok(())
+}
+
+#[implement(Service)]
+pub fn list(&self) -> impl Stream<Item = (&str, TokenInfo)> + Send + '_ {
+	self.db.registrationtoken_info.stream().ignore_err()
+}
+
+/// Whether registering requires a token: the config has one or the admins
+/// created some.
+#[implement(Service)]
+pub async fn required(&self) -> bool {
+	self.services.globals.registration_token.is_some()
+		|| self
+			.db
+			.registrationtoken_info
+			.raw_keys()
+			.next()
+			.await
+			.is_some()
+}
+
+/// Whether the token can be used to register now.
+#[implement(Service)]
+pub async fn is_valid(&self, token: &str) -> bool {
+	if self
+		.services
+		.globals
+		.registration_token
+		.as_deref()
+		.is_some_and(|reg_token| reg_token == token)
+	{
+		return true;
+	}
+
+	self.db
+		.registrationtoken_info
+		.get(token)
+		.await
+		.deserialized::<TokenInfo>()
+		.is_ok_and(|info| info.is_valid())
+}
+
+/// Count a completed registration against the token.
+#[implement(Service)]
+pub async fn mark_used(&self, token: &str) {
+	let Ok(mut info) = self
+		.db
+		.registrationtoken_info
+		.get(token)
+		.await
+		.deserialized::<TokenInfo>()
+	else {
+		return;
+	};
+
+	info.completed = info.completed.saturating_add(1);
+	self.db.registrationtoken_info.raw_put(token, Json(info));
+}
@@ -1,16 +1,21 @@
 use std::{
 	fmt::Debug,
 	net::{IpAddr, SocketAddr},
+	time::{Duration, SystemTime},
 };
 
 use conduwuit::{debug, debug_error, debug_info, debug_warn, err, error, trace, Err, Result};
 use futures::FutureExt;
 use hickory_resolver::error::ResolveError;
+use http::header::CACHE_CONTROL;
 use ipaddress::IPAddress;
 use ruma::ServerName;
 
 use super::{
-	cache::{CachedDest, CachedOverride, MAX_IPS},
+	cache::{
+		expire_after, expire_at, CachedDelegation, CachedDest, CachedOverride, Usage, MAX_IPS,
+		MAX_TTL,
+	},
 	fed::{add_port_to_hostname, get_ip_with_port, FedDest, PortString},
 };
 
@@ -18,6 +23,7 @@ use super::{
 pub(crate) struct ActualDest {
 	pub(crate) dest: FedDest,
 	pub(crate) host: String,
+	pub(crate) expire: SystemTime,
 	pub(crate) cached: bool,
 }
 
@@ -36,9 +42,9 @@ impl super::Service {
 			(self.resolve_actual_dest(server_name, true).boxed().await?, false)
 		};
 
-		let CachedDest { dest, host, .. } = result;
+		let CachedDest { dest, host, expire, .. } = result;
 
-		Ok(ActualDest { dest, host, cached })
+		Ok(ActualDest { dest, host, expire, cached })
 	}
 
 	/// Returns: `actual_destination`, host header
@@ -53,14 +59,20 @@ impl super::Service {
 	) -> Result<CachedDest> {
 		trace!("Finding actual destination for {dest}");
 		let mut host = dest.as_str().to_owned();
+		let mut expire = expire_after(MAX_TTL);
 		let actual_dest = match get_ip_with_port(dest.as_str()) {
 			| Some(host_port) => Self::actual_dest_1(host_port)?,
 			| None =>
 				if let Some(pos) = dest.as_str().find(':') {
 					self.actual_dest_2(dest, cache, pos).await?
-				} else if let Some(delegated) = self.request_well_known(dest.as_str()).await? {
-					self.actual_dest_3(&mut host, cache, delegated).await?
-				} else if let Some(overrider) = self.query_srv_record(dest.as_str()).await? {
+				} else if let Some(delegated) =
+					self.request_well_known(dest.as_str(), &mut expire).await?
+				{
+					self.actual_dest_3(&mut host, &mut expire, cache, delegated)
+						.await?
+				} else if let Some(overrider) =
+					self.query_srv_record(dest.as_str(), &mut expire).await?
+				{
 					self.actual_dest_4(&host, cache, overrider).await?
 				} else {
 					self.actual_dest_5(dest, cache).await?
@@ -83,11 +95,18 @@ impl super::Service {
 			FedDest::Named(host, FedDest::default_port())
 		};
 
+		// The destination is only good for as long as the addresses it was
+		// resolved to.
+		if let Some(over) = self.get_cached_override(&actual_dest.hostname()) {
+			expire = expire.min(over.expire);
+		}
+
 		debug!("Actual destination: {actual_dest:?} hostname: {host:?}");
 		Ok(CachedDest {
 			dest: actual_dest,
 			host: host.uri_string(),
-			expire: CachedDest::default_expire(),
+			expire,
+			usage: Usage::new(),
 		})
 	}
 
@@ -115,6 +134,7 @@ impl super::Service {
 	async fn actual_dest_3(
 		&self,
 		host: &mut String,
+		expire: &mut SystemTime,
 		cache: bool,
 		delegated: String,
 	) -> Result<FedDest> {
@@ -127,7 +147,7 @@ impl super::Service {
 					self.actual_dest_3_2(cache, delegated, pos).await
 				} else {
 					trace!("Delegated hostname has no port in this branch");
-					if let Some(overrider) = self.query_srv_record(&delegated).await? {
+					if let Some(overrider) = self.query_srv_record(&delegated, expire).await? {
 						self.actual_dest_3_3(cache, delegated, overrider).await
 					} else {
 						self.actual_dest_3_4(cache, delegated).await
@@ -231,12 +251,45 @@ impl super::Service {
 	}
 
 	#[tracing::instrument(skip_all, name = "well-known")]
-	async fn request_well_known(&self, dest: &str) -> Result<Option<String>> {
+	async fn request_well_known(
+		&self,
+		dest: &str,
+		expire: &mut SystemTime,
+	) -> Result<Option<String>> {
+		if let Some(cached) = self.get_cached_delegation(dest) {
+			debug!("Cached delegation for {dest}: {:?}", cached.server);
+			*expire = (*expire).min(cached.expire);
+			return Ok(cached.server);
+		}
+
 		if !self.has_cached_override(dest) {
 			self.query_and_cache_override(dest, dest, 8448).await?;
 		}
 
 		self.services.server.check_running()?;
+		let (server, max_age) = self.fetch_well_known(dest).await?;
+
+		let config = &self.services.server.config;
+		let ttl = if server.is_some() {
+			max_age
+				.unwrap_or(Duration::from_secs(config.well_known_cache_ttl))
+				.min(Duration::from_secs(config.well_known_cache_max_ttl))
+		} else {
+			Duration::from_secs(config.well_known_negative_cache_ttl)
+		};
+
+		*expire = (*expire).min(expire_after(ttl));
+
+		if !ttl.is_zero() {
+			self.set_cached_delegation(dest, CachedDelegation::new(server.clone(), ttl));
+		}
+
+		Ok(server)
+	}
+
+	/// The delegated server name from the well-known of the destination, with
+	/// the max-age of its Cache-Control header.
+	async fn fetch_well_known(&self, dest: &str) -> Result<(Option<String>, Option<Duration>)> {
 		trace!("Requesting well known for {dest}");
 		let response = self
 			.services
@@ -249,20 +302,26 @@ impl super::Service {
 		trace!("response: {response:?}");
 		if let Err(e) = &response {
 			debug!("error: {e:?}");
-			return Ok(None);
+			return Ok((None, None));
 		}
 
 		let response = response?;
 		if !response.status().is_success() {
 			debug!("response not 2XX");
-			return Ok(None);
+			return Ok((None, None));
 		}
 
+		let max_age = response
+			.headers()
+			.get(CACHE_CONTROL)
+			.and_then(|value| value.to_str().ok())
+			.and_then(cache_control_max_age);
+
 		let text = response.text().await?;
 		trace!("response text: {text:?}");
 		if text.len() >= 12288 {
 			debug_warn!("response contains junk");
-			return Ok(None);
+			return Ok((None, None));
 		}
 
 		let body: serde_json::Value = serde_json::from_str(&text).unwrap_or_default();
@@ -275,11 +334,11 @@ impl super::Service {
 
 		if ruma::identifiers_validation::server_name::validate(m_server).is_err() {
 			debug_error!("response content missing or invalid");
-			return Ok(None);
+			return Ok((None, None));
 		}
 
 		debug_info!("{dest:?} found at {m_server:?}");
-		Ok(Some(m_server.to_owned()))
+		Ok((Some(m_server.to_owned()), max_age))
 	}
 
 	#[inline]
@@ -315,10 +374,12 @@ impl super::Service {
 					debug_info!("{overname:?} overriden by {hostname:?}");
 				}
 
+				let expire = expire_at(override_ip.valid_until());
 				self.set_cached_override(overname, CachedOverride {
 					ips: override_ip.into_iter().take(MAX_IPS).collect(),
 					port,
-					expire: CachedOverride::default_expire(),
+					expire,
+					usage: Usage::new(),
 				});
 
 				Ok(())
@@ -327,7 +388,11 @@ impl super::Service {
 	}
 
 	#[tracing::instrument(skip_all, name = "srv")]
-	async fn query_srv_record(&self, hostname: &'_ str) -> Result<Option<FedDest>> {
+	async fn query_srv_record(
+		&self,
+		hostname: &'_ str,
+		expire: &mut SystemTime,
+	) -> Result<Option<FedDest>> {
 		let hostnames =
 			[format!("_matrix-fed._tcp.{hostname}."), format!("_matrix._tcp.{hostname}.")];
 
@@ -338,7 +403,8 @@ impl super::Service {
 			let hostname = hostname.trim_end_matches('.');
 			match self.resolver.resolver.srv_lookup(hostname).await {
 				| Err(e) => Self::handle_resolve_error(&e, hostname)?,
-				| Ok(result) =>
+				| Ok(result) => {
+					*expire = (*expire).min(expire_at(result.as_lookup().valid_until()));
 					return Ok(result.iter().next().map(|result| {
 						FedDest::Named(
 							result.target().to_string().trim_end_matches('.').to_owned(),
@@ -347,7 +413,8 @@ impl super::Service {
 								.try_into()
 								.unwrap_or_else(|_| FedDest::default_port()),
 						)
-					})),
+					}));
+				},
 			}
 		}
 
@@ -415,3 +482,29 @@ impl super::Service {
 		Ok(())
 	}
 }
+
+/// How long a response may be cached according to its Cache-Control header;
+/// zero when it must not be.
+pub(super) fn cache_control_max_age(value: &str) -> Option<Duration> {
+	let mut max_age = None;
+	for directive in value.split(',').map(str::trim) {
+		if directive.eq_ignore_ascii_case("no-store")
+			|| directive.eq_ignore_ascii_case("no-cache")
+		{
+			return Some(Duration::ZERO);
+		}
+
+		if let Some((name, secs)) = directive.split_once('=') {
+			if name.trim().eq_ignore_ascii_case("max-age") {
+				max_age = secs
+					.trim()
+					.trim_matches('"')
+					.parse()
+					.ok()
+					.map(Duration::from_secs);
+			}
+		}
+	}
+
+	max_age
+}
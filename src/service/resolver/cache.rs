@@ -1,22 +1,32 @@
 use std::{
 	collections::HashMap,
 	net::IpAddr,
-	sync::{Arc, RwLock},
-	time::SystemTime,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, RwLock,
+	},
+	time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
 use arrayvec::ArrayVec;
 use conduwuit::{
-	trace,
-	utils::{math::Expected, rand},
+	debug, trace,
+	utils::{
+		math::Expected,
+		millis_since_unix_epoch,
+		stream::{ReadyExt, TryIgnore},
+	},
 };
+use futures::StreamExt;
 use ruma::{OwnedServerName, ServerName};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 
-use super::fed::FedDest;
+use super::fed::{add_port_to_hostname, get_ip_with_port, FedDest};
 
 pub struct Cache {
 	pub destinations: RwLock<WellKnownMap>, // actual_destination, host
 	pub overrides: RwLock<TlsNameMap>,
+	pub delegations: RwLock<DelegationMap>,
 }
 
 #[derive(Clone, Debug)]
@@ -24,6 +34,15 @@ pub struct CachedDest {
 	pub dest: FedDest,
 	pub host: String,
 	pub expire: SystemTime,
+	pub usage: Usage,
+}
+
+/// The result of a server's well-known delegation lookup; `server` is `None`
+/// when it has none, which is cached as well.
+#[derive(Clone, Debug)]
+pub struct CachedDelegation {
+	pub server: Option<String>,
+	pub expire: SystemTime,
 }
 
 #[derive(Clone, Debug)]
@@ -31,19 +50,64 @@ pub struct CachedOverride {
 	pub ips: IpAddrs,
 	pub port: u16,
 	pub expire: SystemTime,
+	pub usage: Usage,
+}
+
+/// The requests made through a cached destination or override since it was
+/// cached. Entries unused for `resolver_cache_idle_timeout` are evicted. The
+/// counters are atomic so requests are counted under the read lock of the
+/// cache.
+#[derive(Debug)]
+pub struct Usage {
+	successes: AtomicU64,
+	failures: AtomicU64,
+
+	/// Milliseconds since the epoch.
+	last_used: AtomicU64,
+}
+
+/// The counters of a [`Usage`] at one point.
+#[derive(Clone, Copy, Debug)]
+pub struct UsageCounts {
+	pub successes: u64,
+	pub failures: u64,
+	pub last_used: SystemTime,
 }
 
 pub type WellKnownMap = HashMap<OwnedServerName, CachedDest>;
 pub type TlsNameMap = HashMap<String, CachedOverride>;
+pub type DelegationMap = HashMap<String, CachedDelegation>;
 
 pub type IpAddrs = ArrayVec<IpAddr, MAX_IPS>;
 pub(crate) const MAX_IPS: usize = 3;
 
+/// Nothing is cached for longer than the spec allows the well-known to be,
+/// whatever the TTLs of the DNS records.
+pub(crate) const MAX_TTL: Duration = Duration::from_secs(60 * 60 * 48);
+
+/// Persisted records lead with the big-endian expiry in milliseconds since the
+/// epoch, so the database can remove them once they expired; the JSON of the
+/// entry follows.
+const EXPIRE_LEN: usize = size_of::<u64>();
+
+#[derive(Deserialize, Serialize)]
+struct StoredDest {
+	dest: String,
+	host: String,
+}
+
+#[derive(Deserialize, Serialize)]
+struct StoredOverride {
+	ips: Vec<IpAddr>,
+	port: u16,
+}
+
 impl Cache {
 	pub(super) fn new() -> Arc<Self> {
 		Arc::new(Self {
 			destinations: RwLock::new(WellKnownMap::new()),
 			overrides: RwLock::new(TlsNameMap::new()),
+			delegations: RwLock::new(DelegationMap::new()),
 		})
 	}
 }
@@ -55,6 +119,15 @@ impl super::Service {
 		dest: CachedDest,
 	) -> Option<CachedDest> {
 		trace!(?name, ?dest, "set cached destination");
+		let stored = StoredDest {
+			dest: dest.dest.uri_string(),
+			host: dest.host.clone(),
+		};
+
+		if let Some(val) = encode(dest.expire, &stored) {
+			self.db.servername_destination.insert(name.as_str(), val);
+		}
+
 		self.cache
 			.destinations
 			.write()
@@ -62,6 +135,7 @@ impl super::Service {
 			.insert(name, dest)
 	}
 
+	/// The cached destination of the server, unless it expired.
 	#[must_use]
 	pub fn get_cached_destination(&self, name: &ServerName) -> Option<CachedDest> {
 		self.cache
@@ -69,6 +143,7 @@ impl super::Service {
 			.read()
 			.expect("locked for reading")
 			.get(name)
+			.filter(|dest| dest.valid())
 			.cloned()
 	}
 
@@ -78,6 +153,12 @@ impl super::Service {
 		over: CachedOverride,
 	) -> Option<CachedOverride> {
 		trace!(?name, ?over, "set cached override");
+		let stored = StoredOverride { ips: over.ips.to_vec(), port: over.port };
+
+		if let Some(val) = encode(over.expire, &stored) {
+			self.db.hostname_override.insert(name, val);
+		}
+
 		self.cache
 			.overrides
 			.write()
@@ -85,6 +166,7 @@ impl super::Service {
 			.insert(name.into(), over)
 	}
 
+	/// The cached override of the hostname, unless it expired.
 	#[must_use]
 	pub fn get_cached_override(&self, name: &str) -> Option<CachedOverride> {
 		self.cache
@@ -92,6 +174,7 @@ impl super::Service {
 			.read()
 			.expect("locked for reading")
 			.get(name)
+			.filter(|over| over.valid())
 			.cloned()
 	}
 
@@ -101,22 +184,160 @@ impl super::Service {
 			.overrides
 			.read()
 			.expect("locked for reading")
-			.contains_key(name)
+			.get(name)
+			.is_some_and(CachedOverride::valid)
 	}
-}
 
-impl CachedDest {
-	#[inline]
+	pub fn set_cached_delegation(&self, name: &str, delegation: CachedDelegation) {
+		trace!(?name, ?delegation, "set cached delegation");
+		self.cache
+			.delegations
+			.write()
+			.expect("locked for writing")
+			.insert(name.into(), delegation);
+	}
+
+	/// The cached delegation of the server, unless it expired.
 	#[must_use]
-	pub fn valid(&self) -> bool { true }
+	pub fn get_cached_delegation(&self, name: &str) -> Option<CachedDelegation> {
+		self.cache
+			.delegations
+			.read()
+			.expect("locked for reading")
+			.get(name)
+			.filter(|delegation| delegation.valid())
+			.cloned()
+	}
 
-	//pub fn valid(&self) -> bool { self.expire > SystemTime::now() }
+	/// Count a request to the server through its cached destination and the
+	/// override of the host it connected to, as succeeded when the server
+	/// responded at all.
+	pub fn record_destination_use(&self, name: &ServerName, host: &str, success: bool) {
+		if let Some(cached) = self
+			.cache
+			.destinations
+			.read()
+			.expect("locked for reading")
+			.get(name)
+		{
+			cached.usage.record(success);
+		}
 
-	#[must_use]
-	pub(crate) fn default_expire() -> SystemTime {
-		rand::timepoint_secs(60 * 60 * 18..60 * 60 * 36)
+		if let Some(cached) = self
+			.cache
+			.overrides
+			.read()
+			.expect("locked for reading")
+			.get(host)
+		{
+			cached.usage.record(success);
+		}
 	}
 
+	/// Evict the destinations and overrides which expired or were unused for
+	/// the duration. Returns how many were.
+	pub fn evict_unused(&self, idle: Duration) -> usize {
+		let Some(cutoff) = SystemTime::now().checked_sub(idle) else {
+			return 0;
+		};
+
+		let mut destinations = self.cache.destinations.write().expect("locked for writing");
+		let count = destinations.len();
+		destinations.retain(|_, cached| cached.valid() && cached.usage.last_used() >= cutoff);
+		let evicted = count.saturating_sub(destinations.len());
+		drop(destinations);
+
+		let mut overrides = self.cache.overrides.write().expect("locked for writing");
+		let count = overrides.len();
+		overrides.retain(|_, cached| cached.valid() && cached.usage.last_used() >= cutoff);
+		evicted.saturating_add(count.saturating_sub(overrides.len()))
+	}
+
+	/// Forget the server's delegation along with the destination resolved from
+	/// it, so both are looked up again on the next request. Returns whether
+	/// anything was cached.
+	pub fn invalidate_delegation(&self, name: &ServerName) -> bool {
+		let delegation = self
+			.cache
+			.delegations
+			.write()
+			.expect("locked for writing")
+			.remove(name.as_str());
+
+		let destination = self
+			.cache
+			.destinations
+			.write()
+			.expect("locked for writing")
+			.remove(name);
+
+		self.db.servername_destination.remove(name.as_str());
+
+		delegation.is_some() || destination.is_some()
+	}
+
+	/// Load the destinations and overrides persisted before the last shutdown
+	/// which haven't expired yet, so they needn't all be resolved again.
+	pub(super) async fn load_cache(&self) {
+		let destinations: Vec<(OwnedServerName, CachedDest)> = self
+			.db
+			.servername_destination
+			.stream()
+			.ignore_err()
+			.ready_filter_map(|(name, val): (&ServerName, &[u8])| {
+				let (expire, StoredDest { dest, host }) = decode(val)?;
+				let dest = get_ip_with_port(&dest).unwrap_or_else(|| add_port_to_hostname(&dest));
+				let cached = CachedDest { dest, host, expire, usage: Usage::new() };
+
+				cached.valid().then(|| (name.to_owned(), cached))
+			})
+			.collect()
+			.await;
+
+		let overrides: Vec<(String, CachedOverride)> = self
+			.db
+			.hostname_override
+			.stream()
+			.ignore_err()
+			.ready_filter_map(|(name, val): (&str, &[u8])| {
+				let (expire, StoredOverride { ips, port }) = decode(val)?;
+				let cached = CachedOverride {
+					ips: ips.into_iter().take(MAX_IPS).collect(),
+					port,
+					expire,
+					usage: Usage::new(),
+				};
+
+				cached.valid().then(|| (name.to_owned(), cached))
+			})
+			.collect()
+			.await;
+
+		debug!(
+			destinations = destinations.len(),
+			overrides = overrides.len(),
+			"Loaded persisted destinations and overrides"
+		);
+
+		self.cache
+			.destinations
+			.write()
+			.expect("locked for writing")
+			.extend(destinations);
+
+		self.cache
+			.overrides
+			.write()
+			.expect("locked for writing")
+			.extend(overrides);
+	}
+}
+
+impl CachedDest {
+	#[inline]
+	#[must_use]
+	pub fn valid(&self) -> bool { self.expire > SystemTime::now() }
+
 	#[inline]
 	#[must_use]
 	pub fn size(&self) -> usize {
@@ -124,22 +345,119 @@ impl CachedDest {
 			.size()
 			.expected_add(self.host.len())
 			.expected_add(size_of_val(&self.expire))
+			.expected_add(size_of_val(&self.usage))
 	}
 }
 
-impl CachedOverride {
-	#[inline]
+impl CachedDelegation {
 	#[must_use]
-	pub fn valid(&self) -> bool { true }
+	pub fn new(server: Option<String>, ttl: Duration) -> Self {
+		Self { server, expire: expire_after(ttl) }
+	}
 
-	//pub fn valid(&self) -> bool { self.expire > SystemTime::now() }
+	#[inline]
+	#[must_use]
+	pub fn valid(&self) -> bool { self.expire > SystemTime::now() }
 
+	#[inline]
 	#[must_use]
-	pub(crate) fn default_expire() -> SystemTime {
-		rand::timepoint_secs(60 * 60 * 6..60 * 60 * 12)
+	pub fn size(&self) -> usize {
+		self.server
+			.as_ref()
+			.map_or(0, String::len)
+			.expected_add(size_of_val(self))
 	}
+}
+
+impl CachedOverride {
+	#[inline]
+	#[must_use]
+	pub fn valid(&self) -> bool { self.expire > SystemTime::now() }
 
 	#[inline]
 	#[must_use]
 	pub fn size(&self) -> usize { size_of_val(self) }
 }
+
+impl Usage {
+	#[must_use]
+	pub fn new() -> Self {
+		Self {
+			successes: AtomicU64::new(0),
+			failures: AtomicU64::new(0),
+			last_used: AtomicU64::new(millis_since_unix_epoch()),
+		}
+	}
+
+	#[must_use]
+	pub fn load(&self) -> UsageCounts {
+		UsageCounts {
+			successes: self.successes.load(Ordering::Relaxed),
+			failures: self.failures.load(Ordering::Relaxed),
+			last_used: self.last_used(),
+		}
+	}
+
+	#[must_use]
+	pub fn last_used(&self) -> SystemTime {
+		let last_used = Duration::from_millis(self.last_used.load(Ordering::Relaxed));
+		UNIX_EPOCH.checked_add(last_used).unwrap_or(UNIX_EPOCH)
+	}
+
+	fn record(&self, success: bool) {
+		let counter = if success { &self.successes } else { &self.failures };
+		counter.fetch_add(1, Ordering::Relaxed);
+		self.last_used
+			.fetch_max(millis_since_unix_epoch(), Ordering::Relaxed);
+	}
+}
+
+impl Clone for Usage {
+	fn clone(&self) -> Self {
+		Self {
+			successes: AtomicU64::new(self.successes.load(Ordering::Relaxed)),
+			failures: AtomicU64::new(self.failures.load(Ordering::Relaxed)),
+			last_used: AtomicU64::new(self.last_used.load(Ordering::Relaxed)),
+		}
+	}
+}
+
+impl Default for Usage {
+	fn default() -> Self { Self::new() }
+}
+
+/// When an entry cached for the TTL expires, capped at [`MAX_TTL`].
+#[must_use]
+pub(crate) fn expire_after(ttl: Duration) -> SystemTime {
+	SystemTime::now()
+		.checked_add(ttl.min(MAX_TTL))
+		.unwrap_or(UNIX_EPOCH)
+}
+
+/// When an entry cached from a DNS record valid until the instant expires,
+/// capped at [`MAX_TTL`].
+#[must_use]
+pub(crate) fn expire_at(valid_until: Instant) -> SystemTime {
+	expire_after(valid_until.saturating_duration_since(Instant::now()))
+}
+
+fn encode<T: Serialize>(expire: SystemTime, val: &T) -> Option<Vec<u8>> {
+	let expire: u64 = expire
+		.duration_since(UNIX_EPOCH)
+		.ok()?
+		.as_millis()
+		.try_into()
+		.ok()?;
+
+	let mut buf = expire.to_be_bytes().to_vec();
+	serde_json::to_writer(&mut buf, val).ok()?;
+
+	Some(buf)
+}
+
+fn decode<T: DeserializeOwned>(val: &[u8]) -> Option<(SystemTime, T)> {
+	let (expire, val) = val.split_first_chunk::<EXPIRE_LEN>()?;
+	let expire = UNIX_EPOCH.checked_add(Duration::from_millis(u64::from_be_bytes(*expire)))?;
+
+	Some((expire, serde_json::from_slice(val).ok()?))
+}
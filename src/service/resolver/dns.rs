@@ -94,6 +94,7 @@ impl Resolve for Hooked {
 			.read()
 			.expect("locked for reading")
 			.get(name.as_str())
+			.filter(|cached| cached.valid())
 			.cloned();
 
 		cached.map_or_else(
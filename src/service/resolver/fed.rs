@@ -56,7 +56,7 @@ impl FedDest {
 	}
 
 	#[inline]
-	pub(crate) fn hostname(&self) -> Cow<'_, str> {
+	pub fn hostname(&self) -> Cow<'_, str> {
 		match &self {
 			| Self::Literal(addr) => addr.ip().to_string().into(),
 			| Self::Named(host, _) => host.into(),
@@ -4,17 +4,31 @@ mod dns;
 pub mod fed;
 mod tests;
 
-use std::{fmt::Write, sync::Arc};
+use std::{fmt::Write, sync::Arc, time::Duration};
 
-use conduwuit::{utils, utils::math::Expected, Result, Server};
+use async_trait::async_trait;
+use conduwuit::{debug, utils, utils::math::Expected, Result, Server};
+use database::Map;
+use tokio::{sync::Notify, time::interval};
 
 use self::{cache::Cache, dns::Resolver};
 use crate::{client, Dep};
 
+/// How often at most the destinations and overrides unused for
+/// `resolver_cache_idle_timeout` are evicted.
+const EVICT_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
 pub struct Service {
 	pub cache: Arc<Cache>,
 	pub resolver: Arc<Resolver>,
+	db: Data,
 	services: Services,
+	interrupt: Notify,
+}
+
+struct Data {
+	hostname_override: Arc<Map>,
+	servername_destination: Arc<Map>,
 }
 
 struct Services {
@@ -22,6 +36,7 @@ struct Services {
 	client: Dep<client::Service>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	#[allow(clippy::as_conversions, clippy::cast_sign_loss, clippy::cast_possible_truncation)]
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
@@ -29,13 +44,45 @@ impl crate::Service for Service {
 		Ok(Arc::new(Self {
 			cache: cache.clone(),
 			resolver: Resolver::build(args.server, cache)?,
+			db: Data {
+				hostname_override: args.db["hostname_override"].clone(),
+				servername_destination: args.db["servername_destination"].clone(),
+			},
 			services: Services {
 				server: args.server.clone(),
 				client: args.depend::<client::Service>("client"),
 			},
+			interrupt: Notify::new(),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		self.load_cache().await;
+
+		let idle = self.services.server.config.resolver_cache_idle_timeout;
+		if idle == 0 {
+			return Ok(());
+		}
+
+		let idle = Duration::from_secs(idle);
+		let mut interval = interval(idle.min(EVICT_INTERVAL));
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = interval.tick() => {
+					let evicted = self.evict_unused(idle);
+					if evicted > 0 {
+						debug!(?evicted, "Evicted unused destinations and overrides");
+					}
+				},
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn memory_usage(&self, out: &mut dyn Write) -> Result {
 		use utils::bytes::pretty;
 
@@ -53,8 +100,16 @@ impl crate::Service for Service {
 			},
 		);
 
+		let (wc_count, wc_bytes) = self.cache.delegations.read()?.iter().fold(
+			(0_usize, 0_usize),
+			|(count, bytes), (key, val)| {
+				(count.expected_add(1), bytes.expected_add(key.len()).expected_add(val.size()))
+			},
+		);
+
 		writeln!(out, "resolver_overrides_cache: {oc_count} ({})", pretty(oc_bytes))?;
 		writeln!(out, "resolver_destinations_cache: {dc_count} ({})", pretty(dc_bytes))?;
+		writeln!(out, "resolver_delegations_cache: {wc_count} ({})", pretty(wc_bytes))?;
 
 		Ok(())
 	}
@@ -66,6 +121,11 @@ impl crate::Service for Service {
 			.write()
 			.expect("write locked")
 			.clear();
+		self.cache
+			.delegations
+			.write()
+			.expect("write locked")
+			.clear();
 		self.resolver.resolver.clear_cache();
 	}
 
@@ -1,6 +1,11 @@
 #![cfg(test)]
 
-use super::fed::{add_port_to_hostname, get_ip_with_port, FedDest};
+use std::time::Duration;
+
+use super::{
+	actual::cache_control_max_age,
+	fed::{add_port_to_hostname, get_ip_with_port, FedDest},
+};
 
 #[test]
 fn ips_get_default_ports() {
@@ -41,3 +46,17 @@ fn hostnames_keep_custom_ports() {
 		FedDest::Named(String::from("example.com"), ":1337".try_into().unwrap())
 	);
 }
+
+#[test]
+fn cache_control_max_age_parsed() {
+	assert_eq!(cache_control_max_age("max-age=3600"), Some(Duration::from_secs(3600)));
+	assert_eq!(cache_control_max_age("public, Max-Age=\"60\""), Some(Duration::from_secs(60)));
+	assert_eq!(cache_control_max_age("public"), None);
+	assert_eq!(cache_control_max_age("max-age=junk"), None);
+}
+
+#[test]
+fn cache_control_no_store_not_cached() {
+	assert_eq!(cache_control_max_age("max-age=3600, no-store"), Some(Duration::ZERO));
+	assert_eq!(cache_control_max_age("no-cache"), Some(Duration::ZERO));
+}
@@ -0,0 +1,310 @@
+//! Delayed events (MSC4140): events a client schedules to be sent into a room
+//! once a timeout elapses, unless the timeout is restarted or the event is
+//! cancelled first. A client keeps restarting the timeout while it is alive so
+//! the event (e.g. leaving a call) is only sent once it goes away.
+
+use std::{sync::Arc, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug, debug_info, err, http, implement,
+	pdu::PduBuilder,
+	utils::{self, stream::TryIgnore, ReadyExt},
+	warn, Err, Error, Result, Server,
+};
+use database::{Deserialized, Ignore, Interfix, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{api::client::error::ErrorKind, OwnedRoomId, OwnedUserId, RoomId, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue as RawJsonValue;
+use tokio::{sync::Notify, time::sleep};
+
+use crate::{rooms, Dep};
+
+pub struct Service {
+	interrupt: Notify,
+	changed: Notify,
+	db: Data,
+	services: Services,
+}
+
+struct Data {
+	userdelayid_delayedevent: Arc<Map>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	state: Dep<rooms::state::Service>,
+	timeline: Dep<rooms::timeline::Service>,
+}
+
+/// An event waiting to be sent, in the form it is listed to its sender.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct DelayedEvent {
+	pub delay_id: String,
+
+	pub room_id: OwnedRoomId,
+
+	#[serde(skip)]
+	pub sender: Option<OwnedUserId>,
+
+	#[serde(rename = "type")]
+	pub event_type: String,
+
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub state_key: Option<String>,
+
+	pub content: Box<RawJsonValue>,
+
+	/// Milliseconds after `running_since` the event is sent.
+	pub delay: u64,
+
+	/// When the timeout was last (re)started, in milliseconds since the epoch.
+	pub running_since: u64,
+}
+
+/// Delay ID length; these only need to be unique per user.
+const DELAY_ID_LENGTH: usize = 16;
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			changed: Notify::new(),
+			db: Data {
+				userdelayid_delayedevent: args.db["userdelayid_delayedevent"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+			},
+		}))
+	}
+
+	#[tracing::instrument(skip_all, name = "delayed", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		while self.services.server.running() {
+			let now = utils::millis_since_unix_epoch();
+			let next = self
+				.all()
+				.ready_fold(None, |next: Option<u64>, event| {
+					let due = event.running_since.saturating_add(event.delay);
+					Some(next.map_or(due, |next| next.min(due)))
+				})
+				.await;
+
+			if next.is_some_and(|due| due <= now) {
+				self.send_due(now).await;
+				continue;
+			}
+
+			let timeout =
+				next.map_or(Duration::MAX, |due| Duration::from_millis(due.saturating_sub(now)));
+
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				() = self.changed.notified() => (),
+				() = sleep(timeout) => (),
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// Schedule an event to be sent by the user after the delay and return the ID
+/// the user manages it by.
+#[implement(Service)]
+#[tracing::instrument(skip(self, content), level = "debug")]
+pub async fn schedule(
+	&self,
+	sender: &UserId,
+	room_id: &RoomId,
+	event_type: String,
+	state_key: Option<String>,
+	content: Box<RawJsonValue>,
+	delay: Duration,
+) -> Result<String> {
+	let config = &self.services.server.config;
+	if config.max_event_delay == 0 {
+		return Err!(Request(Unrecognized("Delayed events are not enabled on this server.")));
+	}
+
+	if delay > Duration::from_secs(config.max_event_delay) {
+		return Err!(Request(InvalidParam(
+			"Delay exceeds the maximum of {} seconds.",
+			config.max_event_delay
+		)));
+	}
+
+	let pending = self.list(sender).count().await;
+	if pending >= config.max_delayed_events_per_user {
+		return Err(Error::Request(
+			ErrorKind::LimitExceeded { retry_after: None },
+			"Too many delayed events are pending.".into(),
+			http::StatusCode::TOO_MANY_REQUESTS,
+		));
+	}
+
+	let delay_id = utils::random_string(DELAY_ID_LENGTH);
+	let event = DelayedEvent {
+		delay_id: delay_id.clone(),
+		room_id: room_id.to_owned(),
+		sender: None,
+		event_type,
+		state_key,
+		content,
+		delay: delay.as_millis().try_into()?,
+		running_since: utils::millis_since_unix_epoch(),
+	};
+
+	let key = (sender, delay_id.as_str());
+	self.db.userdelayid_delayedevent.put(key, Json(event));
+	self.changed.notify_one();
+
+	Ok(delay_id)
+}
+
+/// Discard the delayed event without sending it.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn cancel(&self, sender: &UserId, delay_id: &str) -> Result {
+	self.get(sender, delay_id).await?;
+	self.db.userdelayid_delayedevent.del((sender, delay_id));
+	self.changed.notify_one();
+
+	Ok(())
+}
+
+/// Start the delay over from now.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn restart(&self, sender: &UserId, delay_id: &str) -> Result {
+	let mut event = self.get(sender, delay_id).await?;
+	event.running_since = utils::millis_since_unix_epoch();
+
+	let key = (sender, delay_id);
+	self.db.userdelayid_delayedevent.put(key, Json(event));
+	self.changed.notify_one();
+
+	Ok(())
+}
+
+/// Send the delayed event immediately.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn send_now(&self, sender: &UserId, delay_id: &str) -> Result {
+	let event = self.get(sender, delay_id).await?;
+	self.db.userdelayid_delayedevent.del((sender, delay_id));
+	self.changed.notify_one();
+
+	self.send(sender, event).await
+}
+
+/// Cancel every delayed state event for the key; sending state to a key
+/// supersedes anything scheduled for it.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn cancel_state(&self, room_id: &RoomId, event_type: &str, state_key: &str) {
+	let superseded: Vec<_> = self
+		.all()
+		.ready_filter(|event| {
+			event.room_id == room_id
+				&& event.event_type == event_type
+				&& event.state_key.as_deref() == Some(state_key)
+		})
+		.collect()
+		.await;
+
+	for event in superseded {
+		let sender = event.sender.as_deref().expect("sender set by all()");
+		debug!(?sender, delay_id = %event.delay_id, "Cancelling superseded delayed event");
+		self.db
+			.userdelayid_delayedevent
+			.del((sender, event.delay_id.as_str()));
+	}
+}
+
+/// The user's pending delayed events.
+#[implement(Service)]
+pub fn list<'a>(&'a self, sender: &'a UserId) -> impl Stream<Item = DelayedEvent> + Send + 'a {
+	let prefix = (sender, Interfix);
+	self.db
+		.userdelayid_delayedevent
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.map(|(_, event): (Ignore, DelayedEvent)| event)
+}
+
+#[implement(Service)]
+async fn get(&self, sender: &UserId, delay_id: &str) -> Result<DelayedEvent> {
+	let key = (sender, delay_id);
+	self.db
+		.userdelayid_delayedevent
+		.qry(&key)
+		.await
+		.deserialized()
+		.map_err(|_| err!(Request(NotFound("No delayed event with this ID."))))
+}
+
+#[implement(Service)]
+fn all(&self) -> impl Stream<Item = DelayedEvent> + Send + '_ {
+	self.db.userdelayid_delayedevent.stream().ignore_err().map(
+		|((sender, _), mut event): ((&UserId, Ignore), DelayedEvent)| {
+			event.sender = Some(sender.to_owned());
+			event
+		},
+	)
+}
+
+#[implement(Service)]
+async fn send_due(&self, now: u64) {
+	let due: Vec<_> = self
+		.all()
+		.ready_filter(|event| event.running_since.saturating_add(event.delay) <= now)
+		.collect()
+		.await;
+
+	for event in due {
+		let sender = event.sender.clone().expect("sender set by all()");
+		self.db
+			.userdelayid_delayedevent
+			.del((&sender, event.delay_id.as_str()));
+
+		let delay_id = event.delay_id.clone();
+		if let Err(e) = self.send(&sender, event).await {
+			warn!(%sender, %delay_id, "Failed to send delayed event: {e}");
+		}
+	}
+}
+
+#[implement(Service)]
+async fn send(&self, sender: &UserId, event: DelayedEvent) -> Result {
+	let room_id = &event.room_id;
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+	let event_id = self
+		.services
+		.timeline
+		.build_and_append_pdu(
+			PduBuilder {
+				event_type: event.event_type.into(),
+				content: event.content,
+				state_key: event.state_key,
+				..Default::default()
+			},
+			sender,
+			room_id,
+			&state_lock,
+		)
+		.await?;
+
+	debug_info!(%sender, %room_id, %event_id, delay_id = %event.delay_id, "Sent delayed event");
+
+	Ok(())
+}
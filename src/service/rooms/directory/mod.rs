@@ -1,23 +1,73 @@
-use std::sync::Arc;
+use std::{
+	collections::{BTreeSet, HashSet},
+	sync::Arc,
+};
 
-use conduwuit::{implement, utils::stream::TryIgnore, Result};
-use database::Map;
-use futures::Stream;
-use ruma::{api::client::room::Visibility, RoomId};
+use conduwuit::{
+	err, implement,
+	utils::{stream::TryIgnore, ReadyExt},
+	Err, Result,
+};
+use database::{Deserialized, Json, Map};
+use futures::{Stream, StreamExt};
+use ruma::{api::client::room::Visibility, directory::RoomTypeFilter, OwnedRoomId, RoomId};
+use serde::{Deserialize, Serialize};
+
+use crate::{rooms, Dep};
 
 pub struct Service {
 	db: Data,
+	services: Services,
 }
 
 struct Data {
 	publicroomids: Arc<Map>,
+	publicroomjoined_roomid: Arc<Map>,
+	publicroomkeyword_roomid: Arc<Map>,
+}
+
+struct Services {
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
+}
+
+/// What a published room is indexed by, kept so its index entries can be
+/// found again when they change.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct Indexed {
+	joined: u64,
+	room_type: Option<String>,
+	keywords: BTreeSet<String>,
+}
+
+/// A page of the published rooms, by descending joined member count.
+#[derive(Debug, Default)]
+pub struct PublicRoomsPage {
+	pub rooms: Vec<OwnedRoomId>,
+	pub prev_batch: Option<String>,
+	pub next_batch: Option<String>,
+
+	/// Estimated number of the rooms matching, counting every published room
+	/// when there is no search term.
+	pub total: usize,
 }
 
+/// Key of the joined member count index: the count is inverted so the largest
+/// rooms sort first.
+type JoinedKey<'a> = (u64, &'a RoomId);
+
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			db: Data {
 				publicroomids: args.db["publicroomids"].clone(),
+				publicroomjoined_roomid: args.db["publicroomjoined_roomid"].clone(),
+				publicroomkeyword_roomid: args.db["publicroomkeyword_roomid"].clone(),
+			},
+			services: Services {
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 			},
 		}))
 	}
@@ -26,10 +76,19 @@ impl crate::Service for Service {
 }
 
 #[implement(Service)]
-pub fn set_public(&self, room_id: &RoomId) { self.db.publicroomids.insert(room_id, []); }
+pub async fn set_public(&self, room_id: &RoomId) {
+	self.db
+		.publicroomids
+		.raw_put(room_id, Json(Indexed::default()));
+
+	self.reindex(room_id).await;
+}
 
 #[implement(Service)]
-pub fn set_not_public(&self, room_id: &RoomId) { self.db.publicroomids.remove(room_id); }
+pub async fn set_not_public(&self, room_id: &RoomId) {
+	self.unindex(room_id).await;
+	self.db.publicroomids.remove(room_id);
+}
 
 #[implement(Service)]
 pub fn public_rooms(&self) -> impl Stream<Item = &RoomId> + Send {
@@ -49,3 +108,226 @@ pub async fn visibility(&self, room_id: &RoomId) -> Visibility {
 		Visibility::Private
 	}
 }
+
+/// Update the index entries of a published room after its joined member
+/// count, type, name, topic or canonical alias changed. Rooms which are not
+/// published are ignored.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn reindex(&self, room_id: &RoomId) {
+	if !self.is_public_room(room_id).await {
+		return;
+	}
+
+	let state_accessor = &self.services.state_accessor;
+	let mut keywords = BTreeSet::new();
+	let name = state_accessor.get_name(room_id).await.ok();
+	let topic = state_accessor.get_room_topic(room_id).await.ok();
+	let alias = state_accessor.get_canonical_alias(room_id).await.ok();
+	for text in [name.as_deref(), topic.as_deref(), alias.as_ref().map(AsRef::as_ref)]
+		.into_iter()
+		.flatten()
+	{
+		keywords.extend(words(text));
+	}
+
+	let indexed = Indexed {
+		joined: self
+			.services
+			.state_cache
+			.room_joined_count(room_id)
+			.await
+			.unwrap_or(0),
+		room_type: state_accessor
+			.get_room_type(room_id)
+			.await
+			.ok()
+			.map(|room_type| room_type.to_string()),
+		keywords,
+	};
+
+	self.unindex(room_id).await;
+	let room_type = indexed.room_type.as_deref().unwrap_or_default();
+	let key: JoinedKey<'_> = (u64::MAX.saturating_sub(indexed.joined), room_id);
+	self.db.publicroomjoined_roomid.put_raw(key, room_type);
+	for keyword in &indexed.keywords {
+		self.db
+			.publicroomkeyword_roomid
+			.put_raw((keyword, room_id), []);
+	}
+
+	self.db.publicroomids.raw_put(room_id, Json(indexed));
+}
+
+#[implement(Service)]
+async fn unindex(&self, room_id: &RoomId) {
+	let Ok(indexed) = self
+		.db
+		.publicroomids
+		.get(room_id)
+		.await
+		.deserialized::<Indexed>()
+	else {
+		return;
+	};
+
+	let key: JoinedKey<'_> = (u64::MAX.saturating_sub(indexed.joined), room_id);
+	self.db.publicroomjoined_roomid.del(key);
+	for keyword in &indexed.keywords {
+		self.db.publicroomkeyword_roomid.del((keyword, room_id));
+	}
+}
+
+/// A page of the published rooms matching the search term and room types,
+/// largest first. Every word of the search term must begin one of the words
+/// of a room's name, topic or canonical alias. The page starts after (or, for
+/// a `p` token, ends before) the room the `since` token was given for, read
+/// from the joined member count index from there on.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn public_rooms_page(
+	&self,
+	search_term: Option<&str>,
+	room_types: &[RoomTypeFilter],
+	since: Option<&str>,
+	limit: usize,
+) -> Result<PublicRoomsPage> {
+	let since = since.map(parse_token).transpose()?;
+	let candidates = match search_term.map(words) {
+		| Some(terms) if !terms.is_empty() => Some(self.search(terms).await),
+		| _ => None,
+	};
+
+	let matches = |((_, room_id), room_type): &(JoinedKey<'_>, &str)| {
+		let room_type = Some(*room_type).filter(|room_type| !room_type.is_empty());
+		candidates
+			.as_ref()
+			.is_none_or(|candidates| candidates.contains(*room_id))
+			&& (room_types.is_empty()
+				|| room_types.iter().any(|filter| filter.as_str() == room_type))
+	};
+
+	// One more room than the limit is taken to tell whether another page follows
+	let take = limit.saturating_add(1);
+	let index = &self.db.publicroomjoined_roomid;
+	let mut page: Vec<(u64, OwnedRoomId)> = match &since {
+		| None =>
+			index
+				.stream()
+				.ignore_err()
+				.ready_filter(matches)
+				.map(|((inverted, room_id), _)| (inverted, room_id.to_owned()))
+				.take(take)
+				.collect()
+				.await,
+		| Some((backwards, (inverted, room_id))) => {
+			let position: JoinedKey<'_> = (*inverted, room_id);
+			let entries = if *backwards {
+				index.rev_stream_from(&position).boxed()
+			} else {
+				index.stream_from(&position).boxed()
+			};
+
+			entries
+				.ignore_err()
+				.ready_filter(|entry: &(JoinedKey<'_>, &str)| {
+					entry.0 != position && matches(entry)
+				})
+				.map(|((inverted, room_id), _)| (inverted, room_id.to_owned()))
+				.take(take)
+				.collect()
+				.await
+		},
+	};
+
+	let more = page.len() > limit;
+	page.truncate(limit);
+	let (prev, next) = match since {
+		| None => (false, more),
+		| Some((false, _)) => (true, more),
+		| Some((true, _)) => {
+			page.reverse();
+			(more, true)
+		},
+	};
+
+	let total = match &candidates {
+		| Some(candidates) => candidates.len(),
+		| None => usize::try_from(index.stats().keys).unwrap_or(usize::MAX),
+	};
+
+	let token = |prefix, (inverted, room_id): &(u64, OwnedRoomId)| {
+		format!("{prefix}{inverted}_{room_id}")
+	};
+
+	Ok(PublicRoomsPage {
+		prev_batch: page.first().filter(|_| prev).map(|first| token('p', first)),
+		next_batch: page.last().filter(|_| next).map(|last| token('n', last)),
+		rooms: page.into_iter().map(|(_, room_id)| room_id).collect(),
+		total,
+	})
+}
+
+/// Rooms with a keyword beginning with each of the terms.
+#[implement(Service)]
+async fn search(&self, terms: BTreeSet<String>) -> HashSet<OwnedRoomId> {
+	let mut found: Option<HashSet<OwnedRoomId>> = None;
+	for term in &terms {
+		let rooms: HashSet<OwnedRoomId> = self
+			.db
+			.publicroomkeyword_roomid
+			.keys_raw_prefix(term)
+			.ignore_err()
+			.map(|(_, room_id): (&str, &RoomId)| room_id.to_owned())
+			.collect()
+			.await;
+
+		let rooms = match found {
+			| None => rooms,
+			| Some(mut found) => {
+				found.retain(|room_id| rooms.contains(room_id));
+				found
+			},
+		};
+
+		let empty = rooms.is_empty();
+		found = Some(rooms);
+		if empty {
+			break;
+		}
+	}
+
+	found.unwrap_or_default()
+}
+
+/// The lowercase words of the text; anything which is not alphanumeric
+/// separates words.
+pub(crate) fn words(text: &str) -> BTreeSet<String> {
+	text.split(|c: char| !c.is_alphanumeric())
+		.filter(|word| !word.is_empty())
+		.map(str::to_lowercase)
+		.collect()
+}
+
+fn parse_token(token: &str) -> Result<(bool, (u64, OwnedRoomId))> {
+	let backwards = match token.chars().next() {
+		| Some('n') => false,
+		| Some('p') => true,
+		| _ => return Err!(Request(InvalidParam("Invalid `since` token"))),
+	};
+
+	let (inverted, room_id) = token
+		.get(1..)
+		.and_then(|position| position.split_once('_'))
+		.ok_or_else(|| err!(Request(InvalidParam("Invalid `since` token"))))?;
+
+	let inverted = inverted
+		.parse()
+		.map_err(|_| err!(Request(InvalidParam("Invalid `since` token"))))?;
+
+	let room_id = room_id
+		.try_into()
+		.map_err(|_| err!(Request(InvalidParam("Invalid `since` token"))))?;
+
+	Ok((backwards, (inverted, room_id)))
+}
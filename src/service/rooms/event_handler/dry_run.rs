@@ -0,0 +1,98 @@
+use std::collections::HashMap;
+
+use conduwuit::{implement, info, PduEvent, Result};
+use ruma::{OwnedEventId, RoomId, RoomVersionId};
+
+use crate::rooms::timeline::RawPduId;
+
+/// Whether inbound events for the room are validated without being committed.
+#[implement(super::Service)]
+pub fn is_dry_run(&self, room_id: &RoomId) -> bool {
+	self.services
+		.server
+		.config
+		.federation_dry_run_rooms
+		.iter()
+		.any(|dry_run| dry_run == room_id)
+}
+
+/// Log what accepting the event would do to the room, in place of doing it.
+/// The event has passed the auth checks against the state at the event by
+/// now; for a state event the room state is resolved as it would be, but the
+/// result is only compared with the current state and then discarded.
+#[implement(super::Service)]
+#[tracing::instrument(
+	name = "dry_run",
+	level = "debug",
+	skip_all,
+	fields(event_id = %incoming_pdu.event_id)
+)]
+pub(super) async fn dry_run(
+	&self,
+	incoming_pdu: &PduEvent,
+	room_id: &RoomId,
+	room_version_id: &RoomVersionId,
+	mut state_at_incoming_event: HashMap<u64, OwnedEventId>,
+	soft_fail: bool,
+) -> Result<Option<RawPduId>> {
+	let kind = &incoming_pdu.kind;
+	let sender = &incoming_pdu.sender;
+	if soft_fail {
+		info!(%kind, %sender, "Dry run: event would be soft failed");
+		return Ok(None);
+	}
+
+	let Some(state_key) = &incoming_pdu.state_key else {
+		info!(%kind, %sender, "Dry run: event would be accepted");
+		return Ok(None);
+	};
+
+	let shortstatekey = self
+		.services
+		.short
+		.get_or_create_shortstatekey(&kind.to_string().into(), state_key)
+		.await;
+
+	state_at_incoming_event.insert(shortstatekey, incoming_pdu.event_id.clone());
+	let new_room_state = self
+		.resolve_state(room_id, room_version_id, state_at_incoming_event)
+		.await?;
+
+	let current_shortstatehash = self.services.state.get_room_shortstatehash(room_id).await?;
+	let current_state = self
+		.services
+		.state_compressor
+		.load_shortstatehash_info(current_shortstatehash)
+		.await?
+		.pop()
+		.expect("there is always one layer")
+		.full_state;
+
+	let added = new_room_state
+		.iter()
+		.filter(|event| !current_state.contains(*event))
+		.count();
+
+	let removed = current_state
+		.iter()
+		.filter(|event| !new_room_state.contains(*event))
+		.count();
+
+	let compressed = self
+		.services
+		.state_compressor
+		.compress_state_event(shortstatekey, &incoming_pdu.event_id)
+		.await;
+
+	info!(
+		%kind,
+		%sender,
+		%state_key,
+		%added,
+		%removed,
+		in_resolved_state = new_room_state.contains(&compressed),
+		"Dry run: state event would be accepted"
+	);
+
+	Ok(None)
+}
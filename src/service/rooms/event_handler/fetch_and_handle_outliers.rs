@@ -1,19 +1,17 @@
 use std::{
-	collections::{hash_map, BTreeMap, HashSet, VecDeque},
+	collections::{BTreeMap, HashSet, VecDeque},
 	sync::Arc,
-	time::Instant,
 };
 
-use conduwuit::{
-	debug, debug_error, debug_warn, implement, pdu, trace,
-	utils::continue_exponential_backoff_secs, warn, PduEvent,
-};
+use conduwuit::{debug, debug_error, debug_warn, implement, pdu, trace, warn, PduEvent};
 use futures::TryFutureExt;
 use ruma::{
 	api::federation::event::get_event, CanonicalJsonValue, OwnedEventId, RoomId, RoomVersionId,
 	ServerName,
 };
 
+use crate::globals::Backoff;
+
 /// Find the event and auth it. Once the event is validated (steps 1 - 8)
 /// it is appended to the outliers Tree.
 ///
@@ -32,21 +30,7 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 	room_id: &'a RoomId,
 	room_version_id: &'a RoomVersionId,
 ) -> Vec<(Arc<PduEvent>, Option<BTreeMap<String, CanonicalJsonValue>>)> {
-	let back_off = |id| match self
-		.services
-		.globals
-		.bad_event_ratelimiter
-		.write()
-		.expect("locked")
-		.entry(id)
-	{
-		| hash_map::Entry::Vacant(e) => {
-			e.insert((Instant::now(), 1));
-		},
-		| hash_map::Entry::Occupied(mut e) => {
-			*e.get_mut() = (Instant::now(), e.get().1.saturating_add(1));
-		},
-	};
+	let back_off = |id| self.services.globals.back_off_event(id);
 
 	let mut events_with_auth_events = Vec::with_capacity(events.len());
 	for id in events {
@@ -66,30 +50,13 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 		let mut events_in_reverse_order = Vec::with_capacity(todo_auth_events.len());
 		let mut events_all = HashSet::with_capacity(todo_auth_events.len());
 		while let Some(next_id) = todo_auth_events.pop_front() {
-			if let Some((time, tries)) = self
+			if let Some((tries, elapsed)) = self
 				.services
 				.globals
-				.bad_event_ratelimiter
-				.read()
-				.expect("locked")
-				.get(&*next_id)
+				.event_backed_off(&next_id, Backoff::Fetch)
 			{
-				// Exponential backoff
-				const MIN_DURATION: u64 = 60 * 2;
-				const MAX_DURATION: u64 = 60 * 60 * 8;
-				if continue_exponential_backoff_secs(
-					MIN_DURATION,
-					MAX_DURATION,
-					time.elapsed(),
-					*tries,
-				) {
-					debug_warn!(
-						tried = ?*tries,
-						elapsed = ?time.elapsed(),
-						"Backing off from {next_id}",
-					);
-					continue;
-				}
+				debug_warn!(?tries, ?elapsed, "Backing off from {next_id}");
+				continue;
 			}
 
 			if events_all.contains(&next_id) {
@@ -168,26 +135,13 @@ pub(super) async fn fetch_and_handle_outliers<'a>(
 		}
 
 		for (next_id, value) in events_in_reverse_order.into_iter().rev() {
-			if let Some((time, tries)) = self
+			if let Some((tries, elapsed)) = self
 				.services
 				.globals
-				.bad_event_ratelimiter
-				.read()
-				.expect("locked")
-				.get(&*next_id)
+				.event_backed_off(&next_id, Backoff::Handle)
 			{
-				// Exponential backoff
-				const MIN_DURATION: u64 = 5 * 60;
-				const MAX_DURATION: u64 = 60 * 60 * 24;
-				if continue_exponential_backoff_secs(
-					MIN_DURATION,
-					MAX_DURATION,
-					time.elapsed(),
-					*tries,
-				) {
-					debug!("Backing off from {next_id}");
-					continue;
-				}
+				debug!(?tries, ?elapsed, "Backing off from {next_id}");
+				continue;
 			}
 
 			match Box::pin(self.handle_outlier_pdu(
@@ -1,8 +1,4 @@
-use std::{
-	collections::{hash_map, BTreeMap},
-	sync::Arc,
-	time::Instant,
-};
+use std::{collections::BTreeMap, sync::Arc, time::Instant};
 
 use conduwuit::{debug, err, implement, warn, Err, Result};
 use futures::{FutureExt, TryFutureExt};
@@ -137,26 +133,8 @@ pub async fn handle_incoming_pdu<'a>(
 			)
 			.await
 		{
-			use hash_map::Entry;
-
-			let now = Instant::now();
 			warn!("Prev event {prev_id} failed: {e}");
-
-			match self
-				.services
-				.globals
-				.bad_event_ratelimiter
-				.write()
-				.expect("locked")
-				.entry(prev_id)
-			{
-				| Entry::Vacant(e) => {
-					e.insert((now, 1));
-				},
-				| Entry::Occupied(mut e) => {
-					*e.get_mut() = (now, e.get().1.saturating_add(1));
-				},
-			};
+			self.services.globals.back_off_event(prev_id);
 		}
 	}
 
@@ -1,6 +1,7 @@
 use std::{
 	collections::{hash_map, BTreeMap, HashMap},
 	sync::Arc,
+	time::Instant,
 };
 
 use conduwuit::{debug, debug_info, err, implement, trace, warn, Err, Error, PduEvent, Result};
@@ -12,7 +13,7 @@ use ruma::{
 	CanonicalJsonObject, CanonicalJsonValue, EventId, RoomId, ServerName,
 };
 
-use super::{check_room_id, get_room_version_id, to_room_version};
+use super::{check_room_id, get_room_version_id, to_room_version, Stage};
 
 #[implement(super::Service)]
 #[allow(clippy::too_many_arguments)]
@@ -33,12 +34,15 @@ pub(super) async fn handle_outlier_pdu<'a>(
 	// 2. Check signatures, otherwise drop
 	// 3. check content hash, redact if doesn't match
 	let room_version_id = get_room_version_id(create_event)?;
-	let mut val = match self
+	let started = Instant::now();
+	let verified = self
 		.services
 		.server_keys
 		.verify_event(&value, Some(&room_version_id))
-		.await
-	{
+		.await;
+
+	self.record_stage(Stage::Signatures, started);
+	let mut val = match verified {
 		| Ok(ruma::signatures::Verified::All) => value,
 		| Ok(ruma::signatures::Verified::Signatures) => {
 			// Redact
@@ -79,6 +83,7 @@ pub(super) async fn handle_outlier_pdu<'a>(
 		//    the auth events are also rejected "due to auth events"
 		// NOTE: Step 5 is not applied anymore because it failed too often
 		debug!("Fetching auth events");
+		let started = Instant::now();
 		Box::pin(self.fetch_and_handle_outliers(
 			origin,
 			&incoming_pdu.auth_events,
@@ -87,6 +92,8 @@ pub(super) async fn handle_outlier_pdu<'a>(
 			&room_version_id,
 		))
 		.await;
+
+		self.record_stage(Stage::AuthChain, started);
 	}
 
 	// 6. Reject "due to auth events" if the event doesn't pass auth based on the
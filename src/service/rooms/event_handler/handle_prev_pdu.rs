@@ -4,11 +4,11 @@ use std::{
 	time::Instant,
 };
 
-use conduwuit::{
-	debug, implement, utils::continue_exponential_backoff_secs, Err, PduEvent, Result,
-};
+use conduwuit::{debug, implement, Err, PduEvent, Result};
 use ruma::{CanonicalJsonValue, EventId, OwnedEventId, RoomId, ServerName};
 
+use crate::globals::Backoff;
+
 #[implement(super::Service)]
 #[allow(clippy::type_complexity)]
 #[allow(clippy::too_many_arguments)]
@@ -39,25 +39,13 @@ pub(super) async fn handle_prev_pdu<'a>(
 		))));
 	}
 
-	if let Some((time, tries)) = self
+	if let Some((tries, elapsed)) = self
 		.services
 		.globals
-		.bad_event_ratelimiter
-		.read()
-		.expect("locked")
-		.get(prev_id)
+		.event_backed_off(prev_id, Backoff::Handle)
 	{
-		// Exponential backoff
-		const MIN_DURATION: u64 = 5 * 60;
-		const MAX_DURATION: u64 = 60 * 60 * 24;
-		if continue_exponential_backoff_secs(MIN_DURATION, MAX_DURATION, time.elapsed(), *tries) {
-			debug!(
-				?tries,
-				duration = ?time.elapsed(),
-				"Backing off from prev_event"
-			);
-			return Ok(());
-		}
+		debug!(?tries, duration = ?elapsed, "Backing off from prev_event");
+		return Ok(());
 	}
 
 	if let Some((pdu, json)) = eventid_info.remove(prev_id) {
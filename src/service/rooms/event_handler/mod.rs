@@ -1,4 +1,5 @@
 mod acl_check;
+mod dry_run;
 mod fetch_and_handle_outliers;
 mod fetch_prev;
 mod fetch_state;
@@ -8,12 +9,13 @@ mod handle_prev_pdu;
 mod parse_incoming_pdu;
 mod resolve_state;
 mod state_at_incoming;
+mod timing;
 mod upgrade_outlier_pdu;
 
 use std::{
 	collections::HashMap,
 	fmt::Write,
-	sync::{Arc, RwLock as StdRwLock},
+	sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock},
 	time::Instant,
 };
 
@@ -21,17 +23,21 @@ use conduwuit::{
 	utils::{MutexMap, TryFutureExtExt},
 	Err, PduEvent, Result, Server,
 };
+use database::Database;
 use futures::TryFutureExt;
 use ruma::{
 	events::room::create::RoomCreateEventContent, state_res::RoomVersion, OwnedEventId,
 	OwnedRoomId, RoomId, RoomVersionId,
 };
 
-use crate::{globals, rooms, sending, server_keys, Dep};
+use self::timing::StageTimings;
+pub use self::timing::{Stage, StageTiming};
+use crate::{globals, rooms, sending, server_keys, spamcheck, users, Dep};
 
 pub struct Service {
 	pub mutex_federation: RoomMutexMap,
 	pub federation_handletime: StdRwLock<HandleTimeMap>,
+	stage_timings: StdMutex<StageTimings>,
 	services: Services,
 }
 
@@ -39,16 +45,20 @@ struct Services {
 	globals: Dep<globals::Service>,
 	sending: Dep<sending::Service>,
 	auth_chain: Dep<rooms::auth_chain::Service>,
+	joins: Dep<rooms::joins::Service>,
 	metadata: Dep<rooms::metadata::Service>,
 	outlier: Dep<rooms::outlier::Service>,
 	pdu_metadata: Dep<rooms::pdu_metadata::Service>,
 	server_keys: Dep<server_keys::Service>,
 	short: Dep<rooms::short::Service>,
+	spamcheck: Dep<spamcheck::Service>,
 	state: Dep<rooms::state::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_compressor: Dep<rooms::state_compressor::Service>,
 	timeline: Dep<rooms::timeline::Service>,
+	users: Dep<users::Service>,
 	server: Arc<Server>,
+	db: Arc<Database>,
 }
 
 type RoomMutexMap = MutexMap<OwnedRoomId, ()>;
@@ -59,22 +69,27 @@ impl crate::Service for Service {
 		Ok(Arc::new(Self {
 			mutex_federation: RoomMutexMap::new(),
 			federation_handletime: HandleTimeMap::new().into(),
+			stage_timings: StageTimings::new().into(),
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
 				auth_chain: args.depend::<rooms::auth_chain::Service>("rooms::auth_chain"),
+				joins: args.depend::<rooms::joins::Service>("rooms::joins"),
 				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
 				outlier: args.depend::<rooms::outlier::Service>("rooms::outlier"),
 				server_keys: args.depend::<server_keys::Service>("server_keys"),
 				pdu_metadata: args.depend::<rooms::pdu_metadata::Service>("rooms::pdu_metadata"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
+				spamcheck: args.depend::<spamcheck::Service>("spamcheck"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				state_compressor: args
 					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
+				users: args.depend::<users::Service>("users"),
 				server: args.server.clone(),
+				db: args.db.clone(),
 			},
 		}))
 	}
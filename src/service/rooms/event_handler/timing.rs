@@ -0,0 +1,95 @@
+use std::{
+	collections::{BTreeMap, VecDeque},
+	time::{Duration, Instant},
+};
+
+use conduwuit::implement;
+
+/// Timings kept per stage; the oldest is dropped as each new one is recorded.
+const TIMING_WINDOW: usize = 1024;
+
+/// Stages of handling an inbound PDU which are timed separately.
+#[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd)]
+pub enum Stage {
+	/// Verifying the signatures and content hash of an event.
+	Signatures,
+
+	/// Fetching and handling an event's missing auth events.
+	AuthChain,
+
+	/// Determining the state at an event, from our copy of its prev_events or
+	/// from a remote server.
+	StateFetch,
+
+	/// Resolving the room state after a state event.
+	StateRes,
+
+	/// Appending an event to the timeline once it has been accepted.
+	Append,
+}
+
+/// Distribution of the recent timings of a stage.
+#[derive(Debug)]
+pub struct StageTiming {
+	pub count: usize,
+	pub mean: Duration,
+	pub p50: Duration,
+	pub p90: Duration,
+	pub p99: Duration,
+	pub max: Duration,
+}
+
+pub(super) type StageTimings = BTreeMap<Stage, VecDeque<Duration>>;
+
+/// Record the time a stage took, having started at `started`.
+#[implement(super::Service)]
+pub(super) fn record_stage(&self, stage: Stage, started: Instant) {
+	let elapsed = started.elapsed();
+	let mut timings = self.stage_timings.lock().expect("locked");
+	let samples = timings.entry(stage).or_default();
+	if samples.len() >= TIMING_WINDOW {
+		samples.pop_front();
+	}
+
+	samples.push_back(elapsed);
+}
+
+/// The distribution of each stage's recent timings, for stages which have run.
+#[implement(super::Service)]
+#[must_use]
+pub fn stage_timings(&self) -> Vec<(Stage, StageTiming)> {
+	let timings = self.stage_timings.lock().expect("locked");
+	timings
+		.iter()
+		.filter(|(_, samples)| !samples.is_empty())
+		.map(|(stage, samples)| {
+			let mut sorted: Vec<_> = samples.iter().copied().collect();
+			sorted.sort_unstable();
+
+			let count = sorted.len();
+			let percentile = |p: usize| {
+				let index = count.saturating_mul(p).saturating_div(100);
+				sorted[index.min(count.saturating_sub(1))]
+			};
+
+			let total = sorted
+				.iter()
+				.fold(Duration::ZERO, |a, b| a.saturating_add(*b));
+			let mean = u32::try_from(count)
+				.ok()
+				.and_then(|count| total.checked_div(count))
+				.unwrap_or_default();
+
+			let timing = StageTiming {
+				count,
+				mean,
+				p50: percentile(50),
+				p90: percentile(90),
+				p99: percentile(99),
+				max: sorted[count.saturating_sub(1)],
+			};
+
+			(*stage, timing)
+		})
+		.collect()
+}
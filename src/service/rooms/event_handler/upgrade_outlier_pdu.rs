@@ -9,12 +9,15 @@ use conduwuit::{debug, debug_info, err, implement, trace, warn, Err, Error, PduE
 use futures::{future::ready, StreamExt};
 use ruma::{
 	api::client::error::ErrorKind,
-	events::{room::redaction::RoomRedactionEventContent, StateEventType, TimelineEventType},
+	events::{
+		room::{member::RoomMemberEventContent, redaction::RoomRedactionEventContent},
+		StateEventType, TimelineEventType,
+	},
 	state_res::{self, EventTypeExt},
 	CanonicalJsonValue, RoomId, RoomVersionId, ServerName,
 };
 
-use super::{get_room_version_id, to_room_version};
+use super::{get_room_version_id, to_room_version, Stage};
 use crate::rooms::{state_compressor::HashSetCompressStateEvent, timeline::RawPduId};
 
 #[implement(super::Service)]
@@ -54,7 +57,13 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 	//     These are not timeline events.
 
 	debug!("Resolving state at event");
-	let mut state_at_incoming_event = if incoming_pdu.prev_events.len() == 1 {
+	let started = Instant::now();
+	// The state we have of a partial-state room lacks the members; the state
+	// at the event is asked of its origin instead.
+	let partial_state = self.services.joins.is_partial_state(room_id).await;
+	let mut state_at_incoming_event = if partial_state {
+		None
+	} else if incoming_pdu.prev_events.len() == 1 {
 		self.state_at_incoming_degree_one(&incoming_pdu).await?
 	} else {
 		self.state_at_incoming_resolved(&incoming_pdu, room_id, &room_version_id)
@@ -67,6 +76,7 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 			.await?;
 	}
 
+	self.record_stage(Stage::StateFetch, started);
 	let state_at_incoming_event =
 		state_at_incoming_event.expect("we always set this to some above");
 	let room_version = to_room_version(&room_version_id);
@@ -121,12 +131,19 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 	.await
 	.map_err(|e| err!(Request(Forbidden("Auth check failed: {e:?}"))))?;
 
+	// The members missing from the state of a partial-state room fail the check
+	// above; only a membership we have of the sender can soft-fail the event.
+	let sender_unknown = partial_state
+		&& !auth_events.contains_key(
+			&StateEventType::RoomMember.with_state_key(incoming_pdu.sender.as_str()),
+		);
+
 	// Soft fail check before doing state res
 	debug!("Performing soft-fail check");
 	let soft_fail = {
 		use RoomVersionId::*;
 
-		!auth_check
+		!auth_check && !sender_unknown
 			|| incoming_pdu.kind == TimelineEventType::RoomRedaction
 				&& match room_version_id {
 					| V1 | V2 | V3 | V4 | V5 | V6 | V7 | V8 | V9 | V10 => {
@@ -165,139 +182,181 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 				}
 	};
 
+	debug!("Checking event for spam");
+	let soft_fail = soft_fail
+		|| self
+			.services
+			.spamcheck
+			.check_event_for_spam(&incoming_pdu)
+			.await
+			.is_denied()
+		|| self.member_displayname_forbidden(&incoming_pdu);
+
+	if self.is_dry_run(room_id) {
+		return self
+			.dry_run(&incoming_pdu, room_id, &room_version_id, state_at_incoming_event, soft_fail)
+			.await;
+	}
+
 	// 13. Use state resolution to find new room state
 
 	// We start looking at current room state now, so lets lock the room
 	trace!("Locking the room");
 	let state_lock = self.services.state.mutex.lock(room_id).await;
 
-	// Now we calculate the set of extremities this room has after the incoming
-	// event has been applied. We start with the previous extremities (aka leaves)
-	trace!("Calculating extremities");
-	let mut extremities: HashSet<_> = self
-		.services
-		.state
-		.get_forward_extremities(room_id)
-		.map(ToOwned::to_owned)
-		.collect()
-		.await;
-
-	// Remove any forward extremities that are referenced by this incoming event's
-	// prev_events
-	trace!(
-		"Calculated {} extremities; checking against {} prev_events",
-		extremities.len(),
-		incoming_pdu.prev_events.len()
-	);
-	for prev_event in &incoming_pdu.prev_events {
-		extremities.remove(&(**prev_event));
-	}
-
-	// Only keep those extremities were not referenced yet
-	let mut retained = HashSet::new();
-	for id in &extremities {
-		if !self
-			.services
-			.pdu_metadata
-			.is_event_referenced(room_id, id)
-			.await
-		{
-			retained.insert(id.clone());
-		}
-	}
-
-	extremities.retain(|id| retained.contains(id));
-	debug!("Retained {} extremities. Compressing state", extremities.len());
-
-	let state_ids_compressed: HashSet<_> = self
+	// Commit the event's local writes together; everything it needs from
+	// federation has been fetched by now.
+	let pdu_id = self
 		.services
-		.state_compressor
-		.compress_state_events(
-			state_at_incoming_event
-				.iter()
-				.map(|(ssk, eid)| (ssk, eid.borrow())),
-		)
-		.collect()
-		.await;
-
-	let state_ids_compressed = Arc::new(state_ids_compressed);
-
-	if incoming_pdu.state_key.is_some() {
-		debug!("Event is a state-event. Deriving new room state");
-
-		// We also add state after incoming event to the fork states
-		let mut state_after = state_at_incoming_event.clone();
-		if let Some(state_key) = &incoming_pdu.state_key {
-			let shortstatekey = self
+		.db
+		.group_commit(async {
+			// Now we calculate the set of extremities this room has after the
+			// incoming event has been applied. We start with the previous
+			// extremities (aka leaves)
+			trace!("Calculating extremities");
+			let mut extremities: HashSet<_> = self
 				.services
-				.short
-				.get_or_create_shortstatekey(&incoming_pdu.kind.to_string().into(), state_key)
+				.state
+				.get_forward_extremities(room_id)
+				.map(ToOwned::to_owned)
+				.collect()
 				.await;
 
-			let event_id = &incoming_pdu.event_id;
-			state_after.insert(shortstatekey, event_id.clone());
-		}
-
-		let new_room_state = self
-			.resolve_state(room_id, &room_version_id, state_after)
-			.await?;
-
-		// Set the new room state to the resolved state
-		debug!("Forcing new room state");
-		let HashSetCompressStateEvent { shortstatehash, added, removed } = self
-			.services
-			.state_compressor
-			.save_state(room_id, new_room_state)
-			.await?;
-
-		self.services
-			.state
-			.force_state(room_id, shortstatehash, added, removed, &state_lock)
-			.await?;
-	}
-
-	// 14. Check if the event passes auth based on the "current state" of the room,
-	//     if not soft fail it
-	if soft_fail {
-		debug!("Soft failing event");
-		self.services
-			.timeline
-			.append_incoming_pdu(
-				&incoming_pdu,
-				val,
-				extremities.iter().map(|e| (**e).to_owned()).collect(),
-				state_ids_compressed,
-				soft_fail,
-				&state_lock,
-			)
-			.await?;
+			// Remove any forward extremities that are referenced by this
+			// incoming event's prev_events
+			trace!(
+				"Calculated {} extremities; checking against {} prev_events",
+				extremities.len(),
+				incoming_pdu.prev_events.len()
+			);
+			for prev_event in &incoming_pdu.prev_events {
+				extremities.remove(&(**prev_event));
+			}
+
+			// Only keep those extremities were not referenced yet
+			let mut retained = HashSet::new();
+			for id in &extremities {
+				if !self
+					.services
+					.pdu_metadata
+					.is_event_referenced(room_id, id)
+					.await
+				{
+					retained.insert(id.clone());
+				}
+			}
 
-		// Soft fail, we keep the event as an outlier but don't add it to the timeline
-		warn!("Event was soft failed: {incoming_pdu:?}");
-		self.services
-			.pdu_metadata
-			.mark_event_soft_failed(&incoming_pdu.event_id);
+			extremities.retain(|id| retained.contains(id));
+			debug!("Retained {} extremities. Compressing state", extremities.len());
 
-		return Err(Error::BadRequest(ErrorKind::InvalidParam, "Event has been soft failed"));
-	}
+			let state_ids_compressed: HashSet<_> = self
+				.services
+				.state_compressor
+				.compress_state_events(
+					state_at_incoming_event
+						.iter()
+						.map(|(ssk, eid)| (ssk, eid.borrow())),
+				)
+				.collect()
+				.await;
 
-	trace!("Appending pdu to timeline");
-	extremities.insert(incoming_pdu.event_id.clone());
+			let state_ids_compressed = Arc::new(state_ids_compressed);
+
+			if incoming_pdu.state_key.is_some() {
+				debug!("Event is a state-event. Deriving new room state");
+
+				// We also add state after incoming event to the fork states
+				let mut state_after = state_at_incoming_event.clone();
+				if let Some(state_key) = &incoming_pdu.state_key {
+					let shortstatekey = self
+						.services
+						.short
+						.get_or_create_shortstatekey(
+							&incoming_pdu.kind.to_string().into(),
+							state_key,
+						)
+						.await;
+
+					let event_id = &incoming_pdu.event_id;
+					state_after.insert(shortstatekey, event_id.clone());
+				}
 
-	// Now that the event has passed all auth it is added into the timeline.
-	// We use the `state_at_event` instead of `state_after` so we accurately
-	// represent the state for this event.
-	let pdu_id = self
-		.services
-		.timeline
-		.append_incoming_pdu(
-			&incoming_pdu,
-			val,
-			extremities.into_iter().collect(),
-			state_ids_compressed,
-			soft_fail,
-			&state_lock,
-		)
+				let started = Instant::now();
+				let new_room_state = self
+					.resolve_state(room_id, &room_version_id, state_after)
+					.await?;
+
+				self.record_stage(Stage::StateRes, started);
+
+				// Set the new room state to the resolved state
+				debug!("Forcing new room state");
+				let HashSetCompressStateEvent { shortstatehash, added, removed } = self
+					.services
+					.state_compressor
+					.save_state(room_id, new_room_state)
+					.await?;
+
+				self.services
+					.state
+					.force_state(room_id, shortstatehash, added, removed, &state_lock)
+					.await?;
+			}
+
+			// 14. Check if the event passes auth based on the "current state" of the room,
+			//     if not soft fail it
+			if soft_fail {
+				debug!("Soft failing event");
+				let started = Instant::now();
+				self.services
+					.timeline
+					.append_incoming_pdu(
+						&incoming_pdu,
+						val,
+						extremities.iter().map(|e| (**e).to_owned()).collect(),
+						state_ids_compressed,
+						soft_fail,
+						&state_lock,
+					)
+					.await?;
+
+				self.record_stage(Stage::Append, started);
+
+				// Soft fail, we keep the event as an outlier but don't add it to
+				// the timeline
+				warn!("Event was soft failed: {incoming_pdu:?}");
+				self.services
+					.pdu_metadata
+					.mark_event_soft_failed(&incoming_pdu.event_id);
+
+				return Err(Error::BadRequest(
+					ErrorKind::InvalidParam,
+					"Event has been soft failed",
+				));
+			}
+
+			trace!("Appending pdu to timeline");
+			extremities.insert(incoming_pdu.event_id.clone());
+
+			// Now that the event has passed all auth it is added into the
+			// timeline. We use the `state_at_event` instead of `state_after` so
+			// we accurately represent the state for this event.
+			let started = Instant::now();
+			let pdu_id = self
+				.services
+				.timeline
+				.append_incoming_pdu(
+					&incoming_pdu,
+					val,
+					extremities.into_iter().collect(),
+					state_ids_compressed,
+					soft_fail,
+					&state_lock,
+				)
+				.await?;
+
+			self.record_stage(Stage::Append, started);
+			Ok::<_, Error>(pdu_id)
+		})
 		.await?;
 
 	// Event has passed all auth/stateres checks
@@ -309,3 +368,16 @@ pub(super) async fn upgrade_outlier_to_timeline_pdu(
 
 	Ok(pdu_id)
 }
+
+/// Whether the event is the membership of a remote user with a display name
+/// in forbidden_displaynames, which is not shown to local users.
+#[implement(super::Service)]
+fn member_displayname_forbidden(&self, pdu: &PduEvent) -> bool {
+	pdu.kind == TimelineEventType::RoomMember
+		&& !self.services.globals.user_is_local(&pdu.sender)
+		&& pdu
+			.get_content::<RoomMemberEventContent>()
+			.ok()
+			.and_then(|content| content.displayname)
+			.is_some_and(|displayname| self.services.users.is_displayname_forbidden(&displayname))
+}
@@ -0,0 +1,156 @@
+//! Image packs (MSC2545): custom emotes and stickers a room offers its
+//! members, one pack per `im.ponies.room_emotes` state key.
+
+use std::{
+	collections::BTreeMap,
+	fmt::Write,
+	sync::{Arc, Mutex},
+};
+
+use conduwuit::{
+	err, implement,
+	utils::{self, math::usize_from_f64},
+	Err, Result,
+};
+use lru_cache::LruCache;
+use ruma::{events::StateEventType, OwnedMxcUri, OwnedRoomId, RoomId};
+use serde::Deserialize;
+use serde_json::value::RawValue as RawJsonValue;
+
+use crate::{rooms, rooms::short::ShortStateHash, Dep};
+
+pub struct Service {
+	services: Services,
+	max_images: usize,
+	roomid_imagepacks_cache: Mutex<LruCache<OwnedRoomId, (ShortStateHash, Arc<RoomPacks>)>>,
+}
+
+struct Services {
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+}
+
+/// The room's image packs, by state key, as their event content.
+pub type RoomPacks = BTreeMap<String, Box<RawJsonValue>>;
+
+pub const ROOM_EMOTES: &str = "im.ponies.room_emotes";
+
+#[derive(Deserialize)]
+struct PackContent {
+	#[serde(default)]
+	images: BTreeMap<String, PackImage>,
+}
+
+#[derive(Deserialize)]
+struct PackImage {
+	url: OwnedMxcUri,
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let config = &args.server.config;
+		let cache_size = f64::from(config.image_pack_cache_capacity);
+		let cache_size = cache_size * config.cache_capacity_modifier;
+		Ok(Arc::new(Self {
+			services: Services {
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+			},
+			max_images: config.image_pack_max_images,
+			roomid_imagepacks_cache: Mutex::new(LruCache::new(usize_from_f64(cache_size)?)),
+		}))
+	}
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let (count, bytes) = self.roomid_imagepacks_cache.lock()?.iter().fold(
+			(0_usize, 0_usize),
+			|(count, bytes), (room_id, (_, packs))| {
+				let size = packs
+					.iter()
+					.map(|(state_key, content)| {
+						state_key.len().saturating_add(content.get().len())
+					})
+					.fold(room_id.as_bytes().len(), usize::saturating_add);
+
+				(count.saturating_add(1), bytes.saturating_add(size))
+			},
+		);
+
+		writeln!(out, "roomid_imagepacks_cache: {count} ({})", utils::bytes::pretty(bytes))?;
+
+		Ok(())
+	}
+
+	fn clear_cache(&self) { self.roomid_imagepacks_cache.lock().expect("locked").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+/// All of the room's image packs in its current state. Packs are cached for
+/// the room until its state changes.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn room_packs(&self, room_id: &RoomId) -> Result<Arc<RoomPacks>> {
+	let shortstatehash = self.services.state.get_room_shortstatehash(room_id).await?;
+	if let Some((cached, packs)) = self
+		.roomid_imagepacks_cache
+		.lock()?
+		.get_mut(room_id)
+		.map(|(cached, packs)| (*cached, packs.clone()))
+	{
+		if cached == shortstatehash {
+			return Ok(packs);
+		}
+	}
+
+	let packs: Arc<RoomPacks> = self
+		.services
+		.state_accessor
+		.state_type_pdus(shortstatehash, &StateEventType::from(ROOM_EMOTES))
+		.await?
+		.into_iter()
+		.filter_map(|pdu| {
+			// Packs are removed by emptying them.
+			let content: PackContent = serde_json::from_str(pdu.content.get()).ok()?;
+			(!content.images.is_empty()).then_some((pdu.state_key?, pdu.content))
+		})
+		.collect::<RoomPacks>()
+		.into();
+
+	self.roomid_imagepacks_cache
+		.lock()?
+		.insert(room_id.to_owned(), (shortstatehash, packs.clone()));
+
+	Ok(packs)
+}
+
+/// Check pack content sent by a local user is well-formed and within the
+/// configured size limit.
+#[implement(Service)]
+pub fn validate_pack(&self, content: &RawJsonValue) -> Result {
+	let pack: PackContent = serde_json::from_str(content.get())
+		.map_err(|e| err!(Request(BadJson("Invalid image pack: {e}"))))?;
+
+	if pack.images.len() > self.max_images {
+		return Err!(Request(TooLarge(
+			"Image pack has {} images, more than the maximum of {}.",
+			pack.images.len(),
+			self.max_images
+		)));
+	}
+
+	for (shortcode, image) in &pack.images {
+		if shortcode.is_empty() || shortcode.contains(|c: char| c == ':' || c.is_whitespace()) {
+			return Err!(Request(InvalidParam("Invalid image pack shortcode {shortcode:?}.")));
+		}
+
+		if !image.url.is_valid() {
+			return Err!(Request(InvalidParam(
+				"Invalid URL for image pack shortcode {shortcode:?}."
+			)));
+		}
+	}
+
+	Ok(())
+}
@@ -0,0 +1,343 @@
+//! Joins in progress. A join over federation can take minutes for a large
+//! room, during which clients retry: a join of a user to a room already in
+//! progress is waited for rather than started again. At most
+//! `max_concurrent_remote_joins` remote joins run at once; the others wait for
+//! their turn instead of failing. With `lazy_join_validation` the auth chain
+//! of a joined room is verified here in the background, and the full state of
+//! a room joined with partial state is fetched. The membership templates
+//! made for remote servers are kept a little while for their retries.
+
+mod partial;
+mod templates;
+
+use std::{
+	collections::BTreeMap,
+	fmt,
+	future::Future,
+	sync::{Arc, Mutex},
+	time::Instant,
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug_info, info,
+	utils::stream::{BroadbandExt, IterStream},
+	warn, Result, Server,
+};
+use database::Map;
+use futures::StreamExt;
+use loole::{Receiver, Sender};
+use lru_cache::LruCache;
+use ruma::{OwnedRoomId, OwnedUserId, RoomId, RoomVersionId, UserId};
+use serde_json::value::RawValue as RawJsonValue;
+use tokio::sync::{watch, Semaphore, SemaphorePermit};
+
+pub use self::partial::PartialState;
+use self::templates::{Template, TemplateKey};
+use crate::{globals, rooms, sending, server_keys, Dep};
+
+pub struct Service {
+	joins: Mutex<BTreeMap<Key, Arc<Join>>>,
+	permits: Option<Semaphore>,
+	tasks: (Sender<Task>, Receiver<Task>),
+	templates: Mutex<LruCache<TemplateKey, Template>>,
+	db: Data,
+	services: Services,
+}
+
+struct Data {
+	roomid_partialstate: Arc<Map>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	globals: Dep<globals::Service>,
+	sending: Dep<sending::Service>,
+	server_keys: Dep<server_keys::Service>,
+	outlier: Dep<rooms::outlier::Service>,
+	short: Dep<rooms::short::Service>,
+	state: Dep<rooms::state::Service>,
+	state_accessor: Dep<rooms::state_accessor::Service>,
+	state_compressor: Dep<rooms::state_compressor::Service>,
+}
+
+type Key = (OwnedUserId, OwnedRoomId);
+
+/// Membership templates kept for the retries of remote servers.
+const TEMPLATE_CACHE_CAPACITY: usize = 1024;
+
+/// A join in progress, as listed by `debug join-status`.
+struct Join {
+	started: Instant,
+	stage: Mutex<Stage>,
+
+	/// Closed once the join is over; the requests waiting for it subscribe.
+	done: watch::Sender<()>,
+}
+
+/// How far a join got.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Stage {
+	/// Checking whether the user may join and where to join from.
+	Starting,
+
+	/// Waiting for one of the remote joins running to finish.
+	Queued,
+
+	/// Asking the resident servers for a join event template.
+	MakeJoin,
+
+	/// Sending the signed join event to the resident server.
+	SendJoin,
+
+	/// Checking and storing the state of the room the resident server sent.
+	State,
+}
+
+/// The work done in the background after a join.
+enum Task {
+	Verify(Deferred),
+	Resync(OwnedRoomId),
+}
+
+/// The auth chain of a joined room left to verify.
+struct Deferred {
+	room_id: OwnedRoomId,
+	room_version: RoomVersionId,
+	events: Vec<Box<RawJsonValue>>,
+}
+
+/// Removes the join when it is over or its request was dropped.
+struct Running<'a> {
+	service: &'a Service,
+	key: Key,
+}
+
+#[async_trait]
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let max = args.server.config.max_concurrent_remote_joins;
+		Ok(Arc::new(Self {
+			joins: Mutex::new(BTreeMap::new()),
+			permits: (max > 0).then(|| Semaphore::new(max)),
+			tasks: loole::unbounded(),
+			templates: Mutex::new(LruCache::new(TEMPLATE_CACHE_CAPACITY)),
+			db: Data {
+				roomid_partialstate: args.db["roomid_partialstate"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				globals: args.depend::<globals::Service>("globals"),
+				sending: args.depend::<sending::Service>("sending"),
+				server_keys: args.depend::<server_keys::Service>("server_keys"),
+				outlier: args.depend::<rooms::outlier::Service>("rooms::outlier"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				state_accessor: args
+					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
+				state_compressor: args
+					.depend::<rooms::state_compressor::Service>("rooms::state_compressor"),
+			},
+		}))
+	}
+
+	async fn worker(self: Arc<Self>) -> Result {
+		// The rooms still partial-state when the server stopped.
+		let partial: Vec<_> = self
+			.partial_states()
+			.map(|(room_id, _)| room_id)
+			.collect()
+			.await;
+		for room_id in &partial {
+			self.resync_partial_state(room_id);
+		}
+
+		let receiver = self.tasks.1.clone();
+		while let Ok(task) = receiver.recv_async().await {
+			match task {
+				| Task::Verify(deferred) => self.verify_deferred(deferred).await,
+				| Task::Resync(room_id) => self.resync(&room_id).await,
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) {
+		let (sender, _) = &self.tasks;
+		if !sender.is_closed() {
+			sender.close();
+		}
+	}
+
+	fn queue_len(&self) -> Option<usize> { Some(self.tasks.0.len()) }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Service {
+	/// Run the join of the user to the room once any join of theirs to it
+	/// already in progress is over. The join of a request which was retried
+	/// thus only runs once the first one finished, so it finds the user joined
+	/// already or tries again.
+	pub async fn join<F, T>(&self, user_id: &UserId, room_id: &RoomId, join: F) -> Result<T>
+	where
+		F: Future<Output = Result<T>> + Send,
+	{
+		let key = (user_id.to_owned(), room_id.to_owned());
+		loop {
+			let mut done = {
+				let mut joins = self.joins.lock().expect("locked");
+				match joins.get(&key) {
+					| Some(running) => running.done.subscribe(),
+					| None => {
+						joins.insert(key.clone(), Arc::new(Join::new()));
+						break;
+					},
+				}
+			};
+
+			// Nothing is ever sent; this returns once the join is over.
+			debug_info!(%user_id, %room_id, "Waiting for the join already in progress");
+			done.changed().await.ok();
+		}
+
+		let _running = Running { service: self, key };
+
+		join.await
+	}
+
+	/// Wait for a remote join to run, unless as many as allowed are running.
+	pub async fn permit(
+		&self,
+		user_id: &UserId,
+		room_id: &RoomId,
+	) -> Option<SemaphorePermit<'_>> {
+		let permit = match self.permits.as_ref() {
+			| None => None,
+			| Some(permits) => {
+				if permits.available_permits() == 0 {
+					debug_info!(%user_id, %room_id, "Waiting for a remote join to finish");
+				}
+
+				self.set_stage(user_id, room_id, Stage::Queued);
+				permits.acquire().await.ok()
+			},
+		};
+
+		self.set_stage(user_id, room_id, Stage::MakeJoin);
+
+		permit
+	}
+
+	/// Verify the auth chain of the room in the background.
+	pub fn defer_verification(
+		&self,
+		room_id: &RoomId,
+		room_version: &RoomVersionId,
+		events: Vec<Box<RawJsonValue>>,
+	) {
+		let deferred = Deferred {
+			room_id: room_id.to_owned(),
+			room_version: room_version.clone(),
+			events,
+		};
+
+		if self.tasks.0.send(Task::Verify(deferred)).is_err() {
+			warn!(%room_id, "Not verifying the auth chain of the room while shutting down");
+		}
+	}
+
+	/// Store the events of the auth chain which pass verification; those
+	/// which fail are never stored.
+	async fn verify_deferred(&self, deferred: Deferred) {
+		let Deferred { room_id, room_version, events } = deferred;
+		let concurrency = self
+			.services
+			.server
+			.config
+			.join_validation_concurrency
+			.max(1);
+		let failed = events
+			.iter()
+			.stream()
+			.broadn_filter_map(concurrency, |pdu| {
+				let room_version = &room_version;
+				async move {
+					match self
+						.services
+						.server_keys
+						.validate_and_add_event_id(pdu, room_version)
+						.await
+					{
+						| Ok((event_id, value)) => {
+							self.services.outlier.add_pdu_outlier(&event_id, &value);
+							None
+						},
+						| Err(e) => Some(e),
+					}
+				}
+			})
+			.inspect(|e| warn!(%room_id, "Event of the auth chain failed verification: {e}"))
+			.count()
+			.await;
+
+		info!(%room_id, "Verified the auth chain of {} events, {failed} failed", events.len());
+	}
+
+	/// Record how far the join of the user to the room got.
+	pub fn set_stage(&self, user_id: &UserId, room_id: &RoomId, stage: Stage) {
+		let key = (user_id.to_owned(), room_id.to_owned());
+		if let Some(join) = self.joins.lock().expect("locked").get(&key) {
+			*join.stage.lock().expect("locked") = stage;
+		}
+	}
+
+	/// The joins in progress: who is joining which room, since when, how far
+	/// it got and how many retried requests are waiting for it.
+	#[must_use]
+	pub fn list(&self) -> Vec<(OwnedUserId, OwnedRoomId, Instant, Stage, usize)> {
+		self.joins
+			.lock()
+			.expect("locked")
+			.iter()
+			.map(|((user_id, room_id), join)| {
+				(
+					user_id.clone(),
+					room_id.clone(),
+					join.started,
+					*join.stage.lock().expect("locked"),
+					join.done.receiver_count(),
+				)
+			})
+			.collect()
+	}
+}
+
+impl Join {
+	fn new() -> Self {
+		Self {
+			started: Instant::now(),
+			stage: Mutex::new(Stage::Starting),
+			done: watch::Sender::new(()),
+		}
+	}
+}
+
+impl Drop for Running<'_> {
+	fn drop(&mut self) { self.service.joins.lock().expect("locked").remove(&self.key); }
+}
+
+impl fmt::Display for Stage {
+	fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+		let stage = match self {
+			| Self::Starting => "starting",
+			| Self::Queued => "queued",
+			| Self::MakeJoin => "make_join",
+			| Self::SendJoin => "send_join",
+			| Self::State => "processing state",
+		};
+
+		f.write_str(stage)
+	}
+}
@@ -0,0 +1,318 @@
+//! Partial-state joins (MSC3706). With `partial_state_joins` the resident
+//! server may leave the membership events out of its `send_join` response,
+//! which for a large room is most of the state. The room is then joined with
+//! the state it sent and marked partial-state until the full state at our join
+//! was fetched from one of the servers in the room, in the background. Until
+//! then the room is synced with what we have, and the events received for it
+//! are checked against the state their origin sends along.
+
+use std::{borrow::Borrow, collections::HashMap, sync::Arc, time::Duration};
+
+use conduwuit::{
+	debug_warn, implement, info,
+	utils::{
+		self,
+		stream::{BroadbandExt, IterStream, ReadyExt, TryIgnore},
+		time,
+	},
+	warn, Err, PduEvent, Result,
+};
+use database::{Deserialized, Json};
+use futures::{Stream, StreamExt, TryFutureExt};
+use ruma::{
+	api::federation::event::get_room_state, OwnedEventId, OwnedRoomId, OwnedServerName, RoomId,
+	RoomVersionId,
+};
+use serde::{Deserialize, Serialize};
+use tokio::time::sleep;
+
+use super::Task;
+use crate::rooms::state_compressor::HashSetCompressStateEvent;
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct PartialState {
+	/// Our join event; the full state is fetched at it.
+	pub event_id: OwnedEventId,
+
+	/// The servers the resident server listed as in the room, which it was
+	/// joined through first.
+	pub servers_in_room: Vec<OwnedServerName>,
+
+	/// When the room was joined, in milliseconds since the epoch.
+	pub since: u64,
+
+	/// Attempts to fetch the full state which failed.
+	#[serde(default)]
+	pub attempts: u32,
+
+	/// Why the servers asked did not send the full state the last time.
+	#[serde(default)]
+	pub last_error: Option<String>,
+}
+
+/// The wait before fetching the full state again after the first failure; it
+/// grows with the square of the attempts, up to the maximum.
+const RESYNC_BACKOFF_MIN: Duration = Duration::from_secs(60);
+const RESYNC_BACKOFF_MAX: Duration = Duration::from_secs(60 * 60 * 6);
+
+/// Mark the room partial-state after the join and fetch its full state in the
+/// background.
+#[implement(super::Service)]
+pub fn mark_partial_state(
+	&self,
+	room_id: &RoomId,
+	event_id: OwnedEventId,
+	servers_in_room: Vec<OwnedServerName>,
+) {
+	let partial = PartialState {
+		event_id,
+		servers_in_room,
+		since: utils::millis_since_unix_epoch(),
+		attempts: 0,
+		last_error: None,
+	};
+
+	let servers = partial.servers_in_room.len();
+	info!(%room_id, "Joined the room with partial state, {servers} servers in it are known");
+	self.db.roomid_partialstate.raw_put(room_id, Json(&partial));
+	self.resync_partial_state(room_id);
+}
+
+/// Fetch the full state of the partial-state room in the background.
+#[implement(super::Service)]
+pub fn resync_partial_state(&self, room_id: &RoomId) {
+	if self.tasks.0.send(Task::Resync(room_id.to_owned())).is_err() {
+		warn!(%room_id, "Not fetching the full state of the room while shutting down");
+	}
+}
+
+#[implement(super::Service)]
+pub async fn is_partial_state(&self, room_id: &RoomId) -> bool {
+	self.db.roomid_partialstate.exists(room_id).await.is_ok()
+}
+
+#[implement(super::Service)]
+pub async fn partial_state(&self, room_id: &RoomId) -> Option<PartialState> {
+	self.db
+		.roomid_partialstate
+		.get(room_id)
+		.await
+		.deserialized()
+		.ok()
+}
+
+/// The rooms whose full state was not fetched yet.
+#[implement(super::Service)]
+pub fn partial_states(&self) -> impl Stream<Item = (OwnedRoomId, PartialState)> + Send + '_ {
+	self.db
+		.roomid_partialstate
+		.stream()
+		.ignore_err()
+		.map(|(room_id, partial): (&RoomId, PartialState)| (room_id.to_owned(), partial))
+}
+
+#[implement(super::Service)]
+pub fn clear_partial_state(&self, room_id: &RoomId) {
+	self.db.roomid_partialstate.remove(room_id);
+}
+
+/// Fetch the full state in the background; after a failure it is fetched
+/// again later, backing off with each attempt.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "info")]
+pub(super) async fn resync(&self, room_id: &RoomId) {
+	let Err(e) = self.complete_partial_state(room_id, &[]).await else {
+		return;
+	};
+
+	let Some(partial) = self.partial_state(room_id).await else {
+		return;
+	};
+
+	let tries = partial.attempts.saturating_mul(partial.attempts);
+	let delay = RESYNC_BACKOFF_MIN
+		.saturating_mul(tries)
+		.min(RESYNC_BACKOFF_MAX);
+	warn!("{e}; retrying in {}", time::pretty(delay));
+
+	let server = self.services.server.clone();
+	let sender = self.tasks.0.clone();
+	let room_id = room_id.to_owned();
+	self.services.server.runtime().spawn(async move {
+		tokio::select! {
+			() = sleep(delay) => sender.send(Task::Resync(room_id)).ok(),
+			() = server.until_shutdown() => None,
+		};
+	});
+}
+
+/// Fetch the full state at our join from the first of the servers able to send
+/// it, those given first and then those in the room, and add what is missing
+/// to the current state. Returns how many state events were missing; the error
+/// says why each of the servers asked did not send it.
+#[implement(super::Service)]
+pub async fn complete_partial_state(
+	&self,
+	room_id: &RoomId,
+	via: &[OwnedServerName],
+) -> Result<usize> {
+	let Some(mut partial) = self.partial_state(room_id).await else {
+		return Err!(Request(NotFound("The room has its full state already.")));
+	};
+
+	let room_version = self.services.state.get_room_version(room_id).await?;
+	let mut servers: Vec<&OwnedServerName> = Vec::new();
+	for server in via.iter().chain(&partial.servers_in_room) {
+		if !servers.contains(&server) && !self.services.globals.server_is_ours(server) {
+			servers.push(server);
+		}
+	}
+
+	let mut errors = Vec::new();
+	for server in servers {
+		let request = get_room_state::v1::Request {
+			room_id: room_id.to_owned(),
+			event_id: partial.event_id.clone(),
+		};
+
+		let result = self
+			.services
+			.sending
+			.send_federation_request(server, request)
+			.and_then(|response| self.add_full_state(room_id, &room_version, response))
+			.await;
+
+		match result {
+			| Ok(added) => {
+				self.clear_partial_state(room_id);
+				info!(%server, "Fetched the full state of the room, {added} events were missing");
+				return Ok(added);
+			},
+			| Err(e) => {
+				debug_warn!(%server, "Fetching the full state of the room failed: {e}");
+				errors.push(format!("{server}: {e}"));
+			},
+		}
+	}
+
+	let errors = if errors.is_empty() {
+		"no server is known to be in the room".to_owned()
+	} else {
+		errors.join("; ")
+	};
+
+	partial.attempts = partial.attempts.saturating_add(1);
+	partial.last_error = Some(errors.clone());
+	self.db.roomid_partialstate.raw_put(room_id, Json(&partial));
+
+	Err!(BadServerResponse(
+		"None of the servers sent the full state of {room_id}: {errors}"
+	))
+}
+
+/// Verify the state and auth chain sent and add the state events missing from
+/// the current state of the room; those it has already are newer.
+#[implement(super::Service)]
+async fn add_full_state(
+	&self,
+	room_id: &RoomId,
+	room_version: &RoomVersionId,
+	response: get_room_state::v1::Response,
+) -> Result<usize> {
+	let concurrency = self
+		.services
+		.server
+		.config
+		.join_validation_concurrency
+		.max(1);
+	let get_room_state::v1::Response { auth_chain, pdus } = response;
+	self.services
+		.server_keys
+		.acquire_events_pubkeys(auth_chain.iter().chain(pdus.iter()))
+		.await;
+
+	auth_chain
+		.iter()
+		.stream()
+		.broadn_then(concurrency, |pdu| {
+			self.services
+				.server_keys
+				.validate_and_add_event_id_no_fetch(pdu, room_version)
+		})
+		.ready_filter_map(Result::ok)
+		.ready_for_each(|(event_id, value)| {
+			self.services.outlier.add_pdu_outlier(&event_id, &value);
+		})
+		.await;
+
+	let full_state: HashMap<_, _> = pdus
+		.iter()
+		.stream()
+		.broadn_then(concurrency, |pdu| {
+			self.services
+				.server_keys
+				.validate_and_add_event_id_no_fetch(pdu, room_version)
+		})
+		.ready_filter_map(Result::ok)
+		.filter_map(|(event_id, value)| async move {
+			let pdu = PduEvent::from_id_val(&event_id, value.clone())
+				.inspect_err(|e| debug_warn!("Invalid PDU in the full state: {e:?}"))
+				.ok()?;
+
+			self.services.outlier.add_pdu_outlier(&event_id, &value);
+			let shortstatekey = self
+				.services
+				.short
+				.get_or_create_shortstatekey(
+					&pdu.kind.to_string().into(),
+					pdu.state_key.as_ref()?,
+				)
+				.await;
+
+			Some((shortstatekey, event_id))
+		})
+		.collect()
+		.await;
+
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+	let shortstatehash = self.services.state.get_room_shortstatehash(room_id).await?;
+	let mut state: HashMap<_, OwnedEventId> = self
+		.services
+		.state_accessor
+		.state_full_ids(shortstatehash)
+		.await?;
+
+	let before = state.len();
+	for (shortstatekey, event_id) in full_state {
+		state.entry(shortstatekey).or_insert(event_id);
+	}
+
+	let added = state.len().saturating_sub(before);
+	if added == 0 {
+		return Ok(0);
+	}
+
+	let compressed = self
+		.services
+		.state_compressor
+		.compress_state_events(state.iter().map(|(ssk, eid)| (ssk, eid.borrow())))
+		.collect()
+		.await;
+
+	let HashSetCompressStateEvent {
+		shortstatehash,
+		added: diff_added,
+		removed,
+	} = self
+		.services
+		.state_compressor
+		.save_state(room_id, Arc::new(compressed))
+		.await?;
+
+	self.services
+		.state
+		.force_state(room_id, shortstatehash, diff_added, removed, &state_lock)
+		.await?;
+
+	Ok(added)
+}
@@ -0,0 +1,81 @@
+//! The membership event templates made for remote servers by `make_join`,
+//! `make_leave` and `make_knock`. A flaky server retries these requests, and
+//! each would otherwise take the state lock of the room to make the same
+//! event again; the template is reused for `membership_template_cache_ttl`
+//! seconds while the state of the room stays the same.
+
+use std::time::{Duration, Instant};
+
+use conduwuit::implement;
+use ruma::{events::room::member::MembershipState, OwnedRoomId, OwnedUserId, RoomId, UserId};
+use serde_json::value::RawValue as RawJsonValue;
+
+use crate::rooms::short::ShortStateHash;
+
+pub(super) type TemplateKey = (OwnedRoomId, OwnedUserId, String);
+
+pub(super) struct Template {
+	made: Instant,
+	shortstatehash: ShortStateHash,
+	event: Box<RawJsonValue>,
+}
+
+/// The template made for the membership of the user in the room, unless it
+/// expired or the state of the room changed since.
+#[implement(super::Service)]
+pub async fn template(
+	&self,
+	room_id: &RoomId,
+	user_id: &UserId,
+	membership: &MembershipState,
+) -> Option<Box<RawJsonValue>> {
+	let ttl = Duration::from_secs(self.services.server.config.membership_template_cache_ttl);
+	if ttl.is_zero() {
+		return None;
+	}
+
+	let shortstatehash = self
+		.services
+		.state
+		.get_room_shortstatehash(room_id)
+		.await
+		.ok()?;
+
+	let key = (room_id.to_owned(), user_id.to_owned(), membership.to_string());
+	let mut templates = self.templates.lock().expect("locked");
+	let template = templates.get_mut(&key)?;
+	if template.made.elapsed() >= ttl || template.shortstatehash != shortstatehash {
+		templates.remove(&key);
+		return None;
+	}
+
+	Some(template.event.clone())
+}
+
+/// Keep the template made for the membership of the user in the room; the
+/// state lock of the room must still be held.
+#[implement(super::Service)]
+pub async fn set_template(
+	&self,
+	room_id: &RoomId,
+	user_id: &UserId,
+	membership: &MembershipState,
+	event: &RawJsonValue,
+) {
+	if self.services.server.config.membership_template_cache_ttl == 0 {
+		return;
+	}
+
+	let Ok(shortstatehash) = self.services.state.get_room_shortstatehash(room_id).await else {
+		return;
+	};
+
+	let key = (room_id.to_owned(), user_id.to_owned(), membership.to_string());
+	let template = Template {
+		made: Instant::now(),
+		shortstatehash,
+		event: event.to_owned(),
+	};
+
+	self.templates.lock().expect("locked").insert(key, template);
+}
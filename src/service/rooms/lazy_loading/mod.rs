@@ -9,7 +9,7 @@ use conduwuit::{
 	utils::{stream::TryIgnore, ReadyExt},
 	PduCount, Result,
 };
-use database::{Interfix, Map};
+use database::{Database, Interfix, Map};
 use ruma::{DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, RoomId, UserId};
 
 pub struct Service {
@@ -18,6 +18,7 @@ pub struct Service {
 }
 
 struct Data {
+	db: Arc<Database>,
 	lazyloadedids: Arc<Map>,
 }
 
@@ -30,6 +31,7 @@ impl crate::Service for Service {
 		Ok(Arc::new(Self {
 			lazy_load_waiting: LazyLoadWaiting::new().into(),
 			db: Data {
+				db: args.db.clone(),
 				lazyloadedids: args.db["lazyloadedids"].clone(),
 			},
 		}))
@@ -71,6 +73,11 @@ pub fn lazy_load_mark_sent(
 	lazy_load: HashSet<OwnedUserId>,
 	count: PduCount,
 ) {
+	// Replicas cannot record the delivery, so members are sent again instead.
+	if self.db.db.is_read_only() {
+		return;
+	}
+
 	let key = (user_id.to_owned(), device_id.to_owned(), room_id.to_owned(), count);
 
 	self.lazy_load_waiting
@@ -94,15 +101,56 @@ pub fn lazy_load_confirm_delivery(
 		return;
 	};
 
-	for ll_id in &user_ids {
+	self.lazy_load_mark_delivered(user_id, device_id, room_id, &user_ids);
+}
+
+/// Record member events as delivered immediately, for responses which are not
+/// confirmed by a subsequent request's token (e.g. /members).
+#[implement(Service)]
+#[tracing::instrument(skip(self, user_ids), level = "debug")]
+pub fn lazy_load_mark_delivered(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	room_id: &RoomId,
+	user_ids: &HashSet<OwnedUserId>,
+) {
+	if self.db.db.is_read_only() {
+		return;
+	}
+
+	for ll_id in user_ids {
 		let key = (user_id, device_id, room_id, ll_id);
 		self.db.lazyloadedids.put_raw(key, []);
 	}
 }
 
+/// Forget that a member event was delivered so it is sent again the next time
+/// it is relevant; used when the membership changes without being sent.
+#[implement(Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub fn lazy_load_forget(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	room_id: &RoomId,
+	ll_user: &UserId,
+) {
+	if self.db.db.is_read_only() {
+		return;
+	}
+
+	let key = (user_id, device_id, room_id, ll_user);
+	self.db.lazyloadedids.del(key);
+}
+
 #[implement(Service)]
 #[tracing::instrument(skip(self), level = "debug")]
 pub async fn lazy_load_reset(&self, user_id: &UserId, device_id: &DeviceId, room_id: &RoomId) {
+	if self.db.db.is_read_only() {
+		return;
+	}
+
 	let prefix = (user_id, device_id, room_id, Interfix);
 	self.db
 		.lazyloadedids
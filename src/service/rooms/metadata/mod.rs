@@ -1,11 +1,14 @@
+mod purge;
+
 use std::sync::Arc;
 
 use conduwuit::{implement, utils::stream::TryIgnore, Result};
-use database::Map;
+use database::{Database, Map};
 use futures::{Stream, StreamExt};
 use ruma::RoomId;
 
-use crate::{rooms, Dep};
+pub use self::purge::Purged;
+use crate::{globals, media, rooms, Dep};
 
 pub struct Service {
 	db: Data,
@@ -17,10 +20,17 @@ struct Data {
 	bannedroomids: Arc<Map>,
 	roomid_shortroomid: Arc<Map>,
 	pduid_pdu: Arc<Map>,
+	db: Arc<Database>,
 }
 
 struct Services {
+	globals: Dep<globals::Service>,
+	media: Dep<media::Service>,
+	alias: Dep<rooms::alias::Service>,
+	directory: Dep<rooms::directory::Service>,
 	short: Dep<rooms::short::Service>,
+	state: Dep<rooms::state::Service>,
+	timeline: Dep<rooms::timeline::Service>,
 }
 
 impl crate::Service for Service {
@@ -31,9 +41,16 @@ impl crate::Service for Service {
 				bannedroomids: args.db["bannedroomids"].clone(),
 				roomid_shortroomid: args.db["roomid_shortroomid"].clone(),
 				pduid_pdu: args.db["pduid_pdu"].clone(),
+				db: args.db.clone(),
 			},
 			services: Services {
+				globals: args.depend::<globals::Service>("globals"),
+				media: args.depend::<media::Service>("media"),
+				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
+				directory: args.depend::<rooms::directory::Service>("rooms::directory"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 			},
 		}))
 	}
@@ -0,0 +1,501 @@
+//! Purging a room from the database: its events, state, aliases, the room
+//! account data of users and the local media only its events refer to. Whether
+//! the room is banned or disabled is kept, so it is not joined again by
+//! accident, and so is quarantined media, as evidence.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use conduwuit::{
+	debug, debug_info, implement,
+	utils::{stream::TryIgnore, ReadyExt},
+	warn, Result,
+};
+use database::{Deserialized, Ignore};
+use futures::StreamExt;
+use ruma::{events::TimelineEventType, Mxc, OwnedEventId, OwnedRoomAliasId, OwnedRoomId, RoomId};
+use serde::Deserialize;
+use serde_json::Value as JsonValue;
+
+/// The keys and media deleted purging a room.
+#[derive(Debug, Default)]
+pub struct Purged {
+	/// Keys deleted from each map.
+	pub keys: BTreeMap<&'static str, usize>,
+
+	/// Media files deleted, with their thumbnails.
+	pub media: usize,
+}
+
+/// Maps keyed by the room ID, alone or followed by more.
+const ROOM_PREFIXED: &[&str] = &[
+	"aliasid_alias",
+	"publicroomids",
+	"readreceiptid_readreceipt",
+	"referencedevents",
+	"roomid_invitedcount",
+	"roomid_inviteviaservers",
+	"roomid_joinedcount",
+	"roomid_partialstate",
+	"roomid_pduleaves",
+	"roomid_policylist",
+	"roomid_shortstatehash",
+	"roomserverids",
+	"roomuserdataid_accountdata",
+	"roomuserid_invitecount",
+	"roomuserid_joined",
+	"roomuserid_knockedcount",
+	"roomuserid_lastprivatereadupdate",
+	"roomuserid_leftcount",
+	"roomuserid_privateread",
+	"roomuseroncejoinedids",
+	"roomusertype_roomuserdataid",
+];
+
+/// Maps keyed by the short room ID followed by more.
+const SHORTROOM_PREFIXED: &[&str] =
+	&["pduid_pdu", "roomsynctoken_shortstatehash", "threadid_userids", "tokenids"];
+
+/// Maps with the room ID as a later part of their keys, and which.
+const ROOM_PART: &[(&str, usize)] = &[
+	("lazyloadedids", 2),
+	("serverroomids", 1),
+	("userdeviceroomid_shortstatehash", 2),
+	("userroomid_highlightcount", 1),
+	("userroomid_invitestate", 1),
+	("userroomid_joined", 1),
+	("userroomid_knockedstate", 1),
+	("userroomid_leftstate", 1),
+	("userroomid_notificationcount", 1),
+	("userroomthreadid_highlightcount", 1),
+	("userroomthreadid_notificationcount", 1),
+];
+
+/// Maps keyed by event ID.
+const EVENT_KEYED: &[&str] = &[
+	"eventid_outlierpdu",
+	"eventid_pduid",
+	"eventid_shorteventid",
+	"softfailedeventids",
+];
+
+/// Maps keyed by short event ID.
+const SHORTEVENT_KEYED: &[&str] = &["shorteventid_eventid", "shorteventid_shortstatehash"];
+
+#[derive(Deserialize)]
+struct ExtractRoomId {
+	room_id: OwnedRoomId,
+}
+
+impl Purged {
+	#[must_use]
+	pub fn total(&self) -> usize { self.keys.values().sum() }
+
+	fn add(&mut self, map: &'static str, count: usize) {
+		if count > 0 {
+			let total = self.keys.entry(map).or_default();
+			*total = total.saturating_add(count);
+		}
+	}
+}
+
+/// Delete everything about the room from the database but whether it is banned
+/// or disabled. No local user should be joined anymore, e.g. as it is banned.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "info")]
+pub async fn purge_room(&self, room_id: &RoomId) -> Result<Purged> {
+	let mut purged = Purged::default();
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+
+	// The events, with the counts their relations are keyed by and the media
+	// they refer to, which is deleted before anything referring to it is. Member
+	// events refer to the avatars of the users' profiles, which are kept.
+	let mut event_ids = Vec::new();
+	let mut counts = Vec::new();
+	let mut media = BTreeSet::new();
+	self.services
+		.timeline
+		.pdus(None, room_id, None)
+		.ignore_err()
+		.ready_for_each(|(count, pdu)| {
+			if pdu.kind != TimelineEventType::RoomMember {
+				if let Ok(content) = serde_json::from_str::<JsonValue>(pdu.content.get()) {
+					collect_mxcs(&content, &mut media);
+				}
+			}
+
+			counts.push(count);
+			event_ids.push(pdu.event_id);
+		})
+		.await;
+
+	let outliers: Vec<OwnedEventId> = self.db.db["eventid_outlierpdu"]
+		.raw_stream()
+		.ignore_err()
+		.ready_filter_map(|(key, val)| {
+			let event: ExtractRoomId = serde_json::from_slice(val).ok()?;
+			(event.room_id == room_id)
+				.then(|| OwnedEventId::try_from(std::str::from_utf8(key).ok()?).ok())
+				.flatten()
+		})
+		.collect()
+		.await;
+
+	event_ids.extend(outliers);
+	for mxc in &self.unshared_media(room_id, media).await {
+		let Ok(mxc) = <Mxc<'_>>::try_from(mxc.as_str()) else {
+			continue;
+		};
+
+		if self.services.media.is_quarantined(&mxc).await {
+			continue;
+		}
+
+		match self.services.media.delete(&mxc).await {
+			| Ok(()) => purged.media = purged.media.saturating_add(1),
+			| Err(e) => debug!(%mxc, "Failed to delete media of purged room: {e}"),
+		}
+	}
+
+	// Aliases go through their service, removing them from the directory too.
+	let aliases: Vec<OwnedRoomAliasId> = self
+		.services
+		.alias
+		.local_aliases_for_room(room_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let server_user = &self.services.globals.server_user;
+	for alias in &aliases {
+		if let Err(e) = self.services.alias.remove_alias(alias, server_user).await {
+			warn!(%alias, "Failed to remove alias of purged room: {e}");
+			continue;
+		}
+
+		purged.add("alias_roomid", 1);
+	}
+
+	self.services.directory.set_not_public(room_id).await;
+
+	// The room states the events and the room point to, deleted last unless
+	// something outside the room still refers to them.
+	let mut shortstatehashes = BTreeSet::new();
+	let mut shorteventids = BTreeSet::new();
+	if let Ok(shortstatehash) = self.services.state.get_room_shortstatehash(room_id).await {
+		shortstatehashes.insert(shortstatehash);
+	}
+
+	for event_id in &event_ids {
+		for &map in EVENT_KEYED {
+			purged.add(map, usize::from(self.delete(map, event_id.as_bytes()).await));
+		}
+
+		let Ok(shorteventid) = self.services.short.get_shorteventid(event_id).await else {
+			continue;
+		};
+
+		shorteventids.insert(shorteventid);
+		let key = shorteventid.to_be_bytes();
+		if let Ok(shortstatehash) = self.db.db["shorteventid_shortstatehash"]
+			.get(&key)
+			.await
+			.deserialized::<u64>()
+		{
+			shortstatehashes.insert(shortstatehash);
+		}
+
+		for &map in SHORTEVENT_KEYED {
+			purged.add(map, usize::from(self.delete(map, &key).await));
+		}
+	}
+
+	for count in &counts {
+		let prefix = count.into_unsigned().to_be_bytes();
+		purged.add("tofrom_relation", self.delete_prefix("tofrom_relation", &prefix).await);
+	}
+
+	purged.add("shorteventid_authchain", self.delete_authchains(&shorteventids).await);
+	purged.add("userdelayid_delayedevent", self.delete_delayed_events(room_id).await);
+
+	if let Ok(shortroomid) = self.services.short.get_shortroomid(room_id).await {
+		let prefix = shortroomid.to_be_bytes();
+		for &map in SHORTROOM_PREFIXED {
+			purged.add(map, self.delete_prefix(map, &prefix).await);
+		}
+	}
+
+	let mut prefix = room_id.as_bytes().to_vec();
+	prefix.push(0xFF);
+	for &map in ROOM_PREFIXED {
+		let exact = usize::from(self.delete(map, room_id.as_bytes()).await);
+		purged.add(map, exact.saturating_add(self.delete_prefix(map, &prefix).await));
+	}
+
+	for &(map, part) in ROOM_PART {
+		purged.add(map, self.delete_part(map, part, room_id).await);
+	}
+
+	purged.add(
+		"roomid_shortroomid",
+		usize::from(self.delete("roomid_shortroomid", room_id.as_bytes()).await),
+	);
+
+	let shortstatehashes = self.unreferenced_states(shortstatehashes).await;
+	for shortstatehash in &shortstatehashes {
+		let key = shortstatehash.to_be_bytes();
+		let deleted = self.delete("shortstatehash_statediff", &key).await;
+		purged.add("shortstatehash_statediff", usize::from(deleted));
+	}
+
+	let statehash_shortstatehash = &self.db.db["statehash_shortstatehash"];
+	let statehashes: Vec<Vec<u8>> = statehash_shortstatehash
+		.raw_stream()
+		.ignore_err()
+		.ready_filter_map(|(key, val)| {
+			let shortstatehash = u64::from_be_bytes(val.try_into().ok()?);
+			shortstatehashes
+				.contains(&shortstatehash)
+				.then(|| key.to_vec())
+		})
+		.collect()
+		.await;
+
+	purged.add("statehash_shortstatehash", statehashes.len());
+	for statehash in &statehashes {
+		statehash_shortstatehash.remove(statehash);
+	}
+
+	drop(state_lock);
+	debug_info!(keys = purged.total(), media = purged.media, "Purged room from the database");
+
+	Ok(purged)
+}
+
+/// The local media of the set which neither the events of other rooms nor the
+/// profiles of users refer to.
+#[implement(super::Service)]
+async fn unshared_media(
+	&self,
+	room_id: &RoomId,
+	mut media: BTreeSet<String>,
+) -> BTreeSet<String> {
+	let server_name = self.services.globals.server_name();
+	media.retain(|mxc| {
+		<Mxc<'_>>::try_from(mxc.as_str()).is_ok_and(|mxc| mxc.server_name == server_name)
+	});
+
+	if media.is_empty() {
+		return media;
+	}
+
+	let shortroomid = self
+		.services
+		.short
+		.get_shortroomid(room_id)
+		.await
+		.ok()
+		.map(u64::to_be_bytes);
+
+	self.db
+		.pduid_pdu
+		.raw_stream()
+		.ignore_err()
+		.ready_filter(|(key, _)| shortroomid.is_none_or(|prefix| !key.starts_with(&prefix)))
+		.ready_for_each(|(_, val)| media.retain(|mxc| !contains(val, mxc.as_bytes())))
+		.await;
+
+	self.db.db["eventid_outlierpdu"]
+		.raw_stream()
+		.ignore_err()
+		.ready_filter(|(_, val)| {
+			serde_json::from_slice::<ExtractRoomId>(val)
+				.is_ok_and(|event| event.room_id != room_id)
+		})
+		.ready_for_each(|(_, val)| media.retain(|mxc| !contains(val, mxc.as_bytes())))
+		.await;
+
+	self.db.db["userid_avatarurl"]
+		.raw_stream()
+		.ignore_err()
+		.ready_for_each(|(_, val)| media.retain(|mxc| !contains(val, mxc.as_bytes())))
+		.await;
+
+	media
+}
+
+/// The states of the set nothing outside the purged room refers to, once the
+/// keys of the room were deleted: not the current state of another room, nor
+/// the state before another event, nor the one a sync or device was at, nor
+/// the parent of a diff kept. The state before a create event is the empty
+/// one, shared by every room.
+#[implement(super::Service)]
+async fn unreferenced_states(&self, mut states: BTreeSet<u64>) -> BTreeSet<u64> {
+	let db = &self.db.db;
+	let leading = |val: &[u8]| -> Option<u64> {
+		Some(u64::from_be_bytes(val.get(..size_of::<u64>())?.try_into().ok()?))
+	};
+
+	for map in [
+		"roomid_shortstatehash",
+		"roomsynctoken_shortstatehash",
+		"shorteventid_shortstatehash",
+	] {
+		db[map]
+			.raw_stream()
+			.ignore_err()
+			.ready_for_each(|(_, val)| {
+				if let Some(shortstatehash) = leading(val) {
+					states.remove(&shortstatehash);
+				}
+			})
+			.await;
+	}
+
+	db["userdeviceroomid_shortstatehash"]
+		.stream()
+		.ignore_err()
+		.ready_for_each(|(_, (_, shortstatehash)): (Ignore, (u64, u64))| {
+			states.remove(&shortstatehash);
+		})
+		.await;
+
+	// The diffs of the states of the set are only followed once they are kept.
+	let mut parents = BTreeMap::new();
+	db["shortstatehash_statediff"]
+		.raw_stream()
+		.ignore_err()
+		.ready_for_each(|(key, val)| {
+			let (Some(shortstatehash), Some(parent)) = (leading(key), leading(val)) else {
+				return;
+			};
+
+			if states.contains(&shortstatehash) {
+				parents.insert(shortstatehash, parent);
+			} else {
+				states.remove(&parent);
+			}
+		})
+		.await;
+
+	let mut kept: Vec<u64> = parents
+		.keys()
+		.filter(|shortstatehash| !states.contains(shortstatehash))
+		.copied()
+		.collect();
+
+	while let Some(shortstatehash) = kept.pop() {
+		if let Some(&parent) = parents.get(&shortstatehash) {
+			if states.remove(&parent) {
+				kept.push(parent);
+			}
+		}
+	}
+
+	states
+}
+
+/// Delete the cached auth chains of the events, whether of one event or of a
+/// bucket of them keyed by their concatenated short IDs, returning how many.
+#[implement(super::Service)]
+async fn delete_authchains(&self, shorteventids: &BTreeSet<u64>) -> usize {
+	if shorteventids.is_empty() {
+		return 0;
+	}
+
+	let map = &self.db.db["shorteventid_authchain"];
+	map.raw_keys()
+		.ignore_err()
+		.ready_filter(|key| {
+			key.chunks_exact(size_of::<u64>()).any(|short| {
+				short
+					.try_into()
+					.is_ok_and(|short| shorteventids.contains(&u64::from_be_bytes(short)))
+			})
+		})
+		.ready_fold(0_usize, |count, key| {
+			map.remove(key);
+			count.saturating_add(1)
+		})
+		.await
+}
+
+/// Delete the pending delayed events of the room, returning how many.
+#[implement(super::Service)]
+async fn delete_delayed_events(&self, room_id: &RoomId) -> usize {
+	let map = &self.db.db["userdelayid_delayedevent"];
+	map.raw_stream()
+		.ignore_err()
+		.ready_filter(|(_, val)| {
+			serde_json::from_slice::<ExtractRoomId>(val)
+				.is_ok_and(|event| event.room_id == room_id)
+		})
+		.ready_fold(0_usize, |count, (key, _)| {
+			map.remove(key);
+			count.saturating_add(1)
+		})
+		.await
+}
+
+/// Delete the key from the map, returning whether it was there.
+#[implement(super::Service)]
+async fn delete(&self, map: &str, key: &[u8]) -> bool {
+	let map = &self.db.db[map];
+	if map.get(key).await.is_err() {
+		return false;
+	}
+
+	map.remove(key);
+	true
+}
+
+/// Delete the keys of the map beginning with the prefix, returning how many.
+#[implement(super::Service)]
+async fn delete_prefix(&self, map: &str, prefix: &[u8]) -> usize {
+	let map = &self.db.db[map];
+	map.raw_keys_prefix(prefix)
+		.ignore_err()
+		.ready_fold(0_usize, |count, key| {
+			map.remove(key);
+			count.saturating_add(1)
+		})
+		.await
+}
+
+/// Delete the keys of the map with the room ID as their part at the index,
+/// parts being separated by 0xFF, returning how many.
+#[implement(super::Service)]
+async fn delete_part(&self, map: &str, part: usize, room_id: &RoomId) -> usize {
+	let map = &self.db.db[map];
+	map.raw_keys()
+		.ignore_err()
+		.ready_filter(|key| key.split(|&b| b == 0xFF).nth(part) == Some(room_id.as_bytes()))
+		.ready_fold(0_usize, |count, key| {
+			map.remove(key);
+			count.saturating_add(1)
+		})
+		.await
+}
+
+/// Every `mxc://` URI in the content of an event.
+fn collect_mxcs(value: &JsonValue, mxcs: &mut BTreeSet<String>) {
+	match value {
+		| JsonValue::String(string) if string.starts_with("mxc://") => {
+			mxcs.insert(string.clone());
+		},
+		| JsonValue::Array(values) =>
+			for value in values {
+				collect_mxcs(value, mxcs);
+			},
+		| JsonValue::Object(object) =>
+			for value in object.values() {
+				collect_mxcs(value, mxcs);
+			},
+		| _ => {},
+	}
+}
+
+fn contains(haystack: &[u8], needle: &[u8]) -> bool {
+	haystack
+		.windows(needle.len())
+		.any(|window| window == needle)
+}
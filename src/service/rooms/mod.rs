@@ -1,7 +1,10 @@
 pub mod alias;
 pub mod auth_chain;
+pub mod delayed;
 pub mod directory;
 pub mod event_handler;
+pub mod image_packs;
+pub mod joins;
 pub mod lazy_loading;
 pub mod metadata;
 pub mod outlier;
@@ -24,8 +27,11 @@ use std::sync::Arc;
 pub struct Service {
 	pub alias: Arc<alias::Service>,
 	pub auth_chain: Arc<auth_chain::Service>,
+	pub delayed: Arc<delayed::Service>,
 	pub directory: Arc<directory::Service>,
 	pub event_handler: Arc<event_handler::Service>,
+	pub image_packs: Arc<image_packs::Service>,
+	pub joins: Arc<joins::Service>,
 	pub lazy_loading: Arc<lazy_loading::Service>,
 	pub metadata: Arc<metadata::Service>,
 	pub outlier: Arc<outlier::Service>,
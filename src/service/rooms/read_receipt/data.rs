@@ -1,4 +1,4 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
 use conduwuit::{
 	utils::{stream::TryIgnore, ReadyExt},
@@ -7,7 +7,10 @@ use conduwuit::{
 use database::{Deserialized, Json, Map};
 use futures::{Stream, StreamExt};
 use ruma::{
-	events::{receipt::ReceiptEvent, AnySyncEphemeralRoomEvent},
+	events::{
+		receipt::{ReceiptEvent, ReceiptThread},
+		AnySyncEphemeralRoomEvent,
+	},
 	serde::Raw,
 	CanonicalJsonObject, RoomId, UserId,
 };
@@ -46,14 +49,22 @@ impl Data {
 		room_id: &RoomId,
 		event: &ReceiptEvent,
 	) {
-		// Remove old entry
+		// Remove old entries of the same threads; receipts of other threads remain
+		let threads = receipt_threads(event, user_id);
 		let last_possible_key = (room_id, u64::MAX);
 		self.readreceiptid_readreceipt
-			.rev_keys_from_raw(&last_possible_key)
+			.rev_stream_from_raw(&last_possible_key)
 			.ignore_err()
-			.ready_take_while(|key| key.starts_with(room_id.as_bytes()))
-			.ready_filter_map(|key| key.ends_with(user_id.as_bytes()).then_some(key))
-			.ready_for_each(|key| self.readreceiptid_readreceipt.del(key))
+			.ready_take_while(|(key, _)| key.starts_with(room_id.as_bytes()))
+			.ready_filter(|(key, _)| key.ends_with(user_id.as_bytes()))
+			.ready_filter(|(_, val)| {
+				serde_json::from_slice::<ReceiptEvent>(val).map_or(true, |old| {
+					receipt_threads(&old, user_id)
+						.iter()
+						.any(|thread| threads.contains(thread))
+				})
+			})
+			.ready_for_each(|(key, _)| self.readreceiptid_readreceipt.del(key))
 			.await;
 
 		let count = self.services.globals.next_count().unwrap();
@@ -116,3 +127,15 @@ impl Data {
 			.unwrap_or(0)
 	}
 }
+
+/// The threads the receipts of the user in the event are for.
+fn receipt_threads(event: &ReceiptEvent, user_id: &UserId) -> Vec<ReceiptThread> {
+	event
+		.content
+		.0
+		.values()
+		.flat_map(BTreeMap::values)
+		.filter_map(|receipts| receipts.get(user_id))
+		.map(|receipt| receipt.thread.clone())
+		.collect()
+}
@@ -43,7 +43,8 @@ impl crate::Service for Service {
 }
 
 impl Service {
-	/// Replaces the previous read receipt.
+	/// Replaces the previous read receipt of the user for the same thread, or
+	/// the unthreaded one.
 	pub async fn readreceipt_update(
 		&self,
 		user_id: &UserId,
@@ -56,6 +57,15 @@ impl Service {
 			.flush_room(room_id)
 			.await
 			.expect("room flush failed");
+
+		if let Err(e) = self
+			.services
+			.sending
+			.send_ephemeral_room(room_id, event)
+			.await
+		{
+			warn!(%room_id, "Failed to send read receipt to appservices: {e}");
+		}
 	}
 
 	/// Gets the latest private read receipt from the user in the room
@@ -2,7 +2,7 @@ use std::{
 	collections::{HashMap, HashSet},
 	fmt::Write,
 	iter::once,
-	sync::Arc,
+	sync::{Arc, Mutex},
 };
 
 use conduwuit::{
@@ -13,7 +13,7 @@ use conduwuit::{
 		stream::{BroadbandExt, TryIgnore},
 		IterStream, MutexMap, MutexMapGuard, ReadyExt,
 	},
-	warn, PduEvent, Result,
+	warn, PduEvent, Result, Server,
 };
 use database::{Deserialized, Ignore, Interfix, Map};
 use futures::{
@@ -30,7 +30,7 @@ use ruma::{
 };
 
 use crate::{
-	globals, rooms,
+	admin, globals, rooms,
 	rooms::{
 		short::{ShortEventId, ShortStateHash},
 		state_compressor::{parse_compressed_state_event, CompressedStateEvent},
@@ -40,11 +40,14 @@ use crate::{
 
 pub struct Service {
 	pub mutex: RoomMutexMap,
+	excess_extremities: Mutex<HashMap<OwnedRoomId, usize>>,
 	services: Services,
 	db: Data,
 }
 
 struct Services {
+	server: Arc<Server>,
+	admin: Dep<admin::Service>,
 	globals: Dep<globals::Service>,
 	short: Dep<rooms::short::Service>,
 	spaces: Dep<rooms::spaces::Service>,
@@ -67,7 +70,10 @@ impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			mutex: RoomMutexMap::new(),
+			excess_extremities: Mutex::new(HashMap::new()),
 			services: Services {
+				server: args.server.clone(),
+				admin: args.depend::<admin::Service>("admin"),
 				globals: args.depend::<globals::Service>("globals"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
@@ -90,6 +96,9 @@ impl crate::Service for Service {
 		let mutex = self.mutex.len();
 		writeln!(out, "state_mutex: {mutex}")?;
 
+		let excess_extremities = self.excess_extremities.lock().expect("locked").len();
+		writeln!(out, "excess_extremities: {excess_extremities}")?;
+
 		Ok(())
 	}
 
@@ -322,6 +331,16 @@ impl Service {
 		}
 	}
 
+	/// Forgets the state before an event which was purged. The state itself is
+	/// kept, as other events and the room may refer to it.
+	pub fn delete_event_state(&self, shorteventid: ShortEventId) {
+		const BUFSIZE: usize = size_of::<ShortEventId>();
+
+		self.db
+			.shorteventid_shortstatehash
+			.adel::<BUFSIZE, _>(shorteventid);
+	}
+
 	#[tracing::instrument(skip_all, level = "debug")]
 	pub async fn summary_stripped(&self, event: &PduEvent) -> Vec<Raw<AnyStrippedStateEvent>> {
 		let cells = [
@@ -332,6 +351,9 @@ impl Service {
 			(&StateEventType::RoomAvatar, ""),
 			(&StateEventType::RoomMember, event.sender.as_str()), // Add recommended events
 			(&StateEventType::RoomEncryption, ""),
+			// Invitees trust room keys shared with them for the history before they
+			// joined only if it was visible to them when invited (MSC3061)
+			(&StateEventType::RoomHistoryVisibility, ""),
 			(&StateEventType::RoomTopic, ""),
 		];
 
@@ -417,6 +439,58 @@ impl Service {
 			let key = (room_id, event_id);
 			self.db.roomid_pduleaves.put_raw(key, event_id);
 		}
+
+		self.track_extremities(room_id, event_ids.len());
+	}
+
+	/// The rooms with more forward extremities than the warning threshold of
+	/// the config, with how many, as of their latest event.
+	pub fn excess_extremities(&self) -> Vec<(OwnedRoomId, usize)> {
+		self.excess_extremities
+			.lock()
+			.expect("locked")
+			.iter()
+			.map(|(room_id, count)| (room_id.clone(), *count))
+			.collect()
+	}
+
+	/// Warn in the admin room once a room exceeds the threshold, until it is
+	/// back within it. The message is sent from a task of its own, as the state
+	/// lock of the admin room may be held here.
+	fn track_extremities(&self, room_id: &RoomId, count: usize) {
+		let threshold = self
+			.services
+			.server
+			.config
+			.forward_extremities_warn_threshold;
+		if threshold == 0 {
+			return;
+		}
+
+		let mut excess_extremities = self.excess_extremities.lock().expect("locked");
+		if count <= threshold {
+			excess_extremities.remove(room_id);
+			return;
+		}
+
+		if excess_extremities
+			.insert(room_id.to_owned(), count)
+			.is_some()
+		{
+			return;
+		}
+
+		warn!(%room_id, %count, "Room has too many forward extremities");
+		let admin = Arc::clone(&self.services.admin);
+		let body = format!(
+			"Room {room_id} has {count} forward extremities, more than the threshold of \
+			 {threshold}; state resolution in it may slow down. Prune them with `!admin rooms \
+			 prune-extremities {room_id}`."
+		);
+
+		self.services.server.runtime().spawn(async move {
+			admin.send_text(&body).await;
+		});
 	}
 
 	/// This fetches auth events from the current state.
@@ -80,6 +80,54 @@ impl Data {
 		Ok(full_pdus)
 	}
 
+	/// Returns all m.room.member events in the state snapshot.
+	#[inline]
+	pub(super) async fn state_full_members(
+		&self,
+		shortstatehash: ShortStateHash,
+	) -> Result<Vec<PduEvent>> {
+		self.state_full_type(shortstatehash, &StateEventType::RoomMember)
+			.await
+	}
+
+	/// Returns all events of the type in the state snapshot. The compressed
+	/// state is walked once and both the statekeys and the events are resolved
+	/// in batches rather than with a query for each state key.
+	pub(super) async fn state_full_type(
+		&self,
+		shortstatehash: ShortStateHash,
+		event_type: &StateEventType,
+	) -> Result<Vec<PduEvent>> {
+		let short_ids = self.state_full_shortids(shortstatehash).await?;
+
+		let type_ids: Vec<ShortEventId> = self
+			.services
+			.short
+			.multi_get_statekey_from_short(short_ids.iter().map(at!(0)).stream())
+			.zip(short_ids.iter().stream().map(at!(1)))
+			.ready_filter_map(|(statekey, shorteventid)| {
+				statekey
+					.ok()
+					.filter(|(kind, _)| kind == event_type)
+					.map(|_| shorteventid)
+			})
+			.collect()
+			.await;
+
+		let pdus = self
+			.services
+			.short
+			.multi_get_eventid_from_short(type_ids.into_iter().stream())
+			.ready_filter_map(Result::ok)
+			.broad_filter_map(|event_id: OwnedEventId| async move {
+				self.services.timeline.get_pdu(&event_id).await.ok()
+			})
+			.collect()
+			.await;
+
+		Ok(pdus)
+	}
+
 	pub(super) async fn state_full_ids<Id>(
 		&self,
 		shortstatehash: ShortStateHash,
@@ -218,6 +266,16 @@ impl Data {
 			.await
 	}
 
+	/// Returns all m.room.member events in the current room state.
+	pub(super) async fn room_state_members(&self, room_id: &RoomId) -> Result<Vec<PduEvent>> {
+		self.services
+			.state
+			.get_room_shortstatehash(room_id)
+			.and_then(|shortstatehash| self.state_full_members(shortstatehash))
+			.map_err(|e| err!(Database("Missing state members for {room_id:?}: {e:?}")))
+			.await
+	}
+
 	/// Returns a single EventId from `room_id` with key
 	/// (`event_type`,`state_key`).
 	pub(super) async fn room_state_get_id<Id>(
@@ -5,17 +5,20 @@ use std::{
 	collections::HashMap,
 	fmt::Write,
 	sync::{Arc, Mutex as StdMutex, Mutex},
+	time::Instant,
 };
 
+use async_trait::async_trait;
 use conduwuit::{
-	err, error,
+	at, debug_info, err, error,
 	pdu::PduBuilder,
 	utils,
 	utils::{
 		math::{usize_from_f64, Expected},
+		stream::{BroadbandExt, IterStream},
 		ReadyExt,
 	},
-	Err, Error, PduEvent, Result,
+	Err, Error, PduCount, PduEvent, Result, Server,
 };
 use futures::StreamExt;
 use lru_cache::LruCache;
@@ -38,8 +41,8 @@ use ruma::{
 	},
 	room::RoomType,
 	space::SpaceRoomJoinRule,
-	EventEncryptionAlgorithm, EventId, JsOption, OwnedRoomAliasId, OwnedRoomId, OwnedServerName,
-	OwnedUserId, RoomId, ServerName, UserId,
+	EventEncryptionAlgorithm, EventId, JsOption, OwnedEventId, OwnedRoomAliasId, OwnedRoomId,
+	OwnedServerName, OwnedUserId, RoomId, ServerName, UserId,
 };
 use serde::Deserialize;
 
@@ -61,10 +64,15 @@ pub struct Service {
 }
 
 struct Services {
+	server: Arc<Server>,
+	metadata: Dep<rooms::metadata::Service>,
+	short: Dep<rooms::short::Service>,
+	state: Dep<rooms::state::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
 	timeline: Dep<rooms::timeline::Service>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		let config = &args.server.config;
@@ -75,6 +83,10 @@ impl crate::Service for Service {
 
 		Ok(Arc::new(Self {
 			services: Services {
+				server: args.server.clone(),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
+				short: args.depend::<rooms::short::Service>("rooms::short"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 			},
@@ -88,6 +100,17 @@ impl crate::Service for Service {
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		let count = self.services.server.config.cache_warmup_rooms;
+		if count > 0 {
+			self.warm_caches(count).await;
+		}
+
+		Ok(())
+	}
+
+	fn replica_worker(&self) -> bool { true }
+
 	fn memory_usage(&self, out: &mut dyn Write) -> Result {
 		use utils::bytes::pretty;
 
@@ -130,6 +153,69 @@ impl crate::Service for Service {
 }
 
 impl Service {
+	/// Preloads the caches for the `count` most recently active rooms. The
+	/// short-ids, compressed state snapshot and current state events of each
+	/// room are read so they are resident before clients ask for them.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn warm_caches(&self, count: usize) {
+		let timer = Instant::now();
+		let mut rooms: Vec<(PduCount, OwnedRoomId)> = self
+			.services
+			.metadata
+			.iter_ids()
+			.broad_filter_map(|room_id| async move {
+				self.services
+					.timeline
+					.last_timeline_count(None, room_id)
+					.await
+					.ok()
+					.map(|count| (count, room_id.to_owned()))
+			})
+			.collect()
+			.await;
+
+		rooms.sort_unstable_by(|a, b| b.0.cmp(&a.0));
+		rooms.truncate(count);
+
+		let warmed = rooms
+			.iter()
+			.stream()
+			.broad_filter_map(|(_, room_id)| async move { self.warm_room(room_id).await.ok() })
+			.count()
+			.await;
+
+		debug_info!(
+			elapsed = ?timer.elapsed(),
+			"Preloaded caches for {warmed} of {count} most recently active rooms",
+		);
+	}
+
+	async fn warm_room(&self, room_id: &RoomId) -> Result {
+		self.services.short.get_shortroomid(room_id).await?;
+
+		let shortstatehash = self.services.state.get_room_shortstatehash(room_id).await?;
+		let short_ids = self.state_full_shortids(shortstatehash).await?;
+
+		self.services
+			.short
+			.multi_get_statekey_from_short(short_ids.iter().map(at!(0)).stream())
+			.count()
+			.await;
+
+		self.services
+			.short
+			.multi_get_eventid_from_short(short_ids.iter().map(at!(1)).stream())
+			.ready_filter_map(Result::ok)
+			.broad_filter_map(|event_id: OwnedEventId| async move {
+				self.services.short.get_shorteventid(&event_id).await.ok()?;
+				self.services.timeline.get_pdu(&event_id).await.ok()
+			})
+			.count()
+			.await;
+
+		Ok(())
+	}
+
 	/// Builds a StateMap by iterating over all keys that start
 	/// with state_hash, this gives the full state for the given state_hash.
 	#[tracing::instrument(skip(self), level = "debug")]
@@ -159,6 +245,23 @@ impl Service {
 		self.db.state_full(shortstatehash).await
 	}
 
+	/// Returns every m.room.member event in the state snapshot in a single
+	/// pass, rather than looking up each member's state individually.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn state_members(&self, shortstatehash: ShortStateHash) -> Result<Vec<PduEvent>> {
+		self.db.state_full_members(shortstatehash).await
+	}
+
+	/// Returns every event of the type in the state snapshot in a single pass.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn state_type_pdus(
+		&self,
+		shortstatehash: ShortStateHash,
+		event_type: &StateEventType,
+	) -> Result<Vec<PduEvent>> {
+		self.db.state_full_type(shortstatehash, event_type).await
+	}
+
 	/// Returns a single EventId from `room_id` with key (`event_type`,
 	/// `state_key`).
 	#[tracing::instrument(skip(self), level = "debug")]
@@ -393,6 +496,12 @@ impl Service {
 		self.db.room_state_full_pdus(room_id).await
 	}
 
+	/// Returns every m.room.member event in the current room state.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn room_state_members(&self, room_id: &RoomId) -> Result<Vec<PduEvent>> {
+		self.db.room_state_members(room_id).await
+	}
+
 	/// Returns a single EventId from `room_id` with key (`event_type`,
 	/// `state_key`).
 	#[tracing::instrument(skip(self), level = "debug")]
@@ -639,4 +748,22 @@ impl Service {
 			.await
 			.is_ok()
 	}
+
+	/// Whether the room is encrypted and its history is visible to the users
+	/// invited to it, so their devices may be sent the room keys of the history
+	/// before they joined, marked as `shared_history` (MSC3061)
+	pub async fn is_history_shared(&self, room_id: &RoomId) -> bool {
+		if !self.is_encrypted_room(room_id).await {
+			return false;
+		}
+
+		let history_visibility = self
+			.room_state_get_content(room_id, &StateEventType::RoomHistoryVisibility, "")
+			.await
+			.map_or(HistoryVisibility::Shared, |c: RoomHistoryVisibilityEventContent| {
+				c.history_visibility
+			});
+
+		matches!(history_visibility, HistoryVisibility::Shared | HistoryVisibility::WorldReadable)
+	}
 }
@@ -38,6 +38,7 @@ pub struct Service {
 
 struct Services {
 	account_data: Dep<account_data::Service>,
+	directory: Dep<rooms::directory::Service>,
 	globals: Dep<globals::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	users: Dep<users::Service>,
@@ -70,6 +71,7 @@ impl crate::Service for Service {
 			appservice_in_room_cache: RwLock::new(HashMap::new()),
 			services: Services {
 				account_data: args.depend::<account_data::Service>("account_data"),
+				directory: args.depend::<rooms::directory::Service>("rooms::directory"),
 				globals: args.depend::<globals::Service>("globals"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
@@ -132,6 +134,16 @@ impl Service {
 				self.services.users.create(user_id, None)?;
 			}
 
+			// Index the user in the user directory by the display name it joined with,
+			// unless we have its profile
+			if membership == MembershipState::Join
+				&& self.services.users.displayname(user_id).await.is_err()
+			{
+				self.services
+					.users
+					.reindex_directory(user_id, membership_event.displayname.as_deref());
+			}
+
 			/*
 			// Try to update our local copy of the user if ours does not match
 			if ((self.services.users.displayname(user_id)? != membership_event.displayname)
@@ -265,6 +277,17 @@ impl Service {
 				self.mark_as_invited(user_id, room_id, last_state, invite_via)
 					.await;
 			},
+			| MembershipState::Knock => {
+				// The stripped state the resident server answered the knock with tells
+				// more of a room we aren't in than we know ourselves; keep it over the
+				// state of the knock event appended after.
+				let knock_state = match self.knock_state(user_id, room_id).await {
+					| Ok(knock_state) if !knock_state.is_empty() => Some(knock_state),
+					| _ => last_state,
+				};
+
+				self.mark_as_knocked(user_id, room_id, knock_state);
+			},
 			| MembershipState::Leave | MembershipState::Ban => {
 				self.mark_as_left(user_id, room_id);
 			},
@@ -921,6 +944,8 @@ impl Service {
 			.write()
 			.expect("locked")
 			.remove(room_id);
+
+		self.services.directory.reindex(room_id).await;
 	}
 
 	#[tracing::instrument(level = "debug", skip(self))]
@@ -11,13 +11,27 @@ use conduwuit::{
 use database::{Deserialized, Map};
 use futures::{Stream, StreamExt};
 use ruma::{
-	api::client::threads::get_threads::v1::IncludeThreads, events::relation::BundledThread, uint,
-	CanonicalJsonValue, EventId, OwnedUserId, RoomId, UserId,
+	api::client::threads::get_threads::v1::IncludeThreads,
+	events::relation::{BundledThread, RelationType},
+	uint, CanonicalJsonValue, EventId, OwnedEventId, OwnedUserId, RoomId, UserId,
 };
+use serde::Deserialize;
 use serde_json::json;
 
 use crate::{rooms, rooms::short::ShortRoomId, Dep};
 
+#[derive(Deserialize)]
+struct ExtractThread {
+	#[serde(rename = "m.relates_to")]
+	relates_to: ExtractThreadRelation,
+}
+
+#[derive(Deserialize)]
+struct ExtractThreadRelation {
+	rel_type: RelationType,
+	event_id: OwnedEventId,
+}
+
 pub struct Service {
 	db: Data,
 	services: Services,
@@ -49,6 +63,16 @@ impl crate::Service for Service {
 }
 
 impl Service {
+	/// The root of the thread the event is in, if it is in one. The root itself
+	/// is in the main timeline.
+	#[must_use]
+	pub fn thread_root(&self, pdu: &PduEvent) -> Option<OwnedEventId> {
+		pdu.get_content::<ExtractThread>()
+			.ok()
+			.filter(|content| content.relates_to.rel_type == RelationType::Thread)
+			.map(|content| content.relates_to.event_id)
+	}
+
 	pub async fn add_to_thread(&self, root_event_id: &EventId, pdu: &PduEvent) -> Result<()> {
 		let root_id = self
 			.services
@@ -203,6 +203,13 @@ impl Data {
 		self.eventid_outlierpdu.remove(event_id);
 	}
 
+	/// Deletes a pdu, e.g. once it is older than the room's retention policy
+	/// allows.
+	pub(super) fn purge_pdu(&self, pdu_id: &RawPduId, event_id: &EventId) {
+		self.pduid_pdu.remove(pdu_id);
+		self.eventid_pduid.remove(event_id);
+	}
+
 	/// Removes a pdu and creates a new one with the same id.
 	pub(super) async fn replace_pdu(
 		&self,
@@ -1,4 +1,5 @@
 mod data;
+mod retention;
 
 use std::{
 	cmp,
@@ -8,9 +9,12 @@ use std::{
 	sync::Arc,
 };
 
+use async_trait::async_trait;
 use conduwuit::{
 	at, debug, debug_warn, err, error, implement, info,
-	pdu::{gen_event_id, EventHash, PduBuilder, PduCount, PduEvent},
+	pdu::{
+		check_pdu_format, check_pdu_size, gen_event_id, EventHash, PduBuilder, PduCount, PduEvent,
+	},
 	utils::{
 		self, future::TryExtExt, stream::TryIgnore, IterStream, MutexMap, MutexMapGuard, ReadyExt,
 	},
@@ -24,7 +28,7 @@ use ruma::{
 	api::federation,
 	canonical_json::to_canonical_value,
 	events::{
-		push_rules::PushRulesEvent,
+		receipt::ReceiptThread,
 		room::{
 			create::RoomCreateEventContent,
 			encrypted::Relation,
@@ -32,22 +36,23 @@ use ruma::{
 			power_levels::RoomPowerLevelsEventContent,
 			redaction::RoomRedactionEventContent,
 		},
-		GlobalAccountDataEventType, StateEventType, TimelineEventType,
+		StateEventType, TimelineEventType,
 	},
-	push::{Action, Ruleset, Tweak},
+	push::{Action, Tweak},
 	state_res::{self, Event, RoomVersion},
 	uint, CanonicalJsonObject, CanonicalJsonValue, EventId, OwnedEventId, OwnedRoomId,
 	OwnedServerName, OwnedUserId, RoomId, RoomVersionId, ServerName, UserId,
 };
 use serde::Deserialize;
 use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
+use tokio::sync::Notify;
 
 use self::data::Data;
 pub use self::data::PdusIterItem;
 use crate::{
-	account_data, admin, appservice,
+	admin, appservice,
 	appservice::NamespaceRegex,
-	globals, pusher, rooms,
+	bus, globals, moderation, pusher, rooms,
 	rooms::{short::ShortRoomId, state_compressor::CompressedStateEvent},
 	sending, server_keys, users, Dep,
 };
@@ -77,16 +82,19 @@ struct ExtractBody {
 pub struct Service {
 	services: Services,
 	db: Data,
+	interrupt: Notify,
 	pub mutex_insert: RoomMutexMap,
 }
 
 struct Services {
 	server: Arc<Server>,
-	account_data: Dep<account_data::Service>,
 	appservice: Dep<appservice::Service>,
 	admin: Dep<admin::Service>,
 	alias: Dep<rooms::alias::Service>,
+	bus: Dep<bus::Service>,
+	directory: Dep<rooms::directory::Service>,
 	globals: Dep<globals::Service>,
+	metadata: Dep<rooms::metadata::Service>,
 	short: Dep<rooms::short::Service>,
 	state: Dep<rooms::state::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
@@ -102,21 +110,25 @@ struct Services {
 	search: Dep<rooms::search::Service>,
 	spaces: Dep<rooms::spaces::Service>,
 	event_handler: Dep<rooms::event_handler::Service>,
+	policy: Dep<moderation::policy::Service>,
 }
 
 type RoomMutexMap = MutexMap<OwnedRoomId, ()>;
 pub type RoomMutexGuard = MutexMapGuard<OwnedRoomId, ()>;
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			services: Services {
 				server: args.server.clone(),
-				account_data: args.depend::<account_data::Service>("account_data"),
 				appservice: args.depend::<appservice::Service>("appservice"),
 				admin: args.depend::<admin::Service>("admin"),
 				alias: args.depend::<rooms::alias::Service>("rooms::alias"),
+				bus: args.depend::<bus::Service>("bus"),
+				directory: args.depend::<rooms::directory::Service>("rooms::directory"),
 				globals: args.depend::<globals::Service>("globals"),
+				metadata: args.depend::<rooms::metadata::Service>("rooms::metadata"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
@@ -134,12 +146,18 @@ impl crate::Service for Service {
 				spaces: args.depend::<rooms::spaces::Service>("rooms::spaces"),
 				event_handler: args
 					.depend::<rooms::event_handler::Service>("rooms::event_handler"),
+				policy: args.depend::<moderation::policy::Service>("moderation::policy"),
 			},
 			db: Data::new(&args),
+			interrupt: Notify::new(),
 			mutex_insert: RoomMutexMap::new(),
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result<()> { self.retention_worker().await }
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn memory_usage(&self, out: &mut dyn Write) -> Result<()> {
 		let mutex_insert = self.mutex_insert.len();
 		writeln!(out, "insert_mutex: {mutex_insert}")?;
@@ -346,9 +364,16 @@ impl Service {
 		self.services
 			.read_receipt
 			.private_read_set(&pdu.room_id, &pdu.sender, count1);
+		// Replying in a thread only reads the thread, and sending to the main
+		// timeline only the main timeline
+		let thread_root = self.services.threads.thread_root(pdu);
+		let receipt_thread = thread_root
+			.clone()
+			.map_or(ReceiptThread::Main, ReceiptThread::Thread);
 		self.services
 			.user
-			.reset_notification_counts(&pdu.sender, &pdu.room_id);
+			.reset_receipt_notification_counts(&pdu.sender, &pdu.room_id, &receipt_thread)
+			.await;
 
 		let count2 = PduCount::Normal(self.services.globals.next_count().unwrap());
 		let pdu_id: RawPduId = PduId { shortroomid, shorteventid: count2 }.into();
@@ -393,15 +418,7 @@ impl Service {
 		}
 
 		for user in &push_target {
-			let rules_for_user = self
-				.services
-				.account_data
-				.get_global(user, GlobalAccountDataEventType::PushRules)
-				.await
-				.map_or_else(
-					|_| Ruleset::server_default(user),
-					|ev: PushRulesEvent| ev.content.global,
-				);
+			let rules_for_user = self.services.pusher.rules(user).await;
 
 			let mut highlight = false;
 			let mut notify = false;
@@ -446,9 +463,29 @@ impl Service {
 				.await;
 		}
 
+		if let Some(thread_root) = &thread_root {
+			self.services.user.increment_thread_notification_counts(
+				&pdu.room_id,
+				thread_root,
+				notifies.iter().map(|user| &**user),
+				highlights.iter().map(|user| &**user),
+			);
+		}
+
 		self.db
 			.increment_notification_counts(&pdu.room_id, notifies, highlights);
 
+		// Syncs waiting on other instances don't see the write to this one.
+		let sender = self
+			.services
+			.globals
+			.user_is_local(&pdu.sender)
+			.then_some(&pdu.sender);
+
+		for user in push_target.iter().chain(sender) {
+			self.services.bus.wake_syncs(user);
+		}
+
 		match pdu.kind {
 			| TimelineEventType::RoomRedaction => {
 				use RoomVersionId::*;
@@ -482,6 +519,11 @@ impl Service {
 					},
 				};
 			},
+			| TimelineEventType::RoomName
+			| TimelineEventType::RoomTopic
+			| TimelineEventType::RoomCanonicalAlias => {
+				self.services.directory.reindex(&pdu.room_id).await;
+			},
 			| TimelineEventType::SpaceChild =>
 				if let Some(_state_key) = &pdu.state_key {
 					self.services
@@ -536,6 +578,8 @@ impl Service {
 			| _ => {},
 		}
 
+		self.services.policy.notify(pdu);
+
 		if let Ok(content) = pdu.get_content::<ExtractRelatesToEventId>() {
 			if let Ok(related_pducount) = self.get_pdu_count(&content.relates_to.event_id).await {
 				self.services
@@ -781,6 +825,11 @@ impl Service {
 				.expect("server name is a valid CanonicalJsonValue"),
 		);
 
+		// Refuse events remote servers would reject, before signing them
+		check_pdu_format(&pdu_json).inspect_err(|e| {
+			debug_warn!(event_type = ?pdu.kind, "Refusing to sign invalid event: {e}");
+		})?;
+
 		if let Err(e) = self
 			.services
 			.server_keys
@@ -794,6 +843,11 @@ impl Service {
 			};
 		}
 
+		// The hashes and signatures count towards the size remote servers check
+		check_pdu_size(&pdu_json).inspect_err(|e| {
+			debug_warn!(event_type = ?pdu.kind, "Refusing signed event too large: {e}");
+		})?;
+
 		// Generate event id
 		pdu.event_id = gen_event_id(&pdu_json, &room_version_id)?;
 
@@ -826,6 +880,14 @@ impl Service {
 			.create_hash_and_sign_event(pdu_builder, sender, room_id, state_lock)
 			.await?;
 
+		// The events of shadow-banned users are dropped as if sent, so they never
+		// reach the room; only their own membership changes are kept.
+		let own_membership = pdu.kind == TimelineEventType::RoomMember
+			&& pdu.state_key.as_deref() == Some(sender.as_str());
+		if !own_membership && self.services.users.is_shadow_banned(sender).await {
+			return Ok(pdu.event_id);
+		}
+
 		if self.services.admin.is_admin_room(&pdu.room_id).await {
 			self.check_pdu_for_admin_room(&pdu, sender).boxed().await?;
 		}
@@ -1055,6 +1117,11 @@ impl Service {
 
 	#[tracing::instrument(skip(self), level = "debug")]
 	pub async fn backfill_if_required(&self, room_id: &RoomId, from: PduCount) -> Result<()> {
+		if self.services.globals.is_read_only() {
+			// A read replica serves the history it has; the primary backfills
+			return Ok(());
+		}
+
 		if self
 			.services
 			.state_cache
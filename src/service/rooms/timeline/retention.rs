@@ -0,0 +1,320 @@
+//! Retention policies of rooms: the events of a room older than the maximum
+//! lifetime of its `m.room.retention` policy, or of the default one of the
+//! config, are purged. State events and the forward extremities of a room are
+//! kept, so its state and the events referring to them remain; the room states
+//! only the purged events referred to are deleted with them.
+
+use std::{
+	collections::{BTreeSet, HashSet},
+	mem::size_of,
+	time::Duration,
+};
+
+use conduwuit::{
+	debug, debug_info, implement,
+	utils::{self, stream::TryIgnore, ReadyExt},
+	warn, Config, PduEvent, Result,
+};
+use database::Ignore;
+use futures::{future, StreamExt, TryStreamExt};
+use ruma::{events::StateEventType, OwnedEventId, RoomId};
+use serde::Deserialize;
+use tokio::time::sleep;
+
+use super::ExtractBody;
+use crate::rooms::short::{ShortRoomId, ShortStateHash};
+
+/// How many expired events of a room are purged at a time, holding the state
+/// lock of the room.
+const PURGE_BATCH: usize = 256;
+
+/// The content of `m.room.retention` events; lifetimes are in milliseconds.
+#[derive(Debug, Default, Deserialize)]
+struct RoomRetentionEventContent {
+	#[serde(default)]
+	max_lifetime: Option<u64>,
+}
+
+#[implement(super::Service)]
+pub(super) async fn retention_worker(&self) -> Result {
+	let config = &self.services.server.config;
+	if !config.allow_room_retention {
+		return Ok(());
+	}
+
+	let interval = Duration::from_secs(config.retention_purge_interval.max(1));
+	while self.services.server.running() {
+		tokio::select! {
+			() = self.interrupt.notified() => break,
+			() = sleep(interval) => (),
+		}
+
+		let mut purged = 0_usize;
+		let room_ids: Vec<_> = self
+			.services
+			.metadata
+			.iter_ids()
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for room_id in room_ids {
+			if !self.services.server.running() {
+				break;
+			}
+
+			match self.enforce_retention(&room_id).await {
+				| Ok(count) => purged = purged.saturating_add(count),
+				| Err(e) => warn!(%room_id, "Failed to enforce room retention: {e}"),
+			}
+		}
+
+		if purged > 0 {
+			debug_info!(%purged, "Purged events of rooms past their retention");
+		} else {
+			debug!("No events past their room's retention to purge");
+		}
+	}
+
+	Ok(())
+}
+
+/// Purge the events of the room older than its retention policy allows,
+/// returning how many were.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn enforce_retention(&self, room_id: &RoomId) -> Result<usize> {
+	let Some(max_lifetime) = self.max_lifetime(room_id).await else {
+		return Ok(0);
+	};
+
+	// Timestamps are the sender's, so an expired event may follow one dated in
+	// the future; the whole timeline is looked through.
+	let cutoff = utils::millis_since_unix_epoch().saturating_sub(max_lifetime);
+	let expired: Vec<PduEvent> = self
+		.pdus(None, room_id, None)
+		.map_ok(|(_, pdu)| pdu)
+		.try_filter(|pdu| {
+			future::ready(pdu.state_key.is_none() && u64::from(pdu.origin_server_ts) < cutoff)
+		})
+		.try_collect()
+		.await?;
+
+	if expired.is_empty() {
+		return Ok(0);
+	}
+
+	let shortroomid = self.services.short.get_shortroomid(room_id).await?;
+	let mut purged = 0_usize;
+	let mut states = BTreeSet::new();
+	for batch in expired.chunks(PURGE_BATCH) {
+		if !self.services.server.running() {
+			break;
+		}
+
+		let state_lock = self.services.state.mutex.lock(room_id).await;
+		let extremities: HashSet<OwnedEventId> = self
+			.services
+			.state
+			.get_forward_extremities(room_id)
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		for pdu in batch {
+			if extremities.contains(&pdu.event_id) {
+				continue;
+			}
+
+			if self.purge_pdu(shortroomid, pdu, &mut states).await {
+				purged = purged.saturating_add(1);
+			}
+		}
+
+		drop(state_lock);
+	}
+
+	if purged > 0 {
+		let compacted = self.compact_states(room_id, shortroomid, states).await;
+		debug!(%purged, %compacted, "Purged events and room states past retention");
+		self.compact_pdus(shortroomid).await?;
+	}
+
+	Ok(purged)
+}
+
+/// Delete an event with its search index entries and the state before it,
+/// adding the room state it referred to to the states.
+#[implement(super::Service)]
+async fn purge_pdu(
+	&self,
+	shortroomid: ShortRoomId,
+	pdu: &PduEvent,
+	states: &mut BTreeSet<ShortStateHash>,
+) -> bool {
+	let Ok(pdu_id) = self.get_pdu_id(&pdu.event_id).await else {
+		return false;
+	};
+
+	if let Ok(content) = pdu.get_content::<ExtractBody>() {
+		if let Some(body) = content.body {
+			self.services
+				.search
+				.deindex_pdu(shortroomid, &pdu_id, &body);
+		}
+	}
+
+	if let Ok(shortstatehash) = self
+		.services
+		.state_accessor
+		.pdu_shortstatehash(&pdu.event_id)
+		.await
+	{
+		states.insert(shortstatehash);
+	}
+
+	if let Ok(shorteventid) = self.services.short.get_shorteventid(&pdu.event_id).await {
+		self.services.state.delete_event_state(shorteventid);
+	}
+
+	debug!(event_id = %pdu.event_id, "Purging event past its room's retention");
+	self.db.purge_pdu(&pdu_id, &pdu.event_id);
+	true
+}
+
+/// Delete the room states only purged events referred to: those which are not
+/// the current state of the room, nor the state of another event, nor the one
+/// another state is a diff of, nor the one a sync was at. Returns how many
+/// were deleted.
+#[implement(super::Service)]
+async fn compact_states(
+	&self,
+	room_id: &RoomId,
+	shortroomid: ShortRoomId,
+	mut states: BTreeSet<ShortStateHash>,
+) -> usize {
+	let db = &self.db.db;
+	let unreferenced = |states: &mut BTreeSet<ShortStateHash>, val: &[u8]| {
+		if let Some(shortstatehash) = val.get(..size_of::<u64>()) {
+			let shortstatehash = u64::from_be_bytes(shortstatehash.try_into().expect("8 bytes"));
+			states.remove(&shortstatehash);
+		}
+	};
+
+	if !states.is_empty() {
+		db["roomsynctoken_shortstatehash"]
+			.raw_stream_prefix(&shortroomid.to_be_bytes())
+			.ignore_err()
+			.ready_for_each(|(_, val)| unreferenced(&mut states, val))
+			.await;
+	}
+
+	// Both begin with a state: the parent of a diff, the state before an event.
+	for map in ["shortstatehash_statediff", "shorteventid_shortstatehash"] {
+		if states.is_empty() {
+			break;
+		}
+
+		db[map]
+			.raw_stream()
+			.ignore_err()
+			.ready_for_each(|(_, val)| unreferenced(&mut states, val))
+			.await;
+	}
+
+	if !states.is_empty() {
+		db["userdeviceroomid_shortstatehash"]
+			.stream()
+			.ignore_err()
+			.ready_for_each(|(_, (_, shortstatehash)): (Ignore, (u64, ShortStateHash))| {
+				states.remove(&shortstatehash);
+			})
+			.await;
+	}
+
+	// New events are only based on the current state, so with the state lock
+	// held no other state can be referred to again.
+	let state_lock = self.services.state.mutex.lock(room_id).await;
+	if let Ok(current) = self.services.state.get_room_shortstatehash(room_id).await {
+		states.remove(&current);
+	}
+
+	if states.is_empty() {
+		return 0;
+	}
+
+	for shortstatehash in &states {
+		db["shortstatehash_statediff"].remove(&shortstatehash.to_be_bytes());
+	}
+
+	let statehash_shortstatehash = &db["statehash_shortstatehash"];
+	let statehashes: Vec<Vec<u8>> = statehash_shortstatehash
+		.raw_stream()
+		.ignore_err()
+		.ready_filter_map(|(key, val)| {
+			let shortstatehash = u64::from_be_bytes(val.try_into().ok()?);
+			states.contains(&shortstatehash).then(|| key.to_vec())
+		})
+		.collect()
+		.await;
+
+	for statehash in &statehashes {
+		statehash_shortstatehash.remove(statehash);
+	}
+
+	drop(state_lock);
+	states.len()
+}
+
+/// Compact the room's range of the timeline, so the space of the events
+/// purged is reclaimed instead of waiting for the database to get to it.
+#[implement(super::Service)]
+async fn compact_pdus(&self, shortroomid: ShortRoomId) -> Result {
+	let pduid_pdu = self.db.db["pduid_pdu"].clone();
+	let start = shortroomid.to_be_bytes();
+	let end = shortroomid.saturating_add(1).to_be_bytes();
+
+	self.services
+		.server
+		.runtime()
+		.spawn_blocking(move || pduid_pdu.compact_range(Some(&start[..]), Some(&end[..])))
+		.await?;
+
+	Ok(())
+}
+
+/// The maximum lifetime in milliseconds of the events of the room, if they
+/// are not kept indefinitely.
+#[implement(super::Service)]
+async fn max_lifetime(&self, room_id: &RoomId) -> Option<u64> {
+	let policy = self
+		.services
+		.state_accessor
+		.room_state_get_content::<RoomRetentionEventContent>(
+			room_id,
+			&StateEventType::from("m.room.retention"),
+			"",
+		)
+		.await
+		.ok()
+		.and_then(|content| content.max_lifetime);
+
+	effective_max_lifetime(policy, &self.services.server.config)
+}
+
+/// The maximum lifetime of the room's policy, or else the default one, within
+/// the bounds of the config.
+fn effective_max_lifetime(policy: Option<u64>, config: &Config) -> Option<u64> {
+	let millis = |secs: u64| secs.saturating_mul(1000);
+	let lifetime = policy
+		.filter(|&lifetime| lifetime > 0)
+		.or_else(|| Some(millis(config.retention_default_max_lifetime)))
+		.filter(|&lifetime| lifetime > 0)?;
+
+	let lifetime = lifetime.max(millis(config.retention_min_lifetime));
+	if config.retention_max_lifetime > 0 {
+		return Some(lifetime.min(millis(config.retention_max_lifetime)));
+	}
+
+	Some(lifetime)
+}
@@ -3,12 +3,15 @@ use std::{collections::BTreeMap, sync::Arc};
 use conduwuit::{
 	debug_info, trace,
 	utils::{self, IterStream},
-	Result, Server,
+	Result,
 };
 use futures::StreamExt;
 use ruma::{
 	api::federation::transactions::edu::{Edu, TypingContent},
-	events::SyncEphemeralRoomEvent,
+	events::{
+		typing::{TypingEvent, TypingEventContent},
+		SyncEphemeralRoomEvent,
+	},
 	OwnedRoomId, OwnedUserId, RoomId, UserId,
 };
 use tokio::sync::{broadcast, RwLock};
@@ -16,7 +19,6 @@ use tokio::sync::{broadcast, RwLock};
 use crate::{globals, sending, users, Dep};
 
 pub struct Service {
-	server: Arc<Server>,
 	services: Services,
 	/// u64 is unix timestamp of timeout
 	pub typing: RwLock<BTreeMap<OwnedRoomId, BTreeMap<OwnedUserId, u64>>>,
@@ -34,7 +36,6 @@ struct Services {
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
-			server: args.server.clone(),
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
@@ -76,6 +77,9 @@ impl Service {
 			trace!("receiver found what it was looking for and is no longer interested");
 		}
 
+		// update appservices
+		self.appservice_send(room_id).await?;
+
 		// update federation
 		if self.services.globals.user_is_local(user_id) {
 			self.federation_send(room_id, user_id, true).await?;
@@ -104,6 +108,9 @@ impl Service {
 			trace!("receiver found what it was looking for and is no longer interested");
 		}
 
+		// update appservices
+		self.appservice_send(room_id).await?;
+
 		// update federation
 		if self.services.globals.user_is_local(user_id) {
 			self.federation_send(room_id, user_id, false).await?;
@@ -140,13 +147,15 @@ impl Service {
 		};
 
 		if !removable.is_empty() {
-			let typing = &mut self.typing.write().await;
+			let mut typing = self.typing.write().await;
 			let room = typing.entry(room_id.to_owned()).or_default();
 			for user in &removable {
 				debug_info!("typing timeout {user:?} in {room_id:?}");
 				room.remove(user);
 			}
 
+			drop(typing);
+
 			// update clients
 			self.last_typing_update
 				.write()
@@ -157,6 +166,9 @@ impl Service {
 				trace!("receiver found what it was looking for and is no longer interested");
 			}
 
+			// update appservices
+			self.appservice_send(room_id).await?;
+
 			// update federation
 			for user in &removable {
 				if self.services.globals.user_is_local(user) {
@@ -213,6 +225,28 @@ impl Service {
 		})
 	}
 
+	/// Sends the users typing in the room to the appservices receiving
+	/// ephemeral events.
+	async fn appservice_send(&self, room_id: &RoomId) -> Result<()> {
+		let user_ids = self
+			.typing
+			.read()
+			.await
+			.get(room_id)
+			.map(|room| room.keys().cloned().collect())
+			.unwrap_or_default();
+
+		let event = TypingEvent {
+			content: TypingEventContent { user_ids },
+			room_id: room_id.to_owned(),
+		};
+
+		self.services
+			.sending
+			.send_ephemeral_room(room_id, &event)
+			.await
+	}
+
 	async fn federation_send(
 		&self,
 		room_id: &RoomId,
@@ -224,7 +258,7 @@ impl Service {
 			"tried to broadcast typing status of remote user",
 		);
 
-		if !self.server.config.allow_outgoing_typing {
+		if !self.services.sending.allow_outgoing_typing(room_id).await {
 			return Ok(());
 		}
 
@@ -1,8 +1,12 @@
-use std::sync::Arc;
+use std::{collections::BTreeMap, sync::Arc};
 
-use conduwuit::{implement, Result};
-use database::{Database, Deserialized, Map};
-use ruma::{RoomId, UserId};
+use conduwuit::{
+	implement,
+	utils::{self, stream::TryIgnore, ReadyExt},
+	Result,
+};
+use database::{Database, Deserialized, Interfix, Map};
+use ruma::{events::receipt::ReceiptThread, DeviceId, EventId, OwnedEventId, RoomId, UserId};
 
 use crate::{globals, rooms, rooms::short::ShortStateHash, Dep};
 
@@ -15,8 +19,11 @@ struct Data {
 	db: Arc<Database>,
 	userroomid_notificationcount: Arc<Map>,
 	userroomid_highlightcount: Arc<Map>,
+	userroomthreadid_notificationcount: Arc<Map>,
+	userroomthreadid_highlightcount: Arc<Map>,
 	roomuserid_lastnotificationread: Arc<Map>,
 	roomsynctoken_shortstatehash: Arc<Map>,
+	userdeviceroomid_shortstatehash: Arc<Map>,
 }
 
 struct Services {
@@ -31,8 +38,14 @@ impl crate::Service for Service {
 				db: args.db.clone(),
 				userroomid_notificationcount: args.db["userroomid_notificationcount"].clone(),
 				userroomid_highlightcount: args.db["userroomid_highlightcount"].clone(),
+				userroomthreadid_notificationcount: args.db["userroomthreadid_notificationcount"]
+					.clone(),
+				userroomthreadid_highlightcount: args.db["userroomthreadid_highlightcount"]
+					.clone(),
 				roomuserid_lastnotificationread: args.db["userroomid_highlightcount"].clone(),
 				roomsynctoken_shortstatehash: args.db["roomsynctoken_shortstatehash"].clone(),
+				userdeviceroomid_shortstatehash: args.db["userdeviceroomid_shortstatehash"]
+					.clone(),
 			},
 
 			services: Services {
@@ -46,10 +59,83 @@ impl crate::Service for Service {
 }
 
 #[implement(Service)]
-pub fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
+pub async fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
+	let prefix = (user_id, room_id, Interfix);
+	for map in [
+		&self.db.userroomthreadid_notificationcount,
+		&self.db.userroomthreadid_highlightcount,
+	] {
+		map.keys_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_for_each(|key| map.remove(key))
+			.await;
+	}
+
+	self.set_notification_counts(user_id, room_id, 0, 0);
+}
+
+/// Resets the counts of the user in the room for a read receipt: a threaded
+/// receipt resets those of its thread only, or of the main timeline only, and
+/// an unthreaded one all of them.
+#[implement(Service)]
+pub async fn reset_receipt_notification_counts(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	thread: &ReceiptThread,
+) {
+	match thread {
+		| ReceiptThread::Main => {
+			let (notifications, highlights) = self
+				.thread_notification_counts(user_id, room_id)
+				.await
+				.into_values()
+				.fold((0_u64, 0_u64), |(notifications, highlights), (n, h)| {
+					(notifications.saturating_add(n), highlights.saturating_add(h))
+				});
+
+			self.set_notification_counts(user_id, room_id, notifications, highlights);
+		},
+		| ReceiptThread::Thread(thread_id) => {
+			let key = (user_id, room_id, thread_id);
+			let count = |map: &Arc<Map>| async move {
+				let count: u64 = map.qry(&key).await.deserialized().unwrap_or(0);
+				map.del(key);
+				count
+			};
+
+			let notifications = count(&self.db.userroomthreadid_notificationcount).await;
+			let highlights = count(&self.db.userroomthreadid_highlightcount).await;
+			let notifications = self
+				.notification_count(user_id, room_id)
+				.await
+				.saturating_sub(notifications);
+			let highlights = self
+				.highlight_count(user_id, room_id)
+				.await
+				.saturating_sub(highlights);
+
+			self.set_notification_counts(user_id, room_id, notifications, highlights);
+		},
+		| _ => self.reset_notification_counts(user_id, room_id).await,
+	}
+}
+
+#[implement(Service)]
+fn set_notification_counts(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+	notifications: u64,
+	highlights: u64,
+) {
 	let userroom_id = (user_id, room_id);
-	self.db.userroomid_highlightcount.put(userroom_id, 0_u64);
-	self.db.userroomid_notificationcount.put(userroom_id, 0_u64);
+	self.db
+		.userroomid_highlightcount
+		.put(userroom_id, highlights);
+	self.db
+		.userroomid_notificationcount
+		.put(userroom_id, notifications);
 
 	let roomuser_id = (room_id, user_id);
 	let count = self.services.globals.next_count().unwrap();
@@ -58,6 +144,70 @@ pub fn reset_notification_counts(&self, user_id: &UserId, room_id: &RoomId) {
 		.put(roomuser_id, count);
 }
 
+/// Counts notifications and highlights of the users in the thread, which the
+/// counts of the whole room include as well.
+#[implement(Service)]
+pub fn increment_thread_notification_counts<'a, I, J>(
+	&self,
+	room_id: &RoomId,
+	thread_id: &EventId,
+	notifies: I,
+	highlights: J,
+) where
+	I: IntoIterator<Item = &'a UserId>,
+	J: IntoIterator<Item = &'a UserId>,
+{
+	let _cork = self.db.db.cork();
+	let increment = |map: &Arc<Map>, user_id: &UserId| {
+		let key = database::serialize_key((user_id, room_id, thread_id))
+			.expect("failed to serialize thread count key");
+		let old = map.get_blocking(&key);
+		map.insert(&key, utils::increment(old.ok().as_deref()));
+	};
+
+	for user_id in notifies {
+		increment(&self.db.userroomthreadid_notificationcount, user_id);
+	}
+
+	for user_id in highlights {
+		increment(&self.db.userroomthreadid_highlightcount, user_id);
+	}
+}
+
+/// The notification and highlight counts of the user in each thread of the
+/// room with any.
+#[implement(Service)]
+pub async fn thread_notification_counts(
+	&self,
+	user_id: &UserId,
+	room_id: &RoomId,
+) -> BTreeMap<OwnedEventId, (u64, u64)> {
+	type Key<'a> = (&'a UserId, &'a RoomId, &'a EventId);
+
+	let prefix = (user_id, room_id, Interfix);
+	let mut counts = BTreeMap::<OwnedEventId, (u64, u64)>::new();
+	self.db
+		.userroomthreadid_notificationcount
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.ready_for_each(|((_, _, thread_id), count): (Key<'_>, u64)| {
+			counts.entry(thread_id.to_owned()).or_default().0 = count;
+		})
+		.await;
+
+	self.db
+		.userroomthreadid_highlightcount
+		.stream_prefix(&prefix)
+		.ignore_err()
+		.ready_for_each(|((_, _, thread_id), count): (Key<'_>, u64)| {
+			counts.entry(thread_id.to_owned()).or_default().1 = count;
+		})
+		.await;
+
+	counts.retain(|_, &mut (notifications, highlights)| notifications > 0 || highlights > 0);
+	counts
+}
+
 #[implement(Service)]
 pub async fn notification_count(&self, user_id: &UserId, room_id: &RoomId) -> u64 {
 	let key = (user_id, room_id);
@@ -98,6 +248,11 @@ pub async fn associate_token_shortstatehash(
 	token: u64,
 	shortstatehash: ShortStateHash,
 ) {
+	// Replicas fall back to the state last synced from the primary.
+	if self.db.db.is_read_only() {
+		return;
+	}
+
 	let shortroomid = self
 		.services
 		.short
@@ -127,3 +282,40 @@ pub async fn get_token_shortstatehash(
 		.await
 		.deserialized()
 }
+
+/// Record the room state last delivered to the device, with the token it was
+/// delivered at. Incremental syncs from that token or a later one fall back to
+/// this when the snapshot for their `since` token is missing.
+#[implement(Service)]
+pub fn associate_device_shortstatehash(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	room_id: &RoomId,
+	token: u64,
+	shortstatehash: ShortStateHash,
+) {
+	if self.db.db.is_read_only() {
+		return;
+	}
+
+	let key = (user_id, device_id, room_id);
+	self.db
+		.userdeviceroomid_shortstatehash
+		.put(key, (token, shortstatehash));
+}
+
+#[implement(Service)]
+pub async fn get_device_shortstatehash(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	room_id: &RoomId,
+) -> Result<(u64, ShortStateHash)> {
+	let key = (user_id, device_id, room_id);
+	self.db
+		.userdeviceroomid_shortstatehash
+		.qry(&key)
+		.await
+		.deserialized()
+}
@@ -0,0 +1,126 @@
+//! Backoff of the servers failing our transactions, persisted so it outlasts
+//! restarts. The retry interval doubles with each failure in a row, from
+//! `sender_timeout` up to `sender_retry_backoff_limit`, and up to a quarter of
+//! it more at random so servers coming back aren't all retried at once. A
+//! server failing `sender_dead_server_threshold` times in a row is dead: until
+//! its backoff expires no request is sent to it at all.
+
+use conduwuit::{implement, info, utils, utils::stream::TryIgnore, warn, Error};
+use database::{Deserialized, Json};
+use futures::{Stream, StreamExt};
+use ruma::{OwnedServerName, ServerName};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Backoff {
+	/// Transactions failed in a row.
+	pub failures: u32,
+
+	/// When the last of them failed, in milliseconds since the epoch.
+	pub last_failure: u64,
+
+	/// When the server may be retried, in milliseconds since the epoch.
+	pub retry_at: u64,
+
+	/// Why the last of them failed.
+	pub error: String,
+}
+
+impl Backoff {
+	#[must_use]
+	pub fn is_expired(&self) -> bool { utils::millis_since_unix_epoch() >= self.retry_at }
+}
+
+/// The backoff of the server, if its last transaction failed.
+#[implement(super::Service)]
+pub async fn backoff(&self, server: &ServerName) -> Option<Backoff> {
+	self.db
+		.servername_backoff
+		.get(server)
+		.await
+		.deserialized()
+		.ok()
+}
+
+/// The servers whose last transaction failed, with their backoff.
+#[implement(super::Service)]
+pub fn backoffs(&self) -> impl Stream<Item = (OwnedServerName, Backoff)> + Send + '_ {
+	self.db
+		.servername_backoff
+		.stream()
+		.ignore_err()
+		.map(|(server, backoff): (&ServerName, Backoff)| (server.to_owned(), backoff))
+}
+
+/// Whether the server failed too many transactions in a row to send it
+/// anything until its backoff expires.
+#[implement(super::Service)]
+pub async fn is_dead(&self, server: &ServerName) -> bool {
+	let threshold = self.server.config.sender_dead_server_threshold;
+	threshold > 0
+		&& self
+			.backoff(server)
+			.await
+			.is_some_and(|backoff| backoff.failures >= threshold && !backoff.is_expired())
+}
+
+/// Whether transactions to the server wait for its backoff to expire.
+#[implement(super::Service)]
+pub(super) async fn is_backing_off(&self, server: &ServerName) -> bool {
+	self.backoff(server)
+		.await
+		.is_some_and(|backoff| !backoff.is_expired())
+}
+
+/// Forget the backoff of the server, so it is retried with the next
+/// transaction.
+#[implement(super::Service)]
+pub fn reset_backoff(&self, server: &ServerName) { self.db.servername_backoff.remove(server); }
+
+#[implement(super::Service)]
+pub(super) async fn record_failure(&self, server: &ServerName, error: &Error) {
+	let config = &self.server.config;
+	let failures = self
+		.backoff(server)
+		.await
+		.map_or(0, |backoff| backoff.failures)
+		.saturating_add(1);
+
+	let interval = 2_u64
+		.saturating_pow(failures.saturating_sub(1))
+		.saturating_mul(config.sender_timeout)
+		.min(config.sender_retry_backoff_limit);
+
+	let jitter = utils::rand::secs(0..(interval / 4).saturating_add(1));
+	let now = utils::millis_since_unix_epoch();
+	let backoff = Backoff {
+		failures,
+		last_failure: now,
+		retry_at: interval
+			.saturating_add(jitter.as_secs())
+			.saturating_mul(1000)
+			.saturating_add(now),
+		error: error.to_string(),
+	};
+
+	let threshold = config.sender_dead_server_threshold;
+	if threshold > 0 && failures == threshold {
+		warn!(%server, "Considering the server dead after {failures} failed transactions: {error}");
+	}
+
+	self.db.servername_backoff.raw_put(server, Json(&backoff));
+}
+
+#[implement(super::Service)]
+pub(super) async fn record_success(&self, server: &ServerName) {
+	let Some(backoff) = self.backoff(server).await else {
+		return;
+	};
+
+	let threshold = self.server.config.sender_dead_server_threshold;
+	if threshold > 0 && backoff.failures >= threshold {
+		info!(%server, "Server is back after {} failed transactions", backoff.failures);
+	}
+
+	self.reset_backoff(server);
+}
@@ -0,0 +1,183 @@
+//! Limits on the federation requests in flight, and a circuit breaker for each
+//! server. At most `federation_max_concurrent_requests` requests are in flight
+//! at once, and `federation_max_concurrent_requests_per_server` to any one
+//! server. A server failing `federation_circuit_breaker_threshold` requests in
+//! a row with a 502, 503 or 504 error, a timeout or no connection at all is
+//! sent nothing for `federation_circuit_breaker_timeout`; then one request is
+//! let through, and the breaker closes once one succeeds. Unlike the backoff
+//! this is kept in memory only, and counts all requests rather than
+//! transactions.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{implement, info, warn, Err, Error, Result};
+use http::StatusCode;
+use ruma::{OwnedServerName, ServerName};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+pub(super) struct Limits {
+	global: Option<Semaphore>,
+	per_server: Mutex<HashMap<OwnedServerName, Arc<Semaphore>>>,
+	breakers: Mutex<HashMap<OwnedServerName, Breaker>>,
+}
+
+/// The circuit breaker of a server which failed requests.
+#[derive(Clone, Copy, Debug)]
+pub struct Breaker {
+	/// Requests failed in a row.
+	pub failures: u32,
+
+	/// When the breaker opened, or a request was last let through since.
+	pub opened: Option<Instant>,
+}
+
+/// Held while a request is in flight.
+pub(super) struct Permits<'a> {
+	_global: Option<SemaphorePermit<'a>>,
+	_server: Option<OwnedSemaphorePermit>,
+}
+
+impl Limits {
+	pub(super) fn new(global: usize) -> Self {
+		Self {
+			global: (global > 0).then(|| Semaphore::new(global)),
+			per_server: Mutex::new(HashMap::new()),
+			breakers: Mutex::new(HashMap::new()),
+		}
+	}
+}
+
+/// Wait for the request to the server to be within the limits.
+#[implement(super::Service)]
+pub(super) async fn permits(&self, dest: &ServerName) -> Permits<'_> {
+	let max = self
+		.server
+		.config
+		.federation_max_concurrent_requests_per_server;
+	let server = (max > 0).then(|| {
+		let mut per_server = self.limits.per_server.lock().expect("locked");
+		if !per_server.contains_key(dest) {
+			// Servers with nothing in flight are forgotten as others are added.
+			per_server.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+		}
+
+		per_server
+			.entry(dest.to_owned())
+			.or_insert_with(|| Arc::new(Semaphore::new(max)))
+			.clone()
+	});
+
+	let global = match &self.limits.global {
+		| Some(global) => global.acquire().await.ok(),
+		| None => None,
+	};
+
+	let server = match server {
+		| Some(server) => server.acquire_owned().await.ok(),
+		| None => None,
+	};
+
+	Permits { _global: global, _server: server }
+}
+
+/// Fail the request to the server while its breaker is open, unless it's time
+/// to let one through to try it again.
+#[implement(super::Service)]
+pub(super) fn check_breaker(&self, dest: &ServerName) -> Result {
+	let timeout = Duration::from_secs(self.server.config.federation_circuit_breaker_timeout);
+	let mut breakers = self.limits.breakers.lock().expect("locked");
+	let Some(opened) = breakers
+		.get_mut(dest)
+		.and_then(|breaker| breaker.opened.as_mut())
+	else {
+		return Ok(());
+	};
+
+	let elapsed = opened.elapsed();
+	if elapsed < timeout {
+		let retry_in = timeout.saturating_sub(elapsed).as_secs();
+		return Err!(BadServerResponse(
+			"Requests to {dest} failed too many times in a row; retrying in {retry_in}s."
+		));
+	}
+
+	*opened = Instant::now();
+
+	Ok(())
+}
+
+/// Count the outcome of the request to the server towards its breaker. Only
+/// server errors, timeouts and failures to connect count as failures.
+#[implement(super::Service)]
+pub(super) fn record_breaker<T>(&self, dest: &ServerName, result: &Result<T>) {
+	let threshold = self.server.config.federation_circuit_breaker_threshold;
+	if threshold == 0 {
+		return;
+	}
+
+	let mut breakers = self.limits.breakers.lock().expect("locked");
+	let failed = result.as_ref().is_err_and(is_failure);
+	if !failed {
+		if let Some(breaker) = breakers.remove(dest) {
+			if breaker.opened.is_some() {
+				info!(%dest, "Closed the circuit breaker after a request succeeded");
+			}
+		}
+
+		return;
+	}
+
+	let breaker = breakers
+		.entry(dest.to_owned())
+		.or_insert(Breaker { failures: 0, opened: None });
+
+	breaker.failures = breaker.failures.saturating_add(1);
+	if breaker.failures >= threshold {
+		if breaker.opened.is_none() {
+			warn!(%dest, "Opened the circuit breaker after {} failed requests", breaker.failures);
+		}
+
+		breaker.opened = Some(Instant::now());
+	}
+}
+
+/// The breaker of the server, if its last request failed.
+#[implement(super::Service)]
+pub fn breaker(&self, dest: &ServerName) -> Option<Breaker> {
+	self.limits
+		.breakers
+		.lock()
+		.expect("locked")
+		.get(dest)
+		.copied()
+}
+
+/// Close the breaker of the server, so requests are sent to it again; returns
+/// whether it had failed requests.
+#[implement(super::Service)]
+pub fn reset_breaker(&self, dest: &ServerName) -> bool {
+	self.limits
+		.breakers
+		.lock()
+		.expect("locked")
+		.remove(dest)
+		.is_some()
+}
+
+/// Only the errors of a server down or overloaded count, not those of requests
+/// it refused or could not serve.
+fn is_failure(error: &Error) -> bool {
+	match error {
+		| Error::Reqwest(e) if e.is_timeout() || e.is_connect() => true,
+		| _ => matches!(
+			error.status_code(),
+			StatusCode::BAD_GATEWAY
+				| StatusCode::SERVICE_UNAVAILABLE
+				| StatusCode::GATEWAY_TIMEOUT
+		),
+	}
+}
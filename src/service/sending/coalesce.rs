@@ -0,0 +1,64 @@
+//! Coalescing of the EDUs of a transaction. The receipts of all rooms are sent
+//! in one EDU and the presence of all users in another, keeping the last
+//! update of each user; typing superseded by a later notification of the same
+//! user in the same room is left out. Other EDUs are sent as they are.
+
+use std::collections::{BTreeMap, HashMap};
+
+use conduwuit::debug_warn;
+use ruma::{
+	api::federation::transactions::edu::{
+		Edu, PresenceContent, PresenceUpdate, ReceiptContent, ReceiptMap, TypingContent,
+	},
+	OwnedRoomId, OwnedUserId,
+};
+use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
+
+/// Coalesce the serialized EDUs, in the order they were queued.
+pub(super) fn coalesce_edus<'a, I>(edus: I) -> Vec<Box<RawJsonValue>>
+where
+	I: Iterator<Item = &'a [u8]>,
+{
+	let mut out = Vec::new();
+	let mut receipts = BTreeMap::<OwnedRoomId, ReceiptMap>::new();
+	let mut presence = HashMap::<OwnedUserId, PresenceUpdate>::new();
+	let mut typing = HashMap::<(OwnedRoomId, OwnedUserId), TypingContent>::new();
+	for edu in edus {
+		match serde_json::from_slice(edu) {
+			| Ok(Edu::Receipt(content)) =>
+				for (room_id, receipt_map) in content.receipts {
+					receipts
+						.entry(room_id)
+						.or_insert_with(|| ReceiptMap { read: BTreeMap::new() })
+						.read
+						.extend(receipt_map.read);
+				},
+			| Ok(Edu::Presence(content)) =>
+				for update in content.push {
+					presence.insert(update.user_id.clone(), update);
+				},
+			| Ok(Edu::Typing(content)) => {
+				let key = (content.room_id.clone(), content.user_id.clone());
+				typing.insert(key, content);
+			},
+			| _ => match serde_json::from_slice(edu) {
+				| Ok(raw) => out.push(raw),
+				| Err(e) => debug_warn!("Not sending an invalid EDU: {e}"),
+			},
+		}
+	}
+
+	let presence = (!presence.is_empty())
+		.then(|| Edu::Presence(PresenceContent { push: presence.into_values().collect() }));
+
+	let receipts = (!receipts.is_empty()).then(|| Edu::Receipt(ReceiptContent { receipts }));
+
+	let coalesced = typing
+		.into_values()
+		.map(Edu::Typing)
+		.chain(presence)
+		.chain(receipts);
+
+	out.extend(coalesced.filter_map(|edu| to_raw_value(&edu).ok()));
+	out
+}
@@ -20,6 +20,7 @@ pub(super) type Key = Vec<u8>;
 pub struct Data {
 	servercurrentevent_data: Arc<Map>,
 	servernameevent_data: Arc<Map>,
+	pub(super) servername_backoff: Arc<Map>,
 	servername_educount: Arc<Map>,
 	pub(super) db: Arc<Database>,
 	services: Services,
@@ -35,6 +36,7 @@ impl Data {
 		Self {
 			servercurrentevent_data: db["servercurrentevent_data"].clone(),
 			servernameevent_data: db["servernameevent_data"].clone(),
+			servername_backoff: db["servername_backoff"].clone(),
 			servername_educount: db["servername_educount"].clone(),
 			db: args.db.clone(),
 			services: Services {
@@ -0,0 +1,97 @@
+//! Ephemeral events for the appservices which opted in to receive them
+//! (MSC2409): the typing and receipts of the rooms an appservice is in, and
+//! the presence of its users and of those sharing a room with it. They are
+//! queued in the client format, like the `ephemeral` of a transaction.
+
+use conduwuit::{implement, Result};
+use futures::StreamExt;
+use ruma::{RoomId, UserId};
+use serde::Serialize;
+
+use super::{Destination, Msg, SendingEvent};
+use crate::appservice::RegistrationInfo;
+
+/// Queue the ephemeral event of the room for the appservices in it.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, event), level = "debug")]
+pub async fn send_ephemeral_room<E>(&self, room_id: &RoomId, event: &E) -> Result
+where
+	E: Serialize + Send + Sync,
+{
+	let mut appservices = Vec::new();
+	for info in self.ephemeral_appservices().await {
+		if self
+			.services
+			.state_cache
+			.appservice_in_room(room_id, &info)
+			.await
+		{
+			appservices.push(info.registration.id);
+		}
+	}
+
+	self.send_edu_appservices(appservices, event)
+}
+
+/// Queue the presence event of the user for the appservices interested in
+/// it.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self, event), level = "debug")]
+pub async fn send_ephemeral_presence<E>(&self, user_id: &UserId, event: &E) -> Result
+where
+	E: Serialize + Send + Sync,
+{
+	let mut appservices = Vec::new();
+	for info in self.ephemeral_appservices().await {
+		let interested = info.is_user_match(user_id)
+			|| self
+				.services
+				.state_cache
+				.rooms_joined(user_id)
+				.any(|room_id| self.services.state_cache.appservice_in_room(room_id, &info))
+				.await;
+
+		if interested {
+			appservices.push(info.registration.id);
+		}
+	}
+
+	self.send_edu_appservices(appservices, event)
+}
+
+#[implement(super::Service)]
+async fn ephemeral_appservices(&self) -> Vec<RegistrationInfo> {
+	self.services
+		.appservice
+		.read()
+		.await
+		.values()
+		.filter(|info| info.registration.receive_ephemeral)
+		.cloned()
+		.collect()
+}
+
+#[implement(super::Service)]
+fn send_edu_appservices<E>(&self, appservices: Vec<String>, event: &E) -> Result
+where
+	E: Serialize,
+{
+	if appservices.is_empty() {
+		return Ok(());
+	}
+
+	let serialized = serde_json::to_vec(event)?;
+	let _cork = self.db.db.cork();
+	let requests: Vec<_> = appservices
+		.into_iter()
+		.map(|id| (Destination::Appservice(id), SendingEvent::Edu(serialized.clone())))
+		.collect();
+
+	self.db.queue_requests(requests.iter().map(|(o, e)| (e, o)));
+
+	for (dest, _) in requests {
+		self.dispatch(Msg { dest })?;
+	}
+
+	Ok(())
+}
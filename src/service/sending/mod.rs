@@ -1,37 +1,51 @@
 mod appservice;
+mod backoff;
+mod breaker;
+mod coalesce;
 mod data;
 mod dest;
+mod ephemeral;
+mod room_edus;
 mod send;
 mod sender;
 
 use std::{
-	fmt::Debug,
+	fmt::{Debug, Write},
 	hash::{DefaultHasher, Hash, Hasher},
 	iter::once,
-	sync::Arc,
+	sync::{Arc, Mutex, RwLock},
 };
 
 use async_trait::async_trait;
 use conduwuit::{
 	debug, debug_warn, err, error,
-	utils::{available_parallelism, math::usize_from_u64_truncated, ReadyExt, TryReadyExt},
+	utils::{
+		available_parallelism,
+		math::{usize_from_f64, usize_from_u64_truncated},
+		IterStream, ReadyExt, TryReadyExt,
+	},
 	warn, Result, Server,
 };
 use futures::{FutureExt, Stream, StreamExt};
+use http::HeaderValue;
+use lru_cache::LruCache;
 use ruma::{
 	api::{appservice::Registration, OutgoingRequest},
-	RoomId, ServerName, UserId,
+	OwnedServerName, RoomId, ServerName, UserId,
 };
 use tokio::task::JoinSet;
 
 use self::data::Data;
 pub use self::{
+	backoff::Backoff,
+	breaker::Breaker,
 	dest::Destination,
+	room_edus::{RoomEdus, ROOM_EDUS_EVENT_TYPE},
 	sender::{EDU_LIMIT, PDU_LIMIT},
 };
 use crate::{
-	account_data, client, globals, presence, pusher, resolver, rooms, rooms::timeline::RawPduId,
-	server_keys, users, Dep,
+	account_data, client, globals, moderation, presence, pusher, resolver, rooms,
+	rooms::timeline::RawPduId, server_keys, users, Dep,
 };
 
 pub struct Service {
@@ -39,12 +53,17 @@ pub struct Service {
 	server: Arc<Server>,
 	services: Services,
 	channels: Vec<(loole::Sender<Msg>, loole::Receiver<Msg>)>,
+	signed_requests: Mutex<LruCache<send::RequestDigest, HeaderValue>>,
+	limits: breaker::Limits,
+	room_edus: RwLock<Option<room_edus::RoomEdusCache>>,
 }
 
 struct Services {
+	account_data: Dep<account_data::Service>,
 	client: Dep<client::Service>,
 	globals: Dep<globals::Service>,
 	resolver: Dep<resolver::Service>,
+	joins: Dep<rooms::joins::Service>,
 	state: Dep<rooms::state::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
 	user: Dep<rooms::user::Service>,
@@ -52,17 +71,15 @@ struct Services {
 	presence: Dep<presence::Service>,
 	read_receipt: Dep<rooms::read_receipt::Service>,
 	timeline: Dep<rooms::timeline::Service>,
-	account_data: Dep<account_data::Service>,
 	appservice: Dep<crate::appservice::Service>,
 	pusher: Dep<pusher::Service>,
 	server_keys: Dep<server_keys::Service>,
+	policy: Dep<moderation::policy::Service>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
 struct Msg {
 	dest: Destination,
-	event: SendingEvent,
-	queue_id: Vec<u8>,
 }
 
 #[allow(clippy::module_name_repetitions)]
@@ -77,13 +94,19 @@ pub enum SendingEvent {
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		let num_senders = num_senders(&args);
+		let config = &args.server.config;
+		let signed_request_cache_capacity =
+			f64::from(config.signed_request_cache_capacity) * config.cache_capacity_modifier;
+
 		Ok(Arc::new(Self {
 			db: Data::new(&args),
 			server: args.server.clone(),
 			services: Services {
+				account_data: args.depend::<account_data::Service>("account_data"),
 				client: args.depend::<client::Service>("client"),
 				globals: args.depend::<globals::Service>("globals"),
 				resolver: args.depend::<resolver::Service>("resolver"),
+				joins: args.depend::<rooms::joins::Service>("rooms::joins"),
 				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 				user: args.depend::<rooms::user::Service>("rooms::user"),
@@ -91,12 +114,17 @@ impl crate::Service for Service {
 				presence: args.depend::<presence::Service>("presence"),
 				read_receipt: args.depend::<rooms::read_receipt::Service>("rooms::read_receipt"),
 				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
-				account_data: args.depend::<account_data::Service>("account_data"),
 				appservice: args.depend::<crate::appservice::Service>("appservice"),
 				pusher: args.depend::<pusher::Service>("pusher"),
 				server_keys: args.depend::<server_keys::Service>("server_keys"),
+				policy: args.depend::<moderation::policy::Service>("moderation::policy"),
 			},
 			channels: (0..num_senders).map(|_| loole::unbounded()).collect(),
+			signed_requests: Mutex::new(LruCache::new(usize_from_f64(
+				signed_request_cache_capacity,
+			)?)),
+			limits: breaker::Limits::new(config.federation_max_concurrent_requests),
+			room_edus: RwLock::default(),
 		}))
 	}
 
@@ -134,6 +162,19 @@ impl crate::Service for Service {
 		}
 	}
 
+	fn queue_len(&self) -> Option<usize> {
+		Some(self.channels.iter().map(|(sender, _)| sender.len()).sum())
+	}
+
+	fn memory_usage(&self, out: &mut dyn Write) -> Result {
+		let signed_requests = self.signed_requests.lock()?.len();
+		writeln!(out, "signed_requests: {signed_requests}")?;
+
+		Ok(())
+	}
+
+	fn clear_cache(&self) { self.signed_requests.lock().expect("locked").clear(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -143,12 +184,8 @@ impl Service {
 		let dest = Destination::Push(user.to_owned(), pushkey);
 		let event = SendingEvent::Pdu(*pdu_id);
 		let _cork = self.db.db.cork();
-		let keys = self.db.queue_requests(once((&event, &dest)));
-		self.dispatch(Msg {
-			dest,
-			event,
-			queue_id: keys.into_iter().next().expect("request queue key"),
-		})
+		self.db.queue_requests(once((&event, &dest)));
+		self.dispatch(Msg { dest })
 	}
 
 	#[tracing::instrument(skip(self), level = "debug")]
@@ -156,23 +193,33 @@ impl Service {
 		let dest = Destination::Appservice(appservice_id);
 		let event = SendingEvent::Pdu(pdu_id);
 		let _cork = self.db.db.cork();
-		let keys = self.db.queue_requests(once((&event, &dest)));
-		self.dispatch(Msg {
-			dest,
-			event,
-			queue_id: keys.into_iter().next().expect("request queue key"),
-		})
+		self.db.queue_requests(once((&event, &dest)));
+		self.dispatch(Msg { dest })
 	}
 
 	#[tracing::instrument(skip(self, room_id, pdu_id), level = "debug")]
 	pub async fn send_pdu_room(&self, room_id: &RoomId, pdu_id: &RawPduId) -> Result {
-		let servers = self
+		let mut servers: Vec<OwnedServerName> = self
 			.services
 			.state_cache
 			.room_servers(room_id)
-			.ready_filter(|server_name| !self.services.globals.server_is_ours(server_name));
+			.ready_filter(|server_name| !self.services.globals.server_is_ours(server_name))
+			.map(ToOwned::to_owned)
+			.collect()
+			.await;
+
+		// The members of a partial-state room are mostly unknown, so are the
+		// servers in it but those the resident server listed.
+		if let Some(partial) = self.services.joins.partial_state(room_id).await {
+			for server in partial.servers_in_room {
+				if !servers.contains(&server) && !self.services.globals.server_is_ours(&server) {
+					servers.push(server);
+				}
+			}
+		}
 
-		self.send_pdu_servers(servers, pdu_id).await
+		self.send_pdu_servers(servers.iter().map(AsRef::as_ref).stream(), pdu_id)
+			.await
 	}
 
 	#[tracing::instrument(skip(self, servers, pdu_id), level = "debug")]
@@ -188,10 +235,10 @@ impl Service {
 			.collect::<Vec<_>>()
 			.await;
 
-		let keys = self.db.queue_requests(requests.iter().map(|(o, e)| (e, o)));
+		self.db.queue_requests(requests.iter().map(|(o, e)| (e, o)));
 
-		for ((dest, event), queue_id) in requests.into_iter().zip(keys) {
-			self.dispatch(Msg { dest, event, queue_id })?;
+		for (dest, _) in requests {
+			self.dispatch(Msg { dest })?;
 		}
 
 		Ok(())
@@ -202,12 +249,8 @@ impl Service {
 		let dest = Destination::Federation(server.to_owned());
 		let event = SendingEvent::Edu(serialized);
 		let _cork = self.db.db.cork();
-		let keys = self.db.queue_requests(once((&event, &dest)));
-		self.dispatch(Msg {
-			dest,
-			event,
-			queue_id: keys.into_iter().next().expect("request queue key"),
-		})
+		self.db.queue_requests(once((&event, &dest)));
+		self.dispatch(Msg { dest })
 	}
 
 	#[tracing::instrument(skip(self, room_id, serialized), level = "debug")]
@@ -237,10 +280,10 @@ impl Service {
 			.collect::<Vec<_>>()
 			.await;
 
-		let keys = self.db.queue_requests(requests.iter().map(|(o, e)| (e, o)));
+		self.db.queue_requests(requests.iter().map(|(o, e)| (e, o)));
 
-		for ((dest, event), queue_id) in requests.into_iter().zip(keys) {
-			self.dispatch(Msg { dest, event, queue_id })?;
+		for (dest, _) in requests {
+			self.dispatch(Msg { dest })?;
 		}
 
 		Ok(())
@@ -266,16 +309,18 @@ impl Service {
 			.map(ToOwned::to_owned)
 			.map(Destination::Federation)
 			.map(Ok)
-			.ready_try_for_each(|dest| {
-				self.dispatch(Msg {
-					dest,
-					event: SendingEvent::Flush,
-					queue_id: Vec::<u8>::new(),
-				})
-			})
+			.ready_try_for_each(|dest| self.dispatch(Msg { dest }))
 			.await
 	}
 
+	/// Drop the events queued and in flight for the server; they are never
+	/// sent.
+	#[tracing::instrument(skip(self), level = "debug")]
+	pub async fn drop_queue(&self, server: &ServerName) {
+		let dest = Destination::Federation(server.to_owned());
+		self.db.delete_all_requests_for(&dest).await;
+	}
+
 	/// Sends a request to a federation server
 	#[tracing::instrument(skip_all, name = "request", level = "debug")]
 	pub async fn send_federation_request<T>(
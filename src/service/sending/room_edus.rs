@@ -0,0 +1,178 @@
+//! Overrides of `allow_outgoing_presence`, `allow_outgoing_read_receipts` and
+//! `allow_outgoing_typing` for single rooms, e.g. to not federate typing in an
+//! enormous room where it's pure overhead. They're kept in the room account
+//! data of the server user, so they can be set by admin command or by a client
+//! logged in as the server user. A room without an override follows the
+//! config. The overrides of all rooms are cached once first needed, and kept
+//! up to date as they're set.
+
+use std::collections::HashMap;
+
+use conduwuit::{implement, Result};
+use futures::StreamExt;
+use ruma::{events::RoomAccountDataEventType, OwnedRoomId, RoomId, ServerName, UserId};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// The overrides of the rooms which have any.
+pub(super) type RoomEdusCache = HashMap<OwnedRoomId, RoomEdus>;
+
+/// The type of the room account data holding the overrides.
+pub const ROOM_EDUS_EVENT_TYPE: &str = "org.conduwuit.federation_edus";
+
+/// Which EDUs of a room are federated, where set; the config decides the
+/// others.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+pub struct RoomEdus {
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub presence: Option<bool>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub receipts: Option<bool>,
+
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub typing: Option<bool>,
+}
+
+#[derive(Deserialize)]
+struct Content {
+	content: RoomEdus,
+}
+
+/// The overrides of the room; none are set when it has none.
+#[implement(super::Service)]
+pub async fn room_edus(&self, room_id: &RoomId) -> RoomEdus {
+	self.with_room_edus(|cache| cache.get(room_id).copied())
+		.await
+		.unwrap_or_default()
+}
+
+/// Whether the overrides of any room match the predicate.
+#[implement(super::Service)]
+async fn any_room_edus<F>(&self, predicate: F) -> bool
+where
+	F: Fn(&RoomEdus) -> bool,
+{
+	self.with_room_edus(|cache| cache.values().any(predicate))
+		.await
+}
+
+/// Call the function with the cached overrides, loading them first if they
+/// are not yet.
+#[implement(super::Service)]
+async fn with_room_edus<F, T>(&self, f: F) -> T
+where
+	F: FnOnce(&RoomEdusCache) -> T,
+{
+	if let Some(cache) = self.room_edus.read().expect("locked for reading").as_ref() {
+		return f(cache);
+	}
+
+	let server_user = &self.services.globals.server_user;
+	let loaded: RoomEdusCache = self
+		.services
+		.account_data
+		.rooms_with(server_user, ROOM_EDUS_EVENT_TYPE)
+		.then(|room_id| async move {
+			let edus = self
+				.services
+				.account_data
+				.get_room::<Content>(&room_id, server_user, room_edus_type())
+				.await
+				.map(|Content { content }| content)
+				.unwrap_or_default();
+
+			(room_id, edus)
+		})
+		.collect()
+		.await;
+
+	let mut cache = self.room_edus.write().expect("locked for writing");
+	f(cache.get_or_insert(loaded))
+}
+
+/// Update the cached overrides of the room with the content of the room
+/// account data just set.
+#[implement(super::Service)]
+pub fn set_cached_room_edus(&self, room_id: &RoomId, content: &serde_json::Value) {
+	let edus = RoomEdus::deserialize(content).unwrap_or_default();
+	if let Some(cache) = self.room_edus.write().expect("locked for writing").as_mut() {
+		cache.insert(room_id.to_owned(), edus);
+	}
+}
+
+/// Replace the overrides of the room.
+#[implement(super::Service)]
+pub async fn set_room_edus(&self, room_id: &RoomId, edus: RoomEdus) -> Result {
+	let data = json!({
+		"type": ROOM_EDUS_EVENT_TYPE,
+		"content": edus,
+	});
+
+	self.services
+		.account_data
+		.update(Some(room_id), &self.services.globals.server_user, room_edus_type(), &data)
+		.await
+}
+
+/// Whether presence is federated through the room.
+#[implement(super::Service)]
+pub async fn allow_outgoing_presence(&self, room_id: &RoomId) -> bool {
+	let edus = self.room_edus(room_id).await;
+	edus.presence
+		.unwrap_or(self.server.config.allow_outgoing_presence)
+}
+
+/// Whether the read receipts of the room are federated.
+#[implement(super::Service)]
+pub async fn allow_outgoing_read_receipts(&self, room_id: &RoomId) -> bool {
+	let edus = self.room_edus(room_id).await;
+	edus.receipts
+		.unwrap_or(self.server.config.allow_outgoing_read_receipts)
+}
+
+/// Whether typing in the room is federated.
+#[implement(super::Service)]
+pub async fn allow_outgoing_typing(&self, room_id: &RoomId) -> bool {
+	let edus = self.room_edus(room_id).await;
+	edus.typing
+		.unwrap_or(self.server.config.allow_outgoing_typing)
+}
+
+/// Whether the read receipts of any room are federated, so the rooms of the
+/// server have to be looked through.
+#[implement(super::Service)]
+pub(super) async fn any_outgoing_read_receipts(&self) -> bool {
+	self.server.config.allow_outgoing_read_receipts
+		|| self.any_room_edus(|edus| edus.receipts == Some(true)).await
+}
+
+/// Whether the server shares a room with the user through which presence is
+/// federated.
+#[implement(super::Service)]
+pub(super) async fn server_sees_presence(&self, server: &ServerName, user_id: &UserId) -> bool {
+	// Without overrides differing from the config it decides for every room.
+	let allow = self.server.config.allow_outgoing_presence;
+	if !self
+		.any_room_edus(|edus| edus.presence.is_some_and(|presence| presence != allow))
+		.await
+	{
+		return allow
+			&& self
+				.services
+				.state_cache
+				.server_sees_user(server, user_id)
+				.await;
+	}
+
+	self.services
+		.state_cache
+		.server_rooms(server)
+		.any(|room_id| async move {
+			self.services.state_cache.is_joined(user_id, room_id).await
+				&& self.allow_outgoing_presence(room_id).await
+		})
+		.await
+}
+
+fn room_edus_type() -> RoomAccountDataEventType { ROOM_EDUS_EVENT_TYPE.into() }
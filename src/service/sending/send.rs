@@ -1,26 +1,33 @@
-use std::mem;
+use std::{mem, time::Duration};
 
 use bytes::Bytes;
 use conduwuit::{
 	debug, debug_error, debug_warn, err, error::inspect_debug_log, implement, trace,
 	utils::string::EMPTY, Err, Error, Result,
 };
-use http::{header::AUTHORIZATION, HeaderValue};
+use http::{
+	header::{AUTHORIZATION, RETRY_AFTER},
+	HeaderMap, HeaderValue, StatusCode,
+};
 use ipaddress::IPAddress;
 use reqwest::{Client, Method, Request, Response, Url};
 use ruma::{
 	api::{
-		client::error::Error as RumaError, EndpointError, IncomingResponse, MatrixVersion,
-		OutgoingRequest, SendAccessToken,
+		client::error::{Error as RumaError, ErrorBody, ErrorKind, RetryAfter},
+		EndpointError, IncomingResponse, MatrixVersion, OutgoingRequest, SendAccessToken,
 	},
 	serde::Base64,
 	server_util::authorization::XMatrix,
 	CanonicalJsonObject, CanonicalJsonValue, ServerName, ServerSigningKeyId,
 };
+use sha2::{Digest, Sha256};
 
 use crate::{
 	resolver,
-	resolver::{actual::ActualDest, cache::CachedDest},
+	resolver::{
+		actual::ActualDest,
+		cache::{CachedDest, Usage},
+	},
 };
 
 impl super::Service {
@@ -46,16 +53,29 @@ impl super::Service {
 			.config
 			.forbidden_remote_server_names
 			.contains(dest)
+			|| self.services.policy.is_server_denied(dest)
 		{
 			return Err!(Request(Forbidden(debug_warn!(
 				"Federation with {dest} is not allowed."
 			))));
 		}
 
+		if self.is_dead(dest).await {
+			return Err!(BadServerResponse(debug_warn!(
+				"{dest} failed too many transactions; nothing is sent to it until its backoff \
+				 expires."
+			)));
+		}
+
+		self.check_breaker(dest)?;
 		let actual = self.services.resolver.get_actual_dest(dest).await?;
 		let request = into_http_request::<T>(&actual, request)?;
 		let request = self.prepare(dest, request)?;
-		self.execute::<T>(dest, &actual, request, client).await
+		let _permits = self.permits(dest).await;
+		let result = self.execute::<T>(dest, &actual, request, client).await;
+		self.record_breaker(dest, &result);
+
+		result
 	}
 
 	async fn execute<T>(
@@ -72,19 +92,19 @@ impl super::Service {
 		let method = request.method().clone();
 
 		debug!(?method, ?url, "Sending request");
+		let resolver = &self.services.resolver;
 		match client.execute(request).await {
-			| Ok(response) =>
-				handle_response::<T>(
-					&self.services.resolver,
-					dest,
-					actual,
-					&method,
-					&url,
-					response,
-				)
-				.await,
-			| Err(error) =>
-				Err(handle_error(actual, &method, &url, error).expect_err("always returns error")),
+			| Ok(response) => {
+				let result =
+					handle_response::<T>(resolver, dest, actual, &method, &url, response).await;
+
+				resolver.record_destination_use(dest, &actual.dest.hostname(), true);
+				result
+			},
+			| Err(error) => {
+				resolver.record_destination_use(dest, &actual.dest.hostname(), false);
+				Err(handle_error(actual, &method, &url, error).expect_err("always returns error"))
+			},
 		}
 	}
 
@@ -128,7 +148,8 @@ where
 		resolver.set_cached_destination(dest.to_owned(), CachedDest {
 			dest: actual.dest.clone(),
 			host: actual.host.clone(),
-			expire: CachedDest::default_expire(),
+			expire: actual.expire,
+			usage: Usage::new(),
 		});
 	}
 
@@ -175,6 +196,12 @@ async fn into_http_response(
 		.expect("reqwest body is valid http body");
 
 	debug!("Got {status:?} for {method} {url}");
+	if status == StatusCode::TOO_MANY_REQUESTS {
+		let retry_after = retry_after_header(http_response.headers());
+		let error = RumaError::from_http_response(http_response);
+		return Err(Error::Federation(dest.to_owned(), limit_exceeded(error, retry_after)));
+	}
+
 	if !status.is_success() {
 		return Err(Error::Federation(
 			dest.to_owned(),
@@ -185,6 +212,42 @@ async fn into_http_response(
 	Ok(http_response)
 }
 
+/// The delay of a `Retry-After` header in seconds; HTTP dates are ignored.
+fn retry_after_header(headers: &HeaderMap) -> Option<Duration> {
+	headers
+		.get(RETRY_AFTER)?
+		.to_str()
+		.ok()?
+		.trim()
+		.parse()
+		.ok()
+		.map(Duration::from_secs)
+}
+
+/// Make the error of a 429 response `M_LIMIT_EXCEEDED`, whatever its body, and
+/// take the delay of the `Retry-After` header when the body has none.
+fn limit_exceeded(mut error: RumaError, header: Option<Duration>) -> RumaError {
+	match &mut error.body {
+		| ErrorBody::Standard {
+			kind: ErrorKind::LimitExceeded { retry_after },
+			..
+		} =>
+			if retry_after.is_none() {
+				*retry_after = header.map(RetryAfter::Delay);
+			},
+		| body => {
+			*body = ErrorBody::Standard {
+				kind: ErrorKind::LimitExceeded {
+					retry_after: header.map(RetryAfter::Delay),
+				},
+				message: "Too many requests".to_owned(),
+			};
+		},
+	}
+
+	error
+}
+
 fn handle_error(
 	actual: &ActualDest,
 	method: &Method,
@@ -210,19 +273,53 @@ fn handle_error(
 	Err(e.into())
 }
 
+/// Digest of everything signed for a request, identifying the Authorization
+/// header of identical (i.e. retried) requests.
+pub(super) type RequestDigest = [u8; 32];
+
 #[implement(super::Service)]
 fn sign_request(&self, http_request: &mut http::Request<Vec<u8>>, dest: &ServerName) {
+	let method = http_request.method().as_str();
+	let uri = http_request
+		.uri()
+		.path_and_query()
+		.expect("http::Request missing path_and_query")
+		.as_str();
+
+	// The signature is deterministic for the same request and signing key, so it
+	// is only computed once for requests sent again, e.g. retried transactions.
+	let digest = request_digest(dest, method, uri, http_request.body());
+	let cached = self
+		.signed_requests
+		.lock()
+		.expect("locked")
+		.get_mut(&digest)
+		.cloned();
+
+	let authorization = cached.unwrap_or_else(|| {
+		let authorization = self.authorization(dest, method, uri, http_request.body());
+		self.signed_requests
+			.lock()
+			.expect("locked")
+			.insert(digest, authorization.clone());
+
+		authorization
+	});
+
+	let authorization = http_request
+		.headers_mut()
+		.insert(AUTHORIZATION, authorization);
+
+	debug_assert!(authorization.is_none(), "Authorization header already present");
+}
+
+#[implement(super::Service)]
+fn authorization(&self, dest: &ServerName, method: &str, uri: &str, body: &[u8]) -> HeaderValue {
 	type Member = (String, Value);
 	type Value = CanonicalJsonValue;
 	type Object = CanonicalJsonObject;
 
 	let origin = self.services.globals.server_name();
-	let body = http_request.body();
-	let uri = http_request
-		.uri()
-		.path_and_query()
-		.expect("http::Request missing path_and_query");
-
 	let mut req: Object = if !body.is_empty() {
 		let content: CanonicalJsonValue =
 			serde_json::from_slice(body).expect("failed to serialize body");
@@ -230,18 +327,18 @@ fn sign_request(&self, http_request: &mut http::Request<Vec<u8>>, dest: &ServerN
 		let authorization: [Member; 5] = [
 			("content".into(), content),
 			("destination".into(), dest.as_str().into()),
-			("method".into(), http_request.method().as_str().into()),
+			("method".into(), method.into()),
 			("origin".into(), origin.as_str().into()),
-			("uri".into(), uri.to_string().into()),
+			("uri".into(), uri.into()),
 		];
 
 		authorization.into()
 	} else {
 		let authorization: [Member; 4] = [
 			("destination".into(), dest.as_str().into()),
-			("method".into(), http_request.method().as_str().into()),
+			("method".into(), method.into()),
 			("origin".into(), origin.as_str().into()),
-			("uri".into(), uri.to_string().into()),
+			("uri".into(), uri.into()),
 		];
 
 		authorization.into()
@@ -273,12 +370,18 @@ fn sign_request(&self, http_request: &mut http::Request<Vec<u8>>, dest: &ServerN
 		.expect("signature is valid base64");
 
 	let x_matrix = XMatrix::new(origin.into(), dest.into(), key.into(), sig);
-	let authorization = HeaderValue::from(&x_matrix);
-	let authorization = http_request
-		.headers_mut()
-		.insert(AUTHORIZATION, authorization);
+	HeaderValue::from(&x_matrix)
+}
 
-	debug_assert!(authorization.is_none(), "Authorization header already present");
+fn request_digest(dest: &ServerName, method: &str, uri: &str, body: &[u8]) -> RequestDigest {
+	let mut hasher = Sha256::new();
+	for part in [dest.as_str().as_bytes(), method.as_bytes(), uri.as_bytes(), body] {
+		// length-prefixed so the parts cannot run into each other
+		hasher.update(part.len().to_be_bytes());
+		hasher.update(part);
+	}
+
+	hasher.finalize().into()
 }
 
 fn into_http_request<T>(actual: &ActualDest, request: T) -> Result<http::Request<Vec<u8>>>
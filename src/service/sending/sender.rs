@@ -5,7 +5,7 @@ use std::{
 		atomic::{AtomicU64, AtomicUsize, Ordering},
 		Arc,
 	},
-	time::{Duration, Instant},
+	time::{Duration, Instant, SystemTime},
 };
 
 use base64::{engine::general_purpose, Engine as _};
@@ -16,15 +16,11 @@ use conduwuit::{
 	utils::{calculate_hash, continue_exponential_backoff_secs, ReadyExt},
 	warn, Error, Result,
 };
-use futures::{
-	future::{BoxFuture, OptionFuture},
-	join, pin_mut,
-	stream::FuturesUnordered,
-	FutureExt, StreamExt,
-};
+use futures::{future::BoxFuture, join, pin_mut, stream::FuturesUnordered, FutureExt, StreamExt};
 use ruma::{
 	api::{
 		appservice::event::push_events::v1::EphemeralData,
+		client::error::{ErrorBody, ErrorKind, RetryAfter},
 		federation::transactions::{
 			edu::{
 				DeviceListUpdateContent, Edu, PresenceContent, PresenceUpdate, ReceiptContent,
@@ -34,22 +30,22 @@ use ruma::{
 		},
 	},
 	device_id,
-	events::{
-		push_rules::PushRulesEvent, receipt::ReceiptType, AnySyncEphemeralRoomEvent,
-		GlobalAccountDataEventType,
-	},
-	push, uint, CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedServerName,
+	events::{receipt::ReceiptType, AnySyncEphemeralRoomEvent},
+	uint, CanonicalJsonObject, MilliSecondsSinceUnixEpoch, OwnedRoomId, OwnedServerName,
 	OwnedUserId, RoomId, RoomVersionId, ServerName, UInt,
 };
 use serde_json::value::{to_raw_value, RawValue as RawJsonValue};
 
-use super::{appservice, data::QueueItem, Destination, Msg, SendingEvent, Service};
+use super::{
+	appservice, coalesce::coalesce_edus, data::QueueItem, Destination, Msg, SendingEvent, Service,
+};
 
 #[derive(Debug)]
 enum TransactionStatus {
 	Running,
-	Failed(u32, Instant), // number of times failed, time of last failure
-	Retrying(u32),        // number of times failed
+	Failed(u32, Instant),      // number of times failed, time of last failure
+	Retrying(u32),             // number of times failed
+	RateLimited(u32, Instant), // number of times failed, time to retry after
 }
 
 type SendingError = (Destination, Error);
@@ -63,7 +59,6 @@ const CLEANUP_TIMEOUT_MS: u64 = 3500;
 const SELECT_PRESENCE_LIMIT: usize = 256;
 const SELECT_RECEIPT_LIMIT: usize = 256;
 const SELECT_EDU_LIMIT: usize = EDU_LIMIT - 2;
-const DEQUEUE_LIMIT: usize = 48;
 
 pub const PDU_LIMIT: usize = 50;
 pub const EDU_LIMIT: usize = 100;
@@ -130,18 +125,42 @@ impl Service {
 	) {
 		match response {
 			| Ok(dest) => self.handle_response_ok(&dest, futures, statuses).await,
-			| Err((dest, e)) => Self::handle_response_err(dest, statuses, &e),
+			| Err((dest, e)) => self.handle_response_err(dest, statuses, &e).await,
 		};
 	}
 
-	fn handle_response_err(dest: Destination, statuses: &mut CurTransactionStatus, e: &Error) {
+	async fn handle_response_err(
+		&self,
+		dest: Destination,
+		statuses: &mut CurTransactionStatus,
+		e: &Error,
+	) {
 		debug!(dest = ?dest, "{e:?}");
+
+		// A server rate-limiting us is retried once it says so, not counted as
+		// failing; the backoff limit still caps how long that may be.
+		let rate_limited = retry_after(e).and_then(|retry_after| {
+			let max = Duration::from_secs(self.server.config.sender_retry_backoff_limit);
+			Instant::now().checked_add(retry_after.min(max))
+		});
+
+		if let Destination::Federation(server) = &dest {
+			if rate_limited.is_none() {
+				self.record_failure(server, e).await;
+			}
+		}
+
 		statuses.entry(dest).and_modify(|e| {
-			*e = match e {
-				| TransactionStatus::Running => TransactionStatus::Failed(1, Instant::now()),
-				| TransactionStatus::Retrying(ref n) =>
+			*e = match (&*e, rate_limited) {
+				| (TransactionStatus::Running, Some(until)) =>
+					TransactionStatus::RateLimited(0, until),
+				| (TransactionStatus::Retrying(n), Some(until)) =>
+					TransactionStatus::RateLimited(*n, until),
+				| (TransactionStatus::Running, None) =>
+					TransactionStatus::Failed(1, Instant::now()),
+				| (TransactionStatus::Retrying(n), None) =>
 					TransactionStatus::Failed(n.saturating_add(1), Instant::now()),
-				| TransactionStatus::Failed(..) => {
+				| (TransactionStatus::Failed(..) | TransactionStatus::RateLimited(..), _) => {
 					panic!("Request that was not even running failed?!")
 				},
 			}
@@ -157,21 +176,14 @@ impl Service {
 	) {
 		let _cork = self.db.db.cork();
 		self.db.delete_all_active_requests_for(dest).await;
+		if let Destination::Federation(server) = dest {
+			self.record_success(server).await;
+		}
 
-		// Find events that have been added since starting the last request
-		let new_events = self
-			.db
-			.queued_requests(dest)
-			.take(DEQUEUE_LIMIT)
-			.collect::<Vec<_>>()
-			.await;
-
-		// Insert any pdus we found
+		// Send what has been queued since starting the last request
+		let new_events = self.compose_transaction(dest).await;
 		if !new_events.is_empty() {
-			self.db.mark_as_active(new_events.iter());
-
-			let new_events_vec = new_events.into_iter().map(|(_, event)| event).collect();
-			futures.push(self.send_events(dest.clone(), new_events_vec));
+			futures.push(self.send_events(dest.clone(), new_events));
 		} else {
 			statuses.remove(dest);
 		}
@@ -185,8 +197,7 @@ impl Service {
 		futures: &mut SendingFutures<'a>,
 		statuses: &mut CurTransactionStatus,
 	) {
-		let iv = vec![(msg.queue_id, msg.event)];
-		if let Ok(Some(events)) = self.select_events(&msg.dest, iv, statuses).await {
+		if let Ok(Some(events)) = self.select_events(&msg.dest, statuses).await {
 			if !events.is_empty() {
 				futures.push(self.send_events(msg.dest, events));
 			} else {
@@ -256,6 +267,14 @@ impl Service {
 		}
 
 		for (dest, events) in txns {
+			// Servers failing before the restart are retried once their backoff expires
+			if let Destination::Federation(server) = &dest {
+				if self.is_backing_off(server).await {
+					statuses.insert(dest.clone(), TransactionStatus::Failed(0, Instant::now()));
+					continue;
+				}
+			}
+
 			if self.server.config.startup_netburst && !events.is_empty() {
 				statuses.insert(dest.clone(), TransactionStatus::Running);
 				futures.push(self.send_events(dest.clone(), events));
@@ -267,74 +286,129 @@ impl Service {
 		name = "select",,
 		level = "debug",
 		skip_all,
-		fields(
-			?dest,
-			new_events = %new_events.len(),
-		)
+		fields(?dest)
 	)]
 	async fn select_events(
 		&self,
 		dest: &Destination,
-		new_events: Vec<QueueItem>, // Events we want to send: event and full key
 		statuses: &mut CurTransactionStatus,
 	) -> Result<Option<Vec<SendingEvent>>> {
-		let (allow, retry) = self.select_events_current(dest, statuses)?;
+		let backing_off = match dest {
+			| Destination::Federation(server) => self.is_backing_off(server).await,
+			| _ => false,
+		};
+
+		let (allow, retry) = self.select_events_current(dest, backing_off, statuses)?;
 
 		// Nothing can be done for this remote, bail out.
 		if !allow {
 			return Ok(None);
 		}
 
-		let _cork = self.db.db.cork();
-		let mut events = Vec::new();
-
 		// Must retry any previous transaction for this remote.
 		if retry {
-			self.db
+			let events = self
+				.db
 				.active_requests_for(dest)
-				.ready_for_each(|(_, e)| events.push(e))
+				.map(|(_, e)| e)
+				.collect()
 				.await;
 
 			return Ok(Some(events));
 		}
 
-		// Compose the next transaction
-		let _cork = self.db.db.cork();
-		if !new_events.is_empty() {
-			self.db.mark_as_active(new_events.iter());
-			for (_, e) in new_events {
-				events.push(e);
-			}
-		}
+		Ok(Some(self.compose_transaction(dest).await))
+	}
 
-		// Add EDU's into the transaction
+	/// Compose the next transaction: the EDUs of the server and then the events
+	/// queued, in their order, as many as fit in one.
+	async fn compose_transaction(&self, dest: &Destination) -> Vec<SendingEvent> {
+		let _cork = self.db.db.cork();
+		let mut edus = Vec::new();
 		if let Destination::Federation(server_name) = dest {
 			if let Ok((select_edus, last_count)) = self.select_edus(server_name).await {
 				debug_assert!(select_edus.len() <= EDU_LIMIT, "exceeded edus limit");
-				events.extend(select_edus.into_iter().map(SendingEvent::Edu));
+				edus = select_edus;
 				self.db.set_latest_educount(server_name, last_count);
 			}
 		}
 
-		Ok(Some(events))
+		let queued = self
+			.select_queued(dest, EDU_LIMIT.saturating_sub(edus.len()))
+			.await;
+
+		self.db.mark_as_active(queued.iter());
+		queued
+			.into_iter()
+			.map(|(_, e)| e)
+			.chain(edus.into_iter().map(SendingEvent::Edu))
+			.collect()
+	}
+
+	/// The queued events of the next transaction, up to `PDU_LIMIT` PDUs and
+	/// the EDUs left room for.
+	async fn select_queued(&self, dest: &Destination, edu_limit: usize) -> Vec<QueueItem> {
+		let (mut pdus, mut edus) = (0_usize, 0_usize);
+		let mut events = Vec::new();
+		let queued = self.db.queued_requests(dest);
+
+		pin_mut!(queued);
+		while let Some((key, event)) = queued.next().await {
+			let (count, limit) = match &event {
+				| SendingEvent::Pdu(_) => (&mut pdus, PDU_LIMIT),
+				| SendingEvent::Edu(_) => (&mut edus, edu_limit),
+				| SendingEvent::Flush => continue,
+			};
+
+			if *count >= limit {
+				break;
+			}
+
+			*count = count.saturating_add(1);
+			events.push((key, event));
+		}
+
+		events
 	}
 
 	fn select_events_current(
 		&self,
 		dest: &Destination,
+		backing_off: bool,
 		statuses: &mut CurTransactionStatus,
 	) -> Result<(bool, bool)> {
+		// A server which failed before a restart waits for its backoff the same
+		if backing_off && !statuses.contains_key(dest) {
+			statuses.insert(dest.clone(), TransactionStatus::Failed(0, Instant::now()));
+			return Ok((false, false));
+		}
+
 		let (mut allow, mut retry) = (true, false);
 		statuses
 			.entry(dest.clone()) // TODO: can we avoid cloning?
 			.and_modify(|e| match e {
 				TransactionStatus::Failed(tries, time) => {
-					// Fail if a request has failed recently (exponential backoff)
+					// Fail if a request has failed recently (exponential backoff); that of
+					// servers is persisted
 					let min = self.server.config.sender_timeout;
 					let max = self.server.config.sender_retry_backoff_limit;
-					if continue_exponential_backoff_secs(min, max, time.elapsed(), *tries)
-						&& !matches!(dest, Destination::Appservice(_))
-					{
+					let backoff = match dest {
+						| Destination::Appservice(_) => false,
+						| Destination::Federation(_) => backing_off,
+						| Destination::Push(..) =>
+							continue_exponential_backoff_secs(min, max, time.elapsed(), *tries),
+					};
+
+					if backoff {
+						allow = false;
+					} else {
+						retry = true;
+						*e = TransactionStatus::Retrying(*tries);
+					}
+				},
+				TransactionStatus::RateLimited(tries, until) => {
+					// Fail until the server asked us to retry
+					if Instant::now() < *until {
 						allow = false;
 					} else {
 						retry = true;
@@ -364,31 +438,28 @@ impl Service {
 
 		let events_len = AtomicUsize::default();
 		let max_edu_count = AtomicU64::new(since);
+		let min_unsent = AtomicU64::new(u64::MAX);
 
 		let device_changes =
 			self.select_edus_device_changes(server_name, batch, &max_edu_count, &events_len);
 
-		let receipts: OptionFuture<_> = self
-			.server
-			.config
-			.allow_outgoing_read_receipts
-			.then(|| self.select_edus_receipts(server_name, batch, &max_edu_count))
-			.into();
-
-		let presence: OptionFuture<_> = self
-			.server
-			.config
-			.allow_outgoing_presence
-			.then(|| self.select_edus_presence(server_name, batch, &max_edu_count))
-			.into();
+		// Rooms may allow what the config doesn't; these check each room
+		let receipts = self.select_edus_receipts(server_name, batch, &max_edu_count, &min_unsent);
+		let presence = self.select_edus_presence(server_name, batch, &max_edu_count);
 
 		let (device_changes, receipts, presence) = join!(device_changes, receipts, presence);
 
 		let mut events = device_changes;
-		events.extend(presence.into_iter().flatten());
-		events.extend(receipts.into_iter().flatten());
+		events.extend(presence);
+		events.extend(receipts);
+
+		// The next transaction starts from the first receipt left out of this one
+		let max_edu_count = max_edu_count
+			.load(Ordering::Acquire)
+			.min(min_unsent.load(Ordering::Acquire).saturating_sub(1))
+			.max(since);
 
-		Ok((events, max_edu_count.load(Ordering::Acquire)))
+		Ok((events, max_edu_count))
 	}
 
 	/// Look for device changes
@@ -452,26 +523,55 @@ impl Service {
 		events
 	}
 
-	/// Look for read receipts in this room
+	/// Look for the read receipts of the rooms the server is in; those of all
+	/// rooms are aggregated in one `m.receipt` EDU, up to
+	/// `SELECT_RECEIPT_LIMIT` of them. The count of the first receipt left out
+	/// is kept in `min_unsent`.
 	#[tracing::instrument(
 		name = "receipts",
 		level = "trace",
-		skip(self, server_name, max_edu_count)
+		skip(self, server_name, max_edu_count, min_unsent)
 	)]
 	async fn select_edus_receipts(
 		&self,
 		server_name: &ServerName,
 		since: (u64, u64),
 		max_edu_count: &AtomicU64,
+		min_unsent: &AtomicU64,
 	) -> Option<Vec<u8>> {
+		if !self.any_outgoing_read_receipts().await {
+			return None;
+		}
+
 		let server_rooms = self.services.state_cache.server_rooms(server_name);
 
 		pin_mut!(server_rooms);
 		let mut num = 0;
 		let mut receipts = BTreeMap::<OwnedRoomId, ReceiptMap>::new();
 		while let Some(room_id) = server_rooms.next().await {
+			if !self.allow_outgoing_read_receipts(room_id).await {
+				continue;
+			}
+
+			// The receipts of the rooms left are sent with the next transaction
+			if num >= SELECT_RECEIPT_LIMIT {
+				let left = self
+					.services
+					.read_receipt
+					.readreceipts_since(room_id, since.0);
+
+				pin_mut!(left);
+				if let Some((_, count, _)) = left.next().await {
+					if count <= since.1 {
+						min_unsent.fetch_min(count, Ordering::Relaxed);
+					}
+				}
+
+				continue;
+			}
+
 			let receipt_map = self
-				.select_edus_receipts_room(room_id, since, max_edu_count, &mut num)
+				.select_edus_receipts_room(room_id, since, max_edu_count, min_unsent, &mut num)
 				.await;
 
 			if !receipt_map.read.is_empty() {
@@ -492,12 +592,17 @@ impl Service {
 	}
 
 	/// Look for read receipts in this room
-	#[tracing::instrument(name = "receipts", level = "trace", skip(self, since, max_edu_count))]
+	#[tracing::instrument(
+		name = "receipts",
+		level = "trace",
+		skip(self, since, max_edu_count, min_unsent)
+	)]
 	async fn select_edus_receipts_room(
 		&self,
 		room_id: &RoomId,
 		since: (u64, u64),
 		max_edu_count: &AtomicU64,
+		min_unsent: &AtomicU64,
 		num: &mut usize,
 	) -> ReceiptMap {
 		let receipts = self
@@ -512,6 +617,13 @@ impl Service {
 				break;
 			}
 
+			// One receipt per user fits in an EDU; those of their other threads are
+			// left for the next one
+			if read.contains_key(user_id) {
+				min_unsent.fetch_min(count, Ordering::Relaxed);
+				break;
+			}
+
 			max_edu_count.fetch_max(count, Ordering::Relaxed);
 			if !self.services.globals.user_is_local(user_id) {
 				continue;
@@ -548,6 +660,7 @@ impl Service {
 			if read.insert(user_id.to_owned(), receipt_data).is_none() {
 				*num = num.saturating_add(1);
 				if *num >= SELECT_RECEIPT_LIMIT {
+					min_unsent.fetch_min(count.saturating_add(1), Ordering::Relaxed);
 					break;
 				}
 			}
@@ -582,12 +695,7 @@ impl Service {
 				continue;
 			}
 
-			if !self
-				.services
-				.state_cache
-				.server_sees_user(server_name, user_id)
-				.await
-			{
+			if !self.server_sees_presence(server_name, user_id).await {
 				continue;
 			}
 
@@ -767,15 +875,7 @@ impl Service {
 				continue;
 			}
 
-			let rules_for_user = self
-				.services
-				.account_data
-				.get_global(&user_id, GlobalAccountDataEventType::PushRules)
-				.await
-				.map_or_else(
-					|_| push::Ruleset::server_default(&user_id),
-					|ev: PushRulesEvent| ev.content.global,
-				);
+			let rules_for_user = self.services.pusher.rules(&user_id).await;
 
 			let unread: UInt = self
 				.services
@@ -788,7 +888,7 @@ impl Service {
 			let _response = self
 				.services
 				.pusher
-				.send_push_notice(&user_id, unread, &pusher, rules_for_user, &pdu)
+				.send_push_notice(&user_id, unread, &pusher, &rules_for_user, &pdu)
 				.await
 				.map_err(|e| (Destination::Push(user_id.clone(), pushkey.clone()), e));
 		}
@@ -815,29 +915,21 @@ impl Service {
 				.filter(|event| matches!(event, SendingEvent::Pdu(_)))
 				.count(),
 		);
-		let mut edu_jsons = Vec::with_capacity(
-			events
-				.iter()
-				.filter(|event| matches!(event, SendingEvent::Edu(_)))
-				.count(),
-		);
 
 		for event in &events {
-			match event {
-				// TODO: check room version and remove event_id if needed
-				| SendingEvent::Pdu(pdu_id) => {
-					if let Ok(pdu) = self.services.timeline.get_pdu_json_from_id(pdu_id).await {
-						pdu_jsons.push(self.convert_to_outgoing_federation_event(pdu).await);
-					}
-				},
-				| SendingEvent::Edu(edu) =>
-					if let Ok(raw) = serde_json::from_slice(edu) {
-						edu_jsons.push(raw);
-					},
-				| SendingEvent::Flush => {}, // flush only; no new content
+			// TODO: check room version and remove event_id if needed
+			if let SendingEvent::Pdu(pdu_id) = event {
+				if let Ok(pdu) = self.services.timeline.get_pdu_json_from_id(pdu_id).await {
+					pdu_jsons.push(self.convert_to_outgoing_federation_event(pdu).await);
+				}
 			}
 		}
 
+		let edu_jsons = coalesce_edus(events.iter().filter_map(|event| match event {
+			| SendingEvent::Edu(edu) => Some(edu.as_slice()),
+			| _ => None,
+		}));
+
 		//debug_assert!(pdu_jsons.len() + edu_jsons.len() > 0, "sending empty
 		// transaction");
 
@@ -911,3 +1003,25 @@ impl Service {
 		to_raw_value(&pdu_json).expect("CanonicalJson is valid serde_json::Value")
 	}
 }
+
+/// How long a server answering `M_LIMIT_EXCEEDED` asked us to wait before
+/// retrying, if it did.
+fn retry_after(e: &Error) -> Option<Duration> {
+	let Error::Federation(_, error) = e else {
+		return None;
+	};
+
+	let ErrorBody::Standard {
+		kind: ErrorKind::LimitExceeded { retry_after: Some(retry_after) },
+		..
+	} = &error.body
+	else {
+		return None;
+	};
+
+	match retry_after {
+		| RetryAfter::Delay(delay) => Some(*delay),
+		| RetryAfter::DateTime(time) =>
+			Some(time.duration_since(SystemTime::now()).unwrap_or_default()),
+	}
+}
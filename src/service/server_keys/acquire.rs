@@ -15,7 +15,7 @@ use ruma::{
 use serde_json::value::RawValue as RawJsonValue;
 use tokio::time::{timeout_at, Instant};
 
-use super::key_exists;
+use super::{key_exists, FetchCounts};
 
 type Batch = BTreeMap<OwnedServerName, Vec<OwnedServerSigningKeyId>>;
 
@@ -73,7 +73,21 @@ where
 
 	info!("{missing_keys} keys for {missing_servers} servers will be acquired");
 
+	// Origins configured to be asked first are, and whatever they did not give is
+	// left to the trusted servers.
+	let (direct, rest): (Batch, Batch) = missing
+		.into_iter()
+		.partition(|(origin, _)| self.direct_first(origin));
+
+	missing = rest;
+	let mut direct_missing = if direct.is_empty() {
+		Batch::new()
+	} else {
+		self.acquire_origins(direct.into_iter()).await
+	};
+
 	if notary_first_always || notary_first_on_join {
+		missing.append(&mut direct_missing);
 		missing = self.acquire_notary(missing.into_iter()).await;
 		missing_keys = keys_count(&missing);
 		missing_servers = missing.len();
@@ -88,6 +102,7 @@ where
 
 	if !notary_only {
 		missing = self.acquire_origins(missing.into_iter()).await;
+		missing.append(&mut direct_missing);
 		missing_keys = keys_count(&missing);
 		missing_servers = missing.len();
 		if missing_keys == 0 {
@@ -97,6 +112,7 @@ where
 		debug_warn!("missing {missing_keys} keys for {missing_servers} servers unreachable");
 	}
 
+	missing.append(&mut direct_missing);
 	if !notary_first_always && !notary_first_on_join {
 		missing = self.acquire_notary(missing.into_iter()).await;
 		missing_keys = keys_count(&missing);
@@ -110,6 +126,9 @@ where
 		);
 	}
 
+	missing_keys = keys_count(&missing);
+	missing_servers = missing.len();
+	FetchCounts::add(&self.fetched.failed, missing_servers);
 	if missing_keys > 0 {
 		warn!(
 			"did not obtain {missing_keys} keys for {missing_servers} servers out of \
@@ -186,6 +205,9 @@ async fn acquire_origin(
 
 			self.add_signing_keys(server_keys.clone()).await;
 			key_ids.retain(|key_id| !key_exists(&server_keys, key_id));
+			if key_ids.is_empty() {
+				FetchCounts::add(&self.fetched.origin, 1);
+			}
 		},
 	}
 
@@ -198,9 +220,26 @@ where
 	I: Iterator<Item = (OwnedServerName, Vec<OwnedServerSigningKeyId>)> + Send,
 {
 	let mut missing: Batch = batch.collect();
+	let mut known = BTreeSet::new();
+	for server in missing.keys() {
+		if self.origin_known(server).await {
+			known.insert(server.clone());
+		}
+	}
+
 	for notary in self.services.globals.trusted_servers() {
+		let trusted: BTreeSet<OwnedServerName> = missing
+			.keys()
+			.filter(|server| self.notary_trusted_for(notary, server, known.contains(*server)))
+			.cloned()
+			.collect();
+
+		if trusted.is_empty() {
+			continue;
+		}
+
 		let missing_keys = keys_count(&missing);
-		let missing_servers = missing.len();
+		let missing_servers = trusted.len();
 		debug!(
 			"Asking notary {notary} for {missing_keys} missing keys from {missing_servers} \
 			 servers"
@@ -208,13 +247,17 @@ where
 
 		let batch = missing
 			.iter()
+			.filter(|(server, _)| trusted.contains(*server))
 			.map(|(server, keys)| (server.borrow(), keys.iter().map(Borrow::borrow)));
 
 		match self.batch_notary_request(notary, batch).await {
 			| Err(e) => error!("Failed to contact notary {notary:?}: {e}"),
 			| Ok(results) =>
 				for server_keys in results {
-					self.acquire_notary_result(&mut missing, server_keys).await;
+					// only keys of the origins the notary is trusted for are taken
+					if trusted.contains(&server_keys.server_name) {
+						self.acquire_notary_result(&mut missing, server_keys).await;
+					}
 				},
 		}
 	}
@@ -231,6 +274,7 @@ async fn acquire_notary_result(&self, missing: &mut Batch, server_keys: ServerSi
 		key_ids.retain(|key_id| key_exists(&server_keys, key_id));
 		if key_ids.is_empty() {
 			missing.remove(server);
+			FetchCounts::add(&self.fetched.notary, 1);
 		}
 	}
 }
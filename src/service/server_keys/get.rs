@@ -6,7 +6,7 @@ use ruma::{
 	ServerSigningKeyId,
 };
 
-use super::{extract_key, PubKeyMap, PubKeys};
+use super::{extract_key, FetchCounts, PubKeyMap, PubKeys};
 
 #[implement(super::Service)]
 pub async fn get_event_keys(
@@ -65,8 +65,11 @@ pub async fn get_verify_key(
 	origin: &ServerName,
 	key_id: &ServerSigningKeyId,
 ) -> Result<VerifyKey> {
-	let notary_first = self.services.server.config.query_trusted_key_servers_first;
-	let notary_only = self.services.server.config.only_query_trusted_key_servers;
+	// origins asked first are asked even when only trusted servers are otherwise
+	let direct_first = self.direct_first(origin);
+	let notary_first =
+		self.services.server.config.query_trusted_key_servers_first && !direct_first;
+	let notary_only = self.services.server.config.only_query_trusted_key_servers && !direct_first;
 
 	if let Some(result) = self.verify_keys_for(origin).await.remove(key_id) {
 		return Ok(result);
@@ -74,22 +77,26 @@ pub async fn get_verify_key(
 
 	if notary_first {
 		if let Ok(result) = self.get_verify_key_from_notaries(origin, key_id).await {
+			FetchCounts::add(&self.fetched.notary, 1);
 			return Ok(result);
 		}
 	}
 
 	if !notary_only {
 		if let Ok(result) = self.get_verify_key_from_origin(origin, key_id).await {
+			FetchCounts::add(&self.fetched.origin, 1);
 			return Ok(result);
 		}
 	}
 
 	if !notary_first {
 		if let Ok(result) = self.get_verify_key_from_notaries(origin, key_id).await {
+			FetchCounts::add(&self.fetched.notary, 1);
 			return Ok(result);
 		}
 	}
 
+	FetchCounts::add(&self.fetched.failed, 1);
 	Err!(BadServerResponse(debug_error!(
 		?key_id,
 		?origin,
@@ -103,7 +110,12 @@ async fn get_verify_key_from_notaries(
 	origin: &ServerName,
 	key_id: &ServerSigningKeyId,
 ) -> Result<VerifyKey> {
+	let known = self.origin_known(origin).await;
 	for notary in self.services.globals.trusted_servers() {
+		if !self.notary_trusted_for(notary, origin, known) {
+			continue;
+		}
+
 		if let Ok(server_keys) = self.notary_request(notary, origin).await {
 			for server_key in server_keys.clone() {
 				self.add_signing_keys(server_key).await;
@@ -1,12 +1,15 @@
 mod acquire;
 mod get;
 mod keypair;
+mod policy;
+mod refresh;
 mod request;
 mod sign;
 mod verify;
 
 use std::{collections::BTreeMap, sync::Arc, time::Duration};
 
+use async_trait::async_trait;
 use conduwuit::{
 	implement,
 	utils::{timepoint_from_now, IterStream},
@@ -22,13 +25,17 @@ use ruma::{
 	ServerName, ServerSigningKeyId,
 };
 use serde_json::value::RawValue as RawJsonValue;
+use tokio::{sync::Notify, time::interval};
 
-use crate::{globals, sending, Dep};
+pub use self::policy::FetchCounts;
+use crate::{globals, rooms, sending, Dep};
 
 pub struct Service {
 	keypair: Box<Ed25519KeyPair>,
 	verify_keys: VerifyKeys,
 	minimum_valid: Duration,
+	fetched: FetchCounts,
+	interrupt: Notify,
 	services: Services,
 	db: Data,
 }
@@ -36,6 +43,7 @@ pub struct Service {
 struct Services {
 	globals: Dep<globals::Service>,
 	sending: Dep<sending::Service>,
+	state_cache: Dep<rooms::state_cache::Service>,
 	server: Arc<Server>,
 }
 
@@ -47,6 +55,7 @@ pub type VerifyKeys = BTreeMap<OwnedServerSigningKeyId, VerifyKey>;
 pub type PubKeyMap = PublicKeyMap;
 pub type PubKeys = PublicKeySet;
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		let minimum_valid = Duration::from_secs(3600);
@@ -58,9 +67,12 @@ impl crate::Service for Service {
 			keypair,
 			verify_keys,
 			minimum_valid,
+			fetched: FetchCounts::default(),
+			interrupt: Notify::new(),
 			services: Services {
 				globals: args.depend::<globals::Service>("globals"),
 				sending: args.depend::<sending::Service>("sending"),
+				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 				server: args.server.clone(),
 			},
 			db: Data {
@@ -69,6 +81,26 @@ impl crate::Service for Service {
 		}))
 	}
 
+	async fn worker(self: Arc<Self>) -> Result {
+		let period = self.services.server.config.signing_key_refresh_interval;
+		if period == 0 {
+			return Ok(());
+		}
+
+		let period = Duration::from_secs(period);
+		let mut interval = interval(period);
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = interval.tick() => self.refresh_expiring(period).await,
+			}
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -91,6 +123,12 @@ pub fn active_verify_key(&self) -> (&ServerSigningKeyId, &VerifyKey) {
 		.expect("missing active verify_key")
 }
 
+/// How many servers' keys were fetched from themselves and from trusted
+/// servers.
+#[implement(Service)]
+#[inline]
+pub fn fetch_counts(&self) -> &FetchCounts { &self.fetched }
+
 #[implement(Service)]
 async fn add_signing_keys(&self, new_keys: ServerSigningKeys) {
 	let origin = &new_keys.server_name;
@@ -107,6 +145,7 @@ async fn add_signing_keys(&self, new_keys: ServerSigningKeys) {
 			ServerSigningKeys::new(origin.to_owned(), MilliSecondsSinceUnixEpoch::now())
 		});
 
+	keys.valid_until_ts = keys.valid_until_ts.max(new_keys.valid_until_ts);
 	keys.verify_keys.extend(new_keys.verify_keys);
 	keys.old_verify_keys.extend(new_keys.old_verify_keys);
 	self.db.server_signingkeys.raw_put(origin, Json(&keys));
@@ -0,0 +1,87 @@
+//! Which trusted servers are asked for the keys of which origins
+//! (`[global.key_servers]`), and counts of the keys fetched by each path.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use conduwuit::implement;
+use ruma::ServerName;
+
+/// How many servers' keys were fetched from each path, and how many could not
+/// be fetched at all.
+#[derive(Debug, Default)]
+pub struct FetchCounts {
+	pub origin: AtomicU64,
+	pub notary: AtomicU64,
+	pub failed: AtomicU64,
+}
+
+impl FetchCounts {
+	/// The counts by path, as `(path, count)`.
+	#[must_use]
+	pub fn get(&self) -> [(&'static str, u64); 3] {
+		[
+			("origin", self.origin.load(Ordering::Relaxed)),
+			("notary", self.notary.load(Ordering::Relaxed)),
+			("failed", self.failed.load(Ordering::Relaxed)),
+		]
+	}
+
+	pub(super) fn add(counter: &AtomicU64, servers: usize) {
+		let servers = u64::try_from(servers).unwrap_or(u64::MAX);
+		counter.fetch_add(servers, Ordering::Relaxed);
+	}
+}
+
+/// Whether the origin is asked for its keys itself before any trusted server.
+#[implement(super::Service)]
+pub(super) fn direct_first(&self, origin: &ServerName) -> bool {
+	self.services
+		.server
+		.config
+		.key_servers
+		.direct_origins
+		.iter()
+		.any(|pattern| origin_matches(pattern, origin))
+}
+
+/// Whether the trusted server may be asked for the keys of the origin, which
+/// we know keys of already when `known`.
+#[implement(super::Service)]
+pub(super) fn notary_trusted_for(
+	&self,
+	notary: &ServerName,
+	origin: &ServerName,
+	known: bool,
+) -> bool {
+	let policy = &self.services.server.config.key_servers;
+	if known
+		&& policy
+			.notary_unknown_origins_only
+			.iter()
+			.any(|unknown_only| unknown_only == notary)
+	{
+		return false;
+	}
+
+	policy.notary_origins.get(notary).is_none_or(|patterns| {
+		patterns
+			.iter()
+			.any(|pattern| origin_matches(pattern, origin))
+	})
+}
+
+/// Whether we know any keys of the origin, i.e. have received them before.
+#[implement(super::Service)]
+pub(super) async fn origin_known(&self, origin: &ServerName) -> bool {
+	self.db.server_signingkeys.get(origin).await.is_ok()
+}
+
+/// Matches a server name, a `*.` suffix pattern, or `*` for all servers.
+fn origin_matches(pattern: &str, origin: &ServerName) -> bool {
+	let origin = origin.as_str();
+	match pattern.strip_prefix('*') {
+		| Some("") => true,
+		| Some(suffix) if suffix.starts_with('.') => origin.ends_with(suffix),
+		| _ => pattern.eq_ignore_ascii_case(origin),
+	}
+}
@@ -0,0 +1,128 @@
+//! Refresh of the signing keys of remote servers before they expire. Every
+//! `signing_key_refresh_interval` the keys still valid but for less than that
+//! much longer of the servers we share a room with are fetched again, so
+//! events from the server aren't held up by fetching its keys once they
+//! expired.
+
+use std::time::Duration;
+
+use conduwuit::{
+	debug, debug_warn, implement, info, utils,
+	utils::stream::{BroadbandExt, IterStream, ReadyExt, TryIgnore},
+	Err, Result,
+};
+use futures::StreamExt;
+use ruma::{api::federation::discovery::ServerSigningKeys, OwnedServerName, ServerName};
+
+/// Servers whose keys are refreshed at once.
+const REFRESH_CONCURRENCY: usize = 8;
+
+/// Fetch the keys of the server again, from itself or else from the trusted
+/// servers, regardless of whether those we have expired; returns all the keys
+/// we have for it after.
+#[implement(super::Service)]
+pub async fn refresh_keys(&self, origin: &ServerName) -> Result<ServerSigningKeys> {
+	if self.services.globals.server_is_ours(origin) {
+		return Err!(Request(InvalidParam("Not fetching our own signing keys.")));
+	}
+
+	let notary_only =
+		self.services.server.config.only_query_trusted_key_servers && !self.direct_first(origin);
+
+	let mut errors = Vec::new();
+	if !notary_only {
+		match self.server_request(origin).await {
+			| Ok(server_keys) => {
+				self.add_signing_keys(server_keys).await;
+				return self.signing_keys_for(origin).await;
+			},
+			| Err(e) => errors.push(format!("{origin}: {e}")),
+		}
+	}
+
+	let known = self.origin_known(origin).await;
+	for notary in self.services.globals.trusted_servers() {
+		if !self.notary_trusted_for(notary, origin, known) {
+			continue;
+		}
+
+		match self.notary_request(notary, origin).await {
+			| Ok(server_keys) => {
+				let mut fetched = false;
+				for server_keys in server_keys.filter(|keys| keys.server_name == origin) {
+					self.add_signing_keys(server_keys).await;
+					fetched = true;
+				}
+
+				if fetched {
+					return self.signing_keys_for(origin).await;
+				}
+
+				errors.push(format!("{notary}: no keys sent"));
+			},
+			| Err(e) => errors.push(format!("{notary}: {e}")),
+		}
+	}
+
+	if errors.is_empty() {
+		return Err!(Request(NotFound("No server may be asked for the keys of {origin}")));
+	}
+
+	let errors = errors.join("; ");
+	Err!(BadServerResponse("Failed to fetch the keys of {origin}: {errors}"))
+}
+
+/// Refresh the keys valid for less than `window` longer, of the servers we
+/// share a room with and which are not considered dead. Keys which expired
+/// already are fetched when needed instead.
+#[implement(super::Service)]
+pub(super) async fn refresh_expiring(&self, window: Duration) {
+	let window: u64 = window.as_millis().try_into().unwrap_or(u64::MAX);
+	let now = utils::millis_since_unix_epoch();
+	let refresh_before = now.saturating_add(window);
+	let expiring: Vec<OwnedServerName> = self
+		.db
+		.server_signingkeys
+		.stream()
+		.ignore_err()
+		.ready_filter_map(|(origin, keys): (&ServerName, ServerSigningKeys)| {
+			let valid_until: u64 = keys.valid_until_ts.get().into();
+			(now..refresh_before)
+				.contains(&valid_until)
+				.then(|| origin.to_owned())
+		})
+		.collect()
+		.await;
+
+	let refreshed = expiring
+		.iter()
+		.stream()
+		.ready_filter(|origin| !self.services.globals.server_is_ours(origin))
+		.broadn_filter_map(REFRESH_CONCURRENCY, |origin| async move {
+			if !self.services.server.running()
+				|| self.services.sending.is_dead(origin).await
+				|| self
+					.services
+					.state_cache
+					.server_rooms(origin)
+					.next()
+					.await
+					.is_none()
+			{
+				return None;
+			}
+
+			self.refresh_keys(origin)
+				.await
+				.inspect_err(|e| debug_warn!(%origin, "Failed to refresh signing keys: {e}"))
+				.ok()
+		})
+		.count()
+		.await;
+
+	if refreshed > 0 {
+		info!("Refreshed the signing keys of {refreshed} of {} servers", expiring.len());
+	} else {
+		debug!(expiring = expiring.len(), "No signing keys were refreshed");
+	}
+}
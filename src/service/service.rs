@@ -1,5 +1,6 @@
 use std::{
 	any::Any,
+	cell::RefCell,
 	collections::BTreeMap,
 	fmt::Write,
 	ops::Deref,
@@ -26,6 +27,10 @@ pub(crate) trait Service: Any + Send + Sync {
 	/// task and calls this function after all services have been built.
 	async fn worker(self: Arc<Self>) -> Result<()> { Ok(()) }
 
+	/// Whether the worker is started on a read replica as well. Most workers
+	/// write to the database, which only the primary does.
+	fn replica_worker(&self) -> bool { false }
+
 	/// Interrupt the service. This is sent to initiate a graceful shutdown.
 	/// The service worker should return from its work loop.
 	fn interrupt(&self) {}
@@ -36,6 +41,10 @@ pub(crate) trait Service: Any + Send + Sync {
 	/// Memory usage report in a markdown string.
 	fn memory_usage(&self, _out: &mut dyn Write) -> Result<()> { Ok(()) }
 
+	/// Number of items waiting to be processed by the service's worker, for
+	/// services which have a queue.
+	fn queue_len(&self) -> Option<usize> { None }
+
 	/// Return the name of the service.
 	/// i.e. `crate::service::make_name(std::module_path!())`
 	fn name(&self) -> &str;
@@ -47,6 +56,10 @@ pub(crate) struct Args<'a> {
 	pub(crate) server: &'a Arc<Server>,
 	pub(crate) db: &'a Arc<Database>,
 	pub(crate) service: &'a Arc<Map>,
+
+	/// Names of the services referenced through this instance, recorded for
+	/// introspection of the dependency graph.
+	pub(crate) deps: &'a RefCell<Vec<String>>,
 }
 
 /// Dep is a reference to a service used within another service.
@@ -104,6 +117,7 @@ impl<'a> Args<'a> {
 	/// Create a lazy-reference to a service when constructing another Service.
 	#[inline]
 	pub(crate) fn depend<T: Service>(&'a self, name: &'static str) -> Dep<T> {
+		self.deps.borrow_mut().push(name.to_owned());
 		Dep::<T> {
 			dep: OnceLock::new(),
 			service: Arc::downgrade(self.service),
@@ -115,6 +129,7 @@ impl<'a> Args<'a> {
 	/// Service. The other service must be constructed.
 	#[inline]
 	pub(crate) fn require<T: Service>(&'a self, name: &str) -> Arc<T> {
+		self.deps.borrow_mut().push(name.to_owned());
 		require::<T>(self.service, name)
 	}
 }
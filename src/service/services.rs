@@ -1,5 +1,6 @@
 use std::{
 	any::Any,
+	cell::RefCell,
 	collections::BTreeMap,
 	fmt::Write,
 	sync::{Arc, RwLock},
@@ -10,28 +11,37 @@ use database::Database;
 use tokio::sync::Mutex;
 
 use crate::{
-	account_data, admin, appservice, client, emergency, globals, key_backups,
-	manager::Manager,
-	media, presence, pusher, resolver, rooms, sending, server_keys, service,
+	account_data, admin, appservice, bus, client, emergency, globals, hooks, key_backups,
+	manager::{Manager, WorkerStatus},
+	media, moderation, presence, profiles, pusher, ratelimit, registration_tokens, resolver,
+	rooms, sending, server_keys, service,
 	service::{Args, Map, Service},
-	sync, transaction_ids, uiaa, updates, users,
+	spamcheck, sso, sync, transaction_ids, uiaa, updates, users,
 };
 
 pub struct Services {
 	pub account_data: Arc<account_data::Service>,
 	pub admin: Arc<admin::Service>,
 	pub appservice: Arc<appservice::Service>,
+	pub bus: Arc<bus::Service>,
 	pub client: Arc<client::Service>,
 	pub emergency: Arc<emergency::Service>,
 	pub globals: Arc<globals::Service>,
+	pub hooks: Arc<hooks::Service>,
 	pub key_backups: Arc<key_backups::Service>,
 	pub media: Arc<media::Service>,
+	pub moderation: moderation::Service,
 	pub presence: Arc<presence::Service>,
+	pub profiles: Arc<profiles::Service>,
 	pub pusher: Arc<pusher::Service>,
+	pub ratelimit: Arc<ratelimit::Service>,
+	pub registration_tokens: Arc<registration_tokens::Service>,
 	pub resolver: Arc<resolver::Service>,
 	pub rooms: rooms::Service,
 	pub sending: Arc<sending::Service>,
 	pub server_keys: Arc<server_keys::Service>,
+	pub spamcheck: Arc<spamcheck::Service>,
+	pub sso: Arc<sso::Service>,
 	pub sync: Arc<sync::Service>,
 	pub transaction_ids: Arc<transaction_ids::Service>,
 	pub uiaa: Arc<uiaa::Service>,
@@ -40,6 +50,7 @@ pub struct Services {
 
 	manager: Mutex<Option<Arc<Manager>>>,
 	pub(crate) service: Arc<Map>,
+	graph: BTreeMap<String, Vec<String>>,
 	pub server: Arc<Server>,
 	pub db: Arc<Database>,
 }
@@ -49,14 +60,18 @@ impl Services {
 	pub async fn build(server: Arc<Server>) -> Result<Arc<Self>> {
 		let db = Database::open(&server).await?;
 		let service: Arc<Map> = Arc::new(RwLock::new(BTreeMap::new()));
+		let mut graph = BTreeMap::new();
 		macro_rules! build {
 			($tyname:ty) => {{
+				let deps = RefCell::new(Vec::new());
 				let built = <$tyname>::build(Args {
 					db: &db,
 					server: &server,
 					service: &service,
+					deps: &deps,
 				})?;
 				add_service(&service, built.clone(), built.clone());
+				graph.insert(built.name().to_owned(), deps.into_inner());
 				built
 			}};
 		}
@@ -65,19 +80,31 @@ impl Services {
 			account_data: build!(account_data::Service),
 			admin: build!(admin::Service),
 			appservice: build!(appservice::Service),
+			bus: build!(bus::Service),
 			resolver: build!(resolver::Service),
 			client: build!(client::Service),
 			emergency: build!(emergency::Service),
 			globals: build!(globals::Service),
+			hooks: build!(hooks::Service),
 			key_backups: build!(key_backups::Service),
 			media: build!(media::Service),
+			moderation: moderation::Service {
+				policy: build!(moderation::policy::Service),
+				reports: build!(moderation::reports::Service),
+			},
 			presence: build!(presence::Service),
+			profiles: build!(profiles::Service),
 			pusher: build!(pusher::Service),
+			ratelimit: build!(ratelimit::Service),
+			registration_tokens: build!(registration_tokens::Service),
 			rooms: rooms::Service {
 				alias: build!(rooms::alias::Service),
 				auth_chain: build!(rooms::auth_chain::Service),
+				delayed: build!(rooms::delayed::Service),
 				directory: build!(rooms::directory::Service),
 				event_handler: build!(rooms::event_handler::Service),
+				image_packs: build!(rooms::image_packs::Service),
+				joins: build!(rooms::joins::Service),
 				lazy_loading: build!(rooms::lazy_loading::Service),
 				metadata: build!(rooms::metadata::Service),
 				outlier: build!(rooms::outlier::Service),
@@ -97,6 +124,8 @@ impl Services {
 			},
 			sending: build!(sending::Service),
 			server_keys: build!(server_keys::Service),
+			spamcheck: build!(spamcheck::Service),
+			sso: build!(sso::Service),
 			sync: build!(sync::Service),
 			transaction_ids: build!(transaction_ids::Service),
 			uiaa: build!(uiaa::Service),
@@ -105,6 +134,7 @@ impl Services {
 
 			manager: Mutex::new(None),
 			service,
+			graph,
 			server,
 			db,
 		}))
@@ -166,6 +196,36 @@ impl Services {
 		Ok(())
 	}
 
+	/// Supervision state of each service's worker, keyed by service name.
+	pub async fn workers(&self) -> BTreeMap<String, WorkerStatus> {
+		self.manager
+			.lock()
+			.await
+			.as_ref()
+			.map(|manager| manager.status())
+			.unwrap_or_default()
+	}
+
+	/// Names of the services each service depends on, keyed by service name.
+	#[inline]
+	#[must_use]
+	pub fn graph(&self) -> &BTreeMap<String, Vec<String>> { &self.graph }
+
+	/// Queue depth of each service which has a work queue.
+	pub fn queues(&self) -> BTreeMap<String, usize> {
+		self.service
+			.read()
+			.expect("locked for reading")
+			.iter()
+			.filter_map(|(name, (service, ..))| {
+				service
+					.upgrade()
+					.and_then(|service| service.queue_len())
+					.map(|len| (name.clone(), len))
+			})
+			.collect()
+	}
+
 	pub async fn clear_cache(&self) {
 		for (service, ..) in self.service.read().expect("locked for reading").values() {
 			if let Some(service) = service.upgrade() {
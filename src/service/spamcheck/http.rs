@@ -0,0 +1,91 @@
+use std::{net::IpAddr, time::Duration};
+
+use async_trait::async_trait;
+use conduwuit::{config::SpamCheckerConfig, warn, PduEvent, Result};
+use ruma::{RoomId, UserId};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use url::Url;
+
+use super::{SpamChecker, Verdict};
+use crate::{client, Dep};
+
+/// The checker of `[global.spam_checker]`, asked over HTTP.
+pub(super) struct HttpChecker {
+	pub(super) url: Url,
+	pub(super) config: SpamCheckerConfig,
+	pub(super) client: Dep<client::Service>,
+}
+
+#[derive(Deserialize)]
+struct Answer {
+	allow: bool,
+	#[serde(default)]
+	reason: Option<String>,
+}
+
+#[async_trait]
+impl SpamChecker for HttpChecker {
+	fn name(&self) -> &str { "http" }
+
+	async fn check_event_for_spam(&self, pdu: &PduEvent) -> Verdict {
+		self.ask("check_event_for_spam", json!({ "event": pdu }))
+			.await
+	}
+
+	async fn user_may_invite(
+		&self,
+		inviter: &UserId,
+		invitee: &UserId,
+		room_id: &RoomId,
+	) -> Verdict {
+		self.ask(
+			"user_may_invite",
+			json!({ "inviter": inviter, "invitee": invitee, "room_id": room_id }),
+		)
+		.await
+	}
+
+	async fn user_may_create_room(&self, user_id: &UserId) -> Verdict {
+		self.ask("user_may_create_room", json!({ "user_id": user_id }))
+			.await
+	}
+
+	async fn check_registration(&self, user_id: &UserId, client: IpAddr) -> Verdict {
+		self.ask("check_registration", json!({ "user_id": user_id, "client_ip": client }))
+			.await
+	}
+}
+
+impl HttpChecker {
+	async fn ask(&self, check: &str, body: JsonValue) -> Verdict {
+		match self.request(check, body).await {
+			| Ok(Answer { allow: true, .. }) => Verdict::Allow,
+			| Ok(Answer { allow: false, reason }) =>
+				Verdict::Deny(reason.unwrap_or_else(|| "Refused by the spam checker.".to_owned())),
+			| Err(e) => {
+				warn!("Spam checker failed to answer {check}: {e}");
+				if self.config.fail_open {
+					Verdict::Allow
+				} else {
+					Verdict::Deny("The spam checker is unavailable.".to_owned())
+				}
+			},
+		}
+	}
+
+	async fn request(&self, check: &str, body: JsonValue) -> Result<Answer> {
+		let url = format!("{}/{check}", self.url.as_str().trim_end_matches('/'));
+		let response = self
+			.client
+			.default
+			.post(url)
+			.timeout(Duration::from_secs(self.config.timeout))
+			.json(&body)
+			.send()
+			.await?
+			.error_for_status()?;
+
+		Ok(serde_json::from_slice(&response.bytes().await?)?)
+	}
+}
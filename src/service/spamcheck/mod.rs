@@ -0,0 +1,200 @@
+//! Spam checkers asked whether events, invites, room creations and
+//! registrations are allowed; for now the one of `[global.spam_checker]`. The
+//! first checker refusing decides.
+
+mod http;
+
+use std::{
+	net::IpAddr,
+	sync::{Arc, Mutex},
+};
+
+use async_trait::async_trait;
+use conduwuit::{
+	debug_info, implement,
+	utils::{hash::sha256, string::EMPTY},
+	PduEvent, Result,
+};
+use futures::future::join_all;
+use lru_cache::LruCache;
+use ruma::{CanonicalJsonObject, EventId, OwnedEventId, RoomId, UserId};
+
+use self::http::HttpChecker;
+use crate::client;
+
+pub struct Service {
+	checkers: Vec<Box<dyn SpamChecker>>,
+	verdicts: Mutex<LruCache<OwnedEventId, (sha256::Digest, Verdict)>>,
+}
+
+/// Verdicts on events checked ahead of handling them which are kept until
+/// they are handled; those of events rejected before are evicted eventually.
+const VERDICT_CACHE_CAPACITY: usize = 4096;
+
+/// What a spam checker decided.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Verdict {
+	Allow,
+	Deny(String),
+}
+
+/// The hooks of a spam checker, each allowing what it checks by default.
+#[async_trait]
+pub trait SpamChecker: Send + Sync {
+	/// Name of the checker in logs.
+	fn name(&self) -> &str;
+
+	/// An event received over federation; refused events are soft failed.
+	async fn check_event_for_spam(&self, _pdu: &PduEvent) -> Verdict { Verdict::Allow }
+
+	/// An invite of the invitee to the room by the inviter, either of whom is
+	/// local.
+	async fn user_may_invite(
+		&self,
+		_inviter: &UserId,
+		_invitee: &UserId,
+		_room_id: &RoomId,
+	) -> Verdict {
+		Verdict::Allow
+	}
+
+	/// A local user creating a room.
+	async fn user_may_create_room(&self, _user_id: &UserId) -> Verdict { Verdict::Allow }
+
+	/// Registration of the account from the client address.
+	async fn check_registration(&self, _user_id: &UserId, _client: IpAddr) -> Verdict {
+		Verdict::Allow
+	}
+}
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		let mut checkers: Vec<Box<dyn SpamChecker>> = Vec::new();
+		if let Some(url) = &args.server.config.spam_checker.url {
+			checkers.push(Box::new(HttpChecker {
+				url: url.clone(),
+				config: args.server.config.spam_checker.clone(),
+				client: args.depend::<client::Service>("client"),
+			}));
+		}
+
+		Ok(Arc::new(Self {
+			checkers,
+			verdicts: Mutex::new(LruCache::new(VERDICT_CACHE_CAPACITY)),
+		}))
+	}
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+impl Verdict {
+	#[inline]
+	#[must_use]
+	pub fn is_denied(&self) -> bool { matches!(self, Self::Deny(_)) }
+}
+
+/// Check the events of a transaction concurrently before the room is locked to
+/// handle them, so a slow checker holds up no other events of the room. The
+/// verdicts are kept for `check_event_for_spam` when the events are handled.
+#[implement(Service)]
+pub async fn precheck_events<'a, I>(&self, pdus: I)
+where
+	I: Iterator<Item = (&'a EventId, &'a CanonicalJsonObject)>,
+{
+	if self.checkers.is_empty() {
+		return;
+	}
+
+	let checks = pdus
+		.filter_map(|(event_id, value)| PduEvent::from_id_val(event_id, value.clone()).ok())
+		.map(|pdu| async move {
+			let verdict = self.ask_event(&pdu).await;
+			(pdu.event_id.clone(), (checked_digest(&pdu), verdict))
+		});
+
+	let verdicts = join_all(checks).await;
+	let mut cache = self.verdicts.lock().expect("locked");
+	for (event_id, checked) in verdicts {
+		cache.insert(event_id, checked);
+	}
+}
+
+/// The verdict on the event, unless it was checked ahead already. An event
+/// the checkers saw otherwise, e.g. another with the same ID in a room version
+/// whose event IDs aren't hashes, is checked again.
+#[implement(Service)]
+pub async fn check_event_for_spam(&self, pdu: &PduEvent) -> Verdict {
+	let checked = self.verdicts.lock().expect("locked").remove(&pdu.event_id);
+
+	match checked {
+		| Some((digest, verdict)) if digest == checked_digest(pdu) => verdict,
+		| _ => self.ask_event(pdu).await,
+	}
+}
+
+#[implement(Service)]
+async fn ask_event(&self, pdu: &PduEvent) -> Verdict {
+	for checker in &self.checkers {
+		let verdict = checker.check_event_for_spam(pdu).await;
+		if verdict.is_denied() {
+			debug_info!(event_id = ?pdu.event_id, "Spam checker {:?}: {verdict:?}", checker.name());
+			return verdict;
+		}
+	}
+
+	Verdict::Allow
+}
+
+#[implement(Service)]
+pub async fn user_may_invite(
+	&self,
+	inviter: &UserId,
+	invitee: &UserId,
+	room_id: &RoomId,
+) -> Verdict {
+	for checker in &self.checkers {
+		let verdict = checker.user_may_invite(inviter, invitee, room_id).await;
+		if verdict.is_denied() {
+			debug_info!(%inviter, %invitee, %room_id, "Spam checker {:?}: {verdict:?}", checker.name());
+			return verdict;
+		}
+	}
+
+	Verdict::Allow
+}
+
+#[implement(Service)]
+pub async fn user_may_create_room(&self, user_id: &UserId) -> Verdict {
+	for checker in &self.checkers {
+		let verdict = checker.user_may_create_room(user_id).await;
+		if verdict.is_denied() {
+			debug_info!(%user_id, "Spam checker {:?}: {verdict:?}", checker.name());
+			return verdict;
+		}
+	}
+
+	Verdict::Allow
+}
+
+#[implement(Service)]
+pub async fn check_registration(&self, user_id: &UserId, client: IpAddr) -> Verdict {
+	for checker in &self.checkers {
+		let verdict = checker.check_registration(user_id, client).await;
+		if verdict.is_denied() {
+			debug_info!(%user_id, %client, "Spam checker {:?}: {verdict:?}", checker.name());
+			return verdict;
+		}
+	}
+
+	Verdict::Allow
+}
+
+/// Digest of what the checkers judge an event by, so a verdict is only reused
+/// for the same event.
+fn checked_digest(pdu: &PduEvent) -> sha256::Digest {
+	let kind = pdu.kind.to_string();
+	let state_key = pdu.state_key.as_deref().unwrap_or(EMPTY);
+	let inputs = [pdu.sender.as_str(), &kind, state_key, pdu.content.get()];
+
+	sha256::delimited(inputs.iter())
+}
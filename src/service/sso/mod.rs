@@ -0,0 +1,329 @@
+//! Single sign-on through an OpenID Connect identity provider
+//! (`[global.oidc]`).
+//!
+//! Users are sent to the provider's authorization endpoint with a random
+//! state, which is kept here along with what the authorization was started
+//! for. The provider redirects them back to the callback with the state and a
+//! code, which is exchanged for an access token to fetch their claims from the
+//! userinfo endpoint. Users are identified by their subject, linked to a local
+//! account on first login.
+
+use std::{
+	collections::HashMap,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{debug, err, implement, utils, Err, Result, Server};
+use database::{Deserialized, Map};
+use ruma::{OwnedDeviceId, OwnedUserId, UserId};
+use serde::Deserialize;
+use serde_json::{Map as JsonMap, Value as JsonValue};
+use tokio::sync::OnceCell;
+use url::Url;
+
+use crate::{client, globals, users, Dep};
+
+pub struct Service {
+	db: Data,
+	services: Services,
+	pending: Mutex<HashMap<String, Pending>>,
+	metadata: OnceCell<Metadata>,
+}
+
+struct Data {
+	ssosubject_userid: Arc<Map>,
+}
+
+struct Services {
+	server: Arc<Server>,
+	client: Dep<client::Service>,
+	globals: Dep<globals::Service>,
+	users: Dep<users::Service>,
+}
+
+/// What an authorization was started for.
+#[derive(Clone, Debug)]
+pub enum Purpose {
+	/// Log in, returning to the client at the URL with a login token.
+	Login {
+		redirect_url: Url,
+	},
+
+	/// Confirm the identity of the user for a user-interactive authentication
+	/// session.
+	Uiaa {
+		user_id: OwnedUserId,
+		device_id: OwnedDeviceId,
+		session: String,
+	},
+}
+
+/// The user the identity provider authenticated.
+#[derive(Debug)]
+pub enum Identity {
+	/// The account linked to the subject.
+	Linked(OwnedUserId),
+
+	/// No account is linked to the subject yet; one should be created and
+	/// linked with [`Service::link`].
+	New {
+		subject: String,
+		user_id: OwnedUserId,
+		displayname: Option<String>,
+	},
+}
+
+struct Pending {
+	purpose: Purpose,
+	started: Instant,
+}
+
+/// The endpoints of the identity provider, from its discovery document.
+#[derive(Debug, Deserialize)]
+struct Metadata {
+	authorization_endpoint: Url,
+	token_endpoint: Url,
+	userinfo_endpoint: Url,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+	access_token: String,
+}
+
+/// The path of the callback the identity provider redirects back to.
+pub const CALLBACK_PATH: &str = "/_conduwuit/sso/callback";
+
+/// How long users have to authenticate with the identity provider.
+const PENDING_TTL: Duration = Duration::from_secs(60 * 10);
+
+const STATE_LENGTH: usize = 32;
+
+impl crate::Service for Service {
+	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
+		Ok(Arc::new(Self {
+			db: Data {
+				ssosubject_userid: args.db["ssosubject_userid"].clone(),
+			},
+			services: Services {
+				server: args.server.clone(),
+				client: args.depend::<client::Service>("client"),
+				globals: args.depend::<globals::Service>("globals"),
+				users: args.depend::<users::Service>("users"),
+			},
+			pending: Mutex::default(),
+			metadata: OnceCell::new(),
+		}))
+	}
+
+	fn clear_cache(&self) { self.pending.lock().expect("locked").clear(); }
+
+	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
+}
+
+#[implement(Service)]
+#[inline]
+#[must_use]
+pub fn enabled(&self) -> bool { self.services.server.config.oidc.enable }
+
+/// The URL of the identity provider's authorization endpoint to send the user
+/// to.
+#[implement(Service)]
+pub async fn authorize(&self, purpose: Purpose) -> Result<Url> {
+	let config = &self.services.server.config.oidc;
+	if !config.enable {
+		return Err!(Request(Unrecognized("SSO login is not enabled.")));
+	}
+
+	let metadata = self.metadata().await?;
+	let state = utils::random_string(STATE_LENGTH);
+	let mut pending = self.pending.lock().expect("locked");
+	pending.retain(|_, pending| pending.started.elapsed() < PENDING_TTL);
+	pending.insert(state.clone(), Pending { purpose, started: Instant::now() });
+
+	let mut url = metadata.authorization_endpoint.clone();
+	url.query_pairs_mut()
+		.append_pair("response_type", "code")
+		.append_pair("client_id", config.client_id.as_deref().unwrap_or_default())
+		.append_pair("redirect_uri", self.callback_url()?.as_str())
+		.append_pair("scope", &config.scopes.join(" "))
+		.append_pair("state", &state);
+
+	Ok(url)
+}
+
+/// Complete an authorization the identity provider redirected the user back
+/// from, returning what it was started for and who authenticated.
+#[implement(Service)]
+pub async fn callback(&self, state: &str, code: &str) -> Result<(Purpose, Identity)> {
+	let pending = self
+		.pending
+		.lock()
+		.expect("locked")
+		.remove(state)
+		.filter(|pending| pending.started.elapsed() < PENDING_TTL)
+		.ok_or_else(|| err!(Request(Forbidden("Unknown or expired SSO session."))))?;
+
+	let claims = self.claims(code).await?;
+	let subject = claims
+		.get("sub")
+		.and_then(JsonValue::as_str)
+		.ok_or_else(|| err!(Request(Forbidden("The identity provider gave no subject."))))?;
+
+	debug!(?subject, "Authenticated by the identity provider");
+	let identity = self.identity(subject, &claims).await?;
+
+	Ok((pending.purpose, identity))
+}
+
+/// Link the subject to the account, so the user logs in to it from now on.
+#[implement(Service)]
+pub fn link(&self, subject: &str, user_id: &UserId) {
+	let idp_id = &self.services.server.config.oidc.idp_id;
+	self.db.ssosubject_userid.put((idp_id, subject), user_id);
+}
+
+#[implement(Service)]
+async fn identity(&self, subject: &str, claims: &JsonMap<String, JsonValue>) -> Result<Identity> {
+	let config = &self.services.server.config.oidc;
+	if let Ok(user_id) = self
+		.db
+		.ssosubject_userid
+		.qry(&(&config.idp_id, subject))
+		.await
+		.deserialized()
+	{
+		return Ok(Identity::Linked(user_id));
+	}
+
+	let claim = |name: &str| claims.get(name).and_then(JsonValue::as_str);
+	let localpart: String = claim(&config.localpart_claim)
+		.unwrap_or_default()
+		.to_lowercase()
+		.chars()
+		.map(|c| {
+			if matches!(c, 'a'..='z' | '0'..='9' | '.' | '_' | '=' | '-' | '/') {
+				c
+			} else {
+				'_'
+			}
+		})
+		.collect();
+
+	if localpart.is_empty() {
+		return Err!(Request(Forbidden(
+			"The identity provider gave no {:?} claim to name the account after.",
+			config.localpart_claim
+		)));
+	}
+
+	let user_id = UserId::parse_with_server_name(localpart, self.services.globals.server_name())
+		.map_err(|e| err!(Request(InvalidUsername("Invalid user ID from the claim: {e}"))))?;
+
+	if self.services.users.exists(&user_id).await {
+		if !config.allow_existing_users {
+			return Err!(Request(UserInUse(
+				"The account {user_id} already exists and is not linked to the identity \
+				 provider."
+			)));
+		}
+
+		self.link(subject, &user_id);
+		return Ok(Identity::Linked(user_id));
+	}
+
+	if !config.allow_registration {
+		return Err!(Request(Forbidden("No account is linked to the identity provider.")));
+	}
+
+	Ok(Identity::New {
+		subject: subject.to_owned(),
+		user_id,
+		displayname: claim(&config.displayname_claim).map(ToOwned::to_owned),
+	})
+}
+
+/// Exchange the code for an access token and fetch the user's claims with it.
+#[implement(Service)]
+async fn claims(&self, code: &str) -> Result<JsonMap<String, JsonValue>> {
+	let config = &self.services.server.config.oidc;
+	let metadata = self.metadata().await?;
+	let client = &self.services.client.default;
+	let redirect_uri = self.callback_url()?;
+	let response = client
+		.post(metadata.token_endpoint.clone())
+		.basic_auth(
+			config.client_id.as_deref().unwrap_or_default(),
+			config.client_secret.as_deref(),
+		)
+		.form(&[
+			("grant_type", "authorization_code"),
+			("code", code),
+			("redirect_uri", redirect_uri.as_str()),
+		])
+		.send()
+		.await?
+		.error_for_status()
+		.map_err(|e| err!(Request(Forbidden("The identity provider refused the code: {e}"))))?;
+
+	let TokenResponse { access_token } = serde_json::from_slice(&response.bytes().await?)?;
+	let response = client
+		.get(metadata.userinfo_endpoint.clone())
+		.bearer_auth(access_token)
+		.send()
+		.await?
+		.error_for_status()
+		.map_err(|e| err!(Request(Forbidden("The identity provider refused the token: {e}"))))?;
+
+	Ok(serde_json::from_slice(&response.bytes().await?)?)
+}
+
+#[implement(Service)]
+async fn metadata(&self) -> Result<&Metadata> {
+	self.metadata
+		.get_or_try_init(|| async {
+			let issuer = self
+				.services
+				.server
+				.config
+				.oidc
+				.issuer
+				.as_ref()
+				.ok_or_else(|| err!(Config("oidc.issuer", "The issuer is not set.")))?;
+
+			let url = format!(
+				"{}/.well-known/openid-configuration",
+				issuer.as_str().trim_end_matches('/')
+			);
+
+			let response = self
+				.services
+				.client
+				.default
+				.get(url)
+				.send()
+				.await?
+				.error_for_status()?;
+
+			let metadata: Metadata = serde_json::from_slice(&response.bytes().await?)?;
+			debug!(?metadata, "Discovered the identity provider");
+
+			Ok(metadata)
+		})
+		.await
+}
+
+#[implement(Service)]
+fn callback_url(&self) -> Result<Url> {
+	let config = &self.services.server.config;
+	let base = config
+		.oidc
+		.public_base_url
+		.as_ref()
+		.or(config.well_known.client.as_ref())
+		.ok_or_else(|| err!(Config("oidc.public_base_url", "The public URL is not set.")))?;
+
+	let url = format!("{}{CALLBACK_PATH}", base.as_str().trim_end_matches('/'));
+	Url::parse(&url).map_err(|e| err!(Config("oidc.public_base_url", "Invalid public URL: {e}")))
+}
@@ -0,0 +1,141 @@
+//! The sync long-polls of each device waiting for something to return now, to
+//! see whether a client is connected at all when it misses messages. A device
+//! opening more than `sync_max_concurrent_per_device` evicts its oldest ones,
+//! which return at once.
+
+use std::{
+	collections::BTreeMap,
+	future::Future,
+	sync::{
+		atomic::{AtomicU64, Ordering},
+		Arc, Mutex,
+	},
+	time::{Duration, Instant},
+};
+
+use conduwuit::{debug, implement};
+use ruma::{DeviceId, OwnedDeviceId, OwnedUserId, UserId};
+use tokio::{sync::Notify, time::timeout};
+
+pub(super) type ActiveSyncs = Arc<Mutex<BTreeMap<ActiveKey, BTreeMap<u64, ActiveSync>>>>;
+type ActiveKey = (OwnedUserId, OwnedDeviceId);
+
+/// A sync long-poll of a device.
+#[derive(Clone, Debug)]
+pub struct ActiveSync {
+	/// The `since` token of the request, none for an initial sync.
+	pub since: Option<String>,
+
+	/// When the request was received.
+	pub started: Instant,
+
+	evict: Arc<Notify>,
+}
+
+/// The sync long-polls of a device.
+#[derive(Debug)]
+pub struct DeviceSyncs {
+	pub device_id: OwnedDeviceId,
+	pub syncs: Vec<ActiveSync>,
+}
+
+/// Tracks a sync long-poll until dropped, when the request returns or is
+/// cancelled.
+#[must_use]
+pub struct SyncGuard {
+	active: ActiveSyncs,
+	key: ActiveKey,
+	id: u64,
+	evict: Arc<Notify>,
+}
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+
+/// Track a sync long-poll of the device until the returned guard is dropped,
+/// evicting the oldest ones of the device beyond the maximum of the config.
+#[implement(super::Service)]
+pub fn track_sync(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	since: Option<&str>,
+) -> SyncGuard {
+	let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+	let key = (user_id.to_owned(), device_id.to_owned());
+	let evict = Arc::new(Notify::new());
+	let sync = ActiveSync {
+		since: since.map(ToOwned::to_owned),
+		started: Instant::now(),
+		evict: evict.clone(),
+	};
+
+	let max = self.services.server.config.sync_max_concurrent_per_device;
+	let mut active = self.active.lock().expect("locked");
+	let syncs = active.entry(key.clone()).or_default();
+	syncs.insert(id, sync);
+	while max > 0 && syncs.len() > max {
+		if let Some((_, oldest)) = syncs.pop_first() {
+			debug!(%user_id, %device_id, "Evicting the oldest sync long-poll of the device");
+			oldest.evict.notify_one();
+		}
+	}
+
+	drop(active);
+	SyncGuard {
+		active: self.active.clone(),
+		key,
+		id,
+		evict,
+	}
+}
+
+/// Wait for the watcher until the timeout of the request, within the maximum
+/// of the config, or until the long-poll is evicted.
+#[implement(super::Service)]
+pub async fn wait<F>(&self, guard: &SyncGuard, watcher: F, request_timeout: Option<Duration>)
+where
+	F: Future + Send,
+{
+	let max = Duration::from_secs(self.services.server.config.sync_timeout_max);
+	let duration = request_timeout.unwrap_or(max).min(max);
+
+	tokio::select! {
+		_ = timeout(duration, watcher) => (),
+		() = guard.evict.notified() => (),
+	}
+}
+
+/// The sync long-polls of each device of the user, the oldest first.
+#[implement(super::Service)]
+#[must_use]
+pub fn active_syncs(&self, user_id: &UserId) -> Vec<DeviceSyncs> {
+	self.active
+		.lock()
+		.expect("locked")
+		.iter()
+		.filter(|((user, _), _)| user == user_id)
+		.map(|((_, device_id), syncs)| DeviceSyncs {
+			device_id: device_id.clone(),
+			syncs: syncs.values().cloned().collect(),
+		})
+		.collect()
+}
+
+impl ActiveSync {
+	/// How long the request has been waiting.
+	#[inline]
+	#[must_use]
+	pub fn connected(&self) -> Duration { self.started.elapsed() }
+}
+
+impl Drop for SyncGuard {
+	fn drop(&mut self) {
+		let mut active = self.active.lock().expect("locked");
+		if let Some(syncs) = active.get_mut(&self.key) {
+			syncs.remove(&self.id);
+			if syncs.is_empty() {
+				active.remove(&self.key);
+			}
+		}
+	}
+}
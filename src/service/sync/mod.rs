@@ -1,3 +1,4 @@
+mod active;
 mod watch;
 
 use std::{
@@ -16,13 +17,16 @@ use ruma::{
 	DeviceId, OwnedDeviceId, OwnedRoomId, OwnedUserId, UserId,
 };
 
-use crate::{rooms, Dep};
+use self::active::ActiveSyncs;
+pub use self::active::{ActiveSync, DeviceSyncs, SyncGuard};
+use crate::{bus, rooms, Dep};
 
 pub struct Service {
 	db: Data,
 	services: Services,
 	connections: DbConnections<DbConnectionsKey, DbConnectionsVal>,
 	snake_connections: DbConnections<SnakeConnectionsKey, SnakeConnectionsVal>,
+	active: ActiveSyncs,
 }
 
 pub struct Data {
@@ -41,6 +45,7 @@ pub struct Data {
 
 struct Services {
 	server: Arc<Server>,
+	bus: Dep<bus::Service>,
 	short: Dep<rooms::short::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
 	typing: Dep<rooms::typing::Service>,
@@ -86,12 +91,14 @@ impl crate::Service for Service {
 			},
 			services: Services {
 				server: args.server.clone(),
+				bus: args.depend::<bus::Service>("bus"),
 				short: args.depend::<rooms::short::Service>("rooms::short"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
 				typing: args.depend::<rooms::typing::Service>("rooms::typing"),
 			},
 			connections: StdMutex::new(BTreeMap::new()),
 			snake_connections: StdMutex::new(BTreeMap::new()),
+			active: ActiveSyncs::default(),
 		}))
 	}
 
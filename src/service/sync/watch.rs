@@ -96,6 +96,9 @@ pub async fn watch(&self, user_id: &UserId, device_id: &DeviceId) -> Result {
 			.watch_prefix(&userid_bytes),
 	);
 
+	// Writes made by other instances
+	futures.push(self.services.bus.wait_for_wakeup(user_id).boxed());
+
 	// Server shutdown
 	let server_shutdown = self.services.server.clone().until_shutdown().boxed();
 	futures.push(server_shutdown);
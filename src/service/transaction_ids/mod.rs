@@ -1,23 +1,31 @@
 use std::sync::Arc;
 
-use conduwuit::{implement, Result};
-use database::{Handle, Map};
-use ruma::{DeviceId, TransactionId, UserId};
+use conduwuit::{err, implement, utils::time::now_secs, Err, Result, Server};
+use database::Map;
+use ruma::{DeviceId, ServerName, TransactionId, UserId};
 
 pub struct Service {
 	db: Data,
+	server: Arc<Server>,
 }
 
 struct Data {
+	servertxnid_response: Arc<Map>,
 	userdevicetxnid_response: Arc<Map>,
 }
 
+/// Records lead with the big-endian creation time in seconds so the database
+/// can expire them once `transaction_id_ttl` has passed.
+const TIMESTAMP_LEN: usize = size_of::<u64>();
+
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
 			db: Data {
+				servertxnid_response: args.db["servertxnid_response"].clone(),
 				userdevicetxnid_response: args.db["userdevicetxnid_response"].clone(),
 			},
+			server: args.server.clone(),
 		}))
 	}
 
@@ -38,17 +46,71 @@ pub fn add_txnid(
 	key.push(0xFF);
 	key.extend_from_slice(txn_id.as_bytes());
 
-	self.db.userdevicetxnid_response.insert(&key, data);
+	let mut val = Vec::with_capacity(TIMESTAMP_LEN.saturating_add(data.len()));
+	val.extend_from_slice(&now_secs().to_be_bytes());
+	val.extend_from_slice(data);
+
+	self.db.userdevicetxnid_response.insert(&key, &val);
 }
 
-// If there's no entry, this is a new transaction
+/// The response recorded for the transaction. If there's no entry, or it is
+/// older than `transaction_id_ttl` and awaiting removal, this is a new
+/// transaction.
 #[implement(Service)]
 pub async fn existing_txnid(
 	&self,
 	user_id: &UserId,
 	device_id: Option<&DeviceId>,
 	txn_id: &TransactionId,
-) -> Result<Handle<'_>> {
+) -> Result<Vec<u8>> {
 	let key = (user_id, device_id, txn_id);
-	self.db.userdevicetxnid_response.qry(&key).await
+	let val = self.db.userdevicetxnid_response.qry(&key).await?;
+	let (timestamp, response) = val
+		.split_first_chunk::<TIMESTAMP_LEN>()
+		.ok_or_else(|| err!(Database("Invalid transaction id record for {txn_id:?}")))?;
+
+	let ttl = self.server.config.transaction_id_ttl;
+	let created = u64::from_be_bytes(*timestamp);
+	if ttl > 0 && created.saturating_add(ttl) < now_secs() {
+		return Err!(Request(NotFound("Transaction id has expired.")));
+	}
+
+	Ok(response.to_vec())
+}
+
+#[implement(Service)]
+pub fn add_server_txnid(&self, origin: &ServerName, txn_id: &TransactionId, data: &[u8]) {
+	let mut key = origin.as_bytes().to_vec();
+	key.push(0xFF);
+	key.extend_from_slice(txn_id.as_bytes());
+
+	let mut val = Vec::with_capacity(TIMESTAMP_LEN.saturating_add(data.len()));
+	val.extend_from_slice(&now_secs().to_be_bytes());
+	val.extend_from_slice(data);
+
+	self.db.servertxnid_response.insert(&key, &val);
+}
+
+/// The result recorded for the federation transaction of the server. If
+/// there's no entry, or it is older than `federation_transaction_ttl` and
+/// awaiting removal, the server sent a new transaction.
+#[implement(Service)]
+pub async fn existing_server_txnid(
+	&self,
+	origin: &ServerName,
+	txn_id: &TransactionId,
+) -> Result<Vec<u8>> {
+	let key = (origin, txn_id);
+	let val = self.db.servertxnid_response.qry(&key).await?;
+	let (timestamp, response) = val
+		.split_first_chunk::<TIMESTAMP_LEN>()
+		.ok_or_else(|| err!(Database("Invalid transaction id record for {txn_id:?}")))?;
+
+	let ttl = self.server.config.federation_transaction_ttl;
+	let created = u64::from_be_bytes(*timestamp);
+	if ttl > 0 && created.saturating_add(ttl) < now_secs() {
+		return Err!(Request(NotFound("Transaction id has expired.")));
+	}
+
+	Ok(response.to_vec())
 }
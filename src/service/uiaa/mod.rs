@@ -1,53 +1,61 @@
-use std::{
-	collections::BTreeMap,
-	sync::{Arc, RwLock},
-};
+use std::sync::Arc;
 
 use conduwuit::{
 	err, error, implement, utils,
-	utils::{hash, string::EMPTY},
-	Error, Result,
+	utils::{hash, string::EMPTY, time::now_secs},
+	Err, Error, Result, Server,
 };
-use database::{Deserialized, Json, Map};
+use database::Map;
 use ruma::{
 	api::client::{
 		error::ErrorKind,
-		uiaa::{AuthData, AuthType, Password, UiaaInfo, UserIdentifier},
+		uiaa::{AuthData, AuthFlow, AuthType, Password, UiaaInfo, UserIdentifier},
 	},
 	CanonicalJsonValue, DeviceId, OwnedDeviceId, OwnedUserId, UserId,
 };
+use serde::Serialize;
 
-use crate::{globals, users, Dep};
+use crate::{globals, registration_tokens, users, Dep};
 
 pub struct Service {
-	userdevicesessionid_uiaarequest: RwLock<RequestMap>,
 	db: Data,
 	services: Services,
 }
 
 struct Services {
+	server: Arc<Server>,
 	globals: Dep<globals::Service>,
+	registration_tokens: Dep<registration_tokens::Service>,
 	users: Dep<users::Service>,
 }
 
 struct Data {
+	sessionid_uiaauserdevice: Arc<Map>,
 	userdevicesessionid_uiaainfo: Arc<Map>,
+	userdevicesessionid_uiaarequest: Arc<Map>,
 }
 
-type RequestMap = BTreeMap<RequestKey, CanonicalJsonValue>;
-type RequestKey = (OwnedUserId, OwnedDeviceId, String);
-
 pub const SESSION_ID_LENGTH: usize = 32;
 
+/// Records lead with the big-endian time in seconds the session last made
+/// progress so the database can expire them once `uiaa_session_ttl` has
+/// passed.
+const TIMESTAMP_LEN: usize = size_of::<u64>();
+
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
-			userdevicesessionid_uiaarequest: RwLock::new(RequestMap::new()),
 			db: Data {
+				sessionid_uiaauserdevice: args.db["sessionid_uiaauserdevice"].clone(),
 				userdevicesessionid_uiaainfo: args.db["userdevicesessionid_uiaainfo"].clone(),
+				userdevicesessionid_uiaarequest: args.db["userdevicesessionid_uiaarequest"]
+					.clone(),
 			},
 			services: Services {
+				server: args.server.clone(),
 				globals: args.depend::<globals::Service>("globals"),
+				registration_tokens: args
+					.depend::<registration_tokens::Service>("registration_tokens"),
 				users: args.depend::<users::Service>("users"),
 			},
 		}))
@@ -56,15 +64,27 @@ impl crate::Service for Service {
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
-/// Creates a new Uiaa session. Make sure the session token is unique.
+/// Creates a new Uiaa session. Make sure the session token is unique. Where a
+/// password is asked for, confirming the identity through the SSO identity
+/// provider is offered as well when that is enabled.
 #[implement(Service)]
-pub fn create(
+pub async fn create(
 	&self,
 	user_id: &UserId,
 	device_id: &DeviceId,
-	uiaainfo: &UiaaInfo,
+	uiaainfo: &mut UiaaInfo,
 	json_body: &CanonicalJsonValue,
 ) {
+	let flows = &mut uiaainfo.flows;
+	if self.services.server.config.oidc.enable
+		&& flows
+			.iter()
+			.any(|flow| flow.stages.contains(&AuthType::Password))
+		&& !flows.iter().any(|flow| flow.stages == [AuthType::Sso])
+	{
+		flows.push(AuthFlow { stages: vec![AuthType::Sso] });
+	}
+
 	// TODO: better session error handling (why is uiaainfo.session optional in
 	// ruma?)
 	self.set_uiaa_request(
@@ -79,7 +99,8 @@ pub fn create(
 		device_id,
 		uiaainfo.session.as_ref().expect("session should be set"),
 		Some(uiaainfo),
-	);
+	)
+	.await;
 }
 
 #[implement(Service)]
@@ -154,10 +175,9 @@ pub async fn try_auth(
 		| AuthData::RegistrationToken(t) => {
 			if self
 				.services
-				.globals
-				.registration_token
-				.as_ref()
-				.is_some_and(|reg_token| t.token.trim() == reg_token)
+				.registration_tokens
+				.is_valid(t.token.trim())
+				.await
 			{
 				uiaainfo.completed.push(AuthType::RegistrationToken);
 			} else {
@@ -171,6 +191,8 @@ pub async fn try_auth(
 		| AuthData::Dummy(_) => {
 			uiaainfo.completed.push(AuthType::Dummy);
 		},
+		// Stages completed out of band (e.g. SSO) are already in the session.
+		| AuthData::FallbackAcknowledgement(_) => (),
 		| k => error!("type not supported: {:?}", k),
 	}
 
@@ -192,7 +214,8 @@ pub async fn try_auth(
 			device_id,
 			uiaainfo.session.as_ref().expect("session is always set"),
 			Some(&uiaainfo),
-		);
+		)
+		.await;
 
 		return Ok((false, uiaainfo));
 	}
@@ -203,11 +226,42 @@ pub async fn try_auth(
 		device_id,
 		uiaainfo.session.as_ref().expect("session is always set"),
 		None,
-	);
+	)
+	.await;
 
 	Ok((true, uiaainfo))
 }
 
+/// Record a stage completed out of band, i.e. through its fallback page,
+/// for the client to continue the session with.
+#[implement(Service)]
+pub async fn complete_stage(
+	&self,
+	user_id: &UserId,
+	device_id: &DeviceId,
+	session: &str,
+	stage: AuthType,
+) -> Result {
+	let mut uiaainfo = self.get_uiaa_session(user_id, device_id, session).await?;
+	if !uiaainfo.completed.contains(&stage) {
+		uiaainfo.completed.push(stage);
+	}
+
+	self.update_uiaa_session(user_id, device_id, session, Some(&uiaainfo))
+		.await;
+
+	Ok(())
+}
+
+/// The user and device of a session, for fallback pages which are only given
+/// the session.
+#[implement(Service)]
+pub async fn find_session(&self, session: &str) -> Option<(OwnedUserId, OwnedDeviceId)> {
+	let val = self.db.sessionid_uiaauserdevice.get(session).await.ok()?;
+
+	serde_json::from_slice(self.unexpired(&val)?).ok()
+}
+
 #[implement(Service)]
 fn set_uiaa_request(
 	&self,
@@ -216,35 +270,36 @@ fn set_uiaa_request(
 	session: &str,
 	request: &CanonicalJsonValue,
 ) {
-	let key = (user_id.to_owned(), device_id.to_owned(), session.to_owned());
-	self.userdevicesessionid_uiaarequest
-		.write()
-		.expect("locked for writing")
-		.insert(key, request.to_owned());
+	let key = (user_id, device_id, session);
+	self.db
+		.userdevicesessionid_uiaarequest
+		.put_raw(key, stamped(request));
 }
 
+/// The body of the request which started the session, unless the session has
+/// expired.
 #[implement(Service)]
-pub fn get_uiaa_request(
+pub async fn get_uiaa_request(
 	&self,
 	user_id: &UserId,
 	device_id: Option<&DeviceId>,
 	session: &str,
 ) -> Option<CanonicalJsonValue> {
-	let key = (
-		user_id.to_owned(),
-		device_id.unwrap_or_else(|| EMPTY.into()).to_owned(),
-		session.to_owned(),
-	);
+	let key = (user_id, device_id.unwrap_or_else(|| EMPTY.into()), session);
+	let val = self
+		.db
+		.userdevicesessionid_uiaarequest
+		.qry(&key)
+		.await
+		.ok()?;
 
-	self.userdevicesessionid_uiaarequest
-		.read()
-		.expect("locked for reading")
-		.get(&key)
-		.cloned()
+	serde_json::from_slice(self.unexpired(&val)?).ok()
 }
 
+/// Store the session's progress, or remove the session once it is complete.
+/// Progress restarts the session's lifetime.
 #[implement(Service)]
-fn update_uiaa_session(
+async fn update_uiaa_session(
 	&self,
 	user_id: &UserId,
 	device_id: &DeviceId,
@@ -252,13 +307,28 @@ fn update_uiaa_session(
 	uiaainfo: Option<&UiaaInfo>,
 ) {
 	let key = (user_id, device_id, session);
-
-	if let Some(uiaainfo) = uiaainfo {
-		self.db
-			.userdevicesessionid_uiaainfo
-			.put(key, Json(uiaainfo));
-	} else {
+	let Some(uiaainfo) = uiaainfo else {
 		self.db.userdevicesessionid_uiaainfo.del(key);
+		self.db.userdevicesessionid_uiaarequest.del(key);
+		self.db.sessionid_uiaauserdevice.remove(session);
+		return;
+	};
+
+	self.db
+		.userdevicesessionid_uiaainfo
+		.put_raw(key, stamped(uiaainfo));
+
+	self.db
+		.sessionid_uiaauserdevice
+		.insert(session, stamped(&(user_id, device_id)));
+
+	if let Ok(request) = self.db.userdevicesessionid_uiaarequest.qry(&key).await {
+		if let Some((_, request)) = request.split_first_chunk::<TIMESTAMP_LEN>() {
+			let restamped = [now_secs().to_be_bytes().as_slice(), request].concat();
+			self.db
+				.userdevicesessionid_uiaarequest
+				.put_raw(key, restamped);
+		}
 	}
 }
 
@@ -270,10 +340,30 @@ async fn get_uiaa_session(
 	session: &str,
 ) -> Result<UiaaInfo> {
 	let key = (user_id, device_id, session);
-	self.db
-		.userdevicesessionid_uiaainfo
-		.qry(&key)
-		.await
-		.deserialized()
-		.map_err(|_| err!(Request(Forbidden("UIAA session does not exist."))))
+	let Ok(val) = self.db.userdevicesessionid_uiaainfo.qry(&key).await else {
+		return Err!(Request(Forbidden("UIAA session does not exist.")));
+	};
+
+	let Some(uiaainfo) = self.unexpired(&val) else {
+		return Err!(Request(Forbidden("UIAA session has expired.")));
+	};
+
+	serde_json::from_slice(uiaainfo)
+		.map_err(|e| err!(Database("Invalid UIAA session {session:?}: {e}")))
+}
+
+/// The record without its timestamp, unless it is older than
+/// `uiaa_session_ttl` and awaiting removal.
+#[implement(Service)]
+fn unexpired<'a>(&self, val: &'a [u8]) -> Option<&'a [u8]> {
+	let (timestamp, val) = val.split_first_chunk::<TIMESTAMP_LEN>()?;
+	let ttl = self.services.server.config.uiaa_session_ttl;
+	let updated = u64::from_be_bytes(*timestamp);
+	(ttl == 0 || updated.saturating_add(ttl) >= now_secs()).then_some(val)
+}
+
+fn stamped<T: Serialize>(val: &T) -> Vec<u8> {
+	let mut stamped = now_secs().to_be_bytes().to_vec();
+	serde_json::to_writer(&mut stamped, val).expect("value serialization can't fail");
+	stamped
 }
@@ -0,0 +1,162 @@
+//! The user directory: every known user indexed by the words of its user ID
+//! and display name. Local users are reindexed when their display name
+//! changes, remote users also when they join a room. Who a search shows is
+//! decided at search time, from the rooms shared with the searching user and
+//! the public rooms of each user.
+
+use std::collections::{BTreeSet, HashSet};
+
+use conduwuit::{implement, utils::stream::TryIgnore};
+use database::{Deserialized, Json};
+use futures::StreamExt;
+use ruma::{
+	events::{
+		room::join_rules::{JoinRule, RoomJoinRulesEventContent},
+		StateEventType,
+	},
+	OwnedUserId, RoomId, UserId,
+};
+
+use crate::rooms::directory::words;
+
+/// Update the directory entries of the user after its display name changed.
+#[implement(super::Service)]
+pub fn reindex_directory(&self, user_id: &UserId, displayname: Option<&str>) {
+	let mut indexed = words(user_id.as_str());
+	indexed.extend(displayname.map(words).unwrap_or_default());
+
+	self.unindex_directory(user_id);
+	for word in &indexed {
+		self.db
+			.userdirectoryword_userid
+			.put_raw((word, user_id), []);
+	}
+
+	self.db
+		.userid_directorywords
+		.raw_put(user_id, Json(indexed));
+}
+
+#[implement(super::Service)]
+fn unindex_directory(&self, user_id: &UserId) {
+	let Ok(indexed) = self
+		.db
+		.userid_directorywords
+		.get_blocking(user_id)
+		.deserialized::<BTreeSet<String>>()
+	else {
+		return;
+	};
+
+	for word in &indexed {
+		self.db.userdirectoryword_userid.del((word, user_id));
+	}
+}
+
+/// The users the sender may see with a word of their user ID or display
+/// name beginning with each of the words of the search term, by user ID, and
+/// whether there were more than the limit.
+#[implement(super::Service)]
+#[tracing::instrument(skip(self), level = "debug")]
+pub async fn search_directory(
+	&self,
+	sender_user: &UserId,
+	search_term: &str,
+	limit: usize,
+) -> (Vec<OwnedUserId>, bool) {
+	let terms = words(search_term);
+	if terms.is_empty() {
+		return (Vec::new(), false);
+	}
+
+	let mut found: Option<HashSet<OwnedUserId>> = None;
+	for term in &terms {
+		let users: HashSet<OwnedUserId> = self
+			.db
+			.userdirectoryword_userid
+			.keys_raw_prefix(term)
+			.ignore_err()
+			.map(|(_, user_id): (&str, &UserId)| user_id.to_owned())
+			.collect()
+			.await;
+
+		let users = match found {
+			| None => users,
+			| Some(mut found) => {
+				found.retain(|user_id| users.contains(user_id));
+				found
+			},
+		};
+
+		let empty = users.is_empty();
+		found = Some(users);
+		if empty {
+			break;
+		}
+	}
+
+	let candidates: BTreeSet<OwnedUserId> = found.unwrap_or_default().into_iter().collect();
+
+	let mut results = Vec::new();
+	for user_id in candidates {
+		if !self.is_visible_in_directory(sender_user, &user_id).await {
+			continue;
+		}
+
+		if results.len() >= limit {
+			return (results, true);
+		}
+
+		results.push(user_id);
+	}
+
+	(results, false)
+}
+
+/// Whether the user may be shown to the sender by the user directory: when
+/// they share a room or the user is in a public room, meaning one anyone may
+/// join or whose history anyone may read. Service accounts, deactivated local
+/// users and, unless the config includes them, remote users are never shown.
+#[implement(super::Service)]
+async fn is_visible_in_directory(&self, sender_user: &UserId, user_id: &UserId) -> bool {
+	if self.services.globals.user_is_local(user_id) {
+		if !self.is_active_local(user_id).await {
+			return false;
+		}
+	} else if !self
+		.services
+		.server
+		.config
+		.user_directory_search_remote_users
+	{
+		return false;
+	}
+
+	if self.is_service_account(user_id).await {
+		return false;
+	}
+
+	if sender_user == user_id {
+		return true;
+	}
+
+	self.services
+		.state_cache
+		.rooms_joined(user_id)
+		.any(|room_id| self.is_public_room(room_id))
+		.await || self
+		.services
+		.state_cache
+		.user_sees_user(sender_user, user_id)
+		.await
+}
+
+#[implement(super::Service)]
+async fn is_public_room(&self, room_id: &RoomId) -> bool {
+	let state_accessor = &self.services.state_accessor;
+	state_accessor
+		.room_state_get_content(room_id, &StateEventType::RoomJoinRules, "")
+		.await
+		.is_ok_and(|content: RoomJoinRulesEventContent| content.join_rule == JoinRule::Public)
+		|| state_accessor.is_world_readable(room_id).await
+}
@@ -0,0 +1,164 @@
+//! The display name policy of local users: the display name new users get,
+//! from `new_user_displayname_template` or their localpart and suffix, and the
+//! `forbidden_displaynames` no local user may have.
+
+use conduwuit::{debug_info, implement, info, pdu::PduBuilder, warn, Result};
+use futures::StreamExt;
+use ruma::{
+	events::room::{
+		member::{MembershipState, RoomMemberEventContent},
+		message::RoomMessageEventContent,
+	},
+	OwnedRoomId, OwnedUserId, UserId,
+};
+
+/// The display name of a new local user. The name given, e.g. by an SSO
+/// provider, replaces the localpart.
+#[implement(super::Service)]
+#[must_use]
+pub fn new_user_displayname(&self, user_id: &UserId, name: Option<&str>) -> String {
+	let config = &self.services.server.config;
+	let suffix = &config.new_user_displayname_suffix;
+	let name = name.unwrap_or_else(|| user_id.localpart());
+
+	match config.new_user_displayname_template.as_deref() {
+		| Some(template) => template
+			.replace("{name}", name)
+			.replace("{localpart}", user_id.localpart())
+			.replace("{suffix}", suffix)
+			.trim()
+			.to_owned(),
+		| None if suffix.is_empty() => name.to_owned(),
+		| None => format!("{name} {suffix}"),
+	}
+}
+
+#[implement(super::Service)]
+#[must_use]
+pub fn is_displayname_forbidden(&self, displayname: &str) -> bool {
+	self.services
+		.server
+		.config
+		.forbidden_displaynames
+		.is_match(displayname)
+}
+
+/// Reset the forbidden display names of local users to their default one,
+/// telling each by a server notice. Returns how many were reset.
+#[implement(super::Service)]
+pub async fn enforce_displayname_policy(&self) -> usize {
+	if self
+		.services
+		.server
+		.config
+		.forbidden_displaynames
+		.is_empty()
+	{
+		return 0;
+	}
+
+	let users: Vec<OwnedUserId> = self
+		.list_local_users()
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	let mut reset = 0_usize;
+	for user_id in users {
+		let Ok(displayname) = self.displayname(&user_id).await else {
+			continue;
+		};
+
+		if !self.is_displayname_forbidden(&displayname) {
+			continue;
+		}
+
+		match self.reset_displayname(&user_id, &displayname).await {
+			| Ok(()) => reset = reset.saturating_add(1),
+			| Err(e) => warn!(%user_id, "Failed to reset forbidden display name: {e}"),
+		}
+	}
+
+	reset
+}
+
+#[implement(super::Service)]
+pub(super) async fn enforce_displayname_policy_logged(&self) {
+	let reset = self.enforce_displayname_policy().await;
+	if reset > 0 {
+		info!("Reset {reset} forbidden display names of local users");
+	}
+}
+
+#[implement(super::Service)]
+async fn reset_displayname(&self, user_id: &UserId, forbidden: &str) -> Result {
+	let displayname = Some(self.new_user_displayname(user_id, None))
+		.filter(|displayname| !self.is_displayname_forbidden(displayname));
+
+	debug_info!(%user_id, ?displayname, "Resetting forbidden display name {forbidden:?}");
+	self.set_displayname(user_id, displayname.clone());
+
+	let rooms: Vec<OwnedRoomId> = self
+		.services
+		.state_cache
+		.rooms_joined(user_id)
+		.map(ToOwned::to_owned)
+		.collect()
+		.await;
+
+	for room_id in &rooms {
+		let state_lock = self.services.state.mutex.lock(room_id).await;
+		let Ok(current) = self
+			.services
+			.state_accessor
+			.get_member(room_id, user_id)
+			.await
+		else {
+			continue;
+		};
+
+		let forbidden_here = current
+			.displayname
+			.as_deref()
+			.is_some_and(|displayname| self.is_displayname_forbidden(displayname));
+
+		if current.membership != MembershipState::Join || !forbidden_here {
+			continue;
+		}
+
+		// Only the display name is reset; whatever else the membership carries,
+		// like a per-room avatar, stays as it is.
+		let content = RoomMemberEventContent {
+			displayname: displayname.clone(),
+			join_authorized_via_users_server: None,
+			reason: None,
+			..current
+		};
+
+		if let Err(e) = self
+			.services
+			.timeline
+			.build_and_append_pdu(
+				PduBuilder::state(user_id.to_string(), &content),
+				user_id,
+				room_id,
+				&state_lock,
+			)
+			.await
+		{
+			warn!(%user_id, %room_id, "Failed to update membership with reset display name: {e}");
+		}
+	}
+
+	let notice = format!(
+		"Your display name {forbidden:?} is not allowed on this server, so it was reset to {}.",
+		displayname
+			.as_deref()
+			.map_or_else(|| "none".to_owned(), |displayname| format!("{displayname:?}")),
+	);
+
+	self.services
+		.admin
+		.send_server_notice(user_id, RoomMessageEventContent::notice_plain(notice))
+		.await
+}
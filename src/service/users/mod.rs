@@ -1,8 +1,18 @@
-use std::{collections::BTreeMap, mem, mem::size_of, sync::Arc};
+mod directory;
+mod displayname;
+
+use std::{
+	collections::BTreeMap,
+	mem,
+	mem::size_of,
+	sync::{Arc, Mutex},
+	time::{Duration, Instant},
+};
 
+use async_trait::async_trait;
 use conduwuit::{
 	debug_warn, err, trace,
-	utils::{self, stream::TryIgnore, string::Unquoted, ReadyExt},
+	utils::{self, stream::TryIgnore, string::Unquoted, IterStream, ReadyExt},
 	Err, Error, Result, Server,
 };
 use database::{Database, Deserialized, Ignore, Interfix, Json, Map};
@@ -17,13 +27,52 @@ use ruma::{
 	DeviceId, KeyId, MilliSecondsSinceUnixEpoch, OneTimeKeyAlgorithm, OneTimeKeyId,
 	OneTimeKeyName, OwnedDeviceId, OwnedKeyId, OwnedMxcUri, OwnedUserId, RoomId, UInt, UserId,
 };
-use serde_json::json;
+use serde_json::{json, value::to_raw_value};
+use tokio::{sync::Notify, time::interval};
 
-use crate::{account_data, admin, globals, rooms, Dep};
+use crate::{account_data, admin, bus, globals, rooms, Dep};
 
 pub struct Service {
 	services: Services,
 	db: Data,
+	interrupt: Notify,
+	ephemeral_to_device: Mutex<EphemeralToDevice>,
+	to_device_delivered: Mutex<ToDeviceDelivered>,
+}
+
+/// To-device events for devices which were syncing when the events arrived,
+/// held in memory rather than written unless they go unacknowledged.
+type EphemeralToDevice = BTreeMap<(OwnedUserId, OwnedDeviceId), Vec<EphemeralEvent>>;
+
+/// Read replicas cannot remove the to-device events they delivered, so they
+/// remember the count each device has been sent events up to instead.
+type ToDeviceDelivered = BTreeMap<(OwnedUserId, OwnedDeviceId), u64>;
+
+/// Restriction of an account short of deactivation, keeping all its data.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LockState {
+	/// The account cannot be used at all, apart from logging out (MSC3939).
+	Locked,
+
+	/// The account can read but not change anything, apart from leaving rooms
+	/// and logging out (MSC3823).
+	Suspended,
+}
+
+impl LockState {
+	#[must_use]
+	pub fn as_str(&self) -> &'static str {
+		match self {
+			| Self::Locked => "locked",
+			| Self::Suspended => "suspended",
+		}
+	}
+}
+
+struct EphemeralEvent {
+	count: u64,
+	queued: Instant,
+	event: Raw<AnyToDeviceEvent>,
 }
 
 struct Services {
@@ -31,9 +80,12 @@ struct Services {
 	db: Arc<Database>,
 	account_data: Dep<account_data::Service>,
 	admin: Dep<admin::Service>,
+	bus: Dep<bus::Service>,
 	globals: Dep<globals::Service>,
+	state: Dep<rooms::state::Service>,
 	state_accessor: Dep<rooms::state_accessor::Service>,
 	state_cache: Dep<rooms::state_cache::Service>,
+	timeline: Dep<rooms::timeline::Service>,
 }
 
 struct Data {
@@ -46,31 +98,44 @@ struct Data {
 	token_userdeviceid: Arc<Map>,
 	userdeviceid_metadata: Arc<Map>,
 	userdeviceid_token: Arc<Map>,
+	userdeviceroomid_shortstatehash: Arc<Map>,
+	userdirectoryword_userid: Arc<Map>,
 	userfilterid_filter: Arc<Map>,
 	userid_avatarurl: Arc<Map>,
 	userid_blurhash: Arc<Map>,
 	userid_devicelistversion: Arc<Map>,
+	userid_directorywords: Arc<Map>,
 	userid_displayname: Arc<Map>,
 	userid_lastonetimekeyupdate: Arc<Map>,
+	userid_lockstate: Arc<Map>,
 	userid_masterkeyid: Arc<Map>,
 	userid_password: Arc<Map>,
 	userid_selfsigningkeyid: Arc<Map>,
+	userid_serviceaccount: Arc<Map>,
+	userid_shadowbanned: Arc<Map>,
 	userid_usersigningkeyid: Arc<Map>,
 	useridprofilekey_value: Arc<Map>,
 }
 
+#[async_trait]
 impl crate::Service for Service {
 	fn build(args: crate::Args<'_>) -> Result<Arc<Self>> {
 		Ok(Arc::new(Self {
+			interrupt: Notify::new(),
+			ephemeral_to_device: Mutex::default(),
+			to_device_delivered: Mutex::default(),
 			services: Services {
 				server: args.server.clone(),
 				db: args.db.clone(),
 				account_data: args.depend::<account_data::Service>("account_data"),
 				admin: args.depend::<admin::Service>("admin"),
+				bus: args.depend::<bus::Service>("bus"),
 				globals: args.depend::<globals::Service>("globals"),
+				state: args.depend::<rooms::state::Service>("rooms::state"),
 				state_accessor: args
 					.depend::<rooms::state_accessor::Service>("rooms::state_accessor"),
 				state_cache: args.depend::<rooms::state_cache::Service>("rooms::state_cache"),
+				timeline: args.depend::<rooms::timeline::Service>("rooms::timeline"),
 			},
 			db: Data {
 				keychangeid_userid: args.db["keychangeid_userid"].clone(),
@@ -82,21 +147,65 @@ impl crate::Service for Service {
 				token_userdeviceid: args.db["token_userdeviceid"].clone(),
 				userdeviceid_metadata: args.db["userdeviceid_metadata"].clone(),
 				userdeviceid_token: args.db["userdeviceid_token"].clone(),
+				userdeviceroomid_shortstatehash: args.db["userdeviceroomid_shortstatehash"]
+					.clone(),
+				userdirectoryword_userid: args.db["userdirectoryword_userid"].clone(),
 				userfilterid_filter: args.db["userfilterid_filter"].clone(),
 				userid_avatarurl: args.db["userid_avatarurl"].clone(),
 				userid_blurhash: args.db["userid_blurhash"].clone(),
 				userid_devicelistversion: args.db["userid_devicelistversion"].clone(),
+				userid_directorywords: args.db["userid_directorywords"].clone(),
 				userid_displayname: args.db["userid_displayname"].clone(),
 				userid_lastonetimekeyupdate: args.db["userid_lastonetimekeyupdate"].clone(),
+				userid_lockstate: args.db["userid_lockstate"].clone(),
 				userid_masterkeyid: args.db["userid_masterkeyid"].clone(),
 				userid_password: args.db["userid_password"].clone(),
 				userid_selfsigningkeyid: args.db["userid_selfsigningkeyid"].clone(),
+				userid_serviceaccount: args.db["userid_serviceaccount"].clone(),
+				userid_shadowbanned: args.db["userid_shadowbanned"].clone(),
 				userid_usersigningkeyid: args.db["userid_usersigningkeyid"].clone(),
 				useridprofilekey_value: args.db["useridprofilekey_value"].clone(),
 			},
 		}))
 	}
 
+	#[tracing::instrument(skip_all, name = "users", level = "debug")]
+	async fn worker(self: Arc<Self>) -> Result<()> {
+		self.enforce_displayname_policy_logged().await;
+
+		let config = &self.services.server.config;
+		let window = config.to_device_ephemeral_window;
+		let policy = config.forbidden_displaynames_interval;
+		if window == 0 && (policy == 0 || config.forbidden_displaynames.is_empty()) {
+			return Ok(());
+		}
+
+		let window = Duration::from_secs(window);
+		let mut persist_interval = interval(window.max(Duration::from_secs(1)));
+		let mut policy_interval = interval(Duration::from_secs(policy.max(1)));
+		policy_interval.reset();
+		while self.services.server.running() {
+			tokio::select! {
+				() = self.interrupt.notified() => break,
+				_ = persist_interval.tick(), if !window.is_zero() => {
+					self.persist_ephemeral_to_device(Some(window));
+				},
+				_ = policy_interval.tick(), if policy > 0 => {
+					self.enforce_displayname_policy_logged().await;
+				},
+			}
+		}
+
+		// Nothing held in memory may be lost at shutdown.
+		if !window.is_zero() {
+			self.persist_ephemeral_to_device(None);
+		}
+
+		Ok(())
+	}
+
+	fn interrupt(&self) { self.interrupt.notify_waiters(); }
+
 	fn name(&self) -> &str { crate::service::make_name(std::module_path!()) }
 }
 
@@ -162,6 +271,75 @@ impl Service {
 			.await
 	}
 
+	/// Whether the account is locked or suspended.
+	pub async fn lock_state(&self, user_id: &UserId) -> Option<LockState> {
+		let lock_state: String = self
+			.db
+			.userid_lockstate
+			.get(user_id)
+			.await
+			.deserialized()
+			.ok()?;
+
+		match lock_state.as_str() {
+			| "locked" => Some(LockState::Locked),
+			| "suspended" => Some(LockState::Suspended),
+			| _ => None,
+		}
+	}
+
+	/// Lock or suspend the account, or lift either with `None`.
+	pub fn set_lock_state(&self, user_id: &UserId, lock_state: Option<LockState>) {
+		match lock_state {
+			| Some(lock_state) => self
+				.db
+				.userid_lockstate
+				.insert(user_id, lock_state.as_str()),
+			| None => self.db.userid_lockstate.remove(user_id),
+		}
+	}
+
+	/// Shadow-ban the user, so their events appear to be sent but are dropped,
+	/// except for their own membership changes, or lift it.
+	pub fn set_shadow_banned(&self, user_id: &UserId, shadow_banned: bool) {
+		if shadow_banned {
+			self.db.userid_shadowbanned.insert(user_id, []);
+		} else {
+			self.db.userid_shadowbanned.remove(user_id);
+		}
+	}
+
+	#[inline]
+	pub async fn is_shadow_banned(&self, user_id: &UserId) -> bool {
+		self.db.userid_shadowbanned.get(user_id).await.is_ok()
+	}
+
+	pub fn list_shadow_banned(&self) -> impl Stream<Item = &UserId> + Send + '_ {
+		self.db.userid_shadowbanned.keys().ignore_err()
+	}
+
+	/// Make the user a service account, for bots without an appservice
+	/// registration, or a normal user again. Service accounts are neither rate
+	/// limited nor listed in the user directory, and their devices are
+	/// non-interactive: neither their requests nor presence timers change
+	/// their presence.
+	pub fn set_service_account(&self, user_id: &UserId, service_account: bool) {
+		if service_account {
+			self.db.userid_serviceaccount.insert(user_id, []);
+		} else {
+			self.db.userid_serviceaccount.remove(user_id);
+		}
+	}
+
+	#[inline]
+	pub async fn is_service_account(&self, user_id: &UserId) -> bool {
+		self.db.userid_serviceaccount.get(user_id).await.is_ok()
+	}
+
+	pub fn list_service_accounts(&self) -> impl Stream<Item = &UserId> + Send + '_ {
+		self.db.userid_serviceaccount.keys().ignore_err()
+	}
+
 	/// Check if account is active, infallible
 	pub async fn is_active(&self, user_id: &UserId) -> bool {
 		!self.is_deactivated(user_id).await.unwrap_or(true)
@@ -234,6 +412,7 @@ impl Service {
 	/// Sets a new displayname or removes it if displayname is None. You still
 	/// need to nofify all rooms of this change.
 	pub fn set_displayname(&self, user_id: &UserId, displayname: Option<String>) {
+		self.reindex_directory(user_id, displayname.as_deref());
 		if let Some(displayname) = displayname {
 			self.db.userid_displayname.insert(user_id, displayname);
 		} else {
@@ -310,6 +489,11 @@ impl Service {
 		}
 
 		// Remove todevice events
+		self.ephemeral_to_device
+			.lock()
+			.expect("locked")
+			.remove(&(user_id.to_owned(), device_id.to_owned()));
+
 		let prefix = (user_id, device_id, Interfix);
 		self.db
 			.todeviceid_events
@@ -318,6 +502,14 @@ impl Service {
 			.ready_for_each(|key| self.db.todeviceid_events.remove(key))
 			.await;
 
+		// Remove the room states last delivered by sync
+		self.db
+			.userdeviceroomid_shortstatehash
+			.keys_prefix_raw(&prefix)
+			.ignore_err()
+			.ready_for_each(|key| self.db.userdeviceroomid_shortstatehash.remove(key))
+			.await;
+
 		// TODO: Remove onetimekeys
 
 		increment(&self.db.userid_devicelistversion, user_id.as_bytes());
@@ -698,6 +890,24 @@ impl Service {
 			})
 			.await;
 
+		// The members of rooms sharing their history with the user invited to them
+		// need the user's devices to send them the room keys (MSC3061)
+		self.services
+			.state_cache
+			.rooms_invited(user_id)
+			.filter_map(|(room_id, _)| async move {
+				self.services
+					.state_accessor
+					.is_history_shared(&room_id)
+					.await
+					.then_some(room_id)
+			})
+			.ready_for_each(|room_id| {
+				let key = (&room_id, count);
+				self.db.keychangeid_userid.put_raw(key, user_id);
+			})
+			.await;
+
 		let key = (user_id, count);
 		self.db.keychangeid_userid.put_raw(key, user_id);
 	}
@@ -776,16 +986,56 @@ impl Service {
 		content: serde_json::Value,
 	) {
 		let count = self.services.globals.next_count().unwrap();
+		let event = json!({
+			"type": event_type,
+			"sender": sender,
+			"content": content,
+		});
+
+		// A device waiting in sync will fetch the event right away and most likely
+		// acknowledge it on its next sync, so it is only written if it isn't.
+		let mut prefix = target_user_id.as_bytes().to_vec();
+		prefix.push(0xFF);
+		prefix.extend_from_slice(target_device_id.as_bytes());
+		prefix.push(0xFF);
+		if self.services.server.config.to_device_ephemeral_window > 0
+			&& self.db.todeviceid_events.is_watched(&prefix)
+		{
+			if let Ok(event) = to_raw_value(&event).map(Raw::from_json) {
+				self.ephemeral_to_device
+					.lock()
+					.expect("locked")
+					.entry((target_user_id.to_owned(), target_device_id.to_owned()))
+					.or_default()
+					.push(EphemeralEvent { count, queued: Instant::now(), event });
+
+				self.db.todeviceid_events.notify(&prefix);
+				return;
+			}
+		}
 
 		let key = (target_user_id, target_device_id, count);
-		self.db.todeviceid_events.put(
-			key,
-			Json(json!({
-				"type": event_type,
-				"sender": sender,
-				"content": content,
-			})),
-		);
+		self.db.todeviceid_events.put(key, Json(event));
+		self.services.bus.wake_syncs(target_user_id);
+	}
+
+	/// Write ephemeral to-device events held longer than `older_than`, or all
+	/// of them, to the database so they remain until acknowledged.
+	fn persist_ephemeral_to_device(&self, older_than: Option<Duration>) {
+		let mut ephemeral = self.ephemeral_to_device.lock().expect("locked");
+		ephemeral.retain(|(user_id, device_id), events| {
+			events.retain(|EphemeralEvent { count, queued, event }| {
+				let persist = older_than.is_none_or(|older_than| queued.elapsed() >= older_than);
+				if persist {
+					let key = (user_id, device_id, *count);
+					self.db.todeviceid_events.put(key, Json(event));
+				}
+
+				!persist
+			});
+
+			!events.is_empty()
+		});
 	}
 
 	pub fn get_to_device_events<'a>(
@@ -793,12 +1043,35 @@ impl Service {
 		user_id: &'a UserId,
 		device_id: &'a DeviceId,
 	) -> impl Stream<Item = Raw<AnyToDeviceEvent>> + Send + 'a {
+		let ephemeral: Vec<_> = self
+			.ephemeral_to_device
+			.lock()
+			.expect("locked")
+			.get(&(user_id.to_owned(), device_id.to_owned()))
+			.into_iter()
+			.flatten()
+			.map(|ephemeral| ephemeral.event.clone())
+			.collect();
+
+		let delivered = self
+			.to_device_delivered
+			.lock()
+			.expect("locked")
+			.get(&(user_id.to_owned(), device_id.to_owned()))
+			.copied()
+			.unwrap_or(0);
+
 		let prefix = (user_id, device_id, Interfix);
 		self.db
 			.todeviceid_events
 			.stream_prefix(&prefix)
 			.ignore_err()
-			.map(|(_, val): (Ignore, Raw<AnyToDeviceEvent>)| val)
+			.ready_filter_map(
+				move |((_, _, count), val): ((Ignore, Ignore, u64), Raw<AnyToDeviceEvent>)| {
+					(count > delivered).then_some(val)
+				},
+			)
+			.chain(ephemeral.into_iter().stream())
 	}
 
 	pub async fn remove_to_device_events(
@@ -815,6 +1088,26 @@ impl Service {
 		let mut last = prefix.clone();
 		last.extend_from_slice(&until.to_be_bytes());
 
+		let userdeviceid = (user_id.to_owned(), device_id.to_owned());
+		let mut ephemeral = self.ephemeral_to_device.lock().expect("locked");
+		if let Some(events) = ephemeral.get_mut(&userdeviceid) {
+			events.retain(|event| event.count > until);
+			if events.is_empty() {
+				ephemeral.remove(&userdeviceid);
+			}
+		}
+
+		drop(ephemeral);
+
+		if self.services.db.is_read_only() {
+			self.to_device_delivered
+				.lock()
+				.expect("locked")
+				.insert(userdeviceid, until);
+
+			return;
+		}
+
 		let _cork = self.services.db.cork_and_flush();
 		self.db
 			.todeviceid_events